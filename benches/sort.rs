@@ -0,0 +1,89 @@
+//! Radix sort of fixed width keys compared to the comparison sort, on the same rows, and the
+//! `Sort` operation executed or bound and read as a cursor.
+//!
+//! Run with `cargo bench --bench sort`.
+
+#![feature(test)]
+
+extern crate dbkit_engine;
+extern crate test;
+
+use dbkit_engine::allocator;
+use dbkit_engine::block::View;
+use dbkit_engine::operation::{CursorChunk, Operation, ScanView, Sort, DEFAULT_CURSOR_FETCH};
+use dbkit_engine::operation::sort::{comparison_sorted_rows, radix_sorted_rows};
+use dbkit_engine::plan::SortOrder;
+use dbkit_engine::schema::Schema;
+use dbkit_engine::table::{Table, TableAppender};
+use dbkit_engine::types::Type;
+use test::Bencher;
+
+const ROWS: usize = 100_000;
+
+/// Pseudo random (fixed LCG) rows of a single INT64 or FLOAT64 column
+fn table(dtype: Type) -> Table<'static> {
+    let float = dtype == Type::FLOAT64;
+    let schema = Schema::make_one_attr("key", false, dtype);
+    let mut table = Table::new(&allocator::GLOBAL, &schema, Some(ROWS));
+    {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut appender = TableAppender::new(&mut table);
+
+        for _ in 0 .. ROWS {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            appender = if float {
+                appender.add_row().set((state >> 11) as f64 / 1e6 - 1e6)
+            } else {
+                appender.add_row().set(state as i64)
+            };
+        }
+
+        assert!(appender.done().is_none());
+    }
+    table
+}
+
+#[bench]
+fn radix_int64(b: &mut Bencher) {
+    let table = table(Type::INT64);
+    b.iter(|| radix_sorted_rows(&table, 0, SortOrder::ASC).unwrap());
+}
+
+#[bench]
+fn comparison_int64(b: &mut Bencher) {
+    let table = table(Type::INT64);
+    b.iter(|| comparison_sorted_rows(&table, &[(0, SortOrder::ASC)]).unwrap());
+}
+
+#[bench]
+fn radix_float64(b: &mut Bencher) {
+    let table = table(Type::FLOAT64);
+    b.iter(|| radix_sorted_rows(&table, 0, SortOrder::DESC).unwrap());
+}
+
+#[bench]
+fn comparison_float64(b: &mut Bencher) {
+    let table = table(Type::FLOAT64);
+    b.iter(|| comparison_sorted_rows(&table, &[(0, SortOrder::DESC)]).unwrap());
+}
+
+#[bench]
+fn execute_int64(b: &mut Bencher) {
+    let table = table(Type::INT64);
+    let op = Sort::new(ScanView::new(&table, None), &[("key", SortOrder::ASC)]);
+    b.iter(|| op.execute(&allocator::GLOBAL).unwrap());
+}
+
+#[bench]
+fn operation_int64(b: &mut Bencher) {
+    let table = table(Type::INT64);
+    let op = Sort::new(ScanView::new(&table, None), &[("key", SortOrder::ASC)]);
+    b.iter(|| {
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut rows = 0;
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            rows += view.rows();
+        }
+        rows
+    });
+}
@@ -0,0 +1,168 @@
+//! `#[derive(Record)]` for structs with named fields.
+//!
+//! Generates an implementation of `dbkit_engine::record::Record`: an attribute per field, named
+//! like the field and typed by the field's `RecordField` implementation, and the conversions of
+//! the struct to and from a row of values in field order.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate dbkit_derive;
+//! extern crate dbkit_engine;
+//!
+//! #[derive(Record)]
+//! struct Order {
+//!     id: i64,
+//!     customer: String,
+//!     discount: Option<f64>,
+//! }
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/// Name and type of a struct field
+struct Field {
+    name: String,
+    dtype: String,
+}
+
+fn is_punct(token: &TokenTree, ch: char) -> bool {
+    match *token {
+        TokenTree::Punct(ref p) => p.as_char() == ch,
+        _ => false,
+    }
+}
+
+fn is_ident(token: &TokenTree, name: &str) -> bool {
+    match *token {
+        TokenTree::Ident(ref i) => i.to_string() == name,
+        _ => false,
+    }
+}
+
+/// Tokens after the leading attributes and visibility
+fn skip_attributes(tokens: &[TokenTree]) -> &[TokenTree] {
+    let mut rest = tokens;
+    loop {
+        match rest {
+            [ref hash, TokenTree::Group(_), ..] if is_punct(hash, '#') => rest = &rest[2 ..],
+            [ref vis, TokenTree::Group(ref g), ..]
+                if is_ident(vis, "pub") && g.delimiter() == Delimiter::Parenthesis =>
+                rest = &rest[2 ..],
+            [ref vis, ..] if is_ident(vis, "pub") => rest = &rest[1 ..],
+            _ => return rest,
+        }
+    }
+}
+
+/// Split the tokens at commas outside of generic arguments
+fn split_fields(tokens: Vec<TokenTree>) -> Vec<Vec<TokenTree>> {
+    let mut out = Vec::new();
+    let mut current = Vec::new();
+    let mut depth = 0;
+
+    for token in tokens {
+        if is_punct(&token, '<') {
+            depth += 1;
+        } else if is_punct(&token, '>') {
+            depth -= 1;
+        } else if is_punct(&token, ',') && depth == 0 {
+            out.push(current);
+            current = Vec::new();
+            continue
+        }
+        current.push(token);
+    }
+
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+fn parse_field(tokens: &[TokenTree]) -> Field {
+    let tokens = skip_attributes(tokens);
+    match tokens {
+        [TokenTree::Ident(ref name), ref colon, _, ..] if is_punct(colon, ':') => Field {
+            name: name.to_string(),
+            dtype: tokens[2 ..].iter().cloned().collect::<TokenStream>().to_string(),
+        },
+        _ => panic!("#[derive(Record)] expects named struct fields"),
+    }
+}
+
+/// Struct name and fields
+fn parse_struct(input: TokenStream) -> (String, Vec<Field>) {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+
+    match skip_attributes(&tokens) {
+        [ref keyword, TokenTree::Ident(ref name), TokenTree::Group(ref body), ..]
+            if is_ident(keyword, "struct") && body.delimiter() == Delimiter::Brace =>
+        {
+            let fields = split_fields(body.stream().into_iter().collect()).iter()
+                .map(|f| parse_field(f))
+                .collect();
+            (name.to_string(), fields)
+        },
+        [ref keyword, TokenTree::Ident(_), ref generics, ..]
+            if is_ident(keyword, "struct") && is_punct(generics, '<') =>
+            panic!("#[derive(Record)] doesn't support generic structs"),
+        _ => panic!("#[derive(Record)] is only supported for structs with named fields"),
+    }
+}
+
+#[proc_macro_derive(Record)]
+pub fn derive_record(input: TokenStream) -> TokenStream {
+    let (name, fields) = parse_struct(input);
+
+    let attributes: Vec<String> = fields.iter()
+        .map(|f| format!(
+            "::dbkit_engine::schema::Attribute {{
+                name: \"{name}\".to_string(),
+                nullable: <{dtype} as ::dbkit_engine::record::RecordField>::nullable(),
+                dtype: <{dtype} as ::dbkit_engine::record::RecordField>::dtype(),
+            }}", name = f.name, dtype = f.dtype))
+        .collect();
+
+    let values: Vec<String> = fields.iter()
+        .map(|f| format!("::dbkit_engine::record::RecordField::to_value(&self.{})", f.name))
+        .collect();
+
+    let from_values: Vec<String> = fields.iter().enumerate()
+        .map(|(pos, f)| format!(
+            "{name}: <{dtype} as ::dbkit_engine::record::RecordField>::from_value(&row[{pos}])
+                .ok_or_else(|| ::dbkit_engine::error::DBError::AttributeType(
+                    \"{name}\".to_string()))?", name = f.name, dtype = f.dtype, pos = pos))
+        .collect();
+
+    let out = format!("
+        impl ::dbkit_engine::record::Record for {name} {{
+            fn schema() -> ::dbkit_engine::schema::Schema {{
+                ::dbkit_engine::schema::Schema::from_vec(vec![{attributes}])
+                    .expect(\"Record schema of {name}\")
+            }}
+
+            fn into_row(&self) -> Vec<::dbkit_engine::types::Value> {{
+                vec![{values}]
+            }}
+
+            fn from_row(row: &[::dbkit_engine::types::Value])
+                -> Result<{name}, ::dbkit_engine::error::DBError>
+            {{
+                if row.len() != {count} {{
+                    return Err(::dbkit_engine::error::DBError::ExpressionInputCount(
+                        format!(\"{{}} values for {count} fields of {name}\", row.len())))
+                }}
+
+                Ok({name} {{ {from_values} }})
+            }}
+        }}",
+        name = name,
+        attributes = attributes.join(", "),
+        values = values.join(", "),
+        count = fields.len(),
+        from_values = from_values.join(", "));
+
+    out.parse().expect("Generated Record implementation")
+}
@@ -0,0 +1,70 @@
+//! Derived `Record` implementations round trip through tables.
+
+#[macro_use]
+extern crate dbkit_derive;
+extern crate dbkit_engine;
+
+use dbkit_engine::allocator;
+use dbkit_engine::block::View;
+use dbkit_engine::error::DBError;
+use dbkit_engine::record::{Record, collect_records, records_table};
+use dbkit_engine::types::{Type, Value};
+
+#[derive(Record, Clone, PartialEq, Debug)]
+pub struct Order {
+    /// Order number
+    pub id: i64,
+    customer: String,
+    discount: Option<f64>,
+    tags: Option<Vec<u8>>,
+    shipped: bool,
+}
+
+fn orders() -> Vec<Order> {
+    vec![
+        Order { id: 1, customer: "alice".to_string(), discount: None, tags: Some(vec![1, 2]),
+                shipped: true },
+        Order { id: 2, customer: "bob".to_string(), discount: Some(0.5), tags: None,
+                shipped: false },
+    ]
+}
+
+// The schema has an attribute per field, options are nullable
+#[test]
+fn derived_schema() {
+    let schema = Order::schema();
+    assert_eq!(schema.count(), 5);
+    assert_eq!(schema[0].name, "id");
+    assert!(schema[0].dtype == Type::INT64);
+    assert!(!schema[0].nullable);
+    assert!(schema[2].dtype == Type::FLOAT64);
+    assert!(schema[2].nullable);
+    assert!(schema[3].dtype == Type::BLOB);
+}
+
+// Records loaded into a table are collected back unchanged
+#[test]
+fn round_trip() {
+    let records = orders();
+    let table = records_table(&allocator::GLOBAL, &records).unwrap();
+    assert_eq!(table.rows(), 2);
+    assert!(table.block_ref().value(1, 1).unwrap() == Value::TEXT("bob"));
+    assert!(table.block_ref().value(0, 2).unwrap() == Value::NULL);
+
+    let collected: Vec<Order> = collect_records(table.block_ref()).unwrap();
+    assert_eq!(collected, records);
+
+    match Order::from_row(&[Value::INT64(1)]) {
+        Err(DBError::ExpressionInputCount(_)) => (), // nop
+        Err(e) => assert!(false, "Unexpected error {}", e),
+        Ok(_) => assert!(false, "Expected error"),
+    }
+
+    let mistyped = [Value::INT32(1), Value::TEXT("carol"), Value::NULL, Value::NULL,
+                    Value::BOOLEAN(false)];
+    match Order::from_row(&mistyped) {
+        Err(DBError::AttributeType(ref name)) => assert_eq!(name, "id"),
+        Err(e) => assert!(false, "Unexpected error {}", e),
+        Ok(_) => assert!(false, "Expected error"),
+    }
+}
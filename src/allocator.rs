@@ -4,9 +4,13 @@ use alloc::heap::{Alloc, AllocErr, Heap, Layout};
 use std::mem;
 use std::ptr;
 use std::slice;
-use std::cmp::min;
+use std::cmp::{max, min};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use super::error::DBError;
+use super::types::RawData;
+#[cfg(any(all(feature = "hugepages", target_os = "linux"), all(feature = "mmap", unix)))]
+use super::util::math::round_up;
 
 /// Minimum alignment for platform.
 ///
@@ -17,11 +21,23 @@ use super::error::DBError;
 // const MIN_ALIGN: usize = mem::size_of::<usize>();
 pub const MIN_ALIGN: usize = 32;
 
+/// Default alignment of `Allocator::allocate` chunks (eg. column data): a cache line, which is
+/// enough for aligned AVX-512 loads and what Arrow expects of its buffers.
+pub const DEFAULT_ALIGN: usize = 64;
+
 /// Allocator trait, used through out the operations in dbkit.
 ///
 /// Allocators have to maintain their own synchronization
 pub trait Allocator : Send + Sync {
-    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError>;
+    /// Alignment guaranteed for `allocate` chunks, a power of two not smaller than `MIN_ALIGN`
+    fn alignment(&self) -> usize {
+        DEFAULT_ALIGN
+    }
+
+    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
+        self.allocate_aligned(size, self.alignment())
+    }
+
     fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError>;
 
     /// Resize; will try to resize in place if possible
@@ -92,19 +108,34 @@ impl<'a> Drop for OwnedChunk<'a> {
 }
 
 /// Simple heap allocator without memory tracking
-pub struct HeapAllocator { }
+pub struct HeapAllocator {
+    align: usize,
+}
 
 
 unsafe impl Send for HeapAllocator{}
 unsafe impl Sync for HeapAllocator{}
 
 /// A instance of default allocator when you don't care memory accounting, limitation
-pub static GLOBAL: HeapAllocator = HeapAllocator{};
+pub static GLOBAL: HeapAllocator = HeapAllocator{ align: DEFAULT_ALIGN };
+
+impl HeapAllocator {
+    /// Heap allocator with `align` aligned chunks. The alignment has to be a power of two, it's
+    /// raised to `MIN_ALIGN` if smaller.
+    pub fn with_alignment(align: usize) -> Result<HeapAllocator, DBError> {
+        if !align.is_power_of_two() {
+            let err = AllocErr::Unsupported{details: "Alignment not a power of two"};
+            return Err(DBError::Memory(err))
+        }
+
+        Ok(HeapAllocator { align: max(align, MIN_ALIGN) })
+    }
+}
 
 /// Simple heap allocator that delegates to `alloc::heap`
 impl Allocator for HeapAllocator {
-    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
-        self.allocate_aligned(size, MIN_ALIGN)
+    fn alignment(&self) -> usize {
+        self.align
     }
 
     fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
@@ -149,9 +180,428 @@ impl Allocator for HeapAllocator {
     }
 }
 
-/// Result of arena append
-/// Chunk offset & pointer
-pub struct ArenaAppend(pub usize, pub *mut u8);
+/// Size of (x86-64) huge pages
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// Allocator that backs large chunks with huge pages, reducing TLB misses when scanning large
+/// columns. Chunks smaller than the threshold come from the heap.
+///
+/// Uses explicit (hugetlbfs) pages when requested and available, falls back to transparent huge
+/// pages otherwise. Huge page chunks are rounded up to `HUGE_PAGE_SIZE`.
+#[cfg(all(feature = "hugepages", target_os = "linux"))]
+pub struct HugePageAllocator {
+    threshold: usize,
+    explicit: bool,
+}
+
+#[cfg(all(feature = "hugepages", target_os = "linux"))]
+impl HugePageAllocator {
+    /// Transparent huge pages for chunks of at least `threshold` bytes
+    pub fn transparent(threshold: usize) -> HugePageAllocator {
+        HugePageAllocator { threshold: threshold, explicit: false }
+    }
+
+    /// Explicit huge pages (reserved by the administrator) for chunks of at least `threshold`
+    /// bytes
+    pub fn explicit(threshold: usize) -> HugePageAllocator {
+        HugePageAllocator { threshold: threshold, explicit: true }
+    }
+
+    /// Huge page chunks are page aligned, larger alignments come from the heap
+    fn is_huge(&self, size: usize, align: usize) -> bool {
+        size >= self.threshold && size > 0 && align <= 4096
+    }
+
+    unsafe fn map(&self, size: usize) -> Result<*mut u8, DBError> {
+        use libc::*;
+
+        let len = round_up(size, HUGE_PAGE_SIZE);
+        let prot = PROT_READ | PROT_WRITE;
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+
+        if self.explicit {
+            let ptr = mmap(ptr::null_mut(), len, prot, flags | MAP_HUGETLB, -1, 0);
+            if ptr != MAP_FAILED {
+                return Ok(ptr as *mut u8)
+            }
+        }
+
+        let ptr = mmap(ptr::null_mut(), len, prot, flags, -1, 0);
+        if ptr == MAP_FAILED {
+            return Err(DBError::MemoryLimit)
+        }
+
+        // Only advice, the kernel might not have THP enabled
+        madvise(ptr, len, MADV_HUGEPAGE);
+        Ok(ptr as *mut u8)
+    }
+
+    unsafe fn raw_allocate(&self, size: usize, align: usize) -> Result<*mut u8, DBError> {
+        if self.is_huge(size, align) {
+            self.map(size)
+        } else {
+            Heap.alloc(Layout::from_size_align_unchecked(size, align))
+                .map_err(|err| DBError::Memory(err))
+        }
+    }
+
+    unsafe fn unmap(&self, ptr: *mut u8, size: usize) {
+        ::libc::munmap(ptr as *mut ::libc::c_void, round_up(size, HUGE_PAGE_SIZE));
+    }
+}
+
+#[cfg(all(feature = "hugepages", target_os = "linux"))]
+impl Allocator for HugePageAllocator {
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        unsafe {
+            let data = self.raw_allocate(size, align)?;
+            let slice = slice::from_raw_parts_mut::<u8>(data, size);
+            Ok(OwnedChunk { parent: Some(self), data: Some(slice), align: align })
+        }
+    }
+
+    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Option<DBError> {
+        let old_huge = self.is_huge(prev.len(), prev.align);
+        let new_huge = self.is_huge(size, prev.align);
+
+        if !old_huge && !new_huge {
+            return GLOBAL.resize(prev, size)
+        }
+
+        // Fits within the already mapped huge pages
+        let same_pages = round_up(size, HUGE_PAGE_SIZE) == round_up(prev.len(), HUGE_PAGE_SIZE);
+        if old_huge && new_huge && same_pages {
+            let data = prev.as_mut_ptr();
+            prev.data = Some(slice::from_raw_parts_mut::<u8>(data, size));
+            return None
+        }
+
+        let data = match self.raw_allocate(size, prev.align) {
+            Ok(data) => data,
+            Err(e) => return Some(e),
+        };
+
+        ptr::copy_nonoverlapping(prev.as_ptr(), data, min(prev.len(), size));
+        self.putback(prev);
+        prev.data = Some(slice::from_raw_parts_mut::<u8>(data, size));
+        None
+    }
+
+    fn putback(&self, c: &mut OwnedChunk) {
+        if let Some(ref mut data) = c.data {
+            self.putback_raw(data.as_mut_ptr(), data.len(), c.align)
+        }
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        if self.is_huge(size, align) {
+            unsafe { self.unmap(ptr, size) }
+        } else {
+            GLOBAL.putback_raw(ptr, size, align)
+        }
+    }
+}
+
+/// Allocator that maps large chunks directly from the OS (anonymous `mmap`), for very large
+/// arenas and buffers. Unlike heap memory, the pages of a returned chunk are released back to the
+/// OS right away. Chunks smaller than the threshold come from the heap.
+///
+/// Mapped chunks are page aligned and rounded up to whole pages.
+#[cfg(all(feature = "mmap", unix))]
+pub struct MmapAllocator {
+    threshold: usize,
+    page_size: usize,
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl MmapAllocator {
+    /// Mapped chunks for chunks of at least `threshold` bytes
+    pub fn new(threshold: usize) -> MmapAllocator {
+        let page_size = unsafe { ::libc::sysconf(::libc::_SC_PAGESIZE) };
+        let page_size = if page_size > 0 { page_size as usize } else { 4096 };
+        MmapAllocator { threshold: threshold, page_size: page_size }
+    }
+
+    /// Mapped chunks are page aligned, larger alignments come from the heap
+    fn is_mapped(&self, size: usize, align: usize) -> bool {
+        size >= self.threshold && size > 0 && align <= self.page_size
+    }
+
+    unsafe fn raw_allocate(&self, size: usize, align: usize) -> Result<*mut u8, DBError> {
+        use libc::*;
+
+        if !self.is_mapped(size, align) {
+            return Heap.alloc(Layout::from_size_align_unchecked(size, align))
+                .map_err(|err| DBError::Memory(err))
+        }
+
+        let len = round_up(size, self.page_size);
+        let ptr = mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS,
+                       -1, 0);
+        if ptr == MAP_FAILED {
+            return Err(DBError::MemoryLimit)
+        }
+
+        Ok(ptr as *mut u8)
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Allocator for MmapAllocator {
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        unsafe {
+            let data = self.raw_allocate(size, align)?;
+            let slice = slice::from_raw_parts_mut::<u8>(data, size);
+            Ok(OwnedChunk { parent: Some(self), data: Some(slice), align: align })
+        }
+    }
+
+    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Option<DBError> {
+        let old_mapped = self.is_mapped(prev.len(), prev.align);
+        let new_mapped = self.is_mapped(size, prev.align);
+
+        if !old_mapped && !new_mapped {
+            return GLOBAL.resize(prev, size)
+        }
+
+        // Fits within the already mapped pages
+        let same_pages = round_up(size, self.page_size) == round_up(prev.len(), self.page_size);
+        if old_mapped && new_mapped && same_pages {
+            let data = prev.as_mut_ptr();
+            prev.data = Some(slice::from_raw_parts_mut::<u8>(data, size));
+            return None
+        }
+
+        let data = match self.raw_allocate(size, prev.align) {
+            Ok(data) => data,
+            Err(e) => return Some(e),
+        };
+
+        ptr::copy_nonoverlapping(prev.as_ptr(), data, min(prev.len(), size));
+        self.putback(prev);
+        prev.data = Some(slice::from_raw_parts_mut::<u8>(data, size));
+        None
+    }
+
+    fn putback(&self, c: &mut OwnedChunk) {
+        if let Some(ref mut data) = c.data {
+            self.putback_raw(data.as_mut_ptr(), data.len(), c.align)
+        }
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        if self.is_mapped(size, align) {
+            unsafe { ::libc::munmap(ptr as *mut ::libc::c_void, round_up(size, self.page_size)); }
+        } else {
+            GLOBAL.putback_raw(ptr, size, align)
+        }
+    }
+}
+
+/// Allocator backend selection, for picking the allocator of a query (or of a single block) from
+/// configuration. Backends that aren't compiled in (see the `mmap` and `hugepages` features) are
+/// `DBError::Unsupported`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    /// `HeapAllocator` with the default alignment
+    Heap,
+    /// `MmapAllocator` mapping chunks of at least `threshold` bytes
+    Mmap { threshold: usize },
+    /// `HugePageAllocator` for chunks of at least `threshold` bytes, with explicit or transparent
+    /// huge pages
+    HugePages { threshold: usize, explicit: bool },
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Backend::Heap           => "heap",
+            Backend::Mmap { .. }    => "mmap",
+            Backend::HugePages { .. } => "hugepages",
+        }
+    }
+
+    /// New allocator of the backend
+    pub fn create(&self) -> Result<Box<Allocator>, DBError> {
+        match *self {
+            Backend::Heap => Ok(Box::new(HeapAllocator { align: DEFAULT_ALIGN })),
+            #[cfg(all(feature = "mmap", unix))]
+            Backend::Mmap { threshold } => Ok(Box::new(MmapAllocator::new(threshold))),
+            #[cfg(all(feature = "hugepages", target_os = "linux"))]
+            Backend::HugePages { threshold, explicit: true } =>
+                Ok(Box::new(HugePageAllocator::explicit(threshold))),
+            #[cfg(all(feature = "hugepages", target_os = "linux"))]
+            Backend::HugePages { threshold, explicit: false } =>
+                Ok(Box::new(HugePageAllocator::transparent(threshold))),
+            #[allow(unreachable_patterns)]
+            _ => Err(DBError::Unsupported(format!("{} allocator not compiled in", self.name()))),
+        }
+    }
+}
+
+/// Allocator charging every allocation to a memory pool, to bound the memory used by a query (or
+/// any part of it).
+///
+/// Trackers form a hierarchy: an allocation is charged to the tracker and all of its parents, and
+/// fails with `DBError::MemoryLimit` if any of them would go over its limit. Memory is allocated
+/// from the underlying allocator, returned memory is credited back.
+pub struct MemoryTracker<'a> {
+    name: String,
+    alloc: &'a Allocator,
+    parent: Option<&'a MemoryTracker<'a>>,
+    /// Maximum of bytes charged at a time, `None` for no limit
+    limit: Option<usize>,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl<'a> MemoryTracker<'a> {
+    /// Root tracker allocating from `alloc`
+    pub fn new(alloc: &'a Allocator, name: &str, limit: Option<usize>) -> MemoryTracker<'a> {
+        MemoryTracker {
+            name: name.to_string(),
+            alloc: alloc,
+            parent: None,
+            limit: limit,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Tracker whose allocations are also charged to this one (eg. an operator of a query)
+    pub fn child(&'a self, name: &str, limit: Option<usize>) -> MemoryTracker<'a> {
+        MemoryTracker {
+            name: name.to_string(),
+            alloc: self.alloc,
+            parent: Some(self),
+            limit: limit,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Bytes currently charged, including to the children
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Largest number of bytes charged at a time
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    fn update_peak(&self, used: usize) {
+        let mut peak = self.peak.load(Ordering::Relaxed);
+        while used > peak {
+            match self.peak.compare_exchange_weak(peak, used, Ordering::Relaxed,
+                                                  Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => peak = current,
+            }
+        }
+    }
+
+    /// Charge `size` bytes to this tracker and its parents, nothing is charged on failure
+    pub fn reserve(&self, size: usize) -> Result<(), DBError> {
+        let mut used = self.used.load(Ordering::Relaxed);
+        loop {
+            let next = used.checked_add(size).ok_or(DBError::MemoryLimit)?;
+            if self.limit.map_or(false, |limit| next > limit) {
+                return Err(DBError::MemoryLimit)
+            }
+
+            match self.used.compare_exchange_weak(used, next, Ordering::Relaxed,
+                                                  Ordering::Relaxed) {
+                Ok(_) => {
+                    self.update_peak(next);
+                    break
+                },
+                Err(current) => used = current,
+            }
+        }
+
+        if let Some(parent) = self.parent {
+            if let Err(e) = parent.reserve(size) {
+                self.used.fetch_sub(size, Ordering::Relaxed);
+                return Err(e)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Credit back `size` bytes previously reserved
+    pub fn release(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+        if let Some(parent) = self.parent {
+            parent.release(size);
+        }
+    }
+}
+
+/// Allocations charged to the tracker, made by the underlying allocator
+impl<'a> Allocator for MemoryTracker<'a> {
+    fn alignment(&self) -> usize {
+        self.alloc.alignment()
+    }
+
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        self.reserve(size)?;
+
+        match self.alloc.allocate_aligned(size, align) {
+            Ok(mut chunk) => {
+                // Returned to the tracker, so it's credited back
+                chunk.parent = Some(self);
+                Ok(chunk)
+            },
+            Err(e) => {
+                self.release(size);
+                Err(e)
+            },
+        }
+    }
+
+    unsafe fn resize<'c>(&self, prev: &mut OwnedChunk<'c>, size: usize) -> Option<DBError> {
+        let old = prev.len();
+
+        if size > old {
+            if let Err(e) = self.reserve(size - old) {
+                return Some(e)
+            }
+        }
+
+        let status = self.alloc.resize(prev, size);
+
+        if status.is_some() {
+            if size > old {
+                self.release(size - old);
+            }
+        } else if size < old {
+            self.release(old - size);
+        }
+
+        status
+    }
+
+    fn putback(&self, c: &mut OwnedChunk) {
+        if let Some(ref mut data) = c.data {
+            self.putback_raw(data.as_mut_ptr(), data.len(), c.align)
+        }
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        self.alloc.putback_raw(ptr, size, align);
+        self.release(size);
+    }
+}
 
 /// Arena styled allocator. Stores data in non-relocatable/non-movable arenas.
 ///
@@ -161,6 +611,9 @@ pub struct ChainedArena<'a> {
     chunks: Vec<&'a mut [u8]>,
     min_size: usize,
     max_size: usize,
+    /// Chunk being allocated from
+    current: usize,
+    /// Position in the current chunk
     pos: usize,
 }
 
@@ -184,6 +637,7 @@ impl<'a> ChainedArena<'a> {
             chunks: Vec::new(),
             min_size: min_size,
             max_size: max_size,
+            current: 0,
             pos: 0,
         }
     }
@@ -193,31 +647,85 @@ impl<'a> ChainedArena<'a> {
             return Err(DBError::MemoryLimit);
         }
 
-        let new_size = if let Some(ref mut arena) = self.chunks.last_mut() {
-            if arena.len() - self.pos >= size {
-                let ptr = arena.as_mut_ptr().offset(size as isize);
-                self.pos += size;
-                return Ok(ptr);
+        // Continue in the current chunk, then in the following ones kept by `reset()`
+        loop {
+            if let Some(arena) = self.chunks.get_mut(self.current) {
+                if arena.len() - self.pos >= size {
+                    let ptr = arena.as_mut_ptr().offset(self.pos as isize);
+                    self.pos += size;
+                    return Ok(ptr);
+                }
             }
 
-            min(arena.len() * 2, self.max_size)
-        } else {
-            self.min_size
-        };
+            if self.current + 1 >= self.chunks.len() {
+                break
+            }
+
+            self.current += 1;
+            self.pos = 0;
+        }
+
+        // The last chunk is the largest one
+        let new_size = self.chunks.last()
+            .map_or(self.min_size, |arena| min(arena.len() * 2, self.max_size));
 
-        let new_arena = make_arena(self.parent, new_size)?;
+        // Value might not fit into the minimum next size
+        let new_arena = make_arena(self.parent, max(new_size, size))?;
         let ptr = new_arena.as_mut_ptr();
 
         self.chunks.push(new_arena);
+        self.current = self.chunks.len() - 1;
+        self.pos = size;
         Ok(ptr)
     }
 
-    pub fn append(&mut self, data: &[u8]) -> Result<ArenaAppend, DBError> {
+    /// Forget all the arena allocations. The chunks are kept around (in allocation order) for
+    /// re-use, so refilling with as much data doesn't allocate.
+    pub fn reset(&mut self) {
+        self.current = 0;
+        self.pos = 0;
+    }
+
+    /// Copy of the arena with the same chunks, so handles of this arena are valid in the copy
+    pub fn try_clone(&self) -> Result<ChainedArena<'a>, DBError> {
+        let mut out = ChainedArena::new(self.parent, self.min_size, self.max_size);
+        for chunk in &self.chunks {
+            let copy = unsafe { make_arena(self.parent, chunk.len())? };
+            copy.copy_from_slice(chunk);
+            out.chunks.push(copy);
+        }
+
+        out.current = self.current;
+        out.pos = self.pos;
+        Ok(out)
+    }
+
+    /// Bytes allocated from the parent allocator
+    pub fn capacity(&self) -> usize {
+        self.chunks.iter().map(|arena| arena.len()).sum()
+    }
+
+    /// Chunks allocated from, `Heap::Chunks` of the `append` handles
+    pub fn chunks(&self) -> &[&'a mut [u8]] {
+        &self.chunks
+    }
+
+    /// Copy the data into the arena, returns its handle in the arena chunks
+    pub fn append(&mut self, data: &[u8]) -> Result<RawData, DBError> {
+        if data.is_empty() {
+            return Ok(RawData::default())
+        }
+
         unsafe {
             let ptr = self.allocate(data.len())?;
             ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
-            Ok(ArenaAppend(self.chunks.len(), ptr))
         }
+
+        Ok(RawData {
+            chunk: self.current as u32,
+            offset: (self.pos - data.len()) as u32,
+            size: data.len() as u32,
+        })
     }
 }
 
@@ -231,3 +739,152 @@ impl<'a> Drop for ChainedArena<'a> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Allocations are charged to the tracker and its parents, up to the limits
+    #[test]
+    fn tracker_limits() {
+        let query = MemoryTracker::new(&GLOBAL, "query", Some(4096));
+        let scan = query.child("scan", Some(2048));
+        let sort = query.child("sort", None);
+
+        let mut a = scan.allocate(1024).unwrap();
+        let b = sort.allocate(2560).unwrap();
+        assert_eq!((scan.used(), sort.used(), query.used()), (1024, 2560, 3584));
+
+        // Over the scan limit, then over the query limit
+        match scan.allocate(1536) {
+            Err(DBError::MemoryLimit) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        assert!(a.resize(2048).is_some());
+        assert_eq!((scan.used(), query.used()), (1024, 3584));
+
+        assert!(a.resize(512).is_none());
+        assert_eq!((scan.used(), query.used()), (512, 3072));
+
+        drop(b);
+        assert_eq!((sort.used(), query.used()), (0, 512));
+        assert_eq!(query.peak(), 3584);
+
+        drop(a);
+        assert_eq!(query.used(), 0);
+    }
+
+    // Chunks have the allocator alignment unless asked for a specific one
+    #[test]
+    fn aligned_chunks() {
+        let wide = HeapAllocator::with_alignment(256).unwrap();
+        let tracker = MemoryTracker::new(&wide, "wide", None);
+        assert_eq!(GLOBAL.alignment(), DEFAULT_ALIGN);
+        assert_eq!(tracker.alignment(), 256);
+        assert_eq!(HeapAllocator::with_alignment(8).unwrap().alignment(), MIN_ALIGN);
+        assert!(HeapAllocator::with_alignment(48).is_err());
+
+        for size in &[1, 100, 4096] {
+            let chunk = GLOBAL.allocate(*size).unwrap();
+            assert_eq!(unsafe { chunk.as_ptr() } as usize % DEFAULT_ALIGN, 0);
+
+            let mut chunk = tracker.allocate(*size).unwrap();
+            assert_eq!(unsafe { chunk.as_ptr() } as usize % 256, 0);
+            assert!(chunk.resize(size * 3).is_none());
+            assert_eq!(unsafe { chunk.as_ptr() } as usize % 256, 0);
+        }
+    }
+
+    // Appended data is addressed by handles (also in arena copies), reset arenas reuse their
+    // chunks before allocating new ones
+    #[test]
+    fn arena_reuse() {
+        let tracker = MemoryTracker::new(&GLOBAL, "arena", None);
+        let mut arena = ChainedArena::new(&tracker, 64, 1024);
+        let value = [7u8; 40];
+
+        let handles: Vec<RawData> = (0 .. 10).map(|_| arena.append(&value).unwrap()).collect();
+        for handle in &handles {
+            assert_eq!(::types::Heap::Chunks(arena.chunks()).bytes(handle), &value[..]);
+        }
+
+        let used = tracker.used();
+        assert_eq!(arena.capacity(), used);
+
+        for _ in 0 .. 3 {
+            arena.reset();
+            for _ in 0 .. 10 {
+                arena.append(&value).unwrap();
+            }
+            assert_eq!(tracker.used(), used);
+        }
+
+        let copy = arena.try_clone().unwrap();
+        assert_eq!(tracker.used(), used * 2);
+        assert_eq!(::types::Heap::Chunks(copy.chunks()).bytes(&handles[9]), &value[..]);
+
+        drop(copy);
+        drop(arena);
+        assert_eq!(tracker.used(), 0);
+    }
+
+    // Backends that are compiled in can be created, others are unsupported
+    #[test]
+    fn backends() {
+        let heap = Backend::Heap.create().unwrap();
+        let chunk = heap.allocate(100).unwrap();
+        assert_eq!(chunk.len(), 100);
+
+        let mmap = Backend::Mmap { threshold: 4096 }.create();
+        assert_eq!(mmap.is_ok(), cfg!(all(feature = "mmap", unix)));
+
+        let huge = Backend::HugePages { threshold: HUGE_PAGE_SIZE, explicit: false }.create();
+        match huge {
+            Ok(_) => assert!(cfg!(all(feature = "hugepages", target_os = "linux"))),
+            Err(DBError::Unsupported(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+        }
+    }
+
+    // Chunks move between the heap and mapped pages as they cross the threshold
+    #[cfg(all(feature = "mmap", unix))]
+    #[test]
+    fn mmap_resize() {
+        let alloc = MmapAllocator::new(1 << 16);
+        let tracker = MemoryTracker::new(&alloc, "mmap", None);
+
+        let mut chunk = tracker.allocate(1024).unwrap();
+        unsafe { ptr::write_bytes(chunk.as_mut_ptr(), 7, 1024) };
+
+        assert!(chunk.resize(1 << 20).is_none());
+        assert_eq!(unsafe { chunk.as_ptr() } as usize % alloc.page_size, 0);
+        assert_eq!(chunk.data.as_ref().unwrap()[1023], 7);
+        assert_eq!(tracker.used(), 1 << 20);
+
+        assert!(chunk.resize((1 << 20) + 1).is_none());
+        assert!(chunk.resize(512).is_none());
+        assert_eq!(chunk.data.as_ref().unwrap()[511], 7);
+
+        drop(chunk);
+        assert_eq!(tracker.used(), 0);
+    }
+
+    // Chunks move between the heap and huge pages as they cross the threshold
+    #[cfg(all(feature = "hugepages", target_os = "linux"))]
+    #[test]
+    fn huge_page_resize() {
+        let alloc = HugePageAllocator::transparent(HUGE_PAGE_SIZE);
+
+        let mut chunk = alloc.allocate(1024).unwrap();
+        unsafe { ptr::write_bytes(chunk.as_mut_ptr(), 7, 1024) };
+
+        assert!(chunk.resize(3 * HUGE_PAGE_SIZE).is_none());
+        assert_eq!(chunk.len(), 3 * HUGE_PAGE_SIZE);
+        assert_eq!(chunk.data.as_ref().unwrap()[1023], 7);
+
+        assert!(chunk.resize(3 * HUGE_PAGE_SIZE + 1).is_none());
+        assert!(chunk.resize(512).is_none());
+        assert_eq!(chunk.data.as_ref().unwrap()[511], 7);
+    }
+}
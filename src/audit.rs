@@ -0,0 +1,413 @@
+// vim: set ts=4 sw=4 et :
+
+//! Statement audit log.
+//!
+//! An `AuditLog` records each executed statement: who ran it (the principal), the statement (SQL
+//! text, or eg. the `explain()` of a plan), the tables it references, when it started and
+//! finished, the rows it returned and the error of a failed statement. Records are written to an
+//! `AuditSink`: a `FileSink` appending JSON lines, or a `TableSink` collecting rows that can be
+//! queried with the engine.
+//!
+//! Statements are audited by wrapping their operation in `Audited`, or by running SQL with
+//! `SqlContext::query` on a context with an audit log.
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ::allocator::Allocator;
+use ::block::View;
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk, ExecutionContext, Operation};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::{Table, TableAppender};
+use ::types::{Type, Value};
+use ::util::temporal::format_timestamp;
+
+/// Audited execution of a statement
+#[derive(Clone, PartialEq, Debug)]
+pub struct AuditRecord {
+    pub principal: String,
+    pub statement: String,
+    /// Names of the referenced tables
+    pub tables: Vec<String>,
+    /// Microseconds since Unix epoch (UTC), like TIMESTAMP values
+    pub started: i64,
+    pub finished: i64,
+    pub rows: RowOffset,
+    /// Error of a failed statement
+    pub error: Option<String>,
+}
+
+/// Destination of audit records
+pub trait AuditSink {
+    fn write(&self, record: &AuditRecord) -> Result<(), DBError>;
+}
+
+/// Appends a JSON object per record to a file
+pub struct FileSink {
+    out: RefCell<File>,
+}
+
+/// Collects the records as rows of a table with the `audit_schema()`
+pub struct TableSink<'a> {
+    alloc: &'a Allocator,
+    table: RefCell<Table<'a>>,
+}
+
+/// Audit hook: the principal statements run as and where they're recorded
+#[derive(Clone)]
+pub struct AuditLog<'a> {
+    pub principal: String,
+    pub sink: Rc<AuditSink + 'a>,
+}
+
+/// Passes the source rows through unchanged, writing an audit record once the source is
+/// exhausted, fails, or the cursor is dropped before the end.
+pub struct Audited<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub log: AuditLog<'a>,
+    pub statement: String,
+    pub tables: Vec<String>,
+}
+
+/// Implementation of the `Audited` operation
+struct AuditedCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    sink: Rc<AuditSink + 'a>,
+    /// Written once, None afterwards
+    record: Option<AuditRecord>,
+}
+
+/// Current time in microseconds since Unix epoch
+pub fn now_micros() -> i64 {
+    let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    since.as_secs() as i64 * 1_000_000 + since.subsec_micros() as i64
+}
+
+/// Schema of the `TableSink` table. Tables are a comma separated list.
+pub fn audit_schema() -> Schema {
+    let attr = |name: &str, nullable, dtype| {
+        Attribute { name: name.to_string(), nullable: nullable, dtype: dtype }
+    };
+
+    Schema::from_vec(vec![
+        attr("principal", false, Type::TEXT),
+        attr("statement", false, Type::TEXT),
+        attr("tables", false, Type::TEXT),
+        attr("started", false, Type::TIMESTAMP),
+        attr("finished", false, Type::TIMESTAMP),
+        attr("rows", false, Type::UINT64),
+        attr("error", true, Type::TEXT),
+    ]).unwrap()
+}
+
+/// JSON string literal of `s`
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"'             => out.push_str("\\\""),
+            '\\'            => out.push_str("\\\\"),
+            '\n'            => out.push_str("\\n"),
+            '\r'            => out.push_str("\\r"),
+            '\t'            => out.push_str("\\t"),
+            c if c < ' '    => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c               => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+impl AuditRecord {
+    /// Single line JSON object, with timestamps formatted like TIMESTAMP values (in UTC)
+    pub fn to_json(&self) -> String {
+        let tables: Vec<String> = self.tables.iter().map(|t| json_string(t)).collect();
+        let error = self.error.as_ref().map_or(String::from("null"), |e| json_string(e));
+
+        format!("{{\"principal\":{},\"statement\":{},\"tables\":[{}],\"started\":{},\
+                 \"finished\":{},\"rows\":{},\"error\":{}}}",
+                json_string(&self.principal), json_string(&self.statement), tables.join(","),
+                json_string(&format_timestamp(self.started)),
+                json_string(&format_timestamp(self.finished)), self.rows, error)
+    }
+}
+
+impl FileSink {
+    /// Append to the file, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileSink, DBError> {
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(DBError::IO)?;
+        Ok(FileSink { out: RefCell::new(file) })
+    }
+}
+
+impl AuditSink for FileSink {
+    fn write(&self, record: &AuditRecord) -> Result<(), DBError> {
+        let line = record.to_json() + "\n";
+        self.out.borrow_mut().write_all(line.as_bytes()).map_err(DBError::IO)
+    }
+}
+
+impl<'a> TableSink<'a> {
+    pub fn new(alloc: &'a Allocator) -> TableSink<'a> {
+        TableSink { alloc: alloc, table: RefCell::new(Table::new(alloc, &audit_schema(), None)) }
+    }
+
+    pub fn rows(&self) -> RowOffset {
+        self.table.borrow().rows()
+    }
+
+    /// Take the records collected so far, leaving an empty table
+    pub fn take(&self) -> Table<'a> {
+        let empty = Table::new(self.alloc, &audit_schema(), None);
+        mem::replace(&mut *self.table.borrow_mut(), empty)
+    }
+}
+
+impl<'a> AuditSink for TableSink<'a> {
+    fn write(&self, record: &AuditRecord) -> Result<(), DBError> {
+        let mut table = self.table.borrow_mut();
+
+        let appender = TableAppender::new(&mut table)
+            .add_row()
+            .set(record.principal.as_str())
+            .set(record.statement.as_str())
+            .set(record.tables.join(","))
+            .set(Value::TIMESTAMP(record.started))
+            .set(Value::TIMESTAMP(record.finished))
+            .set(record.rows as u64);
+
+        let mut appender = match record.error {
+            Some(ref e) => appender.set(e.as_str()),
+            None => appender.set_null(true),
+        };
+
+        appender.done().map_or(Ok(()), Err)
+    }
+}
+
+impl<'a> AuditLog<'a> {
+    pub fn new<S, T>(principal: S, sink: T) -> AuditLog<'a>
+        where S: Into<String>, T: AuditSink + 'a
+    {
+        AuditLog { principal: principal.into(), sink: Rc::new(sink) }
+    }
+
+    /// Log writing to a shared sink (eg. a `TableSink` that's read back)
+    pub fn shared<S: Into<String>>(principal: S, sink: Rc<AuditSink + 'a>) -> AuditLog<'a> {
+        AuditLog { principal: principal.into(), sink: sink }
+    }
+
+    /// Record a statement that failed before it could be executed (eg. a query that's invalid)
+    pub fn failed(&self, statement: &str, started: i64, error: &DBError) -> Result<(), DBError> {
+        self.sink.write(&AuditRecord {
+            principal: self.principal.clone(),
+            statement: statement.to_string(),
+            tables: Vec::new(),
+            started: started,
+            finished: now_micros(),
+            rows: 0,
+            error: Some(error.to_string()),
+        })
+    }
+}
+
+impl<'a> Audited<'a> {
+    pub fn new<S: Into<String>>(log: AuditLog<'a>, statement: S, tables: Vec<String>,
+                                src: Box<Operation<'a> + 'a>) -> Audited<'a>
+    {
+        Audited { src: src, log: log, statement: statement.into(), tables: tables }
+    }
+}
+
+impl<'a> Operation<'a> for Audited<'a> {
+    fn describe(&self) -> String {
+        format!("Audited {}", self.statement)
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let mut record = AuditRecord {
+            principal: self.log.principal.clone(),
+            statement: self.statement.clone(),
+            tables: self.tables.clone(),
+            started: now_micros(),
+            finished: 0,
+            rows: 0,
+            error: None,
+        };
+
+        let input = match self.src.bind_context(ctx) {
+            Ok(input) => input,
+            Err(e) => {
+                record.finished = now_micros();
+                record.error = Some(e.to_string());
+                self.log.sink.write(&record)?;
+                return Err(e)
+            },
+        };
+
+        Ok(Box::new(AuditedCursor {
+            input: input,
+            sink: self.log.sink.clone(),
+            record: Some(record),
+        }))
+    }
+}
+
+impl<'a> AuditedCursor<'a> {
+    fn finish(&mut self, error: Option<String>) -> Result<(), DBError> {
+        match self.record.take() {
+            Some(mut record) => {
+                record.finished = now_micros();
+                record.error = error;
+                self.sink.write(&record)
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Cursor<'a> for AuditedCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let chunk = match self.input.next(rows) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                self.finish(Some(e.to_string()))?;
+                return Err(e)
+            },
+        };
+
+        match chunk {
+            CursorChunk::Next(ref view) => {
+                if let Some(ref mut record) = self.record {
+                    record.rows += view.rows();
+                }
+            },
+            CursorChunk::End => self.finish(None)?,
+        }
+
+        Ok(chunk)
+    }
+}
+
+impl<'a> Drop for AuditedCursor<'a> {
+    fn drop(&mut self) {
+        // Abandoned before the end, there's no one to report a failed write to
+        let _ = self.finish(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use ::allocator;
+    use ::block::column_value;
+    use ::operation::ScanView;
+
+    fn make_table<'a>() -> Table<'a> {
+        let schema = Schema::make_one_attr("v", false, Type::UINT64);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 25 {
+                appender = appender.add_row().set(v as u64);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table
+    }
+
+    // One record per execution, with the rows returned
+    #[test]
+    fn audited_rows() {
+        let table = make_table();
+        let sink = Rc::new(TableSink::new(&allocator::GLOBAL));
+        let log = AuditLog::shared("alice", sink.clone());
+
+        let op = Audited::new(log.clone(), "scan t", vec!["t".to_string()],
+                              Box::new(ScanView::new(&table, None)));
+        {
+            let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+            while let CursorChunk::Next(_) = cursor.next(10).unwrap() {}
+            assert!(cursor.next(10).is_ok());
+        }
+
+        // Dropped after the first chunk
+        {
+            let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+            assert!(cursor.next(10).is_ok());
+        }
+
+        let invalid = DBError::QueryInvalid("x".to_string());
+        assert!(log.failed("SELECT", now_micros(), &invalid).is_ok());
+
+        let records = sink.take();
+        assert_eq!(records.rows(), 3);
+        assert_eq!(sink.rows(), 0);
+
+        let value = |col: usize, row: usize| column_value(records.column(col).unwrap(), row);
+        assert!(value(0, 0).unwrap() == Value::TEXT("alice"));
+        assert!(value(2, 0).unwrap() == Value::TEXT("t"));
+        assert!(value(5, 0).unwrap() == Value::UINT64(25));
+        assert!(value(5, 1).unwrap() == Value::UINT64(10));
+        assert!(value(6, 1).unwrap() == Value::NULL);
+        assert!(value(1, 2).unwrap() == Value::TEXT("SELECT"));
+        assert!(value(6, 2).unwrap() != Value::NULL);
+    }
+
+    // JSON lines appended to the file
+    #[test]
+    fn file_sink() {
+        let path = ::std::env::temp_dir().join("dbkit_audit_file_sink.jsonl");
+        let _ = fs::remove_file(&path);
+
+        let record = AuditRecord {
+            principal: "bob".to_string(),
+            statement: "SELECT \"a\"\nFROM t".to_string(),
+            tables: vec!["t".to_string(), "u".to_string()],
+            started: 0,
+            finished: 1_000_000,
+            rows: 3,
+            error: None,
+        };
+
+        {
+            let sink = FileSink::open(&path).unwrap();
+            sink.write(&record).unwrap();
+            sink.write(&record).unwrap();
+        }
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "{\"principal\":\"bob\",\"statement\":\"SELECT \\\"a\\\"\\nFROM t\",\
+                              \"tables\":[\"t\",\"u\"],\"started\":\"1970-01-01 00:00:00\",\
+                              \"finished\":\"1970-01-01 00:00:01\",\"rows\":3,\"error\":null}");
+    }
+}
@@ -1,16 +1,26 @@
 // vim : set ts=4 sw=4 et :
 
 // libstd
+use std::cell::RefCell;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
 use std::mem;
+use std::ptr;
 use std::slice;
+use std::str;
 use std::ops::{Index, IndexMut};
+use std::sync::Arc;
 
 // DBKit
 use ::allocator::{Allocator, OwnedChunk, ChainedArena, MIN_ALIGN};
-use ::types::ValueInfo;
-use ::schema::{Attribute, Schema};
+use ::types::{self, Heap, ListData, RawData, Type, Value, ValueInfo};
+use ::schema::{Attribute, Schema, DEFAULT_BATCH_BYTES};
+use ::stats::{BlockStats, ColumnStats};
 use ::error::DBError;
+use ::intern::{InternPool, InternStats};
 use ::row::{RowOffset, RowRange};
+use ::testing::{Tolerance, views_diff};
+use ::util::copy_value::{ValueSetter, copy_column};
 use ::util::math::*;
 
 pub type BoolBitmap<'a> = &'a [u8];
@@ -28,6 +38,36 @@ pub struct ColumnRows<'a, T: ValueInfo>
 {
     pub values: &'a [T::Store],
     pub nulls: BoolBitmap<'a>,
+    /// Data of VARLEN rows
+    pub heap: Heap<'a>,
+}
+
+impl<'a, T: ValueInfo<Store=RawData>> ColumnRows<'a, T> {
+    /// Data of a VARLEN row
+    pub fn bytes(&self, row: RowOffset) -> &'a [u8] {
+        self.heap.bytes(&self.values[row])
+    }
+}
+
+impl<'a> ColumnRows<'a, types::Text> {
+    /// Text of the row, an error if it's not valid UTF-8
+    pub fn try_as_str(&self, row: RowOffset) -> Result<&'a str, str::Utf8Error> {
+        str::from_utf8(self.bytes(row))
+    }
+
+    /// Text of the row, without checking it's valid UTF-8.
+    ///
+    /// Unsafe since the data has to be valid UTF-8. TEXT columns don't guarantee it, their rows
+    /// can be written as raw bytes (eg. through `Column::rows_mut()`).
+    pub unsafe fn as_str_unchecked(&self, row: RowOffset) -> &'a str {
+        str::from_utf8_unchecked(self.bytes(row))
+    }
+
+    /// Text of the row, `DBError::InvalidUtf8` of the column if it's not valid UTF-8
+    fn checked_str(&self, row: RowOffset, column: &str) -> Result<&'a str, DBError> {
+        self.try_as_str(row)
+            .map_err(|_| DBError::InvalidUtf8 { row: row, column: column.to_string() })
+    }
 }
 
 pub struct ColumnRowsMut<'a, T: ValueInfo>
@@ -37,6 +77,91 @@ pub struct ColumnRowsMut<'a, T: ValueInfo>
     pub nulls: MutBoolBitmap<'a>,
 }
 
+/// How the column row data is stored
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    /// Row data is an array of the type's native store values
+    PLAIN,
+    /// TEXT row data is an array of `u32` codes, indexes into a dictionary of unique values. The
+    /// dictionary is the column's first child column.
+    DICTIONARY,
+    /// Runs of rows with the same value. Row data is an array of `u32` (exclusive) run end rows,
+    /// the run values are in the column's first child column. The null vector is not encoded.
+    RLE,
+}
+
+/// Size of a single row (run for RLE) in the column row data
+fn row_size(attr: &Attribute, encoding: Encoding) -> usize {
+    match encoding {
+        Encoding::PLAIN      => attr.dtype.size_of(),
+        Encoding::DICTIONARY => mem::size_of::<u32>(),
+        Encoding::RLE        => mem::size_of::<u32>(),
+    }
+}
+
+/// Rows of a DICTIONARY encoded column
+pub struct DictionaryRows<'a> {
+    pub codes: &'a [u32],
+    pub nulls: BoolBitmap<'a>,
+}
+
+/// Runs of a RLE encoded column
+pub struct RleRows<'a> {
+    /// (Exclusive) end row of each run, in row order
+    pub run_ends: &'a [u32],
+    /// Rows of the encoded data the column refers to
+    pub window: RowRange,
+    pub nulls: BoolBitmap<'a>,
+}
+
+/// Iterator over the runs in a RLE column window
+pub struct RleRuns<'a> {
+    run_ends: &'a [u32],
+    window: RowRange,
+    run: usize,
+    row: RowOffset,
+}
+
+impl<'a> RleRows<'a> {
+    /// Run containing the column row
+    pub fn run_of(&self, row: RowOffset) -> usize {
+        let target = (self.window.offset + row) as u32;
+        match self.run_ends.binary_search(&target) {
+            Ok(pos) => pos + 1,
+            Err(pos) => pos,
+        }
+    }
+
+    /// Runs as (run, first column row, rows in the run), limited to the column window
+    pub fn runs(&self) -> RleRuns<'a> {
+        RleRuns { run_ends: self.run_ends, window: self.window, run: self.run_of(0), row: 0 }
+    }
+}
+
+impl<'a> Iterator for RleRuns<'a> {
+    type Item = (usize, RowOffset, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row >= self.window.rows || self.run >= self.run_ends.len() {
+            return None
+        }
+
+        let end = self.run_ends[self.run] as usize - self.window.offset;
+        let end = min(end, self.window.rows);
+        let out = (self.run, self.row, end - self.row);
+
+        self.run += 1;
+        self.row = end;
+        Some(out)
+    }
+}
+
+/// Rows of a LIST column. Each row is a range of rows in the element child column.
+pub struct ListRows<'a> {
+    pub ranges: &'a [ListData],
+    pub nulls: BoolBitmap<'a>,
+}
+
 /// Trait representing a reference to column data.
 /// Data can be owned by current object or references from another one.
 pub trait RefColumn<'re> {
@@ -54,6 +179,35 @@ pub trait RefColumn<'re> {
     /// Pointer to the beginning of the raw row data.
     /// ptr can be nil
     unsafe fn nulls_ptr(&self) -> *const u8;
+
+    /// Child columns of nested types, eg. the element column of a LIST column.
+    fn child(&self, _pos: usize) -> Option<&RefColumn<'re>> {
+        None
+    }
+
+    /// Data the VARLEN row handles refer to
+    fn heap(&self) -> Heap {
+        Heap::Empty
+    }
+
+    fn encoding(&self) -> Encoding {
+        Encoding::PLAIN
+    }
+
+    /// Rows of the encoded row data the column refers to. RLE runs can't be sliced like other row
+    /// data, so aliases of RLE columns reference all the runs and this window of rows.
+    fn row_window(&self) -> RowRange {
+        RowRange { offset: 0, rows: self.capacity() }
+    }
+}
+
+#[inline]
+fn expect_plain(col: &RefColumn) -> Result<(), DBError> {
+    if col.encoding() != Encoding::PLAIN {
+        Err(DBError::ColumnEncoding(col.attribute().name.clone()))
+    } else {
+        Ok(())
+    }
 }
 
 /// Helper badness for converting raw column data into a typed slice of rows.
@@ -95,10 +249,612 @@ pub fn column_row_data<'c, T: ValueInfo>(col: &'c RefColumn) -> Result<ColumnRow
         return Err(DBError::AttributeType(attr.name.clone()))
     }
 
+    expect_plain(col)?;
+
     unsafe {
         Ok(ColumnRows{
             values: rows_from_rawptr_const::<T::Store>(col.rows_ptr(), rows),
             nulls: rows_from_rawptr_const::<u8>(col.nulls_ptr(), rows),
+            heap: col.heap(),
+        })
+    }
+}
+
+/// Null vector of the column, `None` if the column is not nullable.
+#[inline]
+pub fn column_nulls<'c>(col: &'c RefColumn) -> Option<BoolBitmap<'c>> {
+    if !col.attribute().nullable {
+        return None
+    }
+
+    unsafe {
+        Some(rows_from_rawptr_const::<u8>(col.nulls_ptr(), col.capacity()))
+    }
+}
+
+/// Rust value of a column row of the type, see `column_iter_opt()`
+pub trait RowValue<'a>: ValueInfo + Sized {
+    type Item;
+
+    fn row_value(rows: &ColumnRows<'a, Self>, row: RowOffset) -> Self::Item;
+
+    /// Check the non NULL rows of the column can be read with `row_value()`
+    fn check_rows(_rows: &ColumnRows<'a, Self>, _nulls: Option<BoolBitmap<'a>>, _count: RowOffset,
+                  _column: &str)
+        -> Result<(), DBError>
+    {
+        Ok(())
+    }
+}
+
+macro_rules! row_value_copy {
+    ($($t:ty),*) => {$(
+        impl<'a> RowValue<'a> for $t {
+            type Item = <$t as ValueInfo>::Store;
+
+            fn row_value(rows: &ColumnRows<'a, Self>, row: RowOffset) -> Self::Item {
+                rows.values[row]
+            }
+        }
+    )*}
+}
+
+row_value_copy!(types::UInt32, types::UInt64, types::Int32, types::Int64, types::Float32,
+                types::Float64, types::Boolean, types::Timestamp, types::Interval, types::Uuid);
+
+impl<'a> RowValue<'a> for types::Text {
+    type Item = &'a str;
+
+    fn row_value(rows: &ColumnRows<'a, Self>, row: RowOffset) -> &'a str {
+        // The rows are checked by `check_rows()`
+        unsafe { rows.as_str_unchecked(row) }
+    }
+
+    fn check_rows(rows: &ColumnRows<'a, Self>, nulls: Option<BoolBitmap<'a>>, count: RowOffset,
+                  column: &str)
+        -> Result<(), DBError>
+    {
+        for row in 0 .. count {
+            if !nulls.map_or(false, |n| n[row] != 0) {
+                rows.checked_str(row, column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> RowValue<'a> for types::Blob {
+    type Item = &'a [u8];
+
+    fn row_value(rows: &ColumnRows<'a, Self>, row: RowOffset) -> &'a [u8] {
+        rows.bytes(row)
+    }
+}
+
+/// Rows of a column as `Option`s, `None` for the NULL rows
+pub struct OptRows<'a, T: RowValue<'a>> {
+    rows: ColumnRows<'a, T>,
+    nulls: Option<BoolBitmap<'a>>,
+    pos: RowOffset,
+    end: RowOffset,
+}
+
+impl<'a, T: RowValue<'a>> Iterator for OptRows<'a, T> {
+    type Item = Option<T::Item>;
+
+    fn next(&mut self) -> Option<Option<T::Item>> {
+        if self.pos == self.end {
+            return None
+        }
+
+        let row = self.pos;
+        self.pos += 1;
+        if self.nulls.map_or(false, |n| n[row] != 0) {
+            Some(None)
+        } else {
+            Some(Some(T::row_value(&self.rows, row)))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.pos, Some(self.end - self.pos))
+    }
+}
+
+/// The first `rows` rows of a PLAIN column of the type, as `Option`s (`None` for NULL). TEXT rows
+/// are checked to be UTF-8 first, failing with `DBError::InvalidUtf8`. Eg.:
+///
+/// ```ignore
+/// let ids: Vec<Option<u32>> = column_iter_opt::<types::UInt32>(col, view.rows())?.collect();
+/// ```
+pub fn column_iter_opt<'c, T: RowValue<'c>>(col: &'c RefColumn, rows: RowOffset)
+    -> Result<OptRows<'c, T>, DBError>
+{
+    if rows > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let (data, nulls) = (column_row_data::<T>(col)?, column_nulls(col));
+    T::check_rows(&data, nulls, rows, &col.attribute().name)?;
+    Ok(OptRows { rows: data, nulls: nulls, pos: 0, end: rows })
+}
+
+/// Ranges, and null vector of a LIST column. The list elements are in the column's first child.
+#[inline]
+pub fn column_list_data<'c>(col: &'c RefColumn) -> Result<ListRows<'c>, DBError> {
+    let attr = col.attribute();
+    let rows = col.capacity();
+
+    match attr.dtype {
+        Type::LIST(_) => (),
+        _ => return Err(DBError::AttributeType(attr.name.clone())),
+    }
+
+    expect_plain(col)?;
+
+    unsafe {
+        Ok(ListRows{
+            ranges: rows_from_rawptr_const::<ListData>(col.rows_ptr(), rows),
+            nulls: rows_from_rawptr_const::<u8>(col.nulls_ptr(), rows),
+        })
+    }
+}
+
+/// Dictionary codes and null vector of a DICTIONARY encoded column. The dictionary values are in
+/// the column's first child.
+#[inline]
+pub fn column_dictionary_data<'c>(col: &'c RefColumn) -> Result<DictionaryRows<'c>, DBError> {
+    let rows = col.capacity();
+
+    if col.encoding() != Encoding::DICTIONARY {
+        return Err(DBError::ColumnEncoding(col.attribute().name.clone()))
+    }
+
+    unsafe {
+        Ok(DictionaryRows{
+            codes: rows_from_rawptr_const::<u32>(col.rows_ptr(), rows),
+            nulls: rows_from_rawptr_const::<u8>(col.nulls_ptr(), rows),
+        })
+    }
+}
+
+/// Runs and null vector of a RLE encoded column. The run values are in the column's first child.
+#[inline]
+pub fn column_rle_data<'c>(col: &'c RefColumn) -> Result<RleRows<'c>, DBError> {
+    if col.encoding() != Encoding::RLE {
+        return Err(DBError::ColumnEncoding(col.attribute().name.clone()))
+    }
+
+    let runs = col.child(0).map_or(0, |c| c.capacity());
+
+    unsafe {
+        Ok(RleRows{
+            run_ends: rows_from_rawptr_const::<u32>(col.rows_ptr(), runs),
+            window: col.row_window(),
+            nulls: rows_from_rawptr_const::<u8>(col.nulls_ptr(), col.capacity()),
+        })
+    }
+}
+
+/// Read a single column row as a `Value`. TEXT / BLOB values reference the column data.
+///
+/// Encoded columns are decoded.
+pub fn column_value<'c, 'r>(col: &'c RefColumn<'r>, row: RowOffset) -> Result<Value<'c>, DBError> {
+    if row >= col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    if column_nulls(col).map_or(false, |n| n[row] != 0) {
+        return Ok(Value::NULL)
+    }
+
+    if col.encoding() == Encoding::DICTIONARY {
+        let code = column_dictionary_data(col)?.codes[row];
+        let dictionary = col.child(0)
+            .ok_or(DBError::ColumnEncoding(col.attribute().name.clone()))?;
+
+        return column_value(dictionary, code as RowOffset)
+    }
+
+    if col.encoding() == Encoding::RLE {
+        let run = column_rle_data(col)?.run_of(row);
+        let values = col.child(0)
+            .ok_or(DBError::ColumnEncoding(col.attribute().name.clone()))?;
+
+        return column_value(values, run)
+    }
+
+    let value = match col.attribute().dtype {
+        Type::UINT32    => Value::UINT32(column_row_data::<types::UInt32>(col)?.values[row]),
+        Type::UINT64    => Value::UINT64(column_row_data::<types::UInt64>(col)?.values[row]),
+        Type::INT32     => Value::INT32(column_row_data::<types::Int32>(col)?.values[row]),
+        Type::INT64     => Value::INT64(column_row_data::<types::Int64>(col)?.values[row]),
+        Type::FLOAT32   => Value::FLOAT32(column_row_data::<types::Float32>(col)?.values[row]),
+        Type::FLOAT64   => Value::FLOAT64(column_row_data::<types::Float64>(col)?.values[row]),
+        Type::BOOLEAN   => Value::BOOLEAN(column_row_data::<types::Boolean>(col)?.values[row]),
+        Type::TIMESTAMP => Value::TIMESTAMP(column_row_data::<types::Timestamp>(col)?.values[row]),
+        Type::INTERVAL  => Value::INTERVAL(column_row_data::<types::Interval>(col)?.values[row]),
+        Type::UUID      => Value::UUID(column_row_data::<types::Uuid>(col)?.values[row]),
+        Type::TEXT      => Value::TEXT(column_row_data::<types::Text>(col)?
+                                           .checked_str(row, &col.attribute().name)?),
+        Type::BLOB      => Value::BLOB(column_row_data::<types::Blob>(col)?.bytes(row)),
+        Type::STRUCT(ref fields) => {
+            let mut out = Vec::with_capacity(fields.len());
+            for pos in 0 .. fields.len() {
+                let field = col.child(pos)
+                    .ok_or(DBError::make_column_unknown_pos(pos))?;
+                out.push(column_value(field, row)?);
+            }
+
+            Value::STRUCT(out)
+        },
+        Type::LIST(_)   => {
+            let range = column_list_data(col)?.ranges[row];
+            let items = col.child(0)
+                .ok_or(DBError::AttributeType(col.attribute().name.clone()))?;
+
+            let mut out = Vec::with_capacity(range.len);
+            for item in range.offset .. range.offset + range.len {
+                out.push(column_value(items, item)?);
+            }
+
+            Value::LIST(out)
+        },
+    };
+
+    Ok(value)
+}
+
+/// Allocate a chunk holding a copy of `data`
+fn allocate_copy<'a, T: Copy>(alloc: &'a Allocator, data: &[T]) -> Result<OwnedChunk<'a>, DBError> {
+    if data.is_empty() {
+        return Ok(OwnedChunk::empty())
+    }
+
+    let mut chunk = alloc.allocate(data.len() * mem::size_of::<T>())?;
+    unsafe {
+        rows_from_rawptr::<T>(chunk.as_mut_ptr(), data.len()).copy_from_slice(data);
+    }
+
+    Ok(chunk)
+}
+
+/// Build a RLE column from `runs` of (run in `values`, exclusive end row) and the NULL vector
+/// (ignored for not nullable attributes).
+fn make_rle_column<'a>(alloc: &'a Allocator, attr: &Attribute, values: &RefColumn,
+                       runs: &[(usize, u32)], nulls: &[u8], rows: RowOffset)
+    -> Result<Column<'a>, DBError>
+{
+    let values_attr = Attribute {
+        name: String::from("values"),
+        nullable: false,
+        dtype: attr.dtype.clone(),
+    };
+
+    let mut run_values = Column::new(alloc, values_attr);
+    if !runs.is_empty() {
+        if let Some(err) = run_values.set_capacity(runs.len()) {
+            return Err(err)
+        }
+    }
+
+    // NULL runs have a zeroed value, the NULL vector is checked first
+    if !runs.is_empty() {
+        unsafe {
+            let raw = run_values.data_mut()?.raw.as_mut_ptr();
+            ptr::write_bytes(raw, 0, runs.len() * attr.dtype.size_of());
+        }
+    }
+
+    for (pos, &(run, _)) in runs.iter().enumerate() {
+        let value = column_value(values, run)?;
+        if !value.is_null() {
+            value.set_row(&mut run_values, pos)?;
+        }
+    }
+
+    let ends: Vec<u32> = runs.iter().map(|r| r.1).collect();
+
+    let mut out = Column::new(alloc, attr.clone());
+    out.data_mut()?.raw = allocate_copy(alloc, ends.as_slice())?;
+    if attr.nullable {
+        out.data_mut()?.raw_nulls = allocate_copy(alloc, &nulls[.. rows])?;
+    }
+
+    out.children = vec![run_values];
+    out.child_rows = runs.len();
+    out.encoding = Encoding::RLE;
+    out.encoded_rows = rows;
+    Ok(out)
+}
+
+/// Plain column with the `rows` values of `src` (in order).
+fn make_plain_column<'a, I>(alloc: &'a Allocator, src: &RefColumn, rows: I, count: usize)
+    -> Result<Column<'a>, DBError>
+    where I: Iterator<Item=RowOffset>
+{
+    let mut out = Column::new(alloc, src.attribute().clone());
+    if count > 0 {
+        if let Some(err) = out.set_capacity(count) {
+            return Err(err)
+        }
+    }
+
+    for (pos, row) in rows.enumerate() {
+        let value = column_value(src, row)?;
+        value.set_row(&mut out, pos)?;
+
+        if out.attr.nullable && !value.is_null() {
+            out.nulls_mut()?[pos] = 0;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Rows of `src` with a non zero `selection` entry. There's one `selection` entry per row.
+///
+/// RLE columns are filtered a run at a time and the output stays RLE encoded, other encodings are
+/// decoded into a PLAIN column.
+pub fn filter_column<'a>(alloc: &'a Allocator, src: &RefColumn, selection: BoolBitmap)
+    -> Result<Column<'a>, DBError>
+{
+    let rows = selection.len();
+    if rows > src.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    if src.encoding() != Encoding::RLE {
+        let count = selection.iter().filter(|s| **s != 0).count();
+        let selected = (0 .. rows).filter(|r| selection[*r] != 0);
+        return make_plain_column(alloc, src, selected, count)
+    }
+
+    let rle = column_rle_data(src)?;
+    let nullable = src.attribute().nullable;
+    let mut runs = Vec::new();
+    let mut nulls = Vec::new();
+    let mut out_rows = 0;
+
+    for (run, start, len) in rle.runs() {
+        if start >= rows {
+            break
+        }
+
+        let range = start .. min(start + len, rows);
+        let selected = selection[range.clone()].iter().filter(|s| **s != 0).count();
+        if selected == 0 {
+            continue
+        }
+
+        if nullable {
+            nulls.extend(range.filter(|r| selection[*r] != 0).map(|r| rle.nulls[r]));
+        }
+
+        out_rows += selected;
+        runs.push((run, out_rows as u32));
+    }
+
+    let values = src.child(0)
+        .ok_or(DBError::ColumnEncoding(src.attribute().name.clone()))?;
+    make_rle_column(alloc, src.attribute(), values, runs.as_slice(), nulls.as_slice(), out_rows)
+}
+
+/// Rows of `src` at `indices` (in `indices` order).
+///
+/// For RLE columns each index is looked up in the runs and the output stays RLE encoded, other
+/// encodings are decoded into a PLAIN column.
+pub fn take_column<'a>(alloc: &'a Allocator, src: &RefColumn, indices: &[RowOffset])
+    -> Result<Column<'a>, DBError>
+{
+    let capacity = src.capacity();
+    if indices.iter().any(|i| *i >= capacity) {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    if src.encoding() != Encoding::RLE {
+        return make_plain_column(alloc, src, indices.iter().cloned(), indices.len())
+    }
+
+    let rle = column_rle_data(src)?;
+    let nullable = src.attribute().nullable;
+    let mut runs: Vec<(usize, u32)> = Vec::new();
+    let mut nulls = Vec::new();
+
+    for (pos, &row) in indices.iter().enumerate() {
+        let run = rle.run_of(row);
+        let end = (pos + 1) as u32;
+
+        match runs.last_mut() {
+            Some(last) if last.0 == run => last.1 = end,
+            _ => runs.push((run, end)),
+        }
+
+        if nullable {
+            nulls.push(rle.nulls[row]);
+        }
+    }
+
+    let values = src.child(0)
+        .ok_or(DBError::ColumnEncoding(src.attribute().name.clone()))?;
+    let rows = indices.len();
+    make_rle_column(alloc, src.attribute(), values, runs.as_slice(), nulls.as_slice(), rows)
+}
+
+/// Block of the `src` rows at `indices`, in `indices` order. Rows can be taken more than once
+/// (or not at all), eg. to reorder rows by a sort permutation or to gather the probe rows of a
+/// join. Columns are taken by `take_column()`.
+pub fn take<'a, 'v>(alloc: &'a Allocator, src: &'v View<'v>, indices: &[RowOffset])
+    -> Result<Block<'a>, DBError>
+{
+    if indices.iter().any(|i| *i >= src.rows()) {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let mut columns = Vec::with_capacity(src.schema().count());
+    for pos in 0 .. src.schema().count() {
+        let col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        columns.push(take_column(alloc, col, indices)?);
+    }
+
+    Block::from_columns(alloc, columns, indices.len())
+}
+
+/// Pick an encoding for the first `rows` rows of a PLAIN column from its data statistics.
+///
+/// Fixed width columns whose RLE runs take less than half of the PLAIN row data are RLE encoded,
+/// TEXT columns where each unique value repeats twice (on average) are DICTIONARY encoded.
+pub fn choose_encoding(col: &RefColumn, rows: RowOffset) -> Result<Encoding, DBError> {
+    expect_plain(col)?;
+
+    if rows == 0 {
+        return Ok(Encoding::PLAIN)
+    }
+
+    match col.attribute().dtype {
+        Type::TEXT => {
+            let mut unique = HashSet::new();
+            for row in 0 .. rows {
+                if let Value::TEXT(v) = column_value(col, row)? {
+                    unique.insert(v);
+                }
+            }
+
+            Ok(if unique.len() * 2 <= rows { Encoding::DICTIONARY } else { Encoding::PLAIN })
+        },
+        Type::BLOB | Type::LIST(_) | Type::STRUCT(_) =>
+            Ok(Encoding::PLAIN),
+        ref dtype => {
+            let mut runs = 1;
+            let mut prev = column_value(col, 0)?;
+
+            for row in 1 .. rows {
+                let value = column_value(col, row)?;
+                if value != prev {
+                    runs += 1;
+                }
+                prev = value;
+            }
+
+            let size_of = dtype.size_of();
+            let rle_size = runs * (mem::size_of::<u32>() + size_of);
+            Ok(if rle_size * 2 <= rows * size_of { Encoding::RLE } else { Encoding::PLAIN })
+        },
+    }
+}
+
+/// Attributes of the child columns of a nested type column
+fn child_attributes(attr: &Attribute) -> Vec<Attribute> {
+    match attr.dtype {
+        Type::LIST(ref item) =>
+            vec![Attribute { name: String::from("item"), nullable: true, dtype: (**item).clone() }],
+        Type::STRUCT(ref fields) =>
+            fields.clone(),
+        _ =>
+            Vec::new(),
+    }
+}
+
+/// Check the value can be stored in a column of the attribute: its variant matches the type
+/// (LIST elements and STRUCT fields included) and it's only NULL if the attribute is nullable.
+fn check_value(attr: &Attribute, value: &Value) -> Result<(), DBError> {
+    let matches = match (value, &attr.dtype) {
+        (&Value::NULL, _)                           => attr.nullable,
+        (&Value::UINT32(_), &Type::UINT32)          => true,
+        (&Value::UINT64(_), &Type::UINT64)          => true,
+        (&Value::INT32(_), &Type::INT32)            => true,
+        (&Value::INT64(_), &Type::INT64)            => true,
+        (&Value::FLOAT32(_), &Type::FLOAT32)        => true,
+        (&Value::FLOAT64(_), &Type::FLOAT64)        => true,
+        (&Value::BOOLEAN(_), &Type::BOOLEAN)        => true,
+        (&Value::TIMESTAMP(_), &Type::TIMESTAMP)    => true,
+        (&Value::INTERVAL(_), &Type::INTERVAL)      => true,
+        (&Value::UUID(_), &Type::UUID)              => true,
+        (&Value::TEXT(_), &Type::TEXT)              => true,
+        (&Value::BLOB(_), &Type::BLOB)              => true,
+        (&Value::LIST(_), &Type::LIST(_)) => {
+            let item = &child_attributes(attr)[0];
+            for v in value.as_list().unwrap_or(&[]) {
+                check_value(item, v)?;
+            }
+            true
+        },
+        (&Value::STRUCT(_), &Type::STRUCT(_)) => {
+            let fields = value.as_struct().unwrap_or(&[]);
+            let attrs = child_attributes(attr);
+            if fields.len() != attrs.len() {
+                return Err(DBError::AttributeType(attr.name.clone()))
+            }
+            for (a, v) in attrs.iter().zip(fields) {
+                check_value(a, v)?;
+            }
+            true
+        },
+        _                                           => false,
+    };
+
+    match (matches, value) {
+        (true, _)               => Ok(()),
+        (false, &Value::NULL)   => Err(DBError::AttributeNullability(attr.name.clone())),
+        (false, _)              => Err(DBError::AttributeType(attr.name.clone())),
+    }
+}
+
+/// Set the row of the column to the value, including the NULL flag of nullable columns
+fn set_value(col: &mut Column, row: RowOffset, value: &Value) -> Result<(), DBError> {
+    value.set_row(col, row)?;
+    if col.attr.nullable {
+        col.nulls_mut()?[row] = value.is_null() as u8;
+    }
+    Ok(())
+}
+
+/// Child column rows correspond to the parent column rows (STRUCT fields), as opposed to being
+/// addressed by the parent row values (LIST elements).
+fn children_share_rows(attr: &Attribute) -> bool {
+    match attr.dtype {
+        Type::STRUCT(_) => true,
+        _               => false,
+    }
+}
+
+/// Row data, null vector and arena of a `Column`, shared by the clones of the column
+struct ColumnData<'alloc> {
+    raw_nulls: OwnedChunk<'alloc>,
+    raw: OwnedChunk<'alloc>,
+    /// Used to store varlen column values
+    arena: ChainedArena<'alloc>,
+    /// Handles of the distinct values in the arena, for interning columns
+    intern: Option<InternPool>,
+}
+
+impl<'alloc> ColumnData<'alloc> {
+    fn new(a: &'alloc Allocator) -> ColumnData<'alloc> {
+        ColumnData {
+            raw_nulls: OwnedChunk::empty(),
+            raw: OwnedChunk::empty(),
+            arena: ChainedArena::new(a, ARENA_MIN_SIZE, ARENA_MAX_SIZE),
+            intern: None,
+        }
+    }
+
+    /// Copy of the data. The arena chunks are copied as they are, so the VARLEN row handles are
+    /// valid in the copy.
+    fn try_clone(&self, a: &'alloc Allocator) -> Result<ColumnData<'alloc>, DBError> {
+        let bytes = |chunk: &OwnedChunk| chunk.data.as_ref().map_or(0, |d| d.len());
+        let copy = |chunk: &OwnedChunk| if chunk.is_null() {
+            Ok(OwnedChunk::empty())
+        } else {
+            let mut out = a.allocate_aligned(bytes(chunk), chunk.align)?;
+            unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), out.as_mut_ptr(), bytes(chunk)); }
+            Ok(out)
+        };
+
+        Ok(ColumnData {
+            raw_nulls: copy(&self.raw_nulls)?,
+            raw: copy(&self.raw)?,
+            arena: self.arena.try_clone()?,
+            intern: self.intern.clone(),
         })
     }
 }
@@ -107,13 +863,21 @@ pub fn column_row_data<'c, T: ValueInfo>(col: &'c RefColumn) -> Result<ColumnRow
 ///
 /// Knows its capacity but not size, has no concept of current. Those properties are fulfilled by
 /// it's parent container (types such as Block).
+///
+/// Clones share the column data (copy-on-write), modifying a column with live clones first copies
+/// its data.
+#[derive(Clone)]
 pub struct Column<'alloc> {
     allocator: &'alloc Allocator,
     attr: Attribute,
-    raw_nulls: OwnedChunk<'alloc>,
-    raw: OwnedChunk<'alloc>,
-    /// Used to store varlen column values
-    arena: ChainedArena<'alloc>
+    data: Arc<ColumnData<'alloc>>,
+    /// Columns of nested type values (eg. LIST elements)
+    children: Vec<Column<'alloc>>,
+    /// Rows of the LIST element column (or dictionary) in use. Element rows are only appended.
+    child_rows: RowOffset,
+    encoding: Encoding,
+    /// Rows of RLE encoded columns, since those can't be derived from the row data size
+    encoded_rows: RowOffset,
 }
 
 /// Typed Data Column that references another column
@@ -122,6 +886,10 @@ pub struct AliasColumn<'parent> {
     attr: Attribute,
     raw_nulls: &'parent [u8],
     raw: &'parent [u8],
+    heap: Heap<'parent>,
+    children: Vec<AliasColumn<'parent>>,
+    encoding: Encoding,
+    window: RowRange,
 }
 
 /// Create another read only alias of a column
@@ -132,16 +900,21 @@ pub fn alias_column<'a>(src: &'a RefColumn<'a>, range: Option<RowRange>)
 {
     let (offset, rows) = range.map_or((0, src.capacity()), |r| (r.offset, r.rows));
 
-    let size_of = src.attribute().dtype.size_of();
+    let size_of = row_size(src.attribute(), src.encoding());
     let start = offset * size_of;
-    let len = rows + size_of;
+    let len = rows * size_of;
 
     if offset + rows > src.capacity() {
         return Err(DBError::RowOutOfBounds)
     }
 
-    let raw = src.rows_raw_slice();
-    let col = &raw[start .. start + len];
+    let (col, window) = if src.encoding() == Encoding::RLE {
+        let window = src.row_window();
+        (src.rows_raw_slice(), RowRange { offset: window.offset + offset, rows: rows })
+    } else {
+        let raw = src.rows_raw_slice();
+        (&raw[start .. start + len], RowRange { offset: 0, rows: rows })
+    };
 
     let nulls = if src.attribute().nullable {
         let raw = src.nulls_raw_slice();
@@ -150,13 +923,89 @@ pub fn alias_column<'a>(src: &'a RefColumn<'a>, range: Option<RowRange>)
         &[]
     };
 
+    // LIST element rows are addressed by the parent row values, so alias the whole child
+    let child_range = if children_share_rows(src.attribute()) { range } else { None };
+    let mut children = Vec::new();
+    while let Some(child) = src.child(children.len()) {
+        children.push(alias_column(child, child_range)?);
+    }
+
     Ok(AliasColumn {
         attr: src.attribute().clone(),
         raw: col,
         raw_nulls: nulls,
+        heap: src.heap(),
+        children: children,
+        encoding: src.encoding(),
+        window: window,
     })
 }
 
+impl<'parent> AliasColumn<'parent> {
+    /// Alias raw column data kept outside of a `Column` (eg. a memory mapped file).
+    ///
+    /// Unsafe since the data is not checked: `raw` has to be aligned row data of the `encoding`
+    /// for the attribute type, `raw_nulls` a byte per row of nullable attributes, `heap` the
+    /// data of VARLEN row handles and `children` the child columns the encoding (or type)
+    /// expects.
+    pub unsafe fn from_raw(attr: Attribute, raw: &'parent [u8], raw_nulls: &'parent [u8],
+                           heap: Heap<'parent>, children: Vec<AliasColumn<'parent>>,
+                           encoding: Encoding, window: RowRange)
+        -> AliasColumn<'parent>
+    {
+        AliasColumn {
+            attr: attr,
+            raw: raw,
+            raw_nulls: raw_nulls,
+            heap: heap,
+            children: children,
+            encoding: encoding,
+            window: window,
+        }
+    }
+
+    /// Alias a sub-range of rows of this alias. The new alias references the same parent data (and
+    /// is not tied to the lifetime of this alias).
+    pub fn slice(&self, range: RowRange) -> Result<AliasColumn<'parent>, DBError> {
+        if range.offset + range.rows > self.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let size_of = row_size(&self.attr, self.encoding);
+        let raw: &'parent [u8] = self.raw;
+        let raw_nulls: &'parent [u8] = self.raw_nulls;
+
+        let (raw, window) = if self.encoding == Encoding::RLE {
+            (raw, RowRange { offset: self.window.offset + range.offset, rows: range.rows })
+        } else {
+            let start = range.offset * size_of;
+            (&raw[start .. start + range.rows * size_of], RowRange { offset: 0, rows: range.rows })
+        };
+
+        Ok(AliasColumn {
+            attr: self.attr.clone(),
+            raw: raw,
+            raw_nulls: if self.attr.nullable {
+                &raw_nulls[range.offset .. range.offset + range.rows]
+            } else {
+                &[]
+            },
+            heap: self.heap,
+            children: if children_share_rows(&self.attr) {
+                let mut children = Vec::with_capacity(self.children.len());
+                for child in &self.children {
+                    children.push(child.slice(range)?);
+                }
+                children
+            } else {
+                self.children.clone()
+            },
+            encoding: self.encoding,
+            window: window,
+        })
+    }
+}
+
 impl<'parent> RefColumn<'parent> for AliasColumn<'parent> {
     fn attribute(&self) -> &Attribute {
         &self.attr
@@ -164,7 +1013,10 @@ impl<'parent> RefColumn<'parent> for AliasColumn<'parent> {
 
     /// Row capacity
     fn capacity(&self) -> usize {
-        self.raw.len() / self.attr.dtype.size_of()
+        match self.encoding {
+            Encoding::RLE => self.window.rows,
+            _             => self.raw.len() / row_size(&self.attr, self.encoding),
+        }
     }
 
     /// Pointer to the beginning of the raw row data
@@ -177,59 +1029,196 @@ impl<'parent> RefColumn<'parent> for AliasColumn<'parent> {
         self.raw_nulls.as_ptr()
     }
 
-    fn rows_raw_slice(&'parent self) -> &'parent [u8] {
-        self.raw
+    fn rows_raw_slice(&'parent self) -> &'parent [u8] {
+        self.raw
+    }
+
+    fn nulls_raw_slice(&'parent self) -> &'parent [u8] {
+        self.raw_nulls
+    }
+
+    fn child(&self, pos: usize) -> Option<&RefColumn<'parent>> {
+        self.children.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+
+    fn heap(&self) -> Heap {
+        self.heap
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    fn row_window(&self) -> RowRange {
+        self.window
+    }
+}
+
+impl<'alloc> RefColumn<'alloc> for Column<'alloc> {
+    fn attribute(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// Row capacity
+    fn capacity(&self) -> usize {
+        match self.encoding {
+            Encoding::RLE => self.encoded_rows,
+            _             => self.data.raw.len() / row_size(&self.attr, self.encoding),
+        }
+    }
+
+    /// Pointer to the beginning of the raw row data
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        self.data.raw.as_ptr()
+    }
+
+    /// Pointer to the beginning of the raw row data
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        self.data.raw_nulls.as_ptr()
+    }
+
+    fn rows_raw_slice(&'alloc self) -> &'alloc [u8] {
+        self.data.raw.data.as_ref()
+            .map_or(&[], |f| f as &'alloc [u8])
+    }
+
+    fn nulls_raw_slice(&'alloc self) -> &'alloc [u8] {
+        self.data.raw_nulls.data.as_ref()
+            .map_or(&[], |f| f as &'alloc [u8])
+    }
+
+    fn child(&self, pos: usize) -> Option<&RefColumn<'alloc>> {
+        self.children.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+
+    fn heap(&self) -> Heap {
+        Heap::Chunks(self.data.arena.chunks())
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+}
+
+impl<'alloc> Column<'alloc> {
+    /// Empty column of the attribute, without row space
+    pub fn new(a: &'alloc Allocator, attr: Attribute) -> Column<'alloc> {
+        let children = child_attributes(&attr).into_iter()
+            .map(|c| Column::new(a, c))
+            .collect();
+
+        Column {
+            allocator: a,
+            attr: attr,
+            data: Arc::new(ColumnData::new(a)),
+            children: children,
+            child_rows: 0,
+            encoding: Encoding::PLAIN,
+            encoded_rows: 0,
+        }
+    }
+
+    /// Column data for modification, copying it first if it's shared with a clone
+    fn data_mut(&mut self) -> Result<&mut ColumnData<'alloc>, DBError> {
+        if Arc::get_mut(&mut self.data).is_none() {
+            let copy = self.data.try_clone(self.allocator)?;
+            self.data = Arc::new(copy);
+        }
+
+        Ok(Arc::get_mut(&mut self.data).unwrap())
     }
 
-    fn nulls_raw_slice(&'parent self) -> &'parent [u8] {
-        self.raw_nulls
+    /// True if the column data is shared with a clone
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.data) > 1
     }
-}
 
-impl<'alloc> RefColumn<'alloc> for Column<'alloc> {
-    fn attribute(&self) -> &Attribute {
-        &self.attr
+    /// Mutable child column of a nested type column
+    pub fn child_mut(&mut self, pos: usize) -> Option<&mut Column<'alloc>> {
+        self.children.get_mut(pos)
     }
 
-    /// Row capacity
-    fn capacity(&self) -> usize {
-        self.raw.len() / self.attr.dtype.size_of()
+    pub fn list_rows_mut(&mut self) -> Result<&mut [ListData], DBError> {
+        match self.attr.dtype {
+            Type::LIST(_) => (),
+            _ => return Err(DBError::AttributeType(self.attr.name.clone())),
+        }
+
+        expect_plain(self)?;
+
+        let capacity = self.capacity();
+        unsafe {
+            Ok(rows_from_rawptr::<ListData>(self.data_mut()?.raw.as_mut_ptr(), capacity))
+        }
     }
 
-    /// Pointer to the beginning of the raw row data
-    unsafe fn rows_ptr(&self) -> *const u8 {
-        self.raw.as_ptr()
+    /// Make room for a `len` element list in the element column (first child) and point the LIST
+    /// column row at it. Returns the range of element column rows the caller has to fill in.
+    pub fn list_append(&mut self, row: RowOffset, len: usize) -> Result<ListData, DBError> {
+        if row >= self.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let range = ListData { offset: self.child_rows, len: len };
+
+        {
+            let items = self.children.get_mut(0)
+                .ok_or(DBError::AttributeType(self.attr.name.clone()))?;
+
+            let needed = range.offset + len;
+            if needed > items.capacity() {
+                let new_cap = round_up(needed.max(items.capacity() * 2), 1024);
+                if let Some(err) = items.set_capacity(new_cap) {
+                    return Err(err)
+                }
+            }
+        }
+
+        self.list_rows_mut()?[row] = range;
+        self.child_rows += len;
+        Ok(range)
     }
 
-    /// Pointer to the beginning of the raw row data
-    unsafe fn nulls_ptr(&self) -> *const u8 {
-        self.raw_nulls.as_ptr()
+    /// The first `rows` rows as `Option`s, see `column_iter_opt()`
+    pub fn iter_opt<'c, T: RowValue<'c>>(&'c self, rows: RowOffset)
+        -> Result<OptRows<'c, T>, DBError>
+    {
+        column_iter_opt(self, rows)
     }
 
-    fn rows_raw_slice(&'alloc self) -> &'alloc [u8] {
-        self.raw.data.as_ref()
-            .map_or(&[], |f| f as &'alloc [u8])
+    /// Arena of the VARLEN values, for modification
+    pub fn arena(&mut self) -> Result<&mut ChainedArena<'alloc>, DBError> {
+        Ok(&mut self.data_mut()?.arena)
     }
 
-    fn nulls_raw_slice(&'alloc self) -> &'alloc [u8] {
-        self.raw_nulls.data.as_ref()
-            .map_or(&[], |f| f as &'alloc [u8])
+    /// Store a VARLEN value in the arena, once per distinct value when interning
+    pub fn append_varlen(&mut self, value: &[u8]) -> Result<RawData, DBError> {
+        let ColumnData { ref mut arena, ref mut intern, .. } = *self.data_mut()?;
+        match *intern {
+            Some(ref mut pool) => pool.intern(value, arena),
+            None => arena.append(value),
+        }
     }
-}
 
-impl<'alloc> Column<'alloc> {
-    fn new(a: &'alloc Allocator, attr: Attribute) -> Column<'alloc> {
-        Column {
-            allocator: a,
-            attr: attr,
-            raw_nulls: OwnedChunk::empty(),
-            raw: OwnedChunk::empty(),
-            arena: ChainedArena::new(a, ARENA_MIN_SIZE, ARENA_MAX_SIZE),
+    /// Intern the values of a TEXT column: rows set to a value already in the column share its
+    /// arena copy. The pool keeps up to `max_entries` distinct values.
+    pub fn intern_text(&mut self, max_entries: usize) -> Result<(), DBError> {
+        if self.attr.dtype != Type::TEXT {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        let data = self.data_mut()?;
+        if data.intern.is_none() {
+            data.intern = Some(InternPool::new(max_entries));
         }
+        Ok(())
     }
 
-    pub fn arena(&mut self) -> &mut ChainedArena<'alloc> {
-        &mut self.arena
+    /// Counters of the intern pool, `None` when the column isn't interning
+    pub fn intern_stats(&self) -> Option<InternStats> {
+        self.data.intern.as_ref().map(|pool| pool.stats())
     }
 
     pub fn nulls_mut(&mut self) -> Result<MutBoolBitmap, DBError> {
@@ -237,7 +1226,7 @@ impl<'alloc> Column<'alloc> {
             return Err(DBError::AttributeNullability(self.attr.name.clone()))
         }
 
-        let out: MutBoolBitmap = match self.raw_nulls.data {
+        let out: MutBoolBitmap = match self.data_mut()?.raw_nulls.data {
             Some(ref mut slice) => slice,
             _ => &mut[],
         };
@@ -250,12 +1239,15 @@ impl<'alloc> Column<'alloc> {
             return Err(DBError::AttributeType(self.attr.name.clone()))
         }
 
+        expect_plain(self)?;
+
+        let capacity = self.capacity();
         unsafe {
-            let ptr: *mut T::Store = mem::transmute(self.raw.as_mut_ptr());
+            let ptr: *mut T::Store = mem::transmute(self.data_mut()?.raw.as_mut_ptr());
             let out = if ptr.is_null() {
                 &mut []
             } else {
-                slice::from_raw_parts_mut(ptr, self.capacity())
+                slice::from_raw_parts_mut(ptr, capacity)
             };
 
             Ok(out)
@@ -267,15 +1259,19 @@ impl<'alloc> Column<'alloc> {
             return Err(DBError::AttributeType(self.attr.name.clone()))
         }
 
+        expect_plain(self)?;
+
+        let capacity = self.capacity();
+        let data = self.data_mut()?;
         unsafe {
-            let ptr: *mut T::Store = mem::transmute(self.raw.as_mut_ptr());
+            let ptr: *mut T::Store = mem::transmute(data.raw.as_mut_ptr());
             let rows = if ptr.is_null() {
                 &mut []
             } else {
-                slice::from_raw_parts_mut(ptr, self.capacity())
+                slice::from_raw_parts_mut(ptr, capacity)
             };
 
-            let nulls: MutBoolBitmap = match self.raw_nulls.data {
+            let nulls: MutBoolBitmap = match data.raw_nulls.data {
                 Some(ref mut slice) => slice,
                 _ => &mut[],
             };
@@ -284,36 +1280,199 @@ impl<'alloc> Column<'alloc> {
         }
     }
 
+    /// Dictionary encode the first `rows` rows of a PLAIN TEXT column. The row data is replaced by
+    /// codes into a dictionary of the unique values, in order of their first appearance.
+    ///
+    /// The encoded column can be read but no longer modified.
+    pub fn encode_dictionary(&mut self, rows: RowOffset) -> Result<(), DBError> {
+        if self.attr.dtype != Type::TEXT {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        expect_plain(self)?;
+
+        let capacity = self.capacity();
+        if rows > capacity {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let dict_attr = Attribute {
+            name: String::from("dictionary"),
+            nullable: false,
+            dtype: Type::TEXT,
+        };
+
+        let mut dictionary = Column::new(self.allocator, dict_attr);
+        let mut codes_chunk = self.allocator.allocate(capacity * mem::size_of::<u32>())?;
+
+        let unique = {
+            let values = column_row_data::<types::Text>(self)?;
+            let nulls = column_nulls(self);
+            let codes = unsafe { rows_from_rawptr::<u32>(codes_chunk.as_mut_ptr(), capacity) };
+            let mut lookup: HashMap<&str, u32> = HashMap::new();
+
+            for row in 0 .. rows {
+                // Values of NULL rows are not initialized
+                if nulls.map_or(false, |n| n[row] != 0) {
+                    codes[row] = 0;
+                    continue
+                }
+
+                let value = values.checked_str(row, &self.attr.name)?;
+                let next = lookup.len() as u32;
+                let code = *lookup.entry(value).or_insert(next);
+
+                if code == next {
+                    if code as usize >= dictionary.capacity() {
+                        let new_cap = round_up(code as usize + 1, 1024);
+                        if let Some(err) = dictionary.set_capacity(new_cap) {
+                            return Err(err)
+                        }
+                    }
+
+                    value.set_row(&mut dictionary, code as usize)?;
+                }
+
+                codes[row] = code;
+            }
+
+            lookup.len()
+        };
+
+        // Values now live in the dictionary arena
+        {
+            let allocator = self.allocator;
+            let data = self.data_mut()?;
+            data.raw = codes_chunk;
+            data.arena = ChainedArena::new(allocator, ARENA_MIN_SIZE, ARENA_MAX_SIZE);
+            if let Some(ref mut pool) = data.intern {
+                pool.clear();
+            }
+        }
+        self.child_rows = unique;
+        self.children = vec![dictionary];
+        self.encoding = Encoding::DICTIONARY;
+        Ok(())
+    }
+
+    /// Forget the column values, keeping the allocated row data and arena. Encoded columns, and
+    /// columns sharing their data with a clone, go back to being PLAIN and empty (without row
+    /// data), returns true if so.
+    fn clear(&mut self) -> bool {
+        if self.encoding != Encoding::PLAIN || self.is_shared() {
+            let intern = self.data.intern.clone();
+            *self = Column::new(self.allocator, self.attr.clone());
+            if let Some(mut pool) = intern {
+                pool.clear();
+                self.data_mut().unwrap().intern = Some(pool);
+            }
+            return true
+        }
+
+        let data = self.data_mut().unwrap();
+        data.arena.reset();
+        if let Some(ref mut pool) = data.intern {
+            pool.clear();
+        }
+        self.child_rows = 0;
+
+        for child in &mut self.children {
+            child.clear();
+        }
+
+        false
+    }
+
+    /// RLE encode the first `rows` rows of a PLAIN fixed width column. Consecutive rows with the
+    /// same value (or NULL rows) become a single run.
+    ///
+    /// The encoded column can be read but no longer modified.
+    pub fn encode_rle(&mut self, rows: RowOffset) -> Result<(), DBError> {
+        let encoded = dispatch_type!(self.attr.dtype, fixed, T => self.rle_encoded::<T>(rows)?,
+            _ => return Err(DBError::AttributeType(self.attr.name.clone())));
+
+        *self = encoded;
+        Ok(())
+    }
+
+    fn rle_encoded<T: ValueInfo>(&self, rows: RowOffset) -> Result<Column<'alloc>, DBError>
+        where T::Store: PartialEq + Copy
+    {
+        if rows > self.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let data = column_row_data::<T>(self)?;
+        let nulls = column_nulls(self);
+        let is_null = |row: RowOffset| nulls.map_or(false, |n| n[row] != 0);
+
+        // Consecutive NULLs are one run, regardless of the (uninitialized) values
+        let mut runs: Vec<(usize, u32)> = Vec::new();
+        for row in 0 .. rows {
+            let same = row > 0 && match (is_null(row - 1), is_null(row)) {
+                (true, true)    => true,
+                (false, false)  => data.values[row - 1] == data.values[row],
+                _               => false,
+            };
+
+            if same {
+                runs.last_mut().unwrap().1 = (row + 1) as u32;
+            } else {
+                runs.push((row, (row + 1) as u32));
+            }
+        }
+
+        make_rle_column(self.allocator, &self.attr, self, runs.as_slice(), data.nulls, rows)
+    }
+
     /// Change the capacity of the Column
     pub fn set_capacity(&mut self, rows: RowOffset) -> Option<DBError> {
-        let new_size = rows * self.attr.dtype.size_of();
+        if self.encoding == Encoding::RLE {
+            return Some(DBError::ColumnEncoding(self.attr.name.clone()))
+        }
+
+        let new_size = rows * row_size(&self.attr, self.encoding);
+        let (allocator, nullable) = (self.allocator, self.attr.nullable);
+        let data = match self.data_mut() {
+            Ok(data) => data,
+            Err(e) => return Some(e),
+        };
 
-        if self.raw.is_null() {
-            match self.allocator.allocate(new_size) {
-                Ok(chunk) => self.raw = chunk,
+        if data.raw.is_null() {
+            match allocator.allocate(new_size) {
+                Ok(chunk) => data.raw = chunk,
                 Err(e) => return Some(e)
             }
 
-            if self.attr.nullable {
-                match self.allocator.allocate(rows) {
-                    Ok(chunk) => self.raw_nulls = chunk,
+            if nullable {
+                match allocator.allocate(rows) {
+                    Ok(chunk) => data.raw_nulls = chunk,
                     Err(e) => return Some(e)
                 }
             }
         } else {
-            let status = self.raw.resize(new_size);
+            let status = data.raw.resize(new_size);
             if status.is_some() {
                 return status;
             }
 
-            if self.attr.nullable {
-                let nulls_status = self.raw_nulls.resize(rows);
+            if nullable {
+                let nulls_status = data.raw_nulls.resize(rows);
                 if nulls_status.is_some() {
                     return nulls_status;
                 }
             }
         }
 
+        if children_share_rows(&self.attr) {
+            for child in &mut self.children {
+                let status = child.set_capacity(rows);
+                if status.is_some() {
+                    return status;
+                }
+            }
+        }
+
         None
     }
 }
@@ -326,6 +1485,21 @@ pub trait View<'v> {
 
     /// Number of rows
     fn rows(&self) -> RowOffset;
+
+    /// Zone map statistics of the view data, if they have been computed
+    fn stats(&self) -> Option<&BlockStats> {
+        None
+    }
+
+    /// Value of the `row` of the column at `pos`, of any type or encoding
+    fn value(&'v self, row: RowOffset, pos: usize) -> Result<Value<'v>, DBError> {
+        if row >= self.rows() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let col = self.column(pos).ok_or_else(|| DBError::make_column_unknown_pos(pos))?;
+        column_value(col, row)
+    }
 }
 
 /// An implementation of a View that doesn't "own" the data but aliases it
@@ -389,6 +1563,37 @@ impl<'a> RefView<'a> {
     pub fn new(schema: Schema, columns: Vec<AliasColumn<'a>>, rows: RowOffset) -> RefView<'a> {
         RefView { schema: schema, columns: columns, rows: rows }
     }
+
+    /// Window into the same data as this view. Unlike `window_alias` the window is not tied to
+    /// the lifetime of this view.
+    pub fn window(&self, range: RowRange) -> Result<RefView<'a>, DBError> {
+        if range.offset + range.rows > self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let mut columns = Vec::with_capacity(self.columns.len());
+        for col in &self.columns {
+            columns.push(col.slice(range)?);
+        }
+
+        Ok(RefView { schema: self.schema.clone(), columns: columns, rows: range.rows })
+    }
+
+    /// Windows of the rows before `row` and of the rest of the rows, aliasing the same data
+    pub fn split_at(&self, row: RowOffset) -> Result<(RefView<'a>, RefView<'a>), DBError> {
+        if row > self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let head = self.window(RowRange { offset: 0, rows: row })?;
+        let tail = self.window(RowRange { offset: row, rows: self.rows - row })?;
+        Ok((head, tail))
+    }
+
+    /// Aliases of the view columns
+    pub fn columns(&self) -> &[AliasColumn<'a>] {
+        &self.columns
+    }
 }
 
 /// A container for column data conforming to a pre-defined schema. This container is the owner of
@@ -399,6 +1604,8 @@ pub struct Block<'b> {
     columns: Vec<Column<'b>>,
     rows: RowOffset,
     capacity: RowOffset,
+    /// Cleared when the block is modified
+    stats: Option<BlockStats>,
 }
 
 impl<'b> View<'b> for Block<'b> {
@@ -414,6 +1621,25 @@ impl<'b> View<'b> for Block<'b> {
     fn rows(&self) -> RowOffset {
         self.rows
     }
+
+    fn stats(&self) -> Option<&BlockStats> {
+        self.stats.as_ref()
+    }
+}
+
+/// Clones share the column data (copy-on-write, see `Column`), so cloning is cheap. The zone map
+/// statistics are not cloned.
+impl<'b> Clone for Block<'b> {
+    fn clone(&self) -> Block<'b> {
+        Block {
+            allocator: self.allocator,
+            schema: self.schema.clone(),
+            columns: self.columns.clone(),
+            rows: self.rows,
+            capacity: self.capacity,
+            stats: None,
+        }
+    }
 }
 
 impl<'b> Block<'b> {
@@ -423,7 +1649,8 @@ impl<'b> Block<'b> {
             schema: schema.clone(),
             rows: 0,
             capacity: 0,
-            columns: Vec::new()
+            columns: Vec::new(),
+            stats: None,
         };
 
         for attr in schema.iter() {
@@ -433,6 +1660,162 @@ impl<'b> Block<'b> {
         b
     }
 
+    /// Block of already built columns (eg. the output of `filter_column()`) with `rows` rows.
+    pub fn from_columns(alloc: &'b Allocator, columns: Vec<Column<'b>>, rows: RowOffset)
+        -> Result<Block<'b>, DBError>
+    {
+        if columns.iter().any(|c| c.capacity() < rows) {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let attrs = columns.iter().map(|c| c.attribute().clone()).collect();
+        let capacity = columns.iter().map(|c| c.capacity()).min().unwrap_or(rows);
+
+        Ok(Block {
+            allocator: alloc,
+            schema: Schema::from_vec(attrs)?,
+            rows: rows,
+            capacity: capacity,
+            columns: columns,
+            stats: None,
+        })
+    }
+
+    /// Block with the declared `schema` (eg. carrying attribute metadata) in place of the one
+    /// derived from its columns. The attributes have to match by name and type. Columns of not
+    /// nullable attributes can't have NULL rows (an `AttributeNullability` error), nullable
+    /// columns of not nullable attributes drop their null vector and vice versa.
+    pub fn conform(mut self, schema: &Schema) -> Result<Block<'b>, DBError> {
+        if schema.count() != self.columns.len() {
+            return Err(DBError::ExpressionInputCount(
+                format!("{} columns for {} attributes", self.columns.len(), schema.count())))
+        }
+
+        let rows = self.rows;
+        for (col, attr) in self.columns.iter_mut().zip(schema.iter()) {
+            if col.attr.name != attr.name {
+                return Err(DBError::AttributeMissing(attr.name.clone()))
+            }
+            if col.attr.dtype != attr.dtype {
+                return Err(DBError::AttributeType(attr.name.clone()))
+            }
+            if col.attr.nullable == attr.nullable {
+                continue
+            }
+
+            expect_plain(col)?;
+            if attr.nullable {
+                let capacity = col.capacity();
+                col.attr.nullable = true;
+                if capacity > 0 {
+                    let nulls = col.allocator.allocate(capacity)?;
+                    col.data_mut()?.raw_nulls = nulls;
+                }
+                for flag in col.nulls_mut()?.iter_mut() {
+                    *flag = 0;
+                }
+            } else {
+                if let Some(nulls) = column_nulls(col) {
+                    if nulls[.. rows].iter().any(|&n| n != 0) {
+                        return Err(DBError::make_column_not_nullable(attr.name.clone()))
+                    }
+                }
+
+                col.attr.nullable = false;
+                col.data_mut()?.raw_nulls = OwnedChunk::empty();
+            }
+        }
+
+        self.schema = schema.clone();
+        Ok(self)
+    }
+
+    /// Allocator of the block's column data
+    pub fn allocator(&self) -> &'b Allocator {
+        self.allocator
+    }
+
+    /// Copy of the block rows with its own column data. Encoded columns are decoded.
+    pub fn deep_copy(&self) -> Result<Block<'b>, DBError> {
+        let mut out = Block::new(self.allocator, &self.schema);
+        if let Some(err) = out.set_capacity(self.rows) {
+            return Err(err)
+        }
+
+        for (src, dst) in self.columns.iter().zip(out.columns.iter_mut()) {
+            if src.data.intern.is_some() {
+                // Copy of the data as is, so the intern pool handles stay valid
+                *dst = src.clone();
+                dst.data_mut()?;
+            } else {
+                copy_column(src, dst, self.rows)?;
+            }
+        }
+
+        out.rows = self.rows;
+        Ok(out)
+    }
+
+    /// View of `len` rows from `offset`, aliasing the block's column data instead of copying it
+    pub fn slice(&self, offset: RowOffset, len: RowOffset) -> Result<RefView, DBError> {
+        window_alias(self, Some(RowRange { offset: offset, rows: len }))
+    }
+
+    /// Views of the rows before `row` and of the rest of the rows, aliasing the block's column
+    /// data
+    pub fn split_at(&self, row: RowOffset) -> Result<(RefView, RefView), DBError> {
+        if row > self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        Ok((self.slice(0, row)?, self.slice(row, self.rows - row)?))
+    }
+
+    /// Copy of the rows of all the blocks, in order, into a block with its own column data. The
+    /// blocks have to have the same attribute types and nullability, the attribute names are the
+    /// first block's. Encoded columns are decoded.
+    pub fn concat(blocks: &[&Block<'b>]) -> Result<Block<'b>, DBError> {
+        let first = match blocks.first() {
+            Some(block) => block,
+            None => return Err(DBError::ExpressionInputCount("concat of no blocks".to_string())),
+        };
+
+        let same = |block: &Block| block.schema.count() == first.schema.count() &&
+            first.schema.iter().zip(block.schema.iter())
+                .all(|(a, b)| a.dtype == b.dtype && a.nullable == b.nullable);
+
+        if !blocks.iter().all(|b| same(b)) {
+            return Err(DBError::AttributeType("concatenated block schema".to_string()))
+        }
+
+        let mut out = Block::new(first.allocator, &first.schema);
+        if let Some(err) = out.set_capacity(blocks.iter().map(|b| b.rows).sum()) {
+            return Err(err)
+        }
+
+        for block in blocks {
+            let start = out.rows;
+            out.add_rows(block.rows)?;
+
+            for (src, dst) in block.columns.iter().zip(out.columns.iter_mut()) {
+                for row in 0 .. block.rows {
+                    set_value(dst, start + row, &column_value(src, row)?)?;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// True if the blocks have the same attribute types and the same rows, in the same order.
+    /// Attribute names and column encodings don't matter, NULL equals NULL and NaN equals NaN.
+    pub fn eq_data(&self, other: &Block) -> bool {
+        match views_diff(self, other, Tolerance::Exact) {
+            Ok(diff) => diff.is_none(),
+            Err(_) => false,
+        }
+    }
+
     /// Number of rows the Block can currently grow to without re-allocating column data.
     pub fn capacity(&self) -> RowOffset {
         self.capacity
@@ -440,6 +1823,8 @@ impl<'b> Block<'b> {
 
     /// Grow possible row space for each column
     pub fn set_capacity(&mut self, row_cap: RowOffset) -> Option<DBError> {
+        self.stats = None;
+
         for ref mut col in &mut self.columns {
             let status = col.set_capacity(row_cap);
             if status.is_some() {
@@ -455,15 +1840,18 @@ impl<'b> Block<'b> {
         None
     }
 
-    /// Returns rowid of the added row
+    /// Returns rowid of the added row. The capacity grows by a batch of rows sized for the schema
+    /// width (see `Schema::batch_rows()`).
     pub fn add_row(&mut self) -> Result<RowOffset, DBError> {
+        self.stats = None;
+
         if self.capacity > self.rows {
             let rowid = self.rows;
             self.rows += 1;
             Ok(rowid)
         } else {
             let rowid = self.rows;
-            let new_cap = self.capacity + 1024;
+            let new_cap = self.capacity + self.schema.batch_rows(DEFAULT_BATCH_BYTES);
 
             if let Some(err) = self.set_capacity(new_cap) {
                 Err(err)
@@ -476,6 +1864,8 @@ impl<'b> Block<'b> {
 
     /// Add a slew of uninitialized rows
     pub fn add_rows(&mut self, rows: RowOffset) -> Result<RowOffset, DBError> {
+        self.stats = None;
+
         if self.capacity > self.rows + rows {
             let rowid = self.rows + rows;
             self.rows += rows;
@@ -494,10 +1884,134 @@ impl<'b> Block<'b> {
         }
     }
 
+    /// Append a row of the values, one per attribute, returns its rowid. The values are checked
+    /// against the schema before the row is added. TEXT and BLOB data is copied into the column
+    /// arenas.
+    pub fn append_row(&mut self, values: &[Value]) -> Result<RowOffset, DBError> {
+        self.check_row(values)?;
+
+        let row = self.add_row()?;
+        for (col, value) in self.columns.iter_mut().zip(values) {
+            set_value(col, row, value)?;
+        }
+
+        Ok(row)
+    }
+
+    /// Append a row for each of the value tuples, returns the rowid of the first one. Nothing is
+    /// added unless all of the rows conform to the schema.
+    pub fn append_rows<'v, R>(&mut self, rows: &[R]) -> Result<RowOffset, DBError>
+        where R: AsRef<[Value<'v>]>
+    {
+        for values in rows {
+            self.check_row(values.as_ref())?;
+        }
+
+        let first = self.rows;
+        self.add_rows(rows.len())?;
+
+        for (row, values) in (first ..).zip(rows) {
+            for (col, value) in self.columns.iter_mut().zip(values.as_ref()) {
+                set_value(col, row, value)?;
+            }
+        }
+
+        Ok(first)
+    }
+
+    fn check_row(&self, values: &[Value]) -> Result<(), DBError> {
+        if values.len() != self.columns.len() {
+            return Err(DBError::ExpressionInputCount(
+                format!("{} values for {} attributes", values.len(), self.columns.len())))
+        }
+
+        for (col, value) in self.columns.iter().zip(values) {
+            if col.encoding != Encoding::PLAIN {
+                return Err(DBError::ColumnEncoding(col.attr.name.clone()))
+            }
+            check_value(&col.attr, value)?;
+        }
+
+        Ok(())
+    }
+
     /// Mutable reference to column and its data.
     pub fn column_mut(&mut self, pos: usize) -> Option<&mut Column<'b>> {
+        self.stats = None;
         self.columns.get_mut(pos)
     }
+
+    /// Intern the values of the named TEXT columns (see `Column::intern_text()`), set before
+    /// filling the block
+    pub fn intern_columns(&mut self, names: &[&str], max_entries: usize) -> Result<(), DBError> {
+        for name in names {
+            let pos = self.schema.exists_ok(name)?;
+            self.columns[pos].intern_text(max_entries)?;
+        }
+        Ok(())
+    }
+
+    /// Intern pool counters of the interning columns, by attribute name
+    pub fn intern_stats(&self) -> Vec<(String, InternStats)> {
+        self.columns.iter()
+            .filter_map(|c| c.intern_stats().map(|stats| (c.attr.name.clone(), stats)))
+            .collect()
+    }
+
+    /// Compute the zone map of the block rows. Done once the block is built, modifying the block
+    /// discards the statistics.
+    pub fn compute_stats(&mut self) -> Result<&BlockStats, DBError> {
+        if self.stats.is_none() {
+            let mut columns = Vec::with_capacity(self.columns.len());
+            for col in &self.columns {
+                columns.push(ColumnStats::compute(col, self.rows)?);
+            }
+
+            self.stats = Some(BlockStats { rows: self.rows, columns: columns });
+        }
+
+        Ok(self.stats.as_ref().unwrap())
+    }
+
+    /// Remove all rows. Column data and arenas (all of their chunks) are kept allocated, so the
+    /// block can be re-filled with as much data without new allocations.
+    pub fn clear(&mut self) -> Option<DBError> {
+        self.rows = 0;
+        self.stats = None;
+
+        let capacity = self.capacity;
+        for col in &mut self.columns {
+            // Encoded and shared columns have their data released
+            let released = col.clear();
+            if released && capacity > 0 {
+                if let Some(err) = col.set_capacity(capacity) {
+                    return Some(err)
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Encode each PLAIN column using the encoding picked by `choose_encoding`. Done once the
+    /// block is built, since encoded columns can't be modified.
+    pub fn encode_columns(&mut self) -> Result<(), DBError> {
+        let rows = self.rows;
+
+        for col in &mut self.columns {
+            if col.encoding != Encoding::PLAIN {
+                continue
+            }
+
+            match choose_encoding(col, rows)? {
+                Encoding::DICTIONARY    => col.encode_dictionary(rows)?,
+                Encoding::RLE           => col.encode_rle(rows)?,
+                Encoding::PLAIN         => (),
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<'a> Index<usize> for Block<'a> {
@@ -513,4 +2027,53 @@ impl<'a> IndexMut<usize> for Block<'a> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         &mut self.columns[index]
     }
-}
\ No newline at end of file
+}
+
+/// Pool of cleared blocks.
+///
+/// Operations return blocks they are done with and get a block back when building the next
+/// output. A returned block is reused for the same schema, which skips re-allocating column data
+/// and arenas in steady-state pipelines.
+pub struct BlockPool<'a> {
+    allocator: &'a Allocator,
+    blocks: RefCell<Vec<Block<'a>>>,
+    max_blocks: usize,
+}
+
+impl<'a> BlockPool<'a> {
+    /// Pool holding on to no more than `max_blocks` blocks, extra returned blocks are dropped.
+    pub fn new(alloc: &'a Allocator, max_blocks: usize) -> BlockPool<'a> {
+        BlockPool { allocator: alloc, blocks: RefCell::new(Vec::new()), max_blocks: max_blocks }
+    }
+
+    /// Empty block for `schema`, either a pooled one or a newly created one.
+    pub fn get(&self, schema: &Schema) -> Block<'a> {
+        let mut blocks = self.blocks.borrow_mut();
+
+        match blocks.iter().position(|b| b.schema.iter().eq(schema.iter())) {
+            Some(pos) => blocks.swap_remove(pos),
+            None => Block::new(self.allocator, schema),
+        }
+    }
+
+    /// Return a block to the pool. The block is cleared first.
+    pub fn put(&self, mut block: Block<'a>) {
+        if block.clear().is_some() {
+            return
+        }
+
+        let mut blocks = self.blocks.borrow_mut();
+        if blocks.len() < self.max_blocks {
+            blocks.push(block);
+        }
+    }
+
+    /// Number of blocks currently available for reuse
+    pub fn len(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,265 @@
+//! Typed column and block builders.
+//!
+//! `ColumnBuilder` appends values to a column of a `ValueInfo` type, growing its row space as
+//! needed, so data can be built from Rust values without touching the column's raw data.
+//! `BlockBuilder` assembles the built columns into a `Block` of a schema.
+
+use std::marker::PhantomData;
+
+use ::allocator::Allocator;
+use ::block::{Block, Column, RefColumn};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::ValueInfo;
+use ::util::copy_value::ValueSetter;
+use ::util::math::round_up;
+
+/// Column of `T` values built by appending rows
+pub struct ColumnBuilder<'a, T: ValueInfo> {
+    alloc: &'a Allocator,
+    column: Column<'a>,
+    rows: RowOffset,
+    dtype: PhantomData<T>,
+}
+
+impl<'a, T: ValueInfo> ColumnBuilder<'a, T> {
+    /// Builder of a column of the attribute, it must be of `T`'s type
+    pub fn new(alloc: &'a Allocator, attr: Attribute) -> Result<ColumnBuilder<'a, T>, DBError> {
+        if attr.dtype != T::ENUM {
+            return Err(DBError::AttributeType(attr.name))
+        }
+
+        Ok(ColumnBuilder {
+            alloc: alloc,
+            column: Column::new(alloc, attr),
+            rows: 0,
+            dtype: PhantomData,
+        })
+    }
+
+    /// Builder of a column named `name`
+    pub fn named(alloc: &'a Allocator, name: &str, nullable: bool) -> ColumnBuilder<'a, T> {
+        let attr = Attribute { name: name.to_string(), nullable: nullable, dtype: T::ENUM };
+        ColumnBuilder {
+            alloc: alloc,
+            column: Column::new(alloc, attr),
+            rows: 0,
+            dtype: PhantomData,
+        }
+    }
+
+    pub fn attribute(&self) -> &Attribute {
+        self.column.attribute()
+    }
+
+    /// Number of rows appended
+    pub fn rows(&self) -> RowOffset {
+        self.rows
+    }
+
+    /// Make room for `rows` more rows, returns the first of them
+    fn reserve(&mut self, rows: RowOffset) -> Result<RowOffset, DBError> {
+        let needed = self.rows + rows;
+        if needed > self.column.capacity() {
+            let new_cap = round_up(needed.max(self.column.capacity() * 2), 1024);
+            if let Some(err) = self.column.set_capacity(new_cap) {
+                return Err(err)
+            }
+        }
+
+        Ok(self.rows)
+    }
+
+    /// Append a row of the value, which has to match the column type (eg. `Value::TIMESTAMP` for
+    /// TIMESTAMP columns) or be NULL. Returns the row offset.
+    pub fn append<V: ValueSetter>(&mut self, value: V) -> Result<RowOffset, DBError> {
+        let row = self.reserve(1)?;
+
+        value.set_row(&mut self.column, row)?;
+        if self.column.attribute().nullable {
+            self.column.nulls_mut()?[row] = value.is_null() as u8;
+        }
+
+        self.rows += 1;
+        Ok(row)
+    }
+
+    /// Append a NULL row, the column must be nullable
+    pub fn append_null(&mut self) -> Result<RowOffset, DBError> {
+        if !self.column.attribute().nullable {
+            return Err(DBError::AttributeNullability(self.column.attribute().name.clone()))
+        }
+
+        let row = self.reserve(1)?;
+        self.column.nulls_mut()?[row] = 1;
+        self.rows += 1;
+        Ok(row)
+    }
+
+    /// Append non NULL rows of the stored values. Variable length types (TEXT, BLOB) store
+    /// references to data in the column arena, their values have to be appended with `append()`.
+    pub fn append_slice(&mut self, values: &[T::Store]) -> Result<RowOffset, DBError>
+        where T::Store: Copy
+    {
+        if T::VARLEN {
+            return Err(DBError::AttributeType(self.column.attribute().name.clone()))
+        }
+
+        let start = self.reserve(values.len())?;
+        let end = start + values.len();
+
+        self.column.rows_mut::<T>()?[start .. end].copy_from_slice(values);
+        if self.column.attribute().nullable {
+            for flag in &mut self.column.nulls_mut()?[start .. end] {
+                *flag = 0;
+            }
+        }
+
+        self.rows = end;
+        Ok(start)
+    }
+
+    /// Single column block of the appended rows
+    pub fn finish(self) -> Result<Block<'a>, DBError> {
+        Block::from_columns(self.alloc, vec![self.column], self.rows)
+    }
+}
+
+/// Block of a schema assembled from a column builder for each of its attributes
+pub struct BlockBuilder<'a> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    columns: Vec<Option<(Column<'a>, RowOffset)>>,
+}
+
+impl<'a> BlockBuilder<'a> {
+    pub fn new(alloc: &'a Allocator, schema: &Schema) -> BlockBuilder<'a> {
+        BlockBuilder {
+            alloc: alloc,
+            schema: schema.clone(),
+            columns: schema.iter().map(|_| None).collect(),
+        }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Empty builder of the named attribute's column, `T` must be the attribute type
+    pub fn column<T: ValueInfo>(&self, name: &str) -> Result<ColumnBuilder<'a, T>, DBError> {
+        let pos = self.schema.exists_ok(name)?;
+        ColumnBuilder::new(self.alloc, self.schema[pos].clone())
+    }
+
+    /// Add the built column of one of the schema's attributes
+    pub fn add<T: ValueInfo>(&mut self, column: ColumnBuilder<'a, T>) -> Result<(), DBError> {
+        let pos = self.schema.exists_ok(&column.attribute().name)?;
+        if *column.attribute() != self.schema[pos] {
+            return Err(DBError::AttributeType(column.attribute().name.clone()))
+        }
+        if self.columns[pos].is_some() {
+            return Err(DBError::AttributeDuplicate(column.attribute().name.clone()))
+        }
+
+        self.columns[pos] = Some((column.column, column.rows));
+        Ok(())
+    }
+
+    /// Block of the added columns, every attribute needs a column and all of them the same number
+    /// of rows
+    pub fn finish(self) -> Result<Block<'a>, DBError> {
+        let mut columns = Vec::with_capacity(self.columns.len());
+        let mut rows = None;
+
+        for (attr, column) in self.schema.iter().zip(self.columns) {
+            let (column, column_rows) = column
+                .ok_or_else(|| DBError::AttributeMissing(attr.name.clone()))?;
+
+            if *rows.get_or_insert(column_rows) != column_rows {
+                return Err(DBError::RowOutOfBounds)
+            }
+            columns.push(column);
+        }
+
+        Block::from_columns(self.alloc, columns, rows.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_value};
+    use ::types::{Int64, Text, Timestamp, Type, Value, NULL_VALUE};
+
+    // Appended values, NULLs and slices end up in the built block
+    #[test]
+    fn build_block() {
+        let attr = |name: &str, nullable, dtype| Attribute { name: name.to_string(),
+                                                             nullable: nullable, dtype: dtype };
+        let schema = Schema::from_vec(vec![
+            attr("id", false, Type::INT64),
+            attr("name", true, Type::TEXT),
+        ]).unwrap();
+
+        let mut builder = BlockBuilder::new(&allocator::GLOBAL, &schema);
+
+        let mut ids = builder.column::<Int64>("id").unwrap();
+        ids.append_slice(&(0 .. 2000).collect::<Vec<i64>>()).unwrap();
+        assert_eq!(ids.append(5i64).unwrap(), 2000);
+        assert!(ids.append_null().is_err());
+
+        let mut names = builder.column::<Text>("name").unwrap();
+        for row in 0 .. 2001 {
+            if row % 3 == 0 {
+                names.append_null().unwrap();
+            } else {
+                names.append(format!("n{}", row)).unwrap();
+            }
+        }
+        assert!(names.append_slice(&[]).is_err());
+
+        assert!(builder.column::<Text>("id").is_err());
+        builder.add(ids).unwrap();
+        let other = ColumnBuilder::<Int64>::named(&allocator::GLOBAL, "id", false);
+        assert!(builder.add(other).is_err());
+        builder.add(names).unwrap();
+
+        let block = builder.finish().unwrap();
+        assert_eq!(block.rows(), 2001);
+        assert!(column_value(block.column(0).unwrap(), 1999).unwrap() == Value::INT64(1999));
+        assert!(column_value(block.column(0).unwrap(), 2000).unwrap() == Value::INT64(5));
+        assert!(column_value(block.column(1).unwrap(), 3).unwrap() == Value::NULL);
+        assert!(column_value(block.column(1).unwrap(), 4).unwrap() == Value::TEXT("n4"));
+
+        // Missing columns and columns of different lengths
+        let mut builder = BlockBuilder::new(&allocator::GLOBAL, &schema);
+        let mut ids = builder.column::<Int64>("id").unwrap();
+        ids.append(1i64).unwrap();
+        builder.add(ids).unwrap();
+        let mut names = builder.column::<Text>("name").unwrap();
+        names.append("a").unwrap();
+        names.append("b").unwrap();
+        builder.add(names).unwrap();
+        match builder.finish() {
+            Err(DBError::RowOutOfBounds) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        match BlockBuilder::new(&allocator::GLOBAL, &schema).finish() {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let mut times = ColumnBuilder::<Timestamp>::named(&allocator::GLOBAL, "ts", true);
+        times.append(Value::TIMESTAMP(10)).unwrap();
+        times.append(NULL_VALUE).unwrap();
+        times.append_slice(&[20, 30]).unwrap();
+        let block = times.finish().unwrap();
+        assert_eq!(block.rows(), 4);
+        assert!(column_value(block.column(0).unwrap(), 1).unwrap() == Value::NULL);
+        assert!(column_value(block.column(0).unwrap(), 3).unwrap() == Value::TIMESTAMP(30));
+    }
+}
@@ -0,0 +1,167 @@
+// vim: set ts=4 sw=4 et :
+
+//! Cached query results.
+//!
+//! A `ResultCache` keeps the materialized rows of queries by a fingerprint of the query, along
+//! with the versions of the tables they were computed from. A cached result is only returned
+//! while all of its tables are still at the same version, so repeated identical queries (eg. of
+//! dashboards) are answered without executing them again until the data changes. When full,
+//! the least recently used result is dropped.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ::block::Block;
+
+/// Number of results kept by `ResultCache::default()`
+pub const DEFAULT_CACHE_CAPACITY: usize = 64;
+
+struct CachedResult<'a> {
+    /// (table name, version) of each table the result was computed from
+    versions: Vec<(String, u64)>,
+    rows: Rc<Block<'a>>,
+    last_used: u64,
+}
+
+/// Query results by fingerprint
+pub struct ResultCache<'a> {
+    capacity: usize,
+    entries: HashMap<String, CachedResult<'a>>,
+    /// Incremented on every use, orders the entries by recency
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<'a> ResultCache<'a> {
+    /// Cache of at most `capacity` results
+    pub fn new(capacity: usize) -> ResultCache<'a> {
+        ResultCache {
+            capacity: capacity,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of lookups that returned a cached result
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of lookups without a (valid) cached result
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Cached result of the fingerprint, if its tables are still at the versions it was
+    /// computed from. `version` returns the current version of a table, a result with a table
+    /// that's gone or no longer versioned is stale too. Stale results are dropped.
+    pub fn get<F>(&mut self, fingerprint: &str, version: F) -> Option<Rc<Block<'a>>>
+        where F: Fn(&str) -> Option<u64>
+    {
+        let valid = match self.entries.get(fingerprint) {
+            Some(entry) => entry.versions.iter().all(|v| version(&v.0) == Some(v.1)),
+            None => {
+                self.misses += 1;
+                return None
+            },
+        };
+
+        if !valid {
+            self.entries.remove(fingerprint);
+            self.misses += 1;
+            return None
+        }
+
+        self.clock += 1;
+        self.hits += 1;
+        let entry = self.entries.get_mut(fingerprint).unwrap();
+        entry.last_used = self.clock;
+        Some(entry.rows.clone())
+    }
+
+    /// Keep the result of the fingerprint, computed from the tables at `versions`. Replaces any
+    /// previous result of the fingerprint, drops the least recently used result when full.
+    pub fn insert(&mut self, fingerprint: String, versions: Vec<(String, u64)>,
+                  rows: Rc<Block<'a>>)
+    {
+        if self.capacity == 0 {
+            return
+        }
+
+        if !self.entries.contains_key(&fingerprint) && self.entries.len() >= self.capacity {
+            let oldest = self.entries.iter()
+                .min_by_key(|&(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(key) = oldest {
+                self.entries.remove(&key);
+            }
+        }
+
+        self.clock += 1;
+        let entry = CachedResult { versions: versions, rows: rows, last_used: self.clock };
+        self.entries.insert(fingerprint, entry);
+    }
+
+    /// Drop all the cached results
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<'a> Default for ResultCache<'a> {
+    fn default() -> ResultCache<'a> {
+        ResultCache::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::schema::Schema;
+    use ::types::Type;
+
+    fn rows<'a>() -> Rc<Block<'a>> {
+        Rc::new(Block::new(&allocator::GLOBAL, &Schema::make_one_attr("a", false, Type::INT32)))
+    }
+
+    // Results are returned while their table versions match, least recently used are dropped
+    #[test]
+    fn cached_versions() {
+        let mut cache = ResultCache::new(2);
+        let current = |v: u64| move |name: &str| if name == "t" { Some(v) } else { None };
+
+        cache.insert("q1".to_string(), vec![("t".to_string(), 1)], rows());
+        assert!(cache.get("q1", current(1)).is_some());
+        assert!(cache.get("q2", current(1)).is_none());
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // A new table version invalidates the result
+        assert!(cache.get("q1", current(2)).is_none());
+        assert!(cache.is_empty());
+
+        cache.insert("q1".to_string(), vec![("t".to_string(), 2)], rows());
+        cache.insert("q2".to_string(), vec![], rows());
+        assert!(cache.get("q1", current(2)).is_some());
+        cache.insert("q3".to_string(), vec![("gone".to_string(), 1)], rows());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("q2", current(2)).is_none());
+        assert!(cache.get("q1", current(2)).is_some());
+        assert!(cache.get("q3", current(2)).is_none());
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+// vim: set ts=4 sw=4 et :
+
+//! Named tables.
+//!
+//! A `Catalog` maps names to `TableProvider`s, so query frontends can resolve table references
+//! (eg. SQL `FROM` clauses) and tools can list the available datasets and their schemas.
+//! Providers of versioned data (eg. `TableSnapshot`s) report the version of their rows, so
+//! results computed from them can be cached until the data changes.
+
+use std::collections::BTreeMap;
+
+use ::block::View;
+use ::error::DBError;
+use ::plan::LogicalPlan;
+use ::schema::Schema;
+use ::table::TableSnapshot;
+
+/// Source of a named table's rows
+pub trait TableProvider<'a> {
+    fn schema(&self) -> &Schema;
+
+    /// Plan reading all the rows of the table
+    fn scan(&self) -> Result<LogicalPlan<'a>, DBError>;
+
+    /// Version of the rows, changing whenever they change. `None` if the rows aren't versioned.
+    fn version(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Provider for in memory data: a `Table`, `Block` or any other `View`
+pub struct ViewProvider<'a> {
+    view: &'a View<'a>,
+}
+
+/// Provider for a table snapshot, versioned by the table version it was taken at
+pub struct SnapshotProvider<'a> {
+    snapshot: &'a TableSnapshot<'a>,
+}
+
+pub struct Catalog<'a> {
+    tables: BTreeMap<String, Box<TableProvider<'a> + 'a>>,
+}
+
+impl<'a> ViewProvider<'a> {
+    pub fn new(view: &'a View<'a>) -> ViewProvider<'a> {
+        ViewProvider { view: view }
+    }
+}
+
+impl<'a> TableProvider<'a> for ViewProvider<'a> {
+    fn schema(&self) -> &Schema {
+        self.view.schema()
+    }
+
+    fn scan(&self) -> Result<LogicalPlan<'a>, DBError> {
+        Ok(LogicalPlan::scan(self.view))
+    }
+}
+
+impl<'a> SnapshotProvider<'a> {
+    pub fn new(snapshot: &'a TableSnapshot<'a>) -> SnapshotProvider<'a> {
+        SnapshotProvider { snapshot: snapshot }
+    }
+}
+
+impl<'a> TableProvider<'a> for SnapshotProvider<'a> {
+    fn schema(&self) -> &Schema {
+        self.snapshot.schema()
+    }
+
+    fn scan(&self) -> Result<LogicalPlan<'a>, DBError> {
+        Ok(LogicalPlan::scan(self.snapshot))
+    }
+
+    fn version(&self) -> Option<u64> {
+        Some(self.snapshot.version())
+    }
+}
+
+impl<'a> Catalog<'a> {
+    pub fn new() -> Catalog<'a> {
+        Catalog { tables: BTreeMap::new() }
+    }
+
+    /// Add the table, replacing any previous table by that name
+    pub fn register<S, T>(&mut self, name: S, provider: T)
+        where S: Into<String>, T: TableProvider<'a> + 'a
+    {
+        self.tables.insert(name.into(), Box::new(provider));
+    }
+
+    pub fn register_view<S: Into<String>>(&mut self, name: S, view: &'a View<'a>) {
+        self.register(name, ViewProvider::new(view))
+    }
+
+    pub fn register_snapshot<S: Into<String>>(&mut self, name: S, snapshot: &'a TableSnapshot<'a>) {
+        self.register(name, SnapshotProvider::new(snapshot))
+    }
+
+    /// Remove the table, returns false if there's no such table
+    pub fn deregister(&mut self, name: &str) -> bool {
+        self.tables.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Result<&TableProvider<'a>, DBError> {
+        self.tables.get(name)
+            .map(|t| &**t)
+            .ok_or_else(|| DBError::TableMissing(name.to_string()))
+    }
+
+    pub fn schema(&self, name: &str) -> Result<&Schema, DBError> {
+        self.get(name).map(|t| t.schema())
+    }
+
+    /// Version of the table's rows, `None` if they aren't versioned
+    pub fn version(&self, name: &str) -> Result<Option<u64>, DBError> {
+        self.get(name).map(|t| t.version())
+    }
+
+    /// Plan scanning the table, its scans are named `name` (see `LogicalPlan::lineage`)
+    pub fn scan(&self, name: &str) -> Result<LogicalPlan<'a>, DBError> {
+        Ok(self.get(name)?.scan()?.with_table(name))
+    }
+
+    /// Names of the registered tables, in sorted order
+    pub fn names(&self) -> Vec<&str> {
+        self.tables.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::table::Table;
+    use ::types::Type;
+
+    #[test]
+    fn register_and_lookup() {
+        let first = Table::new(&allocator::GLOBAL, &Schema::make_one_attr("a", false, Type::INT32),
+                               None);
+        let second = Table::new(&allocator::GLOBAL, &Schema::make_one_attr("b", true, Type::TEXT),
+                                None);
+
+        let mut catalog = Catalog::new();
+        catalog.register_view("second", &second);
+        catalog.register_view("first", &first);
+        assert_eq!(catalog.names(), vec!["first", "second"]);
+
+        assert_eq!(catalog.schema("second").unwrap().get(0).unwrap().name, "b");
+        assert_eq!(catalog.scan("first").unwrap().explain(), "Scan");
+
+        assert!(catalog.deregister("second"));
+        assert!(!catalog.deregister("second"));
+
+        let missing = catalog.schema("second");
+
+        match missing {
+            Err(DBError::TableMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Snapshots are versioned by their table version, views aren't versioned
+    #[test]
+    fn table_versions() {
+        let schema = Schema::make_one_attr("a", false, Type::INT32);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        table.add_row().unwrap();
+        let snapshot = table.snapshot();
+        let view = Table::new(&allocator::GLOBAL, &schema, None);
+
+        let mut catalog = Catalog::new();
+        catalog.register_snapshot("snapshot", &snapshot);
+        catalog.register_view("view", &view);
+
+        assert_eq!(catalog.version("snapshot").unwrap(), Some(table.version()));
+        assert_eq!(catalog.version("view").unwrap(), None);
+        assert_eq!(catalog.scan("snapshot").unwrap().schema().unwrap()[0].name, "a");
+        assert!(catalog.version("missing").is_err());
+    }
+}
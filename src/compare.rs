@@ -0,0 +1,335 @@
+// vim: set ts=4 sw=4 et :
+
+//! Multi-column row comparisons.
+//!
+//! A `RowComparator` orders the rows of a view by a list of `SortKey`s: a column, its sort order
+//! and where its NULLs go. It's the comparison sort of `Sort`, operators matching the rows of two
+//! inputs sorted by the same keys (eg. a merge join) compare them with `compare_with`, and equal
+//! rows compare `Ordering::Equal` (eg. for finding distinct rows of sorted input).
+//!
+//! Non NULL key values are ordered by `compare_keys`: NaNs are greater than any other value and
+//! equal to each other, -0.0 equals 0.0. INTERVAL, LIST and STRUCT keys can't be ordered.
+//!
+//! `normalized_key` encodes the keys of a row as bytes that compare (as byte strings, eg. with
+//! `memcmp`) in the order of the rows. Each key is a NULL flag byte, 0 for NULLs placed first, 2
+//! for NULLs placed last and 1 for other values, followed by the encoding of non NULL values:
+//!
+//! - UINT32, UINT64: big endian bytes
+//! - INT32, INT64, TIMESTAMP: big endian bytes with the sign bit flipped
+//! - FLOAT32, FLOAT64: big endian bits with the sign bit flipped for positive values and all
+//!   bits flipped for negative values. -0.0 is encoded as 0.0, every NaN as the canonical NaN.
+//! - BOOLEAN: a byte 0 or 1
+//! - UUID: its 16 bytes
+//! - TEXT, BLOB: the bytes with every 0 byte escaped as 0 0xff, ending with 0 0
+//!
+//! The value bytes (not the NULL flag) of DESC keys are flipped.
+
+use std::cmp::Ordering;
+use std::{f32, f64};
+
+use ::block::{View, column_value};
+use ::error::DBError;
+use ::plan::SortOrder;
+use ::row::RowOffset;
+use ::stats::compare_values;
+use ::types::Value;
+
+/// Placement of the NULL values of a sort key
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NullOrder {
+    First,
+    Last,
+}
+
+/// Column position, order and NULL placement of a `RowComparator` key
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SortKey {
+    pub column: usize,
+    pub order: SortOrder,
+    pub nulls: NullOrder,
+}
+
+impl SortKey {
+    /// Key with NULLs greater than any value, like `Sort` orders them: last in ASC order, first
+    /// in DESC order
+    pub fn new(column: usize, order: SortOrder) -> SortKey {
+        let nulls = if order == SortOrder::ASC { NullOrder::Last } else { NullOrder::First };
+        SortKey { column: column, order: order, nulls: nulls }
+    }
+
+    pub fn nulls(mut self, nulls: NullOrder) -> SortKey {
+        self.nulls = nulls;
+        self
+    }
+}
+
+/// Total order of key values: NULLs are greatest, followed by NaNs
+pub fn compare_keys(lhs: &Value, rhs: &Value) -> Ordering {
+    match (lhs, rhs) {
+        (&Value::NULL, &Value::NULL)    => Ordering::Equal,
+        (&Value::NULL, _)               => Ordering::Greater,
+        (_, &Value::NULL)               => Ordering::Less,
+        _ => compare_values(lhs, rhs)
+            .unwrap_or_else(|| is_nan(lhs).cmp(&is_nan(rhs))),
+    }
+}
+
+/// True for FLOAT32 and FLOAT64 NaN values
+pub fn is_nan(value: &Value) -> bool {
+    match *value {
+        Value::FLOAT32(v)   => v.is_nan(),
+        Value::FLOAT64(v)   => v.is_nan(),
+        _                   => false,
+    }
+}
+
+/// Order of the values of a key, of the key's order and NULL placement
+fn compare_key(lhs: &Value, rhs: &Value, key: &SortKey) -> Ordering {
+    let null_first = match key.nulls {
+        NullOrder::First => Ordering::Less,
+        NullOrder::Last  => Ordering::Greater,
+    };
+
+    match (lhs, rhs) {
+        (&Value::NULL, &Value::NULL)    => Ordering::Equal,
+        (&Value::NULL, _)               => null_first,
+        (_, &Value::NULL)               => null_first.reverse(),
+        _ => match key.order {
+            SortOrder::ASC  => compare_keys(lhs, rhs),
+            SortOrder::DESC => compare_keys(rhs, lhs),
+        },
+    }
+}
+
+/// Append the memcmp-able encoding of a non NULL value, see the module documentation
+fn encode_value(out: &mut Vec<u8>, value: &Value) {
+    fn push_be(out: &mut Vec<u8>, v: u64, bytes: usize) {
+        for shift in (0 .. bytes).rev() {
+            out.push((v >> (shift * 8)) as u8);
+        }
+    }
+
+    fn push_escaped(out: &mut Vec<u8>, bytes: &[u8]) {
+        for &b in bytes {
+            out.push(b);
+            if b == 0 {
+                out.push(0xff);
+            }
+        }
+        out.extend_from_slice(&[0, 0]);
+    }
+
+    match *value {
+        Value::UINT32(v)        => push_be(out, v as u64, 4),
+        Value::UINT64(v)        => push_be(out, v, 8),
+        Value::INT32(v)         => push_be(out, (v as u32 ^ 1 << 31) as u64, 4),
+        Value::INT64(v)         => push_be(out, v as u64 ^ 1 << 63, 8),
+        Value::TIMESTAMP(v)     => push_be(out, v as u64 ^ 1 << 63, 8),
+        Value::FLOAT32(v)       => {
+            let v = if v == 0.0 { 0.0 } else if v.is_nan() { f32::NAN } else { v };
+            let bits = v.to_bits();
+            push_be(out, (if bits >> 31 == 1 { !bits } else { bits ^ 1 << 31 }) as u64, 4);
+        },
+        Value::FLOAT64(v)       => {
+            let v = if v == 0.0 { 0.0 } else if v.is_nan() { f64::NAN } else { v };
+            let bits = v.to_bits();
+            push_be(out, if bits >> 63 == 1 { !bits } else { bits ^ 1 << 63 }, 8);
+        },
+        Value::BOOLEAN(v)       => out.push(v as u8),
+        Value::UUID(ref v)      => out.extend_from_slice(v),
+        Value::TEXT(v)          => push_escaped(out, v.as_bytes()),
+        Value::BLOB(v)          => push_escaped(out, v),
+        // Unordered types are rejected by `RowComparator::new`
+        _                       => (),
+    }
+}
+
+/// Ordering of the rows of a view by a list of sort keys
+pub struct RowComparator<'v> {
+    keys: Vec<SortKey>,
+    /// Values of each key column, by row
+    values: Vec<Vec<Value<'v>>>,
+    rows: RowOffset,
+}
+
+impl<'v> RowComparator<'v> {
+    /// Comparator of the `view` rows by the `keys`, reads the key values of all the rows. Keys of
+    /// unordered types are an `AttributeType` error.
+    pub fn new(view: &'v View<'v>, keys: &[SortKey]) -> Result<RowComparator<'v>, DBError> {
+        let mut values = Vec::with_capacity(keys.len());
+
+        for key in keys {
+            let col = view.column(key.column)
+                .ok_or(DBError::make_column_unknown_pos(key.column))?;
+
+            let mut column = Vec::with_capacity(view.rows());
+            for row in 0 .. view.rows() {
+                let value = column_value(col, row)?;
+                if compare_values(&value, &value).is_none() {
+                    match value {
+                        Value::NULL | Value::FLOAT32(_) | Value::FLOAT64(_) => (),
+                        // Unordered types
+                        _ => return Err(DBError::AttributeType(col.attribute().name.clone())),
+                    }
+                }
+                column.push(value);
+            }
+
+            values.push(column);
+        }
+
+        Ok(RowComparator { keys: keys.to_vec(), values: values, rows: view.rows() })
+    }
+
+    pub fn keys(&self) -> &[SortKey] {
+        &self.keys
+    }
+
+    /// Number of rows of the view
+    pub fn rows(&self) -> RowOffset {
+        self.rows
+    }
+
+    /// Order of two rows of the view
+    pub fn compare(&self, lhs: RowOffset, rhs: RowOffset) -> Ordering {
+        self.compare_with(lhs, self, rhs)
+    }
+
+    /// Order of a row of the view and a row of the `other` comparator's view, by the keys of this
+    /// comparator. The key columns of `other` have to be of the same types, in the same order.
+    pub fn compare_with(&self, row: RowOffset, other: &RowComparator, other_row: RowOffset)
+        -> Ordering
+    {
+        let pairs = self.values.iter().zip(&other.values);
+        for (key, (values, other_values)) in self.keys.iter().zip(pairs) {
+            let ord = compare_key(&values[row], &other_values[other_row], key);
+            if ord != Ordering::Equal {
+                return ord
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Row offsets of the view in key order. The sort is stable, rows with equal keys stay in
+    /// view order.
+    pub fn sorted_rows(&self) -> Vec<RowOffset> {
+        let mut rows: Vec<RowOffset> = (0 .. self.rows).collect();
+        rows.sort_by(|&l, &r| self.compare(l, r));
+        rows
+    }
+
+    /// Append the normalized (memcmp-able) key of the row, see the module documentation
+    pub fn normalized_key(&self, row: RowOffset, out: &mut Vec<u8>) {
+        for (key, values) in self.keys.iter().zip(&self.values) {
+            let value = &values[row];
+
+            if let Value::NULL = *value {
+                out.push(if key.nulls == NullOrder::First { 0 } else { 2 });
+                continue
+            }
+
+            out.push(1);
+            let start = out.len();
+            encode_value(out, value);
+
+            if key.order == SortOrder::DESC {
+                for b in &mut out[start ..] {
+                    *b = !*b;
+                }
+            }
+        }
+    }
+
+    /// Normalized keys of all the rows, by row
+    pub fn normalized_keys(&self) -> Vec<Vec<u8>> {
+        (0 .. self.rows)
+            .map(|row| {
+                let mut key = Vec::new();
+                self.normalized_key(row, &mut key);
+                key
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::schema::{Attribute, Schema};
+    use ::types::Type;
+
+    fn make_block<'a>() -> Block<'a> {
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "n".to_string(), nullable: true, dtype: Type::INT64},
+            Attribute{name: "f".to_string(), nullable: false, dtype: Type::FLOAT64},
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        let names = [Some("b"), None, Some("a\u{0}z"), Some("a"), Some(""), Some("b"),
+                     Some("a\u{0}")];
+        let floats = [f64::NAN, -0.0, 0.0, f64::NEG_INFINITY, 1e300, -2.5, f64::INFINITY];
+
+        for (row, (name, f)) in names.iter().zip(&floats).enumerate() {
+            let n = if row % 3 == 1 { Value::NULL } else { Value::INT64(row as i64 % 2 - 1) };
+            block.append_row(&[name.map_or(Value::NULL, Value::TEXT), n, Value::FLOAT64(*f)])
+                .unwrap();
+        }
+        block
+    }
+
+    // Keys order rows by their order and NULL placement, rows of two views compare by the keys
+    #[test]
+    fn compare_rows() {
+        let block = make_block();
+
+        let keys = [SortKey::new(0, SortOrder::ASC)];
+        let cmp = RowComparator::new(&block, &keys).unwrap();
+        assert_eq!(cmp.sorted_rows(), vec![4, 3, 6, 2, 0, 5, 1]);
+        assert_eq!(cmp.compare(0, 5), Ordering::Equal);
+
+        let keys = [SortKey::new(0, SortOrder::ASC).nulls(NullOrder::First),
+                    SortKey::new(2, SortOrder::DESC)];
+        let cmp = RowComparator::new(&block, &keys).unwrap();
+        assert_eq!(cmp.sorted_rows(), vec![1, 4, 3, 6, 2, 0, 5]);
+
+        let other = make_block();
+        let other_cmp = RowComparator::new(&other, &keys).unwrap();
+        assert_eq!(cmp.compare_with(3, &other_cmp, 3), Ordering::Equal);
+        assert_eq!(cmp.compare_with(3, &other_cmp, 1), Ordering::Greater);
+
+        let list = Schema::make_one_attr("l", false, Type::LIST(Box::new(Type::INT32)));
+        let mut lists = Block::new(&allocator::GLOBAL, &list);
+        lists.append_row(&[Value::LIST(vec![])]).unwrap();
+        match RowComparator::new(&lists, &[SortKey::new(0, SortOrder::ASC)]) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    // Normalized keys compare like the rows, for all orders and NULL placements
+    #[test]
+    fn normalized_keys_order() {
+        let block = make_block();
+
+        for &nulls in &[NullOrder::First, NullOrder::Last] {
+            for &order in &[SortOrder::ASC, SortOrder::DESC] {
+                let keys = [SortKey::new(1, order).nulls(nulls), SortKey::new(0, order),
+                            SortKey::new(2, SortOrder::ASC)];
+                let cmp = RowComparator::new(&block, &keys).unwrap();
+                let normalized = cmp.normalized_keys();
+
+                for l in 0 .. block.rows() {
+                    for r in 0 .. block.rows() {
+                        assert_eq!(normalized[l].cmp(&normalized[r]), cmp.compare(l, r),
+                                   "rows {} and {}, {:?} {:?}", l, r, order, nulls);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+// vim: set ts=4 sw=4 et :
+
+//! Fluent API for building queries.
+//!
+//! ```ignore
+//! let result = dataframe::scan(&table)
+//!     .filter(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b")))
+//!     .select(&["a"])
+//!     .limit(0, 10)
+//!     .collect(&allocator::GLOBAL)?;
+//! ```
+//!
+//! A `DataFrame` wraps a `LogicalPlan`, so nothing is evaluated until `collect()` optimizes the
+//! plan and runs the resulting operations.
+
+use ::allocator::{Allocator, MemoryTracker};
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::Expr;
+use ::operation::{CursorChunk, ExecutionContext};
+use ::plan::{LogicalPlan, Optimizer, SortOrder};
+use ::projector::{BuildSingleSourceProjector, project_by_name};
+use ::row::RowOffset;
+use ::table::Table;
+
+/// Query over one or more views
+pub struct DataFrame<'a> {
+    plan: LogicalPlan<'a>,
+}
+
+/// `DataFrame` waiting for the aggregates of its groups, see `DataFrame::group_by()`
+pub struct GroupedDataFrame<'a> {
+    plan: LogicalPlan<'a>,
+    keys: Vec<String>,
+}
+
+/// All the rows of the view
+pub fn scan<'a>(src: &'a View<'a>) -> DataFrame<'a> {
+    DataFrame { plan: LogicalPlan::scan(src) }
+}
+
+fn to_strings(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+impl<'a> DataFrame<'a> {
+    pub fn from_plan(plan: LogicalPlan<'a>) -> DataFrame<'a> {
+        DataFrame { plan: plan }
+    }
+
+    /// Rows where the BOOLEAN predicate is true
+    pub fn filter<T: Expr<'a> + 'a>(self, predicate: T) -> DataFrame<'a> {
+        DataFrame { plan: self.plan.filter(predicate) }
+    }
+
+    /// Only the named attributes, in the provided order
+    pub fn select(self, names: &[&str]) -> DataFrame<'a> {
+        let proj = names.iter()
+            .fold(BuildSingleSourceProjector::new(), |b, n| b.add(project_by_name(n)))
+            .done();
+
+        DataFrame { plan: self.plan.project(proj) }
+    }
+
+    /// Equi-join on pairs of (left, right) attribute names
+    pub fn join(self, right: DataFrame<'a>, on: &[(&str, &str)]) -> DataFrame<'a> {
+        let on = on.iter().map(|k| (k.0.to_string(), k.1.to_string())).collect();
+        DataFrame { plan: self.plan.join(right.plan, on) }
+    }
+
+    pub fn group_by(self, keys: &[&str]) -> GroupedDataFrame<'a> {
+        GroupedDataFrame { plan: self.plan, keys: to_strings(keys) }
+    }
+
+    pub fn sort(self, keys: &[(&str, SortOrder)]) -> DataFrame<'a> {
+        let keys = keys.iter().map(|k| (k.0.to_string(), k.1)).collect();
+        DataFrame { plan: self.plan.sort(keys) }
+    }
+
+    /// Skip `offset` rows and return at most `count` rows
+    pub fn limit(self, offset: RowOffset, count: RowOffset) -> DataFrame<'a> {
+        DataFrame { plan: self.plan.limit(offset, count) }
+    }
+
+    pub fn logical_plan(&self) -> &LogicalPlan<'a> {
+        &self.plan
+    }
+
+    pub fn into_plan(self) -> LogicalPlan<'a> {
+        self.plan
+    }
+
+    /// Plan tree as built (before optimization)
+    pub fn explain(&self) -> String {
+        self.plan.explain()
+    }
+
+    /// Optimize and run the query, and render the optimized plan with the metrics of each of its
+    /// operations (see `LogicalPlan::explain_analyze()`)
+    pub fn explain_analyze<'b: 'a>(self, memory: &'b MemoryTracker<'b>) -> Result<String, DBError> {
+        Optimizer::new().optimize(self.plan).explain_analyze(memory)
+    }
+
+    /// Optimize and run the query, copying all the result rows into a single `Block`. Rows are
+    /// fetched in batches sized for the result width (see `LogicalPlan::batch_rows()`).
+    pub fn collect<'b: 'a>(self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.collect_context(&ExecutionContext::new(alloc))
+    }
+
+    /// `collect()` with the query configured by the context (batch size, memory budget, ...)
+    pub fn collect_context<'b: 'a>(self, ctx: &ExecutionContext<'b>)
+        -> Result<Block<'b>, DBError>
+    {
+        let alloc = ctx.allocator;
+        let plan = Optimizer::new().optimize(self.plan);
+        let fetch = plan.batch_rows(ctx.batch_bytes)?;
+        let op = plan.lower()?;
+        let mut cursor = op.bind_context(ctx)?;
+        let mut table = Table::new(alloc, cursor.schema(), None);
+
+        loop {
+            let view = match cursor.next(fetch)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => break,
+            };
+
+            let count = view.schema().count();
+            for row in 0 .. view.rows() {
+                let out = table.add_row()?;
+
+                for pos in 0 .. count {
+                    let col = view.column(pos)
+                        .ok_or(DBError::make_column_unknown_pos(pos))?;
+                    table.set(pos, out, column_value(col, row)?)?;
+                }
+            }
+        }
+
+        Ok(table.take().unwrap())
+    }
+}
+
+impl<'a> GroupedDataFrame<'a> {
+    /// Grouping keys followed by the aggregate expressions computed for each group
+    pub fn agg(self, aggregates: Vec<Box<Expr<'a> + 'a>>) -> DataFrame<'a> {
+        DataFrame { plan: self.plan.aggregate(self.keys, aggregates) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::expression::udf::{Aggregate, FunctionRegistry};
+    use ::schema::{Attribute, Schema};
+    use ::table::TableAppender;
+    use ::types::{Type, UInt32, UInt64, Value};
+
+    fn make_block() -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: true, dtype: Type::UINT32},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 10 {
+                appender = appender.add_row().set(v as u32).set((v % 3) as u32);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    #[test]
+    fn filter_select_collect() {
+        let block = make_block();
+
+        let out = scan(&block)
+            .filter(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b")))
+            .select(&["b", "a"])
+            .limit(0, 2)
+            .collect(&allocator::GLOBAL)
+            .unwrap();
+
+        assert_eq!(out.rows(), 2);
+
+        let names: Vec<&str> = out.schema().iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a"]);
+
+        let rows = column_row_data::<UInt32>(out.column(1).unwrap()).unwrap();
+        assert_eq!(&rows.values[.. 2], &[0, 1]);
+    }
+
+    /// Number of non NULL values
+    struct Count;
+
+    impl Aggregate for Count {
+        type State = u64;
+
+        fn output(&self, _: &Type) -> Result<Type, DBError> {
+            Ok(Type::UINT64)
+        }
+
+        fn init(&self) -> u64 {
+            0
+        }
+
+        fn update(&self, state: &mut u64, _: &Value) -> Result<(), DBError> {
+            *state += 1;
+            Ok(())
+        }
+
+        fn merge(&self, state: &mut u64, other: u64) -> Result<(), DBError> {
+            *state += other;
+            Ok(())
+        }
+
+        fn finalize<'s>(&self, state: &'s u64) -> Result<Value<'s>, DBError> {
+            Ok(Value::UINT64(*state))
+        }
+    }
+
+    // A row per group, the grouping keys followed by the aggregates
+    #[test]
+    fn group_by_agg() {
+        let block = make_block();
+        let mut registry = FunctionRegistry::new();
+        registry.register_aggregate("count", Count);
+        let count = registry.call_aggregate("count", ColumnExpr::named("a")).unwrap();
+
+        let df = scan(&block).group_by(&["b"]).agg(vec![Box::new(count)])
+            .sort(&[("b", SortOrder::DESC)]);
+        assert_eq!(df.explain(), "Sort by b DESC\n  Aggregate by b\n    Scan");
+
+        let out = df.collect(&allocator::GLOBAL).unwrap();
+        let names: Vec<&str> = out.schema().iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "count(a)"]);
+
+        // b is v % 3 of v in 0 .. 10
+        let keys = column_row_data::<UInt32>(out.column(0).unwrap()).unwrap();
+        assert_eq!(&keys.values[.. out.rows()], &[2, 1, 0]);
+        let counts = column_row_data::<UInt64>(out.column(1).unwrap()).unwrap();
+        assert_eq!(&counts.values[.. out.rows()], &[3, 3, 4]);
+    }
+
+    // Each left row with the matching right rows, sorted
+    #[test]
+    fn join_sort() {
+        let block = make_block();
+        let mut table = Table::new(&allocator::GLOBAL,
+                                   &Schema::make_one_attr("k", false, Type::UINT32), None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(2u32)
+                .add_row().set(0u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        let keys = table.take().unwrap();
+
+        let out = scan(&block)
+            .join(scan(&keys), &[("b", "k")])
+            .sort(&[("a", SortOrder::DESC)])
+            .select(&["a", "k"])
+            .limit(1, 4)
+            .collect(&allocator::GLOBAL)
+            .unwrap();
+
+        let a = column_row_data::<UInt32>(out.column(0).unwrap()).unwrap();
+        assert_eq!(&a.values[.. out.rows()], &[8, 6, 5, 3]);
+        let k = column_row_data::<UInt32>(out.column(1).unwrap()).unwrap();
+        assert_eq!(&k.values[.. out.rows()], &[2, 0, 2, 0]);
+    }
+}
@@ -0,0 +1,251 @@
+//! CBOR row decoding.
+//!
+//! A message is a single CBOR map (attributes by name) or array (attributes by position). Tags are
+//! skipped (the tagged item is decoded), indefinite length strings are not supported.
+
+use ::error::DBError;
+use ::schema::{Attribute, Schema};
+use ::types::Value;
+
+use super::{DecodeLimits, Input, Item, RowDecoder, MAX_DEPTH, record_values};
+
+/// Marker ending indefinite length arrays and maps
+const BREAK: u8 = 0xff;
+
+/// Decoder of CBOR messages into rows of a declared schema
+pub struct CborDecoder {
+    schema: Schema,
+    attrs: Vec<Attribute>,
+    limits: DecodeLimits,
+}
+
+impl CborDecoder {
+    pub fn new(schema: Schema) -> CborDecoder {
+        let attrs = schema.iter().cloned().collect();
+        CborDecoder { schema: schema, attrs: attrs, limits: DecodeLimits::default() }
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> CborDecoder {
+        self.limits = limits;
+        self
+    }
+}
+
+/// IEEE 754 half precision float
+fn half_to_f64(bits: u16) -> f64 {
+    let exp = ((bits >> 10) & 0x1f) as i32;
+    let mant = (bits & 0x3ff) as f64;
+
+    let v = match exp {
+        0   => mant * 2f64.powi(-24),
+        31  => if mant == 0.0 { ::std::f64::INFINITY } else { ::std::f64::NAN },
+        _   => (mant + 1024.0) * 2f64.powi(exp - 25),
+    };
+
+    if bits & 0x8000 != 0 { -v } else { v }
+}
+
+/// Argument of an item, `info` being the low 5 bits of its initial byte
+fn argument(input: &mut Input, info: u8) -> Result<u64, DBError> {
+    match info {
+        0 ..= 23    => Ok(info as u64),
+        24          => input.uint(1),
+        25          => input.uint(2),
+        26          => input.uint(4),
+        27          => input.uint(8),
+        31          => Err(DBError::Unsupported(String::from("CBOR indefinite length item"))),
+        _           => Err(DBError::ValueParse(format!("invalid CBOR argument {}", info))),
+    }
+}
+
+/// True (and consumed) if the next byte is a break
+fn at_break(input: &mut Input) -> Result<bool, DBError> {
+    match input.data.get(input.pos) {
+        Some(&BREAK) => { input.pos += 1; Ok(true) },
+        Some(_) => Ok(false),
+        None => Err(DBError::ValueParse(String::from("truncated message"))),
+    }
+}
+
+fn decode_items<'m>(input: &mut Input<'m>, info: u8, depth: usize)
+    -> Result<Vec<Item<'m>>, DBError>
+{
+    let mut out = Vec::new();
+
+    if info == 31 {
+        while !at_break(input)? {
+            out.push(decode_item(input, depth + 1)?);
+        }
+    } else {
+        let count = argument(input, info)? as usize;
+        // Every item takes at least a byte, don't trust the count for the allocation
+        out.reserve(count.min(input.data.len()));
+        for _ in 0 .. count {
+            out.push(decode_item(input, depth + 1)?);
+        }
+    }
+
+    Ok(out)
+}
+
+fn decode_map<'m>(input: &mut Input<'m>, info: u8, depth: usize) -> Result<Item<'m>, DBError> {
+    let mut out = Vec::new();
+
+    if info == 31 {
+        while !at_break(input)? {
+            let key = decode_item(input, depth + 1)?;
+            let value = decode_item(input, depth + 1)?;
+            out.push((key, value));
+        }
+    } else {
+        let count = argument(input, info)? as usize;
+        out.reserve(count.min(input.data.len()));
+        for _ in 0 .. count {
+            let key = decode_item(input, depth + 1)?;
+            let value = decode_item(input, depth + 1)?;
+            out.push((key, value));
+        }
+    }
+
+    Ok(Item::Map(out))
+}
+
+/// Decode the next item of the input
+fn decode_item<'m>(input: &mut Input<'m>, depth: usize) -> Result<Item<'m>, DBError> {
+    if depth > MAX_DEPTH {
+        return Err(DBError::ValueParse(String::from("CBOR item nested too deep")))
+    }
+
+    let initial = input.byte()?;
+    let info = initial & 0x1f;
+
+    Ok(match initial >> 5 {
+        0 => Item::UInt(argument(input, info)?),
+        1 => {
+            let n = argument(input, info)?;
+            if n > ::std::i64::MAX as u64 {
+                return Err(DBError::ValueOutOfRange(format!("CBOR integer -1 - {}", n)))
+            }
+            Item::Int(-1 - n as i64)
+        },
+        2 => {
+            let len = argument(input, info)? as usize;
+            Item::Bin(input.take(len)?)
+        },
+        3 => {
+            let len = argument(input, info)? as usize;
+            Item::Str(input.text(len)?)
+        },
+        4 => Item::Array(decode_items(input, info, depth)?),
+        5 => decode_map(input, info, depth)?,
+        6 => {
+            argument(input, info)?;
+            decode_item(input, depth + 1)?
+        },
+        _ => match info {
+            20      => Item::Bool(false),
+            21      => Item::Bool(true),
+            22 | 23 => Item::Nil,
+            25      => Item::Float(half_to_f64(input.uint(2)? as u16)),
+            26      => Item::Float(f32::from_bits(input.uint(4)? as u32) as f64),
+            27      => Item::Float(f64::from_bits(input.uint(8)?)),
+            _ => return Err(DBError::ValueParse(
+                format!("invalid CBOR simple value {:#x}", initial))),
+        },
+    })
+}
+
+impl RowDecoder for CborDecoder {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    fn decode_values<'m>(&self, message: &'m [u8]) -> Result<Vec<Value<'m>>, DBError> {
+        let mut input = Input::new(message);
+        let item = decode_item(&mut input, 0)?;
+
+        if !input.done() {
+            return Err(DBError::ValueParse(String::from("trailing bytes after CBOR row")))
+        }
+
+        record_values(&self.attrs, item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_value};
+    use ::types::Type;
+
+    // Definite and indefinite maps and arrays, tags, half floats and negative integers
+    #[test]
+    fn decode_rows() {
+        let point = vec![
+            Attribute{name: "x".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "y".to_string(), nullable: false, dtype: Type::FLOAT64},
+        ];
+
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "tags".to_string(), nullable: true,
+                      dtype: Type::LIST(Box::new(Type::INT32))},
+            Attribute{name: "at".to_string(), nullable: true, dtype: Type::STRUCT(point)},
+        ];
+
+        let decoder = CborDecoder::new(Schema::from_vec(attrs).unwrap());
+
+        // {"id": 1000, "name": "ab", "tags": [_ -1, 2], "at": [-500, 1.5 (half)]}
+        let map: &[u8] = &[
+            0xa4,
+            0x62, b'i', b'd', 0x19, 0x03, 0xe8,
+            0x64, b'n', b'a', b'm', b'e', 0x62, b'a', b'b',
+            0x64, b't', b'a', b'g', b's', 0x9f, 0x20, 0x02, 0xff,
+            0x62, b'a', b't', 0x82, 0x39, 0x01, 0xf3, 0xf9, 0x3e, 0x00,
+        ];
+        // {_ "id": 1(7), "name": null}
+        let indefinite: &[u8] = &[
+            0xbf,
+            0x62, b'i', b'd', 0xc1, 0x07,
+            0x64, b'n', b'a', b'm', b'e', 0xf6,
+            0xff,
+        ];
+
+        let block = decoder.decode(&allocator::GLOBAL, vec![map, indefinite]).unwrap();
+        assert_eq!(block.rows(), 2);
+
+        let value = |col, row| column_value(block.column(col).unwrap(), row).unwrap();
+        assert!(value(0, 0) == Value::UINT32(1000));
+        assert!(value(1, 0) == Value::TEXT("ab"));
+        assert!(value(2, 0) == Value::LIST(vec![Value::INT32(-1), Value::INT32(2)]));
+        assert!(value(3, 0) == Value::STRUCT(vec![Value::INT64(-500), Value::FLOAT64(1.5)]));
+        assert!(value(0, 1) == Value::UINT32(7));
+        assert!(value(1, 1) == Value::NULL);
+        assert!(value(2, 1) == Value::NULL);
+        assert!(value(3, 1) == Value::NULL);
+
+        // Truncated array
+        match decoder.decode_values(&[0x84, 0x01]) {
+            Err(DBError::ValueParse(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        // The largest UINT32 id fits, the next one doesn't
+        match decoder.decode_values(&[0x84, 0x1a, 0xff, 0xff, 0xff, 0xff, 0xf6, 0xf6, 0xf6]) {
+            Ok(_) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+        }
+        match decoder.decode_values(&[0x84, 0x1b, 0, 0, 0, 1, 0, 0, 0, 0, 0xf6, 0xf6, 0xf6]) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! Decoder of CSV text (RFC 4180) into blocks.
+//!
+//! Fields are separated by the delimiter and records by "\n" or "\r\n". Quoted fields can
+//! contain delimiters, line breaks and quotes (doubled, `""`). An empty unquoted field is NULL,
+//! an empty quoted field (`""`) is an empty TEXT value.
+
+use ::allocator::Allocator;
+use ::block::Block;
+use ::error::DBError;
+use ::intern::DEFAULT_INTERN_ENTRIES;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Type, Value};
+use ::util::uuid::parse_uuid;
+
+use super::{DecodeLimits, Item, item_value};
+
+/// Reader of CSV text, with a header record (of the attribute names) by default
+#[derive(Clone, Debug)]
+pub struct CsvDecoder {
+    /// ASCII byte between fields, not a quote or line break (see `with_delimiter()`)
+    pub delimiter: u8,
+    pub header: bool,
+    pub limits: DecodeLimits,
+    /// TEXT attributes whose values are interned (see `Block::intern_columns()`)
+    pub intern: Vec<String>,
+}
+
+/// Fields of the records, `None` for NULL
+type Records = Vec<Vec<Option<String>>>;
+
+/// Line (starting at 1) and byte offset where each record starts
+type Positions = Vec<(usize, usize)>;
+
+fn parse_error(msg: &str, line: usize, byte: usize) -> DBError {
+    DBError::ValueParse(format!("CSV {}", msg)).at_position(line, byte)
+}
+
+/// Line breaks ("\n", "\r\n" or "\r") in the text
+fn line_breaks(bytes: &[u8]) -> usize {
+    bytes.iter().enumerate()
+        .filter(|&(i, &c)| c == b'\n' || (c == b'\r' && bytes.get(i + 1) != Some(&b'\n')))
+        .count()
+}
+
+/// Error of a field that's not UTF-8 (split on a non ASCII delimiter), with its row and attribute
+/// (named like `CsvDecoder::infer_schema()` does)
+fn utf8_error(records: &Records, header: bool, field: usize, line: usize, byte: usize) -> DBError {
+    let err = parse_error("field is not UTF-8", line, byte);
+    match (header, records.split_first()) {
+        (true, Some((names, rows))) => {
+            let name = names.get(field).map(|n| n.clone().unwrap_or_default())
+                .unwrap_or_else(|| format!("c{}", field));
+            err.in_column(name).at_row(rows.len())
+        },
+        (true, None) => err,
+        (false, _) => err.in_column(format!("c{}", field)).at_row(records.len()),
+    }
+}
+
+/// Split the text into records of fields, the first one is the header if `header`
+fn records(text: &str, delimiter: u8, header: bool, limits: &DecodeLimits)
+    -> Result<(Records, Positions), DBError>
+{
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut positions = Vec::new();
+    let mut record = Vec::new();
+    let mut pos = 0;
+    let (mut line, mut start) = (1, 0);
+
+    while pos < bytes.len() {
+        let mut field = Vec::new();
+        let quoted = bytes[pos] == b'"';
+
+        if quoted {
+            pos += 1;
+            loop {
+                match bytes.get(pos) {
+                    None => return Err(parse_error("unterminated quoted field", line, start)),
+                    Some(&b'"') if bytes.get(pos + 1) == Some(&b'"') => {
+                        field.push(b'"');
+                        pos += 2;
+                    },
+                    Some(&b'"') => {
+                        pos += 1;
+                        break
+                    },
+                    Some(&c) => {
+                        field.push(c);
+                        pos += 1;
+                    },
+                }
+            }
+        } else {
+            while pos < bytes.len() && bytes[pos] != delimiter && bytes[pos] != b'\n' &&
+                bytes[pos] != b'\r' {
+                field.push(bytes[pos]);
+                pos += 1;
+            }
+        }
+
+        let field = String::from_utf8(field)
+            .map_err(|_| utf8_error(&out, header, record.len(), line, start))?;
+        record.push(if field.is_empty() && !quoted { None } else { Some(field) });
+
+        match bytes.get(pos) {
+            Some(&c) if c == delimiter => {
+                pos += 1;
+                if pos == bytes.len() {
+                    record.push(None);
+                }
+            },
+            Some(&b'\r') if bytes.get(pos + 1) == Some(&b'\n') => pos += 2,
+            Some(&b'\n') | Some(&b'\r') => pos += 1,
+            None => (),
+            Some(_) => return Err(parse_error("text after a quoted field", line, start)),
+        }
+
+        let end = pos == bytes.len() || bytes[pos - 1] == b'\n' || bytes[pos - 1] == b'\r';
+        if end {
+            limits.check_rows(out.len() + 1)?;
+            out.push(record);
+            positions.push((line, start));
+            record = Vec::new();
+            line += line_breaks(&bytes[start .. pos]);
+            start = pos;
+        }
+    }
+
+    Ok((out, positions))
+}
+
+/// "true" or "false", in any case
+fn parse_bool(text: &str) -> Option<bool> {
+    if text.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if text.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Value of a field of the attribute
+fn field_value<'f>(attr: &Attribute, field: &'f Option<String>) -> Result<Value<'f>, DBError> {
+    let text = match *field {
+        Some(ref text) => text.as_str(),
+        None => return item_value(attr, Item::Nil),
+    };
+    let invalid = || DBError::ValueParse(format!("{} value '{}'", attr.name, text));
+
+    let item = match attr.dtype {
+        Type::UINT32 | Type::UINT64 => Item::UInt(text.parse().map_err(|_| invalid())?),
+        Type::INT32 | Type::INT64 | Type::TIMESTAMP =>
+            Item::Int(text.parse().map_err(|_| invalid())?),
+        Type::FLOAT32 | Type::FLOAT64 => Item::Float(text.parse().map_err(|_| invalid())?),
+        Type::BOOLEAN => match parse_bool(text) {
+            Some(v) => Item::Bool(v),
+            None    => return Err(invalid()),
+        },
+        Type::UUID => return parse_uuid(text).map(Value::UUID),
+        _ => Item::Str(text),
+    };
+
+    item_value(attr, item)
+}
+
+/// Narrowest type of the fields: BOOLEAN, INT64, FLOAT64 or TEXT
+fn infer_type<'r, I: Iterator<Item = &'r str>>(fields: I) -> Type {
+    let types = [Type::BOOLEAN, Type::INT64, Type::FLOAT64];
+    let mut candidates = &types[..];
+
+    for text in fields {
+        let fits = |t: &Type| match *t {
+            Type::BOOLEAN => parse_bool(text).is_some(),
+            Type::INT64 => text.parse::<i64>().is_ok(),
+            _ => text.parse::<f64>().is_ok(),
+        };
+        let skip = candidates.iter().take_while(|t| !fits(t)).count();
+        if skip == candidates.len() {
+            return Type::TEXT
+        }
+        candidates = &candidates[skip ..];
+    }
+
+    candidates.first().cloned().unwrap_or(Type::TEXT)
+}
+
+impl Default for CsvDecoder {
+    fn default() -> CsvDecoder {
+        CsvDecoder::new()
+    }
+}
+
+impl CsvDecoder {
+    pub fn new() -> CsvDecoder {
+        CsvDecoder {
+            delimiter: b',',
+            header: true,
+            limits: DecodeLimits::default(),
+            intern: Vec::new(),
+        }
+    }
+
+    /// Fields separated by the delimiter, it has to be ASCII and not a quote or line break
+    pub fn with_delimiter(mut self, delimiter: u8) -> Result<CsvDecoder, DBError> {
+        if !delimiter.is_ascii() || delimiter == b'"' || delimiter == b'\r' || delimiter == b'\n' {
+            return Err(DBError::Unsupported(format!("CSV delimiter {:?}", delimiter as char)))
+        }
+
+        self.delimiter = delimiter;
+        Ok(self)
+    }
+
+    /// The first record is data, not attribute names
+    pub fn without_header(mut self) -> CsvDecoder {
+        self.header = false;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> CsvDecoder {
+        self.limits = limits;
+        self
+    }
+
+    /// Intern the values of the TEXT attributes, for columns with few distinct values
+    pub fn with_interning(mut self, names: &[&str]) -> CsvDecoder {
+        self.intern = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Schema of the text: attribute names from the header ("c0", "c1", ... without one) and the
+    /// narrowest type of each column's values (see `infer_type`), nullable if it has NULLs
+    pub fn infer_schema(&self, text: &str) -> Result<Schema, DBError> {
+        let (records, _) = records(text, self.delimiter, self.header, &self.limits)?;
+        let (names, rows) = match (self.header, records.split_first()) {
+            (true, Some((header, rows))) => {
+                let names = header.iter().map(|n| n.clone().unwrap_or_default()).collect();
+                (names, rows)
+            },
+            (true, None) =>
+                return Err(DBError::ValueParse("CSV text without header".to_string())),
+            (false, _) => {
+                let count = records.first().map_or(0, |r| r.len());
+                ((0 .. count).map(|c| format!("c{}", c)).collect::<Vec<_>>(), &records[..])
+            },
+        };
+
+        let mut attrs = Vec::with_capacity(names.len());
+        for (pos, name) in names.into_iter().enumerate() {
+            let fields = rows.iter().filter_map(|r| r.get(pos).and_then(|f| f.as_ref()));
+            attrs.push(Attribute {
+                name: name,
+                nullable: rows.iter().any(|r| r.get(pos).and_then(|f| f.as_ref()).is_none()),
+                dtype: infer_type(fields.map(|f| f.as_str())),
+            });
+        }
+
+        Schema::from_vec(attrs)
+    }
+
+    /// Decode the records of the text into a block of the schema, fields in schema order.
+    /// Errors have the position of the failing record as context, and its row and attribute
+    /// when a field fails.
+    pub fn decode<'b>(&self, alloc: &'b Allocator, schema: &Schema, text: &str)
+        -> Result<Block<'b>, DBError>
+    {
+        let (records, positions) = records(text, self.delimiter, self.header, &self.limits)?;
+        let skip = if self.header { 1 } else { 0 };
+        let mut table = Table::new(alloc, schema, Some(records.len().saturating_sub(skip)));
+        let intern: Vec<&str> = self.intern.iter().map(|n| n.as_str()).collect();
+        table.intern_columns(&intern, DEFAULT_INTERN_ENTRIES)?;
+
+        for (record, &(line, byte)) in records.iter().zip(&positions).skip(skip) {
+            if record.len() != schema.count() {
+                let msg = format!("{} fields, expected {}", record.len(), schema.count());
+                return Err(parse_error(&msg, line, byte))
+            }
+
+            let row = table.add_row()?;
+            for (pos, (attr, field)) in schema.iter().zip(record).enumerate() {
+                let set = field_value(attr, field)
+                    .and_then(|value| {
+                        self.limits.check_value(attr, &value)?;
+                        table.set(pos, row, value)
+                    });
+                if let Err(e) = set {
+                    return Err(e.in_column(attr.name.as_str()).at_row(row).at_position(line, byte))
+                }
+            }
+        }
+
+        match table.take() {
+            Some(block) => Ok(block),
+            None => Ok(Block::new(alloc, schema)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::View;
+    use ::error::ErrorContext;
+
+    // Quoted fields keep delimiters, line breaks and quotes, empty fields are NULL
+    #[test]
+    fn decode_csv() {
+        let text = "id,name,score\r\n1,\"a, \"\"b\"\"\",1.5\n2,,2\n3,\"multi\nline\",\n";
+        let csv = CsvDecoder::new();
+
+        let schema = csv.infer_schema(text).unwrap();
+        let names: Vec<(&str, bool)> =
+            schema.iter().map(|a| (a.name.as_str(), a.nullable)).collect();
+        assert_eq!(names, vec![("id", false), ("name", true), ("score", true)]);
+        let types: Vec<Type> = schema.iter().map(|a| a.dtype.clone()).collect();
+        assert!(types == vec![Type::INT64, Type::TEXT, Type::FLOAT64]);
+
+        let block = csv.decode(&allocator::GLOBAL, &schema, text).unwrap();
+        assert_eq!(block.rows(), 3);
+        assert!(block.value(0, 1).unwrap() == Value::TEXT("a, \"b\""));
+        assert!(block.value(1, 1).unwrap() == Value::NULL);
+        assert!(block.value(2, 1).unwrap() == Value::TEXT("multi\nline"));
+        assert!(block.value(1, 2).unwrap() == Value::FLOAT64(2.0));
+        assert!(block.value(2, 2).unwrap() == Value::NULL);
+
+        let interned = csv.clone().with_interning(&["name"]);
+        let block = interned.decode(&allocator::GLOBAL, &schema, text).unwrap();
+        assert_eq!(block.intern_stats()[0].1.misses, 2);
+
+        let csv = CsvDecoder::new().with_delimiter(b';').unwrap().without_header();
+        let schema = csv.infer_schema("true;x\nFALSE;\"\"").unwrap();
+        assert_eq!(schema.get(0).unwrap().name, "c0");
+        assert!(schema.get(0).unwrap().dtype == Type::BOOLEAN);
+        let block = csv.decode(&allocator::GLOBAL, &schema, "true;x\nFALSE;\"\"").unwrap();
+        assert!(block.value(1, 1).unwrap() == Value::TEXT(""));
+
+        for text in &["id\n1,2\n", "id\n\"1\n", "id\nx\n"] {
+            let schema = Schema::make_one_attr("id", false, Type::INT64);
+            let decoded = CsvDecoder::new().decode(&allocator::GLOBAL, &schema, text);
+            match decoded.as_ref().map_err(DBError::root) {
+                Err(&DBError::ValueParse(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error"),
+            };
+        }
+
+        // Failing field has its row, attribute and position (after a multi-line record)
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "a".to_string(), nullable: true, dtype: Type::TEXT },
+            Attribute { name: "b".to_string(), nullable: false, dtype: Type::INT64 },
+        ]).unwrap();
+        let text = "a,b\n\"x\ny\",1\nz,2\nw,oops\n";
+        let err = CsvDecoder::new().decode(&allocator::GLOBAL, &schema, text).err().unwrap();
+        assert!(err.context() == Some(&ErrorContext {
+            column: Some("b".to_string()),
+            row: Some(2),
+            line: Some(5),
+            byte: Some(16),
+            ..Default::default()
+        }));
+    }
+
+    // Delimiters have to be ASCII, fields split on other bytes fail instead of panicking
+    #[test]
+    fn csv_delimiter() {
+        for &delimiter in &[0xc3, b'"', b'\r', b'\n'] {
+            match CsvDecoder::new().with_delimiter(delimiter) {
+                Err(DBError::Unsupported(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error for {}", delimiter),
+            };
+        }
+
+        // "é" is 0xc3 0xa9, the second field starts with a continuation byte
+        let csv = CsvDecoder { delimiter: 0xc3, header: false, ..CsvDecoder::new() };
+        let err = csv.infer_schema("ok\nxé\n").err().unwrap();
+        match *err.root() {
+            DBError::ValueParse(_) => (), // nop
+            ref e => assert!(false, "Unexpected error {}", e),
+        };
+        assert!(err.context() == Some(&ErrorContext {
+            column: Some("c1".to_string()),
+            row: Some(1),
+            line: Some(2),
+            byte: Some(3),
+            ..Default::default()
+        }));
+    }
+}
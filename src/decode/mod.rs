@@ -0,0 +1,351 @@
+//! Decoders of external row formats (eg. event stream messages) into blocks.
+//!
+//! Each message (record) is decoded into a row. `protobuf` messages are described by a runtime
+//! descriptor, self describing formats (`msgpack`, `cbor`) are decoded into `Item`s that are
+//! mapped onto a declared schema: a map is matched to the attributes by name (and STRUCT fields
+//! alike), an array by position. Formats without type information (`csv`, `pgcopy`) are decoded
+//! into a declared schema too, `pgcopy` also writes rows (for exporting into PostgreSQL).
+//!
+//! Decoders of untrusted input should be given `DecodeLimits`, so oversized messages, values or
+//! message batches fail with `DBError::InputLimit` instead of being loaded.
+
+use std::{i32, i64, u32};
+
+use ::allocator::Allocator;
+use ::block::Block;
+use ::error::DBError;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Type, Value};
+
+pub mod cbor;
+pub mod csv;
+pub mod msgpack;
+pub mod pgcopy;
+pub mod protobuf;
+
+pub use self::cbor::CborDecoder;
+pub use self::csv::CsvDecoder;
+pub use self::msgpack::MsgPackDecoder;
+pub use self::pgcopy::{PgCopyDecoder, PgCopyWriter};
+pub use self::protobuf::ProtobufDecoder;
+
+/// Size limits of the decoded input, `None` is unlimited
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DecodeLimits {
+    /// Largest message (row) in bytes
+    pub max_message_len: Option<usize>,
+    /// Largest TEXT or BLOB value in bytes, including LIST elements and STRUCT fields
+    pub max_field_len: Option<usize>,
+    /// Most messages decoded into a block
+    pub max_rows: Option<usize>,
+}
+
+static NO_LIMITS: DecodeLimits = DecodeLimits {
+    max_message_len: None,
+    max_field_len: None,
+    max_rows: None,
+};
+
+impl DecodeLimits {
+    pub fn check_message(&self, message: &[u8]) -> Result<(), DBError> {
+        self.check_message_len(message.len())
+    }
+
+    /// Check the length of a message, before reading it
+    pub fn check_message_len(&self, len: usize) -> Result<(), DBError> {
+        match self.max_message_len {
+            Some(max) if len > max => Err(DBError::InputLimit(
+                format!("message of {} bytes, the limit is {}", len, max))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the TEXT and BLOB values of the attribute
+    pub fn check_value(&self, attr: &Attribute, value: &Value) -> Result<(), DBError> {
+        let max = match self.max_field_len {
+            Some(max)   => max,
+            None        => return Ok(()),
+        };
+
+        let len = match *value {
+            Value::TEXT(v)  => v.len(),
+            Value::BLOB(v)  => v.len(),
+            Value::LIST(ref values) | Value::STRUCT(ref values) => {
+                for value in values {
+                    self.check_value(attr, value)?;
+                }
+                return Ok(())
+            },
+            _ => return Ok(()),
+        };
+
+        if len > max {
+            return Err(DBError::InputLimit(
+                format!("{} value of {} bytes, the limit is {}", attr.name, len, max)))
+        }
+
+        Ok(())
+    }
+
+    pub fn check_rows(&self, rows: usize) -> Result<(), DBError> {
+        match self.max_rows {
+            Some(max) if rows > max => Err(DBError::InputLimit(
+                format!("more than {} messages", max))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Decoder of self contained messages into rows of its schema
+pub trait RowDecoder {
+    fn schema(&self) -> &Schema;
+
+    /// Limits checked by `decode_into` and `decode`
+    fn limits(&self) -> &DecodeLimits {
+        &NO_LIMITS
+    }
+
+    /// Decode a message into its row values, in schema order
+    fn decode_values<'m>(&self, message: &'m [u8]) -> Result<Vec<Value<'m>>, DBError>;
+
+    /// Decode a message into a new row of the `table`, which has to have the decoder's schema
+    fn decode_into(&self, message: &[u8], table: &mut Table) -> Result<(), DBError> {
+        let limits = self.limits();
+        limits.check_message(message)?;
+
+        let values = self.decode_values(message)?;
+        for (attr, value) in self.schema().iter().zip(&values) {
+            limits.check_value(attr, value)?;
+        }
+
+        let row = table.add_row()?;
+
+        for (pos, value) in values.into_iter().enumerate() {
+            table.set(pos, row, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decode the `messages` into a block, a row per message
+    fn decode<'b, I>(&self, alloc: &'b Allocator, messages: I) -> Result<Block<'b>, DBError>
+        where I: IntoIterator, I::Item: AsRef<[u8]>, Self: Sized
+    {
+        let mut table = Table::new(alloc, self.schema(), None);
+
+        for (count, message) in messages.into_iter().enumerate() {
+            self.limits().check_rows(count + 1)?;
+            self.decode_into(message.as_ref(), &mut table)?;
+        }
+
+        Ok(table.take().unwrap())
+    }
+}
+
+/// Value of a self describing format, before it's mapped onto an attribute type
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item<'m> {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(&'m str),
+    Bin(&'m [u8]),
+    Array(Vec<Item<'m>>),
+    /// Key, value pairs in message order
+    Map(Vec<(Item<'m>, Item<'m>)>),
+}
+
+/// Maximum nesting of arrays and maps, so malformed messages can't exhaust the stack
+pub const MAX_DEPTH: usize = 64;
+
+/// Position in a message's bytes
+struct Input<'m> {
+    data: &'m [u8],
+    pos: usize,
+}
+
+impl<'m> Input<'m> {
+    fn new(data: &'m [u8]) -> Input<'m> {
+        Input { data: data, pos: 0 }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'m [u8], DBError> {
+        if len > self.data.len() - self.pos {
+            return Err(DBError::ValueParse(String::from("truncated message")))
+        }
+
+        let data: &'m [u8] = self.data;
+        let out = &data[self.pos .. self.pos + len];
+        self.pos += len;
+        Ok(out)
+    }
+
+    fn byte(&mut self) -> Result<u8, DBError> {
+        self.take(1).map(|b| b[0])
+    }
+
+    /// Big endian unsigned integer of `len` (up to 8) bytes
+    fn uint(&mut self, len: usize) -> Result<u64, DBError> {
+        Ok(self.take(len)?.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn text(&mut self, len: usize) -> Result<&'m str, DBError> {
+        let data = self.take(len)?;
+        ::std::str::from_utf8(data)
+            .map_err(|_| DBError::ValueParse(String::from("string is not UTF-8")))
+    }
+}
+
+fn out_of_range(attr: &Attribute, item: &Item) -> DBError {
+    DBError::ValueOutOfRange(format!("{:?} for {} {}", item, attr.dtype, attr.name))
+}
+
+/// Signed value of an integer item
+fn item_i64(attr: &Attribute, item: &Item) -> Result<i64, DBError> {
+    match *item {
+        Item::Int(v)                            => Ok(v),
+        Item::UInt(v) if v <= i64::MAX as u64   => Ok(v as i64),
+        Item::UInt(_)                           => Err(out_of_range(attr, item)),
+        _ => Err(DBError::AttributeType(attr.name.clone())),
+    }
+}
+
+/// Unsigned value of an integer item
+fn item_u64(attr: &Attribute, item: &Item) -> Result<u64, DBError> {
+    match *item {
+        Item::UInt(v)           => Ok(v),
+        Item::Int(v) if v >= 0  => Ok(v as u64),
+        Item::Int(_)            => Err(out_of_range(attr, item)),
+        _ => Err(DBError::AttributeType(attr.name.clone())),
+    }
+}
+
+/// Value of the item as the attribute type. Integers have to fit the type, NULL (nil) is only
+/// allowed for nullable attributes.
+pub fn item_value<'m>(attr: &Attribute, item: Item<'m>) -> Result<Value<'m>, DBError> {
+    let mismatch = || DBError::AttributeType(attr.name.clone());
+
+    if item == Item::Nil {
+        if !attr.nullable {
+            return Err(DBError::make_column_not_nullable(attr.name.clone()))
+        }
+        return Ok(Value::NULL)
+    }
+
+    Ok(match attr.dtype {
+        Type::UINT32 => {
+            let v = item_u64(attr, &item)?;
+            if v > u32::MAX as u64 {
+                return Err(out_of_range(attr, &item))
+            }
+            Value::UINT32(v as u32)
+        },
+        Type::UINT64 =>
+            Value::UINT64(item_u64(attr, &item)?),
+        Type::INT32 => {
+            let v = item_i64(attr, &item)?;
+            if v < i32::MIN as i64 || v > i32::MAX as i64 {
+                return Err(out_of_range(attr, &item))
+            }
+            Value::INT32(v as i32)
+        },
+        Type::INT64 =>
+            Value::INT64(item_i64(attr, &item)?),
+        Type::TIMESTAMP =>
+            Value::TIMESTAMP(item_i64(attr, &item)?),
+        Type::FLOAT32 | Type::FLOAT64 => {
+            let v = match item {
+                Item::Float(v)  => v,
+                Item::Int(v)    => v as f64,
+                Item::UInt(v)   => v as f64,
+                _               => return Err(mismatch()),
+            };
+            if attr.dtype == Type::FLOAT32 { Value::FLOAT32(v as f32) } else { Value::FLOAT64(v) }
+        },
+        Type::BOOLEAN => match item {
+            Item::Bool(v)   => Value::BOOLEAN(v),
+            _               => return Err(mismatch()),
+        },
+        Type::TEXT => match item {
+            Item::Str(v)    => Value::TEXT(v),
+            _               => return Err(mismatch()),
+        },
+        Type::BLOB => match item {
+            Item::Bin(v)    => Value::BLOB(v),
+            Item::Str(v)    => Value::BLOB(v.as_bytes()),
+            _               => return Err(mismatch()),
+        },
+        Type::UUID => match item {
+            Item::Bin(v) if v.len() == 16 => {
+                let mut uuid = [0u8; 16];
+                uuid.copy_from_slice(v);
+                Value::UUID(uuid)
+            },
+            _ => return Err(mismatch()),
+        },
+        Type::LIST(ref elem) => match item {
+            Item::Array(items) => {
+                let elem = Attribute {
+                    name: attr.name.clone(),
+                    nullable: true,
+                    dtype: (**elem).clone(),
+                };
+                let mut out = Vec::with_capacity(items.len());
+                for item in items {
+                    out.push(item_value(&elem, item)?);
+                }
+                Value::LIST(out)
+            },
+            _ => return Err(mismatch()),
+        },
+        Type::STRUCT(ref fields) =>
+            Value::STRUCT(record_values(fields, item)?),
+        Type::INTERVAL =>
+            return Err(DBError::Unsupported(format!("decoding INTERVAL attribute {}", attr.name))),
+    })
+}
+
+/// Values of the attributes of a record, from a map (by attribute name, missing attributes are
+/// NULL) or an array (by position)
+pub fn record_values<'m>(attrs: &[Attribute], item: Item<'m>) -> Result<Vec<Value<'m>>, DBError> {
+    match item {
+        Item::Map(entries) => {
+            let mut found: Vec<Option<Item<'m>>> = attrs.iter().map(|_| None).collect();
+
+            // Keys that aren't attributes are skipped
+            for (key, value) in entries {
+                if let Item::Str(name) = key {
+                    if let Some(pos) = attrs.iter().position(|a| a.name == name) {
+                        found[pos] = Some(value);
+                    }
+                }
+            }
+
+            let mut out = Vec::with_capacity(attrs.len());
+            for (attr, item) in attrs.iter().zip(found) {
+                out.push(item_value(attr, item.unwrap_or(Item::Nil))?);
+            }
+            Ok(out)
+        },
+        Item::Array(items) => {
+            if items.len() != attrs.len() {
+                return Err(DBError::ExpressionInputCount(
+                    format!("record of {} values for {} attributes", items.len(), attrs.len())))
+            }
+
+            let mut out = Vec::with_capacity(attrs.len());
+            for (attr, item) in attrs.iter().zip(items) {
+                out.push(item_value(attr, item)?);
+            }
+            Ok(out)
+        },
+        _ => Err(DBError::ValueParse(format!("record is not a map or array: {:?}", item))),
+    }
+}
@@ -0,0 +1,227 @@
+//! MessagePack row decoding.
+//!
+//! A message is a single MessagePack map (attributes by name) or array (attributes by position).
+//! Extension types are not supported.
+
+use ::error::DBError;
+use ::schema::{Attribute, Schema};
+use ::types::Value;
+
+use super::{DecodeLimits, Input, Item, RowDecoder, MAX_DEPTH, record_values};
+
+/// Decoder of MessagePack messages into rows of a declared schema
+pub struct MsgPackDecoder {
+    schema: Schema,
+    attrs: Vec<Attribute>,
+    limits: DecodeLimits,
+}
+
+impl MsgPackDecoder {
+    pub fn new(schema: Schema) -> MsgPackDecoder {
+        let attrs = schema.iter().cloned().collect();
+        MsgPackDecoder { schema: schema, attrs: attrs, limits: DecodeLimits::default() }
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> MsgPackDecoder {
+        self.limits = limits;
+        self
+    }
+}
+
+fn decode_items<'m>(input: &mut Input<'m>, count: usize, depth: usize)
+    -> Result<Vec<Item<'m>>, DBError>
+{
+    // Every item takes at least a byte, don't trust the count for the allocation
+    let mut out = Vec::with_capacity(count.min(input.data.len()));
+    for _ in 0 .. count {
+        out.push(decode_item(input, depth + 1)?);
+    }
+    Ok(out)
+}
+
+fn decode_map<'m>(input: &mut Input<'m>, count: usize, depth: usize) -> Result<Item<'m>, DBError> {
+    let mut out = Vec::with_capacity(count.min(input.data.len()));
+    for _ in 0 .. count {
+        let key = decode_item(input, depth + 1)?;
+        let value = decode_item(input, depth + 1)?;
+        out.push((key, value));
+    }
+    Ok(Item::Map(out))
+}
+
+/// Decode the next item of the input
+fn decode_item<'m>(input: &mut Input<'m>, depth: usize) -> Result<Item<'m>, DBError> {
+    if depth > MAX_DEPTH {
+        return Err(DBError::ValueParse(String::from("MessagePack item nested too deep")))
+    }
+
+    let marker = input.byte()?;
+
+    Ok(match marker {
+        0x00 ..= 0x7f   => Item::UInt(marker as u64),
+        0x80 ..= 0x8f   => decode_map(input, (marker & 0x0f) as usize, depth)?,
+        0x90 ..= 0x9f   => Item::Array(decode_items(input, (marker & 0x0f) as usize, depth)?),
+        0xa0 ..= 0xbf   => Item::Str(input.text((marker & 0x1f) as usize)?),
+        0xc0            => Item::Nil,
+        0xc2            => Item::Bool(false),
+        0xc3            => Item::Bool(true),
+        0xc4            => { let len = input.uint(1)? as usize; Item::Bin(input.take(len)?) },
+        0xc5            => { let len = input.uint(2)? as usize; Item::Bin(input.take(len)?) },
+        0xc6            => { let len = input.uint(4)? as usize; Item::Bin(input.take(len)?) },
+        0xca            => Item::Float(f32::from_bits(input.uint(4)? as u32) as f64),
+        0xcb            => Item::Float(f64::from_bits(input.uint(8)?)),
+        0xcc            => Item::UInt(input.uint(1)?),
+        0xcd            => Item::UInt(input.uint(2)?),
+        0xce            => Item::UInt(input.uint(4)?),
+        0xcf            => Item::UInt(input.uint(8)?),
+        0xd0            => Item::Int(input.uint(1)? as i8 as i64),
+        0xd1            => Item::Int(input.uint(2)? as i16 as i64),
+        0xd2            => Item::Int(input.uint(4)? as i32 as i64),
+        0xd3            => Item::Int(input.uint(8)? as i64),
+        0xd9            => { let len = input.uint(1)? as usize; Item::Str(input.text(len)?) },
+        0xda            => { let len = input.uint(2)? as usize; Item::Str(input.text(len)?) },
+        0xdb            => { let len = input.uint(4)? as usize; Item::Str(input.text(len)?) },
+        0xdc            => {
+            let len = input.uint(2)? as usize;
+            Item::Array(decode_items(input, len, depth)?)
+        },
+        0xdd            => {
+            let len = input.uint(4)? as usize;
+            Item::Array(decode_items(input, len, depth)?)
+        },
+        0xde            => { let len = input.uint(2)? as usize; decode_map(input, len, depth)? },
+        0xdf            => { let len = input.uint(4)? as usize; decode_map(input, len, depth)? },
+        0xe0 ..= 0xff   => Item::Int(marker as i8 as i64),
+        0xc7 ..= 0xc9 | 0xd4 ..= 0xd8 =>
+            return Err(DBError::Unsupported(String::from("MessagePack extension types"))),
+        _ =>
+            return Err(DBError::ValueParse(format!("invalid MessagePack marker {:#x}", marker))),
+    })
+}
+
+impl RowDecoder for MsgPackDecoder {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    fn decode_values<'m>(&self, message: &'m [u8]) -> Result<Vec<Value<'m>>, DBError> {
+        let mut input = Input::new(message);
+        let item = decode_item(&mut input, 0)?;
+
+        if !input.done() {
+            return Err(DBError::ValueParse(String::from("trailing bytes after MessagePack row")))
+        }
+
+        record_values(&self.attrs, item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_value};
+    use ::types::Type;
+
+    // Maps by name, arrays by position, nested arrays and maps map to LIST and STRUCT
+    #[test]
+    fn decode_rows() {
+        let point = vec![
+            Attribute{name: "x".to_string(), nullable: false, dtype: Type::INT32},
+            Attribute{name: "y".to_string(), nullable: false, dtype: Type::INT32},
+        ];
+
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT64},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "tags".to_string(), nullable: true,
+                      dtype: Type::LIST(Box::new(Type::UINT32))},
+            Attribute{name: "at".to_string(), nullable: true, dtype: Type::STRUCT(point)},
+        ];
+
+        let decoder = MsgPackDecoder::new(Schema::from_vec(attrs).unwrap());
+
+        // {"id": 300, "name": "ab", "tags": [1, 2], "at": {"x": -1, "y": 2}, "extra": nil}
+        let map: &[u8] = &[
+            0x85,
+            0xa2, b'i', b'd', 0xcd, 0x01, 0x2c,
+            0xa4, b'n', b'a', b'm', b'e', 0xa2, b'a', b'b',
+            0xa4, b't', b'a', b'g', b's', 0x92, 0x01, 0x02,
+            0xa2, b'a', b't', 0x82, 0xa1, b'x', 0xff, 0xa1, b'y', 0x02,
+            0xa5, b'e', b'x', b't', b'r', b'a', 0xc0,
+        ];
+        // [7, nil, [], nil]
+        let array: &[u8] = &[0x94, 0x07, 0xc0, 0x90, 0xc0];
+
+        let block = decoder.decode(&allocator::GLOBAL, vec![map, array]).unwrap();
+        assert_eq!(block.rows(), 2);
+
+        let value = |col, row| column_value(block.column(col).unwrap(), row).unwrap();
+        assert!(value(0, 0) == Value::UINT64(300));
+        assert!(value(1, 0) == Value::TEXT("ab"));
+        assert!(value(2, 0) == Value::LIST(vec![Value::UINT32(1), Value::UINT32(2)]));
+        assert!(value(3, 0) == Value::STRUCT(vec![Value::INT32(-1), Value::INT32(2)]));
+        assert!(value(0, 1) == Value::UINT64(7));
+        assert!(value(1, 1) == Value::NULL);
+        assert!(value(2, 1) == Value::LIST(Vec::new()));
+        assert!(value(3, 1) == Value::NULL);
+
+        // Negative id
+        match decoder.decode_values(&[0x94, 0xff, 0xc0, 0x90, 0xc0]) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        // Missing id
+        match decoder.decode_values(&[0x80]) {
+            Err(DBError::AttributeNullability(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Messages, values and batches over the limits fail before they're added to the block
+    #[test]
+    fn decode_limits() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT64},
+            Attribute{name: "tags".to_string(), nullable: true,
+                      dtype: Type::LIST(Box::new(Type::TEXT))},
+        ];
+
+        let limits = DecodeLimits {
+            max_message_len: Some(8),
+            max_field_len: Some(2),
+            max_rows: Some(2),
+        };
+        let decoder = MsgPackDecoder::new(Schema::from_vec(attrs).unwrap()).with_limits(limits);
+
+        let expect_limit = |messages: Vec<&[u8]>| {
+            match decoder.decode(&allocator::GLOBAL, messages) {
+                Err(DBError::InputLimit(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error"),
+            }
+        };
+
+        // [1, ["ab"]], [2, nil]
+        let small: &[u8] = &[0x92, 0x01, 0x91, 0xa2, b'a', b'b'];
+        let empty: &[u8] = &[0x92, 0x02, 0xc0];
+        let block = decoder.decode(&allocator::GLOBAL, vec![small, empty]).unwrap();
+        assert_eq!(block.rows(), 2);
+
+        expect_limit(vec![small, empty, empty]);
+        // [1, ["abc"]]
+        expect_limit(vec![&[0x92, 0x01, 0x91, 0xa3, b'a', b'b', b'c']]);
+        // [1, ["a", "b", "c", "d"]]
+        expect_limit(vec![&[0x92, 0x01, 0x94, 0xa1, b'a', 0xa1, b'b', 0xa1, b'c', 0xa1, b'd']]);
+
+        // Only decode_into() and decode() check the limits
+        assert!(decoder.decode_values(&[0x92, 0x01, 0x91, 0xa3, b'a', b'b', b'c']).is_ok());
+    }
+}
@@ -0,0 +1,447 @@
+//! PostgreSQL binary COPY format (`COPY ... WITH (FORMAT binary)`).
+//!
+//! The format is a header, a tuple per row (a field count, then the length and bytes of each
+//! field, -1 for NULL) and a trailer, integers in network byte order. It doesn't describe the
+//! column types, the schema of the rows is declared (`PgCopyDecoder::decode`) and each value is
+//! read in the binary (`recv`) representation of the PostgreSQL type matching its attribute type:
+//!
+//! | Type               | PostgreSQL                               |
+//! |--------------------|------------------------------------------|
+//! | INT32              | integer (or smallint)                    |
+//! | INT64              | bigint (or integer, smallint)            |
+//! | UINT32, UINT64     | bigint (or integer, smallint), positive  |
+//! | FLOAT32            | real                                     |
+//! | FLOAT64            | double precision (or real)               |
+//! | BOOLEAN            | boolean                                  |
+//! | TIMESTAMP          | timestamp or timestamptz                 |
+//! | INTERVAL           | interval                                 |
+//! | TEXT               | text, varchar, char, name, json          |
+//! | BLOB               | bytea                                    |
+//! | UUID               | uuid                                     |
+//!
+//! `PgCopyWriter` writes the PostgreSQL type of the table above (bigint for the unsigned
+//! integers, UINT64 values past `i64::MAX` can't be written). LIST and STRUCT attributes are not
+//! supported.
+
+use std::io::Write;
+use std::str;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::{DBError, ErrorContext};
+use ::operation::{Cursor, CursorChunk};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{IntervalValue, Type, Value};
+use ::util::temporal::{MICROS_PER_DAY, days_from_civil};
+
+use super::DecodeLimits;
+
+/// Starts the header
+pub const SIGNATURE: &[u8; 11] = b"PGCOPY\n\xff\r\n\0";
+
+/// Header flag of tuples with an OID field
+const FLAG_OIDS: u32 = 1 << 16;
+
+/// Microseconds between the Unix and PostgreSQL (2000-01-01) epochs
+fn pg_epoch() -> i64 {
+    days_from_civil(2000, 1, 1) * MICROS_PER_DAY
+}
+
+fn supported(attr: &Attribute) -> Result<(), DBError> {
+    match attr.dtype {
+        Type::LIST(_) | Type::STRUCT(_) =>
+            Err(DBError::Unsupported(format!("COPY of {} column {}", attr.dtype, attr.name))),
+        _ => Ok(()),
+    }
+}
+
+/// Reader of PostgreSQL binary COPY data
+#[derive(Clone, Debug, Default)]
+pub struct PgCopyDecoder {
+    pub limits: DecodeLimits,
+}
+
+/// Bounds checked reads of the COPY data
+struct Input<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Input<'d> {
+    fn take(&mut self, len: usize) -> Result<&'d [u8], DBError> {
+        if self.data.len() - self.pos < len {
+            return Err(DBError::ValueParse("COPY data is truncated".to_string()))
+        }
+
+        let out = &self.data[self.pos .. self.pos + len];
+        self.pos += len;
+        Ok(out)
+    }
+
+    fn int(&mut self, len: usize) -> Result<i64, DBError> {
+        Ok(be_int(self.take(len)?))
+    }
+}
+
+/// Big endian signed integer of up to 8 bytes
+fn be_int(bytes: &[u8]) -> i64 {
+    let mut out = [0u8; 8];
+    out[8 - bytes.len() ..].copy_from_slice(bytes);
+    let shift = 64 - 8 * bytes.len() as u32;
+    (i64::from_be_bytes(out) << shift) >> shift
+}
+
+/// Value of a field of the attribute
+fn field_value<'d>(attr: &Attribute, field: &'d [u8]) -> Result<Value<'d>, DBError> {
+    let invalid = || DBError::ValueParse(format!("{} field of {} bytes", attr.name, field.len()));
+    let range = || DBError::ValueOutOfRange(format!("{} value", attr.name));
+    let int = || match field.len() {
+        2 | 4 | 8 => Ok(be_int(field)),
+        _ => Err(invalid()),
+    };
+
+    Ok(match attr.dtype {
+        Type::INT32 => {
+            let v = int()?;
+            if v as i32 as i64 != v {
+                return Err(range())
+            }
+            Value::INT32(v as i32)
+        },
+        Type::INT64 => Value::INT64(int()?),
+        Type::UINT32 => match int()? {
+            v if v as u32 as i64 == v => Value::UINT32(v as u32),
+            _ => return Err(range()),
+        },
+        Type::UINT64 => match int()? {
+            v if v >= 0 => Value::UINT64(v as u64),
+            _ => return Err(range()),
+        },
+        Type::FLOAT32 if field.len() == 4 => Value::FLOAT32(f32::from_bits(be_int(field) as u32)),
+        Type::FLOAT64 if field.len() == 4 =>
+            Value::FLOAT64(f32::from_bits(be_int(field) as u32) as f64),
+        Type::FLOAT64 if field.len() == 8 => Value::FLOAT64(f64::from_bits(be_int(field) as u64)),
+        Type::BOOLEAN if field.len() == 1 => Value::BOOLEAN(field[0] != 0),
+        Type::TIMESTAMP if field.len() == 8 =>
+            Value::TIMESTAMP(be_int(field).checked_add(pg_epoch()).ok_or_else(range)?),
+        Type::INTERVAL if field.len() == 16 => Value::INTERVAL(IntervalValue {
+            micros: be_int(&field[.. 8]),
+            days: be_int(&field[8 .. 12]) as i32,
+            months: be_int(&field[12 ..]) as i32,
+        }),
+        Type::TEXT => Value::TEXT(str::from_utf8(field).map_err(|_| {
+            DBError::ValueParse(format!("{} value is not UTF-8", attr.name))
+        })?),
+        Type::BLOB => Value::BLOB(field),
+        Type::UUID if field.len() == 16 => {
+            let mut uuid = [0u8; 16];
+            uuid.copy_from_slice(field);
+            Value::UUID(uuid)
+        },
+        _ => {
+            supported(attr)?;
+            return Err(invalid())
+        },
+    })
+}
+
+impl PgCopyDecoder {
+    /// Read the next tuple into `values`, false at the trailer. Errors of a field have its
+    /// attribute as context.
+    fn tuple<'d>(&self, input: &mut Input<'d>, schema: &Schema, values: &mut Vec<Value<'d>>)
+        -> Result<bool, DBError>
+    {
+        let start = input.pos;
+        let fields = input.int(2)?;
+        if fields == -1 {
+            return Ok(false)
+        }
+        if fields != schema.count() as i64 {
+            let msg = format!("COPY tuple of {} fields, expected {}", fields, schema.count());
+            return Err(DBError::ValueParse(msg))
+        }
+
+        values.clear();
+        for attr in schema.iter() {
+            let value = input.int(4)
+                .and_then(|len| match len {
+                    -1 => Ok(Value::NULL),
+                    len if len < 0 => Err(DBError::ValueParse(format!("field length {}", len))),
+                    len => field_value(attr, input.take(len as usize)?),
+                })
+                .and_then(|value| self.limits.check_value(attr, &value).map(|_| value))
+                .map_err(|e| e.in_column(attr.name.as_str()))?;
+            values.push(value);
+        }
+
+        self.limits.check_message_len(input.pos - start)?;
+        Ok(true)
+    }
+
+    pub fn new() -> PgCopyDecoder {
+        PgCopyDecoder::default()
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> PgCopyDecoder {
+        self.limits = limits;
+        self
+    }
+
+    /// Decode the tuples of the COPY data into a block of the schema, fields in schema order.
+    /// `DecodeLimits::max_message_len` applies to the tuples. Errors have the row and byte
+    /// offset of the failing tuple as context.
+    pub fn decode<'b>(&self, alloc: &'b Allocator, schema: &Schema, data: &[u8])
+        -> Result<Block<'b>, DBError>
+    {
+        for attr in schema.iter() {
+            supported(attr)?;
+        }
+
+        let mut input = Input { data: data, pos: 0 };
+        if input.take(SIGNATURE.len()).ok() != Some(&SIGNATURE[..]) {
+            return Err(DBError::ValueParse("COPY data without the binary signature".to_string()))
+        }
+        if input.int(4)? as u32 & FLAG_OIDS != 0 {
+            return Err(DBError::Unsupported("COPY data WITH OIDS".to_string()))
+        }
+        let extension = input.int(4)?;
+        if extension < 0 {
+            return Err(DBError::ValueParse("COPY header extension length".to_string()))
+        }
+        input.take(extension as usize)?;
+
+        let mut table = Table::new(alloc, schema, None);
+        let mut values = Vec::with_capacity(schema.count());
+        let mut rows = 0;
+
+        loop {
+            let start = input.pos;
+            match self.tuple(&mut input, schema, &mut values) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(e) => {
+                    let at = ErrorContext {
+                        row: Some(rows),
+                        byte: Some(start),
+                        ..Default::default()
+                    };
+                    return Err(e.with_context(at))
+                },
+            }
+
+            let row = table.add_row()?;
+            rows = row + 1;
+            self.limits.check_rows(row + 1)?;
+            for (pos, value) in values.drain(..).enumerate() {
+                table.set(pos, row, value)?;
+            }
+        }
+
+        match table.take() {
+            Some(block) => Ok(block),
+            None => Ok(Block::new(alloc, schema)),
+        }
+    }
+}
+
+/// Writer of PostgreSQL binary COPY data of rows of a schema
+pub struct PgCopyWriter<W: Write> {
+    out: W,
+    schema: Schema,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> PgCopyWriter<W> {
+    /// Start the COPY data, writing the header
+    pub fn new(mut out: W, schema: &Schema) -> Result<PgCopyWriter<W>, DBError> {
+        for attr in schema.iter() {
+            supported(attr)?;
+        }
+
+        let mut header = SIGNATURE.to_vec();
+        header.extend_from_slice(&[0; 8]);
+        out.write_all(&header).map_err(DBError::IO)?;
+
+        Ok(PgCopyWriter { out: out, schema: schema.clone(), buf: Vec::new() })
+    }
+
+    /// Write a tuple per row of the view (of the writer schema)
+    pub fn write_view<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        let schema = view.schema();
+        if schema.count() != self.schema.count() {
+            let msg = format!("{} attributes, the COPY has {}", schema.count(),
+                              self.schema.count());
+            return Err(DBError::AttributeMissing(msg))
+        }
+        for (attr, expected) in schema.iter().zip(self.schema.iter()) {
+            if attr.dtype != expected.dtype {
+                return Err(DBError::AttributeType(attr.name.clone()))
+            }
+        }
+
+        let mut columns = Vec::with_capacity(schema.count());
+        for pos in 0 .. schema.count() {
+            columns.push(view.column(pos).ok_or_else(|| DBError::make_column_unknown_pos(pos))?);
+        }
+
+        for row in 0 .. view.rows() {
+            self.buf.clear();
+            self.buf.extend_from_slice(&(schema.count() as i16).to_be_bytes());
+
+            for (attr, col) in schema.iter().zip(&columns) {
+                let len = self.buf.len();
+                self.buf.extend_from_slice(&[0; 4]);
+
+                let field: i32 = match column_value(*col, row)? {
+                    Value::NULL => -1,
+                    value => {
+                        write_value(attr, &value, &mut self.buf)?;
+                        let field = self.buf.len() - len - 4;
+                        if field as i32 as usize != field {
+                            let msg = format!("{} value of {} bytes", attr.name, field);
+                            return Err(DBError::ValueOutOfRange(msg))
+                        }
+                        field as i32
+                    },
+                };
+                self.buf[len .. len + 4].copy_from_slice(&field.to_be_bytes());
+            }
+
+            self.out.write_all(&self.buf).map_err(DBError::IO)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the rows of the cursor, fetching up to `rows` at a time. Returns the number of rows
+    /// written.
+    pub fn write_cursor<'a>(&mut self, cursor: &mut (Cursor<'a> + 'a), rows: RowOffset)
+        -> Result<RowOffset, DBError>
+    {
+        let mut written = 0;
+        while let CursorChunk::Next(view) = cursor.next(rows)? {
+            self.write_view(&view)?;
+            written += view.rows();
+        }
+        Ok(written)
+    }
+
+    /// End the COPY data, returns the flushed output
+    pub fn finish(mut self) -> Result<W, DBError> {
+        self.out.write_all(&(-1i16).to_be_bytes()).map_err(DBError::IO)?;
+        self.out.flush().map_err(DBError::IO)?;
+        Ok(self.out)
+    }
+}
+
+/// Binary representation of the (non NULL) value
+fn write_value(attr: &Attribute, value: &Value, out: &mut Vec<u8>) -> Result<(), DBError> {
+    let range = || DBError::ValueOutOfRange(format!("{} value", attr.name));
+
+    match *value {
+        Value::INT32(v)     => out.extend_from_slice(&v.to_be_bytes()),
+        Value::INT64(v)     => out.extend_from_slice(&v.to_be_bytes()),
+        Value::UINT32(v)    => out.extend_from_slice(&(v as i64).to_be_bytes()),
+        Value::UINT64(v) if v as i64 >= 0 => out.extend_from_slice(&(v as i64).to_be_bytes()),
+        Value::UINT64(_)    => return Err(range()),
+        Value::FLOAT32(v)   => out.extend_from_slice(&v.to_bits().to_be_bytes()),
+        Value::FLOAT64(v)   => out.extend_from_slice(&v.to_bits().to_be_bytes()),
+        Value::BOOLEAN(v)   => out.push(v as u8),
+        Value::TIMESTAMP(v) => {
+            let v = v.checked_sub(pg_epoch()).ok_or_else(range)?;
+            out.extend_from_slice(&v.to_be_bytes());
+        },
+        Value::INTERVAL(v)  => {
+            out.extend_from_slice(&v.micros.to_be_bytes());
+            out.extend_from_slice(&v.days.to_be_bytes());
+            out.extend_from_slice(&v.months.to_be_bytes());
+        },
+        Value::TEXT(v)      => out.extend_from_slice(v.as_bytes()),
+        Value::BLOB(v)      => out.extend_from_slice(v),
+        Value::UUID(ref v)  => out.extend_from_slice(v),
+        _ => return Err(DBError::Unsupported(format!("COPY of {} value", attr.name))),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::{Operation, ScanView};
+
+    // Rows read back as written, the header, tuple and trailer layout of PostgreSQL
+    #[test]
+    fn copy_round_trip() {
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "id".to_string(), nullable: false, dtype: Type::INT32 },
+            Attribute { name: "n".to_string(), nullable: true, dtype: Type::UINT64 },
+            Attribute { name: "at".to_string(), nullable: false, dtype: Type::TIMESTAMP },
+            Attribute { name: "d".to_string(), nullable: false, dtype: Type::INTERVAL },
+            Attribute { name: "s".to_string(), nullable: true, dtype: Type::TEXT },
+            Attribute { name: "x".to_string(), nullable: false, dtype: Type::BLOB },
+            Attribute { name: "f".to_string(), nullable: false, dtype: Type::FLOAT64 },
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        let interval = IntervalValue { months: 1, days: 2, micros: 3 };
+        block.append_row(&[Value::INT32(1), Value::UINT64(7), Value::TIMESTAMP(0),
+                           Value::INTERVAL(interval), Value::TEXT("héllo"), Value::BLOB(b""),
+                           Value::FLOAT64(-0.5)]).unwrap();
+        block.append_row(&[Value::INT32(-2), Value::NULL, Value::TIMESTAMP(pg_epoch()),
+                           Value::INTERVAL(interval), Value::NULL, Value::BLOB(b"\x00\xff"),
+                           Value::FLOAT64(1e300)]).unwrap();
+
+        let op = ScanView::new(&block, None);
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut writer = PgCopyWriter::new(Vec::new(), &schema).unwrap();
+        assert_eq!(writer.write_cursor(&mut *cursor, 1).unwrap(), 2);
+        let data = writer.finish().unwrap();
+
+        assert_eq!(&data[.. 19], b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0");
+        // Field count and the first field: length 4, integer 1
+        assert_eq!(&data[19 .. 29], &[0, 7, 0, 0, 0, 4, 0, 0, 0, 1]);
+        assert_eq!(&data[data.len() - 2 ..], &[0xff, 0xff]);
+        // 2000-01-01 is 0 in PostgreSQL
+        let mut out = Vec::new();
+        write_value(schema.get(2).unwrap(), &Value::TIMESTAMP(pg_epoch()), &mut out).unwrap();
+        assert_eq!(out, vec![0; 8]);
+
+        let read = PgCopyDecoder::new().decode(&allocator::GLOBAL, &schema, &data).unwrap();
+        assert_eq!(read.rows(), 2);
+        for row in 0 .. 2 {
+            for pos in 0 .. schema.count() {
+                assert!(read.value(row, pos).unwrap() == block.value(row, pos).unwrap());
+            }
+        }
+
+        // Narrower PostgreSQL integers, into a wider attribute
+        let mut data = SIGNATURE.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0xff, 0xfe, 0xff, 0xff]);
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let read = PgCopyDecoder::new().decode(&allocator::GLOBAL, &schema, &data).unwrap();
+        assert!(read.value(0, 0).unwrap() == Value::INT64(-2));
+
+        let schema = Schema::make_one_attr("v", false, Type::UINT32);
+        let err = PgCopyDecoder::new().decode(&allocator::GLOBAL, &schema, &data).err().unwrap();
+        match *err.root() {
+            DBError::ValueOutOfRange(_) => (), // nop
+            ref e => assert!(false, "Unexpected error {}", e),
+        };
+        assert!(err.context() == Some(&ErrorContext {
+            column: Some("v".to_string()),
+            row: Some(0),
+            byte: Some(19),
+            ..Default::default()
+        }));
+        let short = PgCopyDecoder::new().decode(&allocator::GLOBAL, &schema, &data[.. 20]);
+        match short.as_ref().map_err(DBError::root) {
+            Err(&DBError::ValueParse(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
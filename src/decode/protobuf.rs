@@ -0,0 +1,439 @@
+//! Protobuf message decoding.
+//!
+//! Messages are decoded straight from the wire format using a `MessageDescriptor` provided at
+//! runtime (eg. built from a `.proto` file or a schema registry), no generated code is needed.
+//! Each message becomes a row: a field maps to an attribute, repeated fields to LIST attributes
+//! and nested messages to STRUCT attributes, or to one attribute per nested field when flattened.
+//!
+//! Fields that are not in the message are NULL (empty LIST if repeated), fields that are not in
+//! the descriptor are skipped. For a singular field that's in the message more than once the
+//! last one wins, nested messages are not merged.
+
+use std::str;
+
+use ::error::DBError;
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value};
+
+use super::{DecodeLimits, RowDecoder};
+
+/// Protobuf scalar type or nested message of a field
+#[derive(Clone, Debug)]
+pub enum FieldType {
+    Double,
+    Float,
+    Int32,
+    Int64,
+    UInt32,
+    UInt64,
+    SInt32,
+    SInt64,
+    Fixed32,
+    Fixed64,
+    SFixed32,
+    SFixed64,
+    Bool,
+    String,
+    Bytes,
+    Message(MessageDescriptor),
+}
+
+/// Field of a message, identified by its field number on the wire
+#[derive(Clone, Debug)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub number: u32,
+    pub ftype: FieldType,
+    pub repeated: bool,
+}
+
+/// Message type, the fields are decoded to attributes in this order
+#[derive(Clone, Debug)]
+pub struct MessageDescriptor {
+    pub name: String,
+    pub fields: Vec<FieldDescriptor>,
+}
+
+/// How nested (singular) message fields map to attributes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Flatten {
+    /// A STRUCT attribute per message field
+    Struct,
+    /// An attribute per nested field, named by joining the field path with the separator.
+    /// Repeated message fields are LIST of STRUCT either way.
+    Columns(String),
+}
+
+/// Decoder of protobuf messages of one type into rows
+pub struct ProtobufDecoder {
+    descriptor: MessageDescriptor,
+    flatten: Flatten,
+    schema: Schema,
+    limits: DecodeLimits,
+}
+
+/// Protobuf wire types
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+impl FieldDescriptor {
+    pub fn new<S: Into<String>>(name: S, number: u32, ftype: FieldType) -> FieldDescriptor {
+        FieldDescriptor { name: name.into(), number: number, ftype: ftype, repeated: false }
+    }
+
+    pub fn repeated<S: Into<String>>(name: S, number: u32, ftype: FieldType) -> FieldDescriptor {
+        FieldDescriptor { repeated: true, ..FieldDescriptor::new(name, number, ftype) }
+    }
+
+    /// Attribute of the field values, `name` is the (flattened) attribute name
+    fn attribute(&self, name: &str) -> Attribute {
+        let dtype = self.ftype.dtype();
+        Attribute {
+            name: name.to_string(),
+            nullable: !self.repeated,
+            dtype: if self.repeated { Type::LIST(Box::new(dtype)) } else { dtype },
+        }
+    }
+}
+
+impl MessageDescriptor {
+    pub fn new<S: Into<String>>(name: S, fields: Vec<FieldDescriptor>) -> MessageDescriptor {
+        MessageDescriptor { name: name.into(), fields: fields }
+    }
+}
+
+impl FieldType {
+    /// Type of the field values
+    fn dtype(&self) -> Type {
+        match *self {
+            FieldType::Double                                           => Type::FLOAT64,
+            FieldType::Float                                            => Type::FLOAT32,
+            FieldType::Int32 | FieldType::SInt32 | FieldType::SFixed32  => Type::INT32,
+            FieldType::Int64 | FieldType::SInt64 | FieldType::SFixed64  => Type::INT64,
+            FieldType::UInt32 | FieldType::Fixed32                      => Type::UINT32,
+            FieldType::UInt64 | FieldType::Fixed64                      => Type::UINT64,
+            FieldType::Bool                                             => Type::BOOLEAN,
+            FieldType::String                                           => Type::TEXT,
+            FieldType::Bytes                                            => Type::BLOB,
+            FieldType::Message(ref msg)                                 =>
+                Type::STRUCT(msg.fields.iter().map(|f| f.attribute(&f.name)).collect()),
+        }
+    }
+
+    fn wire_type(&self) -> u8 {
+        match *self {
+            FieldType::Double | FieldType::Fixed64 | FieldType::SFixed64    => FIXED64,
+            FieldType::Float | FieldType::Fixed32 | FieldType::SFixed32     => FIXED32,
+            FieldType::String | FieldType::Bytes | FieldType::Message(_)    => LENGTH_DELIMITED,
+            _                                                               => VARINT,
+        }
+    }
+}
+
+/// Position in a message's bytes
+struct Reader<'m> {
+    data: &'m [u8],
+    pos: usize,
+}
+
+fn truncated() -> DBError {
+    DBError::ValueParse(String::from("truncated protobuf message"))
+}
+
+impl<'m> Reader<'m> {
+    fn new(data: &'m [u8]) -> Reader<'m> {
+        Reader { data: data, pos: 0 }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'m [u8], DBError> {
+        if len > self.data.len() - self.pos {
+            return Err(truncated())
+        }
+
+        let data: &'m [u8] = self.data;
+        let out = &data[self.pos .. self.pos + len];
+        self.pos += len;
+        Ok(out)
+    }
+
+    fn varint(&mut self) -> Result<u64, DBError> {
+        let mut out = 0u64;
+
+        for shift in 0 .. 10 {
+            let byte = self.take(1)?[0];
+            out |= ((byte & 0x7f) as u64) << (shift * 7);
+            if byte & 0x80 == 0 {
+                return Ok(out)
+            }
+        }
+
+        Err(DBError::ValueParse(String::from("protobuf varint longer than 10 bytes")))
+    }
+
+    fn fixed32(&mut self) -> Result<u32, DBError> {
+        let b = self.take(4)?;
+        Ok(b.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u32))
+    }
+
+    fn fixed64(&mut self) -> Result<u64, DBError> {
+        let b = self.take(8)?;
+        Ok(b.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn length_delimited(&mut self) -> Result<&'m [u8], DBError> {
+        let len = self.varint()? as usize;
+        self.take(len)
+    }
+
+    fn skip(&mut self, wire_type: u8) -> Result<(), DBError> {
+        match wire_type {
+            VARINT              => self.varint().map(|_| ()),
+            FIXED64             => self.take(8).map(|_| ()),
+            LENGTH_DELIMITED    => self.length_delimited().map(|_| ()),
+            FIXED32             => self.take(4).map(|_| ()),
+            _ => Err(DBError::Unsupported(format!("protobuf wire type {}", wire_type))),
+        }
+    }
+}
+
+fn zigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+/// Decode a single value of the field type
+fn decode_value<'m>(ftype: &FieldType, reader: &mut Reader<'m>) -> Result<Value<'m>, DBError> {
+    Ok(match *ftype {
+        FieldType::Double   => Value::FLOAT64(f64::from_bits(reader.fixed64()?)),
+        FieldType::Float    => Value::FLOAT32(f32::from_bits(reader.fixed32()?)),
+        FieldType::Int32    => Value::INT32(reader.varint()? as i32),
+        FieldType::Int64    => Value::INT64(reader.varint()? as i64),
+        FieldType::UInt32   => Value::UINT32(reader.varint()? as u32),
+        FieldType::UInt64   => Value::UINT64(reader.varint()?),
+        FieldType::SInt32   => Value::INT32(zigzag(reader.varint()?) as i32),
+        FieldType::SInt64   => Value::INT64(zigzag(reader.varint()?)),
+        FieldType::Fixed32  => Value::UINT32(reader.fixed32()?),
+        FieldType::Fixed64  => Value::UINT64(reader.fixed64()?),
+        FieldType::SFixed32 => Value::INT32(reader.fixed32()? as i32),
+        FieldType::SFixed64 => Value::INT64(reader.fixed64()? as i64),
+        FieldType::Bool     => Value::BOOLEAN(reader.varint()? != 0),
+        FieldType::String   => {
+            let data = reader.length_delimited()?;
+            let text = str::from_utf8(data)
+                .map_err(|_| DBError::ValueParse(String::from("protobuf string is not UTF-8")))?;
+            Value::TEXT(text)
+        },
+        FieldType::Bytes    => Value::BLOB(reader.length_delimited()?),
+        FieldType::Message(ref msg) =>
+            Value::STRUCT(decode_message(msg, reader.length_delimited()?)?),
+    })
+}
+
+/// Value of each descriptor field, in descriptor order
+fn decode_message<'m>(desc: &MessageDescriptor, data: &'m [u8]) -> Result<Vec<Value<'m>>, DBError> {
+    let mut out: Vec<Value<'m>> = desc.fields.iter()
+        .map(|f| if f.repeated { Value::LIST(Vec::new()) } else { Value::NULL })
+        .collect();
+
+    let mut reader = Reader::new(data);
+    while !reader.done() {
+        let key = reader.varint()?;
+        let (number, wire_type) = ((key >> 3) as u32, (key & 0x7) as u8);
+
+        let pos = match desc.fields.iter().position(|f| f.number == number) {
+            Some(pos) => pos,
+            None => { reader.skip(wire_type)?; continue },
+        };
+
+        let field = &desc.fields[pos];
+        let expected = field.ftype.wire_type();
+
+        // Repeated scalars can be packed into a single length delimited value
+        let packed = field.repeated && wire_type == LENGTH_DELIMITED && expected != wire_type;
+
+        if wire_type != expected && !packed {
+            let msg = format!("protobuf field {} has wire type {}, expected {}",
+                              field.name, wire_type, expected);
+            return Err(DBError::ValueParse(msg))
+        }
+
+        if !field.repeated {
+            out[pos] = decode_value(&field.ftype, &mut reader)?;
+            continue
+        }
+
+        if let Value::LIST(ref mut items) = out[pos] {
+            if packed {
+                let mut values = Reader::new(reader.length_delimited()?);
+                while !values.done() {
+                    items.push(decode_value(&field.ftype, &mut values)?);
+                }
+            } else {
+                items.push(decode_value(&field.ftype, &mut reader)?);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Attributes of the message fields, nested messages flattened with the separator
+fn flat_attributes(desc: &MessageDescriptor, prefix: &str, separator: &str,
+                   out: &mut Vec<Attribute>)
+{
+    for field in &desc.fields {
+        let name = format!("{}{}", prefix, field.name);
+
+        match field.ftype {
+            FieldType::Message(ref msg) if !field.repeated => {
+                let prefix = format!("{}{}", name, separator);
+                flat_attributes(msg, &prefix, separator, out);
+            },
+            _ => out.push(field.attribute(&name)),
+        }
+    }
+}
+
+/// Flattened values of the message fields. A NULL message has NULL fields.
+fn flat_values<'m>(desc: &MessageDescriptor, values: Vec<Value<'m>>, out: &mut Vec<Value<'m>>) {
+    for (field, value) in desc.fields.iter().zip(values) {
+        match field.ftype {
+            FieldType::Message(ref msg) if !field.repeated => match value {
+                Value::STRUCT(fields) => flat_values(msg, fields, out),
+                _ => flat_values(msg, msg.fields.iter().map(|_| Value::NULL).collect(), out),
+            },
+            _ => out.push(value),
+        }
+    }
+}
+
+impl ProtobufDecoder {
+    pub fn new(descriptor: MessageDescriptor, flatten: Flatten)
+        -> Result<ProtobufDecoder, DBError>
+    {
+        let mut attrs = Vec::new();
+        match flatten {
+            Flatten::Struct =>
+                attrs.extend(descriptor.fields.iter().map(|f| f.attribute(&f.name))),
+            Flatten::Columns(ref separator) =>
+                flat_attributes(&descriptor, "", separator, &mut attrs),
+        }
+
+        Ok(ProtobufDecoder {
+            schema: Schema::from_vec(attrs)?,
+            descriptor: descriptor,
+            flatten: flatten,
+            limits: DecodeLimits::default(),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> ProtobufDecoder {
+        self.limits = limits;
+        self
+    }
+}
+
+impl RowDecoder for ProtobufDecoder {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    fn decode_values<'m>(&self, message: &'m [u8]) -> Result<Vec<Value<'m>>, DBError> {
+        let values = decode_message(&self.descriptor, message)?;
+
+        Ok(match self.flatten {
+            Flatten::Struct => values,
+            Flatten::Columns(_) => {
+                let mut out = Vec::with_capacity(self.schema.count());
+                flat_values(&self.descriptor, values, &mut out);
+                out
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_value};
+
+    fn descriptor() -> MessageDescriptor {
+        let point = MessageDescriptor::new("Point", vec![
+            FieldDescriptor::new("x", 1, FieldType::SInt32),
+            FieldDescriptor::new("y", 2, FieldType::SInt32),
+        ]);
+
+        MessageDescriptor::new("Event", vec![
+            FieldDescriptor::new("id", 1, FieldType::UInt64),
+            FieldDescriptor::new("name", 2, FieldType::String),
+            FieldDescriptor::new("at", 3, FieldType::Message(point)),
+            FieldDescriptor::repeated("tags", 4, FieldType::UInt32),
+            FieldDescriptor::new("score", 5, FieldType::Double),
+        ])
+    }
+
+    // id: 300, name: "ab", at: {x: -1, y: 2}, tags: [1, 2] (packed), unknown field 9, score: 1.5
+    const EVENT: &[u8] = &[
+        0x08, 0xac, 0x02,
+        0x12, 0x02, b'a', b'b',
+        0x1a, 0x04, 0x08, 0x01, 0x10, 0x04,
+        0x22, 0x02, 0x01, 0x02,
+        0x48, 0x07,
+        0x29, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f,
+    ];
+
+    // Scalars, packed repeated and nested as STRUCT, missing fields are NULL
+    #[test]
+    fn decode_struct() {
+        let decoder = ProtobufDecoder::new(descriptor(), Flatten::Struct).unwrap();
+        let values = decoder.decode_values(EVENT).unwrap();
+
+        assert!(values[0] == Value::UINT64(300));
+        assert!(values[1] == Value::TEXT("ab"));
+        assert!(values[2] == Value::STRUCT(vec![Value::INT32(-1), Value::INT32(2)]));
+        assert!(values[3] == Value::LIST(vec![Value::UINT32(1), Value::UINT32(2)]));
+        assert!(values[4] == Value::FLOAT64(1.5));
+
+        // Only id, unpacked tags
+        let values = decoder.decode_values(&[0x08, 0x01, 0x20, 0x05, 0x20, 0x06]).unwrap();
+        assert!(values[1] == Value::NULL && values[2] == Value::NULL);
+        assert!(values[3] == Value::LIST(vec![Value::UINT32(5), Value::UINT32(6)]));
+
+        match decoder.decode_values(&EVENT[.. 5]) {
+            Err(DBError::ValueParse(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Nested fields become their own columns
+    #[test]
+    fn decode_flattened() {
+        let decoder = ProtobufDecoder::new(descriptor(), Flatten::Columns(".".to_string()))
+            .unwrap();
+
+        let names: Vec<&str> = decoder.schema().iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "name", "at.x", "at.y", "tags", "score"]);
+
+        let messages: Vec<&[u8]> = vec![EVENT, &[0x08, 0x01]];
+        let block = decoder.decode(&allocator::GLOBAL, messages).unwrap();
+        assert_eq!(block.rows(), 2);
+
+        let value = |col, row| column_value(block.column(col).unwrap(), row).unwrap();
+        assert!(value(2, 0) == Value::INT32(-1));
+        assert!(value(3, 0) == Value::INT32(2));
+        assert!(value(4, 0) == Value::LIST(vec![Value::UINT32(1), Value::UINT32(2)]));
+        assert!(value(2, 1) == Value::NULL);
+        assert!(value(4, 1) == Value::LIST(Vec::new()));
+    }
+}
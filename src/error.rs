@@ -1,8 +1,12 @@
 // vim: set ts=4 sw=4 et :
 
+use std::error::Error;
 use std::fmt;
 use std::heap::AllocErr;
 use std::io::{Error as IOError};
+use std::path::Path;
+
+use ::row::RowOffset;
 
 
 /// Query execution errors
@@ -14,22 +18,107 @@ pub enum DBError {
     UnknownType(String),
     /// Referencing a missing schema attribute (name or position)
     AttributeMissing(String),
+    /// Referencing an attribute name that's not in the schema, with the similar names in the
+    /// schema (closest first)
+    AttributeUnknown(String, Vec<String>),
     /// Mismatched expectation about attributes nullability
     AttributeNullability(String),
     /// Mismatched expectation about attribute types
     AttributeType(String),
     /// Duplicate attribute in result schema
     AttributeDuplicate(String),
+    /// Column data is not in the encoding expected by the operation
+    ColumnEncoding(String),
     ///
     ExpressionInputType(String),
     ExpressionInputCount(String),
     ExpressionNotCost,
+    /// Unable to parse a value from its string form
+    ValueParse(String),
+    /// Value cannot be represented in the result type
+    ValueOutOfRange(String),
+    /// TEXT value that's not valid UTF-8
+    InvalidUtf8 { row: RowOffset, column: String },
     ///
     RowOutOfBounds,
     /// Unknown memory allocation error
     Memory(AllocErr),
     /// Memory allocation limit reached (via policy)
     MemoryLimit,
+    /// Functionality (eg. a plan node) without an implementation
+    Unsupported(String),
+    /// Malformed query text
+    QueryInvalid(String),
+    /// Referencing a table that's not registered
+    TableMissing(String),
+    /// Calling a function that's not registered
+    FunctionMissing(String),
+    /// Transient errors persisted through all the attempts, the error of each attempt
+    RetriesExhausted(Vec<String>),
+    /// Index doesn't reflect the current table contents (it was modified after the index build)
+    IndexStale(String),
+    /// Input is larger than a configured limit (eg. of `decode::DecodeLimits`)
+    InputLimit(String),
+    /// Page token of a cursor that's not open (closed, expired or already past the page)
+    CursorMissing(String),
+    /// Query was cancelled via its `CancellationToken`, or ran past the token deadline
+    Cancelled(String),
+    /// Error (never itself a `Context`) with where it happened, see `DBError::with_context`
+    Context(Box<DBError>, Box<ErrorContext>),
+}
+
+/// Where an error happened, each part when known
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ErrorContext {
+    /// Operation (its `describe()`) that failed
+    pub operator: Option<String>,
+    /// Attribute of the failing value
+    pub column: Option<String>,
+    /// Row of the failing value, in the input of the operation or parser
+    pub row: Option<RowOffset>,
+    /// File that was read or written
+    pub path: Option<String>,
+    /// Line of the parser input (starting at 1)
+    pub line: Option<usize>,
+    /// Byte offset in the parser input (or file)
+    pub byte: Option<usize>,
+}
+
+impl ErrorContext {
+    /// Fill the unknown parts from the other context, keeping the known (more specific) ones
+    fn merge(&mut self, other: ErrorContext) {
+        self.operator = self.operator.take().or(other.operator);
+        self.column = self.column.take().or(other.column);
+        self.row = self.row.or(other.row);
+        self.path = self.path.take().or(other.path);
+        self.line = self.line.or(other.line);
+        self.byte = self.byte.or(other.byte);
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(ref operator) = self.operator {
+            parts.push(format!("operator {}", operator));
+        }
+        if let Some(ref column) = self.column {
+            parts.push(format!("column {}", column));
+        }
+        if let Some(row) = self.row {
+            parts.push(format!("row {}", row));
+        }
+        if let Some(ref path) = self.path {
+            parts.push(format!("file {}", path));
+        }
+        if let Some(line) = self.line {
+            parts.push(format!("line {}", line));
+        }
+        if let Some(byte) = self.byte {
+            parts.push(format!("byte {}", byte));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
 impl DBError {
@@ -40,6 +129,55 @@ impl DBError {
     pub fn make_column_unknown_pos(pos: usize) -> DBError {
         DBError::AttributeMissing(format!("(pos: {})", pos))
     }
+
+    /// Attach the context to the error. Context already attached (closer to the failure) wins
+    /// over the new one, which only fills in the unknown parts.
+    pub fn with_context(self, context: ErrorContext) -> DBError {
+        match self {
+            DBError::Context(inner, mut known) => {
+                known.merge(context);
+                DBError::Context(inner, known)
+            },
+            e => DBError::Context(Box::new(e), Box::new(context)),
+        }
+    }
+
+    pub fn in_operator<S: Into<String>>(self, operator: S) -> DBError {
+        self.with_context(ErrorContext { operator: Some(operator.into()), ..Default::default() })
+    }
+
+    pub fn in_column<S: Into<String>>(self, column: S) -> DBError {
+        self.with_context(ErrorContext { column: Some(column.into()), ..Default::default() })
+    }
+
+    pub fn at_row(self, row: RowOffset) -> DBError {
+        self.with_context(ErrorContext { row: Some(row), ..Default::default() })
+    }
+
+    pub fn in_file<P: AsRef<Path>>(self, path: P) -> DBError {
+        let path = path.as_ref().display().to_string();
+        self.with_context(ErrorContext { path: Some(path), ..Default::default() })
+    }
+
+    /// Position in parser input: the line (starting at 1) and byte offset
+    pub fn at_position(self, line: usize, byte: usize) -> DBError {
+        self.with_context(ErrorContext { line: Some(line), byte: Some(byte), ..Default::default() })
+    }
+
+    /// The error without its context, to match on the kind of failure
+    pub fn root(&self) -> &DBError {
+        match *self {
+            DBError::Context(ref inner, _) => inner,
+            ref e => e,
+        }
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match *self {
+            DBError::Context(_, ref context) => Some(&**context),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for DBError {
@@ -53,24 +191,56 @@ impl fmt::Display for DBError {
                 write!(f, "Unknown/Unexpected Type {}", t),
             DBError::AttributeMissing(ref attr) =>
                 write!(f, "Unknown Attribute {}", attr),
+            DBError::AttributeUnknown(ref attr, ref similar) if similar.is_empty() =>
+                write!(f, "Unknown Attribute {}", attr),
+            DBError::AttributeUnknown(ref attr, ref similar) =>
+                write!(f, "Unknown Attribute {}, did you mean {}?", attr, similar.join(", ")),
             DBError::AttributeNullability(ref attr) =>
                 write!(f, "Attribute Not Nullable {}", attr),
             DBError::AttributeType(ref attr) =>
                 write!(f, "Attribute Type Mismatch {}", attr),
             DBError::AttributeDuplicate(ref attr) =>
                 write!(f, "Duplicate Attribute name {} in output schema", attr),
+            DBError::ColumnEncoding(ref attr) =>
+                write!(f, "Unexpected encoding of Attribute {} data", attr),
             DBError::ExpressionInputType(ref str) =>
                 write!(f, "Invalid expression input type: {}", str),
             DBError::ExpressionInputCount(ref str) =>
                 write!(f, "Invalid expression input count: {}", str),
             DBError::ExpressionNotCost =>
                 write!(f, "Expression expected to be const"),
+            DBError::ValueParse(ref str) =>
+                write!(f, "Unable to parse value: {}", str),
+            DBError::ValueOutOfRange(ref str) =>
+                write!(f, "Value out of range: {}", str),
+            DBError::InvalidUtf8 { row, ref column } =>
+                write!(f, "Invalid UTF-8 in row {} of TEXT attribute {}", row, column),
             DBError::RowOutOfBounds =>
                 write!(f, "Row out of bounds"),
             DBError::Memory(ref e) =>
                 write!(f, "Memory allocation failure: {}", e),
             DBError::MemoryLimit =>
                 write!(f, "Memory allocation failure due to policy limit"),
+            DBError::Unsupported(ref str) =>
+                write!(f, "Unsupported: {}", str),
+            DBError::QueryInvalid(ref str) =>
+                write!(f, "Invalid query: {}", str),
+            DBError::TableMissing(ref table) =>
+                write!(f, "Unknown Table {}", table),
+            DBError::FunctionMissing(ref func) =>
+                write!(f, "Unknown Function {}", func),
+            DBError::RetriesExhausted(ref history) =>
+                write!(f, "Failed after {} attempts: {}", history.len(), history.join("; ")),
+            DBError::IndexStale(ref str) =>
+                write!(f, "Stale index: {}", str),
+            DBError::InputLimit(ref str) =>
+                write!(f, "Input limit exceeded: {}", str),
+            DBError::CursorMissing(ref token) =>
+                write!(f, "Unknown or expired cursor {}", token),
+            DBError::Cancelled(ref str) =>
+                write!(f, "Query cancelled: {}", str),
+            DBError::Context(ref inner, ref context) =>
+                write!(f, "{} (at {})", inner, context),
         }
     }
 }
@@ -81,3 +251,46 @@ impl fmt::Debug for DBError {
         fmt::Display::fmt(self, f)
     }
 }
+
+impl Error for DBError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            DBError::IO(ref e) => Some(e),
+            DBError::Context(ref inner, _) => Some(&**inner),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    // Context closest to the failure wins, the sources lead to the IO error
+    #[test]
+    fn error_context() {
+        let io = IOError::new(ErrorKind::UnexpectedEof, "short read");
+        let err = DBError::IO(io).at_position(3, 40).in_file("t.csv")
+            .in_operator("Scan").in_operator("Limit");
+
+        assert!(err.context() == Some(&ErrorContext {
+            operator: Some("Scan".to_string()),
+            path: Some("t.csv".to_string()),
+            line: Some(3),
+            byte: Some(40),
+            ..Default::default()
+        }));
+        assert_eq!(err.to_string(),
+            "IO Error short read (at operator Scan, file t.csv, line 3, byte 40)");
+
+        match *err.root() {
+            DBError::IO(_) => (), // nop
+            ref e => assert!(false, "Unexpected error {}", e),
+        }
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "IO Error short read");
+        assert_eq!(source.source().unwrap().to_string(), "short read");
+        assert!(DBError::RowOutOfBounds.source().is_none());
+    }
+}
@@ -0,0 +1,323 @@
+//! Keyed pseudonymization of column values.
+//!
+//! The output only depends on the key and the input value, so datasets exported with the same key
+//! can still be joined and grouped on the pseudonymized values, while the original values can't be
+//! recovered without the key.
+
+use std::i32;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{NULL_VALUE, Type, Value};
+use ::util::copy_value::ValueSetter;
+use ::util::hmac::Hmac;
+
+/// Pseudonymization of the input values
+#[derive(Clone, PartialEq, Debug)]
+pub enum PseudonymFunc {
+    /// TEXT token of the same format: each ASCII letter is replaced by a letter of the same case,
+    /// each digit by a digit, other characters are kept.
+    Token,
+    /// Numeric value plus a noise in [-scale, scale] (rounded for integers, saturating at the type
+    /// bounds). The noise of equal values is equal.
+    Noise(f64),
+}
+
+/// `func(input)` keyed by a secret, NULL for NULL values
+pub struct PseudonymizeExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub key: Vec<u8>,
+    pub func: PseudonymFunc,
+}
+
+struct PseudonymizeBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    hmac: Hmac,
+    func: PseudonymFunc,
+}
+
+impl PseudonymFunc {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PseudonymFunc::Token    => "pseudonym",
+            PseudonymFunc::Noise(_) => "noise",
+        }
+    }
+}
+
+impl<'a> PseudonymizeExpr<'a> {
+    pub fn new<T, K>(input: T, key: K, func: PseudonymFunc) -> PseudonymizeExpr<'a>
+        where T: Expr<'a> + 'a, K: Into<Vec<u8>>
+    {
+        PseudonymizeExpr { input: Box::new(input), key: key.into(), func: func }
+    }
+
+    pub fn token<T, K>(input: T, key: K) -> PseudonymizeExpr<'a>
+        where T: Expr<'a> + 'a, K: Into<Vec<u8>>
+    {
+        PseudonymizeExpr::new(input, key, PseudonymFunc::Token)
+    }
+
+    pub fn noise<T, K>(input: T, key: K, scale: f64) -> PseudonymizeExpr<'a>
+        where T: Expr<'a> + 'a, K: Into<Vec<u8>>
+    {
+        PseudonymizeExpr::new(input, key, PseudonymFunc::Noise(scale))
+    }
+}
+
+/// Format preserving token of the text, the HMAC of the text and a block counter is the stream of
+/// replacement bytes
+fn token(hmac: &Hmac, text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut stream = Vec::new();
+    let mut block = 0u64;
+
+    for (i, c) in text.chars().enumerate() {
+        if i >= stream.len() {
+            let counter: Vec<u8> = (0 .. 8).map(|b| (block >> (56 - 8 * b)) as u8).collect();
+            stream.extend_from_slice(&hmac.sign(&[text.as_bytes(), &counter]));
+            block += 1;
+        }
+
+        let r = stream[i];
+        out.push(match c {
+            'a' ..= 'z' => (b'a' + r % 26) as char,
+            'A' ..= 'Z' => (b'A' + r % 26) as char,
+            '0' ..= '9' => (b'0' + r % 10) as char,
+            _           => c,
+        });
+    }
+
+    out
+}
+
+/// Noise in [-scale, scale] of the value bytes
+fn noise(hmac: &Hmac, bytes: u64, scale: f64) -> f64 {
+    let bytes: Vec<u8> = (0 .. 8).map(|b| (bytes >> (56 - 8 * b)) as u8).collect();
+    let digest = hmac.sign(&[&bytes]);
+    let seed = digest[.. 8].iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+
+    // Uniform in [0, 1) from the 53 high bits
+    let unit = (seed >> 11) as f64 / (1u64 << 53) as f64;
+    (unit * 2.0 - 1.0) * scale
+}
+
+fn add_noise<'v>(hmac: &Hmac, value: Value<'v>, scale: f64) -> Value<'v> {
+    let round = |bytes: u64| noise(hmac, bytes, scale).round() as i64;
+
+    match value {
+        Value::INT32(v) => {
+            let out = v as i64 + round(v as i64 as u64);
+            Value::INT32(out.max(i32::MIN as i64).min(i32::MAX as i64) as i32)
+        },
+        Value::INT64(v) => Value::INT64(v.saturating_add(round(v as u64))),
+        Value::UINT32(v) => {
+            let out = (v as i64 + round(v as u64)).max(0).min(u32::max_value() as i64);
+            Value::UINT32(out as u32)
+        },
+        Value::UINT64(v) => {
+            let n = round(v);
+            Value::UINT64(if n < 0 { v.saturating_sub(n.wrapping_neg() as u64) }
+                          else { v.saturating_add(n as u64) })
+        },
+        Value::FLOAT32(v) => Value::FLOAT32(v + noise(hmac, (v as f64).to_bits(), scale) as f32),
+        Value::FLOAT64(v) => Value::FLOAT64(v + noise(hmac, v.to_bits(), scale)),
+        other => other,
+    }
+}
+
+impl<'b> Expr<'b> for PseudonymizeExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+
+        let schema = {
+            let attr = bound_attribute(&*input)?;
+            let valid = match self.func {
+                PseudonymFunc::Token    => attr.dtype == Type::TEXT,
+                PseudonymFunc::Noise(_) => match attr.dtype {
+                    Type::UINT32 | Type::UINT64 | Type::INT32 | Type::INT64 | Type::FLOAT32
+                        | Type::FLOAT64 => true,
+                    _ => false,
+                },
+            };
+
+            if !valid {
+                return Err(DBError::ExpressionInputType(
+                    format!("{} of {} {}", self.func.name(), attr.dtype, attr.name)))
+            }
+
+            Schema::from_attr(Attribute {
+                name: format!("{}({})", self.func.name(), attr.name),
+                nullable: attr.nullable,
+                dtype: attr.dtype.clone(),
+            })
+        };
+
+        if let PseudonymFunc::Noise(scale) = self.func {
+            if !scale.is_finite() || scale < 0.0 {
+                return Err(DBError::ValueOutOfRange(format!("noise scale {}", scale)))
+            }
+        }
+
+        Ok(Box::new(PseudonymizeBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            hmac: Hmac::new(&self.key),
+            func: self.func.clone(),
+        }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for PseudonymizeBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(self.func.name())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                match (column_value(src, row)?, &self.func) {
+                    (Value::NULL, _) => {
+                        NULL_VALUE.set_row(dst, row)?;
+                        continue
+                    },
+                    (Value::TEXT(text), &PseudonymFunc::Token) =>
+                        token(&self.hmac, text).set_row(dst, row)?,
+                    (value, &PseudonymFunc::Noise(scale)) =>
+                        add_noise(&self.hmac, value, scale).set_row(dst, row)?,
+                    (value, _) => value.set_row(dst, row)?,
+                }
+
+                if nullable {
+                    dst.nulls_mut()?[row] = 0;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "email".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "age".to_string(), nullable: false, dtype: Type::INT32},
+            Attribute{name: "score".to_string(), nullable: false, dtype: Type::FLOAT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("Jane.Doe-42@example.com").set(35i32).set(0.5f64)
+                .add_row().set_null(true).set(35i32).set(0.5f64)
+                .add_row().set("Jane.Doe-42@example.com").set(i32::MAX).set(-2.0f64)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    fn eval<'e, E: Expr<'e>>(block: &Block, expr: E) -> Vec<String> {
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(block, block.rows()).unwrap();
+
+        (0 .. block.rows()).map(|row| match column_value(out.column(0).unwrap(), row).unwrap() {
+            Value::TEXT(v)      => v.to_string(),
+            Value::INT32(v)     => v.to_string(),
+            Value::FLOAT64(v)   => v.to_string(),
+            _                   => String::from("NULL"),
+        }).collect()
+    }
+
+    // Tokens keep the format, equal values (with the same key) get equal tokens
+    #[test]
+    fn format_preserving_token() {
+        let block = make_block();
+        let tokens = eval(&block, PseudonymizeExpr::token(ColumnExpr::named("email"), "secret"));
+
+        assert_eq!(tokens[1], "NULL");
+        assert_eq!(tokens[0], tokens[2]);
+        assert!(tokens[0] != "Jane.Doe-42@example.com");
+
+        let original = "Jane.Doe-42@example.com";
+        assert_eq!(tokens[0].len(), original.len());
+        for (t, o) in tokens[0].chars().zip(original.chars()) {
+            assert!(t.is_ascii_uppercase() == o.is_ascii_uppercase(), "{} for {}", t, o);
+            assert!(t.is_ascii_lowercase() == o.is_ascii_lowercase(), "{} for {}", t, o);
+            assert!(t.is_ascii_digit() == o.is_ascii_digit(), "{} for {}", t, o);
+            assert!(o.is_ascii_alphanumeric() || t == o);
+        }
+
+        let other = eval(&block, PseudonymizeExpr::token(ColumnExpr::named("email"), "other"));
+        assert!(other[0] != tokens[0]);
+
+        match PseudonymizeExpr::token(ColumnExpr::named("age"), "secret")
+            .bind(&allocator::GLOBAL, block.schema())
+        {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Noise is bounded by the scale, the same for equal values, integers saturate
+    #[test]
+    fn numeric_noise() {
+        let block = make_block();
+
+        let ages = eval(&block, PseudonymizeExpr::noise(ColumnExpr::named("age"), "secret", 5.0));
+        assert_eq!(ages[0], ages[1]);
+        let age: i32 = ages[0].parse().unwrap();
+        assert!(age >= 30 && age <= 40, "age {}", age);
+        assert!(ages[2].parse::<i32>().unwrap() > i32::MAX - 6);
+
+        let scores = eval(&block,
+            PseudonymizeExpr::noise(ColumnExpr::named("score"), "secret", 0.1));
+        let score: f64 = scores[0].parse().unwrap();
+        assert!(score != 0.5 && (score - 0.5).abs() <= 0.1, "score {}", score);
+        assert_eq!(scores[0], scores[1]);
+
+        match PseudonymizeExpr::noise(ColumnExpr::named("email"), "secret", 1.0)
+            .bind(&allocator::GLOBAL, block.schema())
+        {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Approximate aggregate functions.
+//!
+//! The aggregates keep a fixed size sketch (see `util::sketch`) as the state of each group, so
+//! high cardinality inputs don't need a hash set of their values and partial states merge.
+
+use ::error::DBError;
+use ::expression::udf::{Aggregate, FunctionRegistry};
+use ::types::{Type, Value};
+use ::util::sketch::{HyperLogLog, QuantileSketch};
+
+/// `HyperLogLog` precision of `ApproxCountDistinct::default()`, about 1.6% standard error
+pub const DEFAULT_PRECISION: u8 = 12;
+
+/// Relative accuracy of `ApproxPercentile` functions registered by `register_approx`
+pub const DEFAULT_ACCURACY: f64 = 0.01;
+
+/// INT64 estimated number of distinct non NULL values, of any type
+pub struct ApproxCountDistinct {
+    empty: HyperLogLog,
+}
+
+/// FLOAT64 estimated percentile of numeric values, NULL for groups without values
+pub struct ApproxPercentile {
+    pub percentile: f64,
+    empty: QuantileSketch,
+}
+
+impl ApproxCountDistinct {
+    pub fn new(precision: u8) -> Result<ApproxCountDistinct, DBError> {
+        Ok(ApproxCountDistinct { empty: HyperLogLog::new(precision)? })
+    }
+}
+
+impl Default for ApproxCountDistinct {
+    fn default() -> ApproxCountDistinct {
+        ApproxCountDistinct::new(DEFAULT_PRECISION).unwrap()
+    }
+}
+
+impl ApproxPercentile {
+    /// Value at `percentile` (0 .. 1) within the relative `accuracy`
+    pub fn new(percentile: f64, accuracy: f64) -> Result<ApproxPercentile, DBError> {
+        if !(percentile >= 0.0 && percentile <= 1.0) {
+            return Err(DBError::ValueOutOfRange(
+                format!("Percentile {} not in 0 .. 1", percentile)))
+        }
+
+        Ok(ApproxPercentile { percentile: percentile, empty: QuantileSketch::new(accuracy)? })
+    }
+}
+
+/// Register `approx_count_distinct`, `approx_median`, `approx_p90` and `approx_p99`
+pub fn register_approx(registry: &mut FunctionRegistry) {
+    registry.register_aggregate("approx_count_distinct", ApproxCountDistinct::default());
+
+    let percentiles = [("approx_median", 0.5), ("approx_p90", 0.9), ("approx_p99", 0.99)];
+    for &(name, percentile) in &percentiles {
+        let func = ApproxPercentile::new(percentile, DEFAULT_ACCURACY).unwrap();
+        registry.register_aggregate(name, func);
+    }
+}
+
+impl Aggregate for ApproxCountDistinct {
+    type State = HyperLogLog;
+
+    fn output(&self, _input: &Type) -> Result<Type, DBError> {
+        Ok(Type::INT64)
+    }
+
+    fn init(&self) -> HyperLogLog {
+        self.empty.clone()
+    }
+
+    fn update(&self, state: &mut HyperLogLog, value: &Value) -> Result<(), DBError> {
+        state.insert(value);
+        Ok(())
+    }
+
+    fn merge(&self, state: &mut HyperLogLog, other: HyperLogLog) -> Result<(), DBError> {
+        state.merge(&other)
+    }
+
+    fn finalize<'s>(&self, state: &'s HyperLogLog) -> Result<Value<'s>, DBError> {
+        Ok(Value::INT64(state.estimate() as i64))
+    }
+}
+
+impl Aggregate for ApproxPercentile {
+    type State = QuantileSketch;
+
+    fn output(&self, input: &Type) -> Result<Type, DBError> {
+        match *input {
+            Type::UINT32 | Type::UINT64 | Type::INT32 | Type::INT64 | Type::FLOAT32
+                | Type::FLOAT64 => Ok(Type::FLOAT64),
+            _ => Err(DBError::ExpressionInputType(format!("percentile of {}", input))),
+        }
+    }
+
+    fn init(&self) -> QuantileSketch {
+        self.empty.clone()
+    }
+
+    fn update(&self, state: &mut QuantileSketch, value: &Value) -> Result<(), DBError> {
+        match *value {
+            Value::UINT32(v)    => state.insert(v as f64),
+            Value::UINT64(v)    => state.insert(v as f64),
+            Value::INT32(v)     => state.insert(v as f64),
+            Value::INT64(v)     => state.insert(v as f64),
+            Value::FLOAT32(v)   => state.insert(v as f64),
+            Value::FLOAT64(v)   => state.insert(v),
+            _                   => (),
+        }
+        Ok(())
+    }
+
+    fn merge(&self, state: &mut QuantileSketch, other: QuantileSketch) -> Result<(), DBError> {
+        state.merge(&other)
+    }
+
+    fn finalize<'s>(&self, state: &'s QuantileSketch) -> Result<Value<'s>, DBError> {
+        Ok(state.quantile(self.percentile).map_or(Value::NULL, Value::FLOAT64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View, column_value};
+    use ::expression::Expr;
+    use ::expression::column::ColumnExpr;
+    use ::schema::{Attribute, Schema};
+    use ::table::Table;
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "v".to_string(), nullable: true, dtype: Type::FLOAT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        for row in 0 .. 2000 {
+            table.add_row().unwrap();
+            table.set(0, row, (row % 500) as i64).unwrap();
+            if row % 4 == 0 {
+                table.set_null(1, row, true).unwrap();
+            } else {
+                table.set(1, row, row as f64).unwrap();
+            }
+        }
+
+        table.take().unwrap()
+    }
+
+    fn aggregate(block: &Block, name: &str, column: &str) -> f64 {
+        let mut registry = FunctionRegistry::new();
+        register_approx(&mut registry);
+
+        let expr = registry.call_aggregate(name, ColumnExpr::named(column)).unwrap();
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(block, block.rows()).unwrap();
+
+        match column_value(out.column(0).unwrap(), 0).unwrap() {
+            Value::INT64(v) => v as f64,
+            Value::FLOAT64(v) => v,
+            _ => panic!("Expected a number"),
+        }
+    }
+
+    // Registered aggregates estimate the distinct count and percentiles of non NULL values
+    #[test]
+    fn approx_aggregates() {
+        let block = make_block();
+
+        let distinct = aggregate(&block, "approx_count_distinct", "id");
+        assert!((distinct - 500.0).abs() < 25.0, "distinct {}", distinct);
+
+        // Non NULL values are the rows not divisible by 4, the median is about 1000
+        let median = aggregate(&block, "approx_median", "v");
+        assert!((median - 1000.0).abs() < 25.0, "median {}", median);
+
+        let p99 = aggregate(&block, "approx_p99", "v");
+        assert!((p99 - 1980.0).abs() < 40.0, "p99 {}", p99);
+
+        let func = ApproxCountDistinct::default();
+        let mut state = func.init();
+        let mut other = func.init();
+        for v in 0 .. 100 {
+            func.update(&mut state, &Value::INT64(v)).unwrap();
+            func.update(&mut other, &Value::INT64(v + 50)).unwrap();
+        }
+        Aggregate::merge(&func, &mut state, other).unwrap();
+        match func.finalize(&state).unwrap() {
+            Value::INT64(v) => assert!((v - 150).abs() <= 3, "distinct {}", v),
+            _ => assert!(false, "Expected INT64"),
+        }
+
+        assert!(ApproxPercentile::new(1.5, DEFAULT_ACCURACY).is_err());
+        assert!(ApproxCountDistinct::new(30).is_err());
+        assert!(ApproxPercentile::new(0.5, 0.01).unwrap().output(&Type::TEXT).is_err());
+    }
+}
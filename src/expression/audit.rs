@@ -0,0 +1,151 @@
+//! Debug evaluation mode that explains where NULLs in an expression output come from.
+//!
+//! The bound expression tree is evaluated node by node. A NULL output row is attributed to the
+//! deepest expression whose output for that row is NULL while none of its inputs are, usually an
+//! input column. Each node is re-evaluated for its own audit so this is considerably slower than
+//! plain evaluation and only meant for diagnosing unexpected NULLs.
+
+use ::block::{Block, View, column_nulls};
+use ::error::DBError;
+use ::expression::BoundExpr;
+use ::row::RowOffset;
+
+/// Origin of a single NULL in the audited expression output
+pub struct NullOrigin {
+    pub row: RowOffset,
+    /// Description of the expression (or input column) that introduced the NULL
+    pub introduced_by: String,
+    /// Expressions the NULL propagated through, from the root expression down to the expression
+    /// that introduced it
+    pub path: Vec<String>,
+}
+
+/// Result of an audited evaluation
+pub struct NullAudit {
+    /// Each NULL of the output, in row order
+    pub origins: Vec<NullOrigin>,
+}
+
+impl NullAudit {
+    /// Count of output NULLs introduced by each expression, in order of first appearance
+    pub fn summary(&self) -> Vec<(String, usize)> {
+        let mut out: Vec<(String, usize)> = Vec::new();
+
+        for origin in &self.origins {
+            let pos = out.iter().position(|e| e.0 == origin.introduced_by);
+            match pos {
+                Some(pos) => out[pos].1 += 1,
+                None => out.push((origin.introduced_by.clone(), 1)),
+            }
+        }
+
+        out
+    }
+}
+
+/// Per row NULL path (root to origin) for one node of the tree. `None` for rows that are not NULL.
+type NullPaths = Vec<Option<Vec<String>>>;
+
+fn audit_node<'alloc, 'v>(expr: &BoundExpr<'alloc>, view: &'v View<'v>, rows: RowOffset)
+    -> Result<(Block<'alloc>, NullPaths), DBError>
+{
+    let mut children = Vec::new();
+    for child in expr.children() {
+        children.push(audit_node(child, view, rows)?.1);
+    }
+
+    let out = expr.evaluate(view, rows)?;
+    let mut paths: NullPaths = Vec::with_capacity(rows);
+
+    {
+        let nulls = out.column(0)
+            .ok_or(DBError::ExpressionInputCount(String::from("0 != 1")))
+            .map(|c| column_nulls(c))?;
+
+        for row in 0 .. rows {
+            let null = nulls.map_or(false, |n| n[row] != 0);
+
+            if !null {
+                paths.push(None);
+                continue
+            }
+
+            let mut path = vec![expr.describe()];
+            if let Some(child_path) = children.iter().filter_map(|c| c[row].as_ref()).next() {
+                path.extend(child_path.iter().cloned());
+            }
+
+            paths.push(Some(path));
+        }
+    }
+
+    Ok((out, paths))
+}
+
+/// Evaluate a bound expression, recording the origin of each NULL in the output.
+pub fn evaluate_audited<'alloc, 'v>(expr: &BoundExpr<'alloc>, view: &'v View<'v>, rows: RowOffset)
+    -> Result<(Block<'alloc>, NullAudit), DBError>
+{
+    let (out, paths) = audit_node(expr, view, rows)?;
+
+    let origins = paths.into_iter()
+        .enumerate()
+        .filter_map(|(row, path)| path.map(|p| NullOrigin {
+            row: row,
+            introduced_by: p.last().unwrap().clone(),
+            path: p,
+        }))
+        .collect();
+
+    Ok((out, NullAudit { origins: origins }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::Expr;
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::schema::{Attribute, Schema};
+    use ::table::{Table, TableAppender};
+    use ::types::*;
+
+    // NULLs from either side of EQUALS are traced back to the input column (through the cast)
+    #[test]
+    fn trace_input_nulls() {
+        let block = {
+            let attrs = vec![
+                Attribute{name: "a".to_string(), nullable: true, dtype: Type::UINT32},
+                Attribute{name: "b".to_string(), nullable: true, dtype: Type::UINT64},
+            ];
+
+            let schema = Schema::from_vec(attrs).unwrap();
+            let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+            {
+                let status = TableAppender::new(&mut table)
+                    .add_row().set(1 as u32).set(1 as u64)
+                    .add_row().set_null(true).set(1 as u64)
+                    .add_row().set(1 as u32).set_null(true)
+                    .done();
+
+                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            }
+
+            table.take().unwrap()
+        };
+
+        let expr = EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b"));
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let (_, audit) = evaluate_audited(&*bound, &block, block.rows()).unwrap();
+
+        assert_eq!(audit.origins.len(), 2);
+        assert_eq!(audit.origins[0].row, 1);
+        assert_eq!(audit.origins[0].introduced_by, "column a");
+        assert_eq!(audit.origins[0].path, vec!["EQUALS", "CAST(UINT32 AS UINT64)", "column a"]);
+        assert_eq!(audit.origins[1].row, 2);
+        assert_eq!(audit.origins[1].introduced_by, "column b");
+        assert_eq!(audit.summary(), vec![("column a".to_string(), 1), ("column b".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Boolean, Type, Value};
+use ::util::bloom::BloomFilter;
+
+/// True if the input value may be in the bloom filter, false if it's definitely not (or NULL).
+///
+/// This is the hook for pushing a join build side filter into the probe side scan, as the scan
+/// predicate.
+pub struct BloomFilterExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub filter: Arc<BloomFilter>,
+}
+
+struct BloomFilterBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    filter: Arc<BloomFilter>,
+}
+
+impl<'a> BloomFilterExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, filter: Arc<BloomFilter>) -> BloomFilterExpr<'a> {
+        BloomFilterExpr { input: Box::new(input), filter: filter }
+    }
+}
+
+impl<'b> Expr<'b> for BloomFilterExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+
+        let schema = {
+            let attr = bound_attribute(&*input)?;
+            Schema::from_attr(Attribute {
+                name: attr.name.clone(),
+                nullable: false,
+                dtype: Type::BOOLEAN,
+            })
+        };
+
+        Ok(Box::new(BloomFilterBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            filter: self.filter.clone(),
+        }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for BloomFilterBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("bloom filter ({} bits)", self.filter.size())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let dst = out.column_mut(0).unwrap().rows_mut::<Boolean>()?;
+
+            for row in 0 .. rows {
+                let value = column_value(src, row)?;
+                dst[row] = value != Value::NULL && self.filter.contains(&value);
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::operation::{CursorChunk, Operation, ScanView, DEFAULT_CURSOR_FETCH};
+    use ::table::{Table, TableAppender};
+    use ::types::UInt32;
+
+    // Probe side scan only returns the rows with keys from the build side
+    #[test]
+    fn bloom_filter_scan() {
+        let schema = Schema::make_one_attr("key", true, Type::UINT32);
+
+        let build = {
+            let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+            {
+                let status = TableAppender::new(&mut table)
+                    .add_row().set(3 as u32)
+                    .add_row().set(5 as u32)
+                    .add_row().set_null(true)
+                    .done();
+
+                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            }
+            table.take().unwrap()
+        };
+
+        let probe = {
+            let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+            {
+                let mut appender = TableAppender::new(&mut table);
+                for key in 0 .. 100 {
+                    appender = appender.add_row().set(key as u32);
+                }
+                appender = appender.add_row().set_null(true);
+
+                let status = appender.done();
+                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            }
+            table.take().unwrap()
+        };
+
+        let filter = BloomFilter::from_column(build.column(0).unwrap(), build.rows(), 0.001)
+            .unwrap();
+        let expr = BloomFilterExpr::new(ColumnExpr::named("key"), Arc::new(filter));
+        let scan = ScanView::new(&probe, None).with_predicate(expr);
+        let mut cursor = scan.bind(&allocator::GLOBAL).unwrap();
+
+        let mut keys = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            keys.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert!(keys.contains(&3) && keys.contains(&5));
+        assert!(keys.len() < 10, "Too many false positives {:?}", keys);
+    }
+}
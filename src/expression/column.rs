@@ -0,0 +1,98 @@
+use ::allocator::Allocator;
+use ::block::{Block, View};
+use ::error::DBError;
+use ::expression::*;
+use ::expression::field::FieldExpr;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::util::copy_value::copy_column;
+
+/// How the input column is referenced
+enum ColumnRef {
+    /// By position in the input schema
+    POS(usize),
+    /// By attribute name in the input schema
+    NAME(String),
+}
+
+/// Expression producing a copy of an input column.
+///
+/// The column reference is resolved against the input schema when the expression is bound. Names
+/// of the form "column.field" that aren't input attributes reference STRUCT column fields.
+pub struct ColumnExpr {
+    src: ColumnRef,
+}
+
+struct ColumnBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    pos: usize,
+}
+
+impl ColumnExpr {
+    /// Reference input column by name
+    pub fn named<S: Into<String>>(name: S) -> ColumnExpr {
+        ColumnExpr { src: ColumnRef::NAME(name.into()) }
+    }
+
+    /// Reference input column by position
+    pub fn position(pos: usize) -> ColumnExpr {
+        ColumnExpr { src: ColumnRef::POS(pos) }
+    }
+}
+
+impl<'b> Expr<'b> for ColumnExpr {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let pos = match self.src {
+            ColumnRef::POS(pos) => { input_schema.get(pos)?; pos },
+            ColumnRef::NAME(ref name)
+                if input_schema.exists(name).is_none() && name.contains('.') =>
+                    return bind_field_path(alloc, input_schema, name),
+            ColumnRef::NAME(ref name) => input_schema.exists_ok(name.as_str())?,
+        };
+
+        let schema = Schema::from_attr(input_schema[pos].clone());
+        Ok(Box::new(ColumnBound { alloc: alloc, schema: schema, pos: pos }))
+    }
+}
+
+/// Bind "column.field.field" as a chain of STRUCT field accesses
+fn bind_field_path<'a: 'b, 'b>(alloc: &'a Allocator, input_schema: &Schema, path: &str)
+    -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+{
+    let mut parts = path.split('.');
+    let mut expr: Box<Expr<'b> + 'b> = Box::new(ColumnExpr::named(parts.next().unwrap()));
+
+    for field in parts {
+        expr = Box::new(FieldExpr { input: expr, field: String::from(field) });
+    }
+
+    expr.bind(alloc, input_schema)
+}
+
+impl<'alloc> BoundExpr<'alloc> for ColumnBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("column {}", self.schema[0].name)
+    }
+
+    fn input_positions(&self) -> Vec<usize> {
+        vec![self.pos]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let src = view.column(self.pos)
+            .ok_or(DBError::make_column_unknown_pos(self.pos))?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        copy_column(src, out.column_mut(0).unwrap(), rows)?;
+        Ok(out)
+    }
+}
@@ -1,46 +1,471 @@
-use std::cmp::Eq;
 use std::marker::PhantomData;
 
-use ::expression::*;
+use ::allocator::Allocator;
+use ::block::{Block, View, column_nulls, column_row_data};
 use ::error::DBError;
-use ::types::ValueInfo;
+use ::expression::*;
+use ::expression::convert::coerce;
+use ::kernels::{CompareOp, NumericKernels};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
 
+/// `lhs = rhs`, see `CompareExpr`
 pub struct EqaulsExpr<'a> {
     pub lhs: Box<Expr<'a> + 'a>,
     pub rhs: Box<Expr<'a> + 'a>,
 }
 
-struct EqualsBound<'a, T: 'a + ValueInfo> {
+/// `lhs op rhs`. Numeric values are ordered, other scalar types can only be compared with EQ and
+/// NE.
+///
+/// Numeric comparisons with a constant (see `Expr::is_constant()`) compare the whole column at
+/// once with the `kernels`.
+pub struct CompareExpr<'a> {
+    pub lhs: Box<Expr<'a> + 'a>,
+    pub op: CompareOp,
+    pub rhs: Box<Expr<'a> + 'a>,
+}
+
+/// EQ or NE comparison of scalar values
+struct EqualsBound<'a, 'e, T: ValueInfo> {
     alloc: &'a Allocator,
     schema: Schema, // TODO: Can this just be a static?
-    phantom: PhantomData<&'a T>,
+    lhs: Box<BoundExpr<'a> + 'e>,
+    rhs: Box<BoundExpr<'a> + 'e>,
+    equal: bool,
+    phantom: PhantomData<T>,
+}
+
+/// Comparison of numeric values
+struct CompareBound<'a, 'e, T: ValueInfo> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    lhs: Box<BoundExpr<'a> + 'e>,
+    op: CompareOp,
+    rhs: Box<BoundExpr<'a> + 'e>,
+    phantom: PhantomData<T>,
+}
+
+/// Comparison of numeric values with a constant, `values op constant`
+struct CompareConstBound<'a, 'e, T: ValueInfo> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    values: Box<BoundExpr<'a> + 'e>,
+    op: CompareOp,
+    constant: Box<BoundExpr<'a> + 'e>,
+    phantom: PhantomData<T>,
 }
 
 impl<'a> EqaulsExpr<'a> {
     pub fn new<T: Expr<'a> + 'a>(lhs: T, rhs: T) -> EqaulsExpr<'a> {
-        EqaulsExpr { lhs: box lhs, rhs: box rhs }
+        EqaulsExpr { lhs: Box::new(lhs), rhs: Box::new(rhs) }
+    }
+}
+
+impl<'a> CompareExpr<'a> {
+    pub fn new<L, R>(lhs: L, op: CompareOp, rhs: R) -> CompareExpr<'a>
+        where L: Expr<'a> + 'a, R: Expr<'a> + 'a
+    {
+        CompareExpr { lhs: Box::new(lhs), op: op, rhs: Box::new(rhs) }
+    }
+}
+
+fn op_name(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::EQ => "EQUALS",
+        CompareOp::NE => "NOT EQUALS",
+        CompareOp::LT => "LESS",
+        CompareOp::LE => "LESS OR EQUALS",
+        CompareOp::GT => "GREATER",
+        CompareOp::GE => "GREATER OR EQUALS",
     }
 }
 
+/// Both sides are converted to their common supertype before comparison. The result is NULL if
+/// either side is NULL.
+fn bind_compare<'a: 'b, 'b>(alloc: &'a Allocator, input_schema: &Schema, lhs: &Expr<'b>,
+                            op: CompareOp, rhs: &Expr<'b>)
+    -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+{
+    let (lhs_constant, rhs_constant) = (lhs.is_constant(), rhs.is_constant());
+    let lhs = lhs.bind(alloc, input_schema)?;
+    let rhs = rhs.bind(alloc, input_schema)?;
+    let name = op_name(op);
+
+    let (schema, dtype) = {
+        let l = bound_attribute(&*lhs)?;
+        let r = bound_attribute(&*rhs)?;
+
+        let dtype = Type::common_supertype(&l.dtype, &r.dtype)
+            .ok_or(DBError::ExpressionInputType(
+                format!("{} cannot compare {} ({}) with {} ({})",
+                        name, l.name, l.dtype, r.name, r.dtype)))?;
+
+        let out = Attribute {
+            name: l.name.clone(),
+            nullable: l.nullable || r.nullable,
+            dtype: Type::BOOLEAN,
+        };
+
+        (Schema::from_attr(out), dtype)
+    };
+
+    let lhs = coerce(alloc, lhs, &dtype)?;
+    let rhs = coerce(alloc, rhs, &dtype)?;
+
+    let out: Box<BoundExpr<'a> + 'b> = dispatch_type!(dtype, numeric,
+        T => if rhs_constant && !lhs_constant {
+            Box::new(CompareConstBound::<T>::new(alloc, schema, lhs, op, rhs))
+        } else if lhs_constant && !rhs_constant {
+            Box::new(CompareConstBound::<T>::new(alloc, schema, rhs, op.swapped(), lhs))
+        } else {
+            Box::new(CompareBound::<T>::new(alloc, schema, lhs, op, rhs))
+        },
+        _ => match op {
+            CompareOp::EQ | CompareOp::NE => dispatch_type!(dtype, scalar,
+                T => Box::new(EqualsBound::<T>::new(alloc, schema, lhs, op == CompareOp::EQ, rhs)),
+                _ => return Err(DBError::ExpressionInputType(
+                    format!("{} cannot compare {}", name, dtype)))),
+            _ => return Err(DBError::ExpressionInputType(
+                format!("{} cannot order {}", name, dtype))),
+        });
+
+    Ok(out)
+}
+
 impl<'b> Expr<'b> for EqaulsExpr<'b> {
+    /// Both sides are converted to their common supertype before comparison. The result is NULL
+    /// if either side is NULL.
     fn bind <'a: 'b> (&self, alloc: &'a Allocator, input_schema: &Schema) ->
-        Result <Box<BoundExpr<'a> + 'a>, DBError>
+        Result <Box<BoundExpr<'a> + 'b>, DBError>
     {
-        Err(DBError::Unknown)
+        bind_compare(alloc, input_schema, &*self.lhs, CompareOp::EQ, &*self.rhs)
+    }
+}
+
+impl<'b> Expr<'b> for CompareExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        bind_compare(alloc, input_schema, &*self.lhs, self.op, &*self.rhs)
+    }
+}
+
+impl<'a, 'e, T: ValueInfo> EqualsBound<'a, 'e, T> {
+    fn new(alloc: &'a Allocator, schema: Schema, lhs: Box<BoundExpr<'a> + 'e>, equal: bool,
+           rhs: Box<BoundExpr<'a> + 'e>) -> EqualsBound<'a, 'e, T>
+    {
+        EqualsBound {
+            alloc: alloc, schema: schema, lhs: lhs, rhs: rhs, equal: equal, phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'e, T: ValueInfo> CompareBound<'a, 'e, T> {
+    fn new(alloc: &'a Allocator, schema: Schema, lhs: Box<BoundExpr<'a> + 'e>, op: CompareOp,
+           rhs: Box<BoundExpr<'a> + 'e>) -> CompareBound<'a, 'e, T>
+    {
+        CompareBound {
+            alloc: alloc, schema: schema, lhs: lhs, op: op, rhs: rhs, phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'e, T: ValueInfo> CompareConstBound<'a, 'e, T> {
+    fn new(alloc: &'a Allocator, schema: Schema, values: Box<BoundExpr<'a> + 'e>, op: CompareOp,
+           constant: Box<BoundExpr<'a> + 'e>) -> CompareConstBound<'a, 'e, T>
+    {
+        CompareConstBound {
+            alloc: alloc,
+            schema: schema,
+            values: values,
+            op: op,
+            constant: constant,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'alloc, 'e, T: ValueInfo> BoundExpr<'alloc> for EqualsBound<'alloc, 'e, T>
+    where T::Store: StoreEq
+{
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(if self.equal { "EQUALS" } else { "NOT EQUALS" })
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.lhs, &*self.rhs]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let lhs = self.lhs.evaluate(view, rows)?;
+        let rhs = self.rhs.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let (l_col, r_col) = (lhs.column(0).unwrap(), rhs.column(0).unwrap());
+            let l = column_row_data::<T>(l_col)?;
+            let r = column_row_data::<T>(r_col)?;
+
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Boolean>()?;
+            if nullable {
+                binary_nulls(column_nulls(l_col), column_nulls(r_col), &mut dst.nulls[.. rows]);
+            }
+
+            for idx in 0 .. rows {
+                // Values of NULL rows are not initialized, don't compare them
+                let null = nullable && dst.nulls[idx] != 0;
+                dst.values[idx] = !null
+                    && l.values[idx].store_eq(&l.heap, &r.values[idx], &r.heap) == self.equal;
+            }
+        }
+
+        Ok(out)
     }
 }
 
-impl<'alloc, T: ValueInfo, V: Eq> BoundExpr<'alloc> for EqualsBound<'alloc, T>
-    where T: ValueInfo<Store=V>
+impl<'alloc, 'e, T: ValueInfo> BoundExpr<'alloc> for CompareBound<'alloc, 'e, T>
+    where T::Store: NumericKernels
 {
-    default fn schema(&self) -> &Schema {
+    fn schema(&self) -> &Schema {
         &self.schema
     }
 
+    fn describe(&self) -> String {
+        String::from(op_name(self.op))
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.lhs, &*self.rhs]
+    }
+
     fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let lhs = self.lhs.evaluate(view, rows)?;
+        let rhs = self.rhs.evaluate(view, rows)?;
+
         let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let (l_col, r_col) = (lhs.column(0).unwrap(), rhs.column(0).unwrap());
+            let l = column_row_data::<T>(l_col)?;
+            let r = column_row_data::<T>(r_col)?;
+
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Boolean>()?;
+            if nullable {
+                binary_nulls(column_nulls(l_col), column_nulls(r_col), &mut dst.nulls[.. rows]);
+            }
+
+            for idx in 0 .. rows {
+                let null = nullable && dst.nulls[idx] != 0;
+                dst.values[idx] = !null && self.op.test(&l.values[idx], &r.values[idx]);
+            }
+        }
 
         Ok(out)
     }
 }
 
+impl<'alloc, 'e, T: ValueInfo> BoundExpr<'alloc> for CompareConstBound<'alloc, 'e, T>
+    where T::Store: NumericKernels
+{
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(op_name(self.op))
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.values, &*self.constant]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let values = self.values.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+        if rows == 0 {
+            return Ok(out)
+        }
+
+        // The constant is the same for each row, evaluate a single one
+        let constant = self.constant.evaluate(view, 1)?;
+
+        {
+            let (v_col, c_col) = (values.column(0).unwrap(), constant.column(0).unwrap());
+            let v = column_row_data::<T>(v_col)?;
+            let c = column_row_data::<T>(c_col)?;
+
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Boolean>()?;
+
+            if column_nulls(c_col).map_or(false, |nulls| nulls[0] != 0) {
+                for idx in 0 .. rows {
+                    dst.values[idx] = false;
+                    dst.nulls[idx] = 1;
+                }
+            } else {
+                let (values, out) = (&v.values[.. rows], &mut dst.values[.. rows]);
+                NumericKernels::compare_const(values, self.op, c.values[0], out);
+
+                if nullable {
+                    binary_nulls(column_nulls(v_col), None, &mut dst.nulls[.. rows]);
+                    for idx in 0 .. rows {
+                        // Values of NULL rows are not initialized
+                        dst.values[idx] &= dst.nulls[idx] == 0;
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_value;
+    use ::expression::column::ColumnExpr;
+    use ::expression::constant::ConstantExpr;
+    use ::table::{Table, TableAppender};
+
+    fn make_block() -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: false, dtype: Type::UINT64},
+            Attribute{name: "c".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1 as u32).set(1 as u64).set("one")
+                .add_row().set(2 as u32).set(3 as u64).set("two")
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    // UINT32 = UINT64 comparison, UINT32 side gets implicitly promoted
+    #[test]
+    fn equals_implicit_cast() {
+        let block = make_block();
+        let expr = EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b"));
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+
+        assert!(bound.schema()[0].dtype == Type::BOOLEAN, "Bad output type");
+
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        let rows = column_row_data::<Boolean>(out.column(0).unwrap()).unwrap();
+        assert_eq!(&rows.values[0 .. 2], &[true, false]);
+    }
+
+    // Comparing an integer to TEXT is a bind time error
+    #[test]
+    fn equals_type_mismatch() {
+        let block = make_block();
+        let expr = EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("c"));
+
+        match expr.bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    #[test]
+    fn equals_unknown_column() {
+        let block = make_block();
+        let expr = EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("missing"));
+
+        match expr.bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Comparing with a constant on either side gives the same rows as comparing with a column of
+    // the constant, NULLs on either side are NULL
+    #[test]
+    fn compare_constant() {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: true, dtype: Type::INT32},
+            Attribute{name: "two".to_string(), nullable: false, dtype: Type::INT64},
+        ];
+        let mut table = Table::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap(), None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 40 {
+                appender = appender.add_row();
+                appender = if v % 9 == 4 { appender.set_null(true) } else { appender.set(v % 5) };
+                appender = appender.set(2i64);
+            }
+            assert!(appender.done().is_none());
+        }
+        let block = table.take().unwrap();
+
+        // Values and NULL flags of the comparison
+        let evaluate = |expr: &Expr| -> Vec<Option<bool>> {
+            let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+            let out = bound.evaluate(&block, block.rows()).unwrap();
+            (0 .. block.rows()).map(|row| match column_value(out.column(0).unwrap(), row) {
+                Ok(Value::BOOLEAN(v)) => Some(v),
+                _ => None,
+            }).collect()
+        };
+        let two = || ConstantExpr::new(&Value::INT64(2), Type::INT64).unwrap();
+
+        let ops = [CompareOp::EQ, CompareOp::NE, CompareOp::LT, CompareOp::LE, CompareOp::GT,
+                   CompareOp::GE];
+        for op in &ops {
+            let columns = evaluate(&CompareExpr::new(ColumnExpr::named("a"), *op,
+                                                     ColumnExpr::named("two")));
+            let expected: Vec<Option<bool>> = (0 .. 40)
+                .map(|v| if v % 9 == 4 { None } else { Some(op.test(&(v % 5), &2)) })
+                .collect();
+            assert_eq!(columns, expected, "{:?} of columns", op);
+
+            let constant = evaluate(&CompareExpr::new(ColumnExpr::named("a"), *op, two()));
+            assert_eq!(constant, expected, "{:?} with a constant", op);
+            let swapped = evaluate(&CompareExpr::new(two(), op.swapped(), ColumnExpr::named("a")));
+            assert_eq!(swapped, expected, "{:?} with a constant on the left", op);
+        }
+
+        let null = ConstantExpr::new(&Value::NULL, Type::INT64).unwrap();
+        let out = evaluate(&CompareExpr::new(ColumnExpr::named("two"), CompareOp::GE, null));
+        assert!(out.iter().all(Option::is_none));
+
+        // TEXT can't be ordered
+        let block = make_block();
+        let expr = CompareExpr::new(ColumnExpr::named("c"), CompareOp::LT, ColumnExpr::named("c"));
+        match expr.bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let expr = CompareExpr::new(ColumnExpr::named("c"), CompareOp::NE, ColumnExpr::named("c"));
+        let out = expr.bind(&allocator::GLOBAL, block.schema()).unwrap()
+            .evaluate(&block, block.rows()).unwrap();
+        assert!(column_value(out.column(0).unwrap(), 0).unwrap() == Value::BOOLEAN(false));
+    }
+}
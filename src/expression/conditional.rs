@@ -0,0 +1,538 @@
+use std::cmp::Ordering;
+
+use ::allocator::Allocator;
+use ::block::{Block, Column, RefColumn, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::expression::convert::coerce;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::stats::compare_values;
+use ::types::{Boolean, Type, Value};
+use ::util::copy_value::ValueSetter;
+
+/// `CASE [operand] WHEN .. THEN .. [ELSE ..] END`
+///
+/// The searched form (without an operand) picks the first branch whose BOOLEAN condition is true,
+/// NULL conditions are not true. The simple form picks the first branch whose value equals the
+/// operand, a NULL operand matches no branch. Rows without a matching branch are the ELSE value,
+/// or NULL.
+///
+/// Results are converted to their common supertype. Conditions aren't evaluated once every row
+/// has matched a branch, results only if some row picked their branch.
+pub struct CaseExpr<'a> {
+    pub operand: Option<Box<Expr<'a> + 'a>>,
+    pub branches: Vec<(Box<Expr<'a> + 'a>, Box<Expr<'a> + 'a>)>,
+    pub otherwise: Option<Box<Expr<'a> + 'a>>,
+}
+
+/// `input [NOT] IN (values..)`: true if the input equals one of the values. Otherwise NULL if the
+/// input or one of the values is NULL, false if not. `NOT IN` negates true and false.
+pub struct InListExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub values: Vec<Value<'a>>,
+    pub negated: bool,
+}
+
+struct CaseBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    operand: Option<Box<BoundExpr<'a> + 'e>>,
+    branches: Vec<(Box<BoundExpr<'a> + 'e>, Box<BoundExpr<'a> + 'e>)>,
+    otherwise: Option<Box<BoundExpr<'a> + 'e>>,
+}
+
+struct InListBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    /// The list values, as a column of the input type
+    list: Block<'a>,
+    has_null: bool,
+    negated: bool,
+}
+
+impl<'a> CaseExpr<'a> {
+    /// `CASE WHEN condition THEN ..`
+    pub fn searched() -> CaseExpr<'a> {
+        CaseExpr { operand: None, branches: Vec::new(), otherwise: None }
+    }
+
+    /// `CASE operand WHEN value THEN ..`
+    pub fn simple<T: Expr<'a> + 'a>(operand: T) -> CaseExpr<'a> {
+        CaseExpr { operand: Some(Box::new(operand)), branches: Vec::new(), otherwise: None }
+    }
+
+    pub fn when<W, T>(mut self, when: W, then: T) -> CaseExpr<'a>
+        where W: Expr<'a> + 'a, T: Expr<'a> + 'a
+    {
+        self.branches.push((Box::new(when), Box::new(then)));
+        self
+    }
+
+    pub fn otherwise<T: Expr<'a> + 'a>(mut self, otherwise: T) -> CaseExpr<'a> {
+        self.otherwise = Some(Box::new(otherwise));
+        self
+    }
+}
+
+impl<'a> InListExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, values: Vec<Value<'a>>) -> InListExpr<'a> {
+        InListExpr { input: Box::new(input), values: values, negated: false }
+    }
+
+    pub fn not_in<T: Expr<'a> + 'a>(input: T, values: Vec<Value<'a>>) -> InListExpr<'a> {
+        InListExpr { input: Box::new(input), values: values, negated: true }
+    }
+}
+
+fn case_error(msg: String) -> DBError {
+    DBError::ExpressionInputType(format!("CASE {}", msg))
+}
+
+/// Common supertype of the outputs of `exprs`
+fn common_type<'a>(exprs: &[&BoundExpr<'a>]) -> Result<Type, DBError> {
+    let mut out: Option<Type> = None;
+
+    for expr in exprs {
+        let attr = bound_attribute(*expr)?;
+        out = Some(match out {
+            None => attr.dtype.clone(),
+            Some(dtype) => Type::common_supertype(&dtype, &attr.dtype)
+                .ok_or_else(|| case_error(format!("cannot mix {} and {} ({})", dtype, attr.dtype,
+                                                  attr.name)))?,
+        });
+    }
+
+    out.ok_or_else(|| case_error(String::from("without branches")))
+}
+
+impl<'b> Expr<'b> for CaseExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let mut operand = match self.operand {
+            Some(ref operand) => Some(operand.bind(alloc, input_schema)?),
+            None => None,
+        };
+
+        let mut whens = Vec::with_capacity(self.branches.len());
+        let mut thens = Vec::with_capacity(self.branches.len());
+        for (when, then) in &self.branches {
+            whens.push(when.bind(alloc, input_schema)?);
+            thens.push(then.bind(alloc, input_schema)?);
+        }
+
+        let otherwise = match self.otherwise {
+            Some(ref otherwise) => Some(otherwise.bind(alloc, input_schema)?),
+            None => None,
+        };
+
+        // Simple form values are compared with the operand as their common supertype
+        if let Some(op) = operand.take() {
+            let dtype = {
+                let mut exprs: Vec<&BoundExpr<'a>> = vec![&*op];
+                exprs.extend(whens.iter().map(|w| &**w));
+                common_type(&exprs)?
+            };
+
+            operand = Some(coerce(alloc, op, &dtype)?);
+            whens = whens.into_iter()
+                .map(|w| coerce(alloc, w, &dtype))
+                .collect::<Result<Vec<_>, DBError>>()?;
+        } else {
+            for when in &whens {
+                let attr = bound_attribute(&**when)?;
+                if attr.dtype != Type::BOOLEAN {
+                    return Err(case_error(format!("condition {} is {}, not BOOLEAN", attr.name,
+                                                  attr.dtype)))
+                }
+            }
+        }
+
+        let (schema, dtype) = {
+            let mut exprs: Vec<&BoundExpr<'a>> = thens.iter().map(|t| &**t).collect();
+            exprs.extend(otherwise.iter().map(|o| &**o));
+            let dtype = common_type(&exprs)?;
+
+            let mut nullable = otherwise.is_none();
+            for expr in &exprs {
+                nullable |= bound_attribute(*expr)?.nullable;
+            }
+
+            let name = bound_attribute(exprs[0])?.name.clone();
+            (Schema::from_attr(Attribute { name: name, nullable: nullable, dtype: dtype.clone() }),
+             dtype)
+        };
+
+        let thens = thens.into_iter()
+            .map(|t| coerce(alloc, t, &dtype))
+            .collect::<Result<Vec<_>, DBError>>()?;
+        let otherwise = match otherwise {
+            Some(o) => Some(coerce(alloc, o, &dtype)?),
+            None => None,
+        };
+
+        Ok(Box::new(CaseBound {
+            alloc: alloc,
+            schema: schema,
+            operand: operand,
+            branches: whens.into_iter().zip(thens).collect(),
+            otherwise: otherwise,
+        }))
+    }
+}
+
+/// Copy the value of `row` from `src` into `dst`
+fn copy_value<'r>(src: &RefColumn<'r>, dst: &mut Column, nullable: bool, row: RowOffset)
+    -> Result<(), DBError>
+{
+    let value = column_value(src, row)?;
+    value.set_row(dst, row)?;
+
+    if nullable && !value.is_null() {
+        dst.nulls_mut()?[row] = 0;
+    }
+
+    Ok(())
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for CaseBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from("CASE")
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        let mut out: Vec<&BoundExpr<'alloc>> = self.operand.iter().map(|o| &**o).collect();
+        for (when, then) in &self.branches {
+            out.push(&**when);
+            out.push(&**then);
+        }
+        out.extend(self.otherwise.iter().map(|o| &**o));
+        out
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let operand = match self.operand {
+            Some(ref operand) => Some(operand.evaluate(view, rows)?),
+            None => None,
+        };
+
+        // Branch picked by each row
+        let mut picked: Vec<Option<usize>> = vec![None; rows];
+        let mut undecided = rows;
+
+        for (branch, (when, _)) in self.branches.iter().enumerate() {
+            if undecided == 0 {
+                break
+            }
+
+            let cond = when.evaluate(view, rows)?;
+            let cond = cond.column(0).unwrap();
+
+            for (row, pick) in picked.iter_mut().enumerate() {
+                if pick.is_some() {
+                    continue
+                }
+
+                let hit = match operand {
+                    Some(ref operand) => {
+                        let value = column_value(operand.column(0).unwrap(), row)?;
+                        !value.is_null() && value == column_value(cond, row)?
+                    },
+                    None => column_value(cond, row)? == Value::BOOLEAN(true),
+                };
+
+                if hit {
+                    *pick = Some(branch);
+                    undecided -= 1;
+                }
+            }
+        }
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap();
+
+            for (branch, (_, then)) in self.branches.iter().enumerate() {
+                if !picked.contains(&Some(branch)) {
+                    continue
+                }
+
+                let result = then.evaluate(view, rows)?;
+                let src = result.column(0).unwrap();
+                for row in (0 .. rows).filter(|r| picked[*r] == Some(branch)) {
+                    copy_value(src, dst, nullable, row)?;
+                }
+            }
+
+            if undecided > 0 {
+                let result = match self.otherwise {
+                    Some(ref otherwise) => Some(otherwise.evaluate(view, rows)?),
+                    None => None,
+                };
+
+                for row in (0 .. rows).filter(|r| picked[*r].is_none()) {
+                    match result {
+                        Some(ref result) => copy_value(result.column(0).unwrap(), dst, nullable,
+                                                       row)?,
+                        None => Value::NULL.set_row(dst, row)?,
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// True if `value` is NULL or a value of type `dtype`
+fn has_type(value: &Value, dtype: &Type) -> bool {
+    match (value, dtype) {
+        (&Value::NULL, _)                           => true,
+        (&Value::UINT32(_), &Type::UINT32)          => true,
+        (&Value::UINT64(_), &Type::UINT64)          => true,
+        (&Value::INT32(_), &Type::INT32)            => true,
+        (&Value::INT64(_), &Type::INT64)            => true,
+        (&Value::FLOAT32(_), &Type::FLOAT32)        => true,
+        (&Value::FLOAT64(_), &Type::FLOAT64)        => true,
+        (&Value::BOOLEAN(_), &Type::BOOLEAN)        => true,
+        (&Value::TIMESTAMP(_), &Type::TIMESTAMP)    => true,
+        (&Value::INTERVAL(_), &Type::INTERVAL)      => true,
+        (&Value::UUID(_), &Type::UUID)              => true,
+        (&Value::TEXT(_), &Type::TEXT)              => true,
+        (&Value::BLOB(_), &Type::BLOB)              => true,
+        _                                           => false,
+    }
+}
+
+impl<'b> Expr<'b> for InListExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+        let has_null = self.values.iter().any(|v| v.is_null());
+
+        let (schema, list_schema) = {
+            let attr = bound_attribute(&*input)?;
+            if let Some(pos) = self.values.iter().position(|v| !has_type(v, &attr.dtype)) {
+                return Err(DBError::ExpressionInputType(
+                    format!("IN list value {} isn't {} like {}", pos, attr.dtype, attr.name)))
+            }
+
+            let schema = Schema::from_attr(Attribute {
+                name: attr.name.clone(),
+                nullable: attr.nullable || has_null,
+                dtype: Type::BOOLEAN,
+            });
+            (schema, Schema::make_one_attr("list", true, attr.dtype.clone()))
+        };
+
+        let mut list = Block::new(alloc, &list_schema);
+        list.add_rows(self.values.len())?;
+        {
+            let col = list.column_mut(0).unwrap();
+            for (row, value) in self.values.iter().enumerate() {
+                value.set_row(col, row)?;
+                if !value.is_null() {
+                    col.nulls_mut()?[row] = 0;
+                }
+            }
+        }
+
+        Ok(Box::new(InListBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            list: list,
+            has_null: has_null,
+            negated: self.negated,
+        }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for InListBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(if self.negated { "NOT IN" } else { "IN" })
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+        let list = self.list.column(0).unwrap();
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Boolean>()?;
+
+            for row in 0 .. rows {
+                let value = column_value(src, row)?;
+
+                let mut found = false;
+                if !value.is_null() {
+                    for pos in 0 .. self.list.rows() {
+                        let item = column_value(list, pos)?;
+                        if compare_values(&value, &item) == Some(Ordering::Equal) {
+                            found = true;
+                            break
+                        }
+                    }
+                }
+
+                if !found && (value.is_null() || self.has_null) {
+                    dst.nulls[row] = 1;
+                } else {
+                    dst.values[row] = found != self.negated;
+                    if nullable {
+                        dst.nulls[row] = 0;
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "n".to_string(), nullable: true, dtype: Type::INT32},
+            Attribute{name: "big".to_string(), nullable: false, dtype: Type::BOOLEAN},
+            Attribute{name: "wide".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "flag".to_string(), nullable: true, dtype: Type::BOOLEAN},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1i32).set(false).set(1i64).set(true)
+                .add_row().set(50i32).set(true).set(20i64).set_null(true)
+                .add_row().set_null(true).set(false).set(30i64).set(false)
+                .add_row().set(2i32).set(false).set(2i64).set(true)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    /// Rows as strings, "NULL" for NULL rows
+    fn eval<'e, E: Expr<'e>>(block: &Block, expr: E) -> Vec<String> {
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(block, block.rows()).unwrap();
+
+        (0 .. block.rows()).map(|row| match column_value(out.column(0).unwrap(), row).unwrap() {
+            Value::INT32(v)     => v.to_string(),
+            Value::INT64(v)     => v.to_string(),
+            Value::BOOLEAN(v)   => v.to_string(),
+            _                   => String::from("NULL"),
+        }).collect()
+    }
+
+    // First true condition wins, NULL conditions aren't true, results are promoted
+    #[test]
+    fn case_searched() {
+        let block = make_block();
+
+        let expr = CaseExpr::searched()
+            .when(ColumnExpr::named("big"), ColumnExpr::named("n"))
+            .when(ColumnExpr::named("flag"), ColumnExpr::named("wide"));
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert!(bound.schema()[0].dtype == Type::INT64);
+        assert!(bound.schema()[0].nullable);
+
+        let expr = CaseExpr::searched()
+            .when(ColumnExpr::named("big"), ColumnExpr::named("n"))
+            .when(ColumnExpr::named("flag"), ColumnExpr::named("wide"));
+        assert_eq!(eval(&block, expr), vec!["1", "50", "NULL", "2"]);
+
+        let expr = CaseExpr::searched()
+            .when(ColumnExpr::named("flag"), ColumnExpr::named("n"))
+            .otherwise(ColumnExpr::named("wide"));
+        assert_eq!(eval(&block, expr), vec!["1", "20", "30", "2"]);
+
+        let expr = CaseExpr::searched().when(ColumnExpr::named("n"), ColumnExpr::named("n"));
+        match expr.bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Operand compared to each WHEN value, a NULL operand picks ELSE
+    #[test]
+    fn case_simple() {
+        let block = make_block();
+
+        let expr = CaseExpr::simple(ColumnExpr::named("n"))
+            .when(ColumnExpr::named("wide"), ColumnExpr::named("big"))
+            .otherwise(ColumnExpr::named("flag"));
+        assert_eq!(eval(&block, expr), vec!["false", "NULL", "false", "false"]);
+
+        // Without ELSE unmatched rows are NULL
+        let expr = CaseExpr::simple(ColumnExpr::named("wide"))
+            .when(ColumnExpr::named("n"), ColumnExpr::named("flag"));
+        assert_eq!(eval(&block, expr), vec!["true", "NULL", "NULL", "true"]);
+    }
+
+    // Three valued IN and NOT IN
+    #[test]
+    fn in_list() {
+        let block = make_block();
+        let n = || ColumnExpr::named("n");
+
+        let bound_rows = |expr: InListExpr<'static>| {
+            let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+            let out = bound.evaluate(&block, block.rows()).unwrap();
+            let data = column_row_data::<Boolean>(out.column(0).unwrap()).unwrap();
+            (0 .. block.rows())
+                .map(|row| if data.nulls[row] != 0 { None } else { Some(data.values[row]) })
+                .collect::<Vec<_>>()
+        };
+
+        let list = || vec![Value::INT32(1), Value::INT32(2)];
+        assert_eq!(bound_rows(InListExpr::new(n(), list())),
+                   vec![Some(true), Some(false), None, Some(true)]);
+        assert_eq!(bound_rows(InListExpr::not_in(n(), list())),
+                   vec![Some(false), Some(true), None, Some(false)]);
+
+        let with_null = || vec![Value::INT32(1), Value::NULL];
+        assert_eq!(bound_rows(InListExpr::new(n(), with_null())),
+                   vec![Some(true), None, None, None]);
+        assert_eq!(bound_rows(InListExpr::not_in(n(), with_null())),
+                   vec![Some(false), None, None, None]);
+
+        match InListExpr::new(n(), vec![Value::INT64(1)]).bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use ::allocator::{self, Allocator};
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::table::Table;
+use ::types::{Type, Value};
+use ::util::copy_value::{ValueSetter, copy_column};
+
+/// Expression producing the same value for every row, eg. a literal of a query.
+///
+/// The value is copied when the expression is made, into a single row block of its own.
+pub struct ConstantExpr {
+    value: Block<'static>,
+}
+
+struct ConstantBound<'a> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    value: Block<'static>,
+}
+
+impl ConstantExpr {
+    /// Value of the type, fails with `DBError::AttributeType` if it's not NULL or of the type
+    pub fn new(value: &Value, dtype: Type) -> Result<ConstantExpr, DBError> {
+        let schema = Schema::make_one_attr("constant", value.is_null(), dtype);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, Some(1));
+        table.add_row()?;
+        table.set(0, 0, copy_value(value))?;
+        Ok(ConstantExpr { value: table.take().unwrap() })
+    }
+}
+
+/// Copy of the value borrowing the same TEXT and BLOB data (`Value` isn't `Clone`)
+fn copy_value<'v>(value: &Value<'v>) -> Value<'v> {
+    match *value {
+        Value::NULL             => Value::NULL,
+        Value::UINT32(v)        => Value::UINT32(v),
+        Value::UINT64(v)        => Value::UINT64(v),
+        Value::INT32(v)         => Value::INT32(v),
+        Value::INT64(v)         => Value::INT64(v),
+        Value::FLOAT32(v)       => Value::FLOAT32(v),
+        Value::FLOAT64(v)       => Value::FLOAT64(v),
+        Value::BOOLEAN(v)       => Value::BOOLEAN(v),
+        Value::TIMESTAMP(v)     => Value::TIMESTAMP(v),
+        Value::INTERVAL(v)      => Value::INTERVAL(v),
+        Value::UUID(v)          => Value::UUID(v),
+        Value::TEXT(v)          => Value::TEXT(v),
+        Value::BLOB(v)          => Value::BLOB(v),
+        Value::LIST(ref v)      => Value::LIST(v.iter().map(copy_value).collect()),
+        Value::STRUCT(ref v)    => Value::STRUCT(v.iter().map(copy_value).collect()),
+    }
+}
+
+impl<'b> Expr<'b> for ConstantExpr {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, _: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let schema = self.value.schema().clone();
+        let mut value = Block::new(&allocator::GLOBAL, &schema);
+        value.add_row()?;
+        copy_column(self.value.column(0).unwrap(), value.column_mut(0).unwrap(), 1)?;
+        Ok(Box::new(ConstantBound { alloc: alloc, schema: schema, value: value }))
+    }
+
+    fn is_constant(&self) -> bool {
+        true
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for ConstantBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("constant {}", self.schema[0].dtype)
+    }
+
+    fn evaluate<'a>(&self, _: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let value = column_value(self.value.column(0).unwrap(), 0)?;
+
+        let mut out = Table::new(self.alloc, &self.schema, Some(rows));
+        for row in 0 .. rows {
+            out.add_row()?;
+            out.set(0, row, copy_value(&value))?;
+        }
+        Ok(out.take().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::table::TableAppender;
+    use ::types::Boolean;
+
+    // The value is repeated for each row, and compares with a column of another type
+    #[test]
+    fn constant_value() {
+        let schema = Schema::make_one_attr("a", false, Type::UINT32);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 4u32 {
+                appender = appender.add_row().set(v % 2);
+            }
+            assert!(appender.done().is_none());
+        }
+        let block = table.take().unwrap();
+
+        let text = String::from("word");
+        let expr = ConstantExpr::new(&Value::TEXT(&text), Type::TEXT).unwrap();
+        drop(text);
+        assert!(Expr::is_constant(&expr));
+
+        let bound = expr.bind(&allocator::GLOBAL, &schema).unwrap();
+        let out = bound.evaluate(&block, 3).unwrap();
+        assert_eq!(out.rows(), 3);
+        assert!(column_value(out.column(0).unwrap(), 2).unwrap() == Value::TEXT("word"));
+
+        let one = ConstantExpr::new(&Value::INT64(1), Type::INT64).unwrap();
+        let equal = EqaulsExpr { lhs: Box::new(ColumnExpr::named("a")), rhs: Box::new(one) };
+        let bound = equal.bind(&allocator::GLOBAL, &schema).unwrap();
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        let rows = column_row_data::<Boolean>(out.column(0).unwrap()).unwrap();
+        assert_eq!(&rows.values[.. 4], &[false, true, false, true]);
+
+        match ConstantExpr::new(&Value::TEXT("1"), Type::INT64) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
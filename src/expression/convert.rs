@@ -1,24 +1,74 @@
+use std::f32;
 use std::marker::PhantomData;
+use std::str::FromStr;
 use std::string::ToString;
 
+use num::{Bounded, NumCast, ToPrimitive};
+
 use ::allocator::Allocator;
-use ::block::{Block, View, column_row_data};
+use ::block::{Block, View, column_row_data, column_value};
 use ::error::DBError;
 use ::expression::*;
 use ::row::RowOffset;
 use ::schema::Schema;
 use ::types::*;
 use ::util::copy_value::ValueSetter;
+use ::util::temporal::{format_timestamp, parse_interval};
+use ::util::uuid::{format_uuid, parse_uuid};
+
+/// What a cast does with values that don't fit the target type: numbers out of its range (or
+/// NaN cast to an integer) and TEXT that doesn't parse as it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OverflowPolicy {
+    /// Evaluating the cast fails
+    Error,
+    /// The value becomes NULL, the output is always nullable
+    Null,
+    /// Numbers are clamped to the smallest / largest value of the target type, NaN becomes 0.
+    /// TEXT that isn't a number still fails.
+    Saturate,
+}
 
+/// Explicit cast of the input to the `to` type, for the conversions allowed by `Type::can_cast`.
+///
+/// Floats cast to integers are truncated towards zero. BOOLEAN true is 1 and non zero numbers
+/// are true. TEXT is trimmed before it's parsed, BOOLEAN parses "true" / "false", "t" / "f", "yes"
+/// / "no" and "1" / "0" (in any case).
 pub struct CastExpr<'b> {
     pub to: Type,
     pub input: Box<Expr<'b> + 'b>,
+    pub overflow: OverflowPolicy,
 }
 
 pub struct ToStr<'b> {
     pub input: Box<Expr<'b> + 'b>,
 }
 
+/// Cast between the numeric types
+struct CastBound<'alloc, 'e, F, T> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'alloc> + 'e>,
+    overflow: OverflowPolicy,
+    pt: PhantomData<(F, T)>,
+}
+
+/// Casts to and from BOOLEAN, TEXT and TIMESTAMP, converting one value at a time
+struct ValueCastBound<'alloc, 'e> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'alloc> + 'e>,
+    from: Type,
+    overflow: OverflowPolicy,
+}
+
+/// ToStr for types whose `Store` has no suitable `ToString`
+struct FormatBound<'alloc, T: ValueInfo> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    format: fn(&T::Store) -> String,
+}
+
 struct ToStrBound<'alloc, T> {
     alloc: &'alloc Allocator,
     schema: Schema, // TODO: Can this just be a static?
@@ -29,17 +79,336 @@ impl<'b> Expr<'b> for CastExpr<'b> {
     fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
         -> Result<Box<BoundExpr<'a> + 'b>, DBError>
     {
-        unimplemented!()
+        let input = self.input.bind(alloc, input_schema)?;
+
+        if bound_attribute(&*input)?.dtype == self.to {
+            return Ok(input)
+        }
+
+        make_cast(alloc, input, &self.to, self.overflow)
+    }
+}
+
+/// Convert the output of a bound expression to `to` type, if it's not already of that type.
+///
+/// Used for inserting implicit casts when binding. Only conversions that follow the promotion
+/// rules of `Type::common_supertype` are allowed.
+pub fn coerce<'a: 'b, 'b>(alloc: &'a Allocator, input: Box<BoundExpr<'a> + 'b>, to: &Type)
+    -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+{
+    let from = bound_attribute(&*input)?.dtype.clone();
+
+    if from == *to {
+        Ok(input)
+    } else if Type::common_supertype(&from, to).as_ref() == Some(to) {
+        make_cast(alloc, input, to, OverflowPolicy::Error)
+    } else {
+        Err(DBError::ExpressionInputType(format!("cannot implicitly cast {} to {}", from, to)))
     }
 }
 
+fn unsupported_cast(from: &Type, to: &Type) -> DBError {
+    DBError::ExpressionInputType(format!("unsupported cast from {} to {}", from, to))
+}
+
+fn make_cast<'a: 'b, 'b>(alloc: &'a Allocator, input: Box<BoundExpr<'a> + 'b>, to: &Type,
+                        overflow: OverflowPolicy)
+    -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+{
+    let from = bound_attribute(&*input)?.dtype.clone();
+
+    if !Type::can_cast(&from, to) {
+        return Err(unsupported_cast(&from, to))
+    }
+
+    let mut attr = bound_attribute(&*input)?.cast(to.clone());
+    attr.nullable |= overflow == OverflowPolicy::Null;
+    let schema = Schema::from_attr(attr);
+
+    if !(from.is_numeric() && to.is_numeric()) {
+        return Ok(Box::new(ValueCastBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            from: from,
+            overflow: overflow,
+        }))
+    }
+
+    dispatch_type!(from, numeric, T => make_cast_from::<T>(alloc, schema, input, to, overflow),
+        _ => Err(unsupported_cast(&from, to)))
+}
+
+fn make_cast_from<'a: 'b, 'b, F>(alloc: &'a Allocator, schema: Schema,
+                                 input: Box<BoundExpr<'a> + 'b>, to: &Type,
+                                 overflow: OverflowPolicy)
+    -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    where F: ValueInfo + 'b, F::Store: ToPrimitive + Copy
+{
+    macro_rules! cast_to {
+        ($t:ty) => {
+            Box::new(CastBound::<F, $t> {
+                alloc: alloc,
+                schema: schema,
+                input: input,
+                overflow: overflow,
+                pt: PhantomData,
+            })
+        }
+    }
+
+    let out: Box<BoundExpr<'a> + 'b> = dispatch_type!(*to, numeric, T => cast_to!(T),
+        _ => return Err(unsupported_cast(&F::ENUM, to)));
+
+    Ok(out)
+}
+
+/// Convert a number to `T` (of the `to` type). None if it's out of range, or NaN cast to an
+/// integer, unless the `overflow` policy saturates.
+fn convert_number<F, T>(value: F, to: &Type, overflow: OverflowPolicy) -> Option<T>
+    where F: ToPrimitive + Copy, T: NumCast + Bounded
+{
+    let wide = value.to_f64();
+
+    // Finite doubles beyond the FLOAT32 range overflow, rather than becoming infinite
+    let in_range = match wide {
+        Some(v) if *to == Type::FLOAT32 && v.is_finite() => v.abs() <= f32::MAX as f64,
+        _ => true,
+    };
+
+    if in_range {
+        if let Some(out) = NumCast::from(value) {
+            return Some(out)
+        }
+    }
+
+    match wide {
+        // Infinities and NaN are in range of both float types
+        Some(v) if !v.is_finite() && *to == Type::FLOAT32 => NumCast::from(v as f32),
+        Some(v) if overflow == OverflowPolicy::Saturate => {
+            if v < 0.0 {
+                Some(T::min_value())
+            } else if v > 0.0 {
+                Some(T::max_value())
+            } else {
+                NumCast::from(0u8)
+            }
+        },
+        _ => None,
+    }
+}
+
+impl<'alloc, 'e, F: ValueInfo, T: ValueInfo> BoundExpr<'alloc> for CastBound<'alloc, 'e, F, T>
+    where F::Store: ToPrimitive + Copy, T::Store: NumCast + Bounded
+{
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("CAST({} AS {})", F::ENUM.name(), T::ENUM.name())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let src = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src_nullable = src.schema()[0].nullable;
+            let src_rows = column_row_data::<F>(src.column(0).unwrap())?;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<T>()?;
+            let nullable = self.schema[0].nullable;
+
+            for idx in 0 .. rows {
+                if src_nullable && src_rows.nulls[idx] != 0 {
+                    dst.nulls[idx] = 1;
+                    continue
+                } else if nullable {
+                    dst.nulls[idx] = 0;
+                }
+
+                match convert_number(src_rows.values[idx], &T::ENUM, self.overflow) {
+                    Some(v) => dst.values[idx] = v,
+                    None if self.overflow == OverflowPolicy::Null => dst.nulls[idx] = 1,
+                    None => return Err(DBError::ValueOutOfRange(
+                        format!("{} value out of range for {}", F::ENUM.name(), T::ENUM.name()))),
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for ValueCastBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("CAST({} AS {})", self.from.name(), self.schema[0].dtype.name())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let src = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src_col = src.column(0).unwrap();
+            let to = &self.schema[0].dtype;
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                let value = column_value(src_col, row)?;
+                if value.is_null() {
+                    NULL_VALUE.set_row(dst, row)?;
+                    continue
+                }
+
+                if *to == Type::TEXT {
+                    format_value(&value).set_row(dst, row)?;
+                } else {
+                    match cast_value(&value, to, self.overflow) {
+                        Some(v) => v.set_row(dst, row)?,
+                        None if self.overflow == OverflowPolicy::Null => {
+                            NULL_VALUE.set_row(dst, row)?;
+                            continue
+                        },
+                        None => return Err(cast_error(&value, to)),
+                    }
+                }
+
+                if nullable {
+                    dst.nulls_mut()?[row] = 0;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Error for a value that doesn't fit the `to` type
+fn cast_error(value: &Value, to: &Type) -> DBError {
+    match *value {
+        Value::TEXT(text)   => DBError::ValueParse(format!("{} '{}'", to, text)),
+        _                   => DBError::ValueOutOfRange(
+            format!("{} value out of range for {}", format_value(value), to)),
+    }
+}
+
+/// TEXT representation of a (non NULL) value
+fn format_value(value: &Value) -> String {
+    match *value {
+        Value::UINT32(v)        => v.to_string(),
+        Value::UINT64(v)        => v.to_string(),
+        Value::INT32(v)         => v.to_string(),
+        Value::INT64(v)         => v.to_string(),
+        Value::FLOAT32(v)       => v.to_string(),
+        Value::FLOAT64(v)       => v.to_string(),
+        Value::BOOLEAN(v)       => v.to_string(),
+        Value::TIMESTAMP(v)     => format_timestamp(v),
+        Value::INTERVAL(ref v)  => v.to_string(),
+        Value::UUID(ref v)      => format_uuid(v),
+        Value::TEXT(v)          => v.to_string(),
+        _                       => String::new(),
+    }
+}
+
+/// Cast a (non NULL) value to a type other than TEXT. None if it doesn't fit the type.
+fn cast_value(value: &Value, to: &Type, overflow: OverflowPolicy) -> Option<Value<'static>> {
+    match (value, to) {
+        (&Value::BOOLEAN(v), _)                 => Some(number_value(v as u8, to)),
+        (&Value::TEXT(text), &Type::BOOLEAN)    => parse_bool(text.trim()).map(Value::BOOLEAN),
+        (&Value::TEXT(text), &Type::INTERVAL)   => parse_interval(text.trim()).ok()
+            .map(Value::INTERVAL),
+        (&Value::TEXT(text), &Type::UUID)       => parse_uuid(text.trim()).ok().map(Value::UUID),
+        (&Value::TEXT(text), _)                 => parse_number(text.trim(), to, overflow),
+        (&Value::TIMESTAMP(v), &Type::INT64)    => Some(Value::INT64(v)),
+        (&Value::INT64(v), &Type::TIMESTAMP)    => Some(Value::TIMESTAMP(v)),
+        (_, &Type::BOOLEAN)                     => is_non_zero(value).map(Value::BOOLEAN),
+        _                                       => None,
+    }
+}
+
+/// Small number as a value of the numeric `to` type
+fn number_value(v: u8, to: &Type) -> Value<'static> {
+    match *to {
+        Type::UINT32    => Value::UINT32(v as u32),
+        Type::UINT64    => Value::UINT64(v as u64),
+        Type::INT32     => Value::INT32(v as i32),
+        Type::INT64     => Value::INT64(v as i64),
+        Type::FLOAT32   => Value::FLOAT32(v as f32),
+        _               => Value::FLOAT64(v as f64),
+    }
+}
+
+fn is_non_zero(value: &Value) -> Option<bool> {
+    match *value {
+        Value::UINT32(v)    => Some(v != 0),
+        Value::UINT64(v)    => Some(v != 0),
+        Value::INT32(v)     => Some(v != 0),
+        Value::INT64(v)     => Some(v != 0),
+        Value::FLOAT32(v)   => Some(v != 0.0),
+        Value::FLOAT64(v)   => Some(v != 0.0),
+        _                   => None,
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.to_lowercase().as_str() {
+        "true" | "t" | "yes" | "1"  => Some(true),
+        "false" | "f" | "no" | "0"  => Some(false),
+        _                           => None,
+    }
+}
+
+fn parse_number(text: &str, to: &Type, overflow: OverflowPolicy) -> Option<Value<'static>> {
+    match *to {
+        Type::UINT32    => parse_as::<u32>(text, to, overflow).map(Value::UINT32),
+        Type::UINT64    => parse_as::<u64>(text, to, overflow).map(Value::UINT64),
+        Type::INT32     => parse_as::<i32>(text, to, overflow).map(Value::INT32),
+        Type::INT64     => parse_as::<i64>(text, to, overflow).map(Value::INT64),
+        Type::FLOAT32   => parse_as::<f32>(text, to, overflow).map(Value::FLOAT32),
+        Type::FLOAT64   => parse_as::<f64>(text, to, overflow).map(Value::FLOAT64),
+        _               => None,
+    }
+}
+
+/// Parse `text` as `T`, numbers with a fraction or out of range of `T` are converted like a
+/// FLOAT64 cast to the type
+fn parse_as<T>(text: &str, to: &Type, overflow: OverflowPolicy) -> Option<T>
+    where T: FromStr + NumCast + Bounded
+{
+    text.parse::<T>().ok()
+        .or_else(|| text.parse::<f64>().ok().and_then(|v| convert_number(v, to, overflow)))
+}
+
 impl<'a> CastExpr<'a> {
     pub fn new<T: Expr<'a> + 'a>(to: Type, input: T) -> CastExpr<'a> {
         CastExpr {
             to: to,
             input: box input,
+            overflow: OverflowPolicy::Error,
         }
     }
+
+    pub fn with_overflow(self, overflow: OverflowPolicy) -> CastExpr<'a> {
+        CastExpr { overflow: overflow, .. self }
+    }
 }
 
 impl<'a> ToStr<'a> {
@@ -48,6 +417,11 @@ impl<'a> ToStr<'a> {
     }
 }
 
+/// TIMESTAMP values are formatted as "YYYY-MM-DD HH:MM:SS[.ffffff]"
+fn timestamp_str(ts: &i64) -> String {
+    format_timestamp(*ts)
+}
+
 impl<'b> Expr<'b> for ToStr<'b> {
     fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema) ->
         Result<Box<BoundExpr<'a> + 'a>, DBError>
@@ -74,11 +448,20 @@ impl<'b> Expr<'b> for ToStr<'b> {
                 box ToStrBound::<Float64>{alloc: alloc, schema: out_schema, pt: PhantomData},
             Type::BOOLEAN =>
                 box ToStrBound::<Float32>{alloc: alloc, schema: out_schema, pt: PhantomData},
+            Type::TIMESTAMP =>
+                box FormatBound::<Timestamp>{alloc: alloc, schema: out_schema, format: timestamp_str},
+            Type::INTERVAL =>
+                box ToStrBound::<Interval>{alloc: alloc, schema: out_schema, pt: PhantomData},
+            Type::UUID =>
+                box FormatBound::<Uuid>{alloc: alloc, schema: out_schema, format: format_uuid},
             Type::TEXT =>
                 // TODO: Just copy
                 unimplemented!(),
             Type::BLOB =>
                 box ToStrBound::<Blob>{alloc: alloc, schema: out_schema, pt: PhantomData},
+            Type::LIST(_) | Type::STRUCT(_) =>
+                return Err(DBError::ExpressionInputType(
+                    format!("unsupported cast from {} to TEXT", input_schema[0].dtype))),
         };
 
         Ok(out)
@@ -87,11 +470,47 @@ impl<'b> Expr<'b> for ToStr<'b> {
 
 impl<'alloc> BoundExpr<'alloc> for ToStrBound<'alloc, Blob>
 {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
     fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
         unimplemented!()
     }
 }
 
+impl<'alloc, T: ValueInfo> BoundExpr<'alloc> for FormatBound<'alloc, T> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let src_col = view.column(0).unwrap();
+        let src_rows = column_row_data::<T>(src_col)?;
+        let nullable = self.schema[0].nullable;
+
+        {
+            let col = out.column_mut(0).unwrap();
+
+            for idx in 0 .. rows {
+                if nullable && src_rows.nulls[idx] != 0 {
+                    NULL_VALUE.set_row(col, idx)?;
+                } else {
+                    (self.format)(&src_rows.values[idx]).set_row(col, idx)?;
+                    if nullable {
+                        col.nulls_mut()?[idx] = 0;
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
 impl<'alloc, T: ValueInfo, V: ToString> BoundExpr<'alloc> for ToStrBound<'alloc, T>
     where T: ValueInfo<Store=V>
 {
@@ -135,3 +554,116 @@ impl<'alloc, T: ValueInfo, V: ToString> BoundExpr<'alloc> for ToStrBound<'alloc,
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::schema::Attribute;
+    use ::table::{Table, TableAppender};
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "big".to_string(), nullable: true, dtype: Type::INT64},
+            Attribute{name: "f".to_string(), nullable: false, dtype: Type::FLOAT64},
+            Attribute{name: "s".to_string(), nullable: false, dtype: Type::TEXT},
+            Attribute{name: "b".to_string(), nullable: false, dtype: Type::BOOLEAN},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(7i64).set(-2.75f64).set(" 42 ").set(true)
+                .add_row().set(1i64 << 40).set(1e300f64).set("TRUE").set(false)
+                .add_row().set_null(true).set(::std::f64::NAN).set("x1").set(true)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    fn cast_rows<'a>(block: &'a Block<'a>, column: &str, to: Type, overflow: OverflowPolicy)
+        -> Result<Vec<String>, DBError>
+    {
+        let expr = CastExpr::new(to, ColumnExpr::named(column)).with_overflow(overflow);
+        let bound = expr.bind(&allocator::GLOBAL, block.schema())?;
+        let out = bound.evaluate(block, block.rows())?;
+
+        let mut rows = Vec::new();
+        for row in 0 .. block.rows() {
+            let value = column_value(out.column(0).unwrap(), row)?;
+            rows.push(if value.is_null() { String::from("NULL") } else { format_value(&value) });
+        }
+        Ok(rows)
+    }
+
+    // Narrowing casts fail, become NULL or saturate on overflow
+    #[test]
+    fn cast_overflow() {
+        let block = make_block();
+
+        match cast_rows(&block, "big", Type::INT32, OverflowPolicy::Error) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        assert_eq!(cast_rows(&block, "big", Type::INT32, OverflowPolicy::Null).unwrap(),
+                   vec!["7", "NULL", "NULL"]);
+        assert_eq!(cast_rows(&block, "big", Type::UINT32, OverflowPolicy::Saturate).unwrap(),
+                   vec!["7", "4294967295", "NULL"]);
+        assert_eq!(cast_rows(&block, "f", Type::INT32, OverflowPolicy::Saturate).unwrap(),
+                   vec!["-2", "2147483647", "0"]);
+        assert_eq!(cast_rows(&block, "f", Type::FLOAT32, OverflowPolicy::Null).unwrap(),
+                   vec!["-2.75", "NULL", "NaN"]);
+    }
+
+    // TEXT and BOOLEAN casts, invalid TEXT follows the overflow policy
+    #[test]
+    fn cast_text_boolean() {
+        let block = make_block();
+
+        assert_eq!(cast_rows(&block, "big", Type::TEXT, OverflowPolicy::Error).unwrap(),
+                   vec!["7", "1099511627776", "NULL"]);
+        assert_eq!(cast_rows(&block, "b", Type::INT32, OverflowPolicy::Error).unwrap(),
+                   vec!["1", "0", "1"]);
+        assert_eq!(cast_rows(&block, "f", Type::BOOLEAN, OverflowPolicy::Error).unwrap(),
+                   vec!["true", "true", "true"]);
+        assert_eq!(cast_rows(&block, "s", Type::INT64, OverflowPolicy::Null).unwrap(),
+                   vec!["42", "NULL", "NULL"]);
+        assert_eq!(cast_rows(&block, "s", Type::BOOLEAN, OverflowPolicy::Null).unwrap(),
+                   vec!["NULL", "true", "NULL"]);
+
+        match cast_rows(&block, "s", Type::INT64, OverflowPolicy::Saturate) {
+            Err(DBError::ValueParse(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        // Not a castable pair of types
+        match cast_rows(&block, "b", Type::TIMESTAMP, OverflowPolicy::Error) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Explicit casts allowed by the rule table, implicit casts only widen
+    #[test]
+    fn cast_rules() {
+        assert!(Type::can_cast(&Type::UINT64, &Type::INT32));
+        assert!(Type::can_cast(&Type::TEXT, &Type::UUID));
+        assert!(Type::can_cast(&Type::TIMESTAMP, &Type::TEXT));
+        assert!(!Type::can_cast(&Type::TEXT, &Type::TIMESTAMP));
+        assert!(!Type::can_cast(&Type::BLOB, &Type::TEXT));
+        assert!(!Type::can_cast(&Type::LIST(Box::new(Type::INT32)), &Type::TEXT));
+
+        assert!(Type::common_supertype(&Type::INT32, &Type::UINT32) == Some(Type::INT64));
+        assert!(Type::common_supertype(&Type::FLOAT32, &Type::INT64) == Some(Type::FLOAT64));
+        assert!(Type::common_supertype(&Type::INT64, &Type::UINT64).is_none());
+        assert!(Type::common_supertype(&Type::TEXT, &Type::INT32).is_none());
+    }
+}
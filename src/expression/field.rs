@@ -0,0 +1,172 @@
+use ::allocator::Allocator;
+use ::block::{Block, View, column_nulls, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value};
+use ::util::copy_value::{ValueSetter, copy_column};
+
+/// Access a field of a STRUCT, `struct_col.field`. NULL if the STRUCT or the field is NULL.
+pub struct FieldExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub field: String,
+}
+
+struct FieldBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    pos: usize,
+}
+
+impl<'a> FieldExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a, S: Into<String>>(input: T, field: S) -> FieldExpr<'a> {
+        FieldExpr { input: Box::new(input), field: field.into() }
+    }
+}
+
+impl<'b> Expr<'b> for FieldExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+
+        let (schema, pos) = {
+            let attr = bound_attribute(&*input)?;
+
+            let fields = match attr.dtype {
+                Type::STRUCT(ref fields) => fields,
+                _ => return Err(DBError::ExpressionInputType(
+                    format!("{} is {}, expected STRUCT", attr.name, attr.dtype))),
+            };
+
+            let pos = fields.iter().position(|f| f.name == self.field)
+                .ok_or(DBError::AttributeMissing(format!("(name: {}.{})", attr.name, self.field)))?;
+
+            let out = Attribute {
+                name: format!("{}.{}", attr.name, self.field),
+                nullable: attr.nullable || fields[pos].nullable,
+                dtype: fields[pos].dtype.clone(),
+            };
+
+            (Schema::from_attr(out), pos)
+        };
+
+        Ok(Box::new(FieldBound { alloc: alloc, schema: schema, input: input, pos: pos }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for FieldBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("field {}", self.schema[0].name)
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let field = src.child(self.pos)
+                .ok_or(DBError::make_column_unknown_pos(self.pos))?;
+
+            let dst = out.column_mut(0).unwrap();
+
+            // Fields of NULL STRUCT rows are not initialized, so they can't be copied wholesale
+            match column_nulls(src) {
+                None => copy_column(field, dst, rows)?,
+                Some(nulls) => for row in 0 .. rows {
+                    let value = if nulls[row] != 0 {
+                        Value::NULL
+                    } else {
+                        column_value(field, row)?
+                    };
+                    value.set_row(dst, row)?;
+
+                    if !value.is_null() {
+                        dst.nulls_mut()?[row] = 0;
+                    }
+                },
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+    use ::types::{Int32, Value};
+
+    #[test]
+    fn struct_field_access() {
+        let dtype = "STRUCT<x INT32, y INT32 NULL>".parse::<Type>().unwrap();
+        assert_eq!(dtype.to_string(), "STRUCT<x INT32, y INT32 NULL>");
+
+        let block = {
+            let schema = Schema::make_one_attr("p", true, dtype);
+            let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+            {
+                let status = TableAppender::new(&mut table)
+                    .add_row().set(Value::STRUCT(vec![Value::INT32(1), Value::INT32(2)]))
+                    .add_row().set_null(true)
+                    .add_row().set(Value::STRUCT(vec![Value::INT32(5), Value::NULL]))
+                    .done();
+
+                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            }
+
+            table.take().unwrap()
+        };
+
+        let bound = ColumnExpr::named("p.y").bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert_eq!(bound.schema()[0].name, "p.y");
+
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        let rows = column_row_data::<Int32>(out.column(0).unwrap()).unwrap();
+        assert_eq!(rows.values[0], 2);
+        assert_eq!(rows.nulls[..3], [0, 1, 1]);
+
+        // Not a field
+        match FieldExpr::new(ColumnExpr::named("p"), "z").bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    #[test]
+    fn struct_schema_validation() {
+        let dtype = Type::STRUCT(vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::INT32},
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::TEXT},
+        ]);
+
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "s".to_string(), nullable: false, dtype: Type::LIST(Box::new(dtype))},
+        ]);
+
+        match schema {
+            Err(DBError::AttributeDuplicate(ref name)) => assert_eq!(name, "s.a"),
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
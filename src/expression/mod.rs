@@ -1,10 +1,15 @@
+use std::rc::Rc;
+
 use ::allocator::Allocator;
 use ::block::{Block, View};
 use ::error::DBError;
-use ::schema::Schema;
+use ::kernels;
+use ::schema::{Attribute, Schema};
 use ::types::Value;
 use ::row::RowOffset;
 
+use self::udf::AggregateUdf;
+
 /// Single expression in a expression AST.
 /// This expression has been been type checked nor materialized.
 pub trait Expr<'b> {
@@ -16,6 +21,18 @@ pub trait Expr<'b> {
     fn is_constant(&self) -> bool {
         false
     }
+
+    /// Attribute and query of a `text_matches` predicate, which a `TextIndex` on the attribute
+    /// can answer
+    fn text_match(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    /// Function and argument of an aggregate function call, which an aggregate operation
+    /// computes for each group
+    fn aggregate_call(&self) -> Option<(&Rc<AggregateUdf>, &Expr<'b>)> {
+        None
+    }
 }
 
 /// Materialized expression. Input and output schema of the operation are know
@@ -36,8 +53,67 @@ pub trait BoundExpr<'alloc> {
     fn evaluate_constant(&self) -> Result<Value<'alloc>, DBError> {
         Err(DBError::ExpressionNotCost)
     }
+
+    /// Short human readable description of the expression (not including its children)
+    fn describe(&self) -> String {
+        let names: Vec<&str> = self.schema().iter().map(|a| a.name.as_str()).collect();
+        names.join(", ")
+    }
+
+    /// Bound input expressions evaluated by this expression
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        Vec::new()
+    }
+
+    /// Positions of the input attributes read by the expression and its children, in order
+    fn input_positions(&self) -> Vec<usize> {
+        let mut out: Vec<usize> = self.children().iter()
+            .flat_map(|c| c.input_positions())
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+}
+
+/// Output attribute of a bound expression that is expected to produce a single column.
+pub fn bound_attribute<'a, 'e>(expr: &'e BoundExpr<'a>) -> Result<&'e Attribute, DBError> {
+    let schema = expr.schema();
+
+    if schema.count() != 1 {
+        return Err(DBError::ExpressionInputCount(format!("{} != 1", schema.count())))
+    }
+
+    schema.get(0)
+}
+
+/// Set the `out` flags of the rows that are NULL in either input of a binary expression. Inputs
+/// that aren't nullable have no flags (see `block::column_nulls()`).
+pub fn binary_nulls(lhs: Option<&[u8]>, rhs: Option<&[u8]>, out: &mut [u8]) {
+    let rows = out.len();
+    match (lhs, rhs) {
+        (Some(l), Some(r)) => kernels::nulls_or(&l[.. rows], &r[.. rows], out),
+        (Some(nulls), None) | (None, Some(nulls)) => out.copy_from_slice(&nulls[.. rows]),
+        (None, None) => for flag in out.iter_mut() { *flag = 0 },
+    }
 }
 
+pub mod anonymize;
+pub mod approx;
+pub mod audit;
+pub mod bloom;
+pub mod column;
+pub mod conditional;
+pub mod constant;
 pub mod convert;
 pub mod comparison;
+pub mod field;
+pub mod numeric;
+pub mod pattern;
+pub mod phonetic;
+pub mod string;
+pub mod temporal;
+pub mod text;
+pub mod udf;
+pub mod vector;
 // pub mod internal;
@@ -0,0 +1,343 @@
+//! Sum, min and max aggregate functions of numeric values.
+//!
+//! The aggregates add the values of whole PLAIN columns with the `kernels` (see
+//! `Aggregate::update_column()`), other columns a value at a time. Like the kernels, float sums
+//! include NaNs and infinities while min and max skip NaNs.
+
+use std::cmp::Ordering;
+
+use ::block::{RefColumn, column_nulls, column_row_data};
+use ::error::DBError;
+use ::expression::udf::{Aggregate, FunctionRegistry, update_rows};
+use ::kernels::NumericKernels;
+use ::row::RowOffset;
+use ::stats::compare_values;
+use ::types::{Type, Value};
+
+/// Sum of the non NULL values, NULL for groups without values. Signed integers sum to INT64,
+/// unsigned ones to UINT64 (wrapping around on overflow) and floats to FLOAT64.
+pub struct Sum;
+
+/// Smallest non NULL value, skipping NaNs. NULL for groups without such a value.
+pub struct Min;
+
+/// Largest non NULL value, skipping NaNs. NULL for groups without such a value.
+pub struct Max;
+
+/// Register `sum`, `min` and `max`
+pub fn register_numeric(registry: &mut FunctionRegistry) {
+    registry.register_aggregate("sum", Sum);
+    registry.register_aggregate("min", Min);
+    registry.register_aggregate("max", Max);
+}
+
+/// Value of a kernel result
+trait KernelValue: Copy {
+    fn value(self) -> Value<'static>;
+}
+
+macro_rules! kernel_value {
+    ($($t:ty => $variant:ident),*) => {$(
+        impl KernelValue for $t {
+            fn value(self) -> Value<'static> {
+                Value::$variant(self)
+            }
+        }
+    )*}
+}
+
+kernel_value!(u32 => UINT32, u64 => UINT64, i32 => INT32, i64 => INT64, f32 => FLOAT32,
+              f64 => FLOAT64);
+
+fn numeric_input(func: &str, input: &Type) -> Result<(), DBError> {
+    if input.is_numeric() {
+        Ok(())
+    } else {
+        Err(DBError::ExpressionInputType(format!("{} of {}", func, input)))
+    }
+}
+
+/// The value as the type of its sum
+fn sum_value(value: &Value) -> Option<Value<'static>> {
+    match *value {
+        Value::UINT32(v)    => Some(Value::UINT64(v as u64)),
+        Value::UINT64(v)    => Some(Value::UINT64(v)),
+        Value::INT32(v)     => Some(Value::INT64(v as i64)),
+        Value::INT64(v)     => Some(Value::INT64(v)),
+        Value::FLOAT32(v)   => Some(Value::FLOAT64(v as f64)),
+        Value::FLOAT64(v)   => Some(Value::FLOAT64(v)),
+        _                   => None,
+    }
+}
+
+fn add_sum(state: &mut Option<Value<'static>>, value: Value<'static>) {
+    let sum = match (state.take(), value) {
+        (None, value) => value,
+        (Some(Value::UINT64(s)), Value::UINT64(v)) => Value::UINT64(s.wrapping_add(v)),
+        (Some(Value::INT64(s)), Value::INT64(v)) => Value::INT64(s.wrapping_add(v)),
+        (Some(Value::FLOAT64(s)), Value::FLOAT64(v)) => Value::FLOAT64(s + v),
+        (Some(s), _) => s,
+    };
+    *state = Some(sum);
+}
+
+/// Replace the state with the value if it's ordered before the state, skipping NaNs
+fn keep_first(state: &mut Option<Value<'static>>, value: Value<'static>, first: Ordering) {
+    if compare_values(&value, &value).is_none() {
+        return
+    }
+
+    let replace = match *state {
+        Some(ref current) => compare_values(&value, current) == Some(first),
+        None => true,
+    };
+    if replace {
+        *state = Some(value);
+    }
+}
+
+/// Copy of a numeric value
+fn owned_value(value: &Value) -> Option<Value<'static>> {
+    match *value {
+        Value::UINT32(v)    => Some(Value::UINT32(v)),
+        Value::UINT64(v)    => Some(Value::UINT64(v)),
+        Value::INT32(v)     => Some(Value::INT32(v)),
+        Value::INT64(v)     => Some(Value::INT64(v)),
+        Value::FLOAT32(v)   => Some(Value::FLOAT32(v)),
+        Value::FLOAT64(v)   => Some(Value::FLOAT64(v)),
+        _                   => None,
+    }
+}
+
+/// Some row of the first `rows` isn't NULL
+fn has_values(nulls: Option<&[u8]>, rows: RowOffset) -> bool {
+    match nulls {
+        Some(nulls) => nulls[.. rows].contains(&0),
+        None => rows > 0,
+    }
+}
+
+/// `Some(body)` evaluated with the `values` and `nulls` of the first `rows` rows of a PLAIN
+/// numeric column, `None` for other columns
+macro_rules! with_kernels {
+    ($col:expr, $rows:expr, |$values:ident, $nulls:ident| $body:expr) => {{
+        let col: &RefColumn = $col;
+        let rows: RowOffset = $rows;
+        let $nulls = column_nulls(col).map(|n| &n[.. rows]);
+
+        dispatch_type!(col.attribute().dtype.clone(), numeric,
+            T => match column_row_data::<T>(col) {
+                Ok(data) => {
+                    let $values = &data.values[.. rows];
+                    Some($body)
+                },
+                Err(_) => None,
+            },
+            _ => None)
+    }}
+}
+
+impl Aggregate for Sum {
+    type State = Option<Value<'static>>;
+
+    fn output(&self, input: &Type) -> Result<Type, DBError> {
+        numeric_input("sum", input)?;
+        Ok(match *input {
+            Type::UINT32 | Type::UINT64 => Type::UINT64,
+            Type::INT32 | Type::INT64   => Type::INT64,
+            _                           => Type::FLOAT64,
+        })
+    }
+
+    fn init(&self) -> Option<Value<'static>> {
+        None
+    }
+
+    fn update(&self, state: &mut Option<Value<'static>>, value: &Value) -> Result<(), DBError> {
+        if let Some(value) = sum_value(value) {
+            add_sum(state, value);
+        }
+        Ok(())
+    }
+
+    fn update_column(&self, state: &mut Option<Value<'static>>, col: &RefColumn, rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        let sum = with_kernels!(col, rows, |values, nulls| {
+            if has_values(nulls, rows) {
+                Some(NumericKernels::sum(values, nulls).value())
+            } else {
+                None
+            }
+        });
+
+        match sum {
+            Some(Some(sum)) => add_sum(state, sum),
+            Some(None) => (),
+            None => update_rows(self, state, col, rows)?,
+        }
+        Ok(())
+    }
+
+    fn merge(&self, state: &mut Option<Value<'static>>, other: Option<Value<'static>>)
+        -> Result<(), DBError>
+    {
+        if let Some(other) = other {
+            add_sum(state, other);
+        }
+        Ok(())
+    }
+
+    fn finalize<'s>(&self, state: &'s Option<Value<'static>>) -> Result<Value<'s>, DBError> {
+        Ok(state.as_ref().and_then(owned_value).unwrap_or(Value::NULL))
+    }
+}
+
+macro_rules! extreme_aggregate {
+    ($t:ty, $name:expr, $kernel:ident, $first:expr) => {
+        impl Aggregate for $t {
+            type State = Option<Value<'static>>;
+
+            fn output(&self, input: &Type) -> Result<Type, DBError> {
+                numeric_input($name, input)?;
+                Ok(input.clone())
+            }
+
+            fn init(&self) -> Option<Value<'static>> {
+                None
+            }
+
+            fn update(&self, state: &mut Option<Value<'static>>, value: &Value)
+                -> Result<(), DBError>
+            {
+                if let Some(value) = owned_value(value) {
+                    keep_first(state, value, $first);
+                }
+                Ok(())
+            }
+
+            fn update_column(&self, state: &mut Option<Value<'static>>, col: &RefColumn,
+                             rows: RowOffset)
+                -> Result<(), DBError>
+            {
+                let found = with_kernels!(col, rows, |values, nulls| {
+                    NumericKernels::$kernel(values, nulls).map(KernelValue::value)
+                });
+
+                match found {
+                    Some(Some(value)) => keep_first(state, value, $first),
+                    Some(None) => (),
+                    None => update_rows(self, state, col, rows)?,
+                }
+                Ok(())
+            }
+
+            fn merge(&self, state: &mut Option<Value<'static>>, other: Option<Value<'static>>)
+                -> Result<(), DBError>
+            {
+                if let Some(other) = other {
+                    keep_first(state, other, $first);
+                }
+                Ok(())
+            }
+
+            fn finalize<'s>(&self, state: &'s Option<Value<'static>>)
+                -> Result<Value<'s>, DBError>
+            {
+                Ok(state.as_ref().and_then(owned_value).unwrap_or(Value::NULL))
+            }
+        }
+    }
+}
+
+extreme_aggregate!(Min, "min", smallest, Ordering::Less);
+extreme_aggregate!(Max, "max", largest, Ordering::Greater);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64;
+    use ::allocator;
+    use ::block::{Block, View, column_value};
+    use ::expression::Expr;
+    use ::expression::column::ColumnExpr;
+    use ::schema::{Attribute, Schema};
+    use ::table::{Table, TableAppender};
+
+    // Whole columns and single values aggregate the same, NULLs are skipped
+    #[test]
+    fn numeric_aggregates() {
+        let attrs = vec![
+            Attribute{name: "i".to_string(), nullable: true, dtype: Type::INT32},
+            Attribute{name: "f".to_string(), nullable: false, dtype: Type::FLOAT64},
+            Attribute{name: "u".to_string(), nullable: true, dtype: Type::UINT32},
+        ];
+        let mut table = Table::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap(), None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(5i32).set(1.5f64).set_null(true)
+                .add_row().set_null(true).set(f64::NAN).set_null(true)
+                .add_row().set(-3i32).set(-2.0f64).set_null(true)
+                .add_row().set(i32::max_value()).set(0.5f64).set_null(true)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        let block = table.take().unwrap();
+
+        let mut registry = FunctionRegistry::new();
+        register_numeric(&mut registry);
+
+        // The aggregate of the whole column, and of a value at a time
+        let aggregate = |func: &str, column: &str| {
+            let expr = registry.call_aggregate(func, ColumnExpr::named(column)).unwrap();
+            let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+            let whole = bound.evaluate(&block, block.rows()).unwrap();
+
+            let pos = block.schema().exists_ok(column).unwrap();
+            let func = registry.aggregate(func).unwrap();
+            let mut state = func.init();
+            for row in 0 .. block.rows() {
+                let value = column_value(block.column(pos).unwrap(), row).unwrap();
+                if value != Value::NULL {
+                    func.update(&mut *state, &value).unwrap();
+                }
+            }
+            let mut single = Block::new(&allocator::GLOBAL, whole.schema());
+            single.add_rows(1).unwrap();
+            func.finalize(&*state, single.column_mut(0).unwrap(), 0).unwrap();
+
+            (owned_value(&column_value(whole.column(0).unwrap(), 0).unwrap()),
+             owned_value(&column_value(single.column(0).unwrap(), 0).unwrap()))
+        };
+
+        let expected = [
+            ("sum", "i", Some(Value::INT64(i32::max_value() as i64 + 2))),
+            ("min", "i", Some(Value::INT32(-3))),
+            ("max", "i", Some(Value::INT32(i32::max_value()))),
+            ("min", "f", Some(Value::FLOAT64(-2.0))),
+            ("max", "f", Some(Value::FLOAT64(1.5))),
+            ("sum", "u", None),
+            ("max", "u", None),
+        ];
+        for &(func, column, ref value) in expected.iter() {
+            let (whole, single) = aggregate(func, column);
+            assert!(whole == *value, "{} of {}", func, column);
+            assert!(single == *value, "{} of {}, a value at a time", func, column);
+        }
+
+        // Sums include NaN
+        match aggregate("sum", "f") {
+            (Some(Value::FLOAT64(l)), Some(Value::FLOAT64(r))) => assert!(l.is_nan() && r.is_nan()),
+            _ => assert!(false, "Expected FLOAT64 sums"),
+        }
+
+        let text = Schema::make_one_attr("t", false, Type::TEXT);
+        match registry.call_aggregate("sum", ColumnExpr::named("t")).unwrap()
+            .bind(&allocator::GLOBAL, &text)
+        {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
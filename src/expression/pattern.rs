@@ -0,0 +1,381 @@
+//! Pattern matching predicates of TEXT values: SQL `LIKE` patterns and (with the `regex`
+//! feature) regular expressions.
+
+#[cfg(feature = "regex")]
+use std::cell::RefCell;
+#[cfg(feature = "regex")]
+use std::collections::HashMap;
+
+#[cfg(feature = "regex")]
+use ::regex::Regex;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Boolean, Type, Value};
+
+/// Element of a LIKE pattern
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LikeToken {
+    Char(char),
+    /// `_`, any single character
+    One,
+    /// `%`, any (possibly empty) sequence of characters
+    Many,
+}
+
+/// Compiled LIKE pattern. Patterns with `%` only at the start and / or end are matched with a
+/// substring search.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LikePattern {
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    /// Patterns with `_` or `%` between other characters, matched character by character
+    General(Vec<LikeToken>),
+}
+
+impl LikePattern {
+    /// Compile a pattern where `%` matches any sequence of characters, `_` any one character and
+    /// `\` escapes the next character
+    pub fn new(pattern: &str) -> LikePattern {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '%'     => LikeToken::Many,
+                '_'     => LikeToken::One,
+                // Trailing escape matches itself
+                '\\'    => LikeToken::Char(chars.next().unwrap_or('\\')),
+                c       => LikeToken::Char(c),
+            });
+        }
+
+        // Consecutive % are the same as one
+        tokens.dedup_by(|l, r| *l == LikeToken::Many && *r == LikeToken::Many);
+
+        let leading = tokens.first() == Some(&LikeToken::Many);
+        let trailing = tokens.len() > 1 && tokens.last() == Some(&LikeToken::Many);
+        let inner = &tokens[leading as usize .. tokens.len() - trailing as usize];
+
+        let literal: Option<String> = inner.iter()
+            .map(|t| match *t { LikeToken::Char(c) => Some(c), _ => None })
+            .collect();
+
+        match (literal, leading, trailing) {
+            (Some(s), false, false) => LikePattern::Exact(s),
+            (Some(s), false, true)  => LikePattern::Prefix(s),
+            (Some(s), true, false)  => LikePattern::Suffix(s),
+            (Some(s), true, true)   => LikePattern::Contains(s),
+            (None, _, _)            => LikePattern::General(tokens),
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        match *self {
+            LikePattern::Exact(ref s)       => text == s,
+            LikePattern::Prefix(ref s)      => text.starts_with(s.as_str()),
+            LikePattern::Suffix(ref s)      => text.ends_with(s.as_str()),
+            LikePattern::Contains(ref s)    => text.contains(s.as_str()),
+            LikePattern::General(ref tokens) => {
+                let chars: Vec<char> = text.chars().collect();
+                like_match(tokens, &chars)
+            },
+        }
+    }
+}
+
+/// Match with backtracking to the last `%`, which only ever needs to consume more characters
+fn like_match(tokens: &[LikeToken], text: &[char]) -> bool {
+    let (mut t, mut c) = (0, 0);
+    // Token after the last %, and the text position it's matched from
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while c < text.len() {
+        match tokens.get(t) {
+            Some(&LikeToken::Many) => {
+                backtrack = Some((t + 1, c));
+                t += 1;
+                continue
+            },
+            Some(&LikeToken::One) => {
+                t += 1;
+                c += 1;
+                continue
+            },
+            Some(&LikeToken::Char(ch)) if ch == text[c] => {
+                t += 1;
+                c += 1;
+                continue
+            },
+            _ => (),
+        }
+
+        match backtrack {
+            Some((after, from)) => {
+                backtrack = Some((after, from + 1));
+                t = after;
+                c = from + 1;
+            },
+            None => return false,
+        }
+    }
+
+    tokens[t ..].iter().all(|t| *t == LikeToken::Many)
+}
+
+/// `input LIKE pattern`, NULL for NULL values
+pub struct LikeExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub pattern: LikePattern,
+}
+
+impl<'a> LikeExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, pattern: &str) -> LikeExpr<'a> {
+        LikeExpr { input: Box::new(input), pattern: LikePattern::new(pattern) }
+    }
+}
+
+/// BOOLEAN result of `test` on each TEXT row of the input
+struct MatchBound<'a, 'e, F> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    name: &'static str,
+    test: F,
+}
+
+fn bind_match<'a: 'b, 'b, F>(alloc: &'a Allocator, input: Box<BoundExpr<'a> + 'b>,
+                             name: &'static str, test: F)
+    -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    where F: Fn(&str) -> bool + 'b
+{
+    let schema = {
+        let attr = bound_attribute(&*input)?;
+        if attr.dtype != Type::TEXT {
+            return Err(DBError::ExpressionInputType(
+                format!("{} expected TEXT but {} is {}", name, attr.name, attr.dtype)))
+        }
+
+        Schema::from_attr(Attribute {
+            name: attr.name.clone(),
+            nullable: attr.nullable,
+            dtype: Type::BOOLEAN,
+        })
+    };
+
+    Ok(Box::new(MatchBound { alloc: alloc, schema: schema, input: input, name: name,
+                             test: test }))
+}
+
+impl<'b> Expr<'b> for LikeExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+        let pattern = self.pattern.clone();
+        bind_match(alloc, input, "LIKE", move |text| pattern.matches(text))
+    }
+}
+
+impl<'alloc, 'e, F: Fn(&str) -> bool> BoundExpr<'alloc> for MatchBound<'alloc, 'e, F> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(self.name)
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Boolean>()?;
+
+            for row in 0 .. rows {
+                match column_value(src, row)? {
+                    Value::TEXT(text) => {
+                        dst.values[row] = (self.test)(text);
+                        if nullable {
+                            dst.nulls[row] = 0;
+                        }
+                    },
+                    _ => dst.nulls[row] = 1,
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Compiled regular expressions of a query, so a pattern used by several expressions is only
+/// compiled once
+#[cfg(feature = "regex")]
+#[derive(Default)]
+pub struct RegexCache {
+    compiled: RefCell<HashMap<String, Regex>>,
+}
+
+#[cfg(feature = "regex")]
+impl RegexCache {
+    pub fn new() -> RegexCache {
+        RegexCache::default()
+    }
+
+    /// Compiled `pattern`, an error if it isn't a valid regular expression
+    pub fn get(&self, pattern: &str) -> Result<Regex, DBError> {
+        if let Some(regex) = self.compiled.borrow().get(pattern) {
+            return Ok(regex.clone())
+        }
+
+        let regex = Regex::new(pattern)
+            .map_err(|e| DBError::QueryInvalid(format!("regex '{}': {}", pattern, e)))?;
+
+        self.compiled.borrow_mut().insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    }
+
+    /// Number of compiled patterns
+    pub fn len(&self) -> usize {
+        self.compiled.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// `regexp_matches(input, pattern)`: true if the pattern matches anywhere in the TEXT value,
+/// NULL for NULL values
+#[cfg(feature = "regex")]
+pub struct RegexMatchExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub regex: Regex,
+}
+
+#[cfg(feature = "regex")]
+impl<'a> RegexMatchExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, pattern: &str) -> Result<RegexMatchExpr<'a>, DBError> {
+        RegexMatchExpr::cached(input, pattern, &RegexCache::new())
+    }
+
+    /// Match with the pattern compiled by the query's `cache`
+    pub fn cached<T: Expr<'a> + 'a>(input: T, pattern: &str, cache: &RegexCache)
+        -> Result<RegexMatchExpr<'a>, DBError>
+    {
+        Ok(RegexMatchExpr { input: Box::new(input), regex: cache.get(pattern)? })
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<'b> Expr<'b> for RegexMatchExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+        let regex = self.regex.clone();
+        bind_match(alloc, input, "regexp_matches", move |text| regex.is_match(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+
+    fn make_block<'a>() -> Block<'a> {
+        let schema = Schema::make_one_attr("s", true, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("hello world")
+                .add_row().set_null(true)
+                .add_row().set("50% off")
+                .add_row().set("")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    /// Matching rows, None for NULL rows
+    fn eval<'e, E: Expr<'e>>(block: &Block, expr: E) -> Vec<Option<bool>> {
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(block, block.rows()).unwrap();
+        let data = column_row_data::<Boolean>(out.column(0).unwrap()).unwrap();
+
+        (0 .. block.rows())
+            .map(|row| if data.nulls[row] != 0 { None } else { Some(data.values[row]) })
+            .collect()
+    }
+
+    // Fast paths are detected, the general matcher backtracks
+    #[test]
+    fn like_patterns() {
+        assert_eq!(LikePattern::new("abc"), LikePattern::Exact("abc".to_string()));
+        assert_eq!(LikePattern::new("abc%%"), LikePattern::Prefix("abc".to_string()));
+        assert_eq!(LikePattern::new("%abc"), LikePattern::Suffix("abc".to_string()));
+        assert_eq!(LikePattern::new("%a\\%c%"), LikePattern::Contains("a%c".to_string()));
+        assert!(LikePattern::new("%").matches(""));
+
+        let general = LikePattern::new("a%b_c%");
+        assert!(general.matches("abxc"));
+        assert!(general.matches("aXbbbYcZ"));
+        assert!(!general.matches("abc"));
+        assert!(LikePattern::new("_é_").matches("héj"));
+        assert!(!LikePattern::new("a%a").matches("ab"));
+    }
+
+    // LIKE of each row, NULL for NULL rows
+    #[test]
+    fn like_rows() {
+        let block = make_block();
+        let like = |pattern| LikeExpr::new(ColumnExpr::named("s"), pattern);
+
+        assert_eq!(eval(&block, like("hello%")), vec![Some(true), None, Some(false), Some(false)]);
+        assert_eq!(eval(&block, like("%\\%%")), vec![Some(false), None, Some(true), Some(false)]);
+        assert_eq!(eval(&block, like("%o_w%")), vec![Some(true), None, Some(false), Some(false)]);
+        assert_eq!(eval(&block, like("%")), vec![Some(true), None, Some(true), Some(true)]);
+    }
+
+    // Patterns are compiled once per cache, invalid patterns are an error
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_rows() {
+        let block = make_block();
+        let cache = RegexCache::new();
+
+        let expr = RegexMatchExpr::cached(ColumnExpr::named("s"), "^[0-9]+%", &cache).unwrap();
+        assert_eq!(eval(&block, expr), vec![Some(false), None, Some(true), Some(false)]);
+
+        let expr = RegexMatchExpr::cached(ColumnExpr::named("s"), "^[0-9]+%", &cache).unwrap();
+        assert_eq!(eval(&block, expr), vec![Some(false), None, Some(true), Some(false)]);
+        assert_eq!(cache.len(), 1);
+
+        match RegexMatchExpr::new(ColumnExpr::named("s"), "(") {
+            Err(DBError::QueryInvalid(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::fuzzy::Phonetic;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{NULL_VALUE, Type, Value};
+use ::util::copy_value::ValueSetter;
+
+/// Phonetic code of a TEXT value, eg. `soundex(name)`. NULL for NULL values.
+pub struct PhoneticExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub encoding: Phonetic,
+}
+
+struct PhoneticBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    encoding: Phonetic,
+}
+
+impl<'a> PhoneticExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, encoding: Phonetic) -> PhoneticExpr<'a> {
+        PhoneticExpr { input: Box::new(input), encoding: encoding }
+    }
+
+    pub fn soundex<T: Expr<'a> + 'a>(input: T) -> PhoneticExpr<'a> {
+        PhoneticExpr::new(input, Phonetic::Soundex)
+    }
+
+    pub fn metaphone<T: Expr<'a> + 'a>(input: T) -> PhoneticExpr<'a> {
+        PhoneticExpr::new(input, Phonetic::Metaphone)
+    }
+}
+
+impl<'b> Expr<'b> for PhoneticExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+
+        let schema = {
+            let attr = bound_attribute(&*input)?;
+
+            if attr.dtype != Type::TEXT {
+                return Err(DBError::ExpressionInputType(
+                    format!("{} expected TEXT but {} is {}", self.encoding.name(), attr.name,
+                            attr.dtype)))
+            }
+
+            Schema::from_attr(Attribute {
+                name: format!("{}({})", self.encoding.name(), attr.name),
+                nullable: attr.nullable,
+                dtype: Type::TEXT,
+            })
+        };
+
+        Ok(Box::new(PhoneticBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            encoding: self.encoding,
+        }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for PhoneticBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(self.encoding.name())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                match column_value(src, row)? {
+                    Value::TEXT(text) => {
+                        self.encoding.encode(text).set_row(dst, row)?;
+                        if nullable {
+                            dst.nulls_mut()?[row] = 0;
+                        }
+                    },
+                    _ => NULL_VALUE.set_row(dst, row)?,
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+
+    // Codes of each row, NULL for NULL names
+    #[test]
+    fn phonetic_rows() {
+        let schema = Schema::make_one_attr("name", true, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("Robert")
+                .add_row().set_null(true)
+                .add_row().set("Rupert")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let bound = PhoneticExpr::soundex(ColumnExpr::named("name"))
+            .bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert_eq!(bound.schema()[0].name, "soundex(name)");
+
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        let value = |row| column_value(out.column(0).unwrap(), row).unwrap();
+        assert!(value(0) == Value::TEXT("R163"));
+        assert!(value(1) == Value::NULL);
+        assert!(value(2) == Value::TEXT("R163"));
+
+        let schema = Schema::make_one_attr("n", false, Type::INT64);
+        match PhoneticExpr::metaphone(ColumnExpr::named("n")).bind(&allocator::GLOBAL, &schema) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
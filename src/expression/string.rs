@@ -0,0 +1,381 @@
+use ::allocator::Allocator;
+use ::block::{Block, Column, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{NULL_VALUE, Type, Value};
+use ::util::copy_value::ValueSetter;
+
+/// String function of a TEXT value and constant arguments. Positions and lengths are in
+/// characters, not bytes.
+#[derive(Clone, PartialEq, Debug)]
+pub enum StringFunc {
+    Lower,
+    Upper,
+    /// Strip leading and trailing whitespace
+    Trim,
+    /// Number of characters, as INT64
+    Length,
+    /// Characters from the 1 based `start` position, `length` of them or up to the end. Like in
+    /// SQL, a `start` before the first character still counts towards the length.
+    Substr { start: i64, length: Option<i64> },
+    /// Every occurrence of `from` replaced with `to`
+    Replace { from: String, to: String },
+    /// BOOLEAN, true if the value starts with the prefix
+    StartsWith(String),
+}
+
+impl StringFunc {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            StringFunc::Lower           => "lower",
+            StringFunc::Upper           => "upper",
+            StringFunc::Trim            => "trim",
+            StringFunc::Length          => "length",
+            StringFunc::Substr { .. }   => "substr",
+            StringFunc::Replace { .. }  => "replace",
+            StringFunc::StartsWith(_)   => "starts_with",
+        }
+    }
+
+    fn dtype(&self) -> Type {
+        match *self {
+            StringFunc::Length          => Type::INT64,
+            StringFunc::StartsWith(_)   => Type::BOOLEAN,
+            _                           => Type::TEXT,
+        }
+    }
+
+    /// Set the `row` of `col` to the function of `text`
+    fn set_row(&self, text: &str, col: &mut Column, row: RowOffset) -> Result<(), DBError> {
+        match *self {
+            StringFunc::Lower       => text.to_lowercase().set_row(col, row),
+            StringFunc::Upper       => text.to_uppercase().set_row(col, row),
+            StringFunc::Trim        => text.trim().set_row(col, row),
+            StringFunc::Length      => (text.chars().count() as i64).set_row(col, row),
+            StringFunc::Substr { start, length } =>
+                substr(text, start, length).set_row(col, row),
+            StringFunc::Replace { ref from, ref to } if !from.is_empty() =>
+                text.replace(from.as_str(), to).set_row(col, row),
+            StringFunc::Replace { .. } =>
+                text.set_row(col, row),
+            StringFunc::StartsWith(ref prefix) =>
+                text.starts_with(prefix.as_str()).set_row(col, row),
+        }
+    }
+}
+
+/// Characters [start, start + length) of `text`, with 1 based positions
+fn substr(text: &str, start: i64, length: Option<i64>) -> &str {
+    let begin = start.saturating_sub(1);
+    let end = length.map_or(i64::max_value(), |l| begin.saturating_add(l.max(0)));
+
+    // Byte offsets of the first character at or after each (clamped) position
+    let offset = |pos: i64| -> usize {
+        if pos <= 0 {
+            return 0
+        }
+        text.char_indices().nth(pos as usize).map_or(text.len(), |(i, _)| i)
+    };
+
+    let (from, to) = (offset(begin), offset(end));
+    if from < to { &text[from .. to] } else { "" }
+}
+
+/// `func(input)` of a TEXT input, NULL for NULL values
+pub struct StringExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub func: StringFunc,
+}
+
+/// `concat(lhs, rhs)` of two TEXT inputs, NULL if either is NULL
+pub struct ConcatExpr<'a> {
+    pub lhs: Box<Expr<'a> + 'a>,
+    pub rhs: Box<Expr<'a> + 'a>,
+}
+
+struct StringBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    func: StringFunc,
+}
+
+struct ConcatBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    lhs: Box<BoundExpr<'a> + 'e>,
+    rhs: Box<BoundExpr<'a> + 'e>,
+}
+
+fn expect_text(expr: &str, attr: &Attribute) -> Result<(), DBError> {
+    if attr.dtype != Type::TEXT {
+        Err(DBError::ExpressionInputType(
+            format!("{} expected TEXT but {} is {}", expr, attr.name, attr.dtype)))
+    } else {
+        Ok(())
+    }
+}
+
+impl<'a> StringExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, func: StringFunc) -> StringExpr<'a> {
+        StringExpr { input: Box::new(input), func: func }
+    }
+
+    pub fn lower<T: Expr<'a> + 'a>(input: T) -> StringExpr<'a> {
+        StringExpr::new(input, StringFunc::Lower)
+    }
+
+    pub fn upper<T: Expr<'a> + 'a>(input: T) -> StringExpr<'a> {
+        StringExpr::new(input, StringFunc::Upper)
+    }
+
+    pub fn trim<T: Expr<'a> + 'a>(input: T) -> StringExpr<'a> {
+        StringExpr::new(input, StringFunc::Trim)
+    }
+
+    pub fn length<T: Expr<'a> + 'a>(input: T) -> StringExpr<'a> {
+        StringExpr::new(input, StringFunc::Length)
+    }
+
+    pub fn substr<T: Expr<'a> + 'a>(input: T, start: i64, length: Option<i64>) -> StringExpr<'a> {
+        StringExpr::new(input, StringFunc::Substr { start: start, length: length })
+    }
+
+    pub fn replace<T, F, R>(input: T, from: F, to: R) -> StringExpr<'a>
+        where T: Expr<'a> + 'a, F: Into<String>, R: Into<String>
+    {
+        StringExpr::new(input, StringFunc::Replace { from: from.into(), to: to.into() })
+    }
+
+    pub fn starts_with<T, P>(input: T, prefix: P) -> StringExpr<'a>
+        where T: Expr<'a> + 'a, P: Into<String>
+    {
+        StringExpr::new(input, StringFunc::StartsWith(prefix.into()))
+    }
+}
+
+impl<'a> ConcatExpr<'a> {
+    pub fn new<L: Expr<'a> + 'a, R: Expr<'a> + 'a>(lhs: L, rhs: R) -> ConcatExpr<'a> {
+        ConcatExpr { lhs: Box::new(lhs), rhs: Box::new(rhs) }
+    }
+}
+
+impl<'b> Expr<'b> for StringExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+
+        let schema = {
+            let attr = bound_attribute(&*input)?;
+            expect_text(self.func.name(), attr)?;
+
+            Schema::from_attr(Attribute {
+                name: format!("{}({})", self.func.name(), attr.name),
+                nullable: attr.nullable,
+                dtype: self.func.dtype(),
+            })
+        };
+
+        Ok(Box::new(StringBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            func: self.func.clone(),
+        }))
+    }
+}
+
+impl<'b> Expr<'b> for ConcatExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let lhs = self.lhs.bind(alloc, input_schema)?;
+        let rhs = self.rhs.bind(alloc, input_schema)?;
+
+        let schema = {
+            let l = bound_attribute(&*lhs)?;
+            let r = bound_attribute(&*rhs)?;
+            expect_text("concat", l)?;
+            expect_text("concat", r)?;
+
+            Schema::from_attr(Attribute {
+                name: format!("concat({}, {})", l.name, r.name),
+                nullable: l.nullable || r.nullable,
+                dtype: Type::TEXT,
+            })
+        };
+
+        Ok(Box::new(ConcatBound { alloc: alloc, schema: schema, lhs: lhs, rhs: rhs }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for StringBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(self.func.name())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let src = input.column(0).unwrap();
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                match column_value(src, row)? {
+                    Value::TEXT(text) => {
+                        self.func.set_row(text, dst, row)?;
+                        if nullable {
+                            dst.nulls_mut()?[row] = 0;
+                        }
+                    },
+                    _ => NULL_VALUE.set_row(dst, row)?,
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for ConcatBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from("concat")
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.lhs, &*self.rhs]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let lhs = self.lhs.evaluate(view, rows)?;
+        let rhs = self.rhs.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let (l, r) = (lhs.column(0).unwrap(), rhs.column(0).unwrap());
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap();
+            let mut buf = String::new();
+
+            for row in 0 .. rows {
+                match (column_value(l, row)?, column_value(r, row)?) {
+                    (Value::TEXT(l), Value::TEXT(r)) => {
+                        buf.clear();
+                        buf.push_str(l);
+                        buf.push_str(r);
+                        buf.as_str().set_row(dst, row)?;
+                        if nullable {
+                            dst.nulls_mut()?[row] = 0;
+                        }
+                    },
+                    _ => NULL_VALUE.set_row(dst, row)?,
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "s".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "t".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("  Grüße Welt ").set("!")
+                .add_row().set_null(true).set("x")
+                .add_row().set("abcabc").set("")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    fn eval<'e, E: Expr<'e>>(block: &Block, expr: E) -> Vec<String> {
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(block, block.rows()).unwrap();
+
+        (0 .. block.rows()).map(|row| match column_value(out.column(0).unwrap(), row).unwrap() {
+            Value::TEXT(v)      => v.to_string(),
+            Value::INT64(v)     => v.to_string(),
+            Value::BOOLEAN(v)   => v.to_string(),
+            _                   => String::from("NULL"),
+        }).collect()
+    }
+
+    // Each function of each row, NULL for NULL rows, positions count characters
+    #[test]
+    fn string_functions() {
+        let block = make_block();
+        let s = || ColumnExpr::named("s");
+
+        let rows = |expr: StringExpr<'static>| eval(&block, expr);
+
+        assert_eq!(rows(StringExpr::upper(s())), vec!["  GRÜSSE WELT ", "NULL", "ABCABC"]);
+        assert_eq!(rows(StringExpr::lower(s())), vec!["  grüße welt ", "NULL", "abcabc"]);
+        assert_eq!(rows(StringExpr::trim(s())), vec!["Grüße Welt", "NULL", "abcabc"]);
+        assert_eq!(rows(StringExpr::length(s())), vec!["13", "NULL", "6"]);
+        assert_eq!(rows(StringExpr::substr(s(), 5, Some(3))), vec!["üße", "NULL", "bc"]);
+        assert_eq!(rows(StringExpr::substr(s(), 0, Some(3))), vec!["  ", "NULL", "ab"]);
+        assert_eq!(rows(StringExpr::substr(s(), 12, None)), vec!["t ", "NULL", ""]);
+        assert_eq!(rows(StringExpr::replace(s(), "bc", "-")),
+                   vec!["  Grüße Welt ", "NULL", "a-a-"]);
+        assert_eq!(rows(StringExpr::starts_with(s(), "abc")), vec!["false", "NULL", "true"]);
+
+        let bound = StringExpr::length(s()).bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert_eq!(bound.schema()[0].name, "length(s)");
+        assert!(bound.schema()[0].dtype == Type::INT64);
+    }
+
+    // NULL if either side is NULL, only TEXT inputs
+    #[test]
+    fn concat_rows() {
+        let block = make_block();
+        let trimmed = StringExpr::trim(ColumnExpr::named("s"));
+        let expr = ConcatExpr::new(trimmed, ColumnExpr::named("t"));
+        assert_eq!(eval(&block, expr), vec!["Grüße Welt!", "NULL", "abcabc"]);
+
+        let schema = Schema::make_one_attr("n", false, Type::INT64);
+        let expr = ConcatExpr::new(ColumnExpr::named("n"), ColumnExpr::named("n"));
+        match expr.bind(&allocator::GLOBAL, &schema) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
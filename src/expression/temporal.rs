@@ -0,0 +1,256 @@
+use ::allocator::Allocator;
+use ::block::{Block, View, column_nulls, column_row_data};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
+use ::util::temporal;
+
+/// `timestamp + interval` or `timestamp - interval`
+pub struct IntervalArithExpr<'a> {
+    pub ts: Box<Expr<'a> + 'a>,
+    pub interval: Box<Expr<'a> + 'a>,
+    pub subtract: bool,
+}
+
+/// `timestamp - timestamp`, producing an INTERVAL of days and time
+pub struct TimestampDiffExpr<'a> {
+    pub lhs: Box<Expr<'a> + 'a>,
+    pub rhs: Box<Expr<'a> + 'a>,
+}
+
+struct IntervalArithBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    ts: Box<BoundExpr<'a> + 'e>,
+    interval: Box<BoundExpr<'a> + 'e>,
+    subtract: bool,
+}
+
+struct TimestampDiffBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    lhs: Box<BoundExpr<'a> + 'e>,
+    rhs: Box<BoundExpr<'a> + 'e>,
+}
+
+fn expect_type(expr: &str, attr: &Attribute, dtype: Type) -> Result<(), DBError> {
+    if attr.dtype != dtype {
+        Err(DBError::ExpressionInputType(format!("{} expected {} but {} is {}",
+            expr, dtype, attr.name, attr.dtype)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Output schema of a binary temporal expression. NULL if either input is NULL.
+fn binary_schema(lhs: &Attribute, rhs: &Attribute, dtype: Type) -> Schema {
+    Schema::from_attr(Attribute {
+        name: lhs.name.clone(),
+        nullable: lhs.nullable || rhs.nullable,
+        dtype: dtype,
+    })
+}
+
+impl<'a> IntervalArithExpr<'a> {
+    pub fn add<T: Expr<'a> + 'a, I: Expr<'a> + 'a>(ts: T, interval: I) -> IntervalArithExpr<'a> {
+        IntervalArithExpr { ts: Box::new(ts), interval: Box::new(interval), subtract: false }
+    }
+
+    pub fn sub<T: Expr<'a> + 'a, I: Expr<'a> + 'a>(ts: T, interval: I) -> IntervalArithExpr<'a> {
+        IntervalArithExpr { ts: Box::new(ts), interval: Box::new(interval), subtract: true }
+    }
+}
+
+impl<'a> TimestampDiffExpr<'a> {
+    pub fn new<L: Expr<'a> + 'a, R: Expr<'a> + 'a>(lhs: L, rhs: R) -> TimestampDiffExpr<'a> {
+        TimestampDiffExpr { lhs: Box::new(lhs), rhs: Box::new(rhs) }
+    }
+}
+
+impl<'b> Expr<'b> for IntervalArithExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let ts = self.ts.bind(alloc, input_schema)?;
+        let interval = self.interval.bind(alloc, input_schema)?;
+
+        let schema = {
+            let name = if self.subtract { "TIMESTAMP - INTERVAL" } else { "TIMESTAMP + INTERVAL" };
+            let t = bound_attribute(&*ts)?;
+            let i = bound_attribute(&*interval)?;
+
+            expect_type(name, t, Type::TIMESTAMP)?;
+            expect_type(name, i, Type::INTERVAL)?;
+            binary_schema(t, i, Type::TIMESTAMP)
+        };
+
+        Ok(Box::new(IntervalArithBound {
+            alloc: alloc,
+            schema: schema,
+            ts: ts,
+            interval: interval,
+            subtract: self.subtract,
+        }))
+    }
+}
+
+impl<'b> Expr<'b> for TimestampDiffExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let lhs = self.lhs.bind(alloc, input_schema)?;
+        let rhs = self.rhs.bind(alloc, input_schema)?;
+
+        let schema = {
+            let l = bound_attribute(&*lhs)?;
+            let r = bound_attribute(&*rhs)?;
+
+            expect_type("TIMESTAMP - TIMESTAMP", l, Type::TIMESTAMP)?;
+            expect_type("TIMESTAMP - TIMESTAMP", r, Type::TIMESTAMP)?;
+            binary_schema(l, r, Type::INTERVAL)
+        };
+
+        Ok(Box::new(TimestampDiffBound { alloc: alloc, schema: schema, lhs: lhs, rhs: rhs }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for IntervalArithBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(if self.subtract { "TIMESTAMP - INTERVAL" } else { "TIMESTAMP + INTERVAL" })
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.ts, &*self.interval]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let ts = self.ts.evaluate(view, rows)?;
+        let interval = self.interval.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let (t_col, i_col) = (ts.column(0).unwrap(), interval.column(0).unwrap());
+            let t = column_row_data::<Timestamp>(t_col)?;
+            let i = column_row_data::<Interval>(i_col)?;
+
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Timestamp>()?;
+            if nullable {
+                binary_nulls(column_nulls(t_col), column_nulls(i_col), &mut dst.nulls[.. rows]);
+            }
+
+            for idx in 0 .. rows {
+                if !nullable || dst.nulls[idx] == 0 {
+                    dst.values[idx] = if self.subtract {
+                        temporal::sub_interval(t.values[idx], &i.values[idx])?
+                    } else {
+                        temporal::add_interval(t.values[idx], &i.values[idx])?
+                    };
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for TimestampDiffBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from("TIMESTAMP - TIMESTAMP")
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.lhs, &*self.rhs]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let lhs = self.lhs.evaluate(view, rows)?;
+        let rhs = self.rhs.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let (l_col, r_col) = (lhs.column(0).unwrap(), rhs.column(0).unwrap());
+            let l = column_row_data::<Timestamp>(l_col)?;
+            let r = column_row_data::<Timestamp>(r_col)?;
+
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Interval>()?;
+            if nullable {
+                binary_nulls(column_nulls(l_col), column_nulls(r_col), &mut dst.nulls[.. rows]);
+            }
+
+            for idx in 0 .. rows {
+                if !nullable || dst.nulls[idx] == 0 {
+                    dst.values[idx] = temporal::timestamp_diff(l.values[idx], r.values[idx])?;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+    use ::util::temporal::{days_from_civil, MICROS_PER_DAY};
+
+    #[test]
+    fn timestamp_plus_interval() {
+        let start = days_from_civil(2018, 1, 31) * MICROS_PER_DAY;
+
+        let block = {
+            let attrs = vec![
+                Attribute{name: "ts".to_string(), nullable: false, dtype: Type::TIMESTAMP},
+                Attribute{name: "iv".to_string(), nullable: false, dtype: Type::INTERVAL},
+            ];
+
+            let schema = Schema::from_vec(attrs).unwrap();
+            let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+            {
+                let status = TableAppender::new(&mut table)
+                    .add_row()
+                        .set(Value::TIMESTAMP(start))
+                        .set("1 day".parse::<IntervalValue>().unwrap())
+                    .add_row()
+                        .set(Value::TIMESTAMP(start))
+                        .set("1 month".parse::<IntervalValue>().unwrap())
+                    .done();
+
+                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            }
+
+            table.take().unwrap()
+        };
+
+        let expr = IntervalArithExpr::add(ColumnExpr::named("ts"), ColumnExpr::named("iv"));
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+
+        let rows = column_row_data::<Timestamp>(out.column(0).unwrap()).unwrap();
+        assert_eq!(rows.values[0], days_from_civil(2018, 2, 1) * MICROS_PER_DAY);
+        assert_eq!(rows.values[1], days_from_civil(2018, 2, 28) * MICROS_PER_DAY);
+
+        // Interval on the wrong side
+        let expr = IntervalArithExpr::add(ColumnExpr::named("iv"), ColumnExpr::named("ts"));
+        assert!(expr.bind(&allocator::GLOBAL, block.schema()).is_err());
+    }
+}
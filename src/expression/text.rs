@@ -0,0 +1,151 @@
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::index::tokenize;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Boolean, Type, Value};
+
+/// `text_matches(column, query)`: true if the TEXT column contains all the words of the query (see
+/// `index::tokenize`), NULL for NULL rows.
+///
+/// Scans filtered by it can be answered from a `TextIndex` on the column instead (see
+/// `LogicalPlan::use_text_index`).
+pub struct TextMatchesExpr {
+    pub column: String,
+    pub query: String,
+}
+
+struct TextMatchesBound<'a> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    pos: usize,
+    words: Vec<String>,
+}
+
+impl TextMatchesExpr {
+    pub fn new<C: Into<String>, Q: Into<String>>(column: C, query: Q) -> TextMatchesExpr {
+        TextMatchesExpr { column: column.into(), query: query.into() }
+    }
+}
+
+impl<'b> Expr<'b> for TextMatchesExpr {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let pos = input_schema.exists_ok(&self.column)?;
+        let attr = &input_schema[pos];
+
+        if attr.dtype != Type::TEXT {
+            return Err(DBError::ExpressionInputType(
+                format!("text_matches expected TEXT but {} is {}", attr.name, attr.dtype)))
+        }
+
+        let schema = Schema::from_attr(Attribute {
+            name: attr.name.clone(),
+            nullable: attr.nullable,
+            dtype: Type::BOOLEAN,
+        });
+
+        let mut words = tokenize(&self.query);
+        words.sort();
+        words.dedup();
+
+        Ok(Box::new(TextMatchesBound { alloc: alloc, schema: schema, pos: pos, words: words }))
+    }
+
+    fn text_match(&self) -> Option<(&str, &str)> {
+        Some((&self.column, &self.query))
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for TextMatchesBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        format!("text_matches {}", self.schema[0].name)
+    }
+
+    fn input_positions(&self) -> Vec<usize> {
+        vec![self.pos]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let src = view.column(self.pos).ok_or(DBError::make_column_unknown_pos(self.pos))?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Boolean>()?;
+
+            for row in 0 .. rows {
+                let text = match column_value(src, row)? {
+                    Value::TEXT(text) => text,
+                    _ => {
+                        if nullable {
+                            dst.nulls[row] = 1;
+                        }
+                        continue
+                    },
+                };
+
+                let found = tokenize(text);
+                if nullable {
+                    dst.nulls[row] = 0;
+                }
+                dst.values[row] = !self.words.is_empty()
+                    && self.words.iter().all(|word| found.contains(word));
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::table::{Table, TableAppender};
+
+    // Rows match if they have all the words, NULL if the text is NULL
+    #[test]
+    fn text_matches_rows() {
+        let schema = Schema::make_one_attr("msg", true, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("Disk full on host-1")
+                .add_row().set_null(true)
+                .add_row().set("host-1 is FULL")
+                .add_row().set("disk ok")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let bound = TextMatchesExpr::new("msg", "full HOST")
+            .bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert!(bound.schema()[0].dtype == Type::BOOLEAN);
+
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        let rows = column_row_data::<Boolean>(out.column(0).unwrap()).unwrap();
+        assert!(rows.values[0]);
+        assert!(rows.values[2]);
+        assert!(!rows.values[3]);
+        assert_eq!(rows.nulls[.. 4], [0, 1, 0, 0]);
+
+        let schema = Schema::make_one_attr("n", false, Type::INT32);
+        match TextMatchesExpr::new("n", "1").bind(&allocator::GLOBAL, &schema) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
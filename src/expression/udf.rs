@@ -0,0 +1,513 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use ::allocator::Allocator;
+use ::block::{Block, Column, RefColumn, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::expression::convert::coerce;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value};
+use ::util::copy_value::ValueSetter;
+
+/// Body of a scalar function: the argument columns (converted to the argument types), the number
+/// of rows and the output column. Every output row has to be set, including its NULL flag if the
+/// output is nullable.
+pub type ScalarFn = Fn(&[&RefColumn], RowOffset, &mut Column) -> Result<(), DBError>;
+
+/// User defined scalar function
+pub struct ScalarUdf {
+    pub name: String,
+    pub args: Vec<Type>,
+    pub output: Type,
+    pub nullable: bool,
+    /// Same output for the same arguments, true by default. Queries calling non deterministic
+    /// functions aren't kept in result caches.
+    pub deterministic: bool,
+    func: Box<ScalarFn>,
+}
+
+/// User defined aggregate function of one input. Partial states (eg. of each block) are merged
+/// into the state of the group.
+pub trait Aggregate {
+    type State: 'static;
+
+    /// Output type for the input type, or an error if the input type isn't supported
+    fn output(&self, input: &Type) -> Result<Type, DBError>;
+
+    /// State of an empty group
+    fn init(&self) -> Self::State;
+
+    /// Add a value of the group, NULL values are skipped
+    fn update(&self, state: &mut Self::State, value: &Value) -> Result<(), DBError>;
+
+    /// Add the first `rows` values of the column, all of the same group. By default each non NULL
+    /// value is added with `update()` (see `update_rows`), functions can add the whole column at
+    /// once (eg. with the `kernels`).
+    fn update_column(&self, state: &mut Self::State, col: &RefColumn, rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        update_rows(self, state, col, rows)
+    }
+
+    fn merge(&self, state: &mut Self::State, other: Self::State) -> Result<(), DBError>;
+
+    /// Result of the group (it can be NULL)
+    fn finalize<'s>(&self, state: &'s Self::State) -> Result<Value<'s>, DBError>;
+}
+
+/// `Aggregate` without its state type, as kept in a `FunctionRegistry`
+pub trait AggregateUdf {
+    fn output(&self, input: &Type) -> Result<Type, DBError>;
+
+    fn init(&self) -> Box<Any>;
+
+    fn update(&self, state: &mut Any, value: &Value) -> Result<(), DBError>;
+
+    fn update_column(&self, state: &mut Any, col: &RefColumn, rows: RowOffset)
+        -> Result<(), DBError>;
+
+    fn merge(&self, state: &mut Any, other: Box<Any>) -> Result<(), DBError>;
+
+    /// Set `row` of `dst` to the result of the group
+    fn finalize(&self, state: &Any, dst: &mut Column, row: RowOffset) -> Result<(), DBError>;
+}
+
+/// Scalar and aggregate functions by (case insensitive) name
+#[derive(Default)]
+pub struct FunctionRegistry {
+    scalars: BTreeMap<String, Rc<ScalarUdf>>,
+    aggregates: BTreeMap<String, Rc<AggregateUdf>>,
+}
+
+/// Call of a `ScalarUdf`, named `name(args..)`
+pub struct ScalarCallExpr<'a> {
+    pub func: Rc<ScalarUdf>,
+    pub args: Vec<Box<Expr<'a> + 'a>>,
+}
+
+/// Call of an `AggregateUdf`, named `name(arg)`. Used as an aggregate of a plan `Aggregate`;
+/// evaluated on its own, it aggregates all the input rows into a single row.
+pub struct AggregateCallExpr<'a> {
+    pub name: String,
+    pub func: Rc<AggregateUdf>,
+    pub arg: Box<Expr<'a> + 'a>,
+}
+
+struct ScalarCallBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    func: Rc<ScalarUdf>,
+    args: Vec<Box<BoundExpr<'a> + 'e>>,
+}
+
+struct AggregateCallBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    func: Rc<AggregateUdf>,
+    arg: Box<BoundExpr<'a> + 'e>,
+}
+
+impl ScalarUdf {
+    pub fn new<S, F>(name: S, args: Vec<Type>, output: Type, nullable: bool, func: F) -> ScalarUdf
+        where S: Into<String>,
+              F: Fn(&[&RefColumn], RowOffset, &mut Column) -> Result<(), DBError> + 'static
+    {
+        ScalarUdf {
+            name: name.into(),
+            args: args,
+            output: output,
+            nullable: nullable,
+            deterministic: true,
+            func: Box::new(func),
+        }
+    }
+
+    /// Function whose output can change between calls with the same arguments (eg. `random()`)
+    pub fn non_deterministic(mut self) -> ScalarUdf {
+        self.deterministic = false;
+        self
+    }
+}
+
+/// Add each non NULL value of the first `rows` rows of the column with `Aggregate::update()`
+pub fn update_rows<A: Aggregate + ?Sized>(func: &A, state: &mut A::State, col: &RefColumn,
+                                          rows: RowOffset)
+    -> Result<(), DBError>
+{
+    for row in 0 .. rows {
+        let value = column_value(col, row)?;
+        if !value.is_null() {
+            func.update(state, &value)?;
+        }
+    }
+    Ok(())
+}
+
+fn state_error() -> DBError {
+    DBError::ExpressionInputType(String::from("aggregate state of another function"))
+}
+
+impl<A: Aggregate> AggregateUdf for A {
+    fn output(&self, input: &Type) -> Result<Type, DBError> {
+        Aggregate::output(self, input)
+    }
+
+    fn init(&self) -> Box<Any> {
+        Box::new(Aggregate::init(self))
+    }
+
+    fn update(&self, state: &mut Any, value: &Value) -> Result<(), DBError> {
+        let state = state.downcast_mut::<A::State>().ok_or_else(state_error)?;
+        Aggregate::update(self, state, value)
+    }
+
+    fn update_column(&self, state: &mut Any, col: &RefColumn, rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        let state = state.downcast_mut::<A::State>().ok_or_else(state_error)?;
+        Aggregate::update_column(self, state, col, rows)
+    }
+
+    fn merge(&self, state: &mut Any, other: Box<Any>) -> Result<(), DBError> {
+        let state = state.downcast_mut::<A::State>().ok_or_else(state_error)?;
+        let other = other.downcast::<A::State>().map_err(|_| state_error())?;
+        Aggregate::merge(self, state, *other)
+    }
+
+    fn finalize(&self, state: &Any, dst: &mut Column, row: RowOffset) -> Result<(), DBError> {
+        let state = state.downcast_ref::<A::State>().ok_or_else(state_error)?;
+        let value = Aggregate::finalize(self, state)?;
+        value.set_row(dst, row)?;
+
+        if !value.is_null() {
+            dst.nulls_mut()?[row] = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    /// Add the function, replacing any previous scalar function by that name
+    pub fn register_scalar(&mut self, func: ScalarUdf) {
+        self.scalars.insert(func.name.to_lowercase(), Rc::new(func));
+    }
+
+    /// Add the function, replacing any previous aggregate function by that name
+    pub fn register_aggregate<S, A>(&mut self, name: S, func: A)
+        where S: Into<String>, A: Aggregate + 'static
+    {
+        self.aggregates.insert(name.into().to_lowercase(), Rc::new(func));
+    }
+
+    pub fn scalar(&self, name: &str) -> Result<Rc<ScalarUdf>, DBError> {
+        self.scalars.get(&name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| DBError::FunctionMissing(name.to_string()))
+    }
+
+    pub fn aggregate(&self, name: &str) -> Result<Rc<AggregateUdf>, DBError> {
+        self.aggregates.get(&name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| DBError::FunctionMissing(name.to_string()))
+    }
+
+    pub fn is_aggregate(&self, name: &str) -> bool {
+        self.aggregates.contains_key(&name.to_lowercase())
+    }
+
+    /// Expression calling the scalar function
+    pub fn call<'a>(&self, name: &str, args: Vec<Box<Expr<'a> + 'a>>)
+        -> Result<ScalarCallExpr<'a>, DBError>
+    {
+        Ok(ScalarCallExpr { func: self.scalar(name)?, args: args })
+    }
+
+    /// Expression calling the aggregate function
+    pub fn call_aggregate<'a, T: Expr<'a> + 'a>(&self, name: &str, arg: T)
+        -> Result<AggregateCallExpr<'a>, DBError>
+    {
+        Ok(AggregateCallExpr {
+            name: name.to_lowercase(),
+            func: self.aggregate(name)?,
+            arg: Box::new(arg),
+        })
+    }
+
+    /// Names of the scalar and aggregate functions, in sorted order
+    pub fn names(&self) -> Vec<&str> {
+        let mut out: Vec<&str> = self.scalars.keys().chain(self.aggregates.keys())
+            .map(|k| k.as_str())
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+}
+
+impl<'b> Expr<'b> for ScalarCallExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let func = &self.func;
+        if self.args.len() != func.args.len() {
+            return Err(DBError::ExpressionInputCount(
+                format!("{} takes {} arguments, not {}", func.name, func.args.len(),
+                        self.args.len())))
+        }
+
+        let mut args = Vec::with_capacity(self.args.len());
+        let mut names = Vec::with_capacity(self.args.len());
+        for (arg, dtype) in self.args.iter().zip(&func.args) {
+            let arg = arg.bind(alloc, input_schema)?;
+            names.push(bound_attribute(&*arg)?.name.clone());
+            args.push(coerce(alloc, arg, dtype)?);
+        }
+
+        let schema = Schema::from_attr(Attribute {
+            name: format!("{}({})", func.name, names.join(", ")),
+            nullable: func.nullable,
+            dtype: func.output.clone(),
+        });
+
+        Ok(Box::new(ScalarCallBound {
+            alloc: alloc,
+            schema: schema,
+            func: func.clone(),
+            args: args,
+        }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for ScalarCallBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        self.func.name.clone()
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        self.args.iter().map(|a| &**a).collect()
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let inputs = self.args.iter()
+            .map(|a| a.evaluate(view, rows))
+            .collect::<Result<Vec<_>, DBError>>()?;
+        let columns: Vec<&RefColumn> = inputs.iter().map(|b| b.column(0).unwrap() as &RefColumn)
+            .collect();
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+        (self.func.func)(&columns, rows, out.column_mut(0).unwrap())?;
+
+        Ok(out)
+    }
+}
+
+impl<'b> Expr<'b> for AggregateCallExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let arg = self.arg.bind(alloc, input_schema)?;
+
+        let schema = {
+            let attr = bound_attribute(&*arg)?;
+            Schema::from_attr(Attribute {
+                name: format!("{}({})", self.name, attr.name),
+                nullable: true,
+                dtype: self.func.output(&attr.dtype)?,
+            })
+        };
+
+        Ok(Box::new(AggregateCallBound {
+            alloc: alloc,
+            schema: schema,
+            func: self.func.clone(),
+            arg: arg,
+        }))
+    }
+
+    fn aggregate_call(&self) -> Option<(&Rc<AggregateUdf>, &Expr<'b>)> {
+        Some((&self.func, &*self.arg))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for AggregateCallBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.arg]
+    }
+
+    /// Single row block of the aggregate of all the rows
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.arg.evaluate(view, rows)?;
+        let src = input.column(0).unwrap();
+
+        let mut state = self.func.init();
+        self.func.update_column(&mut *state, src, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(1)?;
+        self.func.finalize(&*state, out.column_mut(0).unwrap(), 0)?;
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+    use ::types::{Boolean, Int64};
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::INT32},
+            Attribute{name: "b".to_string(), nullable: true, dtype: Type::INT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(3i32).set(4i64)
+                .add_row().set(4i32).set_null(true)
+                .add_row().set(5i32).set(10i64)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    /// BOOLEAN a < b of two INT64 arguments
+    fn less() -> ScalarUdf {
+        ScalarUdf::new("Less", vec![Type::INT64, Type::INT64], Type::BOOLEAN, false,
+            |args, rows, out| {
+                let lhs = column_row_data::<Int64>(args[0])?;
+                let rhs = column_row_data::<Int64>(args[1])?;
+                let dst = out.row_data_mut::<Boolean>()?;
+                for row in 0 .. rows {
+                    dst.values[row] = rhs.nulls[row] == 0 && lhs.values[row] < rhs.values[row];
+                }
+                Ok(())
+            })
+    }
+
+    /// Largest INT64 value
+    struct Max;
+
+    impl Aggregate for Max {
+        type State = Option<i64>;
+
+        fn output(&self, input: &Type) -> Result<Type, DBError> {
+            match *input {
+                Type::INT64 => Ok(Type::INT64),
+                _ => Err(DBError::ExpressionInputType(format!("max of {}", input))),
+            }
+        }
+
+        fn init(&self) -> Option<i64> {
+            None
+        }
+
+        fn update(&self, state: &mut Option<i64>, value: &Value) -> Result<(), DBError> {
+            if let Value::INT64(v) = *value {
+                *state = Some(state.map_or(v, |s| s.max(v)));
+            }
+            Ok(())
+        }
+
+        fn merge(&self, state: &mut Option<i64>, other: Option<i64>) -> Result<(), DBError> {
+            if let Some(v) = other {
+                Aggregate::update(self, state, &Value::INT64(v))?;
+            }
+            Ok(())
+        }
+
+        fn finalize<'s>(&self, state: &'s Option<i64>) -> Result<Value<'s>, DBError> {
+            Ok(state.map_or(Value::NULL, Value::INT64))
+        }
+    }
+
+    // Registered scalar functions are called with converted arguments
+    #[test]
+    fn scalar_udf() {
+        let block = make_block();
+        let mut registry = FunctionRegistry::new();
+        registry.register_scalar(less());
+
+        let args: Vec<Box<Expr>> = vec![Box::new(ColumnExpr::named("a")),
+                                        Box::new(ColumnExpr::named("b"))];
+        let expr = registry.call("less", args).unwrap();
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert_eq!(bound.schema()[0].name, "Less(a, b)");
+
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        let data = column_row_data::<Boolean>(out.column(0).unwrap()).unwrap();
+        assert_eq!(&data.values[.. 3], &[true, false, true]);
+
+        let expr = registry.call("LESS", vec![Box::new(ColumnExpr::named("a"))]).unwrap();
+        match expr.bind(&allocator::GLOBAL, block.schema()) {
+            Err(DBError::ExpressionInputCount(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        match registry.call("missing", Vec::new()) {
+            Err(DBError::FunctionMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Aggregate of all the rows, partial states merge
+    #[test]
+    fn aggregate_udf() {
+        let block = make_block();
+        let mut registry = FunctionRegistry::new();
+        registry.register_aggregate("max", Max);
+        registry.register_scalar(less());
+        assert_eq!(registry.names(), vec!["less", "max"]);
+        assert!(registry.is_aggregate("MAX"));
+
+        let expr = registry.call_aggregate("max", ColumnExpr::named("b")).unwrap();
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert_eq!(bound.schema()[0].name, "max(b)");
+
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+        assert_eq!(out.rows(), 1);
+        assert!(column_value(out.column(0).unwrap(), 0).unwrap() == Value::INT64(10));
+
+        let func = registry.aggregate("max").unwrap();
+        let mut state = func.init();
+        let mut other = func.init();
+        func.update(&mut *state, &Value::INT64(2)).unwrap();
+        func.update(&mut *other, &Value::INT64(7)).unwrap();
+        func.merge(&mut *state, other).unwrap();
+
+        let mut out = Block::new(&allocator::GLOBAL, bound.schema());
+        out.add_rows(1).unwrap();
+        func.finalize(&*state, out.column_mut(0).unwrap(), 0).unwrap();
+        assert!(column_value(out.column(0).unwrap(), 0).unwrap() == Value::INT64(7));
+
+        let expr = registry.call_aggregate("max", ColumnExpr::named("a")).unwrap();
+        assert!(expr.bind(&allocator::GLOBAL, block.schema()).is_err());
+    }
+}
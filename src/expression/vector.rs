@@ -0,0 +1,175 @@
+use ::allocator::Allocator;
+use ::block::{Block, View};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Float32, Type};
+use ::vector::{Metric, column_vectors};
+
+/// Score of a vector (`LIST<FLOAT32>`) against a constant query vector, eg.
+/// `l2_distance(embedding, [0.1, 0.2])`. NULL for NULL vectors and undefined scores.
+pub struct VectorScoreExpr<'a> {
+    pub input: Box<Expr<'a> + 'a>,
+    pub query: Vec<f32>,
+    pub metric: Metric,
+}
+
+struct VectorScoreBound<'a, 'e> {
+    alloc: &'a Allocator,
+    schema: Schema,
+    input: Box<BoundExpr<'a> + 'e>,
+    query: Vec<f32>,
+    metric: Metric,
+}
+
+impl<'a> VectorScoreExpr<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, query: Vec<f32>, metric: Metric)
+        -> VectorScoreExpr<'a>
+    {
+        VectorScoreExpr { input: Box::new(input), query: query, metric: metric }
+    }
+
+    pub fn l2_distance<T: Expr<'a> + 'a>(input: T, query: Vec<f32>) -> VectorScoreExpr<'a> {
+        VectorScoreExpr::new(input, query, Metric::L2)
+    }
+
+    pub fn cosine_similarity<T: Expr<'a> + 'a>(input: T, query: Vec<f32>)
+        -> VectorScoreExpr<'a>
+    {
+        VectorScoreExpr::new(input, query, Metric::Cosine)
+    }
+}
+
+impl<'b> Expr<'b> for VectorScoreExpr<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = self.input.bind(alloc, input_schema)?;
+
+        let schema = {
+            let attr = bound_attribute(&*input)?;
+
+            match attr.dtype {
+                Type::LIST(ref elem) if **elem == Type::FLOAT32 => (),
+                _ => return Err(DBError::ExpressionInputType(
+                    format!("{} is {}, expected LIST<FLOAT32>", attr.name, attr.dtype))),
+            }
+
+            // Cosine similarity of the zero vector is undefined
+            Schema::from_attr(Attribute {
+                name: format!("{}({})", self.metric.name(), attr.name),
+                nullable: attr.nullable || self.metric == Metric::Cosine,
+                dtype: Type::FLOAT32,
+            })
+        };
+
+        Ok(Box::new(VectorScoreBound {
+            alloc: alloc,
+            schema: schema,
+            input: input,
+            query: self.query.clone(),
+            metric: self.metric,
+        }))
+    }
+}
+
+impl<'alloc, 'e> BoundExpr<'alloc> for VectorScoreBound<'alloc, 'e> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn describe(&self) -> String {
+        String::from(self.metric.name())
+    }
+
+    fn children(&self) -> Vec<&BoundExpr<'alloc>> {
+        vec![&*self.input]
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let input = self.input.evaluate(view, rows)?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        {
+            let vectors = column_vectors(input.column(0).unwrap())?;
+
+            let nullable = self.schema[0].nullable;
+            let dst = out.column_mut(0).unwrap().row_data_mut::<Float32>()?;
+
+            for idx in 0 .. rows {
+                let score = match vectors.get(idx, self.query.len())? {
+                    Some(vector) => self.metric.score(vector, &self.query),
+                    None => None,
+                };
+
+                if nullable {
+                    dst.nulls[idx] = score.is_none() as u8;
+                }
+
+                if let Some(score) = score {
+                    dst.values[idx] = score;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::expression::column::ColumnExpr;
+    use ::table::{Table, TableAppender};
+    use ::types::Value;
+
+    // Scores of each row, NULL for NULL and zero vectors (cosine)
+    #[test]
+    fn vector_scores() {
+        let schema = Schema::make_one_attr("v", true, Type::LIST(Box::new(Type::FLOAT32)));
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(Value::LIST(vec![Value::FLOAT32(3.0), Value::FLOAT32(4.0)]))
+                .add_row().set_null(true)
+                .add_row().set(Value::LIST(vec![Value::FLOAT32(0.0), Value::FLOAT32(0.0)]))
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+
+        let l2 = VectorScoreExpr::l2_distance(ColumnExpr::named("v"), vec![0.0, 0.0])
+            .bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert_eq!(l2.schema()[0].name, "l2_distance(v)");
+
+        let out = l2.evaluate(&block, block.rows()).unwrap();
+        let rows = column_row_data::<Float32>(out.column(0).unwrap()).unwrap();
+        assert_eq!(rows.values[0], 5.0);
+        assert_eq!(rows.values[2], 0.0);
+        assert_eq!(rows.nulls[.. 3], [0, 1, 0]);
+
+        let cosine = VectorScoreExpr::cosine_similarity(ColumnExpr::named("v"), vec![0.0, 2.0])
+            .bind(&allocator::GLOBAL, block.schema()).unwrap();
+
+        let out = cosine.evaluate(&block, block.rows()).unwrap();
+        let rows = column_row_data::<Float32>(out.column(0).unwrap()).unwrap();
+        assert_eq!(rows.values[0], 0.8);
+        assert_eq!(rows.nulls[.. 3], [0, 1, 1]);
+
+        // Not a vector
+        let schema = Schema::make_one_attr("t", false, Type::TEXT);
+        match VectorScoreExpr::l2_distance(ColumnExpr::named("t"), vec![1.0])
+            .bind(&allocator::GLOBAL, &schema)
+        {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,580 @@
+//! C API for embedding the engine (eg. from C, or Python and Java through their C FFIs).
+//!
+//! A `DbkitContext` owns the registered tables and the `SqlContext` querying them. Query results
+//! are copied out of the tables, so they stay valid after the context is freed. A result is read
+//! a block at a time through the Arrow C data interface: `dbkit_result_schema` exports the
+//! result schema as a struct (`+s`) `ArrowSchema` with a child per attribute, and
+//! `dbkit_result_next` exports the next block as the matching struct `ArrowArray`. The exported
+//! structs own copies of the data, the consumer calls their `release` callback once done.
+//!
+//! Functions returning a status return `DBKIT_OK` or `DBKIT_ERROR`, the error message is then
+//! available from `dbkit_last_error` on the same thread. Panics are caught and reported as
+//! errors. Strings are NUL terminated UTF-8.
+//!
+//! Exported types: UINT32 (`I`), UINT64 (`L`), INT32 (`i`), INT64 (`l`), FLOAT32 (`f`), FLOAT64
+//! (`g`), BOOLEAN (`b`), TIMESTAMP (`tsu:`, microseconds without a time zone), TEXT (`U`), BLOB
+//! (`Z`) and UUID (`w:16`). Results with LIST, STRUCT or INTERVAL attributes can't be exported.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use ::allocator;
+use ::block::{Block, View, column_value};
+use ::decode::CsvDecoder;
+use ::error::DBError;
+use ::operation::{ExecutionContext, RechunkBlocks};
+use ::schema::{Attribute, Schema};
+use ::sql::SqlContext;
+use ::types::{Type, Value};
+
+pub const DBKIT_OK: c_int = 0;
+pub const DBKIT_ERROR: c_int = 1;
+
+/// `ArrowSchema.flags` of a nullable field
+pub const ARROW_FLAG_NULLABLE: i64 = 2;
+
+/// Arrow C data interface schema
+#[repr(C)]
+pub struct ArrowSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut ArrowSchema,
+    pub dictionary: *mut ArrowSchema,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    pub private_data: *mut c_void,
+}
+
+/// Arrow C data interface array
+#[repr(C)]
+pub struct ArrowArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut ArrowArray,
+    pub dictionary: *mut ArrowArray,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    pub private_data: *mut c_void,
+}
+
+/// Tables and the SQL context querying them
+pub struct DbkitContext {
+    sql: SqlContext<'static>,
+    /// Registered tables (from `Box::into_raw`), kept until the context is freed (even once
+    /// replaced)
+    tables: Vec<*mut Block<'static>>,
+}
+
+/// Rows of a query
+pub struct DbkitResult {
+    schema: Schema,
+    blocks: Vec<Block<'static>>,
+    next: usize,
+}
+
+/// Data owned by an exported `ArrowSchema`
+struct SchemaData {
+    format: CString,
+    name: CString,
+    children: Vec<*mut ArrowSchema>,
+}
+
+/// Data owned by an exported `ArrowArray`
+struct ArrayData {
+    /// 8 byte aligned, only read through `pointers`
+    #[allow(dead_code)]
+    buffers: Vec<Vec<u64>>,
+    pointers: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+impl Drop for DbkitContext {
+    fn drop(&mut self) {
+        drop(mem::replace(&mut self.sql, SqlContext::new()));
+        for table in self.tables.drain(..) {
+            unsafe { drop(Box::from_raw(table)) };
+        }
+    }
+}
+
+/// Run `f`, returning its status and recording its error (or panic)
+fn status<F: FnOnce() -> Result<(), DBError>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => DBKIT_OK,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            DBKIT_ERROR
+        },
+        Err(_) => {
+            set_last_error("panic in dbkit".to_string());
+            DBKIT_ERROR
+        },
+    }
+}
+
+unsafe fn c_str<'s>(s: *const c_char, what: &str) -> Result<&'s str, DBError> {
+    if s.is_null() {
+        return Err(DBError::ValueOutOfRange(format!("NULL {}", what)))
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| DBError::ValueParse(format!("{} is not UTF-8", what)))
+}
+
+unsafe fn non_null<'p, T>(p: *mut T, what: &str) -> Result<&'p mut T, DBError> {
+    p.as_mut().ok_or_else(|| DBError::ValueOutOfRange(format!("NULL {}", what)))
+}
+
+/// Arrow format of the attribute type
+fn arrow_format(attr: &Attribute) -> Result<&'static str, DBError> {
+    Ok(match attr.dtype {
+        Type::UINT32    => "I",
+        Type::UINT64    => "L",
+        Type::INT32     => "i",
+        Type::INT64     => "l",
+        Type::FLOAT32   => "f",
+        Type::FLOAT64   => "g",
+        Type::BOOLEAN   => "b",
+        Type::TIMESTAMP => "tsu:",
+        Type::TEXT      => "U",
+        Type::BLOB      => "Z",
+        Type::UUID      => "w:16",
+        _ => return Err(DBError::Unsupported(format!("exporting {} to Arrow", attr.name))),
+    })
+}
+
+/// Copy of the bytes, 8 byte aligned
+fn buffer(bytes: &[u8]) -> Vec<u64> {
+    let mut out = vec![0u64; (bytes.len() + 7) / 8];
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), out.as_mut_ptr() as *mut u8, bytes.len());
+    }
+    out
+}
+
+/// Bit of each row set when `set(row)`, LSB first
+fn bitmap<F: Fn(usize) -> bool>(rows: usize, set: F) -> Vec<u8> {
+    let mut out = vec![0u8; (rows + 7) / 8];
+    for row in (0 .. rows).filter(|r| set(*r)) {
+        out[row / 8] |= 1 << (row % 8);
+    }
+    out
+}
+
+fn export_schema_node(format: &str, name: &str, flags: i64, children: Vec<*mut ArrowSchema>)
+    -> ArrowSchema
+{
+    let mut data = Box::new(SchemaData {
+        format: CString::new(format).unwrap(),
+        name: CString::new(name.replace('\0', " ")).unwrap(),
+        children: children,
+    });
+
+    ArrowSchema {
+        format: data.format.as_ptr(),
+        name: data.name.as_ptr(),
+        metadata: ptr::null(),
+        flags: flags,
+        n_children: data.children.len() as i64,
+        children: if data.children.is_empty() { ptr::null_mut() } else {
+            data.children.as_mut_ptr()
+        },
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(data) as *mut c_void,
+    }
+}
+
+fn export_schema(schema: &Schema) -> Result<ArrowSchema, DBError> {
+    let mut children = Vec::with_capacity(schema.count());
+    for attr in schema.iter() {
+        let flags = if attr.nullable { ARROW_FLAG_NULLABLE } else { 0 };
+        let child = export_schema_node(arrow_format(attr)?, &attr.name, flags, Vec::new());
+        children.push(Box::into_raw(Box::new(child)));
+    }
+
+    Ok(export_schema_node("+s", "", 0, children))
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    let schema = match schema.as_mut() {
+        Some(schema) => schema,
+        None => return,
+    };
+
+    let data = Box::from_raw(schema.private_data as *mut SchemaData);
+    for child in &data.children {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+
+    schema.release = None;
+}
+
+fn export_array_node(length: usize, null_count: usize, buffers: Vec<Option<Vec<u64>>>,
+                     children: Vec<*mut ArrowArray>) -> ArrowArray {
+    let pointers = buffers.iter()
+        .map(|b| b.as_ref().map_or(ptr::null(), |b| b.as_ptr() as *const c_void))
+        .collect();
+    let mut data = Box::new(ArrayData {
+        buffers: buffers.into_iter().flatten().collect(),
+        pointers: pointers,
+        children: children,
+    });
+
+    ArrowArray {
+        length: length as i64,
+        null_count: null_count as i64,
+        offset: 0,
+        n_buffers: data.pointers.len() as i64,
+        n_children: data.children.len() as i64,
+        buffers: data.pointers.as_mut_ptr(),
+        children: if data.children.is_empty() { ptr::null_mut() } else {
+            data.children.as_mut_ptr()
+        },
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(data) as *mut c_void,
+    }
+}
+
+/// Copy of the column rows in the Arrow layout of the type
+fn export_column(block: &Block, pos: usize) -> Result<ArrowArray, DBError> {
+    let attr = block.schema().get(pos)?;
+    let col = block.column(pos).ok_or_else(|| DBError::make_column_unknown_pos(pos))?;
+    let rows = block.rows();
+
+    let mut values = Vec::with_capacity(rows);
+    for row in 0 .. rows {
+        values.push(column_value(col, row)?);
+    }
+
+    let null_count = values.iter().filter(|v| **v == Value::NULL).count();
+    let validity = if null_count > 0 {
+        Some(buffer(&bitmap(rows, |r| values[r] != Value::NULL)))
+    } else {
+        None
+    };
+
+    let varlen = attr.dtype == Type::TEXT || attr.dtype == Type::BLOB;
+    let mut data = Vec::new();
+    let mut offsets = 0i64.to_ne_bytes().to_vec();
+
+    for value in &values {
+        match *value {
+            // Fixed width slots of NULLs are zeroed, varlen ones empty
+            Value::NULL if varlen || attr.dtype == Type::BOOLEAN => (),
+            Value::NULL => data.resize(data.len() + attr.dtype.size_of(), 0),
+            Value::UINT32(v)  => data.extend_from_slice(&v.to_ne_bytes()),
+            Value::UINT64(v)  => data.extend_from_slice(&v.to_ne_bytes()),
+            Value::INT32(v)   => data.extend_from_slice(&v.to_ne_bytes()),
+            Value::INT64(v) | Value::TIMESTAMP(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            Value::FLOAT32(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            Value::FLOAT64(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            Value::UUID(ref v) => data.extend_from_slice(v),
+            Value::TEXT(v)    => data.extend_from_slice(v.as_bytes()),
+            Value::BLOB(v)    => data.extend_from_slice(v),
+            Value::BOOLEAN(_) => (),
+            _ => return Err(DBError::Unsupported(format!("exporting {} to Arrow", attr.name))),
+        }
+
+        if varlen {
+            offsets.extend_from_slice(&(data.len() as i64).to_ne_bytes());
+        }
+    }
+
+    let buffers = if attr.dtype == Type::BOOLEAN {
+        let set = |r: usize| match values[r] { Value::BOOLEAN(v) => v, _ => false };
+        vec![validity, Some(buffer(&bitmap(rows, set)))]
+    } else if varlen {
+        vec![validity, Some(buffer(&offsets)), Some(buffer(&data))]
+    } else {
+        vec![validity, Some(buffer(&data))]
+    };
+
+    Ok(export_array_node(rows, null_count, buffers, Vec::new()))
+}
+
+fn export_block(block: &Block) -> Result<ArrowArray, DBError> {
+    let mut children = Vec::with_capacity(block.schema().count());
+    for pos in 0 .. block.schema().count() {
+        match export_column(block, pos) {
+            Ok(child) => children.push(Box::into_raw(Box::new(child))),
+            Err(e) => {
+                // Release the columns exported so far
+                let mut partial = export_array_node(0, 0, Vec::new(), children);
+                unsafe { release_array(&mut partial) };
+                return Err(e)
+            },
+        }
+    }
+
+    Ok(export_array_node(block.rows(), 0, vec![None], children))
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    let array = match array.as_mut() {
+        Some(array) => array,
+        None => return,
+    };
+
+    let data = Box::from_raw(array.private_data as *mut ArrayData);
+    for child in &data.children {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+
+    array.release = None;
+}
+
+/// New context without tables, free it with `dbkit_context_free`
+#[no_mangle]
+pub extern "C" fn dbkit_context_new() -> *mut DbkitContext {
+    let ctx = DbkitContext { sql: SqlContext::new(), tables: Vec::new() };
+    Box::into_raw(Box::new(ctx))
+}
+
+/// # Safety
+/// `ctx` is NULL or a context of `dbkit_context_new` not freed yet. Results stay valid.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_context_free(ctx: *mut DbkitContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Error message of the last failed call on this thread, NULL if none failed. Valid until the
+/// next failing call.
+#[no_mangle]
+pub extern "C" fn dbkit_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Register the rows of a CSV file (with a header, see `CsvDecoder`) as table `name`, replacing
+/// any previous table by that name
+///
+/// # Safety
+/// `ctx` is a live context, `name` and `path` NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_register_csv(ctx: *mut DbkitContext, name: *const c_char,
+                                            path: *const c_char) -> c_int {
+    status(|| {
+        let ctx = non_null(ctx, "context")?;
+        let name = c_str(name, "table name")?;
+        let text = fs::read_to_string(c_str(path, "path")?).map_err(DBError::IO)?;
+
+        let csv = CsvDecoder::new();
+        let schema = csv.infer_schema(&text)?;
+        let block = Box::into_raw(Box::new(csv.decode(&allocator::GLOBAL, &schema, &text)?));
+
+        // Freed with the context, once the SQL context referencing it is dropped
+        ctx.tables.push(block);
+        ctx.sql.register(name, &*block);
+        Ok(())
+    })
+}
+
+/// Register the rows of a Parquet file as table `name`. Parquet files can't be read yet, this
+/// always fails.
+///
+/// # Safety
+/// `ctx` is a live context, `name` and `path` NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_register_parquet(ctx: *mut DbkitContext, name: *const c_char,
+                                                path: *const c_char) -> c_int {
+    status(|| {
+        non_null(ctx, "context")?;
+        c_str(name, "table name")?;
+        let path = c_str(path, "path")?;
+        Err(DBError::Unsupported(format!("reading Parquet file {}", path)))
+    })
+}
+
+/// Run the SQL query, storing its rows in `*out` (free them with `dbkit_result_free`)
+///
+/// # Safety
+/// `ctx` is a live context, `sql` a NUL terminated string and `out` writable. `*out` is NULL
+/// on errors.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_query(ctx: *mut DbkitContext, sql: *const c_char,
+                                     out: *mut *mut DbkitResult) -> c_int {
+    status(|| {
+        let ctx = non_null(ctx, "context")?;
+        let out = non_null(out, "result")?;
+        *out = ptr::null_mut();
+
+        let op = ctx.sql.query(c_str(sql, "query")?)?;
+        let exec = ExecutionContext::new(&allocator::GLOBAL);
+        let cursor = op.bind_context(&exec)?;
+        let rows = exec.batch_rows(cursor.schema());
+        let blocks = RechunkBlocks::new(cursor, exec.allocator, rows);
+        let schema = blocks.schema().clone();
+        export_schema(&schema).map(|mut s| release_schema(&mut s))?;
+
+        let blocks = blocks.collect::<Result<Vec<_>, DBError>>()?;
+        *out = Box::into_raw(Box::new(DbkitResult { schema: schema, blocks: blocks, next: 0 }));
+        Ok(())
+    })
+}
+
+/// Export the result schema into `*out`
+///
+/// # Safety
+/// `result` is a live result and `out` a writable (uninitialized or released) schema.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_result_schema(result: *const DbkitResult, out: *mut ArrowSchema)
+    -> c_int
+{
+    status(|| {
+        let result = result.as_ref()
+            .ok_or_else(|| DBError::ValueOutOfRange("NULL result".to_string()))?;
+        let out = non_null(out, "schema")?;
+        ptr::write(out, export_schema(&result.schema)?);
+        Ok(())
+    })
+}
+
+/// Export the next block of the result into `*out`. Past the last block `*out` is released
+/// (its `release` is NULL).
+///
+/// # Safety
+/// `result` is a live result and `out` a writable (uninitialized or released) array.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_result_next(result: *mut DbkitResult, out: *mut ArrowArray)
+    -> c_int
+{
+    status(|| {
+        let result = non_null(result, "result")?;
+        let out = non_null(out, "array")?;
+
+        let array = match result.blocks.get(result.next) {
+            Some(block) => export_block(block)?,
+            None => {
+                let mut end = export_array_node(0, 0, Vec::new(), Vec::new());
+                release_array(&mut end);
+                end
+            },
+        };
+
+        result.next += 1;
+        ptr::write(out, array);
+        Ok(())
+    })
+}
+
+/// Rows of the result
+///
+/// # Safety
+/// `result` is NULL (0 rows) or a live result.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_result_rows(result: *const DbkitResult) -> i64 {
+    result.as_ref().map_or(0, |r| r.blocks.iter().map(|b| b.rows() as i64).sum())
+}
+
+/// # Safety
+/// `result` is NULL or a result of `dbkit_query` not freed yet. Exported arrays stay valid.
+#[no_mangle]
+pub unsafe extern "C" fn dbkit_result_free(result: *mut DbkitResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::slice;
+
+    use super::*;
+
+    unsafe fn text(s: *const c_char) -> String {
+        CStr::from_ptr(s).to_str().unwrap().to_string()
+    }
+
+    // A CSV table is queried through the C API, the result exported as Arrow arrays
+    #[test]
+    fn query_csv() {
+        let path = env::temp_dir().join(format!("dbkit-ffi-{}.csv", ::std::process::id()));
+        File::create(&path).unwrap().write_all(b"a,b\n1,x\n2,\n3,zz\n").unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let name = CString::new("t").unwrap();
+
+        unsafe {
+            let ctx = dbkit_context_new();
+            assert_eq!(dbkit_register_csv(ctx, name.as_ptr(), c_path.as_ptr()), DBKIT_OK);
+            assert_eq!(dbkit_register_parquet(ctx, name.as_ptr(), c_path.as_ptr()), DBKIT_ERROR);
+            assert!(text(dbkit_last_error()).starts_with("Unsupported"));
+
+            let sql = CString::new("SELECT a, b FROM t LIMIT 3").unwrap();
+            let mut result = ptr::null_mut();
+            assert_eq!(dbkit_query(ctx, sql.as_ptr(), &mut result), DBKIT_OK);
+            // Results don't reference the context tables
+            dbkit_context_free(ctx);
+            assert_eq!(dbkit_result_rows(result), 3);
+
+            let mut schema: ArrowSchema = mem::zeroed();
+            assert_eq!(dbkit_result_schema(result, &mut schema), DBKIT_OK);
+            assert_eq!(text(schema.format), "+s");
+            assert_eq!(schema.n_children, 2);
+            let b = &**schema.children.offset(1);
+            assert_eq!((text(b.format), text(b.name), b.flags), ("U".to_string(), "b".to_string(),
+                                                                 ARROW_FLAG_NULLABLE));
+            schema.release.unwrap()(&mut schema);
+            assert!(schema.release.is_none());
+
+            let mut array: ArrowArray = mem::zeroed();
+            assert_eq!(dbkit_result_next(result, &mut array), DBKIT_OK);
+            assert_eq!((array.length, array.n_children), (3, 2));
+
+            let a = &**array.children;
+            let values = slice::from_raw_parts(*a.buffers.offset(1) as *const i64, 3);
+            assert_eq!(values, &[1, 2, 3]);
+
+            let b = &**array.children.offset(1);
+            assert_eq!((b.null_count, b.n_buffers), (1, 3));
+            assert_eq!(*(*b.buffers as *const u8), 0b101);
+            let offsets = slice::from_raw_parts(*b.buffers.offset(1) as *const i64, 4);
+            assert_eq!(offsets, &[0, 1, 1, 3]);
+            let data = slice::from_raw_parts(*b.buffers.offset(2) as *const u8, 3);
+            assert_eq!(data, b"xzz");
+            array.release.unwrap()(&mut array);
+
+            assert_eq!(dbkit_result_next(result, &mut array), DBKIT_OK);
+            assert!(array.release.is_none());
+            dbkit_result_free(result);
+
+            let mut result = ptr::null_mut();
+            let ctx = dbkit_context_new();
+            let sql = CString::new("SELECT a FROM missing").unwrap();
+            assert_eq!(dbkit_query(ctx, sql.as_ptr(), &mut result), DBKIT_ERROR);
+            assert!(result.is_null());
+            assert!(text(dbkit_last_error()).contains("missing"));
+            dbkit_context_free(ctx);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+//! Human readable rendering of rows.
+//!
+//! `pretty_block` renders the rows of a view as an ASCII table, for test failures, examples and
+//! interactive exploration. `Block` implements `Display` with the default options.
+//!
+//! ```text
+//! +----+-------+-------+
+//! | id | name  | score |
+//! +----+-------+-------+
+//! |  1 | alice |   0.5 |
+//! |  2 | NULL  |  NULL |
+//! +----+-------+-------+
+//! (2 rows)
+//! ```
+//!
+//! Numbers are aligned right, other values left. Cells longer than the maximum width (eg. long
+//! TEXT values) are cut short and end with "...", control characters are escaped.
+
+use std::fmt;
+
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk};
+use ::schema::Schema;
+use ::types::{Type, Value};
+use ::util::temporal::{format_local_timestamp, format_timestamp};
+use ::util::uuid::format_uuid;
+
+/// Marker of cells cut short
+const ELLIPSIS: &str = "...";
+
+/// Rendering options of `pretty_block_with` and `pretty_cursor`
+#[derive(Clone, Debug)]
+pub struct PrettyOptions {
+    /// Maximum number of rendered rows
+    pub max_rows: usize,
+    /// Maximum cell width in characters
+    pub max_width: usize,
+    /// Text of NULL cells
+    pub null: String,
+    /// Render timestamps as local time this many microseconds from UTC, followed by the offset
+    /// (see `ExecutionContext::pretty_options()`). UTC without an offset by default.
+    pub utc_offset: Option<i64>,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> PrettyOptions {
+        PrettyOptions { max_rows: 50, max_width: 32, null: "NULL".to_string(), utc_offset: None }
+    }
+}
+
+/// Text of a value of the type, not cut short, with `null` for NULL values
+pub fn format_value(value: &Value, dtype: &Type, null: &str) -> String {
+    format_local_value(value, dtype, null, None)
+}
+
+/// Text of a value like `format_value`, timestamps in local time when there's a `utc_offset`
+fn format_local_value(value: &Value, dtype: &Type, null: &str, utc_offset: Option<i64>)
+    -> String
+{
+    let format = |v: &Value, dtype: &Type| format_local_value(v, dtype, null, utc_offset);
+    let join = |items: Vec<String>, open: &str, close: &str| {
+        format!("{}{}{}", open, items.join(", "), close)
+    };
+
+    match *value {
+        Value::NULL                 => null.to_string(),
+        Value::UINT32(v)            => v.to_string(),
+        Value::UINT64(v)            => v.to_string(),
+        Value::INT32(v)             => v.to_string(),
+        Value::INT64(v)             => v.to_string(),
+        Value::FLOAT32(v)           => format!("{:?}", v),
+        Value::FLOAT64(v)           => format!("{:?}", v),
+        Value::BOOLEAN(v)           => v.to_string(),
+        Value::TIMESTAMP(v)         => match utc_offset {
+            Some(offset) => format_local_timestamp(v, offset),
+            None => format_timestamp(v),
+        },
+        Value::INTERVAL(ref v)      => v.to_string(),
+        Value::UUID(ref v)          => format_uuid(v),
+        Value::TEXT(v)              => escape_control(v),
+        Value::BLOB(v)              =>
+            v.iter().fold("\\x".to_string(), |out, b| out + &format!("{:02x}", b)),
+        Value::LIST(ref items)      => match *dtype {
+            Type::LIST(ref item) =>
+                join(items.iter().map(|v| format(v, item)).collect(), "[", "]"),
+            _ => "?".to_string(),
+        },
+        Value::STRUCT(ref values)   => match *dtype {
+            Type::STRUCT(ref fields) =>
+                join(values.iter().zip(fields)
+                         .map(|(v, f)| format!("{}: {}", f.name, format(v, &f.dtype)))
+                         .collect(), "{", "}"),
+            _ => "?".to_string(),
+        },
+    }
+}
+
+/// Text with its control characters (eg. new lines) escaped
+fn escape_control(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            out.extend(c.escape_default());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Cut the text short to `max_width` characters
+fn truncate(text: String, max_width: usize) -> String {
+    if text.chars().count() <= max_width {
+        return text
+    }
+
+    let keep = max_width.saturating_sub(ELLIPSIS.len());
+    text.chars().take(keep).collect::<String>() + ELLIPSIS
+}
+
+/// Cells of the first `rows` rows of the view
+fn view_cells<'v>(view: &'v View<'v>, rows: usize, options: &PrettyOptions)
+    -> Result<Vec<Vec<String>>, DBError>
+{
+    let schema = view.schema();
+    let mut out = Vec::with_capacity(rows);
+
+    for row in 0 .. rows {
+        let mut cells = Vec::with_capacity(schema.count());
+        for (pos, attr) in schema.iter().enumerate() {
+            let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            let value = column_value(col, row)?;
+            let text = format_local_value(&value, &attr.dtype, &options.null, options.utc_offset);
+            cells.push(truncate(text, options.max_width));
+        }
+        out.push(cells);
+    }
+
+    Ok(out)
+}
+
+/// ASCII table of the header and cells
+fn render(schema: &Schema, rows: &[Vec<String>], footer: &str, max_width: usize) -> String {
+    let header: Vec<String> = schema.iter().map(|a| truncate(a.name.clone(), max_width)).collect();
+
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = widths.iter()
+        .fold("+".to_string(), |out, w| out + &"-".repeat(w + 2) + "+");
+
+    let format_row = |cells: &[String], right: &[bool]| {
+        let mut out = "|".to_string();
+        for ((cell, width), &right) in cells.iter().zip(&widths).zip(right) {
+            let pad = " ".repeat(width - cell.chars().count());
+            if right {
+                out += &format!(" {}{} |", pad, cell);
+            } else {
+                out += &format!(" {}{} |", cell, pad);
+            }
+        }
+        out
+    };
+
+    let left = vec![false; header.len()];
+    let numeric: Vec<bool> = schema.iter().map(|a| a.dtype.is_numeric()).collect();
+
+    let mut out = vec![line.clone(), format_row(&header, &left), line.clone()];
+    for row in rows {
+        out.push(format_row(row, &numeric));
+    }
+    if !rows.is_empty() {
+        out.push(line);
+    }
+    out.push(footer.to_string());
+
+    out.join("\n")
+}
+
+fn rows_footer(shown: usize, total: usize) -> String {
+    match (shown, total) {
+        (_, 1)                      => "(1 row)".to_string(),
+        (s, t) if s == t            => format!("({} rows)", t),
+        (s, t)                      => format!("({} of {} rows)", s, t),
+    }
+}
+
+/// ASCII table of the rows of the view, with the default options
+pub fn pretty_block<'v>(view: &'v View<'v>) -> Result<String, DBError> {
+    pretty_block_with(view, &PrettyOptions::default())
+}
+
+/// ASCII table of the first `options.max_rows` rows of the view
+pub fn pretty_block_with<'v>(view: &'v View<'v>, options: &PrettyOptions)
+    -> Result<String, DBError>
+{
+    let shown = view.rows().min(options.max_rows);
+    let cells = view_cells(view, shown, options)?;
+    Ok(render(view.schema(), &cells, &rows_footer(shown, view.rows()), options.max_width))
+}
+
+/// ASCII table of the first `options.max_rows` rows of the cursor. Reads one more row than
+/// rendered, to tell if there are more.
+pub fn pretty_cursor<'a>(cursor: &mut Cursor<'a>, options: &PrettyOptions)
+    -> Result<String, DBError>
+{
+    let schema = cursor.schema().clone();
+    let mut cells = Vec::new();
+    let mut more = false;
+
+    while cells.len() <= options.max_rows {
+        let view = match cursor.next(options.max_rows + 1 - cells.len())? {
+            CursorChunk::Next(view) => view,
+            CursorChunk::End        => break,
+        };
+
+        let take = view.rows().min(options.max_rows - cells.len());
+        more |= take < view.rows();
+        cells.extend(view_cells(&view, take, options)?);
+        if more {
+            break
+        }
+    }
+
+    let footer = match cells.len() {
+        n if more   => format!("(first {} rows)", n),
+        n           => rows_footer(n, n),
+    };
+    Ok(render(&schema, &cells, &footer, options.max_width))
+}
+
+impl<'b> fmt::Display for Block<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = pretty_block(self).map_err(|_| fmt::Error)?;
+        f.write_str(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::{ExecutionContext, Operation, ScanView};
+    use ::schema::Attribute;
+
+    fn make_block<'a>() -> Block<'a> {
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "score".to_string(), nullable: true, dtype: Type::FLOAT64},
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.append_rows(&[
+            [Value::INT64(1), Value::TEXT("alice"), Value::FLOAT64(0.5)],
+            [Value::INT64(2), Value::NULL, Value::NULL],
+            [Value::INT64(10), Value::TEXT("tab\tand a long name"), Value::FLOAT64(-2.0)],
+        ]).unwrap();
+        block
+    }
+
+    // Aligned cells, NULL markers and cut short values
+    #[test]
+    fn pretty_rows() {
+        let block = make_block();
+        let options = PrettyOptions { max_rows: 2, ..PrettyOptions::default() };
+        assert_eq!(pretty_block_with(&block, &options).unwrap(), "\
++----+-------+-------+
+| id | name  | score |
++----+-------+-------+
+|  1 | alice |   0.5 |
+|  2 | NULL  |  NULL |
++----+-------+-------+
+(2 of 3 rows)");
+
+        let options = PrettyOptions { max_width: 12, null: "-".to_string(), ..options };
+        let text = pretty_block_with(&block, &PrettyOptions { max_rows: 5, ..options }).unwrap();
+        assert!(text.contains("| 10 | tab\\tand ... |  -2.0 |"), "{}", text);
+        assert!(text.contains("|  2 | -            |     - |"), "{}", text);
+        assert!(text.ends_with("(3 rows)"));
+        assert_eq!(block.to_string(), pretty_block(&block).unwrap());
+    }
+
+    // Cursors are rendered up to the maximum number of rows
+    #[test]
+    fn pretty_cursor_rows() {
+        let block = make_block();
+        let options = PrettyOptions { max_rows: 2, ..PrettyOptions::default() };
+
+        let mut cursor = ScanView::new(&block, None).bind(&allocator::GLOBAL).unwrap();
+        let text = pretty_cursor(&mut *cursor, &options).unwrap();
+        assert!(text.ends_with("(first 2 rows)"), "{}", text);
+        assert!(!text.contains("| 10 |"));
+
+        let mut cursor = ScanView::new(&block, None).bind(&allocator::GLOBAL).unwrap();
+        let all = PrettyOptions { max_rows: 3, ..options };
+        assert!(pretty_cursor(&mut *cursor, &all).unwrap().ends_with("(3 rows)"));
+    }
+
+    // Timestamps are rendered in the local time of the context, with its offset
+    #[test]
+    fn pretty_local_timestamps() {
+        let schema = Schema::make_one_attr("ts", false, Type::TIMESTAMP);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.append_row(&[Value::TIMESTAMP(0)]).unwrap();
+        assert!(pretty_block(&block).unwrap().contains("| 1970-01-01 00:00:00 |"));
+
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_utc_offset(60);
+        let text = pretty_block_with(&block, &ctx.pretty_options()).unwrap();
+        assert!(text.contains("| 1970-01-01 01:00:00+01:00 |"), "{}", text);
+    }
+}
@@ -0,0 +1,572 @@
+//! Fuzzy text matching.
+//!
+//! Similarity kernels score how alike two strings are, from 0 (nothing in common) to 1 (same),
+//! comparing characters (not bytes). `FuzzyJoin` pairs up the rows of two inputs whose TEXT
+//! attributes are similar enough, the core of entity resolution (deduplicating names, addresses,
+//! ...) when the same entity is spelled differently.
+//!
+//! Phonetic codes (`soundex`, `metaphone`) map names that sound alike to the same code, for
+//! matching (or blocking a fuzzy join) on the code instead.
+
+use std::cmp::{max, min};
+use std::collections::HashSet;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::operation::{Operation, DEFAULT_CURSOR_FETCH, CursorChunk};
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Type, Value};
+
+/// Maximum common prefix rewarded by `jaro_winkler`
+const WINKLER_PREFIX: usize = 4;
+/// Weight of the common prefix in `jaro_winkler`
+const WINKLER_SCALE: f64 = 0.1;
+
+/// Edit distance: the number of single character insertions, deletions and substitutions turning
+/// one string into the other
+pub fn levenshtein(lhs: &str, rhs: &str) -> usize {
+    let lhs: Vec<char> = lhs.chars().collect();
+    let rhs: Vec<char> = rhs.chars().collect();
+
+    // Distances from the lhs prefix to every rhs prefix, one row at a time
+    let mut prev: Vec<usize> = (0 .. rhs.len() + 1).collect();
+    let mut cur = vec![0; rhs.len() + 1];
+
+    for (i, l) in lhs.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, r) in rhs.iter().enumerate() {
+            let substitute = prev[j] + if l == r { 0 } else { 1 };
+            cur[j + 1] = min(substitute, min(prev[j + 1], cur[j]) + 1);
+        }
+        ::std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[rhs.len()]
+}
+
+/// Levenshtein distance relative to the length of the longer string, as a similarity
+pub fn levenshtein_similarity(lhs: &str, rhs: &str) -> f64 {
+    let len = max(lhs.chars().count(), rhs.chars().count());
+    if len == 0 {
+        return 1.0
+    }
+
+    1.0 - levenshtein(lhs, rhs) as f64 / len as f64
+}
+
+/// Jaro similarity, based on the characters the strings have in common (close to the same
+/// position) and how many of them are out of order
+pub fn jaro(lhs: &str, rhs: &str) -> f64 {
+    let lhs: Vec<char> = lhs.chars().collect();
+    let rhs: Vec<char> = rhs.chars().collect();
+
+    if lhs.is_empty() && rhs.is_empty() {
+        return 1.0
+    }
+    if lhs.is_empty() || rhs.is_empty() {
+        return 0.0
+    }
+
+    let window = (max(lhs.len(), rhs.len()) / 2).saturating_sub(1);
+    let mut rhs_matched = vec![false; rhs.len()];
+    let mut lhs_matches = Vec::new();
+
+    for (i, l) in lhs.iter().enumerate() {
+        let end = min(i + window + 1, rhs.len());
+        for j in i.saturating_sub(window) .. end {
+            if !rhs_matched[j] && rhs[j] == *l {
+                rhs_matched[j] = true;
+                lhs_matches.push(*l);
+                break
+            }
+        }
+    }
+
+    if lhs_matches.is_empty() {
+        return 0.0
+    }
+
+    let rhs_matches = rhs.iter().zip(&rhs_matched)
+        .filter(|&(_, matched)| *matched)
+        .map(|(r, _)| r);
+    let transpositions = lhs_matches.iter().zip(rhs_matches)
+        .filter(|&(l, r)| l != r)
+        .count() / 2;
+
+    let m = lhs_matches.len() as f64;
+    (m / lhs.len() as f64 + m / rhs.len() as f64 + (m - transpositions as f64) / m) / 3.0
+}
+
+/// Jaro similarity boosted for strings with a common prefix (of up to 4 characters), which
+/// suits names and other short strings where typos tend to be at the end
+pub fn jaro_winkler(lhs: &str, rhs: &str) -> f64 {
+    let similarity = jaro(lhs, rhs);
+    let prefix = lhs.chars().zip(rhs.chars())
+        .take(WINKLER_PREFIX)
+        .take_while(|&(l, r)| l == r)
+        .count();
+
+    similarity + prefix as f64 * WINKLER_SCALE * (1.0 - similarity)
+}
+
+/// Distinct `n` character grams of the string, the whole string if shorter
+fn ngrams(text: &str, n: usize) -> HashSet<Vec<char>> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= n {
+        return Some(chars).into_iter().filter(|c| !c.is_empty()).collect()
+    }
+
+    chars.windows(n).map(|gram| gram.to_vec()).collect()
+}
+
+/// Share (Jaccard index) of the `n` character grams the strings have in common. Unlike the edit
+/// based kernels, it doesn't depend on the order of words.
+pub fn ngram_similarity(lhs: &str, rhs: &str, n: usize) -> f64 {
+    let lhs = ngrams(lhs, n);
+    let rhs = ngrams(rhs, n);
+
+    let union = lhs.union(&rhs).count();
+    if union == 0 {
+        return 1.0
+    }
+
+    lhs.intersection(&rhs).count() as f64 / union as f64
+}
+
+/// Text similarity function
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Similarity {
+    /// `levenshtein_similarity`
+    Levenshtein,
+    /// `jaro_winkler`
+    JaroWinkler,
+    /// `ngram_similarity` of character trigrams
+    Trigram,
+}
+
+impl Similarity {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Similarity::Levenshtein => "levenshtein",
+            Similarity::JaroWinkler => "jaro_winkler",
+            Similarity::Trigram     => "trigram",
+        }
+    }
+
+    /// Similarity of the strings, from 0 to 1
+    pub fn score(&self, lhs: &str, rhs: &str) -> f64 {
+        match *self {
+            Similarity::Levenshtein => levenshtein_similarity(lhs, rhs),
+            Similarity::JaroWinkler => jaro_winkler(lhs, rhs),
+            Similarity::Trigram     => ngram_similarity(lhs, rhs, 3),
+        }
+    }
+}
+
+/// Soundex digit of an (uppercase ASCII) letter, '0' for vowels and the letters without one
+fn soundex_digit(c: char) -> char {
+    match c {
+        'B' | 'F' | 'P' | 'V' => '1',
+        'C' | 'G' | 'J' | 'K' | 'Q' | 'S' | 'X' | 'Z' => '2',
+        'D' | 'T' => '3',
+        'L' => '4',
+        'M' | 'N' => '5',
+        'R' => '6',
+        _ => '0',
+    }
+}
+
+/// American Soundex code: the first letter followed by three digits of the consonants after it,
+/// eg. "R163" for both "Robert" and "Rupert". Only ASCII letters are encoded, the code of text
+/// without any is empty.
+pub fn soundex(text: &str) -> String {
+    let mut letters = text.chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase());
+
+    let first = match letters.next() {
+        Some(c) => c,
+        None => return String::new(),
+    };
+
+    let mut out = String::with_capacity(4);
+    out.push(first);
+    let mut last = soundex_digit(first);
+
+    for c in letters {
+        // Don't separate consonants with the same digit, vowels do
+        if c == 'H' || c == 'W' {
+            continue
+        }
+
+        let digit = soundex_digit(c);
+        if digit != '0' && digit != last {
+            out.push(digit);
+            if out.len() == 4 {
+                break
+            }
+        }
+        last = digit;
+    }
+
+    while out.len() < 4 {
+        out.push('0');
+    }
+
+    out
+}
+
+fn is_vowel(c: char) -> bool {
+    match c {
+        'A' | 'E' | 'I' | 'O' | 'U' => true,
+        _ => false,
+    }
+}
+
+/// Metaphone code (Lawrence Philips' original rules): consonant sounds of the text, with '0' for
+/// "th" and 'X' for "sh", eg. "SM0" for "Smith". Only ASCII letters are encoded.
+pub fn metaphone(text: &str) -> String {
+    let mut word: Vec<char> = text.chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    // Silent first letters
+    let start: String = word.iter().take(2).collect();
+    match start.as_str() {
+        "AE" | "GN" | "KN" | "PN" | "WR" => { word.remove(0); },
+        "WH" => { word.remove(1); },
+        _ => (),
+    }
+    if word.first() == Some(&'X') {
+        word[0] = 'S';
+    }
+
+    let at = |i: usize| word.get(i).cloned().unwrap_or(' ');
+    let mut out = String::new();
+
+    for (i, &c) in word.iter().enumerate() {
+        let (prev, next, after) = (if i > 0 { at(i - 1) } else { ' ' }, at(i + 1), at(i + 2));
+
+        // Doubled letters sound once, except for "cc" as in "accept"
+        if c == prev && c != 'C' {
+            continue
+        }
+
+        match c {
+            'A' | 'E' | 'I' | 'O' | 'U' => if i == 0 { out.push(c) },
+            // Silent in a final "mb"
+            'B' => if !(prev == 'M' && i + 1 == word.len()) { out.push('B') },
+            'C' => {
+                if next == 'I' && after == 'A' {
+                    out.push('X')
+                } else if next == 'H' {
+                    out.push(if prev == 'S' { 'K' } else { 'X' })
+                } else if next == 'I' || next == 'E' || next == 'Y' {
+                    if prev != 'S' {
+                        out.push('S')
+                    }
+                } else {
+                    out.push('K')
+                }
+            },
+            'D' => {
+                let soft = next == 'G' && (after == 'E' || after == 'I' || after == 'Y');
+                out.push(if soft { 'J' } else { 'T' })
+            },
+            'G' => {
+                let silent_gh = next == 'H' && after != ' ' && !is_vowel(after);
+                let rest: String = word[i + 1 ..].iter().collect();
+                let silent_gn = rest == "N" || rest == "NED";
+                let soft = (next == 'I' || next == 'E' || next == 'Y') && prev != 'G';
+                // "dge" is already a J
+                let silent_dg = soft && prev == 'D';
+
+                if !silent_gh && !silent_gn && !silent_dg {
+                    out.push(if soft { 'J' } else { 'K' })
+                }
+            },
+            'H' => {
+                let after_consonant = match prev {
+                    'C' | 'G' | 'P' | 'S' | 'T' => true,
+                    _ => false,
+                };
+                if is_vowel(next) && !after_consonant {
+                    out.push('H')
+                }
+            },
+            'K' => if prev != 'C' { out.push('K') },
+            'P' => out.push(if next == 'H' { 'F' } else { 'P' }),
+            'Q' => out.push('K'),
+            'S' => {
+                let sh = next == 'H' || (next == 'I' && (after == 'O' || after == 'A'));
+                out.push(if sh { 'X' } else { 'S' })
+            },
+            'T' => {
+                if next == 'I' && (after == 'O' || after == 'A') {
+                    out.push('X')
+                } else if next == 'H' {
+                    out.push('0')
+                } else if !(next == 'C' && after == 'H') {
+                    out.push('T')
+                }
+            },
+            'V' => out.push('F'),
+            'W' | 'Y' => if is_vowel(next) { out.push(c) },
+            'X' => out.push_str("KS"),
+            'Z' => out.push('S'),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Phonetic code function
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phonetic {
+    Soundex,
+    Metaphone,
+}
+
+impl Phonetic {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Phonetic::Soundex   => "soundex",
+            Phonetic::Metaphone => "metaphone",
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> String {
+        match *self {
+            Phonetic::Soundex   => soundex(text),
+            Phonetic::Metaphone => metaphone(text),
+        }
+    }
+}
+
+/// Position of a TEXT attribute
+fn text_attribute(schema: &Schema, name: &str) -> Result<usize, DBError> {
+    let pos = schema.exists_ok(name)?;
+    if schema[pos].dtype != Type::TEXT {
+        return Err(DBError::AttributeType(name.to_string()))
+    }
+    Ok(pos)
+}
+
+/// Join of the source rows with the rows of a view whose TEXT attributes have a similarity of at
+/// least `threshold`.
+///
+/// Every pair of rows is compared (nested loops), so it's meant for a fairly small view, or after
+/// blocking the inputs on some exact key. Rows with a NULL text don't match. The output is the
+/// source attributes, the view attributes and a FLOAT64 `score` attribute with the similarity;
+/// like `index::LookupJoin` it's materialized into a `Block`.
+pub struct FuzzyJoin<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    /// Source attribute
+    pub left: String,
+    pub right_src: &'a View<'a>,
+    /// View attribute
+    pub right: String,
+    pub similarity: Similarity,
+    pub threshold: f64,
+}
+
+impl<'a> FuzzyJoin<'a> {
+    pub fn new<T>(src: T, left: &str, right_src: &'a View<'a>, right: &str,
+                  similarity: Similarity, threshold: f64)
+        -> FuzzyJoin<'a>
+        where T: Operation<'a> + 'a
+    {
+        FuzzyJoin {
+            src: Box::new(src),
+            left: left.to_string(),
+            right_src: right_src,
+            right: right.to_string(),
+            similarity: similarity,
+            threshold: threshold,
+        }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        let mut cursor = self.src.bind(alloc)?;
+
+        let right_schema = self.right_src.schema();
+        let right_pos = text_attribute(right_schema, &self.right)?;
+
+        let (left_pos, left_count, schema) = {
+            let src_schema = cursor.schema();
+            let mut attrs: Vec<Attribute> = src_schema.iter().cloned().collect();
+            attrs.extend(right_schema.iter().cloned());
+            attrs.push(Attribute { name: String::from("score"), nullable: false,
+                                   dtype: Type::FLOAT64 });
+
+            let pos = text_attribute(src_schema, &self.left)?;
+            (pos, src_schema.count(), Schema::from_vec(attrs)?)
+        };
+
+        let right_col = self.right_src.column(right_pos)
+            .ok_or(DBError::make_column_unknown_pos(right_pos))?;
+        let mut right_text = Vec::with_capacity(self.right_src.rows());
+        for row in 0 .. self.right_src.rows() {
+            right_text.push(match column_value(right_col, row)? {
+                Value::TEXT(text) => Some(text),
+                _ => None,
+            });
+        }
+
+        let mut out = Table::new(alloc, &schema, None);
+
+        loop {
+            let view = match cursor.next(DEFAULT_CURSOR_FETCH)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => break,
+            };
+
+            let left_col = view.column(left_pos).ok_or(DBError::make_column_unknown_pos(left_pos))?;
+
+            for row in 0 .. view.rows() {
+                let text = match column_value(left_col, row)? {
+                    Value::TEXT(text) => text,
+                    _ => continue,
+                };
+
+                for (found, other) in right_text.iter().enumerate() {
+                    let score = match *other {
+                        Some(other) => self.similarity.score(text, other),
+                        None => continue,
+                    };
+
+                    if score < self.threshold {
+                        continue
+                    }
+
+                    let out_row = out.add_row()?;
+
+                    for pos in 0 .. left_count {
+                        let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                        out.set(pos, out_row, column_value(col, row)?)?;
+                    }
+
+                    for pos in 0 .. right_schema.count() {
+                        let col = self.right_src.column(pos)
+                            .ok_or(DBError::make_column_unknown_pos(pos))?;
+                        out.set(left_count + pos, out_row, column_value(col, found)?)?;
+                    }
+
+                    out.set(left_count + right_schema.count(), out_row, score)?;
+                }
+            }
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::ScanView;
+    use ::table::TableAppender;
+    use ::types::UInt32;
+
+    fn assert_near(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-4, "{} is not {}", value, expected);
+    }
+
+    // Known values of the kernels
+    #[test]
+    fn similarity_kernels() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("zürich", "zurich"), 1);
+        assert_near(levenshtein_similarity("kitten", "sitting"), 4.0 / 7.0);
+        assert_near(levenshtein_similarity("", ""), 1.0);
+
+        assert_near(jaro("MARTHA", "MARHTA"), 0.9444);
+        assert_near(jaro_winkler("MARTHA", "MARHTA"), 0.9611);
+        assert_near(jaro_winkler("DIXON", "DICKSONX"), 0.8133);
+        assert_near(jaro_winkler("abc", "xyz"), 0.0);
+
+        assert_near(ngram_similarity("john smith", "smith john", 3), 5.0 / 11.0);
+        assert_near(ngram_similarity("ab", "ab", 3), 1.0);
+        assert_near(ngram_similarity("abcd", "wxyz", 3), 0.0);
+    }
+
+    // Codes of the classic examples, names that sound alike share a code
+    #[test]
+    fn phonetic_codes() {
+        for &(name, code) in &[("Robert", "R163"), ("Rupert", "R163"), ("Rubin", "R150"),
+                               ("Ashcraft", "A261"), ("Tymczak", "T522"), ("Pfister", "P236"),
+                               ("Lee", "L000"), ("o'Hara", "O600"), ("42", "")]
+        {
+            assert_eq!(soundex(name), code, "soundex of {}", name);
+        }
+
+        for &(name, code) in &[("Smith", "SM0"), ("Knight", "NT"), ("Philip", "FLP"),
+                               ("Wright", "RT"), ("Xavier", "SFR"), ("Catherine", "K0RN"),
+                               ("Thumb", "0M"), ("Science", "SNS"), ("Judge", "JJ")]
+        {
+            assert_eq!(metaphone(name), code, "metaphone of {}", name);
+        }
+
+        assert_eq!(Phonetic::Metaphone.encode("Smyth"), Phonetic::Metaphone.encode("Smith"));
+    }
+
+    // Pairs of rows scoring over the threshold, NULL doesn't match
+    #[test]
+    fn fuzzy_join_rows() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut left = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut left)
+                .add_row().set(1 as u32).set("Jon Smith")
+                .add_row().set(2 as u32).set_null(true)
+                .add_row().set(3 as u32).set("Mary Jones")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let attrs = vec![
+            Attribute{name: "ref".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "customer".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut right = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut right)
+                .add_row().set(10 as u32).set("John Smith")
+                .add_row().set(20 as u32).set("Marie Jonas")
+                .add_row().set(30 as u32).set("Bob Stone")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let join = FuzzyJoin::new(ScanView::new(&left, None), "name", &right, "customer",
+                                  Similarity::JaroWinkler, 0.85);
+        let out = join.execute(&allocator::GLOBAL).unwrap();
+        assert_eq!(out.schema().count(), 5);
+        assert_eq!(out.rows(), 2);
+
+        let ids = column_row_data::<UInt32>(out.column(0).unwrap()).unwrap();
+        let refs = column_row_data::<UInt32>(out.column(2).unwrap()).unwrap();
+        assert_eq!(ids.values[.. 2], [1, 3]);
+        assert_eq!(refs.values[.. 2], [10, 20]);
+
+        let join = FuzzyJoin::new(ScanView::new(&left, None), "id", &right, "customer",
+                                  Similarity::Levenshtein, 0.5);
+        match join.execute(&allocator::GLOBAL) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
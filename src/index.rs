@@ -0,0 +1,685 @@
+//! Hash indexes over table columns.
+//!
+//! A `HashIndex` maps the values of one or more key columns of a `Table` to the rows having them,
+//! so equality lookups (and `LookupJoin`) don't have to scan the whole table. The index only
+//! stores row locations, the key values are read back from the table on lookup.
+//!
+//! A `SortedIndex` keeps the rows of a table ordered by a key column, for range lookups and
+//! (`IndexRangeScan`) scanning the table in key order. A `TextIndex` maps the words of a TEXT
+//! column to the rows having them, for full-text search.
+
+use std::cmp::Ordering;
+use std::collections::{Bound, HashMap};
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::operation::{Operation, DEFAULT_CURSOR_FETCH, CursorChunk};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::stats::compare_values;
+use ::table::Table;
+use ::types::{Type, Value};
+use ::util::hash::{hash_key, hash_rows};
+
+/// Equality index of the rows of a `Table` by the values of its key columns.
+///
+/// Rows with a NULL key value are not indexed (NULL doesn't equal anything). The index is built
+/// for a version of the table, modifying the table makes it stale, and using a stale index is a
+/// `DBError::IndexStale` error.
+pub struct HashIndex {
+    /// Positions of the key columns in the table schema
+    columns: Vec<usize>,
+    attrs: Vec<Attribute>,
+    /// Rows by the `util::hash::hash_key` of their key values
+    buckets: HashMap<u64, Vec<RowOffset>>,
+    /// Table version the index was built from
+    version: u64,
+}
+
+/// Values of the `columns` in the row, None if any of them is NULL
+fn row_key<'v>(view: &'v View<'v>, columns: &[usize], row: RowOffset)
+    -> Result<Option<Vec<Value<'v>>>, DBError>
+{
+    let mut key = Vec::with_capacity(columns.len());
+
+    for pos in columns {
+        let col = view.column(*pos).ok_or(DBError::make_column_unknown_pos(*pos))?;
+        let value = column_value(col, row)?;
+        if value == Value::NULL {
+            return Ok(None)
+        }
+        key.push(value);
+    }
+
+    Ok(Some(key))
+}
+
+impl HashIndex {
+    /// Index the current rows of the `table` by the `columns` values
+    pub fn build<'t>(table: &'t Table<'t>, columns: &[&str]) -> Result<HashIndex, DBError> {
+        let mut positions = Vec::with_capacity(columns.len());
+        let mut attrs = Vec::with_capacity(columns.len());
+
+        {
+            let schema = table.schema();
+            for name in columns {
+                let pos = schema.exists_ok(name)?;
+                positions.push(pos);
+                attrs.push(schema[pos].clone());
+            }
+        }
+
+        let hashes = hash_rows(table, &positions, 0)?;
+        let mut buckets = HashMap::new();
+        for (row, hash) in hashes.into_iter().enumerate() {
+            if row_key(table, &positions, row)?.is_some() {
+                buckets.entry(hash).or_insert_with(Vec::new).push(row);
+            }
+        }
+
+        Ok(HashIndex {
+            columns: positions,
+            attrs: attrs,
+            buckets: buckets,
+            version: table.version(),
+        })
+    }
+
+    /// Key attributes, in key order
+    pub fn key(&self) -> &[Attribute] {
+        &self.attrs
+    }
+
+    /// True if the table wasn't modified since the index was built
+    pub fn is_current(&self, table: &Table) -> bool {
+        table.version() == self.version
+    }
+
+    fn check(&self, table: &Table) -> Result<(), DBError> {
+        if self.is_current(table) {
+            Ok(())
+        } else {
+            Err(DBError::IndexStale(format!("built at table version {}, table is at version {}",
+                                            self.version, table.version())))
+        }
+    }
+
+    /// Rows of the `table` where the key columns equal the `key` values (which have to be of the
+    /// key attribute types), in row order.
+    pub fn lookup<'t>(&self, table: &'t Table<'t>, key: &[Value])
+        -> Result<Vec<RowOffset>, DBError>
+    {
+        self.check(table)?;
+
+        if key.len() != self.columns.len() {
+            return Err(DBError::ExpressionInputCount(format!(
+                "index lookup key has {} values, expected {}", key.len(), self.columns.len())))
+        }
+
+        let candidates = match self.buckets.get(&hash_key(key, 0)) {
+            Some(rows) => rows,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut out = Vec::new();
+        for row in candidates {
+            // Hash collisions
+            if let Some(found) = row_key(table, &self.columns, *row)? {
+                if found.as_slice() == key {
+                    out.push(*row);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Inner equality join of a source with an indexed table. Each source row is looked up in the
+/// index instead of scanning the table, so it pays off for selective joins (few source rows, or
+/// few matches each).
+///
+/// The output is the source attributes followed by the table attributes that are not part of the
+/// index key (their values equal the source keys). Cursors can only alias their input, the joined
+/// rows are new data, so the join is materialized into a `Block` instead of being an `Operation`.
+pub struct LookupJoin<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    /// Source attributes matched against the index key, in key order
+    pub keys: Vec<String>,
+    pub table: &'a Table<'a>,
+    pub index: &'a HashIndex,
+}
+
+impl<'a> LookupJoin<'a> {
+    pub fn new<T>(src: T, keys: &[&str], table: &'a Table<'a>, index: &'a HashIndex)
+        -> LookupJoin<'a>
+        where T: Operation<'a> + 'a
+    {
+        LookupJoin {
+            src: Box::new(src),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            table: table,
+            index: index,
+        }
+    }
+
+    /// Positions of the source keys, checked against the index key types
+    fn source_keys(&self, schema: &Schema) -> Result<Vec<usize>, DBError> {
+        if self.keys.len() != self.index.key().len() {
+            return Err(DBError::ExpressionInputCount(
+                format!("lookup join has {} keys, index has {}", self.keys.len(),
+                        self.index.key().len())))
+        }
+
+        let mut out = Vec::with_capacity(self.keys.len());
+        for (name, attr) in self.keys.iter().zip(self.index.key()) {
+            let pos = schema.exists_ok(name)?;
+            if schema[pos].dtype != attr.dtype {
+                return Err(DBError::AttributeType(name.clone()))
+            }
+            out.push(pos);
+        }
+
+        Ok(out)
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.index.check(self.table)?;
+
+        let mut cursor = self.src.bind(alloc)?;
+
+        let table_schema = self.table.schema();
+        let payload: Vec<usize> = (0 .. table_schema.count())
+            .filter(|pos| !self.index.columns.contains(pos))
+            .collect();
+
+        let (keys, src_count, schema) = {
+            let src_schema = cursor.schema();
+            let mut attrs: Vec<Attribute> = src_schema.iter().cloned().collect();
+            attrs.extend(payload.iter().map(|pos| table_schema[*pos].clone()));
+
+            (self.source_keys(src_schema)?, src_schema.count(), Schema::from_vec(attrs)?)
+        };
+
+        let mut out = Table::new(alloc, &schema, None);
+
+        loop {
+            let view = match cursor.next(DEFAULT_CURSOR_FETCH)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => break,
+            };
+
+            for row in 0 .. view.rows() {
+                let key = match row_key(&view, &keys, row)? {
+                    Some(key) => key,
+                    None => continue,
+                };
+
+                for found in self.index.lookup(self.table, &key)? {
+                    let out_row = out.add_row()?;
+
+                    for pos in 0 .. src_count {
+                        let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                        out.set(pos, out_row, column_value(col, row)?)?;
+                    }
+
+                    for (offset, pos) in payload.iter().enumerate() {
+                        let col = self.table.column(*pos)
+                            .ok_or(DBError::make_column_unknown_pos(*pos))?;
+                        out.set(src_count + offset, out_row, column_value(col, found)?)?;
+                    }
+                }
+            }
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+
+/// Index of the rows of a `Table` in the order of a key column, for range lookups.
+///
+/// Like a B-tree leaf level, the row locations are kept sorted by key so a range is found with
+/// binary searches. Rows with a NULL (or NaN) key are not indexed. Keys can be of any type
+/// `stats::compare_values` orders. Like `HashIndex`, using it after the table changed is a
+/// `DBError::IndexStale` error.
+pub struct SortedIndex {
+    column: usize,
+    attr: Attribute,
+    /// Rows by key, rows with the same key in row order
+    rows: Vec<RowOffset>,
+    version: u64,
+}
+
+fn compare_key(attr: &Attribute, lhs: &Value, rhs: &Value) -> Result<Ordering, DBError> {
+    compare_values(lhs, rhs).ok_or_else(|| DBError::AttributeType(attr.name.clone()))
+}
+
+impl SortedIndex {
+    /// Index the current rows of the `table` by the `column` values
+    pub fn build<'t>(table: &'t Table<'t>, column: &str) -> Result<SortedIndex, DBError> {
+        let pos = table.schema().exists_ok(column)?;
+        let attr = table.schema()[pos].clone();
+        let col = table.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+
+        let mut keyed = Vec::with_capacity(table.rows());
+        for row in 0 .. table.rows() {
+            let value = column_value(col, row)?;
+            if compare_values(&value, &value).is_none() {
+                match value {
+                    Value::NULL | Value::FLOAT32(_) | Value::FLOAT64(_) => continue,
+                    // Unordered types
+                    _ => return Err(DBError::AttributeType(attr.name.clone())),
+                }
+            }
+            keyed.push((value, row));
+        }
+
+        // Stable, so equal keys stay in row order
+        keyed.sort_by(|l, r| compare_values(&l.0, &r.0).unwrap_or(Ordering::Equal));
+
+        Ok(SortedIndex {
+            column: pos,
+            attr: attr,
+            rows: keyed.into_iter().map(|(_, row)| row).collect(),
+            version: table.version(),
+        })
+    }
+
+    /// Key attribute
+    pub fn key(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// True if the table wasn't modified since the index was built
+    pub fn is_current(&self, table: &Table) -> bool {
+        table.version() == self.version
+    }
+
+    fn check(&self, table: &Table) -> Result<(), DBError> {
+        if self.is_current(table) {
+            Ok(())
+        } else {
+            Err(DBError::IndexStale(format!("built at table version {}, table is at version {}",
+                                            self.version, table.version())))
+        }
+    }
+
+    /// Position in the sorted rows where the keys are past the bound: first row in a lower
+    /// bound, or end of the rows in an upper bound
+    fn partition<'t>(&self, table: &'t Table<'t>, bound: &Bound<Value>, upper: bool)
+        -> Result<usize, DBError>
+    {
+        let (value, inclusive) = match *bound {
+            Bound::Included(ref v) => (v, true),
+            Bound::Excluded(ref v) => (v, false),
+            Bound::Unbounded => return Ok(if upper { self.rows.len() } else { 0 }),
+        };
+
+        let col = table.column(self.column).ok_or(DBError::make_column_unknown_pos(self.column))?;
+
+        // Lower bounds skip the keys less (or equal if exclusive) than the value, upper bounds
+        // keep the keys less (or equal if inclusive)
+        let (mut low, mut high) = (0, self.rows.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let order = compare_key(&self.attr, &column_value(col, self.rows[mid])?, value)?;
+            let before = match order {
+                Ordering::Less      => true,
+                Ordering::Equal     => upper == inclusive,
+                Ordering::Greater   => false,
+            };
+
+            if before {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        Ok(low)
+    }
+
+    /// Rows of the `table` with a key in the range, in key order
+    pub fn range<'t>(&self, table: &'t Table<'t>, lower: &Bound<Value>, upper: &Bound<Value>)
+        -> Result<&[RowOffset], DBError>
+    {
+        self.check(table)?;
+
+        let start = self.partition(table, lower, false)?;
+        let end = self.partition(table, upper, true)?;
+
+        Ok(if start < end { &self.rows[start .. end] } else { &[] })
+    }
+
+    /// Rows with `low <= key <= high`, in key order
+    pub fn between<'t>(&self, table: &'t Table<'t>, low: Value, high: Value)
+        -> Result<&[RowOffset], DBError>
+    {
+        self.range(table, &Bound::Included(low), &Bound::Included(high))
+    }
+
+    /// Rows with `key > value`, in key order
+    pub fn greater<'t>(&self, table: &'t Table<'t>, value: Value)
+        -> Result<&[RowOffset], DBError>
+    {
+        self.range(table, &Bound::Excluded(value), &Bound::Unbounded)
+    }
+
+    /// Rows with `key < value`, in key order
+    pub fn less<'t>(&self, table: &'t Table<'t>, value: Value) -> Result<&[RowOffset], DBError> {
+        self.range(table, &Bound::Unbounded, &Bound::Excluded(value))
+    }
+}
+
+/// Scan of the rows of a table in a key range of a `SortedIndex`, in key order. The output is
+/// sorted by the key, so it can feed merge joins and sorted aggregation without a sort.
+///
+/// Like `LookupJoin`, the rows are gathered from all over the table, so the scan is materialized
+/// into a `Block` rather than being an `Operation`.
+pub struct IndexRangeScan<'a> {
+    pub table: &'a Table<'a>,
+    pub index: &'a SortedIndex,
+    pub lower: Bound<Value<'a>>,
+    pub upper: Bound<Value<'a>>,
+}
+
+impl<'a> IndexRangeScan<'a> {
+    pub fn new(table: &'a Table<'a>, index: &'a SortedIndex, lower: Bound<Value<'a>>,
+               upper: Bound<Value<'a>>) -> IndexRangeScan<'a>
+    {
+        IndexRangeScan { table: table, index: index, lower: lower, upper: upper }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        let rows = self.index.range(self.table, &self.lower, &self.upper)?;
+
+        let count = self.table.schema().count();
+        let mut out = Table::new(alloc, self.table.schema(), Some(rows.len()));
+
+        for row in rows {
+            let out_row = out.add_row()?;
+
+            for pos in 0 .. count {
+                let col = self.table.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                out.set(pos, out_row, column_value(col, *row)?)?;
+            }
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+
+/// Lower cased alphanumeric words of the text, in text order. This is how `TextIndex` and the
+/// `text_matches` predicate split text.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Inverted index of the words of a TEXT column, for full-text search.
+///
+/// Answers `text_matches(column, query)`: the rows containing every word of the query, in any
+/// order and position (see `tokenize`). NULL rows are not indexed. Like `HashIndex`, using it
+/// after the table changed is a `DBError::IndexStale` error.
+pub struct TextIndex {
+    attr: Attribute,
+    /// Rows of each word, in row order
+    postings: HashMap<String, Vec<RowOffset>>,
+    version: u64,
+}
+
+impl TextIndex {
+    /// Index the words of the current rows of the TEXT `column`
+    pub fn build<'t>(table: &'t Table<'t>, column: &str) -> Result<TextIndex, DBError> {
+        let pos = table.schema().exists_ok(column)?;
+        let attr = table.schema()[pos].clone();
+        let col = table.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+
+        if attr.dtype != Type::TEXT {
+            return Err(DBError::AttributeType(attr.name.clone()))
+        }
+
+        let mut postings: HashMap<String, Vec<RowOffset>> = HashMap::new();
+        for row in 0 .. table.rows() {
+            let text = match column_value(col, row)? {
+                Value::TEXT(text) => text,
+                _ => continue,
+            };
+
+            for word in tokenize(text) {
+                let rows = postings.entry(word).or_insert_with(Vec::new);
+                // Words repeated in the row
+                if rows.last() != Some(&row) {
+                    rows.push(row);
+                }
+            }
+        }
+
+        Ok(TextIndex { attr: attr, postings: postings, version: table.version() })
+    }
+
+    /// Indexed attribute
+    pub fn key(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// True if the table wasn't modified since the index was built
+    pub fn is_current(&self, table: &Table) -> bool {
+        table.version() == self.version
+    }
+
+    fn check(&self, table: &Table) -> Result<(), DBError> {
+        if self.is_current(table) {
+            Ok(())
+        } else {
+            Err(DBError::IndexStale(format!("built at table version {}, table is at version {}",
+                                            self.version, table.version())))
+        }
+    }
+
+    /// Rows of the `table` containing all the words of the `query`, in row order. A query without
+    /// words matches no rows.
+    pub fn search(&self, table: &Table, query: &str) -> Result<Vec<RowOffset>, DBError> {
+        self.check(table)?;
+
+        let mut lists = Vec::new();
+        for word in tokenize(query) {
+            match self.postings.get(&word) {
+                Some(rows) => lists.push(rows),
+                None => return Ok(Vec::new()),
+            }
+        }
+
+        // Intersect starting with the rarest word
+        lists.sort_by_key(|rows| rows.len());
+        let mut out = match lists.first() {
+            Some(rows) => (*rows).clone(),
+            None => return Ok(Vec::new()),
+        };
+
+        for rows in &lists[1 ..] {
+            out.retain(|row| rows.binary_search(row).is_ok());
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::ScanView;
+    use ::table::TableAppender;
+    use ::types::{UInt32, UInt64};
+
+    fn make_table() -> Table<'static> {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: true, dtype: Type::UINT32},
+            Attribute{name: "kind".to_string(), nullable: false, dtype: Type::TEXT},
+            Attribute{name: "price".to_string(), nullable: false, dtype: Type::UINT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 20 {
+                appender = if v == 19 {
+                    appender.add_row().set_null(true)
+                } else {
+                    appender.add_row().set((v % 10) as u32)
+                };
+                appender = appender.set(if v < 10 { "a" } else { "b" }).set((v * 100) as u64);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table
+    }
+
+    // Multi column lookups find all the rows with the key, NULL keys are not indexed
+    #[test]
+    fn build_and_lookup() {
+        let mut table = make_table();
+
+        {
+            let index = HashIndex::build(&table, &["id", "kind"]).unwrap();
+            let lookup = |id, kind| index.lookup(&table, &[id, Value::TEXT(kind)]).unwrap();
+            assert_eq!(lookup(Value::UINT32(3), "b"), vec![13]);
+            assert!(lookup(Value::UINT32(3), "c").is_empty());
+            assert!(lookup(Value::NULL, "b").is_empty());
+
+            let by_id = HashIndex::build(&table, &["id"]).unwrap();
+            assert_eq!(by_id.lookup(&table, &[Value::UINT32(5)]).unwrap(), vec![5, 15]);
+            assert_eq!(by_id.lookup(&table, &[Value::UINT32(9)]).unwrap(), vec![9]);
+        }
+
+        let index = HashIndex::build(&table, &["id"]).unwrap();
+        table.add_row().unwrap();
+        assert!(!index.is_current(&table));
+
+        match index.lookup(&table, &[Value::UINT32(5)]) {
+            Err(DBError::IndexStale(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Each source row joins all the table rows with its key
+    #[test]
+    fn lookup_join() {
+        let table = make_table();
+        let index = HashIndex::build(&table, &["id"]).unwrap();
+
+        let schema = Schema::make_one_attr("id", false, Type::UINT32);
+        let mut probe = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut probe)
+                .add_row().set(2 as u32)
+                .add_row().set(42 as u32)
+                .add_row().set(9 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let probe = probe.take().unwrap();
+        let join = LookupJoin::new(ScanView::new(&probe, None), &["id"], &table, &index);
+        let out = join.execute(&allocator::GLOBAL).unwrap();
+
+        let names: Vec<&str> = out.schema().iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "kind", "price"]);
+        assert_eq!(out.rows(), 3);
+
+        let ids = column_row_data::<UInt32>(out.column(0).unwrap()).unwrap();
+        let prices = column_row_data::<UInt64>(out.column(2).unwrap()).unwrap();
+        assert_eq!(&ids.values[.. 3], &[2, 2, 9]);
+        assert_eq!(&prices.values[.. 3], &[200, 1200, 900]);
+
+        let join = LookupJoin::new(ScanView::new(&probe, None), &["kind"], &table, &index);
+        match join.execute(&allocator::GLOBAL) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Ranges are in key order, equal keys in row order, NULL keys are not indexed
+    #[test]
+    fn sorted_range() {
+        let table = make_table();
+        let index = SortedIndex::build(&table, "id").unwrap();
+
+        assert_eq!(index.between(&table, Value::UINT32(3), Value::UINT32(4)).unwrap(),
+                   &[3, 13, 4, 14]);
+        assert_eq!(index.greater(&table, Value::UINT32(8)).unwrap(), &[9]);
+        assert_eq!(index.less(&table, Value::UINT32(1)).unwrap(), &[0, 10]);
+        assert!(index.between(&table, Value::UINT32(5), Value::UINT32(4)).unwrap().is_empty());
+
+        let all = index.range(&table, &Bound::Unbounded, &Bound::Unbounded).unwrap();
+        assert_eq!(all.len(), 19);
+
+        match index.greater(&table, Value::TEXT("a")) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Scanned rows are copied in key order
+    #[test]
+    fn index_range_scan() {
+        let table = make_table();
+        let index = SortedIndex::build(&table, "id").unwrap();
+
+        let scan = IndexRangeScan::new(&table, &index, Bound::Excluded(Value::UINT32(6)),
+                                       Bound::Included(Value::UINT32(8)));
+        let out = scan.execute(&allocator::GLOBAL).unwrap();
+        assert_eq!(out.rows(), 4);
+
+        let ids = column_row_data::<UInt32>(out.column(0).unwrap()).unwrap();
+        let prices = column_row_data::<UInt64>(out.column(2).unwrap()).unwrap();
+        assert_eq!(&ids.values[.. 4], &[7, 7, 8, 8]);
+        assert_eq!(&prices.values[.. 4], &[700, 1700, 800, 1800]);
+    }
+
+    // Rows with all the query words, whatever their case and position
+    #[test]
+    fn text_search() {
+        let schema = Schema::make_one_attr("msg", true, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("Disk full on host-1")
+                .add_row().set("host-2: disk ok, disk ok")
+                .add_row().set_null(true)
+                .add_row().set("FULL restart of HOST-2")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        assert_eq!(tokenize("host-2: Disk ok"), vec!["host", "2", "disk", "ok"]);
+
+        let index = TextIndex::build(&table, "msg").unwrap();
+        assert_eq!(index.search(&table, "disk").unwrap(), vec![0, 1]);
+        assert_eq!(index.search(&table, "full host").unwrap(), vec![0, 3]);
+        assert_eq!(index.search(&table, "Host 2 full").unwrap(), vec![3]);
+        assert!(index.search(&table, "disk missing").unwrap().is_empty());
+        assert!(index.search(&table, " - ").unwrap().is_empty());
+
+        match TextIndex::build(&make_table(), "id") {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// vim: set ts=4 sw=4 et :
+
+//! Interning of repeated TEXT values.
+//!
+//! A column with an `InternPool` (see `Column::intern_text()`) stores each distinct value in its
+//! arena once, rows with the same value share the `RawData` handle. It's meant for log-like data
+//! with few distinct strings (hosts, levels, status codes); the pool stops taking new values once
+//! it has `max_entries` of them, so a high cardinality column costs a bounded lookup table.
+//!
+//! The lookup table lives on the heap, not in the column allocator.
+
+use std::collections::HashMap;
+
+use ::allocator::ChainedArena;
+use ::error::DBError;
+use ::metrics::Metrics;
+use ::types::RawData;
+
+/// Distinct values kept by a pool when not configured otherwise
+pub const DEFAULT_INTERN_ENTRIES: usize = 4096;
+
+/// Counters of an `InternPool`
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct InternStats {
+    /// Values that were already in the pool
+    pub hits: u64,
+    /// Values copied into the arena (including the ones that didn't fit in the pool)
+    pub misses: u64,
+    /// Distinct values in the pool
+    pub distinct: usize,
+    /// Arena bytes not written thanks to the hits
+    pub bytes_saved: usize,
+}
+
+impl InternStats {
+    /// Share of the values that were hits, 0 without values
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0.0 } else { self.hits as f64 / total as f64 }
+    }
+
+    /// Add the counters to `intern.hits`, `intern.misses` and `intern.bytes_saved`
+    pub fn record(&self, metrics: &Metrics) {
+        metrics.increment("intern.hits", self.hits as i64);
+        metrics.increment("intern.misses", self.misses as i64);
+        metrics.increment("intern.bytes_saved", self.bytes_saved as i64);
+    }
+}
+
+/// Arena handles of the distinct values of a column
+#[derive(Clone, Debug)]
+pub struct InternPool {
+    handles: HashMap<Vec<u8>, RawData>,
+    max_entries: usize,
+    stats: InternStats,
+}
+
+impl InternPool {
+    pub fn new(max_entries: usize) -> InternPool {
+        InternPool {
+            handles: HashMap::new(),
+            max_entries: max_entries,
+            stats: InternStats::default(),
+        }
+    }
+
+    /// Handle of the value, appending it to the arena the first time it's seen
+    pub fn intern(&mut self, data: &[u8], arena: &mut ChainedArena) -> Result<RawData, DBError> {
+        if let Some(handle) = self.handles.get(data) {
+            self.stats.hits += 1;
+            self.stats.bytes_saved += data.len();
+            return Ok(*handle)
+        }
+
+        let handle = arena.append(data)?;
+        self.stats.misses += 1;
+        if self.handles.len() < self.max_entries {
+            self.handles.insert(data.to_vec(), handle);
+            self.stats.distinct = self.handles.len();
+        }
+        Ok(handle)
+    }
+
+    /// Forget the values (their arena was reset or replaced), keeping the counters
+    pub fn clear(&mut self) {
+        self.handles.clear();
+        self.stats.distinct = 0;
+    }
+
+    pub fn stats(&self) -> InternStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View, column_row_data};
+    use ::schema::{Attribute, Schema};
+    use ::types::{self, Type, Value};
+
+    fn handles(block: &Block, pos: usize) -> Vec<(u32, u32)> {
+        let rows = column_row_data::<types::Text>(block.column(pos).unwrap()).unwrap();
+        rows.values[.. block.rows()].iter().map(|h| (h.chunk, h.offset)).collect()
+    }
+
+    // Rows with the same value share the arena copy, up to the pool size
+    #[test]
+    fn intern_text() {
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "level".to_string(), nullable: false, dtype: Type::TEXT },
+            Attribute { name: "msg".to_string(), nullable: false, dtype: Type::TEXT },
+        ]).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.intern_columns(&["level"], 2).unwrap();
+
+        for level in &["info", "warn", "info", "error", "info", "error"] {
+            block.append_row(&[Value::TEXT(level), Value::TEXT("same")]).unwrap();
+        }
+
+        let level = handles(&block, 0);
+        assert_eq!(level[0], level[2]);
+        assert_eq!(level[0], level[4]);
+        assert!(level[3] != level[5], "error doesn't fit in the pool");
+        let msg = handles(&block, 1);
+        assert!(msg[0] != msg[1]);
+        assert!(block.value(3, 0).unwrap() == Value::TEXT("error"));
+
+        let stats = block.intern_stats();
+        assert_eq!(stats, vec![("level".to_string(), InternStats {
+            hits: 2, misses: 4, distinct: 2, bytes_saved: 8,
+        })]);
+        assert!((stats[0].1.hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+        let metrics = Metrics::default();
+        stats[0].1.record(&metrics);
+        assert_eq!(metrics.counter("intern.bytes_saved"), 8);
+
+        // Copies keep sharing, cleared blocks keep interning
+        let copy = block.deep_copy().unwrap();
+        assert_eq!(handles(&copy, 0), level);
+        assert!(copy.value(4, 0).unwrap() == Value::TEXT("info"));
+
+        block.clear();
+        block.append_row(&[Value::TEXT("debug"), Value::TEXT("x")]).unwrap();
+        block.append_row(&[Value::TEXT("debug"), Value::TEXT("x")]).unwrap();
+        let level = handles(&block, 0);
+        assert_eq!(level[0], level[1]);
+        assert_eq!(block.intern_stats()[0].1.hits, 3);
+
+        match block.intern_columns(&["missing"], 2) {
+            Err(DBError::AttributeMissing(_)) | Err(DBError::AttributeUnknown(..)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,1158 @@
+//! Arrow IPC stream format, as read by `pyarrow.ipc.open_stream` (and sent by Arrow Flight).
+//!
+//! A stream is a schema message, record batch messages and an end of stream marker. Each message
+//! is Flatbuffers metadata followed by a body of the column buffers. `IpcStreamWriter` writes a
+//! batch per view (or cursor chunk), `IpcStreamReader` reads each batch into a block. LIST and
+//! STRUCT columns, dictionary encoded fields and compressed bodies are not supported.
+//!
+//! Types: the integers are (unsigned) Int, FLOAT32 and FLOAT64 FloatingPoint, BOOLEAN Bool,
+//! TIMESTAMP a microsecond Timestamp, INTERVAL a MONTH_DAY_NANO Interval, TEXT LargeUtf8, BLOB
+//! LargeBinary and UUID the `arrow.uuid` extension of FixedSizeBinary(16). The reader also takes
+//! 8 and 16 bit Ints (as INT32 or UINT32), Utf8 and Binary, other FixedSizeBinary widths (as
+//! BLOB) and Timestamps of other units (converted to microseconds).
+
+use std::io::{ErrorKind, Read, Write};
+use std::str;
+
+use ::allocator::Allocator;
+use ::block::{Block, RefColumn, View, column_value};
+use ::decode::DecodeLimits;
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{IntervalValue, Type, Value};
+
+/// Marks the length of an (encapsulated) message, a zero length ends the stream
+const CONTINUATION: u32 = 0xffff_ffff;
+
+/// Metadata version V5
+const METADATA_VERSION: i16 = 4;
+
+// MessageHeader union
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_DICTIONARY_BATCH: u8 = 2;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+// Type union
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_BINARY: u8 = 4;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_TIMESTAMP: u8 = 10;
+const TYPE_INTERVAL: u8 = 11;
+const TYPE_FIXED_SIZE_BINARY: u8 = 15;
+const TYPE_LARGE_BINARY: u8 = 19;
+const TYPE_LARGE_UTF8: u8 = 20;
+
+const PRECISION_SINGLE: i16 = 1;
+const PRECISION_DOUBLE: i16 = 2;
+const INTERVAL_MONTH_DAY_NANO: i16 = 2;
+/// Microseconds per unit of TimeUnit SECOND, MILLISECOND and MICROSECOND
+const MICROS_PER_UNIT: [i64; 3] = [1_000_000, 1_000, 1];
+const TIME_UNIT_MICROSECOND: i16 = 2;
+const TIME_UNIT_NANOSECOND: i16 = 3;
+
+const EXTENSION_NAME: &str = "ARROW:extension:name";
+const EXTENSION_METADATA: &str = "ARROW:extension:metadata";
+const UUID_EXTENSION: &str = "arrow.uuid";
+
+/// Alignment of the body buffers
+const BUFFER_ALIGN: usize = 8;
+
+fn invalid<S: Into<String>>(msg: S) -> DBError {
+    DBError::ValueParse(format!("Arrow IPC {}", msg.into()))
+}
+
+fn unsupported<S: Into<String>>(msg: S) -> DBError {
+    DBError::Unsupported(format!("Arrow IPC {}", msg.into()))
+}
+
+/// Table field value of a `FlatBuilder`
+enum Slot {
+    Byte(u8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    /// Object built earlier
+    Offset(usize),
+}
+
+/// Flatbuffers builder. Buffers are built back to front, objects are referenced by their
+/// distance to the end of the buffer (so offsets, to objects built earlier, point forward).
+#[derive(Default)]
+struct FlatBuilder {
+    buf: Vec<u8>,
+}
+
+impl FlatBuilder {
+    fn prepend(&mut self, bytes: &[u8]) {
+        self.buf.splice(0 .. 0, bytes.iter().cloned());
+    }
+
+    /// Pad so the `len` bytes prepended next start aligned
+    fn align(&mut self, len: usize, align: usize) {
+        let pad = (align - (self.buf.len() + len) % align) % align;
+        self.prepend(&[0u8; 8][.. pad]);
+    }
+
+    fn offset(&mut self, target: usize) {
+        self.align(4, 4);
+        let here = self.buf.len() + 4;
+        self.prepend(&((here - target) as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) -> usize {
+        self.align(s.len() + 1, 4);
+        self.prepend(&[0]);
+        self.prepend(s.as_bytes());
+        self.prepend(&(s.len() as u32).to_le_bytes());
+        self.buf.len()
+    }
+
+    /// Vector of objects
+    fn offsets(&mut self, targets: &[usize]) -> usize {
+        for target in targets.iter().rev() {
+            self.offset(*target);
+        }
+        self.prepend(&(targets.len() as u32).to_le_bytes());
+        self.buf.len()
+    }
+
+    /// Vector of (FieldNode or Buffer) structs of two longs
+    fn long_pairs(&mut self, pairs: &[(i64, i64)]) -> usize {
+        self.align(16 * pairs.len(), 8);
+        for &(a, b) in pairs.iter().rev() {
+            self.prepend(&b.to_le_bytes());
+            self.prepend(&a.to_le_bytes());
+        }
+        self.prepend(&(pairs.len() as u32).to_le_bytes());
+        self.buf.len()
+    }
+
+    /// Table of the (field id, value) slots, with its vtable just before it
+    fn table(&mut self, slots: &[(usize, Slot)]) -> usize {
+        let end = self.buf.len();
+        let mut fields = Vec::with_capacity(slots.len());
+
+        for &(id, ref slot) in slots {
+            match *slot {
+                Slot::Byte(v) => self.prepend(&[v]),
+                Slot::Short(v) => {
+                    self.align(2, 2);
+                    self.prepend(&v.to_le_bytes());
+                },
+                Slot::Int(v) => {
+                    self.align(4, 4);
+                    self.prepend(&v.to_le_bytes());
+                },
+                Slot::Long(v) => {
+                    self.align(8, 8);
+                    self.prepend(&v.to_le_bytes());
+                },
+                Slot::Offset(target) => self.offset(target),
+            }
+            fields.push((id, self.buf.len()));
+        }
+
+        let vtable_len = 4 + 2 * slots.iter().map(|s| s.0 + 1).max().unwrap_or(0);
+        self.align(4, 4);
+        self.prepend(&(vtable_len as i32).to_le_bytes());
+        let table = self.buf.len();
+
+        let mut vtable = vec![0u8; vtable_len];
+        vtable[0 .. 2].copy_from_slice(&(vtable_len as u16).to_le_bytes());
+        vtable[2 .. 4].copy_from_slice(&((table - end) as u16).to_le_bytes());
+        for (id, pos) in fields {
+            vtable[4 + 2 * id .. 6 + 2 * id].copy_from_slice(&((table - pos) as u16).to_le_bytes());
+        }
+        self.prepend(&vtable);
+
+        table
+    }
+
+    /// Buffer of the root table, its length a multiple of 8
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        self.align(4, 8);
+        self.offset(root);
+        self.buf
+    }
+}
+
+fn bytes_at(buf: &[u8], pos: usize, len: usize) -> Result<&[u8], DBError> {
+    buf.get(pos .. pos.saturating_add(len)).ok_or_else(|| invalid("metadata is truncated"))
+}
+
+fn u16_at(buf: &[u8], pos: usize) -> Result<u16, DBError> {
+    let mut out = [0u8; 2];
+    out.copy_from_slice(bytes_at(buf, pos, 2)?);
+    Ok(u16::from_le_bytes(out))
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Result<u32, DBError> {
+    let mut out = [0u8; 4];
+    out.copy_from_slice(bytes_at(buf, pos, 4)?);
+    Ok(u32::from_le_bytes(out))
+}
+
+fn i64_at(buf: &[u8], pos: usize) -> Result<i64, DBError> {
+    let mut out = [0u8; 8];
+    out.copy_from_slice(bytes_at(buf, pos, 8)?);
+    Ok(i64::from_le_bytes(out))
+}
+
+/// Table of a Flatbuffers buffer, reads are bounds checked
+#[derive(Clone, Copy)]
+struct FlatTable<'m> {
+    buf: &'m [u8],
+    pos: usize,
+}
+
+impl<'m> FlatTable<'m> {
+    fn root(buf: &'m [u8]) -> Result<FlatTable<'m>, DBError> {
+        Ok(FlatTable { buf: buf, pos: u32_at(buf, 0)? as usize })
+    }
+
+    /// Position of the field, `None` if absent (default)
+    fn field(&self, id: usize) -> Result<Option<usize>, DBError> {
+        let soffset = u32_at(self.buf, self.pos)? as i32 as i64;
+        let vtable = self.pos as i64 - soffset;
+        if vtable < 0 {
+            return Err(invalid("metadata vtable out of bounds"))
+        }
+
+        let vtable = vtable as usize;
+        if 6 + 2 * id > u16_at(self.buf, vtable)? as usize {
+            return Ok(None)
+        }
+
+        match u16_at(self.buf, vtable + 4 + 2 * id)? {
+            0 => Ok(None),
+            offset => Ok(Some(self.pos + offset as usize)),
+        }
+    }
+
+    fn byte(&self, id: usize) -> Result<u8, DBError> {
+        match self.field(id)? {
+            Some(pos) => Ok(bytes_at(self.buf, pos, 1)?[0]),
+            None => Ok(0),
+        }
+    }
+
+    fn short(&self, id: usize) -> Result<i16, DBError> {
+        match self.field(id)? {
+            Some(pos) => Ok(u16_at(self.buf, pos)? as i16),
+            None => Ok(0),
+        }
+    }
+
+    fn int(&self, id: usize) -> Result<i32, DBError> {
+        match self.field(id)? {
+            Some(pos) => Ok(u32_at(self.buf, pos)? as i32),
+            None => Ok(0),
+        }
+    }
+
+    fn long(&self, id: usize) -> Result<i64, DBError> {
+        match self.field(id)? {
+            Some(pos) => i64_at(self.buf, pos),
+            None => Ok(0),
+        }
+    }
+
+    fn target(&self, id: usize) -> Result<Option<usize>, DBError> {
+        match self.field(id)? {
+            Some(pos) => Ok(Some(pos + u32_at(self.buf, pos)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    fn table(&self, id: usize) -> Result<Option<FlatTable<'m>>, DBError> {
+        Ok(self.target(id)?.map(|pos| FlatTable { buf: self.buf, pos: pos }))
+    }
+
+    /// Start and length of a vector of elements of `size` bytes (checked to be in bounds)
+    fn vector(&self, id: usize, size: usize) -> Result<(usize, usize), DBError> {
+        match self.target(id)? {
+            Some(pos) => {
+                let len = u32_at(self.buf, pos)? as usize;
+                bytes_at(self.buf, pos + 4, len * size)?;
+                Ok((pos + 4, len))
+            },
+            None => Ok((0, 0)),
+        }
+    }
+
+    fn string(&self, id: usize) -> Result<Option<&'m str>, DBError> {
+        if self.field(id)?.is_none() {
+            return Ok(None)
+        }
+
+        let (start, len) = self.vector(id, 1)?;
+        str::from_utf8(&self.buf[start .. start + len]).map(Some)
+            .map_err(|_| invalid("metadata string is not UTF-8"))
+    }
+
+    fn tables(&self, id: usize) -> Result<Vec<FlatTable<'m>>, DBError> {
+        let (start, len) = self.vector(id, 4)?;
+        (0 .. len).map(|i| {
+            let pos = start + 4 * i;
+            Ok(FlatTable { buf: self.buf, pos: pos + u32_at(self.buf, pos)? as usize })
+        }).collect()
+    }
+
+    fn long_pairs(&self, id: usize) -> Result<Vec<(i64, i64)>, DBError> {
+        let (start, len) = self.vector(id, 16)?;
+        (0 .. len).map(|i| {
+            Ok((i64_at(self.buf, start + 16 * i)?, i64_at(self.buf, start + 16 * i + 8)?))
+        }).collect()
+    }
+}
+
+/// Metadata and body of a message
+type Message = (Vec<u8>, Vec<u8>);
+
+/// Bit of each row set when `set(row)`, LSB first
+fn bitmap<F: Fn(usize) -> bool>(rows: usize, set: F) -> Vec<u8> {
+    let mut out = vec![0u8; (rows + 7) / 8];
+    for row in (0 .. rows).filter(|r| set(*r)) {
+        out[row / 8] |= 1 << (row % 8);
+    }
+    out
+}
+
+/// Union type and type table of the attribute
+fn write_type(fb: &mut FlatBuilder, attr: &Attribute) -> Result<(u8, usize), DBError> {
+    let int = |fb: &mut FlatBuilder, bits: i32, signed: bool| {
+        (TYPE_INT, fb.table(&[(0, Slot::Int(bits)), (1, Slot::Byte(signed as u8))]))
+    };
+
+    Ok(match attr.dtype {
+        Type::UINT32    => int(fb, 32, false),
+        Type::UINT64    => int(fb, 64, false),
+        Type::INT32     => int(fb, 32, true),
+        Type::INT64     => int(fb, 64, true),
+        Type::FLOAT32   => (TYPE_FLOATING_POINT, fb.table(&[(0, Slot::Short(PRECISION_SINGLE))])),
+        Type::FLOAT64   => (TYPE_FLOATING_POINT, fb.table(&[(0, Slot::Short(PRECISION_DOUBLE))])),
+        Type::BOOLEAN   => (TYPE_BOOL, fb.table(&[])),
+        Type::TIMESTAMP => (TYPE_TIMESTAMP, fb.table(&[(0, Slot::Short(TIME_UNIT_MICROSECOND))])),
+        Type::INTERVAL  =>
+            (TYPE_INTERVAL, fb.table(&[(0, Slot::Short(INTERVAL_MONTH_DAY_NANO))])),
+        Type::TEXT      => (TYPE_LARGE_UTF8, fb.table(&[])),
+        Type::BLOB      => (TYPE_LARGE_BINARY, fb.table(&[])),
+        Type::UUID      => (TYPE_FIXED_SIZE_BINARY, fb.table(&[(0, Slot::Int(16))])),
+        _ => return Err(unsupported(format!("{} column {}", attr.dtype, attr.name))),
+    })
+}
+
+/// Schema message metadata
+fn schema_message(schema: &Schema) -> Result<Vec<u8>, DBError> {
+    let mut fb = FlatBuilder::default();
+    let mut fields = Vec::with_capacity(schema.count());
+
+    for attr in schema.iter() {
+        let (type_type, dtype) = write_type(&mut fb, attr)?;
+
+        let mut metadata = Vec::new();
+        if attr.dtype == Type::UUID {
+            for &(key, value) in &[(EXTENSION_NAME, UUID_EXTENSION), (EXTENSION_METADATA, "")] {
+                let value = fb.string(value);
+                let key = fb.string(key);
+                metadata.push(fb.table(&[(0, Slot::Offset(key)), (1, Slot::Offset(value))]));
+            }
+        }
+        let metadata = fb.offsets(&metadata);
+        let children = fb.offsets(&[]);
+        let name = fb.string(&attr.name);
+
+        fields.push(fb.table(&[
+            (0, Slot::Offset(name)),
+            (1, Slot::Byte(attr.nullable as u8)),
+            (2, Slot::Byte(type_type)),
+            (3, Slot::Offset(dtype)),
+            (5, Slot::Offset(children)),
+            (6, Slot::Offset(metadata)),
+        ]));
+    }
+
+    let fields = fb.offsets(&fields);
+    let header = fb.table(&[(0, Slot::Short(0)), (1, Slot::Offset(fields))]);
+    Ok(message(fb, HEADER_SCHEMA, header, 0))
+}
+
+fn message(mut fb: FlatBuilder, header_type: u8, header: usize, body_len: usize) -> Vec<u8> {
+    let root = fb.table(&[
+        (0, Slot::Short(METADATA_VERSION)),
+        (1, Slot::Byte(header_type)),
+        (2, Slot::Offset(header)),
+        (3, Slot::Long(body_len as i64)),
+    ]);
+    fb.finish(root)
+}
+
+/// Body of a record batch message
+#[derive(Default)]
+struct Body {
+    data: Vec<u8>,
+    nodes: Vec<(i64, i64)>,
+    buffers: Vec<(i64, i64)>,
+}
+
+impl Body {
+    fn buffer(&mut self, bytes: &[u8]) {
+        self.buffers.push((self.data.len() as i64, bytes.len() as i64));
+        self.data.extend_from_slice(bytes);
+
+        let pad = (BUFFER_ALIGN - self.data.len() % BUFFER_ALIGN) % BUFFER_ALIGN;
+        self.data.extend_from_slice(&[0u8; BUFFER_ALIGN][.. pad]);
+    }
+
+    /// Buffers of the column rows: validity bitmap (empty without NULLs), then the values
+    fn column(&mut self, attr: &Attribute, col: &RefColumn, rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        let mut values = Vec::with_capacity(rows);
+        for row in 0 .. rows {
+            values.push(column_value(col, row)?);
+        }
+
+        let null_count = values.iter().filter(|v| **v == Value::NULL).count();
+        self.nodes.push((rows as i64, null_count as i64));
+        if null_count > 0 {
+            self.buffer(&bitmap(rows, |r| values[r] != Value::NULL));
+        } else {
+            self.buffer(&[]);
+        }
+
+        let varlen = attr.dtype == Type::TEXT || attr.dtype == Type::BLOB;
+        let mut data = Vec::new();
+        let mut offsets = 0i64.to_le_bytes().to_vec();
+
+        for value in &values {
+            match *value {
+                // Fixed width slots of NULLs are zeroed, varlen ones empty
+                Value::NULL if varlen || attr.dtype == Type::BOOLEAN => (),
+                Value::NULL => {
+                    let width = if attr.dtype == Type::INTERVAL { 16 } else {
+                        attr.dtype.size_of()
+                    };
+                    data.resize(data.len() + width, 0);
+                },
+                Value::UINT32(v)  => data.extend_from_slice(&v.to_le_bytes()),
+                Value::UINT64(v)  => data.extend_from_slice(&v.to_le_bytes()),
+                Value::INT32(v)   => data.extend_from_slice(&v.to_le_bytes()),
+                Value::INT64(v) | Value::TIMESTAMP(v) => data.extend_from_slice(&v.to_le_bytes()),
+                Value::FLOAT32(v) => data.extend_from_slice(&v.to_bits().to_le_bytes()),
+                Value::FLOAT64(v) => data.extend_from_slice(&v.to_bits().to_le_bytes()),
+                Value::INTERVAL(v) => {
+                    let nanos = v.micros.checked_mul(1000).ok_or_else(|| {
+                        DBError::ValueOutOfRange(format!("{} interval in nanoseconds", attr.name))
+                    })?;
+                    data.extend_from_slice(&v.months.to_le_bytes());
+                    data.extend_from_slice(&v.days.to_le_bytes());
+                    data.extend_from_slice(&nanos.to_le_bytes());
+                },
+                Value::UUID(ref v) => data.extend_from_slice(v),
+                Value::TEXT(v)    => data.extend_from_slice(v.as_bytes()),
+                Value::BLOB(v)    => data.extend_from_slice(v),
+                Value::BOOLEAN(_) => (),
+                _ => return Err(unsupported(format!("{} column {}", attr.dtype, attr.name))),
+            }
+
+            if varlen {
+                offsets.extend_from_slice(&(data.len() as i64).to_le_bytes());
+            }
+        }
+
+        if attr.dtype == Type::BOOLEAN {
+            let set = |r: usize| match values[r] { Value::BOOLEAN(v) => v, _ => false };
+            self.buffer(&bitmap(rows, set));
+        } else if varlen {
+            self.buffer(&offsets);
+            self.buffer(&data);
+        } else {
+            self.buffer(&data);
+        }
+
+        Ok(())
+    }
+}
+
+/// Writer of an Arrow IPC stream of rows of a schema
+pub struct IpcStreamWriter<W: Write> {
+    out: W,
+    schema: Schema,
+}
+
+impl<W: Write> IpcStreamWriter<W> {
+    /// Start the stream, writing the schema message
+    pub fn new(out: W, schema: &Schema) -> Result<IpcStreamWriter<W>, DBError> {
+        let mut writer = IpcStreamWriter { out: out, schema: schema.clone() };
+        let metadata = schema_message(schema)?;
+        writer.write_message(&metadata, &[])?;
+        Ok(writer)
+    }
+
+    fn write_message(&mut self, metadata: &[u8], body: &[u8]) -> Result<(), DBError> {
+        // The metadata length is a multiple of 8, so the body stays aligned
+        let mut prefix = CONTINUATION.to_le_bytes().to_vec();
+        prefix.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+
+        for bytes in &[&prefix[..], metadata, body] {
+            self.out.write_all(bytes).map_err(DBError::IO)?;
+        }
+        Ok(())
+    }
+
+    /// Write the rows of the view (of the stream schema) as a record batch
+    pub fn write_view<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        let schema = view.schema();
+        if schema.count() != self.schema.count() {
+            let msg = format!("{} attributes, the stream has {}", schema.count(),
+                              self.schema.count());
+            return Err(DBError::AttributeMissing(msg))
+        }
+        for (attr, expected) in schema.iter().zip(self.schema.iter()) {
+            if attr.dtype != expected.dtype {
+                return Err(DBError::AttributeType(attr.name.clone()))
+            }
+        }
+
+        let mut body = Body::default();
+        for (pos, attr) in self.schema.iter().enumerate() {
+            let col = view.column(pos).ok_or_else(|| DBError::make_column_unknown_pos(pos))?;
+            body.column(attr, col, view.rows())?;
+        }
+
+        let mut fb = FlatBuilder::default();
+        let buffers = fb.long_pairs(&body.buffers);
+        let nodes = fb.long_pairs(&body.nodes);
+        let header = fb.table(&[
+            (0, Slot::Long(view.rows() as i64)),
+            (1, Slot::Offset(nodes)),
+            (2, Slot::Offset(buffers)),
+        ]);
+
+        let metadata = message(fb, HEADER_RECORD_BATCH, header, body.data.len());
+        self.write_message(&metadata, &body.data)
+    }
+
+    /// Write the rows of the cursor, a record batch per chunk of up to `rows` rows. Returns the
+    /// number of rows written.
+    pub fn write_cursor<'a>(&mut self, cursor: &mut (Cursor<'a> + 'a), rows: RowOffset)
+        -> Result<RowOffset, DBError>
+    {
+        let mut written = 0;
+        while let CursorChunk::Next(view) = cursor.next(rows)? {
+            self.write_view(&view)?;
+            written += view.rows();
+        }
+        Ok(written)
+    }
+
+    /// End the stream, returns the flushed output
+    pub fn finish(mut self) -> Result<W, DBError> {
+        let mut end = CONTINUATION.to_le_bytes().to_vec();
+        end.extend_from_slice(&0u32.to_le_bytes());
+        self.out.write_all(&end).map_err(DBError::IO)?;
+        self.out.flush().map_err(DBError::IO)?;
+        Ok(self.out)
+    }
+}
+
+/// Value layout of an attribute read from a stream
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    /// Little endian integer of the bytes
+    Int(usize),
+    Float32,
+    Float64,
+    Bool,
+    /// Microseconds per unit, 0 for nanoseconds
+    Timestamp(i64),
+    Interval,
+    /// Offsets of the bytes
+    Varlen(usize),
+    Fixed(usize),
+}
+
+fn read_field(field: &FlatTable) -> Result<(Attribute, Layout), DBError> {
+    let name = field.string(0)?.unwrap_or("").to_string();
+    if field.field(4)?.is_some() {
+        return Err(unsupported(format!("dictionary encoded field {}", name)))
+    }
+    if field.vector(5, 4)?.1 > 0 {
+        return Err(unsupported(format!("nested field {}", name)))
+    }
+
+    let mut extension = None;
+    for kv in field.tables(6)? {
+        if kv.string(0)? == Some(EXTENSION_NAME) {
+            extension = kv.string(1)?;
+        }
+    }
+
+    let dtype = field.table(3)?.ok_or_else(|| invalid(format!("field {} without type", name)))?;
+    let unknown = || unsupported(format!("type of field {}", name));
+
+    let (dtype, layout) = match field.byte(2)? {
+        TYPE_INT => {
+            let signed = dtype.byte(1)? != 0;
+            match dtype.int(0)? {
+                bits @ 8 | bits @ 16 | bits @ 32 => {
+                    let dtype = if signed { Type::INT32 } else { Type::UINT32 };
+                    (dtype, Layout::Int(bits as usize / 8))
+                },
+                64 => (if signed { Type::INT64 } else { Type::UINT64 }, Layout::Int(8)),
+                _ => return Err(unknown()),
+            }
+        },
+        TYPE_FLOATING_POINT => match dtype.short(0)? {
+            PRECISION_SINGLE => (Type::FLOAT32, Layout::Float32),
+            PRECISION_DOUBLE => (Type::FLOAT64, Layout::Float64),
+            _ => return Err(unknown()),
+        },
+        TYPE_BOOL => (Type::BOOLEAN, Layout::Bool),
+        TYPE_TIMESTAMP => match dtype.short(0)? {
+            unit @ 0 ..= TIME_UNIT_MICROSECOND =>
+                (Type::TIMESTAMP, Layout::Timestamp(MICROS_PER_UNIT[unit as usize])),
+            TIME_UNIT_NANOSECOND => (Type::TIMESTAMP, Layout::Timestamp(0)),
+            _ => return Err(unknown()),
+        },
+        TYPE_INTERVAL if dtype.short(0)? == INTERVAL_MONTH_DAY_NANO =>
+            (Type::INTERVAL, Layout::Interval),
+        TYPE_UTF8 => (Type::TEXT, Layout::Varlen(4)),
+        TYPE_LARGE_UTF8 => (Type::TEXT, Layout::Varlen(8)),
+        TYPE_BINARY => (Type::BLOB, Layout::Varlen(4)),
+        TYPE_LARGE_BINARY => (Type::BLOB, Layout::Varlen(8)),
+        TYPE_FIXED_SIZE_BINARY => match dtype.int(0)? {
+            16 if extension == Some(UUID_EXTENSION) => (Type::UUID, Layout::Fixed(16)),
+            width if width > 0 => (Type::BLOB, Layout::Fixed(width as usize)),
+            _ => return Err(unknown()),
+        },
+        _ => return Err(unknown()),
+    };
+
+    Ok((Attribute { name: name, nullable: field.byte(1)? != 0, dtype: dtype }, layout))
+}
+
+/// Little endian integer of up to 8 bytes, sign extended if `signed`
+fn le_int(bytes: &[u8], signed: bool) -> i64 {
+    let mut out = [0u8; 8];
+    out[.. bytes.len()].copy_from_slice(bytes);
+    let shift = 64 - 8 * bytes.len() as u32;
+    if signed {
+        (i64::from_le_bytes(out) << shift) >> shift
+    } else {
+        i64::from_le_bytes(out)
+    }
+}
+
+/// Buffers of a column of a record batch body
+struct ColumnBuffers<'b> {
+    name: &'b str,
+    validity: Option<&'b [u8]>,
+    values: &'b [u8],
+    data: &'b [u8],
+}
+
+impl<'b> ColumnBuffers<'b> {
+    fn check(&self, len: usize, what: &str) -> Result<(), DBError> {
+        if len > self.values.len() {
+            return Err(invalid(format!("{} buffer of {} is truncated", what, self.name)))
+        }
+        Ok(())
+    }
+
+    fn value(&self, attr: &Attribute, layout: Layout, row: RowOffset)
+        -> Result<Value<'b>, DBError>
+    {
+        if let Some(validity) = self.validity {
+            if (validity[row / 8] >> (row % 8)) & 1 == 0 {
+                return Ok(Value::NULL)
+            }
+        }
+
+        let fixed = |width: usize| &self.values[row * width .. (row + 1) * width];
+        Ok(match layout {
+            Layout::Int(width) => match attr.dtype {
+                Type::INT32  => Value::INT32(le_int(fixed(width), true) as i32),
+                Type::UINT32 => Value::UINT32(le_int(fixed(width), false) as u32),
+                Type::INT64  => Value::INT64(le_int(fixed(width), true)),
+                _            => Value::UINT64(le_int(fixed(width), false) as u64),
+            },
+            Layout::Float32 => Value::FLOAT32(f32::from_bits(le_int(fixed(4), false) as u32)),
+            Layout::Float64 => Value::FLOAT64(f64::from_bits(le_int(fixed(8), false) as u64)),
+            Layout::Bool => Value::BOOLEAN((self.values[row / 8] >> (row % 8)) & 1 != 0),
+            Layout::Timestamp(0) => Value::TIMESTAMP(le_int(fixed(8), true) / 1000),
+            Layout::Timestamp(scale) => {
+                let v = le_int(fixed(8), true).checked_mul(scale).ok_or_else(|| {
+                    DBError::ValueOutOfRange(format!("{} timestamp", attr.name))
+                })?;
+                Value::TIMESTAMP(v)
+            },
+            Layout::Interval => {
+                let v = fixed(16);
+                Value::INTERVAL(IntervalValue {
+                    months: le_int(&v[0 .. 4], true) as i32,
+                    days: le_int(&v[4 .. 8], true) as i32,
+                    micros: le_int(&v[8 ..], true) / 1000,
+                })
+            },
+            Layout::Varlen(width) => {
+                let start = le_int(fixed(width), true);
+                let end = le_int(&self.values[(row + 1) * width .. (row + 2) * width], true);
+                if start < 0 || end < start || end as u64 > self.data.len() as u64 {
+                    return Err(invalid(format!("offsets of {} out of bounds", self.name)))
+                }
+
+                let bytes = &self.data[start as usize .. end as usize];
+                if attr.dtype == Type::TEXT {
+                    let text = str::from_utf8(bytes).map_err(|_| {
+                        invalid(format!("{} value is not UTF-8", self.name))
+                    })?;
+                    Value::TEXT(text)
+                } else {
+                    Value::BLOB(bytes)
+                }
+            },
+            Layout::Fixed(width) => match attr.dtype {
+                Type::UUID => {
+                    let mut uuid = [0u8; 16];
+                    uuid.copy_from_slice(fixed(16));
+                    Value::UUID(uuid)
+                },
+                _ => Value::BLOB(fixed(width)),
+            },
+        })
+    }
+}
+
+/// Reader of an Arrow IPC stream, the batches are decoded into blocks
+///
+/// The stream is untrusted: the metadata and buffers are bounds checked, and `DecodeLimits`
+/// apply to the message lengths (metadata and body), the rows of a batch and the values.
+pub struct IpcStreamReader<R: Read> {
+    input: R,
+    limits: DecodeLimits,
+    schema: Schema,
+    layouts: Vec<Layout>,
+    done: bool,
+}
+
+impl<R: Read> IpcStreamReader<R> {
+    /// Start reading the stream, reading the schema message
+    pub fn new(input: R) -> Result<IpcStreamReader<R>, DBError> {
+        IpcStreamReader::with_limits(input, DecodeLimits::default())
+    }
+
+    pub fn with_limits(input: R, limits: DecodeLimits) -> Result<IpcStreamReader<R>, DBError> {
+        let mut reader = IpcStreamReader {
+            input: input,
+            limits: limits,
+            schema: Schema::default(),
+            layouts: Vec::new(),
+            done: false,
+        };
+
+        let (metadata, _) = match reader.read_message()? {
+            Some(message) => message,
+            None => return Err(invalid("stream without a schema")),
+        };
+
+        let message = FlatTable::root(&metadata)?;
+        if message.byte(1)? != HEADER_SCHEMA {
+            return Err(invalid("stream doesn't start with a schema"))
+        }
+
+        let schema = message.table(2)?.ok_or_else(|| invalid("message without header"))?;
+        if schema.short(0)? != 0 {
+            return Err(unsupported("big endian stream"))
+        }
+
+        let mut attrs = Vec::new();
+        for field in schema.tables(1)? {
+            let (attr, layout) = read_field(&field)?;
+            attrs.push(attr);
+            reader.layouts.push(layout);
+        }
+
+        reader.schema = Schema::from_vec(attrs)?;
+        Ok(reader)
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Fill `buf`, false if the input ends before any byte is read
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<bool, DBError> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.input.read(&mut buf[read ..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(invalid("stream is truncated")),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(DBError::IO(e)),
+            }
+        }
+        Ok(true)
+    }
+
+    /// The next `len` bytes. Read in pieces so a forged length can't allocate more memory than
+    /// the bytes actually in the stream.
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, DBError> {
+        let mut out = Vec::new();
+        (&mut self.input).take(len as u64).read_to_end(&mut out).map_err(DBError::IO)?;
+        if out.len() != len {
+            return Err(invalid("stream is truncated"))
+        }
+        Ok(out)
+    }
+
+    /// Metadata and body of the next message, `None` at the end of the stream
+    fn read_message(&mut self) -> Result<Option<Message>, DBError> {
+        let mut word = [0u8; 4];
+        if self.done || !self.read_exact(&mut word)? {
+            self.done = true;
+            return Ok(None)
+        }
+
+        // Streams written before Arrow 0.15 have no continuation marker
+        let mut len = u32::from_le_bytes(word);
+        if len == CONTINUATION {
+            if !self.read_exact(&mut word)? {
+                return Err(invalid("stream is truncated"))
+            }
+            len = u32::from_le_bytes(word);
+        }
+
+        if len == 0 {
+            self.done = true;
+            return Ok(None)
+        }
+
+        self.limits.check_message_len(len as usize)?;
+        let metadata = self.read_bytes(len as usize)?;
+
+        let body_len = FlatTable::root(&metadata)?.long(3)?;
+        if body_len < 0 {
+            return Err(invalid("negative body length"))
+        }
+        let total = (len as u64).checked_add(body_len as u64)
+            .ok_or_else(|| invalid("message length overflows"))?;
+        self.limits.check_message_len(total as usize)?;
+        let body = self.read_bytes(body_len as usize)?;
+
+        Ok(Some((metadata, body)))
+    }
+
+    /// Rows of the next record batch, `None` at the end of the stream
+    pub fn next_block<'b>(&mut self, alloc: &'b Allocator) -> Result<Option<Block<'b>>, DBError> {
+        let (metadata, body) = match self.read_message()? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let message = FlatTable::root(&metadata)?;
+        let batch = match message.byte(1)? {
+            HEADER_RECORD_BATCH =>
+                message.table(2)?.ok_or_else(|| invalid("message without header"))?,
+            HEADER_DICTIONARY_BATCH => return Err(unsupported("dictionary batch")),
+            _ => return Err(invalid("unexpected message in a stream")),
+        };
+
+        if batch.field(3)?.is_some() {
+            return Err(unsupported("compressed record batch"))
+        }
+
+        let length = batch.long(0)?;
+        if length < 0 {
+            return Err(invalid("negative record batch length"))
+        }
+        let rows = length as RowOffset;
+        self.limits.check_rows(rows)?;
+
+        let nodes = batch.long_pairs(1)?;
+        let buffers = batch.long_pairs(2)?;
+        let buffer = |pos: usize| -> Result<&[u8], DBError> {
+            let (offset, len) = *buffers.get(pos).ok_or_else(|| invalid("missing buffer"))?;
+            let end = (offset as u64).saturating_add(len as u64);
+            if offset < 0 || len < 0 || end > body.len() as u64 {
+                return Err(invalid("buffer out of bounds"))
+            }
+            Ok(&body[offset as usize .. (offset + len) as usize])
+        };
+
+        let mut columns = Vec::with_capacity(self.schema.count());
+        let mut next = 0;
+
+        for (pos, (attr, layout)) in self.schema.iter().zip(&self.layouts).enumerate() {
+            let node = nodes.get(pos).ok_or_else(|| invalid("missing field node"))?;
+            if node.0 != length {
+                return Err(invalid(format!("{} rows of {}, expected {}", node.0, attr.name, rows)))
+            }
+
+            let validity = buffer(next)?;
+            let validity = if node.1 > 0 {
+                if validity.len() < rows / 8 + (rows % 8 != 0) as usize {
+                    return Err(invalid(format!("validity of {} is truncated", attr.name)))
+                }
+                Some(validity)
+            } else {
+                None
+            };
+
+            let values = buffer(next + 1)?;
+            let data = match *layout {
+                Layout::Varlen(_) => {
+                    next += 1;
+                    buffer(next + 1)?
+                },
+                _ => &[],
+            };
+            next += 2;
+
+            let col = ColumnBuffers {
+                name: &attr.name,
+                validity: validity,
+                values: values,
+                data: data,
+            };
+            let needed = match *layout {
+                Layout::Int(width) | Layout::Fixed(width) => rows.checked_mul(width),
+                Layout::Float32 => rows.checked_mul(4),
+                Layout::Float64 | Layout::Timestamp(_) => rows.checked_mul(8),
+                Layout::Interval => rows.checked_mul(16),
+                Layout::Bool => Some(rows / 8 + (rows % 8 != 0) as usize),
+                Layout::Varlen(_) if rows == 0 => Some(0),
+                Layout::Varlen(width) => rows.checked_add(1).and_then(|n| n.checked_mul(width)),
+            };
+            let needed = needed.ok_or_else(|| invalid(format!("{} rows of {}", rows, attr.name)))?;
+            col.check(needed, "values")?;
+            columns.push(col);
+        }
+
+        let mut block = Block::new(alloc, &self.schema);
+        let mut values = Vec::with_capacity(columns.len());
+        for row in 0 .. rows {
+            values.clear();
+            for (pos, col) in columns.iter().enumerate() {
+                let attr = self.schema.get(pos)?;
+                let value = col.value(attr, self.layouts[pos], row)?;
+                self.limits.check_value(attr, &value)?;
+                values.push(value);
+            }
+            block.append_row(&values)?;
+        }
+
+        Ok(Some(block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::{Operation, ScanView};
+
+    fn make_block() -> Block<'static> {
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "i".to_string(), nullable: false, dtype: Type::INT32 },
+            Attribute { name: "u".to_string(), nullable: true, dtype: Type::UINT64 },
+            Attribute { name: "f".to_string(), nullable: false, dtype: Type::FLOAT64 },
+            Attribute { name: "b".to_string(), nullable: true, dtype: Type::BOOLEAN },
+            Attribute { name: "t".to_string(), nullable: false, dtype: Type::TIMESTAMP },
+            Attribute { name: "d".to_string(), nullable: false, dtype: Type::INTERVAL },
+            Attribute { name: "s".to_string(), nullable: true, dtype: Type::TEXT },
+            Attribute { name: "x".to_string(), nullable: false, dtype: Type::BLOB },
+            Attribute { name: "id".to_string(), nullable: false, dtype: Type::UUID },
+        ]).unwrap();
+
+        let text = ["", "", "bc", "déf", "g"];
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 5 {
+            let interval = IntervalValue { months: v as i32, days: -1, micros: 1500 };
+            block.append_row(&[
+                Value::INT32(-v as i32),
+                if v % 2 == 0 { Value::UINT64(v as u64 * 1000) } else { Value::NULL },
+                Value::FLOAT64(v as f64 / 4.0),
+                if v == 3 { Value::NULL } else { Value::BOOLEAN(v % 2 == 0) },
+                Value::TIMESTAMP(1_600_000_000_000_000 + v),
+                Value::INTERVAL(interval),
+                if v == 1 { Value::NULL } else { Value::TEXT(text[v as usize]) },
+                Value::BLOB(&b"\x00\x01\x02"[.. v as usize % 3]),
+                Value::UUID([v as u8; 16]),
+            ]).unwrap();
+        }
+        block
+    }
+
+    // Rows of every supported type (and NULLs) read back as written, a batch per cursor chunk
+    #[test]
+    fn stream_round_trip() {
+        let block = make_block();
+        let op = ScanView::new(&block, None);
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut writer = IpcStreamWriter::new(Vec::new(), block.schema()).unwrap();
+        assert_eq!(writer.write_cursor(&mut *cursor, 2).unwrap(), 5);
+        let out = writer.finish().unwrap();
+        assert_eq!(&out[.. 4], &[0xff; 4]);
+        assert_eq!(&out[out.len() - 8 ..], &[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+
+        let mut reader = IpcStreamReader::new(&out[..]).unwrap();
+        for (read, attr) in reader.schema().iter().zip(block.schema().iter()) {
+            assert_eq!((&read.name, read.nullable), (&attr.name, attr.nullable));
+            assert!(read.dtype == attr.dtype);
+        }
+
+        let mut rows = 0;
+        while let Some(read) = reader.next_block(&allocator::GLOBAL).unwrap() {
+            for row in 0 .. read.rows() {
+                for pos in 0 .. block.schema().count() {
+                    assert!(read.value(row, pos).unwrap() == block.value(rows + row, pos).unwrap(),
+                            "row {} column {}", rows + row, pos);
+                }
+            }
+            rows += read.rows();
+        }
+        assert_eq!(rows, 5);
+        assert!(reader.next_block(&allocator::GLOBAL).unwrap().is_none());
+
+        let truncated = &out[.. out.len() - 20];
+        let mut reader = IpcStreamReader::new(truncated).unwrap();
+        let mut result = Ok(None);
+        for _ in 0 .. 3 {
+            result = reader.next_block(&allocator::GLOBAL);
+        }
+        match result {
+            Err(DBError::ValueParse(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+
+        let limits = DecodeLimits { max_message_len: Some(64), ..DecodeLimits::default() };
+        match IpcStreamReader::with_limits(&out[..], limits) {
+            Err(DBError::InputLimit(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    // Types only the reader maps (Int16, Utf8, millisecond Timestamp), in a stream without
+    // continuation markers nor an end of stream marker
+    #[test]
+    fn read_legacy_stream() {
+        let mut fb = FlatBuilder::default();
+        let mut fields = Vec::new();
+        let types = [(TYPE_INT, vec![(0, Slot::Int(16)), (1, Slot::Byte(1))]),
+                     (TYPE_UTF8, vec![]),
+                     (TYPE_TIMESTAMP, vec![(0, Slot::Short(1))])];
+        for (name, &(type_type, ref slots)) in ["a", "b", "t"].iter().zip(&types) {
+            let dtype = fb.table(slots);
+            let children = fb.offsets(&[]);
+            let name = fb.string(name);
+            fields.push(fb.table(&[(0, Slot::Offset(name)), (1, Slot::Byte(1)),
+                                   (2, Slot::Byte(type_type)), (3, Slot::Offset(dtype)),
+                                   (5, Slot::Offset(children))]));
+        }
+        let fields = fb.offsets(&fields);
+        let header = fb.table(&[(1, Slot::Offset(fields))]);
+        let schema = message(fb, HEADER_SCHEMA, header, 0);
+
+        let mut body = Body::default();
+        body.nodes = vec![(2, 0), (2, 1), (2, 0)];
+        body.buffer(&[]);
+        body.buffer(&[0xfe, 0xff, 7, 0]);
+        body.buffer(&[0b10]);
+        body.buffer(&[0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+        body.buffer(b"hi");
+        body.buffer(&[]);
+        body.buffer(&[0xe8, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut fb = FlatBuilder::default();
+        let buffers = fb.long_pairs(&body.buffers);
+        let nodes = fb.long_pairs(&body.nodes);
+        let header = fb.table(&[(0, Slot::Long(2)), (1, Slot::Offset(nodes)),
+                                (2, Slot::Offset(buffers))]);
+        let batch = message(fb, HEADER_RECORD_BATCH, header, body.data.len());
+
+        let mut stream = Vec::new();
+        for &(metadata, body) in &[(&schema, &[][..]), (&batch, &body.data[..])] {
+            stream.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+            stream.extend_from_slice(metadata);
+            stream.extend_from_slice(body);
+        }
+
+        let mut reader = IpcStreamReader::new(&stream[..]).unwrap();
+        let types: Vec<Type> = reader.schema().iter().map(|a| a.dtype.clone()).collect();
+        assert!(types == vec![Type::INT32, Type::TEXT, Type::TIMESTAMP]);
+
+        let block = reader.next_block(&allocator::GLOBAL).unwrap().unwrap();
+        assert!(block.value(0, 0).unwrap() == Value::INT32(-2));
+        assert!(block.value(1, 0).unwrap() == Value::INT32(7));
+        assert!(block.value(0, 1).unwrap() == Value::NULL);
+        assert!(block.value(1, 1).unwrap() == Value::TEXT("hi"));
+        assert!(block.value(0, 2).unwrap() == Value::TIMESTAMP(1_000_000));
+        assert!(reader.next_block(&allocator::GLOBAL).unwrap().is_none());
+    }
+
+    // Lengths forged to be huge fail as truncated or invalid streams, without allocating them
+    // nor overflowing the size of the buffers
+    #[test]
+    fn forged_lengths() {
+        let schema = |body_len: usize| {
+            let mut fb = FlatBuilder::default();
+            let dtype = fb.table(&[(0, Slot::Int(32)), (1, Slot::Byte(1))]);
+            let children = fb.offsets(&[]);
+            let name = fb.string("a");
+            let field = fb.table(&[(0, Slot::Offset(name)), (2, Slot::Byte(TYPE_INT)),
+                                   (3, Slot::Offset(dtype)), (5, Slot::Offset(children))]);
+            let fields = fb.offsets(&[field]);
+            let header = fb.table(&[(1, Slot::Offset(fields))]);
+            message(fb, HEADER_SCHEMA, header, body_len)
+        };
+        let batch = |rows: i64| {
+            let mut fb = FlatBuilder::default();
+            let buffers = fb.long_pairs(&[(0, 0), (0, 0)]);
+            let nodes = fb.long_pairs(&[(rows, 0)]);
+            let header = fb.table(&[(0, Slot::Long(rows)), (1, Slot::Offset(nodes)),
+                                    (2, Slot::Offset(buffers))]);
+            message(fb, HEADER_RECORD_BATCH, header, 0)
+        };
+        let stream = |messages: &[&[u8]]| {
+            let mut stream = Vec::new();
+            for metadata in messages {
+                stream.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+                stream.extend_from_slice(metadata);
+            }
+            stream
+        };
+
+        // Metadata and body longer than the stream
+        let mut huge = vec![0xff, 0xff, 0xff, 0xff, 0xf0, 0xff, 0xff, 0x7f];
+        huge.extend_from_slice(b"schema");
+        let streams = vec![huge, stream(&[&schema(1 << 46)]), stream(&[&schema(!0 >> 1)])];
+        for stream in &streams {
+            match IpcStreamReader::new(&stream[..]) {
+                Err(DBError::ValueParse(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error"),
+            }
+        }
+
+        // Rows whose values don't fit in memory, nor in a usize
+        for &rows in &[1 << 40, 1 << 62, i64::max_value()] {
+            let stream = stream(&[&schema(0), &batch(rows)]);
+            let mut reader = IpcStreamReader::new(&stream[..]).unwrap();
+            match reader.next_block(&allocator::GLOBAL) {
+                Err(DBError::ValueParse(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error"),
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Hot loops over column data: comparisons with a constant, combining NULL flags and sum / min /
+//! max aggregation of the fixed width numeric types.
+//!
+//! They're used by the comparisons of `expression::comparison`, the NULL flags of binary
+//! expressions (see `expression::binary_nulls()`) and the aggregates of `expression::numeric`.
+//!
+//! Float sums include NaNs and infinities like any other value (a sum with a NaN is NaN), while
+//! min / max skip NaNs. Operators that aggregate floats take a `NonFinite` treatment, the
+//! `FloatKernels` find the non finite values to skip or reject.
+//!
+//! Every kernel has a portable `scalar` implementation. Built with the `simd` feature, x86-64 CPUs
+//! with AVX2 (detected at run time) use SIMD implementations instead. They give the same results
+//! as the scalar kernels, except that float sums are added up in a different order (and so can be
+//! rounded differently). Aggregating values with NULL flags always uses the scalar kernels.
+//!
+//! NULL flags are bytes of 0 (not NULL) or 1 (NULL), one per row like in column data.
+
+pub mod scalar;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+
+/// Comparison of a value with a constant
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompareOp {
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+}
+
+impl CompareOp {
+    pub fn test<T: PartialOrd>(&self, value: &T, constant: &T) -> bool {
+        match *self {
+            CompareOp::EQ => value == constant,
+            CompareOp::NE => value != constant,
+            CompareOp::LT => value < constant,
+            CompareOp::LE => value <= constant,
+            CompareOp::GT => value > constant,
+            CompareOp::GE => value >= constant,
+        }
+    }
+
+    /// Operator of the comparison with its sides swapped, `constant swapped value` is the same as
+    /// `value op constant`
+    pub fn swapped(&self) -> CompareOp {
+        match *self {
+            CompareOp::LT => CompareOp::GT,
+            CompareOp::LE => CompareOp::GE,
+            CompareOp::GT => CompareOp::LT,
+            CompareOp::GE => CompareOp::LE,
+            op => op,
+        }
+    }
+}
+
+/// Treatment of NaN and infinite values by float aggregates
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NonFinite {
+    /// Aggregated like any other value, eg. sums with a NaN are NaN
+    Include,
+    /// Skipped like NULL values
+    Skip,
+    /// Aggregating a NaN or infinite value is an error
+    Error,
+}
+
+impl Default for NonFinite {
+    fn default() -> NonFinite {
+        NonFinite::Include
+    }
+}
+
+/// True if the SIMD kernels are used on this CPU
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+pub fn simd_enabled() -> bool {
+    simd::available()
+}
+
+/// True if the SIMD kernels are used on this CPU
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+pub fn simd_enabled() -> bool {
+    false
+}
+
+/// Kernels over the values of a fixed width numeric type
+pub trait NumericKernels: Copy + PartialOrd + Sized {
+    /// Type of sums, wide enough that 32 bit integers don't overflow. 64 bit integer sums wrap
+    /// around on overflow.
+    type Sum: Copy;
+
+    /// Set `out` to the rows where `values[row] op constant`
+    fn compare_const(values: &[Self], op: CompareOp, constant: Self, out: &mut [bool]);
+
+    /// Sum of the values, skipping NULL rows
+    fn sum(values: &[Self], nulls: Option<&[u8]>) -> Self::Sum;
+
+    /// Smallest value, skipping NULL rows and NaNs. None if there's no such value.
+    fn smallest(values: &[Self], nulls: Option<&[u8]>) -> Option<Self>;
+
+    /// Largest value, skipping NULL rows and NaNs. None if there's no such value.
+    fn largest(values: &[Self], nulls: Option<&[u8]>) -> Option<Self>;
+}
+
+/// Kernels finding the NaN and infinite values of a float type. They only have scalar versions.
+pub trait FloatKernels: NumericKernels {
+    /// Set `out` to the rows whose values are neither NaN nor infinite, see `is_finite`
+    fn finite_rows(values: &[Self], out: &mut [bool]);
+
+    /// Set the `out` flags of the rows that are NULL or not finite, eg. to aggregate only the
+    /// finite values (`NonFinite::Skip`)
+    fn finite_nulls(values: &[Self], nulls: Option<&[u8]>, out: &mut [u8]);
+
+    /// First non NULL row that isn't finite, eg. to report it (`NonFinite::Error`)
+    fn first_non_finite(values: &[Self], nulls: Option<&[u8]>) -> Option<usize>;
+}
+
+/// Use the SIMD kernel if the CPU supports it, fall back to the scalar one
+macro_rules! dispatch {
+    ($simd:expr, $scalar:expr) => {{
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        {
+            if simd::available() {
+                return unsafe { $simd }
+            }
+        }
+
+        $scalar
+    }}
+}
+
+macro_rules! numeric_kernels {
+    ($t:ty, $sum:ty, $add:expr, $compare:ident, $simd_sum:ident, $simd_min:ident,
+     $simd_max:ident) => {
+        impl NumericKernels for $t {
+            type Sum = $sum;
+
+            fn compare_const(values: &[$t], op: CompareOp, constant: $t, out: &mut [bool]) {
+                dispatch!(simd::$compare(values, op, constant, out),
+                          scalar::compare_const(values, op, &constant, out))
+            }
+
+            fn sum(values: &[$t], nulls: Option<&[u8]>) -> $sum {
+                match nulls {
+                    Some(_) => scalar::sum(values, nulls, 0 as $sum, $add),
+                    None => dispatch!(simd::$simd_sum(values),
+                                      scalar::sum(values, None, 0 as $sum, $add)),
+                }
+            }
+
+            fn smallest(values: &[$t], nulls: Option<&[u8]>) -> Option<$t> {
+                match nulls {
+                    Some(_) => scalar::min(values, nulls),
+                    None => dispatch!(simd::$simd_min(values), scalar::min(values, None)),
+                }
+            }
+
+            fn largest(values: &[$t], nulls: Option<&[u8]>) -> Option<$t> {
+                match nulls {
+                    Some(_) => scalar::max(values, nulls),
+                    None => dispatch!(simd::$simd_max(values), scalar::max(values, None)),
+                }
+            }
+        }
+    }
+}
+
+numeric_kernels!(i32, i64, |s: i64, v: i32| s.wrapping_add(v as i64),
+                 compare_i32, sum_i32, min_i32, max_i32);
+numeric_kernels!(u32, u64, |s: u64, v: u32| s.wrapping_add(v as u64),
+                 compare_u32, sum_u32, min_u32, max_u32);
+numeric_kernels!(i64, i64, |s: i64, v: i64| s.wrapping_add(v),
+                 compare_i64, sum_i64, min_i64, max_i64);
+numeric_kernels!(u64, u64, |s: u64, v: u64| s.wrapping_add(v),
+                 compare_u64, sum_u64, min_u64, max_u64);
+numeric_kernels!(f32, f64, |s: f64, v: f32| s + v as f64,
+                 compare_f32, sum_f32, min_f32, max_f32);
+numeric_kernels!(f64, f64, |s: f64, v: f64| s + v,
+                 compare_f64, sum_f64, min_f64, max_f64);
+
+macro_rules! float_kernels {
+    ($t:ty) => {
+        impl FloatKernels for $t {
+            fn finite_rows(values: &[$t], out: &mut [bool]) {
+                scalar::is_finite(values, <$t>::is_finite, out)
+            }
+
+            fn finite_nulls(values: &[$t], nulls: Option<&[u8]>, out: &mut [u8]) {
+                scalar::finite_nulls(values, nulls, <$t>::is_finite, out)
+            }
+
+            fn first_non_finite(values: &[$t], nulls: Option<&[u8]>) -> Option<usize> {
+                scalar::first_non_finite(values, nulls, <$t>::is_finite)
+            }
+        }
+    }
+}
+
+float_kernels!(f32);
+float_kernels!(f64);
+
+/// Set `out` to the rows whose values are neither NaN nor infinite
+pub fn is_finite<T: FloatKernels>(values: &[T], out: &mut [bool]) {
+    T::finite_rows(values, out)
+}
+
+/// Set the `out` flags of rows NULL in both `lhs` and `rhs`
+pub fn nulls_and(lhs: &[u8], rhs: &[u8], out: &mut [u8]) {
+    dispatch!(simd::nulls_and(lhs, rhs, out), scalar::nulls_and(lhs, rhs, out))
+}
+
+/// Set the `out` flags of rows NULL in either `lhs` or `rhs`, eg. the NULL rows of the result
+/// of a binary expression
+pub fn nulls_or(lhs: &[u8], rhs: &[u8], out: &mut [u8]) {
+    dispatch!(simd::nulls_or(lhs, rhs, out), scalar::nulls_or(lhs, rhs, out))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+    use ::testing::{Tolerance, approx_eq_f64};
+
+    /// Pseudo random values (of a fixed LCG), with every 7th value one of the edge cases
+    fn values<T: Copy, F: Fn(u64) -> T>(edges: &[T], make: F) -> Vec<T> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        (0 .. 1000).map(|i| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            if i % 7 == 3 { edges[i / 7 % edges.len()] } else { make(state >> 16) }
+        }).collect()
+    }
+
+    /// The kernels (SIMD ones if enabled) give the same results as the scalar ones, for lengths
+    /// around the vector sizes. `sum` is the scalar sum and `same_sum` compares sums.
+    fn check_kernels<T, F, E>(all: &[T], constants: &[T], sum: F, same_sum: E)
+        where T: NumericKernels + Debug, F: Fn(&[T]) -> T::Sum, E: Fn(T::Sum, T::Sum) -> bool
+    {
+        let ops = [CompareOp::EQ, CompareOp::NE, CompareOp::LT, CompareOp::LE, CompareOp::GT,
+                   CompareOp::GE];
+
+        for len in (0 .. 40).chain(vec![255, 256, 257, 1000]) {
+            let values = &all[.. len];
+
+            for op in &ops {
+                for constant in constants {
+                    let mut expected = vec![false; len];
+                    let mut out = vec![false; len];
+                    scalar::compare_const(values, *op, constant, &mut expected);
+                    T::compare_const(values, *op, *constant, &mut out);
+                    assert_eq!(out, expected, "{:?} {:?} of {} rows", op, constant, len);
+                }
+            }
+
+            assert!(same_sum(T::sum(values, None), sum(values)), "sum of {} rows", len);
+            assert_eq!(T::smallest(values, None), scalar::min(values, None), "min of {} rows", len);
+            assert_eq!(T::largest(values, None), scalar::max(values, None), "max of {} rows", len);
+        }
+    }
+
+    // Integer kernels, including the extremes of each type
+    #[test]
+    fn integer_kernels() {
+        let all = values(&[i32::min_value(), i32::max_value(), 0, -1], |r| r as i32 % 1000);
+        check_kernels(&all, &[0, -1, 500, i32::min_value()],
+                      |v| scalar::sum(v, None, 0, |s: i64, x| s.wrapping_add(x as i64)),
+                      |l, r| l == r);
+
+        let all = values(&[u32::max_value(), 0, 1 << 31], |r| r as u32 % 1000);
+        check_kernels(&all, &[0, 500, 1 << 31, u32::max_value()],
+                      |v| scalar::sum(v, None, 0, |s: u64, x| s.wrapping_add(x as u64)),
+                      |l, r| l == r);
+
+        let all = values(&[i64::min_value(), i64::max_value(), -1], |r| r as i64 - (1 << 40));
+        check_kernels(&all, &[0, -1, i64::max_value()],
+                      |v| scalar::sum(v, None, 0, |s: i64, x| s.wrapping_add(x)),
+                      |l, r| l == r);
+
+        let all = values(&[u64::max_value(), 0, 1 << 63], |r| r << 20);
+        check_kernels(&all, &[0, 1 << 63, 1 << 40],
+                      |v| scalar::sum(v, None, 0, |s: u64, x| s.wrapping_add(x)),
+                      |l, r| l == r);
+    }
+
+    // Float kernels with NaN and infinities, sums are only rounded the same approximately
+    #[test]
+    fn float_kernels() {
+        use std::{f32, f64};
+
+        let same_sum = |l: f64, r: f64| approx_eq_f64(l, r, Tolerance::Relative(1e-9));
+
+        let all = values(&[f32::NAN, -0.0, f32::MAX], |r| (r % 2001) as f32 / 8.0 - 125.0);
+        check_kernels(&all, &[0.0, f32::NAN, 12.5, f32::INFINITY],
+                      |v| scalar::sum(v, None, 0.0, |s: f64, x| s + x as f64), &same_sum);
+
+        let all = values(&[f64::NAN, f64::NEG_INFINITY, 0.0], |r| (r % 2001) as f64 / 8.0);
+        check_kernels(&all, &[0.0, f64::NAN, 100.0],
+                      |v| scalar::sum(v, None, 0.0, |s: f64, x| s + x), |l: f64, r: f64| {
+                          // NEG_INFINITY in the sum
+                          l == r || same_sum(l, r)
+                      });
+
+        // Only NaN
+        let nan = vec![f32::NAN; 20];
+        assert_eq!(f32::smallest(&nan, None), None);
+        assert_eq!(f32::largest(&nan, None), None);
+    }
+
+    // Non finite values are found, and skipped as NULLs
+    #[test]
+    fn finite_values() {
+        use std::f64;
+
+        let values = [1.0, f64::NAN, f64::INFINITY, -2.0, f64::NEG_INFINITY];
+        let mut finite = [false; 5];
+        is_finite(&values, &mut finite);
+        assert_eq!(finite, [true, false, false, true, false]);
+
+        let mut nulls = [0u8; 5];
+        f64::finite_nulls(&values, Some(&[0, 0, 0, 1, 0]), &mut nulls);
+        assert_eq!(nulls, [0, 1, 1, 1, 1]);
+        assert_eq!(f64::sum(&values, Some(&nulls)), 1.0);
+        assert!(f64::sum(&values, None).is_nan());
+
+        assert_eq!(f64::first_non_finite(&values, Some(&[0, 1, 0, 0, 0])), Some(2));
+        assert_eq!(f32::first_non_finite(&[1.0, 2.0], None), None);
+    }
+
+    // NULL rows are skipped, NULL flags are combined byte-wise
+    #[test]
+    fn nulls() {
+        let values = [5i32, -3, 8, 1];
+        let nulls = [0u8, 1, 0, 0];
+        assert_eq!(i32::sum(&values, Some(&nulls)), 14);
+        assert_eq!(i32::smallest(&values, Some(&nulls)), Some(1));
+        assert_eq!(i32::largest(&values, Some(&[1, 1, 1, 1])), None);
+
+        let lhs = values_u8(0);
+        let rhs = values_u8(1);
+        for &len in &[0, 31, 32, 33, 100] {
+            let (mut and, mut or) = (vec![0u8; len], vec![0u8; len]);
+            nulls_and(&lhs[.. len], &rhs[.. len], &mut and);
+            nulls_or(&lhs[.. len], &rhs[.. len], &mut or);
+
+            for row in 0 .. len {
+                assert_eq!(and[row], lhs[row] & rhs[row]);
+                assert_eq!(or[row], lhs[row] | rhs[row]);
+            }
+        }
+    }
+
+    fn values_u8(seed: u64) -> Vec<u8> {
+        values(&[seed as u8], |r| (r >> 7) as u8 & 1)
+    }
+}
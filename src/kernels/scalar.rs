@@ -0,0 +1,92 @@
+//! Portable implementations of the kernels, the reference the SIMD versions are tested against.
+
+use super::CompareOp;
+
+/// True for the rows whose NULL flag is set, always false without flags
+fn is_null(nulls: Option<&[u8]>, row: usize) -> bool {
+    nulls.map_or(false, |n| n[row] != 0)
+}
+
+pub fn compare_const<T: PartialOrd>(values: &[T], op: CompareOp, constant: &T, out: &mut [bool]) {
+    for (dst, value) in out.iter_mut().zip(values) {
+        *dst = op.test(value, constant);
+    }
+}
+
+pub fn nulls_and(lhs: &[u8], rhs: &[u8], out: &mut [u8]) {
+    for (dst, (l, r)) in out.iter_mut().zip(lhs.iter().zip(rhs)) {
+        *dst = l & r;
+    }
+}
+
+pub fn nulls_or(lhs: &[u8], rhs: &[u8], out: &mut [u8]) {
+    for (dst, (l, r)) in out.iter_mut().zip(lhs.iter().zip(rhs)) {
+        *dst = l | r;
+    }
+}
+
+/// Set `out` to the rows whose value is `finite`
+pub fn is_finite<T: Copy, F: Fn(T) -> bool>(values: &[T], finite: F, out: &mut [bool]) {
+    for (dst, value) in out.iter_mut().zip(values) {
+        *dst = finite(*value);
+    }
+}
+
+/// Set the `out` flags of the rows that are NULL or whose value isn't `finite`
+pub fn finite_nulls<T: Copy, F>(values: &[T], nulls: Option<&[u8]>, finite: F, out: &mut [u8])
+    where F: Fn(T) -> bool
+{
+    for (row, (dst, value)) in out.iter_mut().zip(values).enumerate() {
+        *dst = (is_null(nulls, row) || !finite(*value)) as u8;
+    }
+}
+
+/// First non NULL row whose value isn't `finite`
+pub fn first_non_finite<T: Copy, F>(values: &[T], nulls: Option<&[u8]>, finite: F) -> Option<usize>
+    where F: Fn(T) -> bool
+{
+    values.iter().enumerate()
+        .position(|(row, value)| !is_null(nulls, row) && !finite(*value))
+}
+
+/// Sum of the non NULL values, accumulated with `add`
+pub fn sum<T: Copy, S, F>(values: &[T], nulls: Option<&[u8]>, zero: S, add: F) -> S
+    where F: Fn(S, T) -> S
+{
+    let mut out = zero;
+    for (row, value) in values.iter().enumerate() {
+        if !is_null(nulls, row) {
+            out = add(out, *value);
+        }
+    }
+    out
+}
+
+/// Value of the non NULL values that is `keep` (less or greater) than all the others, ignoring
+/// NaNs. None if there are none.
+fn extreme<T: Copy + PartialOrd>(values: &[T], nulls: Option<&[u8]>, keep: CompareOp)
+    -> Option<T>
+{
+    let mut out: Option<T> = None;
+
+    for (row, value) in values.iter().enumerate() {
+        // NaN isn't ordered, not even with itself
+        if is_null(nulls, row) || value.partial_cmp(value).is_none() {
+            continue
+        }
+
+        if out.map_or(true, |current| keep.test(value, &current)) {
+            out = Some(*value);
+        }
+    }
+
+    out
+}
+
+pub fn min<T: Copy + PartialOrd>(values: &[T], nulls: Option<&[u8]>) -> Option<T> {
+    extreme(values, nulls, CompareOp::LT)
+}
+
+pub fn max<T: Copy + PartialOrd>(values: &[T], nulls: Option<&[u8]>) -> Option<T> {
+    extreme(values, nulls, CompareOp::GT)
+}
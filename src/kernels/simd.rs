@@ -0,0 +1,363 @@
+//! AVX2 implementations of the kernels. They process whole vectors of values and finish the
+//! remaining rows with the scalar kernels. Only to be called if `available()`.
+
+use std::arch::x86_64::*;
+use std::{f32, f64};
+
+use super::{CompareOp, scalar};
+
+pub fn available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[inline]
+unsafe fn load_si256<T>(values: &[T], row: usize) -> __m256i {
+    _mm256_loadu_si256(values.as_ptr().offset(row as isize) as *const __m256i)
+}
+
+/// Set the `out` rows to the bits of a comparison mask, one bit per lane
+#[inline]
+fn store_mask(bits: i32, out: &mut [bool]) {
+    for (lane, dst) in out.iter_mut().enumerate() {
+        *dst = bits & (1 << lane) != 0;
+    }
+}
+
+/// Combine the extreme of the vector lanes (if any vector was processed) with the one of the
+/// remaining rows
+fn combine<T: Copy + PartialOrd>(lanes: Option<T>, tail: Option<T>, keep: CompareOp)
+    -> Option<T>
+{
+    match (lanes, tail) {
+        (Some(l), Some(t)) => Some(if keep.test(&t, &l) { t } else { l }),
+        (Some(l), None) => Some(l),
+        (None, tail) => tail,
+    }
+}
+
+/// Bits of the lanes of `v op c`, for signed 32 bit integers
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn compare_epi32(v: __m256i, c: __m256i, op: CompareOp) -> i32 {
+    let (mask, negate) = match op {
+        CompareOp::EQ => (_mm256_cmpeq_epi32(v, c), false),
+        CompareOp::NE => (_mm256_cmpeq_epi32(v, c), true),
+        CompareOp::GT => (_mm256_cmpgt_epi32(v, c), false),
+        CompareOp::LE => (_mm256_cmpgt_epi32(v, c), true),
+        CompareOp::LT => (_mm256_cmpgt_epi32(c, v), false),
+        CompareOp::GE => (_mm256_cmpgt_epi32(c, v), true),
+    };
+
+    let bits = _mm256_movemask_ps(_mm256_castsi256_ps(mask));
+    if negate { !bits & 0xff } else { bits }
+}
+
+/// Bits of the lanes of `v op c`, for signed 64 bit integers
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn compare_epi64(v: __m256i, c: __m256i, op: CompareOp) -> i32 {
+    let (mask, negate) = match op {
+        CompareOp::EQ => (_mm256_cmpeq_epi64(v, c), false),
+        CompareOp::NE => (_mm256_cmpeq_epi64(v, c), true),
+        CompareOp::GT => (_mm256_cmpgt_epi64(v, c), false),
+        CompareOp::LE => (_mm256_cmpgt_epi64(v, c), true),
+        CompareOp::LT => (_mm256_cmpgt_epi64(c, v), false),
+        CompareOp::GE => (_mm256_cmpgt_epi64(c, v), true),
+    };
+
+    let bits = _mm256_movemask_pd(_mm256_castsi256_pd(mask));
+    if negate { !bits & 0xf } else { bits }
+}
+
+/// Integer comparisons. Unsigned values are compared as signed ones with the sign bit flipped,
+/// which keeps their order.
+macro_rules! compare_int {
+    ($name:ident, $t:ty, $lanes:expr, $set1:ident, $compare:ident, $flip:expr) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t], op: CompareOp, constant: $t, out: &mut [bool]) {
+            let rows = values.len().min(out.len());
+            let flip = $set1($flip);
+            let c = _mm256_xor_si256($set1(constant as _), flip);
+
+            let mut row = 0;
+            while row + $lanes <= rows {
+                let v = _mm256_xor_si256(load_si256(values, row), flip);
+                store_mask($compare(v, c, op), &mut out[row .. row + $lanes]);
+                row += $lanes;
+            }
+
+            scalar::compare_const(&values[row .. rows], op, &constant, &mut out[row .. rows]);
+        }
+    }
+}
+
+compare_int!(compare_i32, i32, 8, _mm256_set1_epi32, compare_epi32, 0);
+compare_int!(compare_u32, u32, 8, _mm256_set1_epi32, compare_epi32, i32::min_value());
+compare_int!(compare_i64, i64, 4, _mm256_set1_epi64x, compare_epi64, 0);
+compare_int!(compare_u64, u64, 4, _mm256_set1_epi64x, compare_epi64, i64::min_value());
+
+/// Float comparisons. Like Rust's operators, NaN is only not equal to anything.
+macro_rules! compare_float {
+    ($name:ident, $t:ty, $lanes:expr, $vec:ty, $set1:ident, $load:ident, $cmp:ident,
+     $movemask:ident) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t], op: CompareOp, constant: $t, out: &mut [bool]) {
+            let rows = values.len().min(out.len());
+            let c = $set1(constant);
+
+            let mut row = 0;
+            while row + $lanes <= rows {
+                let v = $load(values.as_ptr().offset(row as isize));
+                let mask: $vec = match op {
+                    CompareOp::EQ => $cmp(v, c, _CMP_EQ_OQ),
+                    CompareOp::NE => $cmp(v, c, _CMP_NEQ_UQ),
+                    CompareOp::LT => $cmp(v, c, _CMP_LT_OQ),
+                    CompareOp::LE => $cmp(v, c, _CMP_LE_OQ),
+                    CompareOp::GT => $cmp(v, c, _CMP_GT_OQ),
+                    CompareOp::GE => $cmp(v, c, _CMP_GE_OQ),
+                };
+                store_mask($movemask(mask), &mut out[row .. row + $lanes]);
+                row += $lanes;
+            }
+
+            scalar::compare_const(&values[row .. rows], op, &constant, &mut out[row .. rows]);
+        }
+    }
+}
+
+compare_float!(compare_f32, f32, 8, __m256, _mm256_set1_ps, _mm256_loadu_ps, _mm256_cmp_ps,
+               _mm256_movemask_ps);
+compare_float!(compare_f64, f64, 4, __m256d, _mm256_set1_pd, _mm256_loadu_pd, _mm256_cmp_pd,
+               _mm256_movemask_pd);
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn lanes_i64(v: __m256i) -> [i64; 4] {
+    let mut out = [0i64; 4];
+    _mm256_storeu_si256(out.as_mut_ptr() as *mut __m256i, v);
+    out
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn lanes_f64(v: __m256d) -> [f64; 4] {
+    let mut out = [0f64; 4];
+    _mm256_storeu_pd(out.as_mut_ptr(), v);
+    out
+}
+
+/// 32 bit integer sums, widening the values to 64 bit lanes
+macro_rules! sum_int32 {
+    ($name:ident, $t:ty, $sum:ty, $widen:ident) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t]) -> $sum {
+            let mut acc = _mm256_setzero_si256();
+
+            let mut row = 0;
+            while row + 8 <= values.len() {
+                let v = load_si256(values, row);
+                let lo = $widen(_mm256_castsi256_si128(v));
+                let hi = $widen(_mm256_extracti128_si256(v, 1));
+                acc = _mm256_add_epi64(acc, _mm256_add_epi64(lo, hi));
+                row += 8;
+            }
+
+            let tail = scalar::sum(&values[row ..], None, 0,
+                                   |s: $sum, v| s.wrapping_add(v as $sum));
+            lanes_i64(acc).iter().fold(tail, |s, lane| s.wrapping_add(*lane as $sum))
+        }
+    }
+}
+
+sum_int32!(sum_i32, i32, i64, _mm256_cvtepi32_epi64);
+sum_int32!(sum_u32, u32, u64, _mm256_cvtepu32_epi64);
+
+/// 64 bit integer sums, wrapping around like the scalar ones
+macro_rules! sum_int64 {
+    ($name:ident, $t:ty) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t]) -> $t {
+            let mut acc = _mm256_setzero_si256();
+
+            let mut row = 0;
+            while row + 4 <= values.len() {
+                acc = _mm256_add_epi64(acc, load_si256(values, row));
+                row += 4;
+            }
+
+            let tail = scalar::sum(&values[row ..], None, 0, |s: $t, v| s.wrapping_add(v));
+            lanes_i64(acc).iter().fold(tail, |s, lane| s.wrapping_add(*lane as $t))
+        }
+    }
+}
+
+sum_int64!(sum_i64, i64);
+sum_int64!(sum_u64, u64);
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn sum_f32(values: &[f32]) -> f64 {
+    let mut acc = _mm256_setzero_pd();
+
+    let mut row = 0;
+    while row + 8 <= values.len() {
+        let v = _mm256_loadu_ps(values.as_ptr().offset(row as isize));
+        let lo = _mm256_cvtps_pd(_mm256_castps256_ps128(v));
+        let hi = _mm256_cvtps_pd(_mm256_extractf128_ps(v, 1));
+        acc = _mm256_add_pd(acc, _mm256_add_pd(lo, hi));
+        row += 8;
+    }
+
+    let tail = scalar::sum(&values[row ..], None, 0.0, |s: f64, v| s + v as f64);
+    lanes_f64(acc).iter().fold(tail, |s, lane| s + lane)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn sum_f64(values: &[f64]) -> f64 {
+    let mut acc = _mm256_setzero_pd();
+
+    let mut row = 0;
+    while row + 4 <= values.len() {
+        acc = _mm256_add_pd(acc, _mm256_loadu_pd(values.as_ptr().offset(row as isize)));
+        row += 4;
+    }
+
+    let tail = scalar::sum(&values[row ..], None, 0.0, |s: f64, v| s + v);
+    lanes_f64(acc).iter().fold(tail, |s, lane| s + lane)
+}
+
+/// 32 bit integer min / max, with the AVX2 min / max instructions
+macro_rules! extreme_int32 {
+    ($name:ident, $t:ty, $op:ident, $identity:expr, $keep:expr, $scalar:ident) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t]) -> Option<$t> {
+            let mut acc = _mm256_set1_epi32($identity as i32);
+
+            let mut row = 0;
+            while row + 8 <= values.len() {
+                acc = $op(acc, load_si256(values, row));
+                row += 8;
+            }
+
+            let tail = scalar::$scalar(&values[row ..], None);
+            if row == 0 {
+                return tail
+            }
+
+            let mut lanes = [0 as $t; 8];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc);
+            combine(scalar::$scalar(&lanes, None), tail, $keep)
+        }
+    }
+}
+
+extreme_int32!(min_i32, i32, _mm256_min_epi32, i32::max_value(), CompareOp::LT, min);
+extreme_int32!(max_i32, i32, _mm256_max_epi32, i32::min_value(), CompareOp::GT, max);
+extreme_int32!(min_u32, u32, _mm256_min_epu32, u32::max_value(), CompareOp::LT, min);
+extreme_int32!(max_u32, u32, _mm256_max_epu32, u32::min_value(), CompareOp::GT, max);
+
+/// 64 bit integer min / max. Without 64 bit min / max instructions, lanes are selected with
+/// comparisons (of sign flipped values for unsigned integers).
+macro_rules! extreme_int64 {
+    ($name:ident, $t:ty, $identity:expr, $keep:expr, $scalar:ident, $flip:expr) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t]) -> Option<$t> {
+            let flip = _mm256_set1_epi64x($flip);
+            let mut acc = _mm256_set1_epi64x($identity as i64);
+
+            let mut row = 0;
+            while row + 4 <= values.len() {
+                let v = load_si256(values, row);
+                let (acc_cmp, v_cmp) = (_mm256_xor_si256(acc, flip), _mm256_xor_si256(v, flip));
+                // Lanes where v is the one to keep
+                let take = if $keep == CompareOp::LT {
+                    _mm256_cmpgt_epi64(acc_cmp, v_cmp)
+                } else {
+                    _mm256_cmpgt_epi64(v_cmp, acc_cmp)
+                };
+                acc = _mm256_blendv_epi8(acc, v, take);
+                row += 4;
+            }
+
+            let tail = scalar::$scalar(&values[row ..], None);
+            if row == 0 {
+                return tail
+            }
+
+            let mut lanes = [0 as $t; 4];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc);
+            combine(scalar::$scalar(&lanes, None), tail, $keep)
+        }
+    }
+}
+
+extreme_int64!(min_i64, i64, i64::max_value(), CompareOp::LT, min, 0);
+extreme_int64!(max_i64, i64, i64::min_value(), CompareOp::GT, max, 0);
+extreme_int64!(min_u64, u64, u64::max_value(), CompareOp::LT, min, i64::min_value());
+extreme_int64!(max_u64, u64, u64::min_value(), CompareOp::GT, max, i64::min_value());
+
+/// Float min / max ignoring NaNs: the min / max instructions return their second operand (the
+/// accumulator) if the first is NaN. Lanes that saw a value are tracked with an ordered
+/// comparison, since infinities are values too.
+macro_rules! extreme_float {
+    ($name:ident, $t:ty, $lanes:expr, $vec:ty, $op:ident, $identity:expr, $keep:expr,
+     $scalar:ident, $set1:ident, $load:ident, $store:ident, $cmp:ident, $or:ident, $zero:ident,
+     $movemask:ident) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(values: &[$t]) -> Option<$t> {
+            let mut acc: $vec = $set1($identity);
+            let mut seen: $vec = $zero();
+
+            let mut row = 0;
+            while row + $lanes <= values.len() {
+                let v = $load(values.as_ptr().offset(row as isize));
+                acc = $op(v, acc);
+                seen = $or(seen, $cmp(v, v, _CMP_ORD_Q));
+                row += $lanes;
+            }
+
+            let tail = scalar::$scalar(&values[row ..], None);
+            if $movemask(seen) == 0 {
+                return tail
+            }
+
+            let mut lanes = [0 as $t; $lanes];
+            $store(lanes.as_mut_ptr(), acc);
+            combine(scalar::$scalar(&lanes, None), tail, $keep)
+        }
+    }
+}
+
+extreme_float!(min_f32, f32, 8, __m256, _mm256_min_ps, f32::INFINITY, CompareOp::LT, min,
+               _mm256_set1_ps, _mm256_loadu_ps, _mm256_storeu_ps, _mm256_cmp_ps, _mm256_or_ps,
+               _mm256_setzero_ps, _mm256_movemask_ps);
+extreme_float!(max_f32, f32, 8, __m256, _mm256_max_ps, f32::NEG_INFINITY, CompareOp::GT, max,
+               _mm256_set1_ps, _mm256_loadu_ps, _mm256_storeu_ps, _mm256_cmp_ps, _mm256_or_ps,
+               _mm256_setzero_ps, _mm256_movemask_ps);
+extreme_float!(min_f64, f64, 4, __m256d, _mm256_min_pd, f64::INFINITY, CompareOp::LT, min,
+               _mm256_set1_pd, _mm256_loadu_pd, _mm256_storeu_pd, _mm256_cmp_pd, _mm256_or_pd,
+               _mm256_setzero_pd, _mm256_movemask_pd);
+extreme_float!(max_f64, f64, 4, __m256d, _mm256_max_pd, f64::NEG_INFINITY, CompareOp::GT, max,
+               _mm256_set1_pd, _mm256_loadu_pd, _mm256_storeu_pd, _mm256_cmp_pd, _mm256_or_pd,
+               _mm256_setzero_pd, _mm256_movemask_pd);
+
+/// Byte-wise NULL flag combination, 32 rows at a time
+macro_rules! nulls_op {
+    ($name:ident, $op:ident) => {
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn $name(lhs: &[u8], rhs: &[u8], out: &mut [u8]) {
+            let rows = lhs.len().min(rhs.len()).min(out.len());
+
+            let mut row = 0;
+            while row + 32 <= rows {
+                let v = $op(load_si256(lhs, row), load_si256(rhs, row));
+                _mm256_storeu_si256(out.as_mut_ptr().offset(row as isize) as *mut __m256i, v);
+                row += 32;
+            }
+
+            scalar::$name(&lhs[row .. rows], &rhs[row .. rows], &mut out[row .. rows]);
+        }
+    }
+}
+
+nulls_op!(nulls_and, _mm256_and_si256);
+nulls_op!(nulls_or, _mm256_or_si256);
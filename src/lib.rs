@@ -7,6 +7,7 @@
 #![feature(heap_api)]
 #![feature(inclusive_range_syntax)]
 #![feature(specialization)]
+#![cfg_attr(feature = "simd", feature(stdsimd))]
 // #![feature(nll)]
 
 //! DBKit Engine -- Columnar query processing engine
@@ -24,28 +25,97 @@ extern crate itertools;
 
 extern crate num;
 
+#[cfg(any(all(feature = "hugepages", target_os = "linux"), all(feature = "mmap", unix),
+          all(feature = "storage", unix)))]
+extern crate libc;
+
+#[cfg(feature = "regex")]
+extern crate regex;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate toml;
+
 /// Database error type and error utilities
 pub mod error;
 
 /// Allocator facilities for column data and in flight operations & expressions.
 pub mod allocator;
 /// Database Type system
+#[macro_use]
 pub mod types;
 /// Database schema
 pub mod schema;
 pub mod row;
 pub mod util;
 
+/// Comparison, NULL flag and aggregation kernels, with SIMD versions.
+pub mod kernels;
 /// Containers for columnar data.
 pub mod block;
+/// Typed builders of columns and blocks from Rust values.
+pub mod builder;
+/// Interning of repeated TEXT values in column arenas.
+pub mod intern;
+/// Block statistics (zone maps) for skipping data that can't match a predicate.
+pub mod stats;
+/// Multi-column row comparisons and normalized sort keys.
+pub mod compare;
+/// Memory mapped on-disk table files.
+#[cfg(all(feature = "storage", unix))]
+pub mod storage;
 /// Tools for creating, writing & accessing columnar by row or element.
 pub mod table;
+/// Hash, sorted and text indexes for equality, range and full-text lookups of table rows.
+pub mod index;
+/// Embedding vector columns and similarity search.
+pub mod vector;
+/// Text similarity and phonetic code kernels, fuzzy joins.
+pub mod fuzzy;
 
 /// Database operations
 pub mod operation;
 /// Database expressions
 pub mod expression;
+/// Named tables for resolving table references in queries
+pub mod catalog;
+/// Counters and query log of the engine
+pub mod metrics;
+/// Built-in tables describing the catalog and metrics
+pub mod system;
+/// Audit log of executed statements
+pub mod audit;
+/// Server-side cursors for paging through query results
+pub mod paging;
+/// Caches of query results, invalidated by table versions
+pub mod cache;
+/// Logical query plans and their optimizer
+pub mod plan;
+/// SQL query frontend
+#[cfg(feature = "sql")]
+pub mod sql;
+
+/// Decoding rows from external formats
+pub mod decode;
+/// Arrow IPC stream writer and reader
+pub mod ipc;
+/// C embedding API
+#[cfg(feature = "dbkit-ffi")]
+pub mod ffi;
+/// Fluent query building API on top of logical plans
+pub mod dataframe;
+/// Conversion of Rust structs to and from rows
+pub mod record;
 
 /// Data structures for representing schema projections.
 pub mod projector;
 
+/// Rendering rows as ASCII tables
+pub mod fmt;
+
+/// Helpers for testing operations and expressions.
+pub mod testing;
+/// Random schema and block generators for property tests.
+pub mod testutil;
+
@@ -0,0 +1,165 @@
+// vim: set ts=4 sw=4 et :
+
+//! Engine metrics.
+//!
+//! A `Metrics` registry has named counters and a log of the most recent queries. It's updated
+//! through a shared reference (eg. by a `SqlContext` while planning) and can be read back as the
+//! `dbkit_metrics` and `dbkit_queries` system tables.
+//!
+//! Usage of shared services is attributed to tenants: queries are logged with their tenant, and
+//! tenant counters (see `tenant_counter`) are kept next to the totals.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Number of queries kept by `Metrics::default()`
+pub const DEFAULT_QUERY_LOG: usize = 100;
+
+/// Entry of the query log
+#[derive(Clone, PartialEq, Debug)]
+pub struct QueryRecord {
+    /// Sequence number of the query, starting at 1
+    pub id: u64,
+    pub sql: String,
+    pub elapsed: Duration,
+    /// Error of a failed query
+    pub error: Option<String>,
+    /// Tenant the query ran for
+    pub tenant: Option<String>,
+}
+
+/// Name of the tenant's share of the counter, eg. `sql.queries{tenant=acme}`
+pub fn tenant_counter(name: &str, tenant: &str) -> String {
+    format!("{}{{tenant={}}}", name, tenant)
+}
+
+pub struct Metrics {
+    counters: RefCell<BTreeMap<String, i64>>,
+    queries: RefCell<VecDeque<QueryRecord>>,
+    /// Queries recorded so far, including the ones no longer in the log
+    recorded: Cell<u64>,
+    capacity: usize,
+}
+
+impl Metrics {
+    /// Registry keeping the last `capacity` queries
+    pub fn new(capacity: usize) -> Metrics {
+        Metrics {
+            counters: RefCell::new(BTreeMap::new()),
+            queries: RefCell::new(VecDeque::with_capacity(capacity)),
+            recorded: Cell::new(0),
+            capacity: capacity,
+        }
+    }
+
+    /// Add `by` to the counter, counters start at 0
+    pub fn increment(&self, name: &str, by: i64) {
+        *self.counters.borrow_mut().entry(name.to_string()).or_insert(0) += by;
+    }
+
+    /// Add `by` to the counter and, with a tenant, to the tenant's counter
+    pub fn increment_tenant(&self, tenant: Option<&str>, name: &str, by: i64) {
+        self.increment(name, by);
+        if let Some(tenant) = tenant {
+            self.increment(&tenant_counter(name, tenant), by);
+        }
+    }
+
+    pub fn counter(&self, name: &str) -> i64 {
+        self.counters.borrow().get(name).cloned().unwrap_or(0)
+    }
+
+    /// Counters in name order
+    pub fn counters(&self) -> Vec<(String, i64)> {
+        self.counters.borrow().iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+
+    /// Add the query to the log, dropping the oldest query once the log is full. Returns its id.
+    pub fn record_query<S: Into<String>>(&self, sql: S, elapsed: Duration, error: Option<String>)
+        -> u64
+    {
+        self.record_tenant_query(None, sql, elapsed, error)
+    }
+
+    /// `record_query()` of a query of the tenant
+    pub fn record_tenant_query<S: Into<String>>(&self, tenant: Option<&str>, sql: S,
+                                                elapsed: Duration, error: Option<String>) -> u64
+    {
+        let id = self.recorded.get() + 1;
+        self.recorded.set(id);
+
+        let mut queries = self.queries.borrow_mut();
+        if queries.len() == self.capacity {
+            queries.pop_front();
+        }
+
+        if self.capacity > 0 {
+            queries.push_back(QueryRecord {
+                id: id,
+                sql: sql.into(),
+                elapsed: elapsed,
+                error: error,
+                tenant: tenant.map(|t| t.to_string()),
+            });
+        }
+
+        id
+    }
+
+    /// Logged queries, oldest first
+    pub fn queries(&self) -> Vec<QueryRecord> {
+        self.queries.borrow().iter().cloned().collect()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new(DEFAULT_QUERY_LOG)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counters add up, the log keeps the newest queries
+    #[test]
+    fn counters_and_log() {
+        let metrics = Metrics::new(2);
+        metrics.increment("rows", 10);
+        metrics.increment("rows", 5);
+        metrics.increment("errors", 1);
+        assert_eq!(metrics.counter("rows"), 15);
+        assert_eq!(metrics.counter("missing"), 0);
+        assert_eq!(metrics.counters(), vec![("errors".to_string(), 1), ("rows".to_string(), 15)]);
+
+        let elapsed = Duration::from_millis(1);
+        metrics.record_query("SELECT 1", elapsed, None);
+        metrics.record_query("SELECT 2", elapsed, Some("bad".to_string()));
+        assert_eq!(metrics.record_query("SELECT 3", elapsed, None), 3);
+
+        let ids: Vec<u64> = metrics.queries().iter().map(|q| q.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(metrics.queries()[0].error, Some("bad".to_string()));
+    }
+
+    // Tenant usage adds up to the totals, queries keep their tenant
+    #[test]
+    fn tenant_usage() {
+        let metrics = Metrics::default();
+        metrics.increment_tenant(Some("acme"), "rows", 10);
+        metrics.increment_tenant(Some("globex"), "rows", 5);
+        metrics.increment_tenant(None, "rows", 1);
+        assert_eq!(metrics.counter("rows"), 16);
+        assert_eq!(metrics.counter(&tenant_counter("rows", "acme")), 10);
+        assert_eq!(metrics.counter("rows{tenant=globex}"), 5);
+
+        let elapsed = Duration::from_millis(1);
+        metrics.record_tenant_query(Some("acme"), "SELECT 1", elapsed, None);
+        metrics.record_query("SELECT 2", elapsed, None);
+        let tenants: Vec<Option<String>> =
+            metrics.queries().into_iter().map(|q| q.tenant).collect();
+        assert_eq!(tenants, vec![Some("acme".to_string()), None]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::compare::compare_keys;
+use ::error::DBError;
+use ::expression::{BoundExpr, Expr, bound_attribute};
+use ::expression::udf::AggregateUdf;
+use ::schema::Schema;
+use ::table::Table;
+use ::types::Value;
+use ::util::copy_value::ValueSetter;
+use ::util::hash::hash_rows;
+
+use super::{BlocksCursor, Cursor, CursorChunk, ExecutionContext, Operation};
+
+/// Relational Aggregate Operation, groups the rows of `src` by the `group_by` attributes and
+/// computes the `aggregates` for each group. The aggregates are calls of `AggregateUdf`s (see
+/// `FunctionRegistry::call_aggregate()`).
+///
+/// Output rows are the grouping attributes followed by the aggregates, one row per group in
+/// order of the group's first input row. NULL keys are a group of their own (as are NaNs).
+/// Without grouping attributes there's a single group, even without input rows.
+///
+/// Groups are found in a hash table of the keys (see `util::hash`), and materialized into a new
+/// block by `execute` or when the operation is bound. Without grouping attributes, each input
+/// chunk is added to the aggregates at once (see `Aggregate::update_column()`).
+pub struct HashAggregate<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub group_by: Vec<String>,
+    pub aggregates: Vec<Box<Expr<'a> + 'a>>,
+}
+
+/// Aggregate function bound to the input schema
+struct BoundAggregate<'a, 'b: 'a> {
+    func: Rc<AggregateUdf>,
+    arg: Box<BoundExpr<'b> + 'a>,
+}
+
+impl<'a> HashAggregate<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T, group_by: &[&str],
+                                      aggregates: Vec<Box<Expr<'a> + 'a>>)
+        -> HashAggregate<'a>
+    {
+        HashAggregate {
+            src: Box::new(src),
+            group_by: group_by.iter().map(|k| k.to_string()).collect(),
+            aggregates: aggregates,
+        }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.execute_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Row of each group, reading the input in batches of `ctx.batch_rows()`
+    pub fn execute_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Block<'b>, DBError>
+    {
+        let alloc = ctx.allocator;
+        let mut cursor = self.src.bind_context(ctx)?;
+        let schema = cursor.schema().clone();
+
+        let mut keys = Vec::with_capacity(self.group_by.len());
+        let mut attrs = Vec::with_capacity(self.group_by.len() + self.aggregates.len());
+        for name in &self.group_by {
+            let pos = schema.exists_ok(name)?;
+            keys.push(pos);
+            attrs.push(schema[pos].clone());
+        }
+
+        let mut aggregates = Vec::with_capacity(self.aggregates.len());
+        for expr in &self.aggregates {
+            let (func, arg) = expr.aggregate_call().ok_or_else(|| DBError::Unsupported(
+                String::from("aggregate expression that is not an aggregate function call")))?;
+            // The output attribute is the one of the call
+            let attr = bound_attribute(&*expr.bind(alloc, &schema)?)?.clone();
+            let arg = arg.bind(alloc, &schema)?;
+            bound_attribute(&*arg)?;
+
+            attrs.push(attr);
+            aggregates.push(BoundAggregate { func: func.clone(), arg: arg });
+        }
+        let out_schema = Schema::from_vec(attrs)?;
+
+        // Key values and aggregate states of each group, the groups of each key hash
+        let key_schema = Schema::from_vec(keys.iter().map(|p| schema[*p].clone()).collect())?;
+        let mut groups = Table::new(alloc, &key_schema, None);
+        let mut states: Vec<Vec<Box<Any>>> = Vec::new();
+        let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+
+        let fetch = ctx.batch_rows(&schema);
+        while let CursorChunk::Next(view) = cursor.next(fetch)? {
+            let rows = view.rows();
+            let args = aggregates.iter()
+                .map(|a| a.arg.evaluate(&view, rows))
+                .collect::<Result<Vec<_>, DBError>>()?;
+
+            // A single group, the aggregates add the whole columns
+            if keys.is_empty() {
+                if states.is_empty() {
+                    states.push(aggregates.iter().map(|a| a.func.init()).collect());
+                }
+                for (i, aggregate) in aggregates.iter().enumerate() {
+                    aggregate.func.update_column(&mut *states[0][i], args[i].column(0).unwrap(),
+                                                 rows)?;
+                }
+                continue
+            }
+
+            for (row, hash) in hash_rows(&view, &keys, 0)?.into_iter().enumerate() {
+                let mut values = Vec::with_capacity(keys.len());
+                for &pos in &keys {
+                    values.push(column_value(view.column(pos).unwrap(), row)?);
+                }
+
+                let candidates = index.entry(hash).or_insert_with(Vec::new);
+                let mut found = None;
+                for &group in candidates.iter() {
+                    if same_key(groups.block_ref(), group, &values)? {
+                        found = Some(group);
+                        break
+                    }
+                }
+
+                let group = match found {
+                    Some(group) => group,
+                    None => {
+                        let group = groups.add_row()?;
+                        for (pos, value) in values.into_iter().enumerate() {
+                            groups.set(pos, group, value)?;
+                        }
+                        states.push(aggregates.iter().map(|a| a.func.init()).collect());
+                        candidates.push(group);
+                        group
+                    },
+                };
+
+                for (i, aggregate) in aggregates.iter().enumerate() {
+                    let value = column_value(args[i].column(0).unwrap(), row)?;
+                    if !value.is_null() {
+                        aggregate.func.update(&mut *states[group][i], &value)?;
+                    }
+                }
+            }
+        }
+
+        if keys.is_empty() && states.is_empty() {
+            states.push(aggregates.iter().map(|a| a.func.init()).collect());
+        }
+
+        let groups = groups.block_ref();
+        let mut out = Table::new(alloc, &out_schema, Some(states.len()));
+        for (group, state) in states.iter().enumerate() {
+            let row = out.add_row()?;
+
+            for pos in 0 .. keys.len() {
+                out.set(pos, row, column_value(groups.column(pos).unwrap(), group)?)?;
+            }
+
+            for (i, aggregate) in aggregates.iter().enumerate() {
+                let pos = keys.len() + i;
+                let col = out.column_mut(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                aggregate.func.finalize(&*state[i], col, row)?;
+            }
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+/// The key values of the group are equal to `values`
+fn same_key(groups: &Block, group: usize, values: &[Value]) -> Result<bool, DBError> {
+    for (pos, value) in values.iter().enumerate() {
+        let key = column_value(groups.column(pos).unwrap(), group)?;
+        if compare_keys(&key, value) != Ordering::Equal {
+            return Ok(false)
+        }
+    }
+    Ok(true)
+}
+
+impl<'a> Operation<'a> for HashAggregate<'a> {
+    fn describe(&self) -> String {
+        if self.group_by.is_empty() {
+            String::from("HashAggregate")
+        } else {
+            format!("HashAggregate by {}", self.group_by.join(", "))
+        }
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let out = self.execute_context(ctx)?;
+        let schema = out.schema().clone();
+        Ok(Box::new(BlocksCursor::new(schema, vec![out])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::expression::udf::{Aggregate, FunctionRegistry};
+    use ::operation::{Limit, ScanView};
+    use ::schema::Attribute;
+    use ::table::TableAppender;
+    use ::types::Type;
+
+    /// INT64 sum of INT64 values, NULL without values
+    struct Sum;
+
+    impl Aggregate for Sum {
+        type State = Option<i64>;
+
+        fn output(&self, input: &Type) -> Result<Type, DBError> {
+            match *input {
+                Type::INT64 => Ok(Type::INT64),
+                _ => Err(DBError::ExpressionInputType(format!("sum of {}", input))),
+            }
+        }
+
+        fn init(&self) -> Option<i64> {
+            None
+        }
+
+        fn update(&self, state: &mut Option<i64>, value: &Value) -> Result<(), DBError> {
+            if let Value::INT64(v) = *value {
+                *state = Some(state.unwrap_or(0) + v);
+            }
+            Ok(())
+        }
+
+        fn merge(&self, state: &mut Option<i64>, other: Option<i64>) -> Result<(), DBError> {
+            if let Some(v) = other {
+                Aggregate::update(self, state, &Value::INT64(v))?;
+            }
+            Ok(())
+        }
+
+        fn finalize<'s>(&self, state: &'s Option<i64>) -> Result<Value<'s>, DBError> {
+            Ok(state.map_or(Value::NULL, Value::INT64))
+        }
+    }
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "k".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "v".to_string(), nullable: true, dtype: Type::INT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("b").set(1i64)
+                .add_row().set("a").set(2i64)
+                .add_row().set_null(true).set(3i64)
+                .add_row().set("b").set_null(true)
+                .add_row().set("a").set(4i64)
+                .add_row().set_null(true).set(5i64)
+                .add_row().set("c").set_null(true)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    // A row per group in order of appearance, NULL keys grouped together
+    #[test]
+    fn hash_aggregate() {
+        let block = make_block();
+        let mut registry = FunctionRegistry::new();
+        registry.register_aggregate("sum", Sum);
+        let sum = || -> Vec<Box<Expr>> {
+            vec![Box::new(registry.call_aggregate("sum", ColumnExpr::named("v")).unwrap())]
+        };
+
+        let op = HashAggregate::new(ScanView::new(&block, None), &["k"], sum());
+        assert_eq!(op.explain(), "HashAggregate by k\n  ScanView");
+
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_batch_bytes(1);
+        let out = op.execute_context(&ctx).unwrap();
+        assert_eq!(out.schema()[1].name, "sum(v)");
+        assert_eq!(out.rows(), 4);
+        let expected = [(Value::TEXT("b"), Value::INT64(1)), (Value::TEXT("a"), Value::INT64(6)),
+                        (Value::NULL, Value::INT64(8)), (Value::TEXT("c"), Value::NULL)];
+        for (row, &(ref key, ref sum)) in expected.iter().enumerate() {
+            assert!(column_value(out.column(0).unwrap(), row).unwrap() == *key,
+                    "key of row {}", row);
+            assert!(column_value(out.column(1).unwrap(), row).unwrap() == *sum,
+                    "sum of row {}", row);
+        }
+
+        // A single group without keys, even without rows
+        let op = HashAggregate::new(ScanView::new(&block, None), &[], sum());
+        let out = op.execute(&allocator::GLOBAL).unwrap();
+        assert!(column_value(out.column(0).unwrap(), 0).unwrap() == Value::INT64(15));
+
+        let op = HashAggregate::new(Limit::new(0, 0, ScanView::new(&block, None)), &[], sum());
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        match cursor.next(10).unwrap() {
+            CursorChunk::Next(view) =>
+                assert!(column_value(view.column(0).unwrap(), 0).unwrap() == Value::NULL),
+            CursorChunk::End => assert!(false, "Expected a row"),
+        }
+
+        let op = HashAggregate::new(ScanView::new(&block, None), &["k"],
+                                    vec![Box::new(ColumnExpr::named("v"))]);
+        match op.execute(&allocator::GLOBAL) {
+            Err(DBError::Unsupported(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
@@ -0,0 +1,74 @@
+use std::cmp::min;
+
+use ::block::{Block, View, window_alias};
+use ::error::DBError;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+use super::{Cursor, CursorChunk};
+
+/// Cursor returning the rows of blocks it owns, for operations producing new rows when bound
+/// (eg. `HashAggregate`) or as they're read (`HashJoin`). Each chunk aliases (a window of) one of
+/// the blocks.
+///
+/// The blocks are kept, at a fixed address, until the cursor is dropped: chunks are valid for as
+/// long as the cursor is, not for the whole lifetime of the data allocator.
+pub struct BlocksCursor<'a> {
+    schema: Schema,
+    blocks: Vec<Box<Block<'a>>>,
+    /// Blocks read once the ones in `blocks` are returned
+    more: Option<Box<Iterator<Item=Result<Block<'a>, DBError>> + 'a>>,
+    block: usize,
+    offset: RowOffset,
+}
+
+impl<'a> BlocksCursor<'a> {
+    pub fn new(schema: Schema, blocks: Vec<Block<'a>>) -> BlocksCursor<'a> {
+        BlocksCursor {
+            schema: schema,
+            blocks: blocks.into_iter().map(Box::new).collect(),
+            more: None,
+            block: 0,
+            offset: 0,
+        }
+    }
+
+    /// Cursor returning the blocks of `more` as they're read
+    pub fn reading<I>(schema: Schema, more: I) -> BlocksCursor<'a>
+        where I: Iterator<Item=Result<Block<'a>, DBError>> + 'a
+    {
+        let mut cursor = BlocksCursor::new(schema, Vec::new());
+        cursor.more = Some(Box::new(more));
+        cursor
+    }
+}
+
+impl<'a> Cursor<'a> for BlocksCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        loop {
+            if self.block == self.blocks.len() {
+                match self.more.as_mut().and_then(|more| more.next()) {
+                    Some(block) => self.blocks.push(Box::new(block?)),
+                    None => return Ok(CursorChunk::End),
+                }
+            }
+
+            // Boxed blocks don't move when `blocks` grows, and are only dropped with the cursor
+            let block: &'a Block<'a> = unsafe { &*(&*self.blocks[self.block] as *const Block) };
+            let left = block.rows() - self.offset;
+            if left == 0 {
+                self.block += 1;
+                self.offset = 0;
+                continue
+            }
+
+            let range = RowRange { offset: self.offset, rows: min(left, rows) };
+            self.offset += range.rows;
+            return window_alias(block, Some(range)).map(CursorChunk::Next)
+        }
+    }
+}
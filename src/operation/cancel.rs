@@ -0,0 +1,158 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Cancellation flag of a query, with an optional deadline. Clones share the flag, so a query
+/// can be cancelled from another thread (eg. on a client disconnect) while it runs.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+/// Passes the source rows through unchanged, checking the `token` before binding and before
+/// getting each chunk from the source. Once the token is cancelled (or its deadline passed) the
+/// cursor fails with `DBError::Cancelled`.
+///
+/// Cancellation is checked between chunks, a chunk the source is busy getting is not interrupted.
+pub struct Cancellable<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub token: CancellationToken,
+}
+
+/// Implementation of the `Cancellable` operation
+struct CancellableCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    token: CancellationToken,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Also cancel the query once `deadline` is reached
+    pub fn with_deadline(mut self, deadline: Instant) -> CancellationToken {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Also cancel the query once `timeout` from now passed
+    pub fn with_timeout(self, timeout: Duration) -> CancellationToken {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// `DBError::Cancelled` once the token is cancelled or past its deadline
+    pub fn check(&self) -> Result<(), DBError> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(DBError::Cancelled("cancelled".to_string()))
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline =>
+                Err(DBError::Cancelled("deadline exceeded".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> Cancellable<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T, token: CancellationToken) -> Cancellable<'a> {
+        Cancellable { src: Box::new(src), token: token }
+    }
+}
+
+impl<'a> Operation<'a> for Cancellable<'a> {
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        self.token.check()?;
+
+        let input = self.src.bind_context(ctx)?;
+        Ok(Box::new(CancellableCursor { input: input, token: self.token.clone() }))
+    }
+
+    fn describe(&self) -> String {
+        match self.token.deadline {
+            Some(_) => String::from("Cancellable [deadline]"),
+            None => String::from("Cancellable"),
+        }
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+}
+
+impl<'a> Cursor<'a> for CancellableCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        self.token.check()?;
+        self.input.next(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::operation::ScanView;
+    use ::types::{Type, Value};
+
+    // Chunks are returned until the token is cancelled, expired deadlines fail the bind
+    #[test]
+    fn cancel_query() {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 10 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+
+        let token = CancellationToken::new();
+        let op = Cancellable::new(ScanView::new(&block, None), token.clone());
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        match cursor.next(4).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 4),
+            CursorChunk::End => assert!(false, "Expected rows"),
+        }
+
+        token.cancel();
+        assert!(op.token.is_cancelled());
+        match cursor.next(4) {
+            Err(DBError::Cancelled(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+
+        let token = CancellationToken::new().with_deadline(Instant::now());
+        let op = Cancellable::new(ScanView::new(&block, None), token);
+        match op.bind(&allocator::GLOBAL) {
+            Err(DBError::Cancelled(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+
+        let token = CancellationToken::new().with_timeout(Duration::from_secs(3600));
+        let op = Cancellable::new(ScanView::new(&block, None), token);
+        assert!(op.bind(&allocator::GLOBAL).is_ok());
+    }
+}
@@ -0,0 +1,268 @@
+//! Order independent checksums of table partitions.
+//!
+//! `Checksum` summarizes the rows of its input in a fixed number of partitions, so two copies of
+//! a table (eg. a dbkit table and its copy in another database) can be compared by their
+//! checksums alone, and only the rows of partitions that differ need to be transferred.
+//!
+//! The checksum is computed the same way by any system implementing this algorithm:
+//!
+//! 1. The value of each of the checksummed (and partition) columns is encoded as a byte 0 for
+//!    NULL, otherwise a byte 1 followed by:
+//!    - UINT32, UINT64, INT32, INT64, TIMESTAMP (microseconds): the value as a 64 bit big endian
+//!      integer (two's complement for the signed types)
+//!    - FLOAT32, FLOAT64: the 64 bit big endian IEEE 754 bits of the value as a double, -0.0
+//!      encoded as 0.0 and every NaN as `0x7ff8000000000000`
+//!    - BOOLEAN: a byte 0 or 1
+//!    - INTERVAL: months and days as 32 bit, microseconds as a 64 bit big endian integer
+//!    - UUID: its 16 bytes
+//!    - TEXT (UTF-8), BLOB: the length of the bytes as a 64 bit big endian integer, followed by
+//!      the bytes
+//! 2. The hash of the concatenated encoding of a row's values (in column order) is the first 8
+//!    bytes, as a big endian unsigned integer, of its SHA-256 digest.
+//! 3. A row belongs to partition `hash(partition columns) % partitions`, or partition 0 without
+//!    partition columns.
+//! 4. The checksum of a partition is the sum, modulo 2^64, of the row hashes of its rows.
+//!
+//! LIST and STRUCT columns can't be checksummed.
+
+use ::allocator::Allocator;
+use ::block::{Block, RefColumn, View, column_value};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Type, Value};
+use ::util::hmac::sha256;
+
+use super::{Operation, CursorChunk, ExecutionContext};
+
+/// Checksums of the `columns` of the `src` rows in `partitions` partitions of the `partition_by`
+/// columns.
+///
+/// `execute` returns a row for each partition (including empty ones), in partition order: the
+/// UINT64 `partition` number, its number of `rows` and its UINT64 `checksum`.
+pub struct Checksum<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub columns: Vec<String>,
+    pub partition_by: Vec<String>,
+    pub partitions: u64,
+}
+
+/// Append the encoding of the value, see the module documentation
+fn encode_value(out: &mut Vec<u8>, value: &Value) -> bool {
+    fn float_bits(v: f64) -> u64 {
+        if v == 0.0 { 0 } else if v.is_nan() { 0x7ff8_0000_0000_0000 } else { v.to_bits() }
+    }
+
+    fn push_u64(out: &mut Vec<u8>, v: u64) {
+        for shift in (0 .. 8).rev() {
+            out.push((v >> (shift * 8)) as u8);
+        }
+    }
+
+    if *value == Value::NULL {
+        out.push(0);
+        return true
+    }
+
+    out.push(1);
+    match *value {
+        Value::UINT32(v)        => push_u64(out, v as u64),
+        Value::UINT64(v)        => push_u64(out, v),
+        Value::INT32(v)         => push_u64(out, v as i64 as u64),
+        Value::INT64(v)         => push_u64(out, v as u64),
+        Value::TIMESTAMP(v)     => push_u64(out, v as u64),
+        Value::FLOAT32(v)       => push_u64(out, float_bits(v as f64)),
+        Value::FLOAT64(v)       => push_u64(out, float_bits(v)),
+        Value::BOOLEAN(v)       => out.push(v as u8),
+        Value::INTERVAL(v)      => {
+            let head = (v.months as u32 as u64) << 32 | v.days as u32 as u64;
+            push_u64(out, head);
+            push_u64(out, v.micros as u64);
+        },
+        Value::UUID(ref v)      => out.extend_from_slice(v),
+        Value::TEXT(v)          => {
+            push_u64(out, v.len() as u64);
+            out.extend_from_slice(v.as_bytes());
+        },
+        Value::BLOB(v)          => {
+            push_u64(out, v.len() as u64);
+            out.extend_from_slice(v);
+        },
+        _                       => return false,
+    }
+
+    true
+}
+
+/// Hash of the encoded values of the `columns` in the row
+fn row_hash(buf: &mut Vec<u8>, columns: &[&RefColumn], row: RowOffset) -> Result<u64, DBError> {
+    buf.clear();
+    for col in columns {
+        if !encode_value(buf, &column_value(*col, row)?) {
+            return Err(DBError::AttributeType(col.attribute().name.clone()))
+        }
+    }
+
+    let digest = sha256(buf);
+    Ok(digest[.. 8].iter().fold(0, |acc, &b| acc << 8 | b as u64))
+}
+
+/// Positions of the named attributes, which have to be of a type that can be checksummed
+fn positions(schema: &Schema, names: &[String]) -> Result<Vec<usize>, DBError> {
+    let mut out = Vec::with_capacity(names.len());
+    for name in names {
+        let pos = schema.exists_ok(name)?;
+        match schema[pos].dtype {
+            Type::LIST(_) | Type::STRUCT(_) => return Err(DBError::AttributeType(name.clone())),
+            _ => out.push(pos),
+        }
+    }
+
+    Ok(out)
+}
+
+impl<'a> Checksum<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T, columns: &[&str], partition_by: &[&str],
+                                      partitions: u64) -> Checksum<'a>
+    {
+        Checksum {
+            src: Box::new(src),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            partition_by: partition_by.iter().map(|c| c.to_string()).collect(),
+            partitions: partitions,
+        }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.execute_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Row of each partition, reading the input in batches of `ctx.batch_rows()`
+    pub fn execute_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Block<'b>, DBError>
+    {
+        if self.partitions == 0 {
+            return Err(DBError::ValueOutOfRange("checksum of 0 partitions".to_string()))
+        }
+
+        let alloc = ctx.allocator;
+        let mut cursor = self.src.bind_context(ctx)?;
+        let fetch = ctx.batch_rows(cursor.schema());
+        let columns = positions(cursor.schema(), &self.columns)?;
+        let partition_by = positions(cursor.schema(), &self.partition_by)?;
+
+        let mut rows = vec![0u64; self.partitions as usize];
+        let mut sums = vec![0u64; self.partitions as usize];
+        let mut buf = Vec::new();
+
+        loop {
+            let view = match cursor.next(fetch)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => break,
+            };
+
+            let column = |pos: &usize| view.column(*pos)
+                .ok_or_else(|| DBError::make_column_unknown_pos(*pos));
+            let values = columns.iter().map(&column).collect::<Result<Vec<_>, DBError>>()?;
+            let keys = partition_by.iter().map(&column).collect::<Result<Vec<_>, DBError>>()?;
+
+            for row in 0 .. view.rows() {
+                let partition = if keys.is_empty() {
+                    0
+                } else {
+                    (row_hash(&mut buf, &keys, row)? % self.partitions) as usize
+                };
+
+                rows[partition] += 1;
+                sums[partition] = sums[partition].wrapping_add(row_hash(&mut buf, &values, row)?);
+            }
+        }
+
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "partition".to_string(), nullable: false, dtype: Type::UINT64},
+            Attribute{name: "rows".to_string(), nullable: false, dtype: Type::UINT64},
+            Attribute{name: "checksum".to_string(), nullable: false, dtype: Type::UINT64},
+        ])?;
+
+        let mut out = Table::new(alloc, &schema, Some(rows.len()));
+        for (partition, (count, sum)) in rows.iter().zip(&sums).enumerate() {
+            let row = out.add_row()?;
+            out.set(0, row, partition as u64)?;
+            out.set(1, row, *count)?;
+            out.set(2, row, *sum)?;
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::ScanView;
+
+    fn make_block<'a>(rows: &[(i64, &str)]) -> Block<'a> {
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for &(id, name) in rows {
+            let name = if name.is_empty() { Value::NULL } else { Value::TEXT(name) };
+            block.append_row(&[Value::INT64(id), name]).unwrap();
+        }
+        block
+    }
+
+    /// Rows and checksum of each partition, the same when reading the input in small batches
+    fn checksums(block: &Block) -> Vec<(u64, u64)> {
+        let op = Checksum::new(ScanView::new(block, None), &["id", "name"], &["id"], 4);
+        let partitions = |out: Block| -> Vec<(u64, u64)> {
+            (0 .. out.rows())
+                .map(|r| match (out.value(r, 1).unwrap(), out.value(r, 2).unwrap()) {
+                    (Value::UINT64(rows), Value::UINT64(sum)) => (rows, sum),
+                    _ => panic!("Expected UINT64 rows and checksum"),
+                })
+                .collect()
+        };
+
+        let out = partitions(op.execute(&allocator::GLOBAL).unwrap());
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_batch_bytes(1);
+        assert_eq!(partitions(op.execute_context(&ctx).unwrap()), out);
+        out
+    }
+
+    // Checksums don't depend on the row order, changed rows change their partition's checksum
+    #[test]
+    fn partition_checksums() {
+        let rows = [(1, "a"), (2, "b"), (3, ""), (4, "d"), (5, "e"), (6, "f")];
+        let original = checksums(&make_block(&rows));
+        assert_eq!(original.len(), 4);
+        assert_eq!(original.iter().map(|p| p.0).sum::<u64>(), 6);
+
+        let mut reversed = rows.to_vec();
+        reversed.reverse();
+        assert_eq!(checksums(&make_block(&reversed)), original);
+
+        let mut changed = rows.to_vec();
+        changed[2] = (3, "c");
+        let partitions = checksums(&make_block(&changed));
+        let differ = original.iter().zip(&partitions).filter(|&(a, b)| a != b).count();
+        assert_eq!(differ, 1);
+
+        // The documented row encoding
+        let mut buf = Vec::new();
+        encode_value(&mut buf, &Value::INT32(-2));
+        encode_value(&mut buf, &Value::NULL);
+        encode_value(&mut buf, &Value::TEXT("hi"));
+        assert_eq!(buf, vec![1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 0,
+                             1, 0, 0, 0, 0, 0, 0, 0, 2, b'h', b'i']);
+
+        let block = make_block(&rows);
+        let op = Checksum::new(ScanView::new(&block, None), &["id"], &[], 0);
+        assert!(op.execute(&allocator::GLOBAL).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::env;
+use std::path::PathBuf;
+
+use ::allocator::{Allocator, MemoryTracker};
+use ::fmt::PrettyOptions;
+use ::row::RowOffset;
+use ::schema::{Schema, DEFAULT_BATCH_BYTES};
+use ::util::temporal::{MICROS_PER_MINUTE, format_local_timestamp};
+
+/// Configuration of a query, given to the operations when they're bound (see
+/// `Operation::bind_context()`). Operations with inputs bind them with the same context.
+///
+/// `ExecutionContext::new()` has the defaults the operations use when bound with just an
+/// allocator (`Operation::bind()`).
+#[derive(Clone)]
+pub struct ExecutionContext<'a> {
+    /// Allocator of the cursors' data, the memory tracker when there's a budget
+    pub allocator: &'a Allocator,
+    /// Size of the batches fetched from the inputs, see `Schema::batch_rows()`
+    pub batch_bytes: usize,
+    /// Tracker of the query memory, with the budget as its limit
+    pub memory: Option<&'a MemoryTracker<'a>>,
+    /// Directory for the files of data spilled to disk
+    pub temp_dir: PathBuf,
+    /// Threads the query can use
+    pub threads: usize,
+    /// Offset of the local time from UTC, in microseconds (timestamps are in UTC). Business days
+    /// of a `DateSeries` and formatted timestamps are in local time.
+    pub utc_offset: i64,
+}
+
+impl<'a> ExecutionContext<'a> {
+    pub fn new(alloc: &'a Allocator) -> ExecutionContext<'a> {
+        ExecutionContext {
+            allocator: alloc,
+            batch_bytes: DEFAULT_BATCH_BYTES,
+            memory: None,
+            temp_dir: env::temp_dir(),
+            threads: 1,
+            utc_offset: 0,
+        }
+    }
+
+    pub fn with_batch_bytes(mut self, bytes: usize) -> ExecutionContext<'a> {
+        self.batch_bytes = bytes;
+        self
+    }
+
+    /// Allocate through the tracker, failing with `DBError::MemoryLimit` past its limit
+    pub fn with_memory(mut self, memory: &'a MemoryTracker<'a>) -> ExecutionContext<'a> {
+        self.allocator = memory;
+        self.memory = Some(memory);
+        self
+    }
+
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> ExecutionContext<'a> {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> ExecutionContext<'a> {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Local time `minutes` from UTC, eg. -300 for UTC-05:00
+    pub fn with_utc_offset(mut self, minutes: i32) -> ExecutionContext<'a> {
+        self.utc_offset = minutes as i64 * MICROS_PER_MINUTE;
+        self
+    }
+
+    /// Rows to fetch at a time from a cursor of the schema
+    pub fn batch_rows(&self, schema: &Schema) -> RowOffset {
+        schema.batch_rows(self.batch_bytes)
+    }
+
+    /// Local time of the (UTC) timestamp
+    pub fn local_timestamp(&self, ts: i64) -> i64 {
+        ts + self.utc_offset
+    }
+
+    /// The timestamp as local time text with its offset, see `format_local_timestamp()`
+    pub fn format_timestamp(&self, ts: i64) -> String {
+        format_local_timestamp(ts, self.utc_offset)
+    }
+
+    /// Default rendering options of `fmt`, with timestamps in local time
+    pub fn pretty_options(&self) -> PrettyOptions {
+        PrettyOptions { utc_offset: Some(self.utc_offset), ..PrettyOptions::default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::dataframe;
+    use ::error::DBError;
+    use ::operation::{CursorChunk, Instrument, Limit, Operation, OperatorCounters, ScanView};
+    use ::types::{Type, Value};
+
+    // Operations get the context when bound, operations with inputs pass it on
+    #[test]
+    fn bind_context() {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 100 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+
+        let counters = Rc::new(Cell::new(OperatorCounters::default()));
+        let memory = MemoryTracker::new(&allocator::GLOBAL, "query", None);
+        let _held = memory.allocate(100).unwrap();
+        let ctx = ExecutionContext::new(&allocator::GLOBAL)
+            .with_memory(&memory)
+            .with_utc_offset(-90);
+        assert_eq!(ctx.local_timestamp(0), -90 * MICROS_PER_MINUTE);
+        assert_eq!(ctx.format_timestamp(0), "1969-12-31 22:30:00-01:30");
+
+        // Instrument without a tracker of its own samples the context's
+        let scan = Instrument::new(ScanView::new(&block, None), counters.clone());
+        let op = Limit::new(0, 10, scan);
+        let mut cursor = op.bind_context(&ctx).unwrap();
+        while let CursorChunk::Next(_) = cursor.next(4).unwrap() {}
+        assert!(counters.get().peak_memory >= 100);
+
+        // Results are allocated within the budget
+        let small = MemoryTracker::new(&allocator::GLOBAL, "small", Some(64));
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_memory(&small);
+        match dataframe::scan(&block).collect_context(&ctx) {
+            Err(DBError::MemoryLimit) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_batch_bytes(1);
+        assert!(ctx.batch_rows(&schema) < ExecutionContext::new(ctx.allocator).batch_rows(&schema));
+        let rows = dataframe::scan(&block).collect_context(&ctx).unwrap();
+        assert_eq!(rows.rows(), 100);
+    }
+}
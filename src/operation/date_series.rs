@@ -0,0 +1,134 @@
+use ::allocator::Allocator;
+use ::block::Block;
+use ::error::DBError;
+use ::schema::Schema;
+use ::types::{IntervalValue, Timestamp, Type};
+use ::util::temporal::{add_interval, day_of_week};
+
+use super::ExecutionContext;
+
+/// Generated TIMESTAMP "ts" column of `start`, `start + step`, `start + 2 * step`, ... up to
+/// (including) `end`. Dates are timestamps at midnight, eg. a series from a midnight `start` with
+/// a daily `step`.
+///
+/// Each row is `start` plus a multiple of the step, so a monthly series from Jan 31st has the
+/// last day of each month. With `business_days` only Monday to Friday rows are generated, there's
+/// no holiday calendar. Days are in the local time of the context (see `execute_context`), so a
+/// UTC midnight series west of UTC falls on the day before.
+///
+/// The rows are generated into a new block by `execute`, which can be joined with sparse data to
+/// report on every period.
+pub struct DateSeries {
+    pub start: i64,
+    pub end: i64,
+    pub step: IntervalValue,
+    pub business_days: bool,
+}
+
+impl DateSeries {
+    pub fn new(start: i64, end: i64, step: IntervalValue) -> DateSeries {
+        DateSeries { start: start, end: end, step: step, business_days: false }
+    }
+
+    /// Skip the Saturday and Sunday rows
+    pub fn business_days(self) -> DateSeries {
+        DateSeries { business_days: true, .. self }
+    }
+
+    /// `start + n * step`, None if it's out of the TIMESTAMP range
+    fn nth(&self, n: i32) -> Option<i64> {
+        let step = IntervalValue {
+            months: self.step.months.checked_mul(n)?,
+            days: self.step.days.checked_mul(n)?,
+            micros: self.step.micros.checked_mul(n as i64)?,
+        };
+
+        add_interval(self.start, &step).ok()
+    }
+
+    pub fn execute<'b>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.execute_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Rows of the series, business days in the local time of `ctx.utc_offset`
+    pub fn execute_context<'b>(&self, ctx: &ExecutionContext<'b>) -> Result<Block<'b>, DBError> {
+        let step = self.step;
+        let forward = step.months >= 0 && step.days >= 0 && step.micros >= 0;
+        if !forward || step == IntervalValue::default() {
+            return Err(DBError::ValueOutOfRange(format!("DateSeries step of {}", step)))
+        }
+
+        let mut values = Vec::new();
+        for n in 0 .. {
+            let ts = match self.nth(n) {
+                Some(ts) if ts <= self.end => ts,
+                _ => break,
+            };
+
+            if !self.business_days || day_of_week(ctx.local_timestamp(ts)) <= 5 {
+                values.push(ts);
+            }
+        }
+
+        let schema = Schema::make_one_attr("ts", false, Type::TIMESTAMP);
+        let mut out = Block::new(ctx.allocator, &schema);
+        out.add_rows(values.len())?;
+
+        out.column_mut(0).unwrap().rows_mut::<Timestamp>()?[.. values.len()]
+            .copy_from_slice(&values);
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_row_data};
+    use ::util::temporal::{days_from_civil, format_timestamp, MICROS_PER_DAY};
+
+    fn date(year: i64, month: u32, day: u32) -> i64 {
+        days_from_civil(year, month, day) * MICROS_PER_DAY
+    }
+
+    fn dates(series: DateSeries) -> Vec<String> {
+        let block = series.execute(&allocator::GLOBAL).unwrap();
+        let data = column_row_data::<Timestamp>(block.column(0).unwrap()).unwrap();
+        data.values[.. block.rows()].iter().map(|ts| format_timestamp(*ts)[.. 10].to_string())
+            .collect()
+    }
+
+    // Inclusive end, month steps stay on the month end, weekends are skipped
+    #[test]
+    fn date_series_rows() {
+        let day: IntervalValue = "1 day".parse().unwrap();
+        let month: IntervalValue = "1 month".parse().unwrap();
+
+        assert_eq!(dates(DateSeries::new(date(2018, 2, 27), date(2018, 3, 2), day)),
+                   vec!["2018-02-27", "2018-02-28", "2018-03-01", "2018-03-02"]);
+        assert_eq!(dates(DateSeries::new(date(2018, 1, 31), date(2018, 4, 30), month)),
+                   vec!["2018-01-31", "2018-02-28", "2018-03-31", "2018-04-30"]);
+
+        // Friday to the next Tuesday
+        assert_eq!(dates(DateSeries::new(date(2018, 6, 1), date(2018, 6, 5), day).business_days()),
+                   vec!["2018-06-01", "2018-06-04", "2018-06-05"]);
+        assert!(dates(DateSeries::new(date(2018, 6, 2), date(2018, 6, 1), day)).is_empty());
+
+        // At UTC-05:00 midnight UTC is the evening before, Saturday and Monday are business days
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_utc_offset(-300);
+        let series = DateSeries::new(date(2018, 6, 1), date(2018, 6, 5), day).business_days();
+        let block = series.execute_context(&ctx).unwrap();
+        let data = column_row_data::<Timestamp>(block.column(0).unwrap()).unwrap();
+        assert_eq!(&data.values[.. block.rows()],
+                   &[date(2018, 6, 1), date(2018, 6, 2), date(2018, 6, 5)]);
+
+        let back: IntervalValue = "-1 day".parse().unwrap();
+        let series = DateSeries::new(date(2018, 6, 2), date(2018, 6, 1), back);
+        match series.execute(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::index::HashIndex;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Type, Value};
+
+use super::{Operation, CursorChunk, ExecutionContext};
+
+/// Name of the change type column of the `Diff` output
+pub const DIFF_CHANGE_COLUMN: &str = "change";
+
+/// How a row differs between the `Diff` inputs
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Change {
+    /// Key is only in the new input
+    Added,
+    /// Key is only in the old input
+    Removed,
+    /// Key is in both inputs, with different values of the other attributes
+    Changed,
+}
+
+impl Change {
+    /// Value of the change type column
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Change::Added   => "added",
+            Change::Removed => "removed",
+            Change::Changed => "changed",
+        }
+    }
+}
+
+/// Difference between two inputs with the same schema, matching their rows by the `keys`
+/// attributes. Emits a row per added, removed and changed key, unchanged rows are left out.
+///
+/// The output has a not nullable TEXT `change` column (`Change::as_str()`) followed by the input
+/// attributes. Added and changed rows have the `new` values and come first in `new` order,
+/// followed by the removed rows with the `old` values in `old` order. Rows with NULL key values
+/// never match, rows with duplicate keys are matched in input order. NaNs equal each other.
+///
+/// Both inputs are materialized, the diff is computed by `execute`.
+pub struct Diff<'a> {
+    pub old: Box<Operation<'a> + 'a>,
+    pub new: Box<Operation<'a> + 'a>,
+    pub keys: Vec<String>,
+}
+
+/// All the rows of the operation, read in batches of `ctx.batch_rows()`
+fn materialize<'a, 'b: 'a>(op: &Operation<'a>, ctx: &ExecutionContext<'b>)
+    -> Result<Table<'b>, DBError>
+{
+    let mut cursor = op.bind_context(ctx)?;
+    let fetch = ctx.batch_rows(cursor.schema());
+    let mut out = Table::new(ctx.allocator, cursor.schema(), None);
+    loop {
+        match cursor.next(fetch)? {
+            CursorChunk::Next(view) => out.append_block(&view)?,
+            CursorChunk::End        => break,
+        }
+    }
+
+    Ok(out)
+}
+
+/// Output schema, the input schemas have to have the same attribute names and types
+fn diff_schema(old: &Schema, new: &Schema) -> Result<Schema, DBError> {
+    if old.count() != new.count() {
+        return Err(DBError::ExpressionInputCount(
+            format!("diff of {} and {} attributes", old.count(), new.count())))
+    }
+
+    let mut attrs = vec![
+        Attribute { name: DIFF_CHANGE_COLUMN.to_string(), nullable: false, dtype: Type::TEXT },
+    ];
+
+    for (o, n) in old.iter().zip(new.iter()) {
+        if o.name != n.name {
+            return Err(DBError::AttributeMissing(n.name.clone()))
+        }
+        if o.dtype != n.dtype {
+            return Err(DBError::AttributeType(n.name.clone()))
+        }
+
+        attrs.push(Attribute { name: n.name.clone(), nullable: o.nullable || n.nullable,
+                               dtype: n.dtype.clone() });
+    }
+
+    Schema::from_vec(attrs)
+}
+
+fn same_value(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (&Value::FLOAT32(l), &Value::FLOAT32(r)) => l == r || (l.is_nan() && r.is_nan()),
+        (&Value::FLOAT64(l), &Value::FLOAT64(r)) => l == r || (l.is_nan() && r.is_nan()),
+        _ => lhs == rhs,
+    }
+}
+
+/// True if the rows have the same values for all the attributes
+fn same_row<'v>(old: &'v View<'v>, old_row: RowOffset, new: &'v View<'v>, new_row: RowOffset)
+    -> Result<bool, DBError>
+{
+    for pos in 0 .. new.schema().count() {
+        let o = old.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        let n = new.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+
+        if !same_value(&column_value(o, old_row)?, &column_value(n, new_row)?) {
+            return Ok(false)
+        }
+    }
+
+    Ok(true)
+}
+
+/// Append the change row with the values of the `src` row
+fn append_change<'v>(out: &mut Table, change: Change, src: &'v View<'v>, row: RowOffset)
+    -> Result<(), DBError>
+{
+    let out_row = out.add_row()?;
+    out.set(0, out_row, Value::TEXT(change.as_str()))?;
+
+    for pos in 0 .. src.schema().count() {
+        let col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        out.set(pos + 1, out_row, column_value(col, row)?)?;
+    }
+
+    Ok(())
+}
+
+impl<'a> Diff<'a> {
+    pub fn new<O, N>(old: O, new: N, keys: &[&str]) -> Diff<'a>
+        where O: Operation<'a> + 'a, N: Operation<'a> + 'a
+    {
+        Diff {
+            old: Box::new(old),
+            new: Box::new(new),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.execute_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Changed rows, binding the inputs with the context
+    pub fn execute_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Block<'b>, DBError>
+    {
+        if self.keys.is_empty() {
+            return Err(DBError::AttributeMissing("(diff without key attributes)".to_string()))
+        }
+
+        let alloc = ctx.allocator;
+        let old = materialize(&*self.old, ctx)?;
+        let new = materialize(&*self.new, ctx)?;
+        let schema = diff_schema(old.schema(), new.schema())?;
+
+        let mut keys = Vec::with_capacity(self.keys.len());
+        for name in &self.keys {
+            keys.push(new.schema().exists_ok(name)?);
+        }
+
+        let key_names: Vec<&str> = self.keys.iter().map(|k| k.as_str()).collect();
+        let index = HashIndex::build(&old, &key_names)?;
+        let mut matched = vec![false; old.rows()];
+        let mut out = Table::new(alloc, &schema, None);
+
+        for row in 0 .. new.rows() {
+            let mut key = Vec::with_capacity(keys.len());
+            for &pos in &keys {
+                let col = new.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                key.push(column_value(col, row)?);
+            }
+
+            let found = if key.contains(&Value::NULL) {
+                None
+            } else {
+                index.lookup(&old, &key)?.into_iter().find(|&r| !matched[r])
+            };
+
+            match found {
+                Some(old_row) => {
+                    matched[old_row] = true;
+                    if !same_row(&old, old_row, &new, row)? {
+                        append_change(&mut out, Change::Changed, &new, row)?;
+                    }
+                },
+                None => append_change(&mut out, Change::Added, &new, row)?,
+            }
+        }
+
+        for (row, _) in matched.iter().enumerate().filter(|&(_, m)| !*m) {
+            append_change(&mut out, Change::Removed, &old, row)?;
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::ScanView;
+
+    fn make_block<'a>(rows: &[(i64, &str, Option<f64>)]) -> Block<'a> {
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "id".to_string(), nullable: true, dtype: Type::INT64},
+            Attribute{name: "name".to_string(), nullable: false, dtype: Type::TEXT},
+            Attribute{name: "score".to_string(), nullable: true, dtype: Type::FLOAT64},
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for &(id, name, score) in rows {
+            let id = if id < 0 { Value::NULL } else { Value::INT64(id) };
+            let score = score.map_or(Value::NULL, Value::FLOAT64);
+            block.append_row(&[id, Value::TEXT(name), score]).unwrap();
+        }
+        block
+    }
+
+    fn change_rows<'v>(view: &'v View<'v>) -> Vec<(String, Value<'v>)> {
+        (0 .. view.rows())
+            .map(|r| match view.value(r, 0).unwrap() {
+                Value::TEXT(change) => (change.to_string(), view.value(r, 1).unwrap()),
+                _ => panic!("Expected TEXT change"),
+            })
+            .collect()
+    }
+
+    // Added, changed and removed rows of the keys, unchanged rows left out
+    #[test]
+    fn diff_rows() {
+        let old = make_block(&[(1, "a", Some(1.0)), (2, "b", None), (3, "c", Some(::std::f64::NAN)),
+                               (4, "d", Some(4.0)), (-1, "n", None)]);
+        let new = make_block(&[(3, "c", Some(::std::f64::NAN)), (2, "b", Some(2.0)),
+                               (5, "e", None), (1, "a", Some(1.0)), (-1, "n", None)]);
+
+        let diff = Diff::new(ScanView::new(&old, None), ScanView::new(&new, None), &["id"]);
+        let out = diff.execute(&allocator::GLOBAL).unwrap();
+
+        assert_eq!(out.schema()[0].name, DIFF_CHANGE_COLUMN);
+        assert_eq!(out.schema().count(), 4);
+
+        let changes = change_rows(&out);
+        let expected = [("changed", Value::INT64(2)), ("added", Value::INT64(5)),
+                        ("added", Value::NULL), ("removed", Value::INT64(4)),
+                        ("removed", Value::NULL)];
+        assert_eq!(changes.len(), expected.len());
+        for (change, &(kind, ref id)) in changes.iter().zip(expected.iter()) {
+            assert_eq!(change.0, kind);
+            assert!(change.1 == *id);
+        }
+        assert!(out.value(0, 3).unwrap() == Value::FLOAT64(2.0));
+        assert!(out.value(3, 2).unwrap() == Value::TEXT("d"));
+
+        // Inputs read in small batches give the same changes
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_batch_bytes(1);
+        let batched = diff.execute_context(&ctx).unwrap();
+        assert_eq!(batched.rows(), out.rows());
+        for row in 0 .. out.rows() {
+            assert!(batched.value(row, 1).unwrap() == out.value(row, 1).unwrap(), "row {}", row);
+        }
+
+        // Mismatched schemas and keys
+        let other = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("id", true, Type::INT64));
+        let diff = Diff::new(ScanView::new(&old, None), ScanView::new(&other, None), &["id"]);
+        match diff.execute(&allocator::GLOBAL) {
+            Err(DBError::ExpressionInputCount(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let diff = Diff::new(ScanView::new(&old, None), ScanView::new(&new, None), &["missing"]);
+        assert!(diff.execute(&allocator::GLOBAL).is_err());
+    }
+}
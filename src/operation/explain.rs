@@ -0,0 +1,110 @@
+//! Operation tree rendering.
+//!
+//! Renders a tree of physical operations (see `Operation::describe()` and
+//! `Operation::inputs()`) as indented text or a Graphviz DOT graph. Logical plans have their own
+//! `LogicalPlan::explain()` and `plan::to_dot()`.
+
+use ::allocator;
+use ::expression::{BoundExpr, Expr};
+use ::projector::SingleSourceProjector;
+use ::schema::Schema;
+
+use super::Operation;
+
+/// Bound expression and its children as a call, eg. "EQUALS(column a, column b)"
+pub fn describe_expr(expr: &BoundExpr) -> String {
+    let children: Vec<String> = expr.children().iter().map(|c| describe_expr(*c)).collect();
+    if children.is_empty() {
+        expr.describe()
+    } else {
+        format!("{}({})", expr.describe(), children.join(", "))
+    }
+}
+
+/// " [predicate ...]" of a predicate pushed down into a scan of a `schema` source
+pub fn describe_predicate<'a>(predicate: &(Expr<'a> + 'a), schema: &Schema) -> String {
+    // Binding is only used for the description
+    match predicate.bind(&allocator::GLOBAL, schema) {
+        Ok(bound) => format!(" [predicate {}]", describe_expr(&*bound)),
+        Err(e) => format!(" [predicate error: {}]", e),
+    }
+}
+
+/// " [projection ...]" of a projection pushed down into a scan, empty without one
+pub fn describe_projection(projection: &Option<SingleSourceProjector>) -> String {
+    match *projection {
+        Some(ref proj) => format!(" [projection {}]", proj.describe()),
+        None => String::new(),
+    }
+}
+
+/// Operation tree, one operation per line with inputs indented under their parent
+pub fn explain<'a, O: Operation<'a> + ?Sized>(op: &O) -> String {
+    fn walk<'a, O: Operation<'a> + ?Sized>(op: &O, depth: usize, out: &mut Vec<String>) {
+        out.push(format!("{}{}", "  ".repeat(depth), op.describe()));
+        for input in op.inputs() {
+            walk(input, depth + 1, out);
+        }
+    }
+
+    let mut lines = Vec::new();
+    walk(op, 0, &mut lines);
+    lines.join("\n")
+}
+
+/// Graphviz DOT digraph of the operation tree, edges flow from input to consumer
+pub fn to_dot<'a, O: Operation<'a> + ?Sized>(op: &O) -> String {
+    fn walk<'a, O: Operation<'a> + ?Sized>(op: &O, parent: Option<usize>, next: &mut usize,
+                                           out: &mut Vec<String>) {
+        let id = *next;
+        *next += 1;
+
+        let label = op.describe().replace('\\', "\\\\").replace('"', "\\\"");
+        out.push(format!("  n{} [shape=box, label=\"{}\"];", id, label));
+        if let Some(parent) = parent {
+            out.push(format!("  n{} -> n{};", id, parent));
+        }
+
+        for input in op.inputs() {
+            walk(input, Some(id), next, out);
+        }
+    }
+
+    let mut out = vec![String::from("digraph operations {"), String::from("  rankdir=BT;")];
+    walk(op, None, &mut 0, &mut out);
+    out.push(String::from("}"));
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use ::allocator;
+    use ::block::Block;
+    use ::expression::column::ColumnExpr;
+    use ::operation::{Limit, Operation, Project, ScanView};
+    use ::projector::{project_by_name, project_renamed};
+    use ::schema::Schema;
+    use ::types::Type;
+
+    // Scans show their pushed down predicate and projection, inputs are indented under their
+    // consumer
+    #[test]
+    fn explain_operations() {
+        let schema = Schema::make_one_attr("a", false, Type::BOOLEAN);
+        let block = Block::new(&allocator::GLOBAL, &schema);
+
+        let scan = ScanView::new(&block, None)
+            .with_predicate(ColumnExpr::named("a"))
+            .with_projection(project_by_name("a"));
+        let op = Limit::new(1, 2, Project::new(project_renamed(&[("a", "b")]), scan));
+
+        let label = "ScanView [predicate column a] [projection a]";
+        assert_eq!(op.explain(), format!("Limit 2 offset 1\n  Project a AS b\n    {}", label));
+
+        let dot = op.explain_dot();
+        assert!(dot.starts_with("digraph operations {\n  rankdir=BT;\n"));
+        assert!(dot.contains(&format!("  n2 [shape=box, label=\"{}\"];", label)));
+        assert!(dot.contains("  n2 -> n1;"));
+        assert!(dot.ends_with("\n}"));
+    }
+}
@@ -0,0 +1,145 @@
+use ::block::RefView;
+use ::error::DBError;
+use ::expression::{BoundExpr, Expr};
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+use super::scan_view::{Selection, bind_predicate};
+
+/// Relational Filter Operation, returns the rows of `src` where the BOOLEAN `predicate` is true
+/// (NULL doesn't match).
+///
+/// Each chunk is a window of consecutive matching rows of an input chunk. A predicate on the rows
+/// of a view is better pushed into its `ScanView` (see `plan::Optimizer`), the filter is for the
+/// rows of other operations.
+pub struct Filter<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub predicate: Box<Expr<'a> + 'a>,
+}
+
+/// Implementation of the `Filter` operation
+struct FilterCursor<'a, 'b: 'a> {
+    input: Box<Cursor<'a> + 'a>,
+    predicate: Box<BoundExpr<'b> + 'a>,
+    /// Input chunk the rows are returned from
+    current: RefView<'a>,
+    selection: Selection,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new<T, P>(predicate: P, src: T) -> Filter<'a>
+        where T: Operation<'a> + 'a, P: Expr<'a> + 'a
+    {
+        Filter { src: Box::new(src), predicate: Box::new(predicate) }
+    }
+}
+
+impl<'a> Operation<'a> for Filter<'a> {
+    fn describe(&self) -> String {
+        String::from("Filter")
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let input = self.src.bind_context(ctx)?;
+        let predicate = bind_predicate(&*self.predicate, ctx, input.schema(), "filter")?;
+
+        Ok(Box::new(FilterCursor {
+            input: input,
+            predicate: predicate,
+            current: RefView::default(),
+            selection: Selection::default(),
+        }))
+    }
+}
+
+impl<'a, 'b> Cursor<'a> for FilterCursor<'a, 'b> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        loop {
+            let predicate = Some(&*self.predicate as &BoundExpr);
+            if let Some(range) = self.selection.next_range(&self.current, predicate, rows)? {
+                self.selection.offset = range.offset + range.rows;
+                return self.current.window(range).map(CursorChunk::Next)
+            }
+
+            match self.input.next(rows)? {
+                CursorChunk::Next(view) => self.current = view,
+                CursorChunk::End        => return Ok(CursorChunk::End),
+            }
+            self.selection = Selection::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_row_data};
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::operation::{Limit, ScanView};
+    use ::schema::Attribute;
+    use ::table::{Table, TableAppender};
+    use ::types::{Int64, Type};
+
+    // Only the matching rows of each input chunk are returned, NULL doesn't match
+    #[test]
+    fn filter_rows() {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: true, dtype: Type::INT64},
+            Attribute{name: "b".to_string(), nullable: false, dtype: Type::INT64},
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 20i64 {
+                appender = if v == 6 {
+                    appender.add_row().set_null(true)
+                } else {
+                    appender.add_row().set(v % 3)
+                };
+                appender = appender.set(v % 2 * (v % 3));
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        let block = table.take().unwrap();
+
+        let equal = EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b"));
+        let op = Filter::new(equal, Limit::new(2, 100, ScanView::new(&block, None)));
+        assert_eq!(op.explain(), "Filter\n  Limit 100 offset 2\n    ScanView");
+
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(4).unwrap() {
+            assert!(view.rows() > 0 && view.rows() <= 4);
+            let rows = column_row_data::<Int64>(view.column(1).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+        let expected: Vec<i64> = (2 .. 20).filter(|v| *v != 6 && (v % 3 == 0 || v % 2 == 1))
+            .map(|v| v % 2 * (v % 3))
+            .collect();
+        assert_eq!(out, expected);
+
+        // Predicate has to be BOOLEAN
+        let op = Filter::new(ColumnExpr::named("a"), ScanView::new(&block, None));
+        match op.bind(&allocator::GLOBAL) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
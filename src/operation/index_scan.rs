@@ -0,0 +1,144 @@
+use ::block::{RefView, View, window_alias};
+use ::error::DBError;
+use ::index::TextIndex;
+use ::projector::{BoundProjector, SingleSourceProjector};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+use ::table::Table;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+use super::explain::describe_projection;
+
+/// Scan of the table rows matching `text_matches(column, query)`, looked up in a `TextIndex` on
+/// the column instead of evaluating the predicate on every row. Chunks alias runs of consecutive
+/// matching rows.
+pub struct TextIndexScan<'a> {
+    pub table: &'a Table<'a>,
+    pub index: &'a TextIndex,
+    pub query: String,
+    pub projection: Option<SingleSourceProjector>,
+}
+
+/// Implementation of the `TextIndexScan` operation
+struct TextIndexScanCursor<'a> {
+    src: RefView<'a>,
+    /// Matching rows, in row order
+    rows: Vec<RowOffset>,
+    /// Next of the matching rows to return
+    pos: usize,
+    projection: Option<BoundProjector>,
+}
+
+impl<'a> TextIndexScan<'a> {
+    pub fn new<S: Into<String>>(table: &'a Table<'a>, index: &'a TextIndex, query: S)
+        -> TextIndexScan<'a>
+    {
+        TextIndexScan { table: table, index: index, query: query.into(), projection: None }
+    }
+
+    /// Only return the `projection` columns
+    pub fn with_projection(mut self, projection: SingleSourceProjector) -> TextIndexScan<'a> {
+        self.projection = Some(projection);
+        self
+    }
+}
+
+impl<'a> Operation<'a> for TextIndexScan<'a> {
+    fn describe(&self) -> String {
+        let key = &self.index.key().name;
+        let out = format!("TextIndexScan text_matches({}, {:?})", key, self.query);
+        out + &describe_projection(&self.projection)
+    }
+
+    fn bind_context<'b: 'a>(&self, _: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let rows = self.index.search(self.table, &self.query)?;
+        let src = window_alias(self.table, None)?;
+
+        let projection = match self.projection {
+            Some(ref proj) => Some(proj.bind(src.schema())?),
+            None => None,
+        };
+
+        Ok(Box::new(TextIndexScanCursor { src: src, rows: rows, pos: 0, projection: projection }))
+    }
+}
+
+impl<'a> Cursor<'a> for TextIndexScanCursor<'a> {
+    fn schema(&self) -> &Schema {
+        match self.projection {
+            Some(ref proj) => &proj.schema,
+            None => self.src.schema(),
+        }
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        if self.pos >= self.rows.len() || rows == 0 {
+            return Ok(CursorChunk::End)
+        }
+
+        let start = self.rows[self.pos];
+        let mut len = 1;
+        while len < rows && self.pos + len < self.rows.len()
+            && self.rows[self.pos + len] == start + len
+        {
+            len += 1;
+        }
+        self.pos += len;
+
+        let sub = self.src.window(RowRange { offset: start, rows: len })?;
+
+        match self.projection {
+            Some(ref proj) => proj.project_ref_view(&sub).map(CursorChunk::Next),
+            None => Ok(CursorChunk::Next(sub)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::projector::project_by_name;
+    use ::schema::Attribute;
+    use ::table::TableAppender;
+    use ::types::{Type, UInt32};
+
+    // Chunks are the runs of consecutive matching rows
+    #[test]
+    fn scan_matching_runs() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "msg".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 10 {
+                let msg = match v { 1 ..= 3 | 7 => "request failed", _ => "ok" };
+                appender = appender.add_row().set(v as u32).set(msg);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let index = TextIndex::build(&table, "msg").unwrap();
+        let op = TextIndexScan::new(&table, &index, "FAILED")
+            .with_projection(project_by_name("id"));
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        assert_eq!(cursor.schema().count(), 1);
+
+        let mut chunks = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            let data = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            chunks.push(data.values[.. view.rows()].to_vec());
+        }
+
+        assert_eq!(chunks, vec![vec![1, 2], vec![3], vec![7]]);
+    }
+}
@@ -0,0 +1,142 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use ::allocator::MemoryTracker;
+use ::block::View;
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Counters of the rows returned by an `Instrument` operation
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct OperatorCounters {
+    pub rows: RowOffset,
+    pub chunks: usize,
+    /// Time spent getting the chunks, including the time spent in the inputs
+    pub elapsed: Duration,
+    /// Most bytes charged to the query's memory tracker when a chunk was returned, 0 without a
+    /// tracker
+    pub peak_memory: usize,
+}
+
+/// Passes the source rows through unchanged, counting them (and the time it took to get them)
+/// into `counters`. Used to collect the metrics of each operation of a query, see
+/// `LogicalPlan::explain_analyze()`. Errors of the source get its description as context.
+pub struct Instrument<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub counters: Rc<Cell<OperatorCounters>>,
+    /// Tracker of the memory used by the query
+    pub memory: Option<&'a MemoryTracker<'a>>,
+}
+
+/// Implementation of the `Instrument` operation
+struct InstrumentCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    counters: Rc<Cell<OperatorCounters>>,
+    memory: Option<&'a MemoryTracker<'a>>,
+    operator: String,
+}
+
+impl<'a> Instrument<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T, counters: Rc<Cell<OperatorCounters>>)
+        -> Instrument<'a>
+    {
+        Instrument { src: Box::new(src), counters: counters, memory: None }
+    }
+
+    /// Also sample the memory charged to `memory`
+    pub fn with_memory(mut self, memory: &'a MemoryTracker<'a>) -> Instrument<'a> {
+        self.memory = Some(memory);
+        self
+    }
+}
+
+impl<'a> Operation<'a> for Instrument<'a> {
+    fn describe(&self) -> String {
+        String::from("Instrument")
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let operator = self.src.describe();
+        let input = match self.src.bind_context(ctx) {
+            Ok(input) => input,
+            Err(e) => return Err(e.in_operator(operator)),
+        };
+        Ok(Box::new(InstrumentCursor {
+            input: input,
+            counters: self.counters.clone(),
+            memory: self.memory.or(ctx.memory),
+            operator: operator,
+        }))
+    }
+}
+
+impl<'a> Cursor<'a> for InstrumentCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let start = Instant::now();
+        let operator = &self.operator;
+        let chunk = self.input.next(rows).map_err(|e| e.in_operator(operator.as_str()))?;
+
+        let mut counters = self.counters.get();
+        counters.elapsed += start.elapsed();
+
+        if let CursorChunk::Next(ref view) = chunk {
+            counters.rows += view.rows();
+            counters.chunks += 1;
+            if let Some(memory) = self.memory {
+                counters.peak_memory = counters.peak_memory.max(memory.used());
+            }
+        }
+
+        self.counters.set(counters);
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator::{self, Allocator};
+    use ::block::Block;
+    use ::operation::{Limit, ScanView};
+    use ::types::{Type, Value};
+
+    // Counts the rows and chunks passing through, memory is sampled from the tracker
+    #[test]
+    fn instrument_counts() {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 10 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+
+        let scan = Rc::new(Cell::new(OperatorCounters::default()));
+        let limit = Rc::new(Cell::new(OperatorCounters::default()));
+        let memory = MemoryTracker::new(&allocator::GLOBAL, "query", None);
+        let _held = memory.allocate(100).unwrap();
+
+        let op = Instrument::new(ScanView::new(&block, None), scan.clone());
+        let op = Instrument::new(Limit::new(2, 5, op), limit.clone()).with_memory(&memory);
+
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        while let CursorChunk::Next(_) = cursor.next(3).unwrap() {}
+
+        assert_eq!((scan.get().rows, scan.get().chunks, scan.get().peak_memory), (7, 3, 0));
+        assert_eq!((limit.get().rows, limit.get().chunks), (5, 2));
+        assert!(limit.get().peak_memory >= 100);
+        assert!(limit.get().elapsed >= scan.get().elapsed);
+    }
+}
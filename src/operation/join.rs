@@ -0,0 +1,251 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::compare::compare_keys;
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::Value;
+use ::util::hash::hash_rows;
+
+use super::{BlocksCursor, Cursor, CursorChunk, ExecutionContext, Operation};
+
+/// Relational inner equi-join Operation, returns the `left` rows with each of the `right` rows
+/// whose `on` (left, right) attributes are equal. NULL keys don't match.
+///
+/// The right rows are read when the operation is bound, into a hash table of their keys (see
+/// `util::hash`). Left rows are looked up as they're read, each left chunk is joined into a new
+/// block (see `BlocksCursor`). The output is the left attributes followed by the right ones.
+pub struct HashJoin<'a> {
+    pub left: Box<Operation<'a> + 'a>,
+    pub right: Box<Operation<'a> + 'a>,
+    pub on: Vec<(String, String)>,
+}
+
+/// Joined blocks of a `HashJoin`, one for each left chunk with matches
+struct HashJoinBlocks<'a> {
+    left: Box<Cursor<'a> + 'a>,
+    left_keys: Vec<usize>,
+    right: Block<'a>,
+    right_keys: Vec<usize>,
+    /// Right rows of each key hash
+    index: HashMap<u64, Vec<RowOffset>>,
+    schema: Schema,
+    alloc: &'a Allocator,
+    fetch: RowOffset,
+}
+
+impl<'a> HashJoin<'a> {
+    pub fn new<L, R>(left: L, right: R, on: &[(&str, &str)]) -> HashJoin<'a>
+        where L: Operation<'a> + 'a, R: Operation<'a> + 'a
+    {
+        HashJoin {
+            left: Box::new(left),
+            right: Box::new(right),
+            on: on.iter().map(|k| (k.0.to_string(), k.1.to_string())).collect(),
+        }
+    }
+}
+
+impl<'a> Operation<'a> for HashJoin<'a> {
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.on.iter().map(|k| format!("{} = {}", k.0, k.1)).collect();
+        format!("HashJoin on {}", keys.join(", "))
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.left, &*self.right]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let left = self.left.bind_context(ctx)?;
+        let mut right = self.right.bind_context(ctx)?;
+
+        let (left_schema, right_schema) = (left.schema().clone(), right.schema().clone());
+        let mut left_keys = Vec::with_capacity(self.on.len());
+        let mut right_keys = Vec::with_capacity(self.on.len());
+        for key in &self.on {
+            let (lpos, rpos) = (left_schema.exists_ok(&key.0)?, right_schema.exists_ok(&key.1)?);
+            if left_schema[lpos].dtype != right_schema[rpos].dtype {
+                return Err(DBError::AttributeType(format!("{} = {}", key.0, key.1)))
+            }
+            left_keys.push(lpos);
+            right_keys.push(rpos);
+        }
+
+        let mut attrs: Vec<Attribute> = left_schema.iter().cloned().collect();
+        attrs.extend(right_schema.iter().cloned());
+        let schema = Schema::from_vec(attrs)?;
+
+        let fetch = ctx.batch_rows(&right_schema);
+        let mut rows = Table::new(ctx.allocator, &right_schema, None);
+        while let CursorChunk::Next(view) = right.next(fetch)? {
+            rows.append_block(&view)?;
+        }
+        let rows = rows.take().unwrap();
+
+        let mut index: HashMap<u64, Vec<RowOffset>> = HashMap::new();
+        for (row, hash) in hash_rows(&rows, &right_keys, 0)?.into_iter().enumerate() {
+            if !has_null(&rows, &right_keys, row)? {
+                index.entry(hash).or_insert_with(Vec::new).push(row);
+            }
+        }
+
+        let blocks = HashJoinBlocks {
+            left: left,
+            left_keys: left_keys,
+            right: rows,
+            right_keys: right_keys,
+            index: index,
+            schema: schema.clone(),
+            alloc: ctx.allocator,
+            fetch: ctx.batch_rows(&left_schema),
+        };
+        Ok(Box::new(BlocksCursor::reading(schema, blocks)))
+    }
+}
+
+/// One of the `keys` of the row is NULL
+fn has_null<'v>(view: &'v View<'v>, keys: &[usize], row: RowOffset) -> Result<bool, DBError> {
+    for &pos in keys {
+        if column_value(view.column(pos).unwrap(), row)? == Value::NULL {
+            return Ok(true)
+        }
+    }
+    Ok(false)
+}
+
+impl<'a> HashJoinBlocks<'a> {
+    /// Joined rows of the next left chunk with matches, `None` once the left input has no more
+    /// rows
+    fn join_next(&mut self) -> Result<Option<Block<'a>>, DBError> {
+        while let CursorChunk::Next(view) = self.left.next(self.fetch)? {
+            let left_count = view.schema().count();
+            let mut out = Table::new(self.alloc, &self.schema, None);
+
+            for (row, hash) in hash_rows(&view, &self.left_keys, 0)?.into_iter().enumerate() {
+                let matches = match self.index.get(&hash) {
+                    Some(matches) => matches,
+                    None => continue,
+                };
+                if has_null(&view, &self.left_keys, row)? {
+                    continue
+                }
+
+                for &found in matches {
+                    let mut equal = true;
+                    for (&l, &r) in self.left_keys.iter().zip(&self.right_keys) {
+                        let lhs = column_value(view.column(l).unwrap(), row)?;
+                        let rhs = column_value(self.right.column(r).unwrap(), found)?;
+                        equal &= compare_keys(&lhs, &rhs) == Ordering::Equal;
+                    }
+                    if !equal {
+                        continue
+                    }
+
+                    let out_row = out.add_row()?;
+                    for pos in 0 .. left_count {
+                        out.set(pos, out_row, column_value(view.column(pos).unwrap(), row)?)?;
+                    }
+                    for pos in 0 .. self.right.schema().count() {
+                        let value = column_value(self.right.column(pos).unwrap(), found)?;
+                        out.set(left_count + pos, out_row, value)?;
+                    }
+                }
+            }
+
+            if out.rows() > 0 {
+                return Ok(out.take())
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for HashJoinBlocks<'a> {
+    type Item = Result<Block<'a>, DBError>;
+
+    fn next(&mut self) -> Option<Result<Block<'a>, DBError>> {
+        match self.join_next() {
+            Ok(block) => block.map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::ScanView;
+    use ::table::TableAppender;
+    use ::types::{Int64, Type};
+
+    fn make_block<'a>(attrs: &[(&str, Type)], rows: &[(Option<i64>, i64)]) -> Block<'a> {
+        let attrs = attrs.iter()
+            .map(|&(name, ref dtype)| Attribute {
+                name: name.to_string(), nullable: true, dtype: dtype.clone(),
+            })
+            .collect();
+        let mut table = Table::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap(), None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for &(key, value) in rows {
+                appender = match key {
+                    Some(key) => appender.add_row().set(key),
+                    None => appender.add_row().set_null(true),
+                };
+                appender = appender.set(value);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        table.take().unwrap()
+    }
+
+    // Each left row with each matching right row, in left order
+    #[test]
+    fn hash_join() {
+        let orders = make_block(&[("customer", Type::INT64), ("total", Type::INT64)], &[
+            (Some(1), 10), (Some(2), 20), (None, 30), (Some(1), 40), (Some(3), 50),
+        ]);
+        let customers = make_block(&[("id", Type::INT64), ("name", Type::INT64)], &[
+            (Some(1), 100), (Some(2), 200), (None, 300), (Some(2), 201),
+        ]);
+
+        let op = HashJoin::new(ScanView::new(&orders, None), ScanView::new(&customers, None),
+                               &[("customer", "id")]);
+        assert_eq!(op.explain(), "HashJoin on customer = id\n  ScanView\n  ScanView");
+
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_batch_bytes(1);
+        let mut cursor = op.bind_context(&ctx).unwrap();
+        assert_eq!(cursor.schema().count(), 4);
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(1024).unwrap() {
+            let totals = column_row_data::<Int64>(view.column(1).unwrap()).unwrap();
+            let names = column_row_data::<Int64>(view.column(3).unwrap()).unwrap();
+            for row in 0 .. view.rows() {
+                out.push((totals.values[row], names.values[row]));
+            }
+        }
+        assert_eq!(out, vec![(10, 100), (20, 200), (20, 201), (40, 100)]);
+
+        let prices = make_block(&[("id", Type::INT32), ("price", Type::INT64)], &[]);
+        let op = HashJoin::new(ScanView::new(&orders, None), ScanView::new(&prices, None),
+                               &[("customer", "id")]);
+        match op.bind(&allocator::GLOBAL) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
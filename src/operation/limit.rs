@@ -0,0 +1,79 @@
+use std::cmp::min;
+
+use ::block::View;
+use ::error::DBError;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Relational Limit Operation, skips the first `offset` rows and returns at most `count` rows.
+pub struct Limit<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub offset: RowOffset,
+    pub count: RowOffset,
+}
+
+/// Implementation of the `Limit` operation
+struct LimitCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    /// Rows left to skip
+    skip: RowOffset,
+    /// Rows left to return
+    left: RowOffset,
+}
+
+impl<'a> Limit<'a> {
+    pub fn new<T: Operation<'a> + 'a>(offset: RowOffset, count: RowOffset, src: T) -> Limit<'a> {
+        Limit { src: Box::new(src), offset: offset, count: count }
+    }
+}
+
+impl<'a> Operation<'a> for Limit<'a> {
+    fn describe(&self) -> String {
+        format!("Limit {} offset {}", self.count, self.offset)
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let input = self.src.bind_context(ctx)?;
+        Ok(Box::new(LimitCursor { input: input, skip: self.offset, left: self.count }))
+    }
+}
+
+impl<'a> Cursor<'a> for LimitCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        loop {
+            if self.left == 0 {
+                return Ok(CursorChunk::End)
+            }
+
+            let fetch = if self.skip > 0 { self.skip } else { min(rows, self.left) };
+            let view = match self.input.next(fetch)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End => return Ok(CursorChunk::End),
+            };
+
+            let available = view.rows();
+            if self.skip >= available {
+                self.skip -= available;
+                continue
+            }
+
+            let range = RowRange { offset: self.skip, rows: min(available - self.skip, self.left) };
+            self.skip = 0;
+            self.left -= range.rows;
+
+            return view.window(range).map(CursorChunk::Next)
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::cmp::min;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, window_alias};
+use ::error::DBError;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext, DEFAULT_CURSOR_FETCH, RechunkBlocks};
+
+/// Drains its source once into in-memory blocks of `block_rows` rows (see `Rechunk`), which can
+/// then be scanned any number of times (`Materialized::scan()`), eg. for a subtree feeding both
+/// sides of a join or the iterations of a loop.
+///
+/// The blocks are allocated from the `execute` allocator, memory limits of a `MemoryTracker`
+/// apply. To spill a large result, write it to a table file (see `storage::write_view`) and
+/// scan that with `ScanFile`.
+pub struct Materialize<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub block_rows: RowOffset,
+}
+
+/// Rows of a `Materialize` operation
+pub struct Materialized<'a> {
+    schema: Schema,
+    blocks: Vec<Block<'a>>,
+}
+
+/// Scan of `Materialized` rows
+pub struct MaterializedScan<'a> {
+    pub src: &'a Materialized<'a>,
+}
+
+/// Implementation of the `MaterializedScan` operation
+struct MaterializedScanCursor<'a> {
+    src: &'a Materialized<'a>,
+    block: usize,
+    offset: RowOffset,
+}
+
+impl<'a> Materialize<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T) -> Materialize<'a> {
+        Materialize { src: Box::new(src), block_rows: DEFAULT_CURSOR_FETCH }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Materialized<'a>, DBError> {
+        if self.block_rows == 0 {
+            return Err(DBError::ValueOutOfRange("materialize to blocks of 0 rows".to_string()))
+        }
+
+        let blocks = RechunkBlocks::new(self.src.bind(alloc)?, alloc, self.block_rows);
+        let schema = blocks.schema().clone();
+
+        let blocks = blocks.collect::<Result<Vec<_>, DBError>>()?;
+        Ok(Materialized { schema: schema, blocks: blocks })
+    }
+}
+
+impl<'a> Materialized<'a> {
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn rows(&self) -> RowOffset {
+        self.blocks.iter().map(|b| b.rows()).sum()
+    }
+
+    pub fn blocks(&self) -> &[Block<'a>] {
+        &self.blocks
+    }
+
+    /// Operation returning the rows, each chunk aliases (a window of) one of the blocks
+    pub fn scan(&'a self) -> MaterializedScan<'a> {
+        MaterializedScan { src: self }
+    }
+}
+
+impl<'a> Operation<'a> for MaterializedScan<'a> {
+    fn bind_context<'b: 'a>(&self, _: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        Ok(Box::new(MaterializedScanCursor { src: self.src, block: 0, offset: 0 }))
+    }
+
+    fn describe(&self) -> String {
+        format!("MaterializedScan {} rows in {} blocks", self.src.rows(), self.src.blocks.len())
+    }
+}
+
+impl<'a> Cursor<'a> for MaterializedScanCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.src.schema
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let src = self.src;
+
+        while let Some(block) = src.blocks.get(self.block) {
+            let left = block.rows() - self.offset;
+            if left == 0 {
+                self.block += 1;
+                self.offset = 0;
+                continue
+            }
+
+            let range = RowRange { offset: self.offset, rows: min(left, rows) };
+            self.offset += range.rows;
+            return window_alias(block, Some(range)).map(CursorChunk::Next)
+        }
+
+        Ok(CursorChunk::End)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::{Instrument, OperatorCounters, ScanView};
+    use ::types::{Int64, Type, Value};
+
+    /// Values of the scanned rows
+    fn scanned(op: &MaterializedScan, fetch: RowOffset) -> Vec<i64> {
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut out = Vec::new();
+
+        while let CursorChunk::Next(view) = cursor.next(fetch).unwrap() {
+            let data = column_row_data::<Int64>(view.column(0).unwrap()).unwrap();
+            out.extend_from_slice(&data.values[.. view.rows()]);
+        }
+        out
+    }
+
+    // The source is read once, the rows can be scanned again (and by two cursors at a time)
+    #[test]
+    fn rescan_materialized() {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 10 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+
+        let counters = Rc::new(Cell::new(OperatorCounters::default()));
+        let scan = Instrument::new(ScanView::new(&block, None), counters.clone());
+        let mut op = Materialize::new(scan);
+        op.block_rows = 4;
+
+        let rows = op.execute(&allocator::GLOBAL).unwrap();
+        assert_eq!(rows.rows(), 10);
+        assert_eq!(rows.blocks().iter().map(|b| b.rows()).collect::<Vec<_>>(), vec![4, 4, 2]);
+        let chunks = counters.get().chunks;
+
+        let scan = rows.scan();
+        let all: Vec<i64> = (0 .. 10).collect();
+        assert_eq!(scanned(&scan, 3), all);
+
+        let mut left = scan.bind(&allocator::GLOBAL).unwrap();
+        assert_eq!(scanned(&rows.scan(), 1024), all);
+        match left.next(1024).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 4),
+            CursorChunk::End => assert!(false, "Expected rows"),
+        }
+        assert_eq!(counters.get().chunks, chunks);
+
+        let empty = Block::new(&allocator::GLOBAL, &schema);
+        let rows = Materialize::new(ScanView::new(&empty, None)).execute(&allocator::GLOBAL)
+            .unwrap();
+        assert_eq!(rows.schema().get(0).unwrap().name, "v");
+        assert!(scanned(&rows.scan(), 1024).is_empty());
+    }
+}
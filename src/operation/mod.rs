@@ -5,8 +5,8 @@ use super::block::RefView;
 use super::row::RowOffset;
 use super::schema::Schema;
 
-#[allow(dead_code)]
-const DEFAULT_CURSOR_FETCH : RowOffset = 1024;
+/// Default number of rows to fetch from a `Cursor` at a time
+pub const DEFAULT_CURSOR_FETCH : RowOffset = 1024;
 
 /// Next series of `Cursor` data
 pub enum CursorChunk<'a> {
@@ -20,12 +20,13 @@ pub enum CursorChunk<'a> {
 
 /// Materialized operation cursor stream results from previous operations.
 ///
-/// A cursor know it output and (optionally) input schema.
+/// A cursor know it output and (optionally) input schema. Chunks can alias data owned by the
+/// cursor (see `BlocksCursor`), they're not to be used once the cursor is dropped.
 pub trait Cursor<'a> {
     fn schema(&self) -> &Schema;
 
     // Can't quite be an iterator, we can want different batch sizes in subsequent calls
-    fn next(&'a mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError>;
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError>;
 }
 
 /// `Operation` is the basic building model of a query.
@@ -34,14 +35,91 @@ pub trait Cursor<'a> {
 /// one relational Operation into another.
 pub trait Operation<'a> {
 
-    /// Convert operation AST a bound Cursor
+    /// Convert operation AST a bound Cursor, configured by the query's context. Inputs are bound
+    /// with the same context.
     // TODO: Tell bind if we want to shuffle GPU data or memory data
-    fn bind<'b: 'a>(&self, &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError>;
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>;
+
+    /// Bind with the default configuration (`ExecutionContext::new()`)
+    fn bind<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        self.bind_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Short description of the operation (not including its inputs), eg. with its pushed down
+    /// predicate and projected columns
+    fn describe(&self) -> String {
+        String::from("Operation")
+    }
+
+    /// Input operations
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        Vec::new()
+    }
+
+    /// The operation tree, one operation per line with inputs indented under their parent
+    fn explain(&self) -> String {
+        explain::explain(self)
+    }
+
+    /// Graphviz DOT digraph of the operation tree
+    fn explain_dot(&self) -> String {
+        explain::to_dot(self)
+    }
 }
 
+pub mod context;
 pub mod scan_view;
+#[cfg(all(feature = "storage", unix))]
+pub mod scan_file;
+pub mod index_scan;
 pub mod project;
+pub mod filter;
+pub mod aggregate;
+pub mod join;
+pub mod limit;
+pub mod blocks;
+pub mod rechunk;
+pub mod materialize;
+pub mod explain;
+pub mod instrument;
+pub mod progress;
+pub mod retry;
+pub mod throttle;
+pub mod sample;
+pub mod sort;
+pub mod diff;
+pub mod cancel;
+pub mod checksum;
+pub mod range;
+pub mod date_series;
+pub mod values;
+pub mod window;
 
+pub use self::context::ExecutionContext;
 pub use self::scan_view::ScanView;
+#[cfg(all(feature = "storage", unix))]
+pub use self::scan_file::ScanFile;
+pub use self::index_scan::TextIndexScan;
 pub use self::project::Project;
+pub use self::filter::Filter;
+pub use self::aggregate::HashAggregate;
+pub use self::join::HashJoin;
+pub use self::limit::Limit;
+pub use self::blocks::BlocksCursor;
+pub use self::rechunk::{Rechunk, RechunkBlocks};
+pub use self::materialize::{Materialize, Materialized, MaterializedScan};
+pub use self::instrument::{Instrument, OperatorCounters};
+pub use self::progress::{Progress, ProgressReport};
+pub use self::retry::{Retry, RetryPolicy};
+pub use self::throttle::Throttle;
+pub use self::sample::{Sample, SampleMethod};
+pub use self::sort::{NanOrder, Sort};
+pub use self::diff::{Change, Diff};
+pub use self::cancel::{Cancellable, CancellationToken};
+pub use self::checksum::Checksum;
+pub use self::range::Range;
+pub use self::date_series::DateSeries;
+pub use self::values::Values;
+pub use self::window::{WindowAggregate, WindowFunc};
 
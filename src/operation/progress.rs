@@ -0,0 +1,171 @@
+use std::rc::Rc;
+
+use ::block::View;
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Progress of the rows flowing through a `Progress` operation
+#[derive(Clone, Debug)]
+pub struct ProgressReport<'r> {
+    /// Label of the reporting operation
+    pub operation: &'r str,
+    pub chunks: usize,
+    pub rows: RowOffset,
+    /// Estimated from the fixed width of the attributes, VARLEN data is not counted
+    pub bytes: usize,
+    /// The source is exhausted, this is the last report
+    pub done: bool,
+}
+
+/// Passes the source rows through unchanged, calling `callback` every `every` chunks and once
+/// the source is exhausted. Used to show progress of long running queries (eg. in a CLI).
+pub struct Progress<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub label: String,
+    pub every: usize,
+    pub callback: Rc<Fn(&ProgressReport) + 'a>,
+}
+
+/// Implementation of the `Progress` operation
+struct ProgressCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    label: String,
+    every: usize,
+    callback: Rc<Fn(&ProgressReport) + 'a>,
+    row_width: usize,
+    chunks: usize,
+    rows: RowOffset,
+    done: bool,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new<T, S, F>(label: S, every: usize, callback: F, src: T) -> Progress<'a>
+        where T: Operation<'a> + 'a, S: Into<String>, F: Fn(&ProgressReport) + 'a
+    {
+        Progress {
+            src: Box::new(src),
+            label: label.into(),
+            every: every.max(1),
+            callback: Rc::new(callback),
+        }
+    }
+}
+
+impl<'a> Operation<'a> for Progress<'a> {
+    fn describe(&self) -> String {
+        format!("Progress {} every {} chunks", self.label, self.every)
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let input = self.src.bind_context(ctx)?;
+        let row_width = input.schema().iter().map(|a| a.dtype.size_of()).sum();
+
+        Ok(Box::new(ProgressCursor {
+            input: input,
+            label: self.label.clone(),
+            every: self.every,
+            callback: self.callback.clone(),
+            row_width: row_width,
+            chunks: 0,
+            rows: 0,
+            done: false,
+        }))
+    }
+}
+
+impl<'a> ProgressCursor<'a> {
+    fn report(&self) {
+        (self.callback)(&ProgressReport {
+            operation: &self.label,
+            chunks: self.chunks,
+            rows: self.rows,
+            bytes: self.rows * self.row_width,
+            done: self.done,
+        });
+    }
+}
+
+impl<'a> Cursor<'a> for ProgressCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let chunk = self.input.next(rows)?;
+
+        match chunk {
+            CursorChunk::Next(ref view) => {
+                self.chunks += 1;
+                self.rows += view.rows();
+
+                if self.chunks % self.every == 0 {
+                    self.report();
+                }
+            },
+            // Only report the end once, even if the consumer keeps asking
+            CursorChunk::End if !self.done => {
+                self.done = true;
+                self.report();
+            },
+            CursorChunk::End => (),
+        }
+
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use ::allocator;
+    use ::operation::ScanView;
+    use ::table::{Table, TableAppender};
+    use ::types::Type;
+
+    #[test]
+    fn reports_every_n_chunks() {
+        let schema = Schema::make_one_attr("v", false, Type::UINT64);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 50 {
+                appender = appender.add_row().set(v as u64);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let reports = RefCell::new(Vec::new());
+
+        {
+            let callback = |r: &ProgressReport| {
+                reports.borrow_mut().push((r.operation.to_string(), r.rows, r.bytes, r.done));
+            };
+
+            let op = Progress::new("scan", 2, callback, ScanView::new(&block, None));
+            let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+            while let CursorChunk::Next(_) = cursor.next(10).unwrap() {}
+        }
+
+        // 5 chunks of 10 rows, every other chunk and at the end
+        let expect = vec![
+            ("scan".to_string(), 20, 160, false),
+            ("scan".to_string(), 40, 320, false),
+            ("scan".to_string(), 50, 400, true),
+        ];
+        assert_eq!(*reports.borrow(), expect);
+    }
+}
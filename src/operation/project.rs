@@ -1,14 +1,10 @@
-use std::mem::replace;
-
-use ::allocator::Allocator;
-use ::block::RefView;
 use ::error::DBError;
 use ::row::RowOffset;
 use ::schema::Schema;
 
 use ::projector::*;
 
-use super::{Operation, Cursor, CursorChunk};
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
 
 /// Relational Project Operation
 pub struct Project<'a> {
@@ -20,7 +16,6 @@ pub struct Project<'a> {
 struct ProjectCursor<'a> {
     input: Box<Cursor<'a> + 'a>,
     proj: BoundProjector,
-    _next: RefView<'a>,
 }
 
 impl<'a> Project<'a> {
@@ -30,8 +25,18 @@ impl<'a> Project<'a> {
 }
 
 impl<'a> Operation<'a> for Project<'a> {
-    fn bind<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError> {
-        let boxed = self.src.bind(alloc)?;
+    fn describe(&self) -> String {
+        format!("Project {}", self.proj.describe())
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let boxed = self.src.bind_context(ctx)?;
 
         let proj = {
             let cursor = &*boxed;
@@ -39,7 +44,7 @@ impl<'a> Operation<'a> for Project<'a> {
             self.proj.bind(schema)?
         };
 
-        let out = Box::new(ProjectCursor {input: boxed, proj: proj, _next: Default::default()});
+        let out = Box::new(ProjectCursor {input: boxed, proj: proj});
         Ok(out)
     }
 }
@@ -49,13 +54,12 @@ impl<'a> Cursor<'a> for ProjectCursor<'a> {
         &self.proj.schema
     }
 
-    fn next(&'a mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
         let next_chunk = self.input.as_mut()
             .next(rows)?;
 
         if let CursorChunk::Next(src) = next_chunk {
-            replace(&mut self._next, src);
-            self.proj.project_view(&self._next)
+            self.proj.project_ref_view(&src)
                 .map(|v| CursorChunk::Next(v))
         } else {
             Ok(next_chunk)
@@ -118,4 +122,34 @@ mod tests {
             assert_eq!(cursor_schema.get(1).unwrap().name, "two", "Bad cursor schema");
         }
     }
+
+    // Projection by (renamed) names, unknown names suggest the similar attributes
+    #[test]
+    fn project_names_renamed() {
+        let attrs = vec![
+            Attribute{name: "user_id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "ts".to_string(), nullable: false, dtype: Type::TIMESTAMP},
+        ];
+        let table = Table::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap(), None);
+
+        let proj = BuildSingleSourceProjector::new()
+            .add(project_names(&["ts"]))
+            .add(project_renamed(&[("user_id", "uid")]))
+            .done();
+        let cursor = Project::new(proj, ScanView::new(&table, None))
+            .bind(&allocator::GLOBAL)
+            .unwrap();
+        assert_eq!(cursor.schema()[0].name, "ts");
+        assert_eq!(cursor.schema()[1].name, "uid");
+
+        let proj = project_names(&["ts", "userid"]);
+        match Project::new(proj, ScanView::new(&table, None)).bind(&allocator::GLOBAL) {
+            Err(DBError::AttributeUnknown(ref name, ref similar)) => {
+                assert_eq!(name, "userid");
+                assert_eq!(similar, &vec!["user_id".to_string()]);
+            },
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
 }
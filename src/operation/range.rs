@@ -0,0 +1,96 @@
+use ::allocator::Allocator;
+use ::block::Block;
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::types::Type;
+use ::util::copy_value::ValueSetter;
+
+/// Generated INT64 "value" column of `start`, `start + step`, ... up to (excluding) `end`. A
+/// negative `step` counts down to `end`.
+///
+/// The rows are generated into a new block by `execute`, which can be scanned (and joined) like
+/// any other view.
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, step: i64) -> Range {
+        Range { start: start, end: end, step: step }
+    }
+
+    /// Number of generated rows
+    pub fn rows(&self) -> RowOffset {
+        // Difference as unsigned, it doesn't fit in an i64 for large ranges
+        let span = |from: i64, to: i64| (to as u64).wrapping_sub(from as u64);
+
+        if self.step > 0 && self.end > self.start {
+            ((span(self.start, self.end) - 1) / self.step as u64 + 1) as RowOffset
+        } else if self.step < 0 && self.end < self.start {
+            ((span(self.end, self.start) - 1) / self.step.wrapping_neg() as u64 + 1) as RowOffset
+        } else {
+            0
+        }
+    }
+
+    pub fn execute<'b>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        if self.step == 0 {
+            return Err(DBError::ValueOutOfRange(String::from("Range step of 0")))
+        }
+
+        let rows = self.rows();
+        let schema = Schema::make_one_attr("value", false, Type::INT64);
+
+        let mut out = Block::new(alloc, &schema);
+        out.add_rows(rows)?;
+
+        {
+            let col = out.column_mut(0).unwrap();
+            let mut value = self.start;
+
+            for row in 0 .. rows {
+                value.set_row(col, row)?;
+                value = value.wrapping_add(self.step);
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_row_data};
+    use ::types::Int64;
+
+    fn values(range: Range) -> Vec<i64> {
+        let block = range.execute(&allocator::GLOBAL).unwrap();
+        let data = column_row_data::<Int64>(block.column(0).unwrap()).unwrap();
+        data.values[.. block.rows()].to_vec()
+    }
+
+    // End is excluded, steps count up or down, empty ranges have no rows
+    #[test]
+    fn range_values() {
+        assert_eq!(values(Range::new(0, 5, 1)), vec![0, 1, 2, 3, 4]);
+        assert_eq!(values(Range::new(0, 10, 3)), vec![0, 3, 6, 9]);
+        assert_eq!(values(Range::new(5, -5, -4)), vec![5, 1, -3]);
+        assert_eq!(values(Range::new(5, 5, 1)), Vec::<i64>::new());
+        assert_eq!(values(Range::new(0, 5, -1)), Vec::<i64>::new());
+
+        let max = i64::max_value();
+        assert_eq!(values(Range::new(max - 2, max, 5)), vec![max - 2]);
+        assert_eq!(Range::new(i64::min_value(), max, 1 << 62).rows(), 4);
+
+        match Range::new(0, 5, 0).execute(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
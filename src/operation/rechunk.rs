@@ -0,0 +1,234 @@
+use ::allocator::Allocator;
+use ::block::{Block, View};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::table::Table;
+
+use super::{BlocksCursor, Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Coalesces the (often small) chunks of its input into blocks of `rows` rows, so vectorized
+/// kernels downstream (eg. of a filter only keeping a few rows per chunk) work on full blocks.
+///
+/// `blocks` returns the blocks one at a time, every block but the last one has exactly `rows`
+/// rows. The rows are copied into the blocks, encoded input columns are decoded.
+///
+/// Bound as an operation, the cursor returns the blocks as they're filled (split when fetching
+/// fewer rows). It keeps the blocks it returned until it's dropped, see `BlocksCursor`.
+pub struct Rechunk<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub rows: RowOffset,
+}
+
+/// Blocks of a `Rechunk` operation
+pub struct RechunkBlocks<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    alloc: &'a Allocator,
+    rows: RowOffset,
+    /// Input reached its end (or failed)
+    done: bool,
+}
+
+impl<'a> Rechunk<'a> {
+    pub fn new<T: Operation<'a> + 'a>(rows: RowOffset, src: T) -> Rechunk<'a> {
+        Rechunk { src: Box::new(src), rows: rows }
+    }
+
+    pub fn blocks<'b: 'a>(&self, alloc: &'b Allocator) -> Result<RechunkBlocks<'a>, DBError> {
+        if self.rows == 0 {
+            return Err(DBError::ValueOutOfRange("rechunk to blocks of 0 rows".to_string()))
+        }
+
+        let input = self.src.bind(alloc)?;
+        Ok(RechunkBlocks::new(input, alloc, self.rows))
+    }
+}
+
+impl<'a> Operation<'a> for Rechunk<'a> {
+    fn describe(&self) -> String {
+        format!("Rechunk {} rows", self.rows)
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        if self.rows == 0 {
+            return Err(DBError::ValueOutOfRange("rechunk to blocks of 0 rows".to_string()))
+        }
+
+        let blocks = RechunkBlocks::new(self.src.bind_context(ctx)?, ctx.allocator, self.rows);
+        let schema = blocks.schema().clone();
+        Ok(Box::new(BlocksCursor::reading(schema, blocks)))
+    }
+}
+
+impl<'a> RechunkBlocks<'a> {
+    /// Blocks of `rows` (> 0) rows of the `input` cursor rows
+    pub fn new(input: Box<Cursor<'a> + 'a>, alloc: &'a Allocator, rows: RowOffset)
+        -> RechunkBlocks<'a>
+    {
+        RechunkBlocks { input: input, alloc: alloc, rows: rows, done: false }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    /// Next block of rows, `None` once the input has no more rows
+    fn fill(&mut self) -> Result<Option<Block<'a>>, DBError> {
+        let mut out = Table::new(self.alloc, self.input.schema(), Some(self.rows));
+
+        while out.rows() < self.rows {
+            match self.input.next(self.rows - out.rows())? {
+                CursorChunk::Next(view) => out.append_block(&view)?,
+                CursorChunk::End        => {
+                    self.done = true;
+                    break
+                },
+            }
+        }
+
+        if out.rows() == 0 {
+            return Ok(None)
+        }
+
+        Ok(out.take())
+    }
+}
+
+impl<'a> Iterator for RechunkBlocks<'a> {
+    type Item = Result<Block<'a>, DBError>;
+
+    fn next(&mut self) -> Option<Result<Block<'a>, DBError>> {
+        if self.done {
+            return None
+        }
+
+        match self.fill() {
+            Ok(block) => block.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::{ExecutionContext, ScanView};
+    use ::types::{Type, Value};
+
+    /// Cursor returning at most `max` rows at a time
+    struct Small<'a> {
+        input: Box<Cursor<'a> + 'a>,
+        max: RowOffset,
+    }
+
+    struct SmallSource<'a> {
+        src: ScanView<'a>,
+        max: RowOffset,
+    }
+
+    impl<'a> Operation<'a> for SmallSource<'a> {
+        fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+            -> Result<Box<Cursor<'a> + 'a>, DBError>
+        {
+            let input = self.src.bind_context(ctx)?;
+            Ok(Box::new(Small { input: input, max: self.max }))
+        }
+    }
+
+    impl<'a> Cursor<'a> for Small<'a> {
+        fn schema(&self) -> &Schema {
+            self.input.schema()
+        }
+
+        fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+            self.input.next(rows.min(self.max))
+        }
+    }
+
+    // Small chunks are coalesced into full blocks, the last block has the rest of the rows
+    #[test]
+    fn rechunk_blocks() {
+        let schema = Schema::make_one_attr("v", true, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 10 {
+            let value = if v == 4 { Value::NULL } else { Value::INT64(v) };
+            block.append_row(&[value]).unwrap();
+        }
+
+        let op = Rechunk::new(4, SmallSource { src: ScanView::new(&block, None), max: 3 });
+        let blocks = op.blocks(&allocator::GLOBAL).unwrap()
+            .collect::<Result<Vec<_>, DBError>>().unwrap();
+
+        assert_eq!(blocks.iter().map(|b| b.rows()).collect::<Vec<_>>(), vec![4, 4, 2]);
+        assert!(blocks[1].value(0, 0).unwrap() == Value::NULL);
+        assert!(blocks[2].value(1, 0).unwrap() == Value::INT64(9));
+
+        let refs: Vec<&Block> = blocks.iter().collect();
+        assert!(Block::concat(&refs).unwrap().eq_data(&block));
+
+        let empty = Block::new(&allocator::GLOBAL, &schema);
+        let op = Rechunk::new(4, ScanView::new(&empty, None));
+        assert_eq!(op.blocks(&allocator::GLOBAL).unwrap().count(), 0);
+
+        let op = Rechunk::new(0, ScanView::new(&block, None));
+        match op.blocks(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    // Bound as an operation, the cursor returns the coalesced blocks
+    #[test]
+    fn rechunk_cursor() {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 10 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+
+        let op = Rechunk::new(4, SmallSource { src: ScanView::new(&block, None), max: 3 });
+        assert_eq!(op.describe(), "Rechunk 4 rows");
+
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut chunks = Vec::new();
+        let mut next = 0;
+        while let CursorChunk::Next(view) = cursor.next(1024).unwrap() {
+            chunks.push(view.rows());
+            for row in 0 .. view.rows() {
+                assert!(view.value(row, 0).unwrap() == Value::INT64(next));
+                next += 1;
+            }
+        }
+        assert_eq!(chunks, vec![4, 4, 2]);
+        assert_eq!(next, 10);
+
+        // Blocks are split to fetch fewer rows
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        match cursor.next(3).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 3),
+            CursorChunk::End => assert!(false, "Expected rows"),
+        }
+        match cursor.next(3).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 1),
+            CursorChunk::End => assert!(false, "Expected rows"),
+        }
+
+        let op = Rechunk::new(0, ScanView::new(&block, None));
+        match op.bind(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
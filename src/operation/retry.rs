@@ -0,0 +1,200 @@
+use std::cmp::min;
+use std::thread;
+use std::time::Duration;
+
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// How many times, and how long to wait between, attempts of a failing operation
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first
+    pub max_attempts: usize,
+    /// Wait before the first retry, doubled for every following retry
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+/// Retries binding and reading the source when it fails with a transient error (`DBError::IO`),
+/// eg. for network or object store backed sources that can recover. Other errors are returned
+/// right away.
+///
+/// Once the attempts run out the error is `DBError::RetriesExhausted` with the errors of all the
+/// attempts.
+pub struct Retry<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub policy: RetryPolicy,
+}
+
+/// Implementation of the `Retry` operation
+struct RetryCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    policy: RetryPolicy,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+fn is_io(e: &DBError) -> bool {
+    match *e {
+        DBError::IO(_) => true,
+        _ => false,
+    }
+}
+
+impl RetryPolicy {
+    /// Run `f` until it succeeds, fails with a non transient error or attempts run out
+    fn run<T, F>(&self, mut f: F) -> Result<T, DBError>
+        where F: FnMut() -> Result<T, DBError>
+    {
+        let mut history = Vec::new();
+        let mut backoff = self.initial_backoff;
+
+        loop {
+            match f() {
+                Err(DBError::IO(e)) => history.push(e.to_string()),
+                Err(ref e @ DBError::Context(..)) if is_io(e.root()) => history.push(e.to_string()),
+                other => return other,
+            }
+
+            if history.len() >= self.max_attempts {
+                return Err(DBError::RetriesExhausted(history))
+            }
+
+            debug!("retrying after IO error (attempt {}): {}",
+                   history.len(), history.last().unwrap());
+            thread::sleep(backoff);
+            backoff = min(backoff * 2, self.max_backoff);
+        }
+    }
+}
+
+impl<'a> Retry<'a> {
+    pub fn new<T: Operation<'a> + 'a>(policy: RetryPolicy, src: T) -> Retry<'a> {
+        Retry { src: Box::new(src), policy: policy }
+    }
+}
+
+impl<'a> Operation<'a> for Retry<'a> {
+    fn describe(&self) -> String {
+        format!("Retry {} attempts", self.policy.max_attempts)
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let input = self.policy.run(|| self.src.bind_context(ctx))?;
+        Ok(Box::new(RetryCursor { input: input, policy: self.policy }))
+    }
+}
+
+impl<'a> Cursor<'a> for RetryCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let input = &mut self.input;
+        self.policy.run(|| input.next(rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error as IOError, ErrorKind};
+    use ::allocator;
+    use ::block::View;
+    use ::operation::ScanView;
+    use ::table::{Table, TableAppender};
+    use ::types::Type;
+
+    /// Cursor failing with an IO error the first `failures` times it's read
+    struct Flaky<'a> {
+        input: Box<Cursor<'a> + 'a>,
+        failures: usize,
+    }
+
+    struct FlakySource<'a> {
+        src: ScanView<'a>,
+        failures: usize,
+    }
+
+    impl<'a> Operation<'a> for FlakySource<'a> {
+        fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+            -> Result<Box<Cursor<'a> + 'a>, DBError>
+        {
+            let input = self.src.bind_context(ctx)?;
+            Ok(Box::new(Flaky { input: input, failures: self.failures }))
+        }
+    }
+
+    impl<'a> Cursor<'a> for Flaky<'a> {
+        fn schema(&self) -> &Schema {
+            self.input.schema()
+        }
+
+        fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(DBError::IO(IOError::new(ErrorKind::TimedOut, "timed out")))
+            }
+            self.input.next(rows)
+        }
+    }
+
+    #[test]
+    fn retry_transient_errors() {
+        let schema = Schema::make_one_attr("v", false, Type::UINT32);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        let block = table.take().unwrap();
+
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        };
+
+        let source = |failures| FlakySource {
+            src: ScanView::new(&block, None),
+            failures: failures,
+        };
+
+        let op = Retry::new(policy, source(2));
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        match cursor.next(10).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 1),
+            CursorChunk::End => assert!(false, "Expected rows"),
+        }
+
+        let op = Retry::new(policy, source(3));
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let out = cursor.next(10);
+
+        match out {
+            Err(DBError::RetriesExhausted(ref history)) => assert_eq!(history.len(), 3),
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
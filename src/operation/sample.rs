@@ -0,0 +1,367 @@
+use ::block::{RefView, View};
+use ::error::DBError;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+use ::util::random::SplitMix64;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Sampling method of a `Sample` operation
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SampleMethod {
+    /// Keep each row with the probability, independently of the other rows
+    Bernoulli(f64),
+    /// Exactly this many rows (or all the rows of a smaller input), every subset of the input
+    /// rows of that size as likely
+    Reservoir(RowOffset),
+}
+
+/// Returns a random sample of the source rows, in source order. The same `seed` (and source
+/// rows) gives the same sample.
+///
+/// A Bernoulli sample is streamed, only the gaps between the sampled rows are drawn. A reservoir
+/// sample reads all the source rows on the first `next` call, keeping (aliases of) the source
+/// chunks with sampled rows. Chunks alias runs of consecutive sampled rows.
+pub struct Sample<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub method: SampleMethod,
+    pub seed: u64,
+}
+
+/// Implementation of the Bernoulli `Sample` operation
+struct BernoulliCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    rng: SplitMix64,
+    probability: f64,
+    current: Option<RefView<'a>>,
+    offset: RowOffset,
+    /// Rows to skip before the next sampled row
+    skip: RowOffset,
+}
+
+/// Sampled row of a reservoir: input row position, chunk and row in the chunk
+type Sampled = (RowOffset, usize, RowOffset);
+
+/// Implementation of the reservoir `Sample` operation
+struct ReservoirCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    rng: SplitMix64,
+    size: RowOffset,
+    /// Input chunks with sampled rows
+    chunks: Vec<RefView<'a>>,
+    /// Sampled rows, in input order once the input is read
+    sampled: Vec<Sampled>,
+    /// Next of the sampled rows to return, `None` before the input is read
+    pos: Option<usize>,
+    /// Rows to read from the input at a time
+    fetch: RowOffset,
+}
+
+/// Rows to skip until the next row of a geometric distribution with success `probability`
+fn geometric_gap(rng: &mut SplitMix64, probability: f64) -> RowOffset {
+    if probability >= 1.0 {
+        return 0
+    }
+
+    // In (0, 1], capped so the gap fits
+    let u = 1.0 - rng.next_f64();
+    (u.ln() / (1.0 - probability).ln()).floor().min(1e18) as RowOffset
+}
+
+impl<'a> Sample<'a> {
+    pub fn bernoulli<T: Operation<'a> + 'a>(probability: f64, seed: u64, src: T) -> Sample<'a> {
+        Sample { src: Box::new(src), method: SampleMethod::Bernoulli(probability), seed: seed }
+    }
+
+    pub fn reservoir<T: Operation<'a> + 'a>(rows: RowOffset, seed: u64, src: T) -> Sample<'a> {
+        Sample { src: Box::new(src), method: SampleMethod::Reservoir(rows), seed: seed }
+    }
+}
+
+impl<'a> Operation<'a> for Sample<'a> {
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        if let SampleMethod::Bernoulli(p) = self.method {
+            if !(p >= 0.0 && p <= 1.0) {
+                return Err(DBError::ValueOutOfRange(format!("sample probability {}", p)))
+            }
+        }
+
+        let input = self.src.bind_context(ctx)?;
+        let fetch = ctx.batch_rows(input.schema());
+        let mut rng = SplitMix64::new(self.seed);
+
+        Ok(match self.method {
+            SampleMethod::Bernoulli(p) => {
+                let skip = geometric_gap(&mut rng, p);
+                Box::new(BernoulliCursor {
+                    input: input,
+                    rng: rng,
+                    probability: p,
+                    current: None,
+                    offset: 0,
+                    skip: skip,
+                })
+            },
+            SampleMethod::Reservoir(size) => Box::new(ReservoirCursor {
+                input: input,
+                rng: rng,
+                size: size,
+                chunks: Vec::new(),
+                sampled: Vec::new(),
+                pos: None,
+                fetch: fetch,
+            }),
+        })
+    }
+
+    fn describe(&self) -> String {
+        match self.method {
+            SampleMethod::Bernoulli(p) => format!("Sample bernoulli {} seed {}", p, self.seed),
+            SampleMethod::Reservoir(rows) =>
+                format!("Sample reservoir {} rows seed {}", rows, self.seed),
+        }
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+}
+
+impl<'a> Cursor<'a> for BernoulliCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        if self.probability <= 0.0 {
+            return Ok(CursorChunk::End)
+        }
+
+        loop {
+            let available = match self.current {
+                Some(ref view) => view.rows() - self.offset,
+                None => 0,
+            };
+
+            if available == 0 {
+                match self.input.next(rows)? {
+                    CursorChunk::Next(view) => self.current = Some(view),
+                    CursorChunk::End => return Ok(CursorChunk::End),
+                }
+                self.offset = 0;
+                continue
+            }
+
+            if self.skip >= available {
+                self.skip -= available;
+                self.offset += available;
+                continue
+            }
+
+            let end = self.offset + available;
+            self.offset += self.skip;
+            let start = self.offset;
+
+            // Extend the run while the gaps to the next sampled rows are empty
+            loop {
+                self.offset += 1;
+                self.skip = geometric_gap(&mut self.rng, self.probability);
+                if self.skip > 0 || self.offset == end || self.offset - start == rows {
+                    break
+                }
+            }
+
+            let range = RowRange { offset: start, rows: self.offset - start };
+            let view = self.current.as_ref().unwrap();
+            return view.window(range).map(CursorChunk::Next)
+        }
+    }
+}
+
+impl<'a> ReservoirCursor<'a> {
+    /// Sample the input rows, with Algorithm L (Li, 1994): the rows between replacements are
+    /// skipped without drawing numbers for them.
+    fn fill(&mut self) -> Result<(), DBError> {
+        self.pos = Some(0);
+
+        let size = self.size;
+        if size == 0 {
+            return Ok(())
+        }
+
+        let draw = |rng: &mut SplitMix64| (1.0 - rng.next_f64()).ln() / size as f64;
+        let mut row = 0;
+        let mut weight = draw(&mut self.rng).exp();
+        // Position of the next row replacing a sampled one
+        let mut next = size + geometric_gap(&mut self.rng, weight);
+
+        while let CursorChunk::Next(view) = self.input.next(self.fetch)? {
+            let chunk = self.chunks.len();
+            let end = row + view.rows();
+            let mut used = false;
+
+            while row < end && row < size {
+                self.sampled.push((row, chunk, row + view.rows() - end));
+                used = true;
+                row += 1;
+            }
+
+            while next < end {
+                let slot = self.rng.below(size as u64) as usize;
+                self.sampled[slot] = (next, chunk, next + view.rows() - end);
+                used = true;
+
+                weight *= draw(&mut self.rng).exp();
+                next += geometric_gap(&mut self.rng, weight) + 1;
+            }
+
+            row = end;
+            if used {
+                self.chunks.push(view);
+            }
+        }
+
+        self.sampled.sort_by_key(|s| s.0);
+        Ok(())
+    }
+
+    /// Drop the chunks without sampled rows
+    fn compact(&mut self) {
+        let mut kept = vec![None; self.chunks.len()];
+        for sampled in &self.sampled {
+            kept[sampled.1] = Some(0);
+        }
+
+        let chunks: Vec<_> = self.chunks.drain(..).collect();
+        for (chunk, view) in chunks.into_iter().enumerate() {
+            if kept[chunk].is_some() {
+                kept[chunk] = Some(self.chunks.len());
+                self.chunks.push(view);
+            }
+        }
+
+        for sampled in &mut self.sampled {
+            sampled.1 = kept[sampled.1].unwrap();
+        }
+    }
+}
+
+impl<'a> Cursor<'a> for ReservoirCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        if self.pos.is_none() {
+            self.fill()?;
+            self.compact();
+        }
+
+        let start = self.pos.unwrap();
+        if start == self.sampled.len() {
+            return Ok(CursorChunk::End)
+        }
+
+        // Run of consecutive rows of the same chunk
+        let (_, chunk, offset) = self.sampled[start];
+        let mut end = start + 1;
+        while end < self.sampled.len() && end - start < rows {
+            let (_, c, r) = self.sampled[end];
+            if c != chunk || r != offset + (end - start) {
+                break
+            }
+            end += 1;
+        }
+
+        self.pos = Some(end);
+        let range = RowRange { offset: offset, rows: end - start };
+        self.chunks[chunk].window(range).map(CursorChunk::Next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, column_row_data};
+    use ::operation::ScanView;
+    use ::types::{Int64, Type, Value};
+
+    fn make_block(rows: i64) -> Block<'static> {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. rows {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+        block
+    }
+
+    /// Values of the sampled rows
+    fn sampled(op: &Sample, fetch: RowOffset) -> Vec<i64> {
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut out = Vec::new();
+
+        while let CursorChunk::Next(view) = cursor.next(fetch).unwrap() {
+            let data = column_row_data::<Int64>(view.column(0).unwrap()).unwrap();
+            out.extend_from_slice(&data.values[.. view.rows()]);
+        }
+        out
+    }
+
+    // About p of the rows are sampled, in order, the same ones for the same seed
+    #[test]
+    fn bernoulli_sample() {
+        let block = make_block(10000);
+
+        let op = Sample::bernoulli(0.1, 42, ScanView::new(&block, None));
+        let rows = sampled(&op, 1024);
+        assert!(rows.len() > 800 && rows.len() < 1200, "{} rows", rows.len());
+        assert!(rows.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(sampled(&op, 7), rows);
+
+        let other = Sample::bernoulli(0.1, 43, ScanView::new(&block, None));
+        assert!(sampled(&other, 1024) != rows);
+
+        let all = Sample::bernoulli(1.0, 42, ScanView::new(&block, None));
+        assert_eq!(sampled(&all, 1024), (0 .. 10000).collect::<Vec<_>>());
+        let none = Sample::bernoulli(0.0, 42, ScanView::new(&block, None));
+        assert!(sampled(&none, 1024).is_empty());
+
+        let op = Sample::bernoulli(1.5, 42, ScanView::new(&block, None));
+        match op.bind(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    // Exactly N distinct rows in order, each row about as likely to be sampled
+    #[test]
+    fn reservoir_sample() {
+        let block = make_block(3000);
+
+        let op = Sample::reservoir(10, 7, ScanView::new(&block, None));
+        let rows = sampled(&op, 1024);
+        assert_eq!(rows.len(), 10);
+        assert!(rows.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(sampled(&op, 3), rows);
+
+        let all = Sample::reservoir(5000, 7, ScanView::new(&block, None));
+        assert_eq!(sampled(&all, 1024), (0 .. 3000).collect::<Vec<_>>());
+        let none = Sample::reservoir(0, 7, ScanView::new(&block, None));
+        assert!(sampled(&none, 1024).is_empty());
+
+        let small = make_block(20);
+        let mut counts = vec![0; 20];
+        for seed in 0 .. 400 {
+            let op = Sample::reservoir(5, seed, ScanView::new(&small, None));
+            for v in sampled(&op, 1024) {
+                counts[v as usize] += 1;
+            }
+        }
+        // 100 expected for each row
+        assert!(counts.iter().all(|c| *c > 60 && *c < 140), "{:?}", counts);
+    }
+}
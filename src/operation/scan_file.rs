@@ -0,0 +1,157 @@
+use std::cmp::min;
+
+use ::block::{RefView, View};
+use ::error::DBError;
+use ::projector::{BoundProjector, SingleSourceProjector};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+use ::storage::TableFile;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+use super::explain::describe_projection;
+
+/// Scan the blocks of a memory mapped table file. Chunks alias the mapping, only the projected
+/// columns are read from the file.
+pub struct ScanFile<'a> {
+    pub file: &'a TableFile,
+    pub projection: Option<SingleSourceProjector>,
+}
+
+/// Implementation of the `ScanFile` operation
+struct ScanFileCursor<'a> {
+    file: &'a TableFile,
+    /// Next block to read once the current one is exhausted
+    next_block: usize,
+    current: Option<RefView<'a>>,
+    offset: RowOffset,
+    projection: Option<BoundProjector>,
+}
+
+impl<'a> ScanFile<'a> {
+    pub fn new(file: &'a TableFile) -> ScanFile<'a> {
+        ScanFile { file: file, projection: None }
+    }
+
+    /// Only return the `projection` columns
+    pub fn with_projection(mut self, projection: SingleSourceProjector) -> ScanFile<'a> {
+        self.projection = Some(projection);
+        self
+    }
+}
+
+impl<'a> Operation<'a> for ScanFile<'a> {
+    fn describe(&self) -> String {
+        String::from("ScanFile") + &describe_projection(&self.projection)
+    }
+
+    fn bind_context<'b: 'a>(&self, _: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let projection = match self.projection {
+            Some(ref proj) => Some(proj.bind(self.file.schema())?),
+            None => None,
+        };
+
+        Ok(Box::new(ScanFileCursor {
+            file: self.file,
+            next_block: 0,
+            current: None,
+            offset: 0,
+            projection: projection,
+        }))
+    }
+}
+
+impl<'a> Cursor<'a> for ScanFileCursor<'a> {
+    fn schema(&self) -> &Schema {
+        match self.projection {
+            Some(ref proj) => &proj.schema,
+            None => self.file.schema(),
+        }
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        loop {
+            let left = self.current.as_ref().map_or(0, |v| v.rows() - self.offset);
+            if left > 0 {
+                break
+            }
+
+            if self.next_block >= self.file.blocks() {
+                return Ok(CursorChunk::End)
+            }
+
+            self.current = Some(self.file.block(self.next_block)?);
+            self.next_block += 1;
+            self.offset = 0;
+        }
+
+        let view = self.current.as_ref().unwrap();
+        let range = RowRange { offset: self.offset, rows: min(rows, view.rows() - self.offset) };
+        let sub = view.window(range)?;
+        self.offset += range.rows;
+
+        match self.projection {
+            Some(ref proj) => proj.project_ref_view(&sub).map(CursorChunk::Next),
+            None => Ok(CursorChunk::Next(sub)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::projector::project_by_name;
+    use ::schema::Attribute;
+    use ::storage::write_view;
+    use ::table::{Table, TableAppender};
+    use ::types::{Type, UInt32};
+
+    // Chunks don't cross file blocks and only have the projected column
+    #[test]
+    fn scan_projected() {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 25 {
+                appender = appender.add_row().set(v as u32).set("b");
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let path = env::temp_dir().join("dbkit-scan-projected.tbl");
+
+        write_view(&path, &block, 10).unwrap();
+        let file = TableFile::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let op = ScanFile::new(&file).with_projection(project_by_name("a"));
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        assert_eq!(cursor.schema().count(), 1);
+
+        let mut chunks = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(8).unwrap() {
+            assert_eq!(view.schema().count(), 1);
+            let data = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            chunks.push(data.values.to_vec());
+        }
+
+        let sizes: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![8, 2, 8, 2, 5]);
+        assert_eq!(chunks.concat(), (0 .. 25).collect::<Vec<u32>>());
+    }
+}
@@ -1,56 +1,281 @@
 use std::cmp::min;
 
-use ::allocator::Allocator;
-use ::block::{RefView, View, window_alias};
+use ::block::{RefView, View, column_row_data, window_alias};
 use ::error::DBError;
+use ::expression::{BoundExpr, Expr, bound_attribute};
+use ::projector::{BoundProjector, SingleSourceProjector};
 use ::row::{RowRange, RowOffset};
 use ::schema::Schema;
+use ::stats::StatsPredicate;
+use ::types::{Boolean, Type};
 
-use super::{Operation, Cursor, CursorChunk};
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+use super::explain::{describe_predicate, describe_projection};
 
 /// Operation that takes an "external" view and uses it as a source
 pub struct ScanView<'a> {
     pub src: &'a View<'a>,
     pub range: Option<RowRange>,
+    /// Skip the source if its zone map shows no row can match. Rows of a source that may match
+    /// are all returned, it's still up to the consumer to filter them.
+    pub filter: Option<StatsPredicate>,
+    /// Pushed down BOOLEAN predicate on the source schema, only rows where it's true are returned.
+    pub predicate: Option<Box<Expr<'a> + 'a>>,
+    /// Pushed down projection of the source columns, applied after the predicate.
+    pub projection: Option<SingleSourceProjector>,
 }
 
 impl<'a> ScanView<'a> {
     pub fn new(src: &'a View<'a>, range: Option<RowRange>) -> ScanView<'a> {
-        ScanView { src: src, range: range }
+        ScanView { src: src, range: range, filter: None, predicate: None, projection: None }
+    }
+
+    pub fn filtered(src: &'a View<'a>, range: Option<RowRange>, filter: StatsPredicate)
+        -> ScanView<'a>
+    {
+        ScanView { filter: Some(filter), ..ScanView::new(src, range) }
+    }
+
+    /// Only return rows matching the `predicate`
+    pub fn with_predicate<T: Expr<'a> + 'a>(mut self, predicate: T) -> ScanView<'a> {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    /// Only return the `projection` columns
+    pub fn with_projection(mut self, projection: SingleSourceProjector) -> ScanView<'a> {
+        self.projection = Some(projection);
+        self
     }
 }
 
 impl<'a> Operation<'a> for ScanView<'a> {
-    fn bind<'b: 'a>(&self, _: &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+    fn describe(&self) -> String {
+        let mut out = String::from("ScanView");
+        if let Some(range) = self.range {
+            out.push_str(&format!(" rows {}..{}", range.offset, range.offset + range.rows));
+        }
+        if self.filter.is_some() {
+            out.push_str(" [zone map filter]");
+        }
+        if let Some(ref predicate) = self.predicate {
+            out.push_str(&describe_predicate(&**predicate, self.src.schema()));
+        }
+        out + &describe_projection(&self.projection)
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
         let sub = window_alias(self.src, self.range)?;
-        let out = Box::new(ScanViewCursor { src: sub, offset: 0 });
+
+        // The stats cover the whole source, not just the range
+        let skip = match (&self.filter, self.src.stats()) {
+            (&Some(ref filter), Some(stats)) => !filter.may_match(self.src.schema(), stats)?,
+            _ => false,
+        };
+
+        let predicate = match self.predicate {
+            Some(ref expr) => Some(bind_predicate(&**expr, ctx, sub.schema(), "scan")?),
+            None => None,
+        };
+
+        let projection = match self.projection {
+            Some(ref proj) => Some(proj.bind(sub.schema())?),
+            None => None,
+        };
+
+        let offset = if skip { sub.rows() } else { 0 };
+        let out = Box::new(ScanViewCursor {
+            src: sub,
+            predicate: predicate,
+            selection: Selection::starting_at(offset),
+            projection: projection,
+        });
+
         Ok(out)
     }
 }
 
 /// Implementation of the `ScanView` operation
-struct ScanViewCursor<'a> {
+struct ScanViewCursor<'a, 'b: 'a> {
     /// This view is already sub
     src: RefView<'a>,
-    offset: RowOffset,
+    predicate: Option<Box<BoundExpr<'b> + 'a>>,
+    selection: Selection,
+    projection: Option<BoundProjector>,
 }
 
-impl<'a> Cursor<'a> for ScanViewCursor<'a> {
+/// Position in a view, and the predicate result for the rows starting at `evaluated_offset`.
+/// Shared by the scans with a pushed down predicate and `Filter`.
+#[derive(Default)]
+pub struct Selection {
+    /// Next row to return
+    pub offset: RowOffset,
+    evaluated: Vec<bool>,
+    evaluated_offset: RowOffset,
+}
+
+/// Bind the predicate of an operation (`op` for errors) to its input schema, it has to be BOOLEAN
+pub fn bind_predicate<'a, 'b: 'a>(expr: &Expr<'a>, ctx: &ExecutionContext<'b>, schema: &Schema,
+                                  op: &str)
+    -> Result<Box<BoundExpr<'b> + 'a>, DBError>
+{
+    let bound = expr.bind(ctx.allocator, schema)?;
+    if bound_attribute(&*bound)?.dtype != Type::BOOLEAN {
+        return Err(DBError::ExpressionInputType(
+            format!("{} predicate {} is not BOOLEAN", op, bound.describe())))
+    }
+    Ok(bound)
+}
+
+/// Rows of the view matching the predicate (NULL doesn't match)
+fn evaluate_selection(predicate: &BoundExpr, view: &RefView) -> Result<Vec<bool>, DBError> {
+    let rows = view.rows();
+    let out = predicate.evaluate(view, rows)?;
+    let data = column_row_data::<Boolean>(out.column(0).unwrap())?;
+    let nullable = out.schema()[0].nullable;
+
+    Ok((0 .. rows).map(|r| data.values[r] && !(nullable && data.nulls[r] != 0)).collect())
+}
+
+impl Selection {
+    pub fn starting_at(offset: RowOffset) -> Selection {
+        Selection { offset: offset, ..Selection::default() }
+    }
+
+    /// Next range of up to `rows` consecutive rows of `src` matching the predicate, the predicate
+    /// is evaluated `rows` rows at a time. The caller moves `offset` past the rows it returns.
+    pub fn next_range(&mut self, src: &RefView, predicate: Option<&BoundExpr>, rows: RowOffset)
+        -> Result<Option<RowRange>, DBError>
+    {
+        loop {
+            let left = src.rows() - self.offset;
+            if left == 0 {
+                return Ok(None)
+            }
+
+            let predicate = match predicate {
+                Some(p) => p,
+                None => return Ok(Some(RowRange { offset: self.offset, rows: min(left, rows) })),
+            };
+
+            let evaluated = self.evaluated_offset + self.evaluated.len();
+            if self.offset >= evaluated {
+                let range = RowRange { offset: self.offset, rows: min(left, rows) };
+                self.evaluated = evaluate_selection(predicate, &src.window(range)?)?;
+                self.evaluated_offset = self.offset;
+                continue
+            }
+
+            let (selection, base) = (&self.evaluated, self.evaluated_offset);
+            let selected = |row: RowOffset| selection[row - base];
+            let start = (self.offset .. evaluated).find(|r| selected(*r));
+            let start = match start {
+                Some(start) => start,
+                None => { self.offset = evaluated; continue },
+            };
+
+            let end = (start .. min(evaluated, start + rows)).find(|r| !selected(*r))
+                .unwrap_or(min(evaluated, start + rows));
+
+            return Ok(Some(RowRange { offset: start, rows: end - start }))
+        }
+    }
+}
+
+impl<'a, 'b> Cursor<'a> for ScanViewCursor<'a, 'b> {
     fn schema(&self) -> &Schema {
-        self.src.schema()
+        match self.projection {
+            Some(ref proj) => &proj.schema,
+            None => self.src.schema(),
+        }
     }
 
-    fn next(&'a mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
-        let left = self.src.rows() - self.offset;
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let predicate = self.predicate.as_ref().map(|p| &**p as &BoundExpr);
+        let range = match self.selection.next_range(&self.src, predicate, rows)? {
+            Some(range) => range,
+            None => return Ok(CursorChunk::End),
+        };
 
-        if left == 0 {
-            return Ok(CursorChunk::End)
+        let sub = self.src.window(range)?;
+        self.selection.offset = range.offset + range.rows;
+
+        match self.projection {
+            Some(ref proj) => proj.project_ref_view(&sub).map(CursorChunk::Next),
+            None => Ok(CursorChunk::Next(sub)),
         }
+    }
+}
 
-        let range = RowRange { offset: self.offset, rows: min(left, rows) };
-        let sub = window_alias(&self.src, Some(range))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::projector::project_by_name;
+    use ::schema::Attribute;
+    use ::table::{Table, TableAppender};
+    use ::types::UInt32;
 
-        self.offset += range.rows;
-        Ok(CursorChunk::Next(sub))
+    // Only the matching rows of the projected column come out of the scan
+    #[test]
+    fn predicate_pushdown() {
+        let block = {
+            let attrs = vec![
+                Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+                Attribute{name: "b".to_string(), nullable: false, dtype: Type::UINT32},
+                Attribute{name: "c".to_string(), nullable: false, dtype: Type::UINT32},
+            ];
+
+            let schema = Schema::from_vec(attrs).unwrap();
+            let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+            {
+                let status = TableAppender::new(&mut table)
+                    .add_row().set(1 as u32).set(1 as u32).set(10 as u32)
+                    .add_row().set(2 as u32).set(2 as u32).set(11 as u32)
+                    .add_row().set(3 as u32).set(0 as u32).set(12 as u32)
+                    .add_row().set(4 as u32).set(4 as u32).set(13 as u32)
+                    .add_row().set(5 as u32).set(5 as u32).set(14 as u32)
+                    .done();
+
+                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            }
+
+            table.take().unwrap()
+        };
+
+        let scan = ScanView::new(&block, None)
+            .with_predicate(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b")))
+            .with_projection(project_by_name("c"));
+
+        let mut cursor = scan.bind(&allocator::GLOBAL).unwrap();
+        assert_eq!(cursor.schema().count(), 1);
+
+        let mut out = Vec::new();
+        loop {
+            match cursor.next(2).unwrap() {
+                CursorChunk::Next(view) => {
+                    let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+                    out.extend(rows.values[.. view.rows()].iter().cloned());
+                },
+                CursorChunk::End => break,
+            }
+        }
+
+        assert_eq!(out, vec![10, 11, 13, 14]);
+
+        // Predicate has to be BOOLEAN
+        let scan = ScanView::new(&block, None).with_predicate(ColumnExpr::named("a"));
+        let cursor = scan.bind(&allocator::GLOBAL);
+
+        match cursor {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
     }
 }
@@ -0,0 +1,450 @@
+use std::{f32, f64, mem};
+
+use ::allocator::Allocator;
+use ::block::{Block, RefColumn, View, column_row_data, column_value, take};
+use ::compare::{RowComparator, SortKey, is_nan};
+use ::error::DBError;
+use ::plan::SortOrder;
+use ::row::RowOffset;
+use ::table::Table;
+use ::types::{Timestamp, Type, ValueInfo};
+
+use super::{BlocksCursor, Cursor, CursorChunk, ExecutionContext, Operation};
+
+/// Relational Sort Operation, returns the rows of `src` ordered by the `keys` attributes.
+///
+/// The sort is stable, rows with equal keys stay in input order. NULLs are greater than any
+/// value (last in ASC order, first in DESC order), followed by NaNs. -0.0 and 0.0 are equal.
+/// With `NanOrder::Error`, NaN keys are an error instead.
+///
+/// Sorting needs all the input rows, so the sorted rows are materialized into a new block, by
+/// `execute` or when the operation is bound. A single UINT, INT, FLOAT or TIMESTAMP key is radix
+/// sorted, other keys use a comparison sort.
+pub struct Sort<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub keys: Vec<(String, SortOrder)>,
+    pub nans: NanOrder,
+}
+
+/// Ordering of NaN key values, by `Sort` and the partitions of `WindowAggregate`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NanOrder {
+    /// NaNs are greater than any other value except NULL, and equal to each other
+    Greatest,
+    /// Ordering a NaN key is an error
+    Error,
+}
+
+impl Default for NanOrder {
+    fn default() -> NanOrder {
+        NanOrder::Greatest
+    }
+}
+
+impl<'a> Sort<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T, keys: &[(&str, SortOrder)]) -> Sort<'a> {
+        Sort {
+            src: Box::new(src),
+            keys: keys.iter().map(|&(name, order)| (name.to_string(), order)).collect(),
+            nans: NanOrder::default(),
+        }
+    }
+
+    pub fn nans(mut self, nans: NanOrder) -> Sort<'a> {
+        self.nans = nans;
+        self
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.execute_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Sorted rows, reading the input in batches of `ctx.batch_rows()`
+    pub fn execute_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Block<'b>, DBError>
+    {
+        let alloc = ctx.allocator;
+        let mut cursor = self.src.bind_context(ctx)?;
+        let schema = cursor.schema().clone();
+
+        let mut keys = Vec::with_capacity(self.keys.len());
+        for &(ref name, order) in &self.keys {
+            keys.push((schema.exists_ok(name)?, order));
+        }
+
+        let fetch = ctx.batch_rows(&schema);
+        let mut input = Table::new(alloc, &schema, None);
+        loop {
+            match cursor.next(fetch)? {
+                CursorChunk::Next(view) => input.append_block(&view)?,
+                CursorChunk::End        => break,
+            }
+        }
+
+        if self.nans == NanOrder::Error {
+            check_nan_keys(&input, &keys)?;
+        }
+        let rows = sorted_rows(&input, &keys)?;
+
+        take(alloc, &input, &rows)
+    }
+}
+
+impl<'a> Operation<'a> for Sort<'a> {
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().map(|k| format!("{} {:?}", k.0, k.1)).collect();
+        format!("Sort by {}", keys.join(", "))
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let sorted = self.execute_context(ctx)?;
+        let schema = sorted.schema().clone();
+        Ok(Box::new(BlocksCursor::new(schema, vec![sorted])))
+    }
+}
+
+/// Error for the first NaN value of the key columns, for sorting with `NanOrder::Error`
+pub fn check_nan_keys<'v>(view: &'v View<'v>, keys: &[(usize, SortOrder)])
+    -> Result<(), DBError>
+{
+    for &(pos, _) in keys {
+        let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        match col.attribute().dtype {
+            Type::FLOAT32 | Type::FLOAT64 => (),
+            _ => continue,
+        }
+
+        for row in 0 .. view.rows() {
+            if is_nan(&column_value(col, row)?) {
+                let name = &col.attribute().name;
+                return Err(DBError::ValueOutOfRange(format!("NaN key {} of row {}", name, row)))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Row offsets of `view` in the order of the (column position, order) `keys`
+pub fn sorted_rows<'v>(view: &'v View<'v>, keys: &[(usize, SortOrder)])
+    -> Result<Vec<RowOffset>, DBError>
+{
+    if keys.len() == 1 {
+        if let Some(rows) = radix_sorted_rows(view, keys[0].0, keys[0].1)? {
+            return Ok(rows)
+        }
+    }
+
+    comparison_sorted_rows(view, keys)
+}
+
+/// Row offsets of `view` ordered by a single fixed width numeric column, using a LSD radix sort.
+/// None for the other column types.
+pub fn radix_sorted_rows<'v>(view: &'v View<'v>, column: usize, order: SortOrder)
+    -> Result<Option<Vec<RowOffset>>, DBError>
+{
+    let col = view.column(column).ok_or(DBError::make_column_unknown_pos(column))?;
+    let rows = view.rows();
+
+    let dtype = &col.attribute().dtype;
+    let sorted = dispatch_type!(*dtype, numeric, T => radix_rows::<T>(col, rows, order)?,
+        _ => match *dtype {
+            Type::TIMESTAMP => radix_rows::<Timestamp>(col, rows, order)?,
+            _               => return Ok(None),
+        });
+
+    Ok(Some(sorted))
+}
+
+/// Row offsets of `view` ordered by the (column position, order) `keys`, using a (stable)
+/// comparison sort of the key values.
+pub fn comparison_sorted_rows<'v>(view: &'v View<'v>, keys: &[(usize, SortOrder)])
+    -> Result<Vec<RowOffset>, DBError>
+{
+    let keys: Vec<SortKey> = keys.iter().map(|&(pos, order)| SortKey::new(pos, order)).collect();
+    Ok(RowComparator::new(view, &keys)?.sorted_rows())
+}
+
+/// Fixed width values mapped to unsigned integers with the same order
+trait RadixKey: Copy {
+    /// Width of the key in bytes, the number of radix sort passes
+    const BYTES: usize;
+
+    fn radix_key(self) -> u64;
+}
+
+impl RadixKey for u32 {
+    const BYTES: usize = 4;
+
+    fn radix_key(self) -> u64 {
+        self as u64
+    }
+}
+
+impl RadixKey for u64 {
+    const BYTES: usize = 8;
+
+    fn radix_key(self) -> u64 {
+        self
+    }
+}
+
+impl RadixKey for i32 {
+    const BYTES: usize = 4;
+
+    fn radix_key(self) -> u64 {
+        // Flip the sign bit, so negative values come first
+        (self as u32 ^ 1 << 31) as u64
+    }
+}
+
+impl RadixKey for i64 {
+    const BYTES: usize = 8;
+
+    fn radix_key(self) -> u64 {
+        self as u64 ^ 1 << 63
+    }
+}
+
+impl RadixKey for f32 {
+    const BYTES: usize = 4;
+
+    fn radix_key(self) -> u64 {
+        // -0.0 is the same key as 0.0, every NaN the same key greater than infinity
+        let value = if self == 0.0 { 0.0 } else if self.is_nan() { f32::NAN } else { self };
+        let bits = value.to_bits();
+
+        // Negative values have all bits flipped (reversing their order), positive values only
+        // the sign bit
+        (if bits >> 31 == 1 { !bits } else { bits ^ 1 << 31 }) as u64
+    }
+}
+
+impl RadixKey for f64 {
+    const BYTES: usize = 8;
+
+    fn radix_key(self) -> u64 {
+        let value = if self == 0.0 { 0.0 } else if self.is_nan() { f64::NAN } else { self };
+        let bits = value.to_bits();
+        if bits >> 63 == 1 { !bits } else { bits ^ 1 << 63 }
+    }
+}
+
+fn radix_rows<T: ValueInfo>(col: &RefColumn, rows: RowOffset, order: SortOrder)
+    -> Result<Vec<RowOffset>, DBError>
+    where T::Store: RadixKey
+{
+    let data = column_row_data::<T>(col)?;
+    let nullable = col.attribute().nullable;
+
+    let mut keyed = Vec::with_capacity(rows);
+    let mut nulls = Vec::new();
+
+    for row in 0 .. rows {
+        if nullable && data.nulls[row] != 0 {
+            nulls.push(row);
+            continue
+        }
+
+        let key = data.values[row].radix_key();
+        keyed.push((if order == SortOrder::DESC { !key } else { key }, row));
+    }
+
+    radix_sort(&mut keyed, T::Store::BYTES);
+
+    let sorted = keyed.into_iter().map(|(_, row)| row);
+    Ok(match order {
+        SortOrder::ASC  => sorted.chain(nulls).collect(),
+        SortOrder::DESC => nulls.into_iter().chain(sorted).collect(),
+    })
+}
+
+/// Stable LSD radix sort of (key, row) pairs by the lowest `bytes` bytes of the keys, one byte
+/// per pass. Passes where all the keys have the same byte are skipped.
+fn radix_sort(keyed: &mut Vec<(u64, RowOffset)>, bytes: usize) {
+    let mut scratch = vec![(0u64, 0); keyed.len()];
+
+    for pass in 0 .. bytes {
+        let shift = pass * 8;
+
+        let mut counts = [0usize; 256];
+        for &(key, _) in keyed.iter() {
+            counts[(key >> shift) as usize & 0xff] += 1;
+        }
+
+        if counts.contains(&keyed.len()) {
+            continue
+        }
+
+        let mut offsets = [0usize; 256];
+        for digit in 1 .. 256 {
+            offsets[digit] = offsets[digit - 1] + counts[digit - 1];
+        }
+
+        for &(key, row) in keyed.iter() {
+            let digit = (key >> shift) as usize & 0xff;
+            scratch[offsets[digit]] = (key, row);
+            offsets[digit] += 1;
+        }
+
+        mem::swap(keyed, &mut scratch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::{Limit, ScanView};
+    use ::schema::{Attribute, Schema};
+    use ::table::TableAppender;
+    use ::types::{Int32, Int64, Value};
+
+    // The radix sort orders rows like the comparison sort, including NULLs, NaNs, -0.0 and DESC
+    #[test]
+    fn radix_matches_comparison() {
+        let attrs = vec![
+            Attribute{name: "i".to_string(), nullable: true, dtype: Type::INT64},
+            Attribute{name: "u".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "f".to_string(), nullable: true, dtype: Type::FLOAT32},
+            Attribute{name: "d".to_string(), nullable: false, dtype: Type::FLOAT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let edges = [f32::NAN, -0.0, 0.0, f32::NEG_INFINITY, f32::INFINITY, -f32::NAN];
+            let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+            let mut appender = TableAppender::new(&mut table);
+
+            for row in 0 .. 2000 {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let r = state >> 16;
+                let f = if row % 7 == 3 { edges[row / 7 % edges.len()] }
+                        else { (r % 201) as f32 - 100.0 };
+
+                appender = appender.add_row();
+                appender = if row % 11 == 0 { appender.set_null(true) }
+                           else { appender.set((r % 1000) as i64 - (1 << 40)) };
+                appender = appender.set((r >> 8) as u32 % 100);
+                appender = if row % 13 == 0 { appender.set_null(true) } else { appender.set(f) };
+                appender = appender.set(f as f64 * 1e100);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        for column in 0 .. 4 {
+            for &order in &[SortOrder::ASC, SortOrder::DESC] {
+                let radix = radix_sorted_rows(&table, column, order).unwrap().unwrap();
+                let comparison = comparison_sorted_rows(&table, &[(column, order)]).unwrap();
+                assert_eq!(radix, comparison, "column {} {:?}", column, order);
+            }
+        }
+    }
+
+    // Multi-column and TEXT keys use the comparison sort
+    #[test]
+    fn sort_rows() {
+        let attrs = vec![
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "n".to_string(), nullable: false, dtype: Type::INT32},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("b").set(1i32)
+                .add_row().set_null(true).set(2i32)
+                .add_row().set("a").set(3i32)
+                .add_row().set("b").set(4i32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let values = |block: &Block| -> Vec<i32> {
+            column_row_data::<Int32>(block.column(1).unwrap()).unwrap().values[.. block.rows()]
+                .to_vec()
+        };
+
+        let op = Sort::new(ScanView::new(&table, None), &[("name", SortOrder::ASC)]);
+        assert_eq!(values(&op.execute(&allocator::GLOBAL).unwrap()), vec![3, 1, 4, 2]);
+
+        let op = Sort::new(ScanView::new(&table, None),
+                           &[("name", SortOrder::DESC), ("n", SortOrder::DESC)]);
+        assert_eq!(values(&op.execute(&allocator::GLOBAL).unwrap()), vec![2, 4, 1, 3]);
+
+        let op = Sort::new(ScanView::new(&table, None), &[("n", SortOrder::DESC)]);
+        assert_eq!(values(&op.execute(&allocator::GLOBAL).unwrap()), vec![4, 3, 2, 1]);
+
+        let float_schema = Schema::make_one_attr("f", true, Type::FLOAT32);
+        let mut floats = Block::new(&allocator::GLOBAL, &float_schema);
+        for v in &[Some(f32::NAN), None, Some(-1.0)] {
+            floats.append_row(&[v.map_or(Value::NULL, Value::FLOAT32)]).unwrap();
+        }
+
+        let op = Sort::new(ScanView::new(&floats, None), &[("f", SortOrder::ASC)]);
+        let sorted = op.execute(&allocator::GLOBAL).unwrap();
+        assert!(sorted.value(0, 0).unwrap() == Value::FLOAT32(-1.0));
+        assert!(sorted.value(2, 0).unwrap() == Value::NULL);
+
+        let op = Sort::new(ScanView::new(&floats, None), &[("f", SortOrder::ASC)])
+            .nans(NanOrder::Error);
+        match op.execute(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let op = Sort::new(ScanView::new(&table, None), &[("missing", SortOrder::ASC)]);
+        match op.execute(&allocator::GLOBAL) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Bound as an operation, the rows are sorted at bind and read in chunks
+    #[test]
+    fn sort_operation() {
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in &[5i64, 3, 9, 1, 7] {
+                appender = appender.add_row().set(*v);
+            }
+            assert!(appender.done().is_none());
+        }
+        let block = table.take().unwrap();
+
+        let sort = Sort::new(ScanView::new(&block, None), &[("v", SortOrder::DESC)]);
+        let op = Limit::new(1, 3, sort);
+        assert_eq!(op.explain(), "Limit 3 offset 1\n  Sort by v DESC\n    ScanView");
+
+        let ctx = ExecutionContext::new(&allocator::GLOBAL).with_batch_bytes(1);
+        let mut cursor = op.bind_context(&ctx).unwrap();
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            assert!(view.rows() <= 2);
+            out.extend_from_slice(&column_row_data::<Int64>(view.column(0).unwrap()).unwrap()
+                .values[.. view.rows()]);
+        }
+        assert_eq!(out, vec![7, 5, 3]);
+
+        let op = Sort::new(ScanView::new(&block, None), &[("missing", SortOrder::ASC)]);
+        match op.bind(&allocator::GLOBAL) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
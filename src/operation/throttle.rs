@@ -0,0 +1,205 @@
+use std::cmp::{max, min};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ::block::View;
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, ExecutionContext};
+
+/// Sustained rate with the amount that can be emitted at once after being idle
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+/// Caps rows and/or bytes per second returned to the consumer (eg. a sink writing to a rate
+/// limited service). The cursor sleeps until the limit allows the next chunk.
+///
+/// Bytes are estimated from the fixed width of the output attributes, the VARLEN data is not
+/// counted.
+pub struct Throttle<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub rows: Option<RateLimit>,
+    pub bytes: Option<RateLimit>,
+}
+
+/// Token bucket
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Implementation of the `Throttle` operation
+struct ThrottleCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    rows: Option<Bucket>,
+    bytes: Option<Bucket>,
+    row_width: usize,
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Bucket {
+        let limit = RateLimit { per_second: limit.per_second, burst: limit.burst.max(1.0) };
+        Bucket { limit: limit, tokens: limit.burst, updated: Instant::now() }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let added = seconds(now.duration_since(self.updated)) * self.limit.per_second;
+
+        self.tokens = (self.tokens + added).min(self.limit.burst);
+        self.updated = now;
+    }
+
+    /// Wait until at least `wanted` (capped to the burst) tokens are available, returns the
+    /// available tokens.
+    fn acquire(&mut self, wanted: f64) -> f64 {
+        let wanted = wanted.min(self.limit.burst);
+        self.refill();
+
+        if self.tokens < wanted {
+            let wait = (wanted - self.tokens) / self.limit.per_second;
+            thread::sleep(Duration::new(wait as u64, (wait.fract() * 1e9) as u32));
+            self.refill();
+        }
+
+        self.tokens
+    }
+
+    /// Tokens can go negative, a chunk larger than the remaining tokens delays the next one
+    fn consume(&mut self, tokens: f64) {
+        self.tokens -= tokens;
+    }
+}
+
+impl<'a> Throttle<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T) -> Throttle<'a> {
+        Throttle { src: Box::new(src), rows: None, bytes: None }
+    }
+
+    pub fn rows_per_second(mut self, rate: f64, burst: f64) -> Throttle<'a> {
+        self.rows = Some(RateLimit { per_second: rate, burst: burst });
+        self
+    }
+
+    pub fn bytes_per_second(mut self, rate: f64, burst: f64) -> Throttle<'a> {
+        self.bytes = Some(RateLimit { per_second: rate, burst: burst });
+        self
+    }
+}
+
+impl<'a> Operation<'a> for Throttle<'a> {
+    fn describe(&self) -> String {
+        let mut out = String::from("Throttle");
+        if let Some(ref rows) = self.rows {
+            out.push_str(&format!(" [{} rows/s]", rows.per_second));
+        }
+        if let Some(ref bytes) = self.bytes {
+            out.push_str(&format!(" [{} bytes/s]", bytes.per_second));
+        }
+        out
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let input = self.src.bind_context(ctx)?;
+        let row_width = max(1, input.schema().iter().map(|a| a.dtype.size_of()).sum());
+
+        Ok(Box::new(ThrottleCursor {
+            input: input,
+            rows: self.rows.map(Bucket::new),
+            bytes: self.bytes.map(Bucket::new),
+            row_width: row_width,
+        }))
+    }
+}
+
+impl<'a> Cursor<'a> for ThrottleCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next(&mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
+        let mut fetch = rows;
+
+        if let Some(ref mut bucket) = self.rows {
+            fetch = min(fetch, max(1, bucket.acquire(1.0) as RowOffset));
+        }
+
+        let width = self.row_width;
+        if let Some(ref mut bucket) = self.bytes {
+            fetch = min(fetch, max(1, bucket.acquire(width as f64) as RowOffset / width));
+        }
+
+        let chunk = self.input.next(fetch)?;
+
+        if let CursorChunk::Next(ref view) = chunk {
+            let returned = view.rows();
+
+            if let Some(ref mut bucket) = self.rows {
+                bucket.consume(returned as f64);
+            }
+            if let Some(ref mut bucket) = self.bytes {
+                bucket.consume((returned * width) as f64);
+            }
+        }
+
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::ScanView;
+    use ::schema::Schema;
+    use ::table::{Table, TableAppender};
+    use ::types::Type;
+
+    #[test]
+    fn rows_per_second() {
+        let schema = Schema::make_one_attr("v", false, Type::UINT64);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 300 {
+                appender = appender.add_row().set(v as u64);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let op = Throttle::new(ScanView::new(&block, None)).rows_per_second(1000.0, 100.0);
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let start = Instant::now();
+        let mut total = 0;
+
+        while let CursorChunk::Next(view) = cursor.next(1000).unwrap() {
+            assert!(view.rows() <= 100, "Chunk of {} rows exceeds the burst", view.rows());
+            total += view.rows();
+        }
+
+        // The first 100 rows are the burst, the other 200 take 0.2s
+        assert_eq!(total, 300);
+        assert!(seconds(start.elapsed()) >= 0.15);
+    }
+}
@@ -0,0 +1,88 @@
+use ::allocator::Allocator;
+use ::block::Block;
+use ::error::DBError;
+use ::schema::Schema;
+use ::table::Table;
+use ::types::Value;
+use ::util::copy_value::ValueSetter;
+
+/// Literal rows, eg. a small lookup mapping or a test fixture. Each row has one value (or NULL)
+/// per attribute, of the attribute type.
+///
+/// The rows are copied into a new block by `execute`, which can be scanned (and joined) like any
+/// other view.
+pub struct Values<'a> {
+    pub schema: Schema,
+    pub rows: Vec<Vec<Value<'a>>>,
+}
+
+impl<'a> Values<'a> {
+    pub fn new(schema: Schema, rows: Vec<Vec<Value<'a>>>) -> Values<'a> {
+        Values { schema: schema, rows: rows }
+    }
+
+    pub fn execute<'b>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        let mut out = Table::new(alloc, &self.schema, Some(self.rows.len()));
+
+        for (idx, values) in self.rows.iter().enumerate() {
+            if values.len() != self.schema.count() {
+                return Err(DBError::AttributeMissing(format!(
+                    "(row {}: {} values for {} attributes)", idx, values.len(),
+                    self.schema.count())))
+            }
+
+            let row = out.add_row()?;
+            for (pos, value) in values.iter().enumerate() {
+                let col = out.column_mut(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                value.set_row(col, row)?;
+
+                if self.schema[pos].nullable && !value.is_null() {
+                    col.nulls_mut()?[row] = 0;
+                }
+            }
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{View, column_value};
+    use ::schema::Attribute;
+    use ::types::Type;
+
+    fn schema() -> Schema {
+        Schema::from_vec(vec![
+            Attribute{name: "code".to_string(), nullable: false, dtype: Type::TEXT},
+            Attribute{name: "rate".to_string(), nullable: true, dtype: Type::FLOAT64},
+        ]).unwrap()
+    }
+
+    // Rows in order, NULLs allowed in nullable attributes only
+    #[test]
+    fn values_rows() {
+        let values = Values::new(schema(), vec![
+            vec![Value::TEXT("usd"), Value::FLOAT64(1.0)],
+            vec![Value::TEXT("eur"), Value::NULL],
+        ]);
+
+        let block = values.execute(&allocator::GLOBAL).unwrap();
+        assert_eq!(block.rows(), 2);
+        assert!(column_value(block.column(0).unwrap(), 1).unwrap() == Value::TEXT("eur"));
+        assert!(column_value(block.column(1).unwrap(), 0).unwrap() == Value::FLOAT64(1.0));
+        assert!(column_value(block.column(1).unwrap(), 1).unwrap() == Value::NULL);
+
+        let short = Values::new(schema(), vec![vec![Value::TEXT("usd")]]);
+        match short.execute(&allocator::GLOBAL) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let mistyped = Values::new(schema(), vec![vec![Value::INT32(1), Value::NULL]]);
+        assert!(mistyped.execute(&allocator::GLOBAL).is_err());
+    }
+}
@@ -0,0 +1,450 @@
+use std::cmp::Ordering;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::compare::compare_keys;
+use ::error::DBError;
+use ::kernels::NonFinite;
+use ::plan::SortOrder;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Type, Value};
+
+use super::{BlocksCursor, Cursor, CursorChunk, ExecutionContext, Operation};
+use super::sort::{NanOrder, check_nan_keys, sorted_rows};
+
+/// Function computed by a `WindowAggregate` for each row, over the rows of its partition
+#[derive(Clone, PartialEq, Debug)]
+pub enum WindowFunc {
+    /// INT64 position of the row in the partition, starting at 1
+    RowNumber,
+    /// INT64 rank of the row's ORDER BY keys. Rows with equal keys have the same rank, the next
+    /// rank skips over them (1, 2, 2, 4).
+    Rank,
+    /// Value of the attribute `offset` rows before, NULL for the first rows of the partition
+    Lag { input: String, offset: usize },
+    /// Value of the attribute `offset` rows after, NULL for the last rows of the partition
+    Lead { input: String, offset: usize },
+    /// Sum of the attribute from the first row of the partition up to (including) the row, NULL
+    /// until there's a non NULL value. INT64 sum of integers, FLOAT64 sum of floats, NaN and
+    /// infinite floats are summed according to the operation's `NonFinite` treatment.
+    RunningSum(String),
+}
+
+/// Window function operation: the rows of `src` with a column for each of the `functions`.
+/// Rows are grouped into partitions of equal `partition_by` attributes and functions see the
+/// rows of the partition in `order_by` order.
+///
+/// The output rows are ordered by the partition attributes (ascending) and then `order_by`,
+/// they're sorted like the `Sort` operation (including its `NanOrder`) and materialized into a
+/// new block, by `execute` or when the operation is bound.
+pub struct WindowAggregate<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub partition_by: Vec<String>,
+    pub order_by: Vec<(String, SortOrder)>,
+    /// Output attribute name and function
+    pub functions: Vec<(String, WindowFunc)>,
+    pub nans: NanOrder,
+    /// Treatment of NaN and infinite values by running sums
+    pub non_finite: NonFinite,
+}
+
+/// Bound function: input attribute position and running state
+struct WindowState {
+    func: WindowFunc,
+    input: Option<usize>,
+    rank: i64,
+    sum: Option<Sum>,
+}
+
+#[derive(Clone, Copy)]
+enum Sum {
+    Int(i64),
+    Float(f64),
+}
+
+impl WindowFunc {
+    fn input(&self) -> Option<&str> {
+        match *self {
+            WindowFunc::RowNumber | WindowFunc::Rank    => None,
+            WindowFunc::Lag { ref input, .. }
+                | WindowFunc::Lead { ref input, .. }
+                | WindowFunc::RunningSum(ref input)     => Some(input),
+        }
+    }
+
+    /// Output attribute for the function of `input`
+    fn attribute(&self, name: &str, input: Option<&Attribute>) -> Result<Attribute, DBError> {
+        let (nullable, dtype) = match (self, input) {
+            (&WindowFunc::Lag { .. }, Some(attr)) | (&WindowFunc::Lead { .. }, Some(attr)) =>
+                (true, attr.dtype.clone()),
+            (&WindowFunc::RunningSum(_), Some(attr)) => match attr.dtype {
+                Type::UINT32 | Type::UINT64 | Type::INT32 | Type::INT64 =>
+                    (attr.nullable, Type::INT64),
+                Type::FLOAT32 | Type::FLOAT64 => (attr.nullable, Type::FLOAT64),
+                ref other => return Err(DBError::ExpressionInputType(
+                    format!("running sum of {} ({})", other, attr.name))),
+            },
+            _ => (false, Type::INT64),
+        };
+
+        Ok(Attribute { name: name.to_string(), nullable: nullable, dtype: dtype })
+    }
+}
+
+fn add_value(sum: Option<Sum>, value: &Value, non_finite: NonFinite)
+    -> Result<Option<Sum>, DBError>
+{
+    let overflow = || DBError::ValueOutOfRange(String::from("running sum overflows INT64"));
+    let int = |v: i64| match sum {
+        Some(Sum::Int(s)) => s.checked_add(v).map(Sum::Int).ok_or_else(overflow),
+        _ => Ok(Sum::Int(v)),
+    };
+    let float = |v: f64| match sum {
+        Some(Sum::Float(s)) => Sum::Float(s + v),
+        _ => Sum::Float(v),
+    };
+
+    let finite = match *value {
+        Value::FLOAT32(v)   => v.is_finite(),
+        Value::FLOAT64(v)   => v.is_finite(),
+        _                   => true,
+    };
+
+    match non_finite {
+        NonFinite::Skip if !finite  => return Ok(sum),
+        NonFinite::Error if !finite => return Err(DBError::ValueOutOfRange(
+            String::from("running sum of a NaN or infinite value"))),
+        _                           => (),
+    }
+
+    Ok(Some(match *value {
+        Value::UINT32(v)    => int(v as i64)?,
+        Value::UINT64(v)    if v > i64::max_value() as u64 => return Err(overflow()),
+        Value::UINT64(v)    => int(v as i64)?,
+        Value::INT32(v)     => int(v as i64)?,
+        Value::INT64(v)     => int(v)?,
+        Value::FLOAT32(v)   => float(v as f64),
+        Value::FLOAT64(v)   => float(v),
+        _                   => return Ok(sum),
+    }))
+}
+
+impl<'a> WindowAggregate<'a> {
+    pub fn new<T>(src: T, partition_by: &[&str], order_by: &[(&str, SortOrder)])
+        -> WindowAggregate<'a>
+        where T: Operation<'a> + 'a
+    {
+        WindowAggregate {
+            src: Box::new(src),
+            partition_by: partition_by.iter().map(|p| p.to_string()).collect(),
+            order_by: order_by.iter().map(|&(name, order)| (name.to_string(), order)).collect(),
+            functions: Vec::new(),
+            nans: NanOrder::default(),
+            non_finite: NonFinite::default(),
+        }
+    }
+
+    pub fn nans(mut self, nans: NanOrder) -> WindowAggregate<'a> {
+        self.nans = nans;
+        self
+    }
+
+    pub fn non_finite(mut self, non_finite: NonFinite) -> WindowAggregate<'a> {
+        self.non_finite = non_finite;
+        self
+    }
+
+    /// Add the function, as the attribute `name`
+    pub fn function<S: Into<String>>(mut self, name: S, func: WindowFunc) -> WindowAggregate<'a> {
+        self.functions.push((name.into(), func));
+        self
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        self.execute_context(&ExecutionContext::new(alloc))
+    }
+
+    /// Rows with the function columns, reading the input in batches of `ctx.batch_rows()`
+    pub fn execute_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Block<'b>, DBError>
+    {
+        let alloc = ctx.allocator;
+        let mut cursor = self.src.bind_context(ctx)?;
+        let schema = cursor.schema().clone();
+
+        let mut partition = Vec::with_capacity(self.partition_by.len());
+        for name in &self.partition_by {
+            partition.push(schema.exists_ok(name)?);
+        }
+
+        let mut order = Vec::with_capacity(self.order_by.len());
+        for (name, _) in &self.order_by {
+            order.push(schema.exists_ok(name)?);
+        }
+
+        let mut attrs: Vec<Attribute> = schema.iter().cloned().collect();
+        let mut states = Vec::with_capacity(self.functions.len());
+        for (name, func) in &self.functions {
+            let input = match func.input() {
+                Some(input) => Some(schema.exists_ok(input)?),
+                None => None,
+            };
+
+            let mut attr = func.attribute(name, input.map(|pos| &schema[pos]))?;
+            // Skipping the non finite values leaves running sums NULL until a finite value
+            if let WindowFunc::RunningSum(_) = *func {
+                attr.nullable |= attr.dtype == Type::FLOAT64 && self.non_finite == NonFinite::Skip;
+            }
+            attrs.push(attr);
+            states.push(WindowState { func: func.clone(), input: input, rank: 0, sum: None });
+        }
+        let out_schema = Schema::from_vec(attrs)?;
+
+        let fetch = ctx.batch_rows(&schema);
+        let mut input = Table::new(alloc, &schema, None);
+        loop {
+            match cursor.next(fetch)? {
+                CursorChunk::Next(view) => input.append_block(&view)?,
+                CursorChunk::End        => break,
+            }
+        }
+
+        let keys: Vec<(usize, SortOrder)> = partition.iter().map(|p| (*p, SortOrder::ASC))
+            .chain(order.iter().zip(&self.order_by).map(|(p, o)| (*p, o.1)))
+            .collect();
+        if self.nans == NanOrder::Error {
+            check_nan_keys(&input, &keys)?;
+        }
+        let rows = sorted_rows(&input, &keys)?;
+
+        // Rows of `cols` have equal values
+        let same = |cols: &[usize], l: RowOffset, r: RowOffset| -> Result<bool, DBError> {
+            for &pos in cols {
+                let col = input.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                if compare_keys(&column_value(col, l)?, &column_value(col, r)?) != Ordering::Equal {
+                    return Ok(false)
+                }
+            }
+            Ok(true)
+        };
+
+        // Sorted position of the first row of each row's partition, and one past its last row
+        let mut starts = Vec::with_capacity(rows.len());
+        for i in 0 .. rows.len() {
+            let start = if i > 0 && same(&partition, rows[i - 1], rows[i])? {
+                starts[i - 1]
+            } else {
+                i
+            };
+            starts.push(start);
+        }
+
+        let mut ends = vec![rows.len(); rows.len()];
+        for i in (0 .. rows.len()).rev() {
+            if i + 1 < rows.len() && starts[i + 1] == starts[i] {
+                ends[i] = ends[i + 1];
+            } else {
+                ends[i] = i + 1;
+            }
+        }
+
+        let mut out = Table::new(alloc, &out_schema, Some(rows.len()));
+        for (i, &row) in rows.iter().enumerate() {
+            let out_row = out.add_row()?;
+
+            for pos in 0 .. schema.count() {
+                let col = input.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                out.set(pos, out_row, column_value(col, row)?)?;
+            }
+
+            let (start, end) = (starts[i], ends[i]);
+            for (f, state) in states.iter_mut().enumerate() {
+                let pos = schema.count() + f;
+                let col = state.input.map(|p| input.column(p).unwrap());
+
+                match state.func {
+                    WindowFunc::RowNumber =>
+                        out.set(pos, out_row, (i - start + 1) as i64)?,
+                    WindowFunc::Rank => {
+                        if i == start || !same(&order, rows[i - 1], row)? {
+                            state.rank = (i - start + 1) as i64;
+                        }
+                        out.set(pos, out_row, state.rank)?
+                    },
+                    WindowFunc::Lag { offset, .. } if i >= start + offset =>
+                        out.set(pos, out_row, column_value(col.unwrap(), rows[i - offset])?)?,
+                    WindowFunc::Lead { offset, .. } if i + offset < end =>
+                        out.set(pos, out_row, column_value(col.unwrap(), rows[i + offset])?)?,
+                    WindowFunc::Lag { .. } | WindowFunc::Lead { .. } =>
+                        out.set(pos, out_row, Value::NULL)?,
+                    WindowFunc::RunningSum(_) => {
+                        let prev = if i == start { None } else { state.sum };
+                        let value = column_value(col.unwrap(), row)?;
+                        state.sum = add_value(prev, &value, self.non_finite)?;
+
+                        match state.sum {
+                            Some(Sum::Int(v))   => out.set(pos, out_row, v)?,
+                            Some(Sum::Float(v)) => out.set(pos, out_row, v)?,
+                            None                => out.set(pos, out_row, Value::NULL)?,
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(out.take().unwrap())
+    }
+}
+
+impl<'a> Operation<'a> for WindowAggregate<'a> {
+    fn describe(&self) -> String {
+        let funcs: Vec<&str> = self.functions.iter().map(|f| &f.0[..]).collect();
+        let mut out = format!("WindowAggregate {}", funcs.join(", "));
+        if !self.partition_by.is_empty() {
+            out.push_str(&format!(" partition by {}", self.partition_by.join(", ")));
+        }
+        if !self.order_by.is_empty() {
+            let keys: Vec<String> = self.order_by.iter().map(|k| format!("{} {:?}", k.0, k.1))
+                .collect();
+            out.push_str(&format!(" order by {}", keys.join(", ")));
+        }
+        out
+    }
+
+    fn inputs(&self) -> Vec<&Operation<'a>> {
+        vec![&*self.src]
+    }
+
+    fn bind_context<'b: 'a>(&self, ctx: &ExecutionContext<'b>)
+        -> Result<Box<Cursor<'a> + 'a>, DBError>
+    {
+        let out = self.execute_context(ctx)?;
+        let schema = out.schema().clone();
+        Ok(Box::new(BlocksCursor::new(schema, vec![out])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::{Limit, ScanView};
+    use ::table::TableAppender;
+
+    fn make_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute{name: "k".to_string(), nullable: false, dtype: Type::TEXT},
+            Attribute{name: "t".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "v".to_string(), nullable: true, dtype: Type::INT64},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("a").set(3i64).set(30i64)
+                .add_row().set("b").set(1i64).set(5i64)
+                .add_row().set("a").set(1i64).set(10i64)
+                .add_row().set("a").set(2i64).set_null(true)
+                .add_row().set("a").set(2i64).set(20i64)
+                .add_row().set("b").set(2i64).set(7i64)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    fn ints(block: &Block, col: usize) -> Vec<Option<i64>> {
+        (0 .. block.rows()).map(|row| match column_value(block.column(col).unwrap(), row).unwrap() {
+            Value::INT64(v) => Some(v),
+            _ => None,
+        }).collect()
+    }
+
+    // Functions over each partition in order, rows ordered by partition
+    #[test]
+    fn window_functions() {
+        let block = make_block();
+        let op = WindowAggregate::new(ScanView::new(&block, None), &["k"], &[("t", SortOrder::ASC)])
+            .function("rn", WindowFunc::RowNumber)
+            .function("rank", WindowFunc::Rank)
+            .function("prev", WindowFunc::Lag { input: "v".to_string(), offset: 1 })
+            .function("next", WindowFunc::Lead { input: "v".to_string(), offset: 1 })
+            .function("total", WindowFunc::RunningSum("v".to_string()));
+
+        let out = op.execute(&allocator::GLOBAL).unwrap();
+        assert_eq!(out.schema().count(), 8);
+
+        assert_eq!(ints(&out, 1), vec![Some(1), Some(2), Some(2), Some(3), Some(1), Some(2)]);
+        assert_eq!(ints(&out, 3), vec![Some(1), Some(2), Some(3), Some(4), Some(1), Some(2)]);
+        assert_eq!(ints(&out, 4), vec![Some(1), Some(2), Some(2), Some(4), Some(1), Some(2)]);
+        assert_eq!(ints(&out, 5), vec![None, Some(10), None, Some(20), None, Some(5)]);
+        assert_eq!(ints(&out, 6), vec![None, Some(20), Some(30), None, Some(7), None]);
+        assert_eq!(ints(&out, 7), vec![Some(10), Some(10), Some(30), Some(60), Some(5), Some(12)]);
+
+        // Bound as an operation, read in chunks
+        let op = Limit::new(0, 5, op);
+        assert_eq!(op.explain(), "Limit 5 offset 0\n  \
+            WindowAggregate rn, rank, prev, next, total partition by k order by t ASC\n    \
+            ScanView");
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        let mut totals = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            totals.extend((0 .. view.rows()).map(|row| column_value(view.column(7).unwrap(), row)
+                .unwrap() == Value::INT64(60)));
+        }
+        assert_eq!(totals, vec![false, false, false, true, false]);
+
+        let op = WindowAggregate::new(ScanView::new(&block, None), &[], &[])
+            .function("total", WindowFunc::RunningSum("k".to_string()));
+        match op.execute(&allocator::GLOBAL) {
+            Err(DBError::ExpressionInputType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // NaN partitions are last or an error, running sums include, skip or reject NaNs
+    #[test]
+    fn non_finite_floats() {
+        let schema = Schema::make_one_attr("f", false, Type::FLOAT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in &[::std::f64::NAN, 1.0, 2.0] {
+            block.append_row(&[Value::FLOAT64(*v)]).unwrap();
+        }
+
+        let sums = |non_finite| {
+            let op = WindowAggregate::new(ScanView::new(&block, None), &[], &[])
+                .function("total", WindowFunc::RunningSum("f".to_string()))
+                .non_finite(non_finite);
+            let out = op.execute(&allocator::GLOBAL)?;
+            (0 .. out.rows()).map(|row| out.value(row, 1)).collect::<Result<Vec<_>, _>>()
+                .map(|values| values.iter().map(|v| match *v {
+                    Value::FLOAT64(f) => Some(f),
+                    _ => None,
+                }).collect::<Vec<_>>())
+        };
+
+        assert!(sums(NonFinite::Include).unwrap().iter().all(|s| s.unwrap().is_nan()));
+        assert_eq!(sums(NonFinite::Skip).unwrap(), vec![None, Some(1.0), Some(3.0)]);
+        assert!(sums(NonFinite::Error).is_err());
+
+        let partitions = |nans| {
+            WindowAggregate::new(ScanView::new(&block, None), &["f"], &[])
+                .function("rn", WindowFunc::RowNumber)
+                .nans(nans)
+                .execute(&allocator::GLOBAL)
+        };
+
+        let out = partitions(NanOrder::Greatest).unwrap();
+        assert!(out.value(0, 0).unwrap() == Value::FLOAT64(1.0));
+        match partitions(NanOrder::Error) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
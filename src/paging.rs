@@ -0,0 +1,230 @@
+// vim: set ts=4 sw=4 et :
+
+//! Paged results.
+//!
+//! A `Cursors` registry keeps the bound cursors of queries open between requests, so a client can
+//! read a large result one page at a time instead of holding a streaming connection. Opening a
+//! cursor returns the token of its first page, each fetched page comes with the token of the
+//! next one. A token is only valid for the next page; fetching the last page, closing the cursor
+//! or leaving it idle for longer than the idle timeout releases it.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ::allocator::Allocator;
+use ::block::{Block, View};
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk, Operation};
+use ::row::RowOffset;
+use ::table::Table;
+
+/// Idle time after which `Cursors::default()` releases a cursor
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Rows of a cursor page
+pub struct Page<'a> {
+    pub rows: Block<'a>,
+    /// Token of the following page, `None` once the cursor reached the end. A page ending right
+    /// at the end of the result still has a token, the last fetch returns no rows.
+    pub next: Option<String>,
+}
+
+struct OpenCursor<'a> {
+    cursor: Box<Cursor<'a> + 'a>,
+    /// Number of the next page
+    page: u64,
+    last_used: Instant,
+}
+
+/// Open cursors by id
+pub struct Cursors<'a> {
+    alloc: &'a Allocator,
+    open: RefCell<HashMap<u64, OpenCursor<'a>>>,
+    opened: Cell<u64>,
+    idle_timeout: Duration,
+}
+
+fn make_token(id: u64, page: u64) -> String {
+    format!("{:x}-{:x}", id, page)
+}
+
+fn parse_token(token: &str) -> Option<(u64, u64)> {
+    let mut parts = token.splitn(2, '-');
+    let id = parts.next().and_then(|p| u64::from_str_radix(p, 16).ok())?;
+    let page = parts.next().and_then(|p| u64::from_str_radix(p, 16).ok())?;
+    Some((id, page))
+}
+
+impl<'a> Cursors<'a> {
+    /// Registry binding operations (and copying pages) with `alloc`
+    pub fn new(alloc: &'a Allocator, idle_timeout: Duration) -> Cursors<'a> {
+        Cursors {
+            alloc: alloc,
+            open: RefCell::new(HashMap::new()),
+            opened: Cell::new(0),
+            idle_timeout: idle_timeout,
+        }
+    }
+
+    /// Number of open cursors
+    pub fn len(&self) -> usize {
+        self.open.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bind the operation and open its cursor, returns the token of the first page
+    pub fn open(&self, op: &Operation<'a>) -> Result<String, DBError> {
+        let cursor = op.bind(self.alloc)?;
+        Ok(self.open_cursor(cursor))
+    }
+
+    /// Keep the already bound cursor open, returns the token of the first page
+    pub fn open_cursor(&self, cursor: Box<Cursor<'a> + 'a>) -> String {
+        let now = Instant::now();
+        self.expire_at(now);
+
+        let id = self.opened.get() + 1;
+        self.opened.set(id);
+
+        self.open.borrow_mut().insert(id, OpenCursor { cursor: cursor, page: 0, last_used: now });
+        make_token(id, 0)
+    }
+
+    /// Read up to `max_rows` rows of the page
+    pub fn fetch(&self, token: &str, max_rows: RowOffset) -> Result<Page<'a>, DBError> {
+        let now = Instant::now();
+        self.expire_at(now);
+
+        let missing = || DBError::CursorMissing(token.to_string());
+        let (id, page) = parse_token(token).ok_or_else(missing)?;
+
+        let mut open = self.open.borrow_mut();
+        let mut ended = false;
+        let rows = {
+            let entry = match open.get_mut(&id) {
+                Some(entry) if entry.page == page => entry,
+                _ => return Err(missing()),
+            };
+
+            let mut table = Table::new(self.alloc, entry.cursor.schema(), None);
+            while table.rows() < max_rows {
+                match entry.cursor.next(max_rows - table.rows())? {
+                    CursorChunk::Next(chunk) => table.append_block(&chunk)?,
+                    CursorChunk::End => {
+                        ended = true;
+                        break
+                    },
+                }
+            }
+
+            entry.page += 1;
+            entry.last_used = now;
+            table.take().unwrap()
+        };
+
+        if ended {
+            open.remove(&id);
+        }
+
+        Ok(Page { rows: rows, next: if ended { None } else { Some(make_token(id, page + 1)) } })
+    }
+
+    /// Release the cursor of the page token, false if it's not open
+    pub fn close(&self, token: &str) -> bool {
+        match parse_token(token) {
+            Some((id, _)) => self.open.borrow_mut().remove(&id).is_some(),
+            None => false,
+        }
+    }
+
+    /// Release the cursors idle for longer than the timeout, returns how many
+    pub fn expire_idle(&self) -> usize {
+        self.expire_at(Instant::now())
+    }
+
+    fn expire_at(&self, now: Instant) -> usize {
+        let mut open = self.open.borrow_mut();
+        let before = open.len();
+        let timeout = self.idle_timeout;
+        open.retain(|_, c| now.duration_since(c.last_used) <= timeout);
+        before - open.len()
+    }
+}
+
+impl<'a> Default for Cursors<'a> {
+    fn default() -> Cursors<'a> {
+        Cursors::new(&::allocator::GLOBAL, DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::ScanView;
+    use ::schema::Schema;
+    use ::types::{Type, Value};
+
+    fn make_block<'a>() -> Block<'a> {
+        let schema = Schema::make_one_attr("id", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 25 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+        block
+    }
+
+    // Pages cover the result in order, tokens are only good for the next page
+    #[test]
+    fn fetch_pages() {
+        let block = make_block();
+        let cursors = Cursors::new(&allocator::GLOBAL, DEFAULT_IDLE_TIMEOUT);
+
+        let first = cursors.open(&ScanView::new(&block, None)).unwrap();
+        let page = cursors.fetch(&first, 10).unwrap();
+        assert_eq!(page.rows.rows(), 10);
+        assert!(page.rows.value(9, 0).unwrap() == Value::INT64(9));
+
+        // The first page was already read
+        match cursors.fetch(&first, 10) {
+            Err(DBError::CursorMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let page = cursors.fetch(&page.next.unwrap(), 10).unwrap();
+        assert!(page.rows.value(0, 0).unwrap() == Value::INT64(10));
+
+        let last = cursors.fetch(&page.next.unwrap(), 10).unwrap();
+        assert_eq!(last.rows.rows(), 5);
+        assert_eq!(last.next, None);
+        assert!(cursors.is_empty());
+
+        let other = cursors.open(&ScanView::new(&block, None)).unwrap();
+        assert_ne!(other, first);
+        assert!(cursors.close(&other));
+        assert!(!cursors.close(&other));
+        assert!(cursors.fetch("bad token", 10).is_err());
+    }
+
+    // Cursors left idle for longer than the timeout are released
+    #[test]
+    fn idle_timeout() {
+        let block = make_block();
+        let cursors = Cursors::new(&allocator::GLOBAL, Duration::from_secs(60));
+
+        let token = cursors.open(&ScanView::new(&block, None)).unwrap();
+        assert_eq!(cursors.expire_idle(), 0);
+        assert_eq!(cursors.expire_at(Instant::now() + Duration::from_secs(61)), 1);
+
+        match cursors.fetch(&token, 10) {
+            Err(DBError::CursorMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+}
@@ -0,0 +1,166 @@
+//! Plan execution metrics.
+//!
+//! `LogicalPlan::explain_analyze()` runs a plan with each of its operations wrapped in an
+//! `Instrument` operation, and renders the plan tree with the metrics of each node:
+//!
+//! ```text
+//! Limit 2 offset 1 [rows in 3, rows out 2, chunks 1, time 0.012ms, peak memory 0 bytes]
+//!   Scan [predicate] [rows out 3, chunks 1, time 0.009ms, peak memory 0 bytes]
+//! ```
+//!
+//! Rows in are the rows out of the node's inputs. Times include the time spent in the inputs,
+//! the peak memory is the most memory the query held when the node returned a chunk.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use ::allocator::MemoryTracker;
+use ::error::DBError;
+use ::operation::{CursorChunk, Filter, HashAggregate, HashJoin, Instrument, Limit, NanOrder,
+                  Operation, OperatorCounters, Project, Sort};
+use ::row::RowOffset;
+use ::schema::DEFAULT_BATCH_BYTES;
+
+use super::LogicalPlan;
+
+/// Counters of the operations of an instrumented plan, a node per plan node
+#[derive(Clone, Debug)]
+pub struct MetricsTree {
+    /// `LogicalPlan::describe()` of the node
+    pub label: String,
+    /// Updated while the plan runs
+    pub counters: Rc<Cell<OperatorCounters>>,
+    pub inputs: Vec<MetricsTree>,
+}
+
+impl MetricsTree {
+    /// Rows returned by the inputs of the node, `None` for scans
+    pub fn rows_in(&self) -> Option<RowOffset> {
+        if self.inputs.is_empty() {
+            None
+        } else {
+            Some(self.inputs.iter().map(|i| i.counters.get().rows).sum())
+        }
+    }
+
+    /// The tree, one node and its metrics per line with inputs indented under their parent
+    pub fn render(&self) -> String {
+        fn walk(node: &MetricsTree, depth: usize, out: &mut Vec<String>) {
+            let counters = node.counters.get();
+            let rows_in = node.rows_in().map_or(String::new(), |r| format!("rows in {}, ", r));
+            let elapsed = counters.elapsed;
+            let millis = elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 / 1e6;
+
+            out.push(format!("{}{} [{}rows out {}, chunks {}, time {:.3}ms, peak memory {} bytes]",
+                             "  ".repeat(depth), node.label, rows_in, counters.rows,
+                             counters.chunks, millis, counters.peak_memory));
+            for input in &node.inputs {
+                walk(input, depth + 1, out);
+            }
+        }
+
+        let mut lines = Vec::new();
+        walk(self, 0, &mut lines);
+        lines.join("\n")
+    }
+}
+
+/// Lower the plan like `LogicalPlan::lower()`, wrapping every operation in an `Instrument`
+/// operation sampling the `memory` tracker
+pub fn lower_instrumented<'a>(plan: LogicalPlan<'a>, memory: Option<&'a MemoryTracker<'a>>)
+    -> Result<(Box<Operation<'a> + 'a>, MetricsTree), DBError>
+{
+    let label = plan.describe();
+
+    let (op, inputs): (Box<Operation<'a> + 'a>, _) = match plan {
+        LogicalPlan::Filter { input, predicate } => {
+            let (src, metrics) = lower_instrumented(*input, memory)?;
+            (Box::new(Filter { src: src, predicate: predicate }), vec![metrics])
+        },
+        LogicalPlan::Project { input, proj } => {
+            let (src, metrics) = lower_instrumented(*input, memory)?;
+            (Box::new(Project { src: src, proj: proj }), vec![metrics])
+        },
+        LogicalPlan::Join { left, right, on } => {
+            let (left, left_metrics) = lower_instrumented(*left, memory)?;
+            let (right, right_metrics) = lower_instrumented(*right, memory)?;
+            (Box::new(HashJoin { left: left, right: right, on: on }),
+             vec![left_metrics, right_metrics])
+        },
+        LogicalPlan::Aggregate { input, group_by, aggregates } => {
+            let (src, metrics) = lower_instrumented(*input, memory)?;
+            let op = HashAggregate { src: src, group_by: group_by, aggregates: aggregates };
+            (Box::new(op), vec![metrics])
+        },
+        LogicalPlan::Sort { input, keys } => {
+            let (src, metrics) = lower_instrumented(*input, memory)?;
+            (Box::new(Sort { src: src, keys: keys, nans: NanOrder::default() }), vec![metrics])
+        },
+        LogicalPlan::Limit { input, offset, count } => {
+            let (src, metrics) = lower_instrumented(*input, memory)?;
+            (Box::new(Limit { src: src, offset: offset, count: count }), vec![metrics])
+        },
+        // Scans
+        other => (other.lower()?, Vec::new()),
+    };
+
+    let counters = Rc::new(Cell::new(OperatorCounters::default()));
+    let op = Instrument { src: op, counters: counters.clone(), memory: memory };
+    Ok((Box::new(op), MetricsTree { label: label, counters: counters, inputs: inputs }))
+}
+
+/// Run the plan, allocating from `memory`, and render its tree with the metrics of each node
+pub fn explain_analyze<'a, 'b: 'a>(plan: LogicalPlan<'a>, memory: &'b MemoryTracker<'b>)
+    -> Result<String, DBError>
+{
+    let fetch = plan.batch_rows(DEFAULT_BATCH_BYTES)?;
+    let (op, metrics) = lower_instrumented(plan, Some(memory))?;
+
+    {
+        let mut cursor = op.bind(memory)?;
+        while let CursorChunk::Next(_) = cursor.next(fetch)? {}
+    }
+
+    Ok(metrics.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::plan::SortOrder;
+    use ::projector::{BuildSingleSourceProjector, project_by_name};
+    use ::schema::Schema;
+    use ::types::{Type, Value};
+
+    // Each node has the rows it returned, and the rows its input returned
+    #[test]
+    fn analyzed_plan() {
+        let schema = Schema::make_one_attr("a", false, Type::INT64);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for v in 0 .. 10 {
+            block.append_row(&[Value::INT64(v)]).unwrap();
+        }
+
+        let memory = MemoryTracker::new(&allocator::GLOBAL, "query", None);
+        let proj = BuildSingleSourceProjector::new().add(project_by_name("a")).done();
+        let plan = LogicalPlan::scan(&block).project(proj).limit(1, 3);
+
+        let text = explain_analyze(plan, &memory).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", text);
+        assert!(lines[0].starts_with("Limit 3 offset 1 [rows in 4, rows out 3, chunks 1, time "),
+                "{}", text);
+        assert!(lines[1].starts_with("  Project [rows in 4, rows out 4, chunks 2, "), "{}", text);
+        assert!(lines[2].starts_with("    Scan [rows out 4, chunks 2, "), "{}", text);
+        assert!(lines[2].ends_with(" bytes]"));
+
+        let plan = LogicalPlan::scan(&block).sort(vec![("a".to_string(), SortOrder::DESC)]);
+        let text = explain_analyze(plan, &memory).unwrap();
+        assert!(text.lines().nth(1).unwrap().starts_with("  Scan [rows out 10, "), "{}", text);
+
+        let plan = LogicalPlan::scan(&block).sort(vec![("missing".to_string(), SortOrder::ASC)]);
+        assert!(explain_analyze(plan, &memory).is_err());
+    }
+}
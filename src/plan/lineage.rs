@@ -0,0 +1,221 @@
+//! Column lineage.
+//!
+//! `LogicalPlan::lineage()` traces each output attribute of a plan back to the source columns its
+//! values come from, with the expressions computing them. Sources are named by the catalog name of
+//! their scan (see `Catalog::scan`), scans of views that weren't looked up by name have no table.
+
+use ::allocator;
+use ::block::View;
+use ::error::DBError;
+use ::expression::{BoundExpr, bound_attribute};
+use ::schema::Schema;
+
+use super::LogicalPlan;
+
+/// Column of a scanned table
+#[derive(Clone, PartialEq, Debug)]
+pub struct SourceColumn {
+    pub table: Option<String>,
+    pub column: String,
+}
+
+/// Provenance of an output attribute
+#[derive(Clone, PartialEq, Debug)]
+pub struct ColumnLineage {
+    pub name: String,
+    /// Source columns of the values, without duplicates
+    pub sources: Vec<SourceColumn>,
+    /// Descriptions of the expressions computing the values from the sources, outermost first
+    pub transforms: Vec<String>,
+}
+
+/// Lineage of the output attributes of the scanned view
+fn scan_lineage(table: &Option<String>, schema: &Schema) -> Vec<ColumnLineage> {
+    schema.iter()
+        .map(|attr| ColumnLineage {
+            name: attr.name.clone(),
+            sources: vec![SourceColumn { table: table.clone(), column: attr.name.clone() }],
+            transforms: Vec::new(),
+        })
+        .collect()
+}
+
+/// Input attribute lineage as the (renamed) output attributes of the projection
+fn project(input: Vec<ColumnLineage>, sources: Vec<(usize, usize)>, output: &Schema)
+    -> Vec<ColumnLineage>
+{
+    sources.iter().zip(output.iter())
+        .map(|(&(_, pos), attr)| ColumnLineage { name: attr.name.clone(), .. input[pos].clone() })
+        .collect()
+}
+
+/// Descriptions of the expression nodes, skipping references to input attributes
+fn transforms(expr: &BoundExpr, input: &Schema, out: &mut Vec<String>) {
+    let positions = expr.input_positions();
+    let reference = expr.children().is_empty()
+        && positions.len() == 1
+        && expr.schema().count() == 1
+        && expr.schema()[0] == input[positions[0]];
+
+    if !reference {
+        out.push(expr.describe());
+    }
+
+    for child in expr.children() {
+        transforms(child, input, out);
+    }
+}
+
+/// Lineage of the expression of the input attributes
+fn expr_lineage(expr: &BoundExpr, input: &Schema, lineage: &[ColumnLineage])
+    -> Result<ColumnLineage, DBError>
+{
+    let mut out = ColumnLineage {
+        name: bound_attribute(expr)?.name.clone(),
+        sources: Vec::new(),
+        transforms: Vec::new(),
+    };
+
+    transforms(expr, input, &mut out.transforms);
+
+    for pos in expr.input_positions() {
+        for source in &lineage[pos].sources {
+            if !out.sources.contains(source) {
+                out.sources.push(source.clone());
+            }
+        }
+        for transform in &lineage[pos].transforms {
+            if !out.transforms.contains(transform) {
+                out.transforms.push(transform.clone());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Lineage of each output attribute of the plan, in schema order
+pub fn lineage(plan: &LogicalPlan) -> Result<Vec<ColumnLineage>, DBError> {
+    match *plan {
+        LogicalPlan::Scan { src, ref table, ref projection, .. } => {
+            let input = scan_lineage(table, src.schema());
+            match *projection {
+                Some(ref proj) => {
+                    let bound = proj.bind(src.schema())?;
+                    Ok(project(input, bound.sources(), &bound.schema))
+                },
+                None => Ok(input),
+            }
+        },
+        LogicalPlan::IndexScan { src, ref table, ref projection, .. } => {
+            let input = scan_lineage(table, src.schema());
+            match *projection {
+                Some(ref proj) => {
+                    let bound = proj.bind(src.schema())?;
+                    Ok(project(input, bound.sources(), &bound.schema))
+                },
+                None => Ok(input),
+            }
+        },
+        LogicalPlan::Project { ref input, ref proj } => {
+            let bound = proj.bind(&input.schema()?)?;
+            Ok(project(lineage(input)?, bound.sources(), &bound.schema))
+        },
+        LogicalPlan::Join { ref left, ref right, .. } => {
+            let mut out = lineage(left)?;
+            out.extend(lineage(right)?);
+            Ok(out)
+        },
+        LogicalPlan::Aggregate { ref input, ref group_by, ref aggregates } => {
+            let schema = input.schema()?;
+            let input = lineage(input)?;
+            let mut out = Vec::new();
+
+            for name in group_by {
+                out.push(input[schema.exists_ok(name)?].clone());
+            }
+            // Binding is only used for the expression tree
+            for expr in aggregates {
+                let bound = expr.bind(&allocator::GLOBAL, &schema)?;
+                out.push(expr_lineage(&*bound, &schema, &input)?);
+            }
+
+            Ok(out)
+        },
+        LogicalPlan::Filter { ref input, .. }
+            | LogicalPlan::Sort { ref input, .. }
+            | LogicalPlan::Limit { ref input, .. } => lineage(input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::catalog::Catalog;
+    use ::expression::approx::register_approx;
+    use ::expression::column::ColumnExpr;
+    use ::expression::string::StringExpr;
+    use ::expression::udf::FunctionRegistry;
+    use ::projector::{BuildSingleSourceProjector, project_by_name};
+    use ::schema::Attribute;
+    use ::table::Table;
+    use ::types::Type;
+
+    fn source(table: &str, column: &str) -> SourceColumn {
+        SourceColumn { table: Some(table.to_string()), column: column.to_string() }
+    }
+
+    // Sources follow renames and joins, aggregates list their expressions
+    #[test]
+    fn plan_lineage() {
+        let attr = |name: &str, dtype| Attribute { name: name.to_string(), nullable: false,
+                                                   dtype: dtype };
+        let orders = Table::new(&allocator::GLOBAL, &Schema::from_vec(vec![
+            attr("id", Type::INT64),
+            attr("customer", Type::INT64),
+        ]).unwrap(), None);
+        let customers = Table::new(&allocator::GLOBAL, &Schema::from_vec(vec![
+            attr("cid", Type::INT64),
+            attr("name", Type::TEXT),
+        ]).unwrap(), None);
+
+        let mut catalog = Catalog::new();
+        catalog.register_view("orders", &orders);
+        catalog.register_view("customers", &customers);
+
+        let mut functions = FunctionRegistry::new();
+        register_approx(&mut functions);
+
+        let proj = BuildSingleSourceProjector::new()
+            .add(project_by_name("id"))
+            .add_as(project_by_name("name"), "customer_name")
+            .done();
+        let joined = catalog.scan("orders").unwrap()
+            .join(catalog.scan("customers").unwrap(),
+                  vec![("customer".to_string(), "cid".to_string())])
+            .project(proj);
+
+        let out = joined.lineage().unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1].name, "customer_name");
+        assert_eq!(out[1].sources, vec![source("customers", "name")]);
+        assert!(out[1].transforms.is_empty());
+
+        let count = functions.call_aggregate("approx_count_distinct", ColumnExpr::named("id"))
+            .unwrap();
+        let upper = StringExpr::upper(ColumnExpr::named("customer_name"));
+        let plan = joined.aggregate(vec!["customer_name".to_string()],
+                                    vec![Box::new(count), Box::new(upper)]);
+
+        let out = plan.lineage().unwrap();
+        assert_eq!(out[0].sources, vec![source("customers", "name")]);
+        assert_eq!(out[1].sources, vec![source("orders", "id")]);
+        assert_eq!(out[1].transforms, vec!["approx_count_distinct(id)"]);
+        assert_eq!(out[2].name, "upper(customer_name)");
+        assert_eq!(out[2].transforms, vec!["upper"]);
+
+        // Views that aren't scanned by name
+        let out = LogicalPlan::scan(&orders).lineage().unwrap();
+        assert_eq!(out[0].sources, vec![SourceColumn { table: None, column: "id".to_string() }]);
+    }
+}
@@ -0,0 +1,390 @@
+//! Logical query plans.
+//!
+//! A `LogicalPlan` describes what a query computes without picking the operations that compute
+//! it. Plans are rewritten by the `Optimizer` and then lowered into a physical `Operation` tree.
+//!
+//! Indexes are chosen outside of the optimizer rules: `use_text_index` turns the scans of a table
+//! filtered by `text_matches` on an indexed column into index scans.
+//!
+//! `lineage` traces the output attributes of a plan back to their source table columns.
+
+use std::cmp::min;
+
+use ::allocator::{self, MemoryTracker};
+use ::block::View;
+use ::error::DBError;
+use ::expression::{Expr, bound_attribute};
+use ::index::TextIndex;
+use ::operation::{Filter, HashAggregate, HashJoin, Limit, NanOrder, Operation, Project, ScanView,
+                  Sort, TextIndexScan};
+use ::projector::SingleSourceProjector;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::table::Table;
+
+pub mod analyze;
+pub mod lineage;
+pub mod optimizer;
+pub mod visualize;
+
+pub use self::analyze::MetricsTree;
+pub use self::lineage::{ColumnLineage, SourceColumn};
+pub use self::optimizer::{Optimizer, Rule};
+pub use self::visualize::{to_dot, to_mermaid};
+
+/// Assumed fraction of rows matching a predicate, in the absence of statistics
+pub const DEFAULT_SELECTIVITY: f64 = 0.5;
+
+/// Sort direction of a sort key
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortOrder {
+    ASC,
+    DESC,
+}
+
+/// Node of a logical plan tree
+pub enum LogicalPlan<'a> {
+    /// Rows of a view, with an optional predicate and projection pushed into the scan
+    Scan {
+        src: &'a View<'a>,
+        /// Catalog name of the view, if it was scanned by name
+        table: Option<String>,
+        predicate: Option<Box<Expr<'a> + 'a>>,
+        projection: Option<SingleSourceProjector>,
+    },
+    /// Rows of a table matching `text_matches(column, query)`, looked up in a `TextIndex` on the
+    /// column
+    IndexScan {
+        src: &'a Table<'a>,
+        table: Option<String>,
+        index: &'a TextIndex,
+        query: String,
+        projection: Option<SingleSourceProjector>,
+    },
+    /// Input rows where the BOOLEAN predicate is true
+    Filter {
+        input: Box<LogicalPlan<'a>>,
+        predicate: Box<Expr<'a> + 'a>,
+    },
+    Project {
+        input: Box<LogicalPlan<'a>>,
+        proj: SingleSourceProjector,
+    },
+    /// Equi-join on pairs of (left, right) attribute names
+    Join {
+        left: Box<LogicalPlan<'a>>,
+        right: Box<LogicalPlan<'a>>,
+        on: Vec<(String, String)>,
+    },
+    /// Group input rows by attribute names, computing the aggregate expressions for each group
+    Aggregate {
+        input: Box<LogicalPlan<'a>>,
+        group_by: Vec<String>,
+        aggregates: Vec<Box<Expr<'a> + 'a>>,
+    },
+    Sort {
+        input: Box<LogicalPlan<'a>>,
+        keys: Vec<(String, SortOrder)>,
+    },
+    /// Skip `offset` rows and return at most `count` rows
+    Limit {
+        input: Box<LogicalPlan<'a>>,
+        offset: RowOffset,
+        count: RowOffset,
+    },
+}
+
+impl<'a> LogicalPlan<'a> {
+    pub fn scan(src: &'a View<'a>) -> LogicalPlan<'a> {
+        LogicalPlan::Scan { src: src, table: None, predicate: None, projection: None }
+    }
+
+    pub fn filter<T: Expr<'a> + 'a>(self, predicate: T) -> LogicalPlan<'a> {
+        LogicalPlan::Filter { input: Box::new(self), predicate: Box::new(predicate) }
+    }
+
+    pub fn project(self, proj: SingleSourceProjector) -> LogicalPlan<'a> {
+        LogicalPlan::Project { input: Box::new(self), proj: proj }
+    }
+
+    pub fn join(self, right: LogicalPlan<'a>, on: Vec<(String, String)>) -> LogicalPlan<'a> {
+        LogicalPlan::Join { left: Box::new(self), right: Box::new(right), on: on }
+    }
+
+    pub fn aggregate(self, group_by: Vec<String>, aggregates: Vec<Box<Expr<'a> + 'a>>)
+        -> LogicalPlan<'a>
+    {
+        LogicalPlan::Aggregate { input: Box::new(self), group_by: group_by, aggregates: aggregates }
+    }
+
+    pub fn sort(self, keys: Vec<(String, SortOrder)>) -> LogicalPlan<'a> {
+        LogicalPlan::Sort { input: Box::new(self), keys: keys }
+    }
+
+    pub fn limit(self, offset: RowOffset, count: RowOffset) -> LogicalPlan<'a> {
+        LogicalPlan::Limit { input: Box::new(self), offset: offset, count: count }
+    }
+
+    /// Rebuild the node with each of its inputs replaced by `f(input)`
+    pub fn map_inputs<F>(self, f: &mut F) -> LogicalPlan<'a>
+        where F: FnMut(LogicalPlan<'a>) -> LogicalPlan<'a>
+    {
+        let mut map = |input: Box<LogicalPlan<'a>>| Box::new(f(*input));
+
+        match self {
+            LogicalPlan::Filter { input, predicate } =>
+                LogicalPlan::Filter { input: map(input), predicate: predicate },
+            LogicalPlan::Project { input, proj } =>
+                LogicalPlan::Project { input: map(input), proj: proj },
+            LogicalPlan::Join { left, right, on } => {
+                let left = map(left);
+                LogicalPlan::Join { left: left, right: map(right), on: on }
+            },
+            LogicalPlan::Aggregate { input, group_by, aggregates } =>
+                LogicalPlan::Aggregate {
+                    input: map(input),
+                    group_by: group_by,
+                    aggregates: aggregates,
+                },
+            LogicalPlan::Sort { input, keys } =>
+                LogicalPlan::Sort { input: map(input), keys: keys },
+            LogicalPlan::Limit { input, offset, count } =>
+                LogicalPlan::Limit { input: map(input), offset: offset, count: count },
+            scan @ LogicalPlan::Scan { .. } | scan @ LogicalPlan::IndexScan { .. } => scan,
+        }
+    }
+
+    /// Name the scans that don't have a name yet as scans of the catalog table `name`
+    pub fn with_table(self, name: &str) -> LogicalPlan<'a> {
+        match self {
+            LogicalPlan::Scan { src, table: None, predicate, projection } =>
+                LogicalPlan::Scan {
+                    src: src,
+                    table: Some(name.to_string()),
+                    predicate: predicate,
+                    projection: projection,
+                },
+            LogicalPlan::IndexScan { src, table: None, index, query, projection } =>
+                LogicalPlan::IndexScan {
+                    src: src,
+                    table: Some(name.to_string()),
+                    index: index,
+                    query: query,
+                    projection: projection,
+                },
+            other => other.map_inputs(&mut |input| input.with_table(name)),
+        }
+    }
+
+    /// Replace the scans of `table` filtered by `text_matches` on the `index` column (with the
+    /// filter pushed into the scan, see `Optimizer`) by scans of the index.
+    pub fn use_text_index(self, table: &'a Table<'a>, index: &'a TextIndex) -> LogicalPlan<'a> {
+        let scan = match self {
+            LogicalPlan::Scan { src, table: name, predicate: Some(predicate), projection } => {
+                // Same table if same address, views are compared as thin pointers
+                let same = src as *const View as *const u8 == table as *const Table as *const u8;
+
+                let query = match predicate.text_match() {
+                    Some((column, query)) if same && column == index.key().name =>
+                        Some(query.to_string()),
+                    _ => None,
+                };
+
+                match query {
+                    Some(query) => LogicalPlan::IndexScan {
+                        src: table,
+                        table: name,
+                        index: index,
+                        query: query,
+                        projection: projection,
+                    },
+                    None => LogicalPlan::Scan {
+                        src: src,
+                        table: name,
+                        predicate: Some(predicate),
+                        projection: projection,
+                    },
+                }
+            },
+            other => other,
+        };
+
+        scan.map_inputs(&mut |input| input.use_text_index(table, index))
+    }
+
+    /// Short description of the node (not including its inputs)
+    pub fn describe(&self) -> String {
+        match *self {
+            LogicalPlan::Scan { ref predicate, ref projection, .. } => {
+                let mut out = String::from("Scan");
+                if predicate.is_some() {
+                    out.push_str(" [predicate]");
+                }
+                if projection.is_some() {
+                    out.push_str(" [projection]");
+                }
+                out
+            },
+            LogicalPlan::IndexScan { index, ref projection, .. } => {
+                let mut out = format!("IndexScan text_matches({})", index.key().name);
+                if projection.is_some() {
+                    out.push_str(" [projection]");
+                }
+                out
+            },
+            LogicalPlan::Filter { .. }      => String::from("Filter"),
+            LogicalPlan::Project { .. }     => String::from("Project"),
+            LogicalPlan::Join { ref on, .. } => {
+                let keys: Vec<String> = on.iter().map(|k| format!("{} = {}", k.0, k.1)).collect();
+                format!("Join on {}", keys.join(", "))
+            },
+            LogicalPlan::Aggregate { ref group_by, .. } if group_by.is_empty() =>
+                String::from("Aggregate"),
+            LogicalPlan::Aggregate { ref group_by, .. } =>
+                format!("Aggregate by {}", group_by.join(", ")),
+            LogicalPlan::Sort { ref keys, .. } => {
+                let keys: Vec<String> = keys.iter().map(|k| format!("{} {:?}", k.0, k.1)).collect();
+                format!("Sort by {}", keys.join(", "))
+            },
+            LogicalPlan::Limit { offset, count, .. } =>
+                format!("Limit {} offset {}", count, offset),
+        }
+    }
+
+    /// Input plans of the node
+    pub fn inputs(&self) -> Vec<&LogicalPlan<'a>> {
+        match *self {
+            LogicalPlan::Scan { .. } | LogicalPlan::IndexScan { .. } => Vec::new(),
+            LogicalPlan::Join { ref left, ref right, .. } => vec![&**left, &**right],
+            LogicalPlan::Filter { ref input, .. }
+                | LogicalPlan::Project { ref input, .. }
+                | LogicalPlan::Aggregate { ref input, .. }
+                | LogicalPlan::Sort { ref input, .. }
+                | LogicalPlan::Limit { ref input, .. } => vec![&**input],
+        }
+    }
+
+    /// The plan tree, one node per line with inputs indented under their parent
+    pub fn explain(&self) -> String {
+        fn walk(plan: &LogicalPlan, depth: usize, out: &mut Vec<String>) {
+            out.push(format!("{}{}", "  ".repeat(depth), plan.describe()));
+            for input in plan.inputs() {
+                walk(input, depth + 1, out);
+            }
+        }
+
+        let mut lines = Vec::new();
+        walk(self, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    /// Run the plan and render it like `explain()`, each node annotated with the rows, chunks,
+    /// time and peak `memory` of its operation (see `analyze`)
+    pub fn explain_analyze<'b: 'a>(self, memory: &'b MemoryTracker<'b>) -> Result<String, DBError> {
+        analyze::explain_analyze(self, memory)
+    }
+
+    /// Output schema of the node
+    pub fn schema(&self) -> Result<Schema, DBError> {
+        match *self {
+            LogicalPlan::Scan { src, ref projection, .. } => match *projection {
+                Some(ref proj) => Ok(proj.bind(src.schema())?.schema),
+                None => Ok(src.schema().clone()),
+            },
+            LogicalPlan::IndexScan { src, ref projection, .. } => match *projection {
+                Some(ref proj) => Ok(proj.bind(src.schema())?.schema),
+                None => Ok(src.schema().clone()),
+            },
+            LogicalPlan::Project { ref input, ref proj } => Ok(proj.bind(&input.schema()?)?.schema),
+            LogicalPlan::Join { ref left, ref right, .. } => {
+                let mut attrs: Vec<_> = left.schema()?.iter().cloned().collect();
+                attrs.extend(right.schema()?.iter().cloned());
+                Schema::from_vec(attrs)
+            },
+            LogicalPlan::Aggregate { ref input, ref group_by, ref aggregates } => {
+                let input = input.schema()?;
+                let mut attrs = Vec::new();
+
+                for name in group_by {
+                    attrs.push(input.find(name)?.clone());
+                }
+                // Binding is only used for the output attribute
+                for expr in aggregates {
+                    attrs.push(bound_attribute(&*expr.bind(&allocator::GLOBAL, &input)?)?.clone());
+                }
+
+                Schema::from_vec(attrs)
+            },
+            LogicalPlan::Filter { ref input, .. }
+                | LogicalPlan::Sort { ref input, .. }
+                | LogicalPlan::Limit { ref input, .. } => input.schema(),
+        }
+    }
+
+    /// Source columns and expressions of each output attribute, see `lineage`
+    pub fn lineage(&self) -> Result<Vec<ColumnLineage>, DBError> {
+        lineage::lineage(self)
+    }
+
+    /// Guess of the number of output rows, from the source sizes and `DEFAULT_SELECTIVITY`
+    pub fn estimated_rows(&self) -> RowOffset {
+        let filtered = |rows: RowOffset| (rows as f64 * DEFAULT_SELECTIVITY).ceil() as RowOffset;
+
+        match *self {
+            LogicalPlan::Scan { src, ref predicate, .. } if predicate.is_some() =>
+                filtered(src.rows()),
+            LogicalPlan::Scan { src, .. } => src.rows(),
+            LogicalPlan::IndexScan { src, .. } => filtered(src.rows()),
+            LogicalPlan::Filter { ref input, .. } => filtered(input.estimated_rows()),
+            // Assume each row matches one row of the other side
+            LogicalPlan::Join { ref left, ref right, .. } =>
+                left.estimated_rows().max(right.estimated_rows()),
+            LogicalPlan::Aggregate { ref group_by, .. } if group_by.is_empty() => 1,
+            LogicalPlan::Limit { ref input, offset, count } =>
+                min(count, input.estimated_rows().saturating_sub(offset)),
+            LogicalPlan::Project { ref input, .. }
+                | LogicalPlan::Aggregate { ref input, .. }
+                | LogicalPlan::Sort { ref input, .. } => input.estimated_rows(),
+        }
+    }
+
+    /// Rows to fetch at a time from the cursor of the lowered plan, for batches of about
+    /// `batch_bytes` bytes of the output schema (see `Schema::batch_rows()`)
+    pub fn batch_rows(&self, batch_bytes: usize) -> Result<RowOffset, DBError> {
+        Ok(self.schema()?.batch_rows(batch_bytes))
+    }
+
+    /// Convert into a tree of physical operations.
+    ///
+    /// Filters of scans are better pushed into the scan first (see `Optimizer`), other filters are
+    /// lowered to a `Filter` operation. Joins are lowered to a `HashJoin` and aggregates to a
+    /// `HashAggregate`.
+    pub fn lower(self) -> Result<Box<Operation<'a> + 'a>, DBError> {
+        match self {
+            LogicalPlan::Scan { src, predicate, projection, .. } => {
+                let mut scan = ScanView::new(src, None);
+                scan.predicate = predicate;
+                scan.projection = projection;
+                Ok(Box::new(scan))
+            },
+            LogicalPlan::IndexScan { src, index, query, projection, .. } => {
+                let mut scan = TextIndexScan::new(src, index, query);
+                scan.projection = projection;
+                Ok(Box::new(scan))
+            },
+            LogicalPlan::Filter { input, predicate } =>
+                Ok(Box::new(Filter { src: input.lower()?, predicate: predicate })),
+            LogicalPlan::Project { input, proj } =>
+                Ok(Box::new(Project { src: input.lower()?, proj: proj })),
+            LogicalPlan::Join { left, right, on } =>
+                Ok(Box::new(HashJoin { left: left.lower()?, right: right.lower()?, on: on })),
+            LogicalPlan::Aggregate { input, group_by, aggregates } => Ok(Box::new(HashAggregate {
+                src: input.lower()?, group_by: group_by, aggregates: aggregates,
+            })),
+            LogicalPlan::Sort { input, keys } =>
+                Ok(Box::new(Sort { src: input.lower()?, keys: keys, nans: NanOrder::default() })),
+            LogicalPlan::Limit { input, offset, count } =>
+                Ok(Box::new(Limit { src: input.lower()?, offset: offset, count: count })),
+        }
+    }
+}
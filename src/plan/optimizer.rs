@@ -0,0 +1,389 @@
+//! Rule-based logical plan optimizer.
+//!
+//! Rules rewrite a single plan node whose inputs have already been rewritten. The optimizer
+//! applies all the rules bottom-up, repeating until no rule changes the plan.
+
+use std::cmp::min;
+
+use super::LogicalPlan;
+
+/// A plan rewrite
+pub trait Rule {
+    fn name(&self) -> &'static str;
+
+    /// Rewritten plan node and whether the rule changed it
+    fn apply<'a>(&self, plan: LogicalPlan<'a>) -> (LogicalPlan<'a>, bool);
+}
+
+/// Moves filters towards (and into) scans. The scan applies the predicate before its projection,
+/// so filters are only pushed into scans that don't have a projection yet.
+pub struct PushDownFilter;
+
+/// Moves projections towards (and into) scans, so scans only return the columns that are used.
+pub struct PruneProjection;
+
+/// Folds nested limits into a single limit.
+pub struct FoldLimits;
+
+/// Applies optimization rules to a logical plan
+pub struct Optimizer {
+    rules: Vec<Box<Rule>>,
+    /// Upper bound of rewrite passes, in case rules keep undoing each other
+    max_passes: usize,
+}
+
+impl Rule for PushDownFilter {
+    fn name(&self) -> &'static str {
+        "push down filter"
+    }
+
+    fn apply<'a>(&self, plan: LogicalPlan<'a>) -> (LogicalPlan<'a>, bool) {
+        let (input, predicate) = match plan {
+            LogicalPlan::Filter { input, predicate } => (input, predicate),
+            other => return (other, false),
+        };
+
+        match *input {
+            LogicalPlan::Scan { src, table, predicate: None, projection: None } => {
+                let scan = LogicalPlan::Scan {
+                    src: src,
+                    table: table,
+                    predicate: Some(predicate),
+                    projection: None,
+                };
+                (scan, true)
+            },
+            // Filtering doesn't change the order of rows
+            LogicalPlan::Sort { input, keys } => {
+                let filter = LogicalPlan::Filter { input: input, predicate: predicate };
+                (LogicalPlan::Sort { input: Box::new(filter), keys: keys }, true)
+            },
+            other => (LogicalPlan::Filter { input: Box::new(other), predicate: predicate }, false),
+        }
+    }
+}
+
+impl Rule for PruneProjection {
+    fn name(&self) -> &'static str {
+        "prune projection"
+    }
+
+    fn apply<'a>(&self, plan: LogicalPlan<'a>) -> (LogicalPlan<'a>, bool) {
+        let (input, proj) = match plan {
+            LogicalPlan::Project { input, proj } => (input, proj),
+            other => return (other, false),
+        };
+
+        match *input {
+            LogicalPlan::Scan { src, table, predicate, projection: None } => {
+                let scan = LogicalPlan::Scan {
+                    src: src,
+                    table: table,
+                    predicate: predicate,
+                    projection: Some(proj),
+                };
+                (scan, true)
+            },
+            LogicalPlan::IndexScan { src, table, index, query, projection: None } => {
+                let scan = LogicalPlan::IndexScan {
+                    src: src,
+                    table: table,
+                    index: index,
+                    query: query,
+                    projection: Some(proj),
+                };
+                (scan, true)
+            },
+            // Projection doesn't change the number of rows
+            LogicalPlan::Limit { input, offset, count } => {
+                let project = Box::new(LogicalPlan::Project { input: input, proj: proj });
+                (LogicalPlan::Limit { input: project, offset: offset, count: count }, true)
+            },
+            other => (LogicalPlan::Project { input: Box::new(other), proj: proj }, false),
+        }
+    }
+}
+
+impl Rule for FoldLimits {
+    fn name(&self) -> &'static str {
+        "fold limits"
+    }
+
+    fn apply<'a>(&self, plan: LogicalPlan<'a>) -> (LogicalPlan<'a>, bool) {
+        let (input, offset, count) = match plan {
+            LogicalPlan::Limit { input, offset, count } => (input, offset, count),
+            other => return (other, false),
+        };
+
+        match *input {
+            LogicalPlan::Limit { input: inner, offset: inner_offset, count: inner_count } => {
+                let folded = LogicalPlan::Limit {
+                    input: inner,
+                    offset: inner_offset + offset,
+                    count: min(count, inner_count.saturating_sub(offset)),
+                };
+                (folded, true)
+            },
+            other => {
+                let input = Box::new(other);
+                (LogicalPlan::Limit { input: input, offset: offset, count: count }, false)
+            },
+        }
+    }
+}
+
+/// Rewrite the inputs of the node, returning whether any of them changed
+fn rewrite_inputs<'a>(plan: LogicalPlan<'a>, optimizer: &Optimizer) -> (LogicalPlan<'a>, bool) {
+    let mut changed = false;
+
+    let out = plan.map_inputs(&mut |input| {
+        let (out, input_changed) = optimizer.rewrite(input);
+        changed |= input_changed;
+        out
+    });
+
+    (out, changed)
+}
+
+impl Optimizer {
+    /// Optimizer with the default rules: filter pushdown, projection pruning and limit folding.
+    pub fn new() -> Optimizer {
+        Optimizer {
+            rules: vec![Box::new(PushDownFilter), Box::new(PruneProjection), Box::new(FoldLimits)],
+            max_passes: 16,
+        }
+    }
+
+    /// Optimizer with only the provided rules
+    pub fn with_rules(rules: Vec<Box<Rule>>) -> Optimizer {
+        Optimizer { rules: rules, max_passes: 16 }
+    }
+
+    /// Single bottom-up pass over the plan
+    fn rewrite<'a>(&self, plan: LogicalPlan<'a>) -> (LogicalPlan<'a>, bool) {
+        let (mut plan, mut changed) = rewrite_inputs(plan, self);
+
+        for rule in &self.rules {
+            let (out, rule_changed) = rule.apply(plan);
+            if rule_changed {
+                debug!("optimizer rule {} applied to {}", rule.name(), out.describe());
+            }
+
+            plan = out;
+            changed |= rule_changed;
+        }
+
+        (plan, changed)
+    }
+
+    pub fn optimize<'a>(&self, plan: LogicalPlan<'a>) -> LogicalPlan<'a> {
+        let mut plan = plan;
+
+        for _ in 0 .. self.max_passes {
+            let (out, changed) = self.rewrite(plan);
+            plan = out;
+
+            if !changed {
+                break
+            }
+        }
+
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View, column_row_data};
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::expression::text::TextMatchesExpr;
+    use ::index::TextIndex;
+    use ::operation::{CursorChunk, DEFAULT_CURSOR_FETCH};
+    use ::plan::SortOrder;
+    use ::projector::project_by_name;
+    use ::schema::{Attribute, Schema};
+    use ::table::{Table, TableAppender};
+    use ::types::{Type, UInt32};
+
+    fn make_block() -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: false, dtype: Type::UINT32},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 10 {
+                appender = appender.add_row().set(v as u32).set((v % 3) as u32);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    // Filter and projection end up in the scan, nested limits are folded
+    #[test]
+    fn optimize_and_lower() {
+        let block = make_block();
+
+        let plan = LogicalPlan::scan(&block)
+            .filter(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b")))
+            .limit(1, 5)
+            .project(project_by_name("a"))
+            .limit(0, 1);
+
+        let plan = Optimizer::new().optimize(plan);
+        assert_eq!(plan.explain(), "Limit 1 offset 1\n  Scan [predicate] [projection]");
+
+        let op = plan.lower().unwrap();
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+        assert_eq!(cursor.schema().count(), 1);
+
+        // Rows with a == b are 0, 1 and 2, skipping the first leaves 1
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert_eq!(out, vec![1]);
+    }
+
+    #[test]
+    fn filter_below_sort() {
+        let block = make_block();
+
+        let plan = LogicalPlan::scan(&block)
+            .sort(vec![("a".to_string(), SortOrder::DESC)])
+            .filter(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b")));
+
+        let plan = Optimizer::new().optimize(plan);
+        assert_eq!(plan.explain(), "Sort by a DESC\n  Scan [predicate]");
+
+        let op = plan.lower().unwrap();
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert_eq!(out, vec![2, 1, 0]);
+    }
+
+    // Filters that can't be pushed into the scan are lowered to a filter operation
+    #[test]
+    fn filter_above_limit() {
+        let block = make_block();
+
+        let plan = LogicalPlan::scan(&block)
+            .limit(1, 4)
+            .filter(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b")));
+
+        let plan = Optimizer::new().optimize(plan);
+        assert_eq!(plan.explain(), "Filter\n  Limit 4 offset 1\n    Scan");
+
+        let op = plan.lower().unwrap();
+        assert_eq!(op.explain(), "Filter\n  Limit 4 offset 1\n    ScanView");
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert_eq!(out, vec![1, 2]);
+    }
+
+    // Joins and aggregates are lowered to hash operations
+    #[test]
+    fn join_and_aggregate() {
+        let block = make_block();
+        let mut keys = Table::new(&allocator::GLOBAL,
+                                  &Schema::make_one_attr("k", false, Type::UINT32), None);
+        {
+            let status = TableAppender::new(&mut keys)
+                .add_row().set(1 as u32)
+                .add_row().set(0 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        let keys = keys.take().unwrap();
+
+        let plan = LogicalPlan::scan(&block)
+            .join(LogicalPlan::scan(&keys), vec![("b".to_string(), "k".to_string())])
+            .aggregate(vec!["k".to_string()], Vec::new())
+            .sort(vec![("k".to_string(), SortOrder::DESC)]);
+
+        let op = Optimizer::new().optimize(plan).lower().unwrap();
+        assert_eq!(op.explain(), "Sort by k DESC\n  HashAggregate by k\n    \
+                                  HashJoin on b = k\n      ScanView\n      ScanView");
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert_eq!(out, vec![1, 0]);
+    }
+
+    // Scans filtered by text_matches on the indexed column become index scans
+    #[test]
+    fn text_index_scan() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "msg".to_string(), nullable: true, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1 as u32).set("disk full")
+                .add_row().set(2 as u32).set("Disk is FULL again")
+                .add_row().set(3 as u32).set_null(true)
+                .add_row().set(4 as u32).set("disk ok")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let index = TextIndex::build(&table, "msg").unwrap();
+
+        let plan = LogicalPlan::scan(&table)
+            .filter(TextMatchesExpr::new("msg", "full disk"))
+            .project(project_by_name("id"));
+
+        let plan = Optimizer::new().optimize(plan).use_text_index(&table, &index);
+        assert_eq!(plan.explain(), "IndexScan text_matches(msg) [projection]");
+        assert_eq!(plan.schema().unwrap().count(), 1);
+
+        let op = plan.lower().unwrap();
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert_eq!(out, vec![1, 2]);
+
+        // Other columns are not indexed
+        let plan = LogicalPlan::scan(&table).filter(TextMatchesExpr::new("id", "1"));
+        let plan = Optimizer::new().optimize(plan).use_text_index(&table, &index);
+        assert_eq!(plan.explain(), "Scan [predicate]");
+    }
+}
@@ -0,0 +1,155 @@
+//! Plan diagrams.
+//!
+//! Renders a `LogicalPlan` as a Graphviz DOT or Mermaid graph. Nodes show the plan node and its
+//! output schema, edges flow from input to consumer and are labeled with the estimated rows.
+
+use super::LogicalPlan;
+
+/// Plan node in the diagram
+struct Node {
+    id: usize,
+    /// Lines of the node label
+    label: Vec<String>,
+    /// Consumer of the node output
+    parent: Option<usize>,
+    rows: usize,
+}
+
+fn collect(plan: &LogicalPlan, parent: Option<usize>, out: &mut Vec<Node>) {
+    let id = out.len();
+
+    let schema = match plan.schema() {
+        Ok(schema) => {
+            let attrs: Vec<String> = schema.iter()
+                .map(|a| {
+                    let null = if a.nullable { "" } else { " NOT NULL" };
+                    format!("{} {}{}", a.name, a.dtype, null)
+                })
+                .collect();
+            attrs.join(", ")
+        },
+        Err(e) => format!("schema error: {}", e),
+    };
+
+    out.push(Node {
+        id: id,
+        label: vec![plan.describe(), schema],
+        parent: parent,
+        rows: plan.estimated_rows(),
+    });
+
+    for input in plan.inputs() {
+        collect(input, Some(id), out);
+    }
+}
+
+fn nodes(plan: &LogicalPlan) -> Vec<Node> {
+    let mut out = Vec::new();
+    collect(plan, None, &mut out);
+    out
+}
+
+/// Graphviz DOT digraph of the plan
+pub fn to_dot(plan: &LogicalPlan) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let nodes = nodes(plan);
+
+    let mut out = vec![String::from("digraph plan {"), String::from("  rankdir=BT;")];
+
+    for node in &nodes {
+        let label: Vec<String> = node.label.iter().map(|l| escape(l)).collect();
+        out.push(format!("  n{} [shape=box, label=\"{}\"];", node.id, label.join("\\n")));
+    }
+
+    for node in &nodes {
+        if let Some(parent) = node.parent {
+            out.push(format!("  n{} -> n{} [label=\"~{} rows\"];", node.id, parent, node.rows));
+        }
+    }
+
+    out.push(String::from("}"));
+    out.join("\n")
+}
+
+/// Mermaid flowchart of the plan
+pub fn to_mermaid(plan: &LogicalPlan) -> String {
+    let escape = |s: &str| s.replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;");
+    let nodes = nodes(plan);
+
+    let mut out = vec![String::from("graph BT")];
+
+    for node in &nodes {
+        let label: Vec<String> = node.label.iter().map(|l| escape(l)).collect();
+        out.push(format!("  n{}[\"{}\"]", node.id, label.join("<br/>")));
+    }
+
+    for node in &nodes {
+        if let Some(parent) = node.parent {
+            out.push(format!("  n{} -->|~{} rows| n{}", node.id, node.rows, parent));
+        }
+    }
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::expression::column::ColumnExpr;
+    use ::expression::comparison::EqaulsExpr;
+    use ::projector::project_by_name;
+    use ::schema::{Attribute, Schema};
+    use ::table::{Table, TableAppender};
+    use ::types::Type;
+
+    fn make_block() -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: true, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 10 {
+                appender = appender.add_row().set(v as u32).set("x");
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    #[test]
+    fn dot_and_mermaid() {
+        let block = make_block();
+        let plan = LogicalPlan::scan(&block)
+            .filter(EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("a")))
+            .project(project_by_name("a"))
+            .limit(0, 3);
+
+        let expected = vec![
+            "digraph plan {",
+            "  rankdir=BT;",
+            "  n0 [shape=box, label=\"Limit 3 offset 0\\na UINT32 NOT NULL\"];",
+            "  n1 [shape=box, label=\"Project\\na UINT32 NOT NULL\"];",
+            "  n2 [shape=box, label=\"Filter\\na UINT32 NOT NULL, b TEXT\"];",
+            "  n3 [shape=box, label=\"Scan\\na UINT32 NOT NULL, b TEXT\"];",
+            "  n1 -> n0 [label=\"~5 rows\"];",
+            "  n2 -> n1 [label=\"~5 rows\"];",
+            "  n3 -> n2 [label=\"~10 rows\"];",
+            "}",
+        ];
+        assert_eq!(to_dot(&plan), expected.join("\n"));
+
+        let mermaid = to_mermaid(&plan);
+        assert!(mermaid.starts_with("graph BT\n  n0[\"Limit 3 offset 0<br/>a UINT32 NOT NULL\"]"));
+        assert!(mermaid.ends_with("  n3 -->|~10 rows| n2"));
+    }
+}
@@ -59,6 +59,20 @@ pub fn project_by_name<S: ToString>(name: S) ->  SingleSourceProjector {
     SingleSourceProjector(vec![Projector(Source::NAME(name.to_string()), As::ORIG)])
 }
 
+/// Project the named attributes from source, in order
+pub fn project_names(names: &[&str]) -> SingleSourceProjector {
+    SingleSourceProjector(names.iter()
+        .map(|n| Projector(Source::NAME(n.to_string()), As::ORIG))
+        .collect())
+}
+
+/// Project the (name, output name) attributes from source, in order
+pub fn project_renamed(names: &[(&str, &str)]) -> SingleSourceProjector {
+    SingleSourceProjector(names.iter()
+        .map(|&(name, out)| Projector(Source::NAME(name.to_string()), As::NEW(out.to_string())))
+        .collect())
+}
+
 fn mk_bound_attr(input: &Schema, pos: usize, out: &As) -> Result<BoundAttribute, DBError> {
     input.get(pos)
         .map(|attr| match *out {
@@ -78,7 +92,7 @@ impl SingleSourceProjector {
                 Source::POS(pos) =>
                     bound.push(mk_bound_attr(input, pos, &proj.1)?),
                 Source::NAME(ref name) =>
-                    bound.push(mk_bound_attr(input, input.exists_ok(name.as_str())?, &proj.1)?),
+                    bound.push(mk_bound_attr(input, input.resolve(name.as_str())?, &proj.1)?),
                 Source::ALL =>
                     for pos in 0..input.count() {
                         bound.push(mk_bound_attr(input, pos, &proj.1)?)
@@ -89,6 +103,26 @@ impl SingleSourceProjector {
         let attrs = bound.iter().map(|e| e.2.clone()).collect();
         Ok(BoundProjector { schema: Schema::from_vec(attrs)?, bound_attrs: bound })
     }
+
+    /// The projected attributes, without binding to a source schema. Eg. "a, #2, b AS c, * AS l_*"
+    /// (by position, renamed and all attributes prefixed)
+    pub fn describe(&self) -> String {
+        let attrs: Vec<String> = self.0.iter()
+            .map(|proj| {
+                let src = match proj.0 {
+                    Source::POS(pos)        => format!("#{}", pos),
+                    Source::NAME(ref name)  => name.clone(),
+                    Source::ALL             => String::from("*"),
+                };
+                match proj.1 {
+                    As::ORIG                => src,
+                    As::PREFIX(ref prefix)  => format!("{} AS {}{}", src, prefix, src),
+                    As::NEW(ref name)       => format!("{} AS {}", src, name),
+                }
+            })
+            .collect();
+        attrs.join(", ")
+    }
 }
 
 impl BuildSingleSourceProjector {
@@ -132,6 +166,11 @@ impl MultiSourceProjector {
 }
 
 impl BoundProjector {
+    /// Input (source, attribute position) of each output attribute
+    pub fn sources(&self) -> Vec<(usize, usize)> {
+        self.bound_attrs.iter().map(|b| (b.0, b.1)).collect()
+    }
+
     pub fn project_view<'a>(&self, src: &'a View<'a>) -> Result<RefView<'a>, DBError> {
         let mut columns = Vec::new();
         let schema = src.schema().clone();
@@ -147,5 +186,20 @@ impl BoundProjector {
         let out = RefView::new(schema, columns, rows);
         Ok(out)
     }
+
+    /// Project the columns of a `RefView`. The output aliases the same data as `src` (and is not
+    /// tied to the lifetime of `src`).
+    pub fn project_ref_view<'a>(&self, src: &RefView<'a>) -> Result<RefView<'a>, DBError> {
+        let mut columns = Vec::new();
+
+        for bound_attr in &self.bound_attrs {
+            let c = src.columns().get(bound_attr.1)
+                .ok_or(DBError::make_column_unknown_pos(bound_attr.1))?;
+
+            columns.push(c.clone());
+        }
+
+        Ok(RefView::new(self.schema.clone(), columns, src.rows()))
+    }
 }
 
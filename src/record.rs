@@ -0,0 +1,156 @@
+// vim: set ts=4 sw=4 et :
+
+//! Mapping Rust structs to rows.
+//!
+//! A `Record` type has a schema and converts to and from a row of values, so a slice of records
+//! can be loaded into a `Table` (`records_table`) and the rows of a view collected back into
+//! records (`collect_records`). `#[derive(Record)]` from the `dbkit-derive` crate implements it
+//! for structs with named fields of `RecordField` types, an attribute per field:
+//!
+//! - `u32`, `u64`, `i32`, `i64`, `f32`, `f64`, `bool`: the same type
+//! - `String`: TEXT, `Vec<u8>`: BLOB
+//! - `Option<T>`: nullable attribute of `T`'s type
+
+use ::allocator::Allocator;
+use ::block::{Block, View};
+use ::error::DBError;
+use ::schema::Schema;
+use ::table::Table;
+use ::types::{Type, Value};
+
+/// Type of a record field
+pub trait RecordField: Sized {
+    fn dtype() -> Type;
+
+    fn nullable() -> bool {
+        false
+    }
+
+    fn to_value(&self) -> Value;
+
+    /// Field of the value, `None` if the value isn't of the field type
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+/// Struct stored as a row of its schema
+pub trait Record: Sized {
+    fn schema() -> Schema;
+
+    /// Values of the fields, in schema order
+    fn into_row(&self) -> Vec<Value>;
+
+    fn from_row(row: &[Value]) -> Result<Self, DBError>;
+}
+
+macro_rules! record_field {
+    ($native:ty, $dtype:ident) => {
+        impl RecordField for $native {
+            fn dtype() -> Type {
+                Type::$dtype
+            }
+
+            fn to_value(&self) -> Value {
+                Value::$dtype(*self)
+            }
+
+            fn from_value(value: &Value) -> Option<$native> {
+                match *value {
+                    Value::$dtype(v) => Some(v),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+record_field!(u32, UINT32);
+record_field!(u64, UINT64);
+record_field!(i32, INT32);
+record_field!(i64, INT64);
+record_field!(f32, FLOAT32);
+record_field!(f64, FLOAT64);
+record_field!(bool, BOOLEAN);
+
+impl RecordField for String {
+    fn dtype() -> Type {
+        Type::TEXT
+    }
+
+    fn to_value(&self) -> Value {
+        Value::TEXT(self)
+    }
+
+    fn from_value(value: &Value) -> Option<String> {
+        match *value {
+            Value::TEXT(v) => Some(v.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl RecordField for Vec<u8> {
+    fn dtype() -> Type {
+        Type::BLOB
+    }
+
+    fn to_value(&self) -> Value {
+        Value::BLOB(self)
+    }
+
+    fn from_value(value: &Value) -> Option<Vec<u8>> {
+        match *value {
+            Value::BLOB(v) => Some(v.to_vec()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: RecordField> RecordField for Option<T> {
+    fn dtype() -> Type {
+        T::dtype()
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+
+    fn to_value(&self) -> Value {
+        match *self {
+            Some(ref v) => v.to_value(),
+            None => Value::NULL,
+        }
+    }
+
+    fn from_value(value: &Value) -> Option<Option<T>> {
+        match *value {
+            Value::NULL => Some(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// Table of a row per record
+pub fn records_table<'a, R: Record>(alloc: &'a Allocator, records: &[R])
+    -> Result<Table<'a>, DBError>
+{
+    let mut block = Block::new(alloc, &R::schema());
+    let rows: Vec<Vec<Value>> = records.iter().map(|r| r.into_row()).collect();
+    block.append_rows(&rows)?;
+    Ok(Table::from_block(block))
+}
+
+/// Record of each row of the view, the view attributes have to be in the record schema order
+pub fn collect_records<'v, R: Record>(view: &'v View<'v>) -> Result<Vec<R>, DBError> {
+    let count = view.schema().count();
+    let mut out = Vec::with_capacity(view.rows());
+
+    for row in 0 .. view.rows() {
+        let mut values = Vec::with_capacity(count);
+        for pos in 0 .. count {
+            values.push(view.value(row, pos)?);
+        }
+        out.push(R::from_row(&values)?);
+    }
+
+    Ok(out)
+}
@@ -2,25 +2,58 @@
 
 // libstd
 use std::iter::Iterator;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::ops::Index;
 
 // DBKit
 use super::error::DBError;
+use super::fuzzy::levenshtein;
+use super::row::RowOffset;
 use super::types::Type;
 
+/// Maximum number of similar names suggested by `DBError::AttributeUnknown`
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Bytes of a block (or cursor fetch) targeted by the default `Schema::batch_rows()`, small
+/// enough for the columns being processed to stay in cache
+pub const DEFAULT_BATCH_BYTES: usize = 256 * 1024;
+/// Fewest rows of a `Schema::batch_rows()` batch, however wide the rows
+pub const MIN_BATCH_ROWS: RowOffset = 64;
+/// Most rows of a `Schema::batch_rows()` batch, however narrow the rows
+pub const MAX_BATCH_ROWS: RowOffset = 64 * 1024;
+
 /// Attribute represents high level column metadata such as name, nullability and type
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Attribute {
     pub name: String,
     pub nullable: bool,
     pub dtype: Type,
 }
 
+/// Free form key / value metadata of an attribute (eg. a comment, units or an external type)
+pub type Metadata = BTreeMap<String, String>;
+
 /// Describes the attributes and organization of data
 #[derive(Clone, Default)]
 pub struct Schema {
     attrs: Vec<Attribute>,
+    /// Metadata of each attribute, in attribute order
+    metadata: Vec<Metadata>,
+}
+
+/// Fluent builder of a schema, attributes are not nullable unless added with `add_nullable()`.
+///
+/// ```ignore
+/// let schema = SchemaBuilder::new()
+///     .add("id", Type::INT64)
+///     .add_nullable("temp", Type::FLOAT64).meta("unit", "celsius")
+///     .done()?;
+/// ```
+#[derive(Default)]
+pub struct SchemaBuilder {
+    attrs: Vec<Attribute>,
+    metadata: Vec<Metadata>,
+    error: Option<DBError>,
 }
 
 pub struct AttributeIter<'a> {
@@ -30,7 +63,7 @@ pub struct AttributeIter<'a> {
 
 impl Attribute {
     pub fn rename<S: Into<String>>(&self, name: S) -> Attribute {
-        Attribute { name: name.into(), nullable: self.nullable, dtype: self.dtype }
+        Attribute { name: name.into(), nullable: self.nullable, dtype: self.dtype.clone() }
     }
 
     /// Helper methods to create a the same named attribute but of different type
@@ -47,9 +80,11 @@ impl Schema {
             if names.replace(a.name.clone()).is_some() {
                 return Err(DBError::AttributeDuplicate(a.name.clone()))
             }
+
+            validate_type(&a.name, &a.dtype)?;
         }
 
-        Ok(Schema { attrs: Vec::from(attrs) })
+        Ok(Schema { attrs: Vec::from(attrs), metadata: vec![Metadata::new(); attrs.len()] })
     }
 
     pub fn from_vec(attrs: Vec<Attribute>) -> Result<Schema, DBError> {
@@ -58,7 +93,7 @@ impl Schema {
 
     /// Create a single Attribute schema from an external attribute
     pub fn from_attr(attr: Attribute) -> Schema {
-        Schema { attrs: vec!(attr) }
+        Schema { attrs: vec!(attr), metadata: vec!(Metadata::new()) }
     }
 
     /// Create a single Attribute schema
@@ -70,6 +105,21 @@ impl Schema {
         self.attrs.len()
     }
 
+    /// Estimated bytes of a row: the `Type::estimated_width` of each attribute, and the NULL
+    /// flags of nullable attributes
+    pub fn estimated_row_width(&self) -> usize {
+        self.attrs.iter()
+            .map(|a| a.dtype.estimated_width() + a.nullable as usize)
+            .sum()
+    }
+
+    /// Number of rows of a block (or cursor fetch) of about `batch_bytes` bytes, between
+    /// `MIN_BATCH_ROWS` and `MAX_BATCH_ROWS`
+    pub fn batch_rows(&self, batch_bytes: usize) -> RowOffset {
+        let rows = batch_bytes / self.estimated_row_width().max(1);
+        rows.max(MIN_BATCH_ROWS).min(MAX_BATCH_ROWS)
+    }
+
     pub fn exists(&self, name: &str) -> Option<usize> {
         for pos in 0..self.attrs.len() {
             if &self.attrs[pos].name == name {
@@ -106,6 +156,173 @@ impl Schema {
     pub fn iter(&self) -> AttributeIter {
         AttributeIter { schema: self, cur: 0 }
     }
+
+    /// Position of the named attribute. Unknown names are an `AttributeUnknown` error listing
+    /// the similar attribute names, ones differing only in case or a few (up to a third of the
+    /// name) characters.
+    pub fn resolve(&self, name: &str) -> Result<usize, DBError> {
+        self.exists(name)
+            .ok_or_else(|| DBError::AttributeUnknown(name.to_string(), self.similar_names(name)))
+    }
+
+    /// Attribute names similar to `name`, closest first
+    pub fn similar_names(&self, name: &str) -> Vec<String> {
+        let lower = name.to_lowercase();
+        let max_distance = (name.chars().count() / 3).max(1);
+
+        let mut similar: Vec<(usize, &str)> = self.attrs.iter()
+            .map(|a| (levenshtein(&lower, &a.name.to_lowercase()), a.name.as_str()))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .collect();
+
+        similar.sort();
+        similar.into_iter().take(MAX_SUGGESTIONS).map(|(_, n)| n.to_string()).collect()
+    }
+
+    /// Schema of the named attributes (and their metadata), in the order of `names`
+    pub fn project_names(&self, names: &[&str]) -> Result<Schema, DBError> {
+        let mut attrs = Vec::with_capacity(names.len());
+        let mut metadata = Vec::with_capacity(names.len());
+
+        for name in names {
+            let pos = self.resolve(name)?;
+            attrs.push(self.attrs[pos].clone());
+            metadata.push(self.metadata[pos].clone());
+        }
+
+        let mut schema = Schema::from_vec(attrs)?;
+        schema.metadata = metadata;
+        Ok(schema)
+    }
+
+    /// Metadata of the attribute at `pos`
+    pub fn metadata(&self, pos: usize) -> Result<&Metadata, DBError> {
+        self.metadata.get(pos)
+            .ok_or_else(|| DBError::AttributeMissing(format!("(pos: {})", pos)))
+    }
+
+    /// Schema both this and the `other` schema's data can be read as, eg. of files written while
+    /// the schema drifted.
+    ///
+    /// Attributes are matched by name, in this schema's order followed by the attributes only in
+    /// `other`. Matched attributes have the `Type::common_supertype()` of their types (eg. INT64
+    /// for INT32 and INT64) and are nullable if either of them is, attributes missing from either
+    /// schema are nullable. Types without a common supertype are an `AttributeType` error.
+    pub fn merge(&self, other: &Schema) -> Result<Schema, DBError> {
+        let mut attrs = Vec::with_capacity(self.count().max(other.count()));
+        let mut metadata = Vec::with_capacity(attrs.capacity());
+
+        for (attr, meta) in self.attrs.iter().zip(&self.metadata) {
+            let merged = match other.exists(&attr.name) {
+                Some(pos) => {
+                    let theirs = &other.attrs[pos];
+                    let dtype = Type::common_supertype(&attr.dtype, &theirs.dtype)
+                        .ok_or_else(|| DBError::AttributeType(format!("{}: {} merged with {}",
+                            attr.name, attr.dtype, theirs.dtype)))?;
+                    Attribute { name: attr.name.clone(),
+                                nullable: attr.nullable || theirs.nullable, dtype: dtype }
+                },
+                None => Attribute { nullable: true, .. attr.clone() },
+            };
+
+            attrs.push(merged);
+            metadata.push(meta.clone());
+        }
+
+        for (attr, meta) in other.attrs.iter().zip(&other.metadata) {
+            if self.exists(&attr.name).is_none() {
+                attrs.push(Attribute { nullable: true, .. attr.clone() });
+                metadata.push(meta.clone());
+            }
+        }
+
+        Ok(Schema { attrs: attrs, metadata: metadata })
+    }
+
+    /// True if data of this schema can be read as the `other` schema without loss: each of the
+    /// attributes is in `other` (by name, in any order) with the same type or a type it promotes
+    /// to, and is not nullable unless the `other` attribute is. Attributes only in `other` have to
+    /// be nullable.
+    pub fn compatible_with(&self, other: &Schema) -> bool {
+        let promotes = |from: &Attribute, to: &Attribute| {
+            (to.nullable || !from.nullable) &&
+                Type::common_supertype(&from.dtype, &to.dtype) == Some(to.dtype.clone())
+        };
+
+        self.attrs.iter().all(|a| other.find(&a.name).map(|o| promotes(a, o)).unwrap_or(false)) &&
+            other.attrs.iter().all(|o| o.nullable || self.exists(&o.name).is_some())
+    }
+}
+
+impl SchemaBuilder {
+    pub fn new() -> SchemaBuilder {
+        SchemaBuilder::default()
+    }
+
+    /// Add a not nullable attribute
+    pub fn add<S: Into<String>>(self, name: S, dtype: Type) -> SchemaBuilder {
+        self.add_attr(Attribute { name: name.into(), nullable: false, dtype: dtype })
+    }
+
+    /// Add a nullable attribute
+    pub fn add_nullable<S: Into<String>>(self, name: S, dtype: Type) -> SchemaBuilder {
+        self.add_attr(Attribute { name: name.into(), nullable: true, dtype: dtype })
+    }
+
+    pub fn add_attr(mut self, attr: Attribute) -> SchemaBuilder {
+        self.attrs.push(attr);
+        self.metadata.push(Metadata::new());
+        self
+    }
+
+    /// Set a metadata key of the last added attribute
+    pub fn meta<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> SchemaBuilder {
+        match self.metadata.last_mut() {
+            Some(meta) => {
+                meta.insert(key.into(), value.into());
+            },
+            None if self.error.is_none() =>
+                self.error = Some(DBError::AttributeMissing(format!("(metadata {})", key.into()))),
+            None => (),
+        }
+        self
+    }
+
+    /// Schema of the added attributes, fails on the first error (eg. duplicate attribute names)
+    pub fn done(self) -> Result<Schema, DBError> {
+        if let Some(err) = self.error {
+            return Err(err)
+        }
+
+        let mut schema = Schema::from_vec(self.attrs)?;
+        schema.metadata = self.metadata;
+        Ok(schema)
+    }
+}
+
+/// Nested types have to be valid too: STRUCTs need at least one field and unique field names.
+fn validate_type(path: &str, dtype: &Type) -> Result<(), DBError> {
+    match *dtype {
+        Type::LIST(ref item) => validate_type(path, item),
+        Type::STRUCT(ref fields) => {
+            if fields.is_empty() {
+                return Err(DBError::AttributeType(format!("{} (STRUCT without fields)", path)))
+            }
+
+            let mut names = HashSet::with_capacity(fields.len());
+            for field in fields {
+                let name = format!("{}.{}", path, field.name);
+                if names.replace(field.name.clone()).is_some() {
+                    return Err(DBError::AttributeDuplicate(name))
+                }
+
+                validate_type(&name, &field.dtype)?;
+            }
+
+            Ok(())
+        },
+        _ => Ok(()),
+    }
 }
 
 /// Address schema attributes by their index
@@ -132,3 +349,282 @@ impl<'a> Iterator for AttributeIter<'a> {
     }
 }
 
+
+/// Attributes serialize as a `name`, `nullable`, `dtype` struct, schemas as the sequence of their
+/// attributes with an additional `metadata` map for attributes with metadata. Deserialized schemas
+/// are validated like `Schema::from_vec()`, `nullable` defaults to false.
+#[cfg(feature = "serde")]
+mod serialize {
+    use std::fmt;
+
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use ::serde::de::{self, MapAccess, Visitor};
+    use ::serde::ser::SerializeStruct;
+
+    use super::{Attribute, Metadata, Schema};
+
+    const FIELDS: &[&str] = &["name", "nullable", "dtype", "metadata"];
+
+    /// Attribute of a schema with its metadata
+    struct Entry<'a>(&'a Attribute, &'a Metadata);
+
+    struct EntryVisitor;
+
+    impl<'a> Serialize for Entry<'a> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let fields = if self.1.is_empty() { 3 } else { 4 };
+            let mut out = serializer.serialize_struct("Attribute", fields)?;
+            out.serialize_field("name", &self.0.name)?;
+            out.serialize_field("nullable", &self.0.nullable)?;
+            out.serialize_field("dtype", &self.0.dtype)?;
+            if !self.1.is_empty() {
+                out.serialize_field("metadata", self.1)?;
+            }
+            out.end()
+        }
+    }
+
+    impl<'de> Visitor<'de> for EntryVisitor {
+        type Value = (Attribute, Metadata);
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an attribute with a name and dtype")
+        }
+
+        fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+            let mut name = None;
+            let mut nullable = None;
+            let mut dtype = None;
+            let mut metadata = None;
+
+            while let Some(key) = map.next_key::<String>()? {
+                let duplicate = match key.as_str() {
+                    "name"      => name.is_some(),
+                    "nullable"  => nullable.is_some(),
+                    "dtype"     => dtype.is_some(),
+                    "metadata"  => metadata.is_some(),
+                    _           => return Err(de::Error::unknown_field(&key, FIELDS)),
+                };
+                if duplicate {
+                    return Err(de::Error::custom(format!("duplicate field `{}`", key)))
+                }
+
+                match key.as_str() {
+                    "name"      => name = Some(map.next_value()?),
+                    "nullable"  => nullable = Some(map.next_value()?),
+                    "dtype"     => dtype = Some(map.next_value()?),
+                    _           => metadata = Some(map.next_value()?),
+                }
+            }
+
+            let attr = Attribute {
+                name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+                nullable: nullable.unwrap_or(false),
+                dtype: dtype.ok_or_else(|| de::Error::missing_field("dtype"))?,
+            };
+            Ok((attr, metadata.unwrap_or_default()))
+        }
+    }
+
+    /// Deserialized attribute and its metadata
+    struct OwnedEntry(Attribute, Metadata);
+
+    impl<'de> Deserialize<'de> for OwnedEntry {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OwnedEntry, D::Error> {
+            deserializer.deserialize_struct("Attribute", FIELDS, EntryVisitor)
+                .map(|(attr, metadata)| OwnedEntry(attr, metadata))
+        }
+    }
+
+    impl Serialize for Attribute {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            Entry(self, &Metadata::new()).serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Attribute {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Attribute, D::Error> {
+            OwnedEntry::deserialize(deserializer).map(|e| e.0)
+        }
+    }
+
+    impl Serialize for Schema {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.attrs.iter().zip(&self.metadata).map(|(a, m)| Entry(a, m)))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Schema {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Schema, D::Error> {
+            let entries = Vec::<OwnedEntry>::deserialize(deserializer)?;
+            let (attrs, metadata) = entries.into_iter().map(|e| (e.0, e.1)).unzip();
+
+            let mut schema = Schema::from_vec(attrs).map_err(de::Error::custom)?;
+            schema.metadata = metadata;
+            Ok(schema)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "serde")]
+    use ::toml;
+
+    // Built attributes are not nullable by default and keep their metadata
+    #[test]
+    fn build_schema() {
+        let schema = SchemaBuilder::new()
+            .add("id", Type::INT64)
+            .add_nullable("temp", Type::FLOAT64).meta("unit", "celsius").meta("source", "probe")
+            .done()
+            .unwrap();
+
+        assert_eq!(schema.count(), 2);
+        assert!(!schema[0].nullable);
+        assert!(schema[1].nullable);
+        assert!(schema.metadata(0).unwrap().is_empty());
+        assert_eq!(schema.metadata(1).unwrap()["unit"], "celsius");
+        assert_eq!(schema.metadata(1).unwrap().len(), 2);
+        assert!(schema.metadata(2).is_err());
+
+        match SchemaBuilder::new().add("a", Type::INT32).add("a", Type::TEXT).done() {
+            Err(DBError::AttributeDuplicate(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        match SchemaBuilder::new().meta("unit", "celsius").add("a", Type::INT32).done() {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Wider rows make for fewer rows per batch, within the batch row bounds
+    #[test]
+    fn batch_rows_width() {
+        let narrow = Schema::make_one_attr("a", false, Type::INT32);
+        assert_eq!(narrow.estimated_row_width(), 4);
+        assert_eq!(narrow.batch_rows(DEFAULT_BATCH_BYTES), MAX_BATCH_ROWS);
+        assert_eq!(narrow.batch_rows(4096), 1024);
+
+        let wide = (0 .. 40)
+            .fold(SchemaBuilder::new(), |b, i| b.add_nullable(format!("t{}", i), Type::TEXT))
+            .done()
+            .unwrap();
+        let rows = wide.batch_rows(DEFAULT_BATCH_BYTES);
+        assert_eq!(wide.estimated_row_width(), 40 * (Type::TEXT.estimated_width() + 1));
+        assert!(rows > MIN_BATCH_ROWS && rows < 1024, "{} rows", rows);
+        assert_eq!(wide.batch_rows(1), MIN_BATCH_ROWS);
+
+        let nested = Type::LIST(Box::new(Type::INT64));
+        assert_eq!(nested.estimated_width(), nested.size_of() + 4 * 8);
+    }
+
+    // Merged schemas promote types and make attributes missing from either schema nullable
+    #[test]
+    fn merge_schemas() {
+        let v1 = SchemaBuilder::new()
+            .add("id", Type::INT32).meta("comment", "row id")
+            .add("name", Type::TEXT)
+            .add("score", Type::FLOAT32)
+            .done()
+            .unwrap();
+        let v2 = SchemaBuilder::new()
+            .add("id", Type::INT64)
+            .add_nullable("score", Type::FLOAT64)
+            .add("tag", Type::TEXT)
+            .done()
+            .unwrap();
+
+        let merged = v1.merge(&v2).unwrap();
+        let names: Vec<&str> = merged.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "name", "score", "tag"]);
+        assert!(merged[0] == Attribute { name: "id".to_string(), nullable: false,
+                                         dtype: Type::INT64 });
+        assert!(merged[1].nullable && merged[1].dtype == Type::TEXT);
+        assert!(merged[2].nullable && merged[2].dtype == Type::FLOAT64);
+        assert!(merged[3].nullable);
+        assert_eq!(merged.metadata(0).unwrap()["comment"], "row id");
+
+        assert!(v1.compatible_with(&merged));
+        assert!(v2.compatible_with(&merged));
+        assert!(!merged.compatible_with(&v1));
+        assert!(!v1.compatible_with(&v2));
+        assert!(v1.compatible_with(&v1));
+
+        let text_id = Schema::make_one_attr("id", false, Type::TEXT);
+        match v1.merge(&text_id) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Unknown names suggest the attributes differing in case or a few characters
+    #[test]
+    fn resolve_names() {
+        let schema = SchemaBuilder::new()
+            .add("user_id", Type::UINT64).meta("comment", "account")
+            .add("UserName", Type::TEXT)
+            .add("ts", Type::TIMESTAMP)
+            .done()
+            .unwrap();
+
+        assert_eq!(schema.resolve("ts").unwrap(), 2);
+        assert_eq!(schema.similar_names("username"), vec!["UserName".to_string()]);
+        assert_eq!(schema.similar_names("user"), Vec::<String>::new());
+
+        match schema.resolve("user_ids") {
+            Err(DBError::AttributeUnknown(ref name, ref similar)) => {
+                assert_eq!(name, "user_ids");
+                assert_eq!(similar, &vec!["user_id".to_string()]);
+            },
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        let err = schema.resolve("tss").err().unwrap();
+        assert_eq!(err.to_string(), "Unknown Attribute tss, did you mean ts?");
+
+        let projected = schema.project_names(&["ts", "user_id"]).unwrap();
+        assert_eq!(projected[0].name, "ts");
+        assert_eq!(projected.metadata(1).unwrap()["comment"], "account");
+        assert!(schema.project_names(&["ts", "missing"]).is_err());
+    }
+
+    // Schemas round trip through serialized values, types as their full names
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_schema() {
+        let schema = SchemaBuilder::new()
+            .add("id", Type::INT64)
+            .add_nullable("tags", "LIST<STRUCT<k TEXT, v INT32 NULL>>".parse::<Type>().unwrap())
+            .meta("comment", "free form tags")
+            .done()
+            .unwrap();
+
+        let value = toml::Value::try_from(&schema).unwrap();
+        let tags = value.as_array().unwrap()[1].as_table().unwrap();
+        assert_eq!(tags["dtype"].as_str(), Some("LIST<STRUCT<k TEXT, v INT32 NULL>>"));
+        assert!(!value.as_array().unwrap()[0].as_table().unwrap().contains_key("metadata"));
+
+        let out: Schema = value.try_into().unwrap();
+        assert_eq!(out.count(), 2);
+        assert!(out[0] == schema[0]);
+        assert!(out[1] == schema[1]);
+        assert_eq!(out.metadata(1).unwrap()["comment"], "free form tags");
+
+        let attr: Attribute = toml::from_str("name = \"ts\"\ndtype = \"TIMESTAMP\"").unwrap();
+        assert!(attr.dtype == Type::TIMESTAMP);
+        assert!(!attr.nullable);
+
+        assert!(toml::from_str::<Attribute>("name = \"x\"\ndtype = \"INT128\"").is_err());
+        assert!(toml::from_str::<Attribute>("name = \"x\"\nsize = 4").is_err());
+
+        // Deserialized schemas are validated
+        let dup = toml::Value::try_from(vec![schema[0].clone(), schema[0].clone()]).unwrap();
+        assert!(dup.try_into::<Schema>().is_err());
+    }
+}
@@ -0,0 +1,1216 @@
+// vim: set ts=4 sw=4 et :
+
+//! Minimal SQL frontend.
+//!
+//! Parses a single `SELECT` statement into a `LogicalPlan` over the tables of a `Catalog`. The
+//! dialect is small:
+//!
+//! ```text
+//! SELECT (* | column, ...) FROM table
+//!     [JOIN table ON column = column [AND ...]] ...
+//!     [WHERE (operand comparison operand | function(operand, ...)) [AND ...]]
+//!     [GROUP BY column, ...]
+//!     [ORDER BY column [ASC | DESC], ...]
+//!     [LIMIT count [OFFSET offset]]
+//! ```
+//!
+//! Keywords are case insensitive, columns may be qualified by a table name (`t.a`) but the
+//! qualifier is ignored. Functions are the user defined functions of the context's registry:
+//! aggregate functions of one column in the select list and BOOLEAN scalar functions as `WHERE`
+//! conditions. `WHERE` operands are columns or literals: integers (INT64), decimals like `-1.5`
+//! (FLOAT64) and 'strings' (TEXT, with quotes doubled: `'it''s'`). Comparisons are `=`, `<>` (or
+//! `!=`), `<`, `<=`, `>` and `>=`, their sides are cast to a common type (see
+//! `Type::common_supertype()`).
+//!
+//! Every clause is planned into a node the plan lowers to an operation, queries that parse but
+//! can't be run fail to plan instead:
+//!
+//! - `JOIN` conditions compare columns, and the joined tables can't have columns of the same
+//!   name (`DBError::QueryInvalid` and `DBError::AttributeDuplicate`).
+//! - Aggregate arguments are columns, other selected columns have to be grouped by
+//!   (`DBError::QueryInvalid`). Scalar functions can't be selected (`DBError::Unsupported`).
+//! - `ORDER BY` sorts before the select list is projected, by the columns of the grouped rows of
+//!   a query with aggregates.
+//!
+//! With a result cache, `query_cached()` keeps the rows of queries over versioned tables (see
+//! `TableProvider::version`) that only call deterministic functions, and answers an identical
+//! query from the cache until one of its tables changes version. Queries are identified by
+//! their tokens, so they may differ in white space and keyword case.
+
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+use std::time::Instant;
+
+use ::allocator::Allocator;
+use ::audit::{AuditLog, Audited, now_micros};
+use ::block::{Block, View};
+use ::cache::ResultCache;
+use ::catalog::Catalog;
+use ::error::DBError;
+use ::expression::column::ColumnExpr;
+use ::expression::Expr;
+use ::expression::comparison::CompareExpr;
+use ::expression::constant::ConstantExpr;
+use ::expression::udf::FunctionRegistry;
+use ::kernels::CompareOp;
+use ::metrics::Metrics;
+use ::operation::{CursorChunk, ExecutionContext, Operation};
+use ::plan::{LogicalPlan, Optimizer, SortOrder};
+use ::projector::{BuildSingleSourceProjector, project_by_name};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema, DEFAULT_BATCH_BYTES};
+use ::table::Table;
+use ::types::{Type, Value};
+
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "JOIN", "ON", "WHERE", "AND", "GROUP", "ORDER", "BY", "ASC", "DESC",
+    "LIMIT", "OFFSET",
+];
+
+/// Plans queries over the tables of its catalog. Each planned query is logged in its metrics,
+/// along with the `sql.queries` and `sql.errors` counters. Queries of a context with a tenant are
+/// also counted for the tenant.
+pub struct SqlContext<'a> {
+    catalog: Catalog<'a>,
+    functions: FunctionRegistry,
+    metrics: Metrics,
+    audit: Option<AuditLog<'a>>,
+    tenant: Option<String>,
+    results: Option<RefCell<ResultCache<'a>>>,
+    batch_bytes: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    /// Identifier or keyword, as written
+    Word(String),
+    /// Integer or decimal number, as written (with its sign)
+    Number(String),
+    Str(String),
+    Symbol(char),
+    Compare(CompareOp),
+}
+
+enum Operand {
+    Column(String),
+    /// Integer or decimal literal, as written
+    Number(String),
+    Text(String),
+}
+
+enum SelectItem {
+    Column(String),
+    Function(String, Vec<Operand>),
+}
+
+/// AND-ed condition of a WHERE clause
+enum Condition {
+    Compare(Operand, CompareOp, Operand),
+    Call(String, Vec<Operand>),
+}
+
+struct Join {
+    table: String,
+    on: Vec<(Operand, Operand)>,
+}
+
+/// Parsed SELECT statement
+struct Select {
+    /// None for `*`
+    items: Option<Vec<SelectItem>>,
+    from: String,
+    joins: Vec<Join>,
+    filter: Vec<Condition>,
+    group_by: Vec<String>,
+    order_by: Vec<(String, SortOrder)>,
+    /// (offset, count)
+    limit: Option<(RowOffset, RowOffset)>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+fn invalid<T, S: Into<String>>(msg: S) -> Result<T, DBError> {
+    Err(DBError::QueryInvalid(msg.into()))
+}
+
+/// Text of the comparison operator in queries
+fn op_symbol(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::EQ => "=",
+        CompareOp::NE => "<>",
+        CompareOp::LT => "<",
+        CompareOp::LE => "<=",
+        CompareOp::GT => ">",
+        CompareOp::GE => ">=",
+    }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, DBError> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let next = chars.get(pos + 1).cloned();
+        let start = pos;
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c.is_digit(10) || (c == '-' && next.map_or(false, |n| n.is_digit(10))) {
+            // Sign, digits and a fraction. Letters are kept, to fail parsing as a number.
+            pos += 1;
+            while pos < chars.len() && is_word(chars[pos]) {
+                pos += 1;
+            }
+            if pos + 1 < chars.len() && chars[pos] == '.' && chars[pos + 1].is_digit(10) {
+                pos += 1;
+                while pos < chars.len() && is_word(chars[pos]) {
+                    pos += 1;
+                }
+            }
+
+            tokens.push(Token::Number(chars[start .. pos].iter().collect()));
+        } else if is_word(c) {
+            while pos < chars.len() && is_word(chars[pos]) {
+                pos += 1;
+            }
+
+            tokens.push(Token::Word(chars[start .. pos].iter().collect()));
+        } else if c == '\'' {
+            pos += 1;
+
+            let mut literal = String::new();
+            loop {
+                match (chars.get(pos), chars.get(pos + 1)) {
+                    (Some(&'\''), Some(&'\'')) => {
+                        literal.push('\'');
+                        pos += 2;
+                    },
+                    (Some(&'\''), _) => break,
+                    (Some(&c), _) => {
+                        literal.push(c);
+                        pos += 1;
+                    },
+                    (None, _) => return invalid("unterminated string literal"),
+                }
+            }
+
+            pos += 1;
+            tokens.push(Token::Str(literal));
+        } else if "=<>!".contains(c) {
+            let op = match (c, next) {
+                ('<', Some('=')) => CompareOp::LE,
+                ('<', Some('>')) | ('!', Some('=')) => CompareOp::NE,
+                ('>', Some('=')) => CompareOp::GE,
+                ('<', _) => CompareOp::LT,
+                ('>', _) => CompareOp::GT,
+                ('=', _) => CompareOp::EQ,
+                _ => return invalid(format!("unexpected character '{}'", c)),
+            };
+
+            pos += op_symbol(op).len();
+            tokens.push(Token::Compare(op));
+        } else if ",*.();".contains(c) {
+            pos += 1;
+            tokens.push(Token::Symbol(c));
+        } else {
+            return invalid(format!("unexpected character '{}'", c))
+        }
+    }
+
+    Ok(tokens)
+}
+
+impl Parser {
+    fn new(sql: &str) -> Result<Parser, DBError> {
+        Ok(Parser { tokens: tokenize(sql)?, pos: 0 })
+    }
+
+    fn describe_next(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some(&Token::Word(ref w)) | Some(&Token::Number(ref w)) => format!("'{}'", w),
+            Some(&Token::Str(ref s)) => format!("'{}'", s),
+            Some(&Token::Symbol(c)) => format!("'{}'", c),
+            Some(&Token::Compare(op)) => format!("'{}'", op_symbol(op)),
+            None => String::from("end of query"),
+        }
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, DBError> {
+        invalid(format!("expected {}, found {}", expected, self.describe_next()))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(&Token::Word(ref w)) => w.to_uppercase() == keyword,
+            _ => false,
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), DBError> {
+        if self.accept_keyword(keyword) { Ok(()) } else { self.unexpected(keyword) }
+    }
+
+    fn accept_symbol(&mut self, symbol: char) -> bool {
+        let found = self.tokens.get(self.pos) == Some(&Token::Symbol(symbol));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), DBError> {
+        if self.accept_symbol(symbol) { Ok(()) } else { self.unexpected(&format!("'{}'", symbol)) }
+    }
+
+    fn accept_compare(&mut self, op: CompareOp) -> bool {
+        let found = self.tokens.get(self.pos) == Some(&Token::Compare(op));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn comparison(&mut self) -> Result<CompareOp, DBError> {
+        let op = match self.tokens.get(self.pos) {
+            Some(&Token::Compare(op)) => op,
+            _ => return self.unexpected("comparison"),
+        };
+
+        self.pos += 1;
+        Ok(op)
+    }
+
+    /// Non keyword word
+    fn identifier(&mut self) -> Result<String, DBError> {
+        let ident = match self.tokens.get(self.pos) {
+            Some(&Token::Word(ref w)) if !KEYWORDS.contains(&w.to_uppercase().as_str()) =>
+                w.clone(),
+            _ => return self.unexpected("identifier"),
+        };
+
+        self.pos += 1;
+        Ok(ident)
+    }
+
+    /// Column name, dropping the table qualifier
+    fn column(&mut self) -> Result<String, DBError> {
+        let mut name = self.identifier()?;
+        if self.accept_symbol('.') {
+            name = self.identifier()?;
+        }
+        Ok(name)
+    }
+
+    fn number(&mut self) -> Result<RowOffset, DBError> {
+        let num = match self.tokens.get(self.pos) {
+            Some(&Token::Number(ref n)) => n.parse::<RowOffset>()
+                .map_err(|_| DBError::QueryInvalid(format!("invalid row count {}", n)))?,
+            _ => return self.unexpected("number"),
+        };
+
+        self.pos += 1;
+        Ok(num)
+    }
+
+    fn operand(&mut self) -> Result<Operand, DBError> {
+        let literal = match self.tokens.get(self.pos) {
+            Some(&Token::Number(ref n)) => Operand::Number(n.clone()),
+            Some(&Token::Str(ref s)) => Operand::Text(s.clone()),
+            _ => return self.column().map(Operand::Column),
+        };
+
+        self.pos += 1;
+        Ok(literal)
+    }
+
+    /// One or more `lhs = rhs` conditions separated by AND
+    fn conditions(&mut self) -> Result<Vec<(Operand, Operand)>, DBError> {
+        let mut out = Vec::new();
+
+        loop {
+            let lhs = self.operand()?;
+            if !self.accept_compare(CompareOp::EQ) {
+                return self.unexpected("'='")
+            }
+            let rhs = self.operand()?;
+            out.push((lhs, rhs));
+
+            if !self.accept_keyword("AND") {
+                return Ok(out)
+            }
+        }
+    }
+
+    /// Arguments of a function call, after the function name
+    fn arguments(&mut self) -> Result<Vec<Operand>, DBError> {
+        self.expect_symbol('(')?;
+
+        let mut args = Vec::new();
+        if self.accept_symbol(')') {
+            return Ok(args)
+        }
+
+        loop {
+            args.push(self.operand()?);
+            if !self.accept_symbol(',') {
+                break
+            }
+        }
+
+        self.expect_symbol(')')?;
+        Ok(args)
+    }
+
+    /// One or more `lhs op rhs` or `function(args..)` conditions separated by AND
+    fn predicates(&mut self) -> Result<Vec<Condition>, DBError> {
+        let mut out = Vec::new();
+
+        loop {
+            let is_call = self.tokens.get(self.pos + 1) == Some(&Token::Symbol('('));
+            if is_call {
+                let name = self.identifier()?;
+                out.push(Condition::Call(name, self.arguments()?));
+            } else {
+                let lhs = self.operand()?;
+                let op = self.comparison()?;
+                out.push(Condition::Compare(lhs, op, self.operand()?));
+            }
+
+            if !self.accept_keyword("AND") {
+                return Ok(out)
+            }
+        }
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem, DBError> {
+        let is_call = self.tokens.get(self.pos + 1) == Some(&Token::Symbol('('));
+        if !is_call {
+            return Ok(SelectItem::Column(self.column()?))
+        }
+
+        let name = self.identifier()?;
+        Ok(SelectItem::Function(name, self.arguments()?))
+    }
+
+    fn select(&mut self) -> Result<Select, DBError> {
+        self.expect_keyword("SELECT")?;
+
+        let items = if self.accept_symbol('*') {
+            None
+        } else {
+            let mut items = vec![self.select_item()?];
+            while self.accept_symbol(',') {
+                items.push(self.select_item()?);
+            }
+            Some(items)
+        };
+
+        self.expect_keyword("FROM")?;
+        let from = self.identifier()?;
+
+        let mut joins = Vec::new();
+        while self.accept_keyword("JOIN") {
+            let table = self.identifier()?;
+            self.expect_keyword("ON")?;
+            joins.push(Join { table: table, on: self.conditions()? });
+        }
+
+        let filter = if self.accept_keyword("WHERE") { self.predicates()? } else { Vec::new() };
+
+        let mut group_by = Vec::new();
+        if self.accept_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            group_by.push(self.column()?);
+            while self.accept_symbol(',') {
+                group_by.push(self.column()?);
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let column = self.column()?;
+                let order = if self.accept_keyword("DESC") {
+                    SortOrder::DESC
+                } else {
+                    self.accept_keyword("ASC");
+                    SortOrder::ASC
+                };
+                order_by.push((column, order));
+
+                if !self.accept_symbol(',') {
+                    break
+                }
+            }
+        }
+
+        let limit = if self.accept_keyword("LIMIT") {
+            let count = self.number()?;
+            let offset = if self.accept_keyword("OFFSET") { self.number()? } else { 0 };
+            Some((offset, count))
+        } else {
+            None
+        };
+
+        self.accept_symbol(';');
+        if self.pos < self.tokens.len() {
+            return self.unexpected("end of query")
+        }
+
+        Ok(Select {
+            items: items,
+            from: from,
+            joins: joins,
+            filter: filter,
+            group_by: group_by,
+            order_by: order_by,
+            limit: limit,
+        })
+    }
+}
+
+/// All the rows of the operation, fetched in batches of about `batch_bytes`
+fn collect_rows<'a>(op: &Operation<'a>, alloc: &'a Allocator, batch_bytes: usize)
+    -> Result<Block<'a>, DBError>
+{
+    let ctx = ExecutionContext::new(alloc).with_batch_bytes(batch_bytes);
+    let mut cursor = op.bind_context(&ctx)?;
+    let fetch = ctx.batch_rows(cursor.schema());
+    let mut out = Table::new(alloc, cursor.schema(), None);
+    loop {
+        match cursor.next(fetch)? {
+            CursorChunk::Next(view) => out.append_block(&view)?,
+            CursorChunk::End        => break,
+        }
+    }
+
+    Ok(out.take().unwrap())
+}
+
+/// Column name of the operand, for the clauses that only take columns
+fn operand_column(op: Operand, clause: &str) -> Result<String, DBError> {
+    match op {
+        Operand::Column(name) => Ok(name),
+        Operand::Number(n) => invalid(format!("literal {} in {}, expected a column", n, clause)),
+        Operand::Text(s) => invalid(format!("literal '{}' in {}, expected a column", s, clause)),
+    }
+}
+
+/// Column or constant expression of the operand
+fn operand_expr<'a>(op: Operand) -> Result<Box<Expr<'a> + 'a>, DBError> {
+    match op {
+        Operand::Column(name) => Ok(Box::new(ColumnExpr::named(name))),
+        Operand::Number(ref n) if n.contains('.') => {
+            let value = n.parse::<f64>()
+                .map_err(|_| DBError::QueryInvalid(format!("invalid decimal {}", n)))?;
+            Ok(Box::new(ConstantExpr::new(&Value::FLOAT64(value), Type::FLOAT64)?))
+        },
+        Operand::Number(n) => {
+            let value = n.parse::<i64>()
+                .map_err(|_| DBError::QueryInvalid(format!("invalid integer {}", n)))?;
+            Ok(Box::new(ConstantExpr::new(&Value::INT64(value), Type::INT64)?))
+        },
+        Operand::Text(s) => Ok(Box::new(ConstantExpr::new(&Value::TEXT(&s), Type::TEXT)?)),
+    }
+}
+
+impl<'a> SqlContext<'a> {
+    /// Context with an empty catalog
+    pub fn new() -> SqlContext<'a> {
+        SqlContext::from_catalog(Catalog::new())
+    }
+
+    pub fn from_catalog(catalog: Catalog<'a>) -> SqlContext<'a> {
+        SqlContext {
+            catalog: catalog,
+            functions: FunctionRegistry::new(),
+            metrics: Metrics::default(),
+            audit: None,
+            tenant: None,
+            results: None,
+            batch_bytes: DEFAULT_BATCH_BYTES,
+        }
+    }
+
+    pub fn catalog(&self) -> &Catalog<'a> {
+        &self.catalog
+    }
+
+    pub fn catalog_mut(&mut self) -> &mut Catalog<'a> {
+        &mut self.catalog
+    }
+
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    /// Registry of the functions callable from queries
+    pub fn functions_mut(&mut self) -> &mut FunctionRegistry {
+        &mut self.functions
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Attribute the following queries to the tenant
+    pub fn set_tenant(&mut self, tenant: Option<String>) {
+        self.tenant = tenant;
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_ref().map(|t| &t[..])
+    }
+
+    /// Record the queries executed with `query()`
+    pub fn set_audit_log(&mut self, log: Option<AuditLog<'a>>) {
+        self.audit = log;
+    }
+
+    pub fn audit_log(&self) -> Option<&AuditLog<'a>> {
+        self.audit.as_ref()
+    }
+
+    /// Size in bytes of the batches of rows `query_cached()` fetches from queries
+    pub fn set_batch_bytes(&mut self, bytes: usize) {
+        self.batch_bytes = bytes;
+    }
+
+    /// Cache the results of `query_cached()`, at most `capacity` of them. `None` drops the cache.
+    pub fn set_result_cache(&mut self, capacity: Option<usize>) {
+        self.results = capacity.map(|c| RefCell::new(ResultCache::new(c)));
+    }
+
+    pub fn result_cache(&self) -> Option<Ref<ResultCache<'a>>> {
+        self.results.as_ref().map(|c| c.borrow())
+    }
+
+    /// Drop the cached results, eg. after replacing a table through `catalog_mut()`
+    pub fn clear_result_cache(&self) {
+        if let Some(ref cache) = self.results {
+            cache.borrow_mut().clear();
+        }
+    }
+
+    /// Make the view available to queries as `name`, replacing any previous table by that name.
+    /// Drops the cached results.
+    pub fn register<S: Into<String>>(&mut self, name: S, view: &'a View<'a>) {
+        self.catalog.register_view(name, view);
+        self.clear_result_cache();
+    }
+
+    fn scan(&self, name: &str) -> Result<LogicalPlan<'a>, DBError> {
+        self.catalog.scan(name)
+    }
+
+    /// Parse the query into an (unoptimized) logical plan
+    pub fn plan(&self, sql: &str) -> Result<LogicalPlan<'a>, DBError> {
+        self.plan_logged(sql).map(|(plan, _)| plan)
+    }
+
+    /// Plan, optimize and lower the query into the operation to execute. With an audit log, the
+    /// execution of the operation is audited, a query that fails to plan is audited right away.
+    pub fn query(&self, sql: &str) -> Result<Box<Operation<'a> + 'a>, DBError> {
+        self.query_tables(sql).map(|(op, _)| op)
+    }
+
+    /// Rows of the query, from the result cache if the query was cached and its tables haven't
+    /// changed since. Cache hits count as `sql.cache_hits`, they aren't planned, executed nor
+    /// logged as queries. Without a result cache, the query is always executed.
+    pub fn query_cached(&self, sql: &str, alloc: &'a Allocator) -> Result<Rc<Block<'a>>, DBError> {
+        let cache = match self.results {
+            Some(ref cache) => cache,
+            None => {
+                let rows = collect_rows(&*self.query(sql)?, alloc, self.batch_bytes)?;
+                return Ok(Rc::new(rows))
+            },
+        };
+
+        let version = |name: &str| self.catalog.version(name).ok().and_then(|v| v);
+        let fingerprint = self.fingerprint(sql);
+        if let Some(ref fingerprint) = fingerprint {
+            let cached = cache.borrow_mut().get(fingerprint, version);
+            if let Some(rows) = cached {
+                self.metrics.increment_tenant(self.tenant(), "sql.cache_hits", 1);
+                return Ok(rows)
+            }
+        }
+
+        let (op, tables) = self.query_tables(sql)?;
+        let versions: Option<Vec<(String, u64)>> = tables.into_iter()
+            .map(|t| version(&t).map(|v| (t, v)))
+            .collect();
+        let rows = Rc::new(collect_rows(&*op, alloc, self.batch_bytes)?);
+
+        if let (Some(fingerprint), Some(versions)) = (fingerprint, versions) {
+            cache.borrow_mut().insert(fingerprint, versions, rows.clone());
+        }
+
+        Ok(rows)
+    }
+
+    /// Cache key of the query: its tokens, with the keywords in upper case. `None` for queries
+    /// that can't be cached, as they don't tokenize or call non deterministic functions.
+    fn fingerprint(&self, sql: &str) -> Option<String> {
+        let tokens = tokenize(sql).ok()?;
+        let mut out = Vec::with_capacity(tokens.len());
+
+        for (pos, token) in tokens.iter().enumerate() {
+            match *token {
+                Token::Word(ref word) => {
+                    let called = tokens.get(pos + 1) == Some(&Token::Symbol('('));
+                    if called {
+                        if let Ok(func) = self.functions.scalar(word) {
+                            if !func.deterministic {
+                                return None
+                            }
+                        }
+                    }
+
+                    let upper = word.to_uppercase();
+                    if KEYWORDS.contains(&upper.as_str()) {
+                        out.push(upper);
+                    } else {
+                        out.push(word.clone());
+                    }
+                },
+                Token::Number(ref n)    => out.push(n.clone()),
+                Token::Str(ref s)       => out.push(format!("'{}'", s.replace('\'', "''"))),
+                Token::Symbol(';')      => (),
+                Token::Symbol(c)        => out.push(c.to_string()),
+                Token::Compare(op)      => out.push(op_symbol(op).to_string()),
+            }
+        }
+
+        Some(out.join(" "))
+    }
+
+    /// Operation of the query and the tables it reads
+    fn query_tables(&self, sql: &str) -> Result<(Box<Operation<'a> + 'a>, Vec<String>), DBError> {
+        let started = now_micros();
+        let planned = self.plan_logged(sql)
+            .and_then(|(plan, tables)| Ok((Optimizer::new().optimize(plan).lower()?, tables)));
+
+        match (planned, self.audit.as_ref()) {
+            (Ok((op, tables)), Some(log)) => {
+                let audited = Audited::new(log.clone(), sql.trim(), tables.clone(), op);
+                Ok((Box::new(audited), tables))
+            },
+            (Ok(planned), None) => Ok(planned),
+            (Err(e), Some(log)) => {
+                log.failed(sql.trim(), started, &e)?;
+                Err(e)
+            },
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    /// Plan and the referenced tables, logged in the metrics
+    fn plan_logged(&self, sql: &str) -> Result<(LogicalPlan<'a>, Vec<String>), DBError> {
+        let started = Instant::now();
+        let planned = self.plan_select(sql);
+
+        let error = planned.as_ref().err().map(|e| e.to_string());
+        self.metrics.increment_tenant(self.tenant(), "sql.queries", 1);
+        if error.is_some() {
+            self.metrics.increment_tenant(self.tenant(), "sql.errors", 1);
+        }
+        self.metrics.record_tenant_query(self.tenant(), sql.trim(), started.elapsed(), error);
+
+        planned
+    }
+
+    fn plan_select(&self, sql: &str) -> Result<(LogicalPlan<'a>, Vec<String>), DBError> {
+        let Select { items, from, joins, filter, group_by, order_by, limit } =
+            Parser::new(sql)?.select()?;
+
+        let mut plan = self.scan(&from)?;
+        let mut attrs: Vec<Attribute> = self.catalog.schema(&from)?.iter().cloned().collect();
+        let mut tables = vec![from];
+
+        for join in joins {
+            let mut on = Vec::new();
+            for (lhs, rhs) in join.on {
+                on.push((operand_column(lhs, "JOIN")?, operand_column(rhs, "JOIN")?));
+            }
+
+            plan = plan.join(self.scan(&join.table)?, on);
+            // Columns are referenced without their table
+            attrs.extend(self.catalog.schema(&join.table)?.iter().cloned());
+            Schema::from_slice(&attrs)?;
+            if !tables.contains(&join.table) {
+                tables.push(join.table);
+            }
+        }
+
+        // Each AND-ed condition is its own filter
+        for cond in filter {
+            match cond {
+                Condition::Compare(lhs, op, rhs) => {
+                    let lhs = operand_expr(lhs)?;
+                    let rhs = operand_expr(rhs)?;
+                    plan = plan.filter(CompareExpr { lhs: lhs, op: op, rhs: rhs });
+                },
+                Condition::Call(name, args) => {
+                    if self.functions.is_aggregate(&name) {
+                        return invalid(format!("aggregate function {} in WHERE", name))
+                    }
+
+                    let mut exprs: Vec<Box<Expr<'a> + 'a>> = Vec::new();
+                    for arg in args {
+                        exprs.push(operand_expr(arg)?);
+                    }
+                    plan = plan.filter(self.functions.call(&name, exprs)?);
+                },
+            }
+        }
+
+        // Projected columns and the column names of the aggregates
+        let mut columns = Vec::new();
+        let mut grouped = Vec::new();
+        let mut aggregates: Vec<Box<Expr<'a> + 'a>> = Vec::new();
+        for item in items.unwrap_or_else(Vec::new) {
+            match item {
+                SelectItem::Column(name) => {
+                    grouped.push(name.clone());
+                    columns.push(name);
+                },
+                SelectItem::Function(name, mut args) => {
+                    if !self.functions.is_aggregate(&name) {
+                        self.functions.scalar(&name)?;
+                        let msg = format!("scalar function {} in the select list", name);
+                        return Err(DBError::Unsupported(msg))
+                    }
+
+                    if args.len() != 1 {
+                        return Err(DBError::ExpressionInputCount(
+                            format!("{} takes 1 argument, not {}", name, args.len())))
+                    }
+
+                    let arg = operand_column(args.remove(0), "an aggregate")?;
+                    columns.push(format!("{}({})", name.to_lowercase(), arg));
+                    aggregates.push(Box::new(
+                        self.functions.call_aggregate(&name, ColumnExpr::named(arg))?));
+                },
+            }
+        }
+
+        if !group_by.is_empty() || !aggregates.is_empty() {
+            if let Some(c) = grouped.iter().find(|c| !group_by.contains(c)) {
+                return invalid(format!("column {} must appear in GROUP BY", c))
+            }
+
+            plan = plan.aggregate(group_by, aggregates);
+        }
+
+        if !order_by.is_empty() {
+            plan = plan.sort(order_by);
+        }
+
+        if !columns.is_empty() {
+            let proj = columns.into_iter()
+                .fold(BuildSingleSourceProjector::new(), |b, c| b.add(project_by_name(c)))
+                .done();
+            plan = plan.project(proj);
+        }
+
+        if let Some((offset, count)) = limit {
+            plan = plan.limit(offset, count);
+        }
+
+        Ok((plan, tables))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use std::rc::Rc;
+    use ::audit::TableSink;
+    use ::block::{Block, column_row_data, column_value};
+    use ::operation::{CursorChunk, DEFAULT_CURSOR_FETCH};
+    use ::plan::Optimizer;
+    use ::expression::udf::{Aggregate, ScalarUdf};
+    use ::system::SystemTables;
+    use ::table::{Table, TableAppender};
+    use ::types::{Boolean, UInt32};
+
+    /// UINT32 values of the first column of the query's rows
+    fn query_values(ctx: &SqlContext, sql: &str) -> Vec<u32> {
+        let op = Optimizer::new().optimize(ctx.plan(sql).unwrap()).lower().unwrap();
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+        out
+    }
+
+    fn make_block() -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: false, dtype: Type::UINT32},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 10 {
+                appender = appender.add_row().set(v as u32).set((v % 3) as u32);
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.take().unwrap()
+    }
+
+    #[test]
+    fn select_where_limit() {
+        let block = make_block();
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+
+        let plan = ctx.plan("select t.a from t where a = b limit 2 offset 1;").unwrap();
+        assert_eq!(plan.explain(), "Limit 2 offset 1\n  Project\n    Filter\n      Scan");
+
+        let op = Optimizer::new().optimize(plan).lower().unwrap();
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        // Rows with a == b are 0, 1 and 2
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+
+        assert_eq!(out, vec![1, 2]);
+    }
+
+    // Literals are constants of the WHERE conditions
+    #[test]
+    fn select_where_literal() {
+        let block = make_block();
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+
+        let sql = "SELECT a FROM t WHERE b = 2 AND 'x' = 'x' AND 8 = a";
+        assert_eq!(query_values(&ctx, sql), vec![8]);
+        assert_eq!(query_values(&ctx, "SELECT a FROM t WHERE b = 2"), vec![2, 5, 8]);
+    }
+
+    #[test]
+    fn select_where_compare() {
+        let block = make_block();
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+
+        let queries: Vec<(&str, Vec<u32>)> = vec![
+            ("a < 3", vec![0, 1, 2]),
+            ("a <= 3", vec![0, 1, 2, 3]),
+            ("a > 7", vec![8, 9]),
+            ("a >= 7", vec![7, 8, 9]),
+            ("a <> 5 AND a < 7", vec![0, 1, 2, 3, 4, 6]),
+            ("a != 5 AND a > 3", vec![4, 6, 7, 8, 9]),
+            ("3 > a", vec![0, 1, 2]),
+            ("a > -1 AND a < 2", vec![0, 1]),
+            ("a < 2.5", vec![0, 1, 2]),
+            ("a >= -0.5 AND a <= 1.0", vec![0, 1]),
+            ("a = 8 AND 'it''s' = 'it''s'", vec![8]),
+            ("a = 8 AND 'it''s' <> 'its'", vec![8]),
+            ("a = 8 AND 'it''s' = 'its'", vec![]),
+        ];
+
+        for &(ref cond, ref expected) in &queries {
+            let sql = format!("SELECT a FROM t WHERE {}", cond);
+            assert_eq!(&query_values(&ctx, &sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn join_group_order() {
+        let left = make_block();
+        let mut table = Table::new(&allocator::GLOBAL,
+                                   &Schema::make_one_attr("k", false, Type::UINT32), None);
+        {
+            let appender = TableAppender::new(&mut table);
+            let status = appender.add_row().set(1u32).add_row().set(2u32).add_row().set(5u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        let right = table.take().unwrap();
+        let mut ctx = SqlContext::new();
+        ctx.register("l", &left);
+        ctx.register("r", &right);
+
+        let sql = "SELECT b FROM l JOIN r ON l.b = r.k WHERE a = b GROUP BY b ORDER BY b DESC";
+        let plan = ctx.plan(sql).unwrap();
+
+        let expected = vec![
+            "Project",
+            "  Sort by b DESC",
+            "    Aggregate by b",
+            "      Filter",
+            "        Join on b = k",
+            "          Scan",
+            "          Scan",
+        ];
+        assert_eq!(plan.explain(), expected.join("\n"));
+
+        // Rows with a == b are 0, 1 and 2, only 1 and 2 join
+        assert_eq!(query_values(&ctx, sql), vec![2, 1]);
+
+        // Columns are referenced without their table, so they can't be shared
+        ctx.register("same", &left);
+        match ctx.plan("SELECT a FROM l JOIN same ON l.a = same.b") {
+            Err(DBError::AttributeDuplicate(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    #[test]
+    fn invalid_queries() {
+        let block = make_block();
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+
+        let syntax = ["SELECT FROM t", "SELECT a FROM t LIMIT", "SELECT a FROM t WHERE a = b c",
+                      "SELECT a FROM t WHERE a - 1", "SELECT a FROM t WHERE a ! 1",
+                      "SELECT a FROM t WHERE a = 'open", "SELECT a FROM t JOIN t ON a < b"];
+        for sql in &syntax {
+            match ctx.plan(sql) {
+                Err(DBError::QueryInvalid(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error for {}", sql),
+            }
+        }
+
+        match ctx.plan("SELECT a FROM missing") {
+            Err(DBError::TableMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        // Literals are only planned where the clause takes expressions
+        let literals = ["SELECT a FROM t WHERE a = 99999999999999999999",
+                        "SELECT a FROM t JOIN t ON a = 5"];
+        for sql in &literals {
+            match ctx.plan(sql) {
+                Err(DBError::QueryInvalid(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error for {}", sql),
+            }
+        }
+    }
+
+    /// Number of non NULL values
+    struct Count;
+
+    impl Aggregate for Count {
+        type State = i64;
+
+        fn output(&self, _: &Type) -> Result<Type, DBError> {
+            Ok(Type::INT64)
+        }
+
+        fn init(&self) -> i64 {
+            0
+        }
+
+        fn update(&self, state: &mut i64, _: &Value) -> Result<(), DBError> {
+            *state += 1;
+            Ok(())
+        }
+
+        fn merge(&self, state: &mut i64, other: i64) -> Result<(), DBError> {
+            *state += other;
+            Ok(())
+        }
+
+        fn finalize<'s>(&self, state: &'s i64) -> Result<Value<'s>, DBError> {
+            Ok(Value::INT64(*state))
+        }
+    }
+
+    // Scalar functions as WHERE conditions, aggregate functions in the select list
+    #[test]
+    fn function_calls() {
+        let block = make_block();
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+
+        ctx.functions_mut().register_aggregate("count", Count);
+        ctx.functions_mut().register_scalar(ScalarUdf::new("odd", vec![Type::UINT32],
+            Type::BOOLEAN, false, |args, rows, out| {
+                let src = column_row_data::<UInt32>(args[0])?;
+                let dst = out.row_data_mut::<Boolean>()?;
+                for row in 0 .. rows {
+                    dst.values[row] = src.values[row] % 2 == 1;
+                }
+                Ok(())
+            }));
+
+        let plan = ctx.plan("SELECT a FROM t WHERE odd(t.a)").unwrap();
+        let op = Optimizer::new().optimize(plan).lower().unwrap();
+        let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            let rows = column_row_data::<UInt32>(view.column(0).unwrap()).unwrap();
+            out.extend(rows.values[.. view.rows()].iter().cloned());
+        }
+        assert_eq!(out, vec![1, 3, 5, 7, 9]);
+
+        let plan = ctx.plan("SELECT b, COUNT(a) FROM t GROUP BY b").unwrap();
+        assert_eq!(plan.explain(), "Project\n  Aggregate by b\n    Scan");
+        let names: Vec<String> = plan.schema().unwrap().iter().map(|a| a.name.clone()).collect();
+        assert_eq!(names, vec!["b", "count(a)"]);
+
+        let plan = ctx.plan("SELECT count(a) FROM t").unwrap();
+        assert_eq!(plan.explain(), "Project\n  Aggregate\n    Scan");
+
+        match ctx.plan("SELECT a FROM t WHERE even(a)") {
+            Err(DBError::FunctionMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        for sql in &["SELECT a, count(b) FROM t", "SELECT a FROM t WHERE count(a)"] {
+            match ctx.plan(sql) {
+                Err(DBError::QueryInvalid(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error for {}", sql),
+            }
+        }
+    }
+
+    // Cached results are returned until a table changes version
+    #[test]
+    fn cached_results() {
+        let block = make_block();
+        let mut table = Table::from_block(make_block());
+        let first = table.snapshot();
+        let row = table.add_row().unwrap();
+        table.set(0, row, 100u32).unwrap();
+        table.set(1, row, 100u32).unwrap();
+        let second = table.snapshot();
+
+        let mut ctx = SqlContext::new();
+        ctx.set_result_cache(Some(4));
+        ctx.catalog_mut().register_snapshot("t", &first);
+        ctx.catalog_mut().register_view("v", &block);
+
+        let rows = ctx.query_cached("SELECT a FROM t WHERE a = b", &allocator::GLOBAL).unwrap();
+        assert_eq!(rows.rows(), 3);
+        let again = ctx.query_cached("select a\n from t where a = b;", &allocator::GLOBAL).unwrap();
+        assert!(Rc::ptr_eq(&rows, &again));
+        assert_eq!(ctx.metrics().counter("sql.cache_hits"), 1);
+        assert_eq!(ctx.metrics().counter("sql.queries"), 1);
+
+        ctx.catalog_mut().register_snapshot("t", &second);
+        let rows = ctx.query_cached("SELECT a FROM t WHERE a = b", &allocator::GLOBAL).unwrap();
+        assert_eq!(rows.rows(), 4);
+        assert_eq!(ctx.result_cache().unwrap().misses(), 2);
+
+        // Views aren't versioned, non deterministic functions aren't cached
+        ctx.functions_mut().register_scalar(ScalarUdf::new("coin", vec![Type::UINT32],
+            Type::BOOLEAN, false, |_, rows, out| {
+                let dst = out.row_data_mut::<Boolean>()?;
+                for row in 0 .. rows {
+                    dst.values[row] = true;
+                }
+                Ok(())
+            }).non_deterministic());
+
+        for _ in 0 .. 2 {
+            ctx.query_cached("SELECT a FROM v", &allocator::GLOBAL).unwrap();
+            ctx.query_cached("SELECT a FROM t WHERE coin(a)", &allocator::GLOBAL).unwrap();
+        }
+        assert_eq!(ctx.metrics().counter("sql.cache_hits"), 1);
+        assert_eq!(ctx.result_cache().unwrap().len(), 1);
+
+        ctx.clear_result_cache();
+        assert!(ctx.result_cache().unwrap().is_empty());
+    }
+
+    // Executed queries and planning errors are audited
+    #[test]
+    fn audited_queries() {
+        let block = make_block();
+        let sink = Rc::new(TableSink::new(&allocator::GLOBAL));
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+        ctx.register("u", &block);
+        ctx.set_audit_log(Some(AuditLog::shared("carol", sink.clone())));
+
+        {
+            let op = ctx.query("SELECT a FROM t LIMIT 4").unwrap();
+            let mut cursor = op.bind(&allocator::GLOBAL).unwrap();
+            while let CursorChunk::Next(_) = cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {}
+        }
+        assert!(ctx.query("SELECT a FROM t JOIN missing ON a = b").is_err());
+
+        let records = sink.take();
+        assert_eq!(records.rows(), 2);
+
+        let value = |col: usize, row: usize| column_value(records.column(col).unwrap(), row);
+        assert!(value(0, 0).unwrap() == Value::TEXT("carol"));
+        assert!(value(1, 0).unwrap() == Value::TEXT("SELECT a FROM t LIMIT 4"));
+        assert!(value(2, 0).unwrap() == Value::TEXT("t"));
+        assert!(value(5, 0).unwrap() == Value::UINT64(4));
+        assert!(value(6, 0).unwrap() == Value::NULL);
+        assert!(value(6, 1).unwrap() == Value::TEXT("Unknown Table missing"));
+    }
+
+    // Planned queries are logged, the system tables can be queried like other tables
+    #[test]
+    fn system_tables() {
+        let block = make_block();
+        let system;
+        let mut ctx = SqlContext::new();
+        ctx.register("t", &block);
+
+        assert!(ctx.plan("SELECT a FROM t").is_ok());
+        assert!(ctx.plan("SELECT a FROM missing").is_err());
+        assert_eq!(ctx.metrics().counter("sql.queries"), 2);
+        assert_eq!(ctx.metrics().counter("sql.errors"), 1);
+
+        system = SystemTables::snapshot(&allocator::GLOBAL, ctx.catalog(), ctx.metrics()).unwrap();
+        system.register(ctx.catalog_mut());
+
+        let plan = ctx.plan("SELECT name, type FROM dbkit_columns").unwrap();
+        assert_eq!(plan.schema().unwrap().count(), 2);
+        assert_eq!(system.queries.rows(), 2);
+        assert_eq!(ctx.metrics().queries().last().unwrap().sql,
+                   "SELECT name, type FROM dbkit_columns");
+
+        // Queries of a tenant count towards the totals and the tenant's counters
+        ctx.set_tenant(Some("acme".to_string()));
+        assert!(ctx.plan("SELECT b FROM t").is_ok());
+        assert_eq!(ctx.metrics().counter("sql.queries"), 4);
+        assert_eq!(ctx.metrics().counter("sql.queries{tenant=acme}"), 1);
+        assert_eq!(ctx.metrics().queries().last().unwrap().tenant, Some("acme".to_string()));
+    }
+}
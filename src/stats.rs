@@ -0,0 +1,293 @@
+// vim: set ts=4 sw=4 et :
+
+use std::cmp::Ordering;
+
+use ::block::{RefColumn, column_value};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::types::Value;
+
+/// Per column zone map statistics
+pub struct ColumnStats {
+    /// Smallest non NULL value. Not tracked for TEXT, BLOB, INTERVAL and nested types.
+    pub min: Option<Value<'static>>,
+    /// Largest non NULL value. Not tracked for TEXT, BLOB, INTERVAL and nested types.
+    pub max: Option<Value<'static>>,
+    pub null_count: RowOffset,
+}
+
+/// Zone map of a `Block`, statistics for each of its columns
+pub struct BlockStats {
+    pub rows: RowOffset,
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Comparison operator of a `StatsPredicate`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompareOp {
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+}
+
+/// Predicate that can be checked against block statistics, to find blocks where no row can match.
+/// Columns are referenced by name.
+pub enum StatsPredicate {
+    /// `column <op> value`, never true for NULL rows
+    Compare(String, CompareOp, Value<'static>),
+    IsNull(String),
+    IsNotNull(String),
+    And(Vec<StatsPredicate>),
+    Or(Vec<StatsPredicate>),
+}
+
+/// Copy of fixed width, ordered values. Values referencing column data aren't copied.
+fn owned_value(v: &Value) -> Option<Value<'static>> {
+    match *v {
+        Value::UINT32(v)    => Some(Value::UINT32(v)),
+        Value::UINT64(v)    => Some(Value::UINT64(v)),
+        Value::INT32(v)     => Some(Value::INT32(v)),
+        Value::INT64(v)     => Some(Value::INT64(v)),
+        Value::FLOAT32(v)   => Some(Value::FLOAT32(v)),
+        Value::FLOAT64(v)   => Some(Value::FLOAT64(v)),
+        Value::BOOLEAN(v)   => Some(Value::BOOLEAN(v)),
+        Value::TIMESTAMP(v) => Some(Value::TIMESTAMP(v)),
+        Value::UUID(v)      => Some(Value::UUID(v)),
+        _                   => None,
+    }
+}
+
+/// Order of two values of the same type. `None` for different types or unordered values (NaN).
+pub fn compare_values(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (&Value::UINT32(l), &Value::UINT32(r))          => l.partial_cmp(&r),
+        (&Value::UINT64(l), &Value::UINT64(r))          => l.partial_cmp(&r),
+        (&Value::INT32(l), &Value::INT32(r))            => l.partial_cmp(&r),
+        (&Value::INT64(l), &Value::INT64(r))            => l.partial_cmp(&r),
+        (&Value::FLOAT32(l), &Value::FLOAT32(r))        => l.partial_cmp(&r),
+        (&Value::FLOAT64(l), &Value::FLOAT64(r))        => l.partial_cmp(&r),
+        (&Value::BOOLEAN(l), &Value::BOOLEAN(r))        => l.partial_cmp(&r),
+        (&Value::TIMESTAMP(l), &Value::TIMESTAMP(r))    => l.partial_cmp(&r),
+        (&Value::UUID(ref l), &Value::UUID(ref r))      => l.partial_cmp(r),
+        (&Value::TEXT(l), &Value::TEXT(r))              => l.partial_cmp(r),
+        (&Value::BLOB(l), &Value::BLOB(r))              => l.partial_cmp(r),
+        _ => None,
+    }
+}
+
+impl ColumnStats {
+    /// Statistics of the first `rows` rows of a column (of any encoding).
+    pub fn compute(col: &RefColumn, rows: RowOffset) -> Result<ColumnStats, DBError> {
+        let mut stats = ColumnStats { min: None, max: None, null_count: 0 };
+
+        for row in 0 .. rows {
+            let value = column_value(col, row)?;
+            if let Value::NULL = value {
+                stats.null_count += 1;
+                continue
+            }
+
+            // Types without min/max tracking, still counting NULLs
+            let value = match owned_value(&value) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            // NaN
+            if compare_values(&value, &value).is_none() {
+                continue
+            }
+
+            let less = stats.min.as_ref()
+                .map_or(true, |m| compare_values(&value, m) == Some(Ordering::Less));
+            let greater = stats.max.as_ref()
+                .map_or(true, |m| compare_values(&value, m) == Some(Ordering::Greater));
+
+            if less {
+                stats.min = owned_value(&value);
+            }
+
+            if greater {
+                stats.max = owned_value(&value);
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+fn column_stats<'s>(schema: &Schema, stats: &'s BlockStats, name: &str)
+    -> Result<&'s ColumnStats, DBError>
+{
+    let pos = schema.exists_ok(name)?;
+    stats.columns.get(pos).ok_or(DBError::make_column_unknown_pos(pos))
+}
+
+impl StatsPredicate {
+    /// False if no row described by `stats` can satisfy the predicate. Missing statistics (eg.
+    /// TEXT min/max) are treated as "may match".
+    pub fn may_match(&self, schema: &Schema, stats: &BlockStats) -> Result<bool, DBError> {
+        match *self {
+            StatsPredicate::IsNull(ref name) =>
+                Ok(column_stats(schema, stats, name)?.null_count > 0),
+            StatsPredicate::IsNotNull(ref name) =>
+                Ok(column_stats(schema, stats, name)?.null_count < stats.rows),
+            StatsPredicate::Compare(ref name, op, ref value) => {
+                let col = column_stats(schema, stats, name)?;
+                if col.null_count == stats.rows {
+                    return Ok(false)
+                }
+
+                let (min, max) = match (&col.min, &col.max) {
+                    (&Some(ref min), &Some(ref max)) => (min, max),
+                    _ => return Ok(true),
+                };
+
+                let (lo, hi) = match (compare_values(min, value), compare_values(max, value)) {
+                    (Some(lo), Some(hi)) => (lo, hi),
+                    _ => return Ok(true),
+                };
+
+                Ok(match op {
+                    CompareOp::EQ => lo != Ordering::Greater && hi != Ordering::Less,
+                    CompareOp::NE => lo != Ordering::Equal || hi != Ordering::Equal,
+                    CompareOp::LT => lo == Ordering::Less,
+                    CompareOp::LE => lo != Ordering::Greater,
+                    CompareOp::GT => hi == Ordering::Greater,
+                    CompareOp::GE => hi != Ordering::Less,
+                })
+            },
+            StatsPredicate::And(ref preds) => {
+                for p in preds {
+                    if !p.may_match(schema, stats)? {
+                        return Ok(false)
+                    }
+                }
+                Ok(true)
+            },
+            StatsPredicate::Or(ref preds) => {
+                for p in preds {
+                    if p.may_match(schema, stats)? {
+                        return Ok(true)
+                    }
+                }
+                Ok(false)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::operation::{CursorChunk, Operation, ScanView, DEFAULT_CURSOR_FETCH};
+    use ::schema::Attribute;
+    use ::table::{Table, TableAppender};
+    use ::types::Type;
+
+    fn make_block() -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::INT64},
+            Attribute{name: "score".to_string(), nullable: true, dtype: Type::FLOAT64},
+            Attribute{name: "name".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(10 as i64).set(0.5 as f64).set("b")
+                .add_row().set(20 as i64).set_null(true).set("a")
+                .add_row().set(15 as i64).set(-1.5 as f64).set("c")
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let mut block = table.take().unwrap();
+        block.compute_stats().unwrap();
+        block
+    }
+
+    #[test]
+    fn block_stats() {
+        let block = make_block();
+        let stats = block.stats().unwrap();
+        assert_eq!(stats.rows, 3);
+
+        let id = &stats.columns[0];
+        assert!(id.min == Some(Value::INT64(10)) && id.max == Some(Value::INT64(20)));
+        assert_eq!(id.null_count, 0);
+
+        let score = &stats.columns[1];
+        assert!(score.min == Some(Value::FLOAT64(-1.5)));
+        assert_eq!(score.null_count, 1);
+
+        assert!(stats.columns[2].min.is_none());
+    }
+
+    #[test]
+    fn predicate_may_match() {
+        let block = make_block();
+        let schema = block.schema();
+        let stats = block.stats().unwrap();
+
+        let cmp = |name: &str, op, v| StatsPredicate::Compare(name.to_string(), op, v);
+
+        assert!(cmp("id", CompareOp::EQ, Value::INT64(15)).may_match(schema, stats).unwrap());
+        assert!(!cmp("id", CompareOp::EQ, Value::INT64(25)).may_match(schema, stats).unwrap());
+        assert!(!cmp("id", CompareOp::LT, Value::INT64(10)).may_match(schema, stats).unwrap());
+        assert!(cmp("id", CompareOp::LE, Value::INT64(10)).may_match(schema, stats).unwrap());
+        assert!(!cmp("id", CompareOp::GT, Value::INT64(20)).may_match(schema, stats).unwrap());
+
+        // No TEXT min/max, can't rule anything out
+        let text = cmp("name", CompareOp::EQ, Value::INT64(0));
+        assert!(text.may_match(schema, stats).unwrap());
+
+        let is_null = StatsPredicate::IsNull("id".to_string());
+        assert!(!is_null.may_match(schema, stats).unwrap());
+
+        let and = StatsPredicate::And(vec![
+            cmp("id", CompareOp::GE, Value::INT64(0)),
+            cmp("score", CompareOp::GT, Value::FLOAT64(1.0)),
+        ]);
+        assert!(!and.may_match(schema, stats).unwrap());
+
+        match cmp("missing", CompareOp::EQ, Value::INT64(0)).may_match(schema, stats) {
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Scan skips the block when the zone map excludes all rows
+    #[test]
+    fn scan_skips_block() {
+        let block = make_block();
+
+        let filter = StatsPredicate::Compare("id".to_string(), CompareOp::GT, Value::INT64(50));
+        let scan = ScanView::filtered(&block, None, filter);
+        let mut cursor = scan.bind(&allocator::GLOBAL).unwrap();
+
+        match cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            CursorChunk::End => (), // nop
+            CursorChunk::Next(_) => assert!(false, "Expected block to be skipped"),
+        }
+
+        let filter = StatsPredicate::Compare("id".to_string(), CompareOp::GT, Value::INT64(15));
+        let scan = ScanView::filtered(&block, None, filter);
+        let mut cursor = scan.bind(&allocator::GLOBAL).unwrap();
+
+        match cursor.next(DEFAULT_CURSOR_FETCH).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 3),
+            CursorChunk::End => assert!(false, "Expected rows"),
+        }
+    }
+}
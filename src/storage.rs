@@ -0,0 +1,719 @@
+//! On-disk columnar table files.
+//!
+//! A table file is a series of blocks (of up to `block_rows` rows), each block has a segment per
+//! column. Column data is written in the same layout it has in memory, PLAIN or encoded (RLE,
+//! DICTIONARY) as picked by `choose_encoding`, so reading a file is memory mapping it and
+//! aliasing the segments. Only the pages of the columns that are read get loaded.
+//!
+//! Layout:
+//!
+//! ```text
+//! MAGIC
+//! segments (each aligned to SEGMENT_ALIGN)
+//! footer (text: schema and block index)
+//! footer length (u64)
+//! MAGIC
+//! ```
+//!
+//! Segments are written in native byte order, files are not portable between architectures of
+//! different endianness. VARLEN (TEXT & BLOB) segments are an array of `u64` row offsets and the
+//! values heap. LIST and STRUCT columns are not supported.
+
+use std::cmp::min;
+use std::fs::File;
+use std::io::{BufWriter, Error as IOError, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::str;
+
+use ::allocator;
+use ::block::{Block, AliasColumn, Encoding, RefColumn, RefView, View, alias_column};
+use ::block::{column_dictionary_data, column_nulls, column_rle_data, column_row_data};
+use ::error::DBError;
+use ::row::{RowOffset, RowRange};
+use ::schema::{Attribute, Schema};
+use ::types::{self, Heap, RawData, Type};
+use ::util::copy_value::copy_column;
+
+/// Marks the beginning and end of a table file
+pub const MAGIC: &[u8; 8] = b"DBKTBL01";
+/// Alignment of the segments in the file, enough for any row data type
+pub const SEGMENT_ALIGN: usize = 16;
+/// Default number of rows in a file block
+pub const DEFAULT_BLOCK_ROWS: RowOffset = 64 * 1024;
+
+/// Range of bytes in the file
+#[derive(Clone, Copy, Debug, Default)]
+struct Extent {
+    offset: usize,
+    len: usize,
+}
+
+/// Column data of a file block
+struct Segment {
+    encoding: Encoding,
+    /// Rows of the column (window rows of RLE columns)
+    rows: RowOffset,
+    nulls: Extent,
+    /// Row data, `u32` run ends (RLE) or codes (DICTIONARY), `u64` offsets (VARLEN)
+    data: Extent,
+    /// Values of VARLEN rows
+    heap: Extent,
+    /// RLE run values or DICTIONARY values
+    child: Option<Box<Segment>>,
+    /// VARLEN rows, pointing into the mapped heap
+    varlen: Vec<RawData>,
+}
+
+struct FileBlock {
+    rows: RowOffset,
+    columns: Vec<Segment>,
+}
+
+/// Read only memory mapping of a whole file
+struct Mapping {
+    ptr: *const u8,
+    len: usize,
+}
+
+/// Memory mapped table file
+///
+/// Blocks are views aliasing the mapping. The file is trusted to be written by `write_view`,
+/// segment bounds are checked but the row values are not, apart from TEXT rows being UTF-8.
+pub struct TableFile {
+    map: Mapping,
+    schema: Schema,
+    blocks: Vec<FileBlock>,
+}
+
+fn io_error<S: Into<String>>(msg: S) -> DBError {
+    DBError::IO(IOError::new(ErrorKind::InvalidData, msg.into()))
+}
+
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>()) }
+}
+
+fn is_varlen(dtype: &Type) -> bool {
+    match *dtype {
+        Type::TEXT | Type::BLOB => true,
+        _                       => false,
+    }
+}
+
+/// Attribute of the child column of an encoded column
+fn child_attribute(attr: &Attribute, encoding: Encoding) -> Attribute {
+    match encoding {
+        Encoding::DICTIONARY =>
+            Attribute { name: String::from("dictionary"), nullable: false, dtype: Type::TEXT },
+        _ =>
+            Attribute { name: String::from("values"), nullable: false, dtype: attr.dtype.clone() },
+    }
+}
+
+struct Writer<W: Write> {
+    out: W,
+    offset: usize,
+}
+
+impl<W: Write> Writer<W> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DBError> {
+        self.out.write_all(data).map_err(DBError::IO)?;
+        self.offset += data.len();
+        Ok(())
+    }
+
+    fn extent(&mut self, data: &[u8]) -> Result<Extent, DBError> {
+        if data.is_empty() {
+            return Ok(Extent::default())
+        }
+
+        let pad = (SEGMENT_ALIGN - self.offset % SEGMENT_ALIGN) % SEGMENT_ALIGN;
+        self.write(&[0u8; SEGMENT_ALIGN][.. pad])?;
+
+        let out = Extent { offset: self.offset, len: data.len() };
+        self.write(data)?;
+        Ok(out)
+    }
+
+    fn varlen(&mut self, values: &[RawData], heap: Heap, nulls: Option<&[u8]>, rows: RowOffset)
+        -> Result<(Extent, Extent), DBError>
+    {
+        let mut offsets: Vec<u64> = Vec::with_capacity(rows + 1);
+        let mut out: Vec<u8> = Vec::new();
+
+        offsets.push(0);
+        for row in 0 .. rows {
+            // Values of NULL rows are not initialized
+            if nulls.map_or(true, |n| n[row] == 0) {
+                out.extend_from_slice(heap.bytes(&values[row]));
+            }
+            offsets.push(out.len() as u64);
+        }
+
+        Ok((self.extent(as_bytes(&offsets))?, self.extent(&out)?))
+    }
+
+    fn segment<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset)
+        -> Result<Segment, DBError>
+    {
+        let attr = col.attribute();
+        let nulls = column_nulls(col).map(|n| &n[.. rows]);
+        let encoding = col.encoding();
+
+        let null_extent = match nulls {
+            Some(n) => self.extent(n)?,
+            None    => Extent::default(),
+        };
+
+        let mut heap = Extent::default();
+        let mut child = None;
+
+        let data = match (encoding, &attr.dtype) {
+            (Encoding::PLAIN, &Type::LIST(_)) | (Encoding::PLAIN, &Type::STRUCT(_)) =>
+                return Err(DBError::Unsupported(format!("storing {} column {}",
+                                                        attr.dtype, attr.name))),
+            (Encoding::PLAIN, &Type::TEXT) => {
+                let values = column_row_data::<types::Text>(col)?;
+                let (data, values) = self.varlen(values.values, values.heap, nulls, rows)?;
+                heap = values;
+                data
+            },
+            (Encoding::PLAIN, &Type::BLOB) => {
+                let values = column_row_data::<types::Blob>(col)?;
+                let (data, values) = self.varlen(values.values, values.heap, nulls, rows)?;
+                heap = values;
+                data
+            },
+            (Encoding::PLAIN, dtype) => {
+                let raw = col.rows_raw_slice();
+                self.extent(&raw[.. rows * dtype.size_of()])?
+            },
+            (Encoding::RLE, _) => {
+                let runs = column_rle_data(col)?.run_ends;
+                let data = self.extent(as_bytes(runs))?;
+                child = Some(self.segment(col.child(0).unwrap(), runs.len())?);
+                data
+            },
+            (Encoding::DICTIONARY, _) => {
+                let codes = &column_dictionary_data(col)?.codes[.. rows];
+                let unique = (0 .. rows)
+                    .filter(|r| nulls.map_or(true, |n| n[*r] == 0))
+                    .map(|r| codes[r] as usize + 1)
+                    .max()
+                    .unwrap_or(0);
+
+                let data = self.extent(as_bytes(codes))?;
+                child = Some(self.segment(col.child(0).unwrap(), unique)?);
+                data
+            },
+        };
+
+        Ok(Segment {
+            encoding: encoding,
+            rows: rows,
+            nulls: null_extent,
+            data: data,
+            heap: heap,
+            child: child.map(Box::new),
+            varlen: Vec::new(),
+        })
+    }
+}
+
+/// Write the rows of `src` to a new table file at `path`, in blocks of `block_rows` rows.
+/// Columns are encoded per block when `choose_encoding` finds it worthwhile.
+pub fn write_view<'v, P>(path: P, src: &'v View<'v>, block_rows: RowOffset)
+    -> Result<(), DBError>
+    where P: AsRef<Path>
+{
+    write_file(path.as_ref(), src, block_rows).map_err(|e| e.in_file(path))
+}
+
+fn write_file<'v>(path: &Path, src: &'v View<'v>, block_rows: RowOffset) -> Result<(), DBError> {
+    let file = File::create(path).map_err(DBError::IO)?;
+    let mut writer = Writer { out: BufWriter::new(file), offset: 0 };
+    writer.write(MAGIC)?;
+
+    let schema = src.schema();
+    let block_rows = block_rows.max(1);
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    while offset < src.rows() {
+        let rows = min(block_rows, src.rows() - offset);
+        let range = RowRange { offset: offset, rows: rows };
+
+        let mut block = Block::new(&allocator::GLOBAL, schema);
+        block.add_rows(rows)?;
+
+        for pos in 0 .. schema.count() {
+            let col = alias_column(src.column(pos).unwrap(), Some(range))?;
+            copy_column(&col, block.column_mut(pos).unwrap(), rows)?;
+        }
+
+        block.encode_columns()?;
+
+        let mut columns = Vec::with_capacity(schema.count());
+        for pos in 0 .. schema.count() {
+            columns.push(writer.segment(block.column(pos).unwrap(), rows)?);
+        }
+
+        blocks.push(FileBlock { rows: rows, columns: columns });
+        offset += rows;
+    }
+
+    let footer = footer_text(schema, &blocks);
+    writer.write(footer.as_bytes())?;
+    writer.write(as_bytes(&[footer.len() as u64]))?;
+    writer.write(MAGIC)?;
+    writer.out.flush().map_err(DBError::IO)
+}
+
+fn segment_text(seg: &Segment, out: &mut Vec<String>) {
+    out.push(format!("{:?} {} {} {} {} {} {} {}", seg.encoding, seg.rows,
+                     seg.nulls.offset, seg.nulls.len, seg.data.offset, seg.data.len,
+                     seg.heap.offset, seg.heap.len));
+
+    if let Some(ref child) = seg.child {
+        segment_text(child, out);
+    }
+}
+
+/// Schema is a line per attribute (name, type and nullability separated by tabs), followed by a
+/// line per block and the block's column segments.
+fn footer_text(schema: &Schema, blocks: &[FileBlock]) -> String {
+    let mut out = vec![format!("schema {}", schema.count())];
+
+    for attr in schema.iter() {
+        let null = if attr.nullable { "NULL" } else { "NOT NULL" };
+        out.push(format!("{}\t{}\t{}", attr.name, attr.dtype, null));
+    }
+
+    out.push(format!("blocks {}", blocks.len()));
+    for block in blocks {
+        out.push(format!("block {}", block.rows));
+        for seg in &block.columns {
+            segment_text(seg, &mut out);
+        }
+    }
+
+    out.join("\n")
+}
+
+/// Parses the footer lines, checking the segments against the schema and file size
+struct FooterParser<'f> {
+    lines: ::std::str::Lines<'f>,
+    file_len: usize,
+}
+
+impl<'f> FooterParser<'f> {
+    fn line(&mut self) -> Result<&'f str, DBError> {
+        self.lines.next().ok_or_else(|| io_error("table file footer is truncated"))
+    }
+
+    /// Count of the `name` header line
+    fn header(&mut self, name: &str) -> Result<usize, DBError> {
+        let line = self.line()?;
+        let mut words = line.split(' ');
+
+        match (words.next(), words.next().map(|w| w.parse())) {
+            (Some(word), Some(Ok(count))) if word == name => Ok(count),
+            _ => Err(io_error(format!("expected {} in table file footer, got: {}", name, line))),
+        }
+    }
+
+    fn attribute(&mut self) -> Result<Attribute, DBError> {
+        let line = self.line()?;
+        let parts: Vec<&str> = line.split('\t').collect();
+
+        if parts.len() != 3 {
+            return Err(io_error(format!("malformed table file attribute: {}", line)))
+        }
+
+        Ok(Attribute {
+            name: parts[0].to_string(),
+            dtype: parts[1].parse()?,
+            nullable: parts[2] == "NULL",
+        })
+    }
+
+    fn extent(&self, offset: usize, len: usize, expect: usize) -> Result<Extent, DBError> {
+        if len != expect || offset + len > self.file_len || offset % SEGMENT_ALIGN != 0 {
+            return Err(io_error(format!("bad table file segment {}+{}", offset, len)))
+        }
+        Ok(Extent { offset: offset, len: len })
+    }
+
+    fn segment(&mut self, attr: &Attribute) -> Result<Segment, DBError> {
+        let line = self.line()?;
+        let malformed = || io_error(format!("malformed table file segment: {}", line));
+
+        let mut words = line.split(' ');
+        let encoding = match words.next() {
+            Some("PLAIN")       => Encoding::PLAIN,
+            Some("RLE")         => Encoding::RLE,
+            Some("DICTIONARY")  => Encoding::DICTIONARY,
+            _                   => return Err(malformed()),
+        };
+
+        let mut nums = Vec::with_capacity(7);
+        for word in words {
+            nums.push(word.parse::<usize>().map_err(|_| malformed())?);
+        }
+
+        if nums.len() != 7 {
+            return Err(malformed())
+        }
+
+        let rows = nums[0];
+        let nulls = self.extent(nums[1], nums[2], if attr.nullable { rows } else { 0 })?;
+
+        let (data_len, heap_len) = match (encoding, &attr.dtype) {
+            (Encoding::PLAIN, dtype) if is_varlen(dtype) => ((rows + 1) * 8, nums[6]),
+            (Encoding::PLAIN, &Type::LIST(_)) | (Encoding::PLAIN, &Type::STRUCT(_)) =>
+                return Err(malformed()),
+            (Encoding::PLAIN, dtype)    => (rows * dtype.size_of(), 0),
+            // A u32 run end per run, the run count is checked against the values below
+            (Encoding::RLE, _)          => (nums[4] / 4 * 4, 0),
+            (Encoding::DICTIONARY, _)   => (rows * 4, 0),
+        };
+
+        // Empty segments aren't written, their offset is 0
+        let data = self.extent(nums[3], nums[4], data_len)?;
+        let heap = self.extent(nums[5], nums[6], heap_len)?;
+
+        let child = match encoding {
+            Encoding::PLAIN => None,
+            _ => Some(Box::new(self.segment(&child_attribute(attr, encoding))?)),
+        };
+
+        if let Some(ref child) = child {
+            if encoding == Encoding::RLE && child.rows != data.len / 4 {
+                return Err(malformed())
+            }
+        }
+
+        Ok(Segment {
+            encoding: encoding,
+            rows: rows,
+            nulls: nulls,
+            data: data,
+            heap: heap,
+            child: child,
+            varlen: Vec::new(),
+        })
+    }
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> Result<Mapping, DBError> {
+        use libc::*;
+
+        let ptr = unsafe {
+            mmap(ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+
+        if ptr == MAP_FAILED {
+            return Err(DBError::IO(IOError::last_os_error()))
+        }
+
+        Ok(Mapping { ptr: ptr as *const u8, len: len })
+    }
+
+    fn data(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    fn slice(&self, extent: Extent) -> &[u8] {
+        &self.data()[extent.offset .. extent.offset + extent.len]
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { ::libc::munmap(self.ptr as *mut ::libc::c_void, self.len); }
+    }
+}
+
+/// VARLEN rows of a segment, handles of the segment heap
+fn varlen_rows(map: &Mapping, seg: &Segment) -> Result<Vec<RawData>, DBError> {
+    let offsets = unsafe {
+        slice::from_raw_parts(map.slice(seg.data).as_ptr() as *const u64, seg.rows + 1)
+    };
+    let heap = map.slice(seg.heap);
+
+    let mut out = Vec::with_capacity(seg.rows);
+    for row in 0 .. seg.rows {
+        let (start, end) = (offsets[row] as usize, offsets[row + 1] as usize);
+        if start > end || end > heap.len() || end > u32::max_value() as usize {
+            return Err(io_error(format!("bad table file VARLEN offsets in row {}", row)))
+        }
+
+        out.push(RawData { chunk: 0, offset: start as u32, size: (end - start) as u32 });
+    }
+
+    Ok(out)
+}
+
+/// Check the TEXT rows are UTF-8, `first` is the file row of the segment's first row
+fn check_text_rows(rows: &[RawData], heap: Heap, column: &str, first: RowOffset)
+    -> Result<(), DBError>
+{
+    match rows.iter().position(|r| str::from_utf8(heap.bytes(r)).is_err()) {
+        Some(row) => Err(DBError::InvalidUtf8 { row: first + row, column: column.to_string() }),
+        None => Ok(()),
+    }
+}
+
+/// Resolve the VARLEN rows of the segment (and its children) of the `column` attribute
+fn resolve_varlen(map: &Mapping, seg: &mut Segment, attr: &Attribute, column: &str,
+                  first: RowOffset) -> Result<(), DBError>
+{
+    if seg.encoding == Encoding::PLAIN && is_varlen(&attr.dtype) {
+        seg.varlen = varlen_rows(map, seg)?;
+        if attr.dtype == Type::TEXT {
+            check_text_rows(&seg.varlen, Heap::Contiguous(map.slice(seg.heap)), column, first)?;
+        }
+    }
+
+    // Rows of RLE runs and DICTIONARY values are not file rows
+    let child_attr = child_attribute(attr, seg.encoding);
+    match seg.child {
+        Some(ref mut child) => resolve_varlen(map, child, &child_attr, column, 0),
+        None => Ok(()),
+    }
+}
+
+impl TableFile {
+    /// Memory map the table file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TableFile, DBError> {
+        TableFile::open_file(path.as_ref()).map_err(|e| e.in_file(path))
+    }
+
+    fn open_file(path: &Path) -> Result<TableFile, DBError> {
+        let mut file = File::open(path).map_err(DBError::IO)?;
+        let len = file.metadata().map_err(DBError::IO)?.len() as usize;
+
+        let trailer = mem::size_of::<u64>() + MAGIC.len();
+        if len < MAGIC.len() + trailer {
+            return Err(io_error("not a table file: too short"))
+        }
+
+        let mut magic = [0u8; 8];
+        let mut footer_len = [0u8; 8];
+        file.read_exact(&mut magic).map_err(DBError::IO)?;
+        file.seek(SeekFrom::Start((len - trailer) as u64)).map_err(DBError::IO)?;
+        file.read_exact(&mut footer_len).map_err(DBError::IO)?;
+
+        if &magic != MAGIC {
+            return Err(io_error("not a table file: bad magic"))
+        }
+
+        let footer_len: u64 = unsafe { mem::transmute(footer_len) };
+        let footer_len = footer_len as usize;
+        if footer_len > len - trailer - MAGIC.len() {
+            return Err(io_error("bad table file footer length"))
+        }
+
+        let map = Mapping::new(&file, len)?;
+        let footer_start = len - trailer - footer_len;
+
+        let (schema, mut blocks) = {
+            let footer = ::std::str::from_utf8(&map.data()[footer_start .. len - trailer])
+                .map_err(|_| io_error("table file footer is not UTF-8"))?;
+
+            let mut parser = FooterParser { lines: footer.lines(), file_len: footer_start };
+
+            let mut attrs = Vec::new();
+            for _ in 0 .. parser.header("schema")? {
+                attrs.push(parser.attribute()?);
+            }
+
+            let mut blocks = Vec::new();
+            for _ in 0 .. parser.header("blocks")? {
+                let rows = parser.header("block")?;
+                let mut columns = Vec::with_capacity(attrs.len());
+                for attr in &attrs {
+                    let seg = parser.segment(attr)?;
+                    if seg.rows != rows {
+                        return Err(io_error("table file segment rows don't match its block"))
+                    }
+                    columns.push(seg);
+                }
+                blocks.push(FileBlock { rows: rows, columns: columns });
+            }
+
+            (Schema::from_vec(attrs)?, blocks)
+        };
+
+        let mut first = 0;
+        for block in &mut blocks {
+            for (seg, attr) in block.columns.iter_mut().zip(schema.iter()) {
+                resolve_varlen(&map, seg, attr, &attr.name, first)?;
+            }
+            first += block.rows;
+        }
+
+        Ok(TableFile { map: map, schema: schema, blocks: blocks })
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Rows in all the blocks of the file
+    pub fn rows(&self) -> RowOffset {
+        self.blocks.iter().map(|b| b.rows).sum()
+    }
+
+    fn alias<'f>(&'f self, seg: &'f Segment, attr: &Attribute) -> AliasColumn<'f> {
+        let (raw, heap) = if seg.varlen.is_empty() {
+            (self.map.slice(seg.data), Heap::Empty)
+        } else {
+            (as_bytes(&seg.varlen), Heap::Contiguous(self.map.slice(seg.heap)))
+        };
+
+        let children = match seg.child {
+            Some(ref child) => vec![self.alias(child, &child_attribute(attr, seg.encoding))],
+            None => Vec::new(),
+        };
+
+        let window = RowRange { offset: 0, rows: seg.rows };
+        unsafe {
+            AliasColumn::from_raw(attr.clone(), raw, self.map.slice(seg.nulls), heap, children,
+                                  seg.encoding, window)
+        }
+    }
+
+    /// View of the block at `pos`, aliasing the mapped file
+    pub fn block(&self, pos: usize) -> Result<RefView, DBError> {
+        let block = self.blocks.get(pos).ok_or(DBError::RowOutOfBounds)?;
+
+        let columns = block.columns.iter()
+            .zip(self.schema.iter())
+            .map(|(seg, attr)| self.alias(seg, attr))
+            .collect();
+
+        Ok(RefView::new(self.schema.clone(), columns, block.rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use ::block::column_value;
+    use ::table::{Table, TableAppender};
+    use ::types::Value;
+
+    // Values survive the round trip, in PLAIN and encoded blocks
+    #[test]
+    fn write_and_open() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "group".to_string(), nullable: false, dtype: Type::UINT64},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 100 {
+                appender = appender.add_row().set(v as u32).set((v / 50) as u64);
+                appender = if v % 10 == 0 {
+                    appender.set_null(true)
+                } else {
+                    appender.set(if v % 2 == 0 { "even" } else { "odd" })
+                };
+            }
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let path = env::temp_dir().join("dbkit-write-and-open.tbl");
+
+        write_view(&path, &block, 40).unwrap();
+        let file = TableFile::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(file.schema().iter().eq(schema.iter()));
+        assert_eq!(file.blocks(), 3);
+        assert_eq!(file.rows(), 100);
+
+        let first = file.block(0).unwrap();
+        assert_eq!(first.column(0).unwrap().encoding(), Encoding::PLAIN);
+        assert_eq!(first.column(1).unwrap().encoding(), Encoding::RLE);
+        assert_eq!(first.column(2).unwrap().encoding(), Encoding::DICTIONARY);
+
+        let mut row = 0;
+        for pos in 0 .. file.blocks() {
+            let view = file.block(pos).unwrap();
+            for r in 0 .. view.rows() {
+                let id = column_value(view.column(0).unwrap(), r).unwrap();
+                let group = column_value(view.column(1).unwrap(), r).unwrap();
+                let name = column_value(view.column(2).unwrap(), r).unwrap();
+
+                let expect = match row {
+                    n if n % 10 == 0    => Value::NULL,
+                    n if n % 2 == 0     => Value::TEXT("even"),
+                    _                   => Value::TEXT("odd"),
+                };
+
+                assert!(id == Value::UINT32(row as u32), "id of row {}", row);
+                assert!(group == Value::UINT64((row / 50) as u64), "group of row {}", row);
+                assert!(name == expect, "name of row {}", row);
+                row += 1;
+            }
+        }
+        assert_eq!(row, 100);
+
+        let missing = env::temp_dir().join("dbkit-storage-missing.tbl");
+        let err = TableFile::open(&missing).err().expect("Expected error");
+        match *err.root() {
+            DBError::IO(_) => (), // nop
+            ref e => assert!(false, "Unexpected error {}", e),
+        }
+        assert!(err.context().unwrap().path == Some(missing.display().to_string()));
+    }
+
+    // TEXT rows that aren't UTF-8 fail opening the file
+    #[test]
+    fn invalid_text() {
+        let schema = Schema::make_one_attr("name", false, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        for v in 0 .. 10 {
+            let row = table.add_row().unwrap();
+            table.set(0, row, format!("name-{}", v).as_str()).unwrap();
+        }
+
+        let block = table.take().unwrap();
+        let path = env::temp_dir().join("dbkit-invalid-text.tbl");
+        write_view(&path, &block, 4).unwrap();
+
+        let mut data = fs::read(&path).unwrap();
+        let at = data.windows(6).position(|w| w == b"name-6").unwrap();
+        data[at] = 0xff;
+        fs::write(&path, &data).unwrap();
+
+        let opened = TableFile::open(&path);
+        fs::remove_file(&path).unwrap();
+        match opened.as_ref().map_err(DBError::root) {
+            Err(&DBError::InvalidUtf8 { row: 6, ref column }) if column == "name" => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}
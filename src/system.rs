@@ -0,0 +1,192 @@
+// vim: set ts=4 sw=4 et :
+
+//! Built-in introspection tables.
+//!
+//! `SystemTables` is a snapshot of a `Catalog` and a `Metrics` registry as ordinary blocks, so
+//! they can be registered and queried like any other table:
+//!
+//! - `dbkit_tables`: name, columns, rows (estimated from the table scan)
+//! - `dbkit_columns`: table, position, name, type, nullable
+//! - `dbkit_queries`: id, sql, elapsed, error (NULL for successful queries), tenant
+//! - `dbkit_metrics`: name, value
+//!
+//! The tables don't change after the snapshot, take a new one to see later tables or queries.
+
+use ::allocator::Allocator;
+use ::block::Block;
+use ::catalog::Catalog;
+use ::error::DBError;
+use ::metrics::Metrics;
+use ::operation::Values;
+use ::schema::{Attribute, Schema};
+use ::types::{IntervalValue, Type, Value};
+
+pub const TABLES: &str = "dbkit_tables";
+pub const COLUMNS: &str = "dbkit_columns";
+pub const QUERIES: &str = "dbkit_queries";
+pub const METRICS: &str = "dbkit_metrics";
+
+pub struct SystemTables<'a> {
+    pub tables: Block<'a>,
+    pub columns: Block<'a>,
+    pub queries: Block<'a>,
+    pub metrics: Block<'a>,
+}
+
+fn attr(name: &str, nullable: bool, dtype: Type) -> Attribute {
+    Attribute { name: name.to_string(), nullable: nullable, dtype: dtype }
+}
+
+impl<'a> SystemTables<'a> {
+    pub fn snapshot(alloc: &'a Allocator, catalog: &Catalog, metrics: &Metrics)
+        -> Result<SystemTables<'a>, DBError>
+    {
+        let mut tables = Vec::new();
+        let mut columns = Vec::new();
+
+        // Type names are owned, so they're formatted before making the rows
+        let mut types = Vec::new();
+        for name in catalog.names() {
+            let schema = catalog.schema(name)?;
+            types.push(schema.iter().map(|a| a.dtype.to_string()).collect::<Vec<_>>());
+        }
+
+        for (name, types) in catalog.names().into_iter().zip(&types) {
+            let schema = catalog.schema(name)?;
+            let rows = catalog.scan(name)?.estimated_rows();
+            tables.push(vec![
+                Value::TEXT(name),
+                Value::INT64(schema.count() as i64),
+                Value::INT64(rows as i64),
+            ]);
+
+            for (pos, (attr, dtype)) in schema.iter().zip(types).enumerate() {
+                columns.push(vec![
+                    Value::TEXT(name),
+                    Value::INT64(pos as i64),
+                    Value::TEXT(&attr.name),
+                    Value::TEXT(dtype),
+                    Value::BOOLEAN(attr.nullable),
+                ]);
+            }
+        }
+
+        let tables = Values::new(Schema::from_vec(vec![
+            attr("name", false, Type::TEXT),
+            attr("columns", false, Type::INT64),
+            attr("rows", false, Type::INT64),
+        ])?, tables).execute(alloc)?;
+
+        let columns = Values::new(Schema::from_vec(vec![
+            attr("table", false, Type::TEXT),
+            attr("position", false, Type::INT64),
+            attr("name", false, Type::TEXT),
+            attr("type", false, Type::TEXT),
+            attr("nullable", false, Type::BOOLEAN),
+        ])?, columns).execute(alloc)?;
+
+        let logged = metrics.queries();
+        let queries = logged.iter().map(|q| vec![
+            Value::INT64(q.id as i64),
+            Value::TEXT(&q.sql),
+            Value::INTERVAL(IntervalValue {
+                months: 0,
+                days: 0,
+                micros: q.elapsed.as_secs() as i64 * 1_000_000 + q.elapsed.subsec_micros() as i64,
+            }),
+            q.error.as_ref().map_or(Value::NULL, |e| Value::TEXT(e)),
+            q.tenant.as_ref().map_or(Value::NULL, |t| Value::TEXT(t)),
+        ]).collect();
+
+        let queries = Values::new(Schema::from_vec(vec![
+            attr("id", false, Type::INT64),
+            attr("sql", false, Type::TEXT),
+            attr("elapsed", false, Type::INTERVAL),
+            attr("error", true, Type::TEXT),
+            attr("tenant", true, Type::TEXT),
+        ])?, queries).execute(alloc)?;
+
+        let counters = metrics.counters();
+        let metrics = counters.iter()
+            .map(|&(ref name, value)| vec![Value::TEXT(name), Value::INT64(value)])
+            .collect();
+
+        let metrics = Values::new(Schema::from_vec(vec![
+            attr("name", false, Type::TEXT),
+            attr("value", false, Type::INT64),
+        ])?, metrics).execute(alloc)?;
+
+        Ok(SystemTables { tables: tables, columns: columns, queries: queries, metrics: metrics })
+    }
+
+    /// Add the tables to the catalog under their `dbkit_` names
+    pub fn register(&'a self, catalog: &mut Catalog<'a>) {
+        catalog.register_view(TABLES, &self.tables);
+        catalog.register_view(COLUMNS, &self.columns);
+        catalog.register_view(QUERIES, &self.queries);
+        catalog.register_view(METRICS, &self.metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use ::allocator;
+    use ::block::{View, column_value};
+    use ::table::Table;
+
+    fn text<'v>(block: &'v Block, col: usize, row: usize) -> &'v str {
+        match column_value(block.column(col).unwrap(), row).unwrap() {
+            Value::TEXT(v) => v,
+            _ => panic!("Expected TEXT"),
+        }
+    }
+
+    // Catalog tables and metrics as rows, the snapshot can be registered with the catalog
+    #[test]
+    fn snapshot_rows() {
+        let schema = Schema::from_vec(vec![
+            attr("id", false, Type::INT64),
+            attr("label", true, Type::TEXT),
+        ]).unwrap();
+        let mut data = Table::new(&allocator::GLOBAL, &schema, None);
+        data.add_row().unwrap();
+
+        let metrics = Metrics::default();
+        metrics.increment("sql.queries", 2);
+        metrics.record_query("SELECT id FROM data", Duration::from_millis(3), None);
+        metrics.record_tenant_query(Some("acme"), "SELECT", Duration::from_millis(1),
+                                    Some("bad".to_string()));
+
+        let system;
+        let mut catalog = Catalog::new();
+        catalog.register_view("data", &data);
+
+        system = SystemTables::snapshot(&allocator::GLOBAL, &catalog, &metrics).unwrap();
+        system.register(&mut catalog);
+        assert_eq!(catalog.names(),
+                   vec!["data", COLUMNS, METRICS, QUERIES, TABLES]);
+
+        assert_eq!(system.tables.rows(), 1);
+        assert_eq!(text(&system.tables, 0, 0), "data");
+        assert!(column_value(system.tables.column(2).unwrap(), 0).unwrap() == Value::INT64(1));
+
+        assert_eq!(system.columns.rows(), 2);
+        assert_eq!(text(&system.columns, 2, 1), "label");
+        assert_eq!(text(&system.columns, 3, 1), "TEXT");
+        assert!(column_value(system.columns.column(4).unwrap(), 1).unwrap()
+                == Value::BOOLEAN(true));
+
+        assert_eq!(system.queries.rows(), 2);
+        assert_eq!(text(&system.queries, 1, 0), "SELECT id FROM data");
+        assert!(column_value(system.queries.column(3).unwrap(), 0).unwrap() == Value::NULL);
+        assert_eq!(text(&system.queries, 3, 1), "bad");
+        assert!(column_value(system.queries.column(4).unwrap(), 0).unwrap() == Value::NULL);
+        assert_eq!(text(&system.queries, 4, 1), "acme");
+
+        assert_eq!(system.metrics.rows(), 1);
+        assert_eq!(text(&system.metrics, 0, 0), "sql.queries");
+        assert_eq!(catalog.schema(METRICS).unwrap().count(), 2);
+    }
+}
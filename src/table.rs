@@ -1,16 +1,115 @@
+use std::sync::Arc;
+
 use super::allocator::{Allocator};
 use super::block::*;
 use super::error::DBError;
+use super::expression::{Expr, bound_attribute};
+use super::expression::convert::coerce;
 use super::schema::Schema;
+use super::stats::BlockStats;
 use super::row::RowOffset;
 use super::util::copy_value::ValueSetter;
+use super::types::{Boolean, IntervalValue, Type, Value};
 
 /// Abstraction on top of a `Block` for easy construction and modification of contained data.
 ///
 /// The container assumes that all operations on the block are safe and schema type conforming. In
 /// case of errors it simply panics.
+///
+/// The block is shared with the table's snapshots (see `snapshot()`), modifying a table with live
+/// snapshots first copies the block. Snapshots can be sent to and read from other threads.
 pub struct Table<'alloc> {
-    block: Option<Block<'alloc>>,
+    block: Option<Arc<Block<'alloc>>>,
+    /// Incremented by every modification
+    version: u64,
+}
+
+/// Immutable view of a `Table` as of a version. Later modifications of the table are not visible
+/// in the snapshot.
+#[derive(Clone)]
+pub struct TableSnapshot<'alloc> {
+    block: Arc<Block<'alloc>>,
+    version: u64,
+}
+
+/// Handling of table attributes the appended input doesn't have
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MissingColumns {
+    Error,
+    /// Fill with the attribute default: NULL if it's nullable, otherwise the zero value of its
+    /// type (0, false, "", ...)
+    Default,
+}
+
+/// Handling of input attributes the table doesn't have
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExtraColumns {
+    Error,
+    Drop,
+}
+
+/// Reconciliation of an appended input schema with the table schema, see
+/// `Table::append_reconciled()`. Attributes are matched by name.
+pub struct Reconcile {
+    pub missing: MissingColumns,
+    pub extra: ExtraColumns,
+    /// Values of missing attributes by name, used instead of the attribute default
+    pub defaults: Vec<(String, Value<'static>)>,
+}
+
+impl Reconcile {
+    /// The input has to have every table attribute and no others
+    pub fn strict() -> Reconcile {
+        Reconcile {
+            missing: MissingColumns::Error,
+            extra: ExtraColumns::Error,
+            defaults: Vec::new(),
+        }
+    }
+
+    /// Fill missing attributes and drop extra attributes
+    pub fn lenient() -> Reconcile {
+        Reconcile {
+            missing: MissingColumns::Default,
+            extra: ExtraColumns::Drop,
+            defaults: Vec::new(),
+        }
+    }
+
+    /// Fill the missing attribute with `value`
+    pub fn with_default<S: Into<String>>(mut self, name: S, value: Value<'static>) -> Reconcile {
+        self.defaults.push((name.into(), value));
+        self
+    }
+}
+
+/// Source of an attribute of reconciled rows
+enum Fill<'r> {
+    /// Attribute position in the input
+    Input(usize),
+    Value(&'r Value<'static>),
+    Zero(Value<'static>),
+    Null,
+}
+
+/// Zero value of the type, None for STRUCT
+fn zero_value(dtype: &Type) -> Option<Value<'static>> {
+    Some(match *dtype {
+        Type::UINT32    => Value::UINT32(0),
+        Type::UINT64    => Value::UINT64(0),
+        Type::INT32     => Value::INT32(0),
+        Type::INT64     => Value::INT64(0),
+        Type::FLOAT32   => Value::FLOAT32(0.0),
+        Type::FLOAT64   => Value::FLOAT64(0.0),
+        Type::BOOLEAN   => Value::BOOLEAN(false),
+        Type::TIMESTAMP => Value::TIMESTAMP(0),
+        Type::INTERVAL  => Value::INTERVAL(IntervalValue::default()),
+        Type::UUID      => Value::UUID([0; 16]),
+        Type::TEXT      => Value::TEXT(""),
+        Type::BLOB      => Value::BLOB(&[]),
+        Type::LIST(_)   => Value::LIST(Vec::new()),
+        Type::STRUCT(_) => return None,
+    })
 }
 
 impl<'alloc> View<'alloc> for Table<'alloc> {
@@ -34,6 +133,38 @@ impl<'alloc> View<'alloc> for Table<'alloc> {
             .unwrap()
             .rows()
     }
+
+    fn stats(&self) -> Option<&BlockStats> {
+        self.block
+            .as_ref()
+            .unwrap()
+            .stats()
+    }
+}
+
+impl<'alloc> View<'alloc> for TableSnapshot<'alloc> {
+    fn schema(&'alloc self) -> &'alloc Schema {
+        self.block.schema()
+    }
+
+    fn column(&'alloc self, pos: usize) -> Option<&RefColumn> {
+        self.block.column(pos)
+    }
+
+    fn rows(&self) -> RowOffset {
+        self.block.rows()
+    }
+
+    fn stats(&self) -> Option<&BlockStats> {
+        self.block.stats()
+    }
+}
+
+impl<'alloc> TableSnapshot<'alloc> {
+    /// Version of the table when the snapshot was taken
+    pub fn version(&self) -> u64 {
+        self.version
+    }
 }
 
 impl<'alloc> Table<'alloc> {
@@ -45,15 +176,38 @@ impl<'alloc> Table<'alloc> {
         }
 
         Table {
-            block: Some(Block::new(alloc, schema))
+            block: Some(Arc::new(Block::new(alloc, schema))),
+            version: 0,
         }
     }
 
+    /// Table appending to an existing `Block`, eg. one from a `BlockPool`.
+    pub fn from_block(block: Block<'alloc>) -> Table<'alloc> {
+        Table { block: Some(Arc::new(block)), version: 0 }
+    }
+
+    /// Block for modification, copying it first if it's shared with a snapshot
+    fn block_mut(&mut self) -> &mut Block<'alloc> {
+        self.version += 1;
+
+        let block = self.block.as_mut().unwrap();
+        if Arc::get_mut(block).is_none() {
+            let copy = block.deep_copy().expect("Copying the table block for modification");
+            *block = Arc::new(copy);
+        }
+
+        Arc::get_mut(block).unwrap()
+    }
+
+    /// Replace the block with a modified copy
+    fn replace_block(&mut self, block: Block<'alloc>) {
+        self.version += 1;
+        self.block = Some(Arc::new(block));
+    }
+
     /// Add a single row.
     pub fn add_row(&mut self) -> Result<RowOffset, DBError> {
-        self.block
-            .as_mut()
-            .unwrap()
+        self.block_mut()
             .add_row()
     }
 
@@ -64,25 +218,41 @@ impl<'alloc> Table<'alloc> {
     }
 
     pub fn block_ref_mut(&mut self) -> &'alloc mut Block {
-        self.block
-            .as_mut()
-            .unwrap()
+        self.block_mut()
     }
 
     /// Take ownership of the contained `Block`.
     ///
-    /// This is done when the `Table` is complete and is going to be used elsewhere.
+    /// This is done when the `Table` is complete and is going to be used elsewhere. The block is
+    /// copied if it's shared with a snapshot.
     pub fn take(&mut self) -> Option<Block<'alloc>> {
-        self.block.take()
+        self.block.take().map(|block| {
+            Arc::try_unwrap(block).unwrap_or_else(|shared| {
+                shared.deep_copy().expect("Copying the table block")
+            })
+        })
+    }
+
+    /// Cheap immutable handle of the current table rows. Modifying the table afterwards copies the
+    /// block (once), so the snapshot keeps seeing the rows as they are now.
+    pub fn snapshot(&self) -> TableSnapshot<'alloc> {
+        TableSnapshot { block: self.block.as_ref().unwrap().clone(), version: self.version }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Intern the values of the named TEXT columns, see `Block::intern_columns()`
+    pub fn intern_columns(&mut self, names: &[&str], max_entries: usize) -> Result<(), DBError> {
+        self.block_mut().intern_columns(names, max_entries)
     }
 
     /// Get a mutable reference to the `Table`/`Block` column.
     ///
     /// panics on out of bounds column
     pub fn column_mut(&mut self, pos: usize) -> Option<&mut Column<'alloc>> {
-        self.block
-            .as_mut()
-            .unwrap()
+        self.block_mut()
             .column_mut(pos)
     }
 
@@ -106,11 +276,231 @@ impl<'alloc> Table<'alloc> {
             return Err(DBError::RowOutOfBounds)
         }
 
-        // TODO: Clear null value
-
         self.column_mut(col)
             .ok_or(DBError::make_column_unknown_pos(col))
-            .and_then(|c| value.set_row(c, row))
+            .and_then(|c| {
+                value.set_row(c, row)?;
+
+                // Clear out previous NULL (or uninitialized null vector)
+                if c.attribute().nullable && !value.is_null() {
+                    c.nulls_mut()?[row] = 0;
+                }
+
+                Ok(())
+            })
+    }
+
+    /// Append (copy) all the rows of `src`. The attribute types and nullability have to match,
+    /// the attribute names don't.
+    pub fn append_block<'v>(&mut self, src: &'v View<'v>) -> Result<(), DBError> {
+        let count = self.block_ref().schema().count();
+
+        {
+            let schema = self.block_ref().schema();
+            let same = src.schema().count() == count && schema.iter().zip(src.schema().iter())
+                .all(|(a, b)| a.dtype == b.dtype && a.nullable == b.nullable);
+
+            if !same {
+                return Err(DBError::AttributeType(String::from("appended block schema")))
+            }
+        }
+
+        for row in 0 .. src.rows() {
+            let out = self.add_row()?;
+
+            for pos in 0 .. count {
+                let col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                self.set(pos, out, column_value(col, row)?)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Append (copy) all the rows of `src`, matching attributes by name. Table attributes missing
+    /// from `src` and `src` attributes missing from the table are handled as configured by
+    /// `reconcile`, so appends keep working when the input schema drifts (eg. gains a column).
+    ///
+    /// Matched attributes have to be of the same type. A NULL input value of an attribute that's
+    /// not nullable in the table is an error. These errors are found before any row is appended.
+    pub fn append_reconciled<'v>(&mut self, src: &'v View<'v>, reconcile: &Reconcile)
+        -> Result<(), DBError>
+    {
+        let fills = {
+            let schema = self.block_ref().schema();
+            let input = src.schema();
+
+            if reconcile.extra == ExtraColumns::Error {
+                if let Some(attr) = input.iter().find(|a| schema.find(&a.name).is_err()) {
+                    let msg = format!("{} (not in the table)", attr.name);
+                    return Err(DBError::AttributeMissing(msg))
+                }
+            }
+
+            let mut fills = Vec::with_capacity(schema.count());
+            for attr in schema.iter() {
+                let fill = match input.iter().position(|a| a.name == attr.name) {
+                    Some(pos) => {
+                        if input[pos].dtype != attr.dtype {
+                            return Err(DBError::AttributeType(format!("{}: {} appended to {}",
+                                attr.name, input[pos].dtype, attr.dtype)))
+                        }
+                        Fill::Input(pos)
+                    },
+                    None if reconcile.missing == MissingColumns::Error =>
+                        return Err(DBError::AttributeMissing(
+                            format!("{} (not in the input)", attr.name))),
+                    None => match reconcile.defaults.iter().find(|d| d.0 == attr.name) {
+                        Some(&(_, Value::NULL)) if !attr.nullable =>
+                            return Err(DBError::make_column_not_nullable(attr.name.clone())),
+                        Some((_, value)) => Fill::Value(value),
+                        None if attr.nullable => Fill::Null,
+                        None => Fill::Zero(zero_value(&attr.dtype).ok_or_else(||
+                            DBError::Unsupported(format!("default value of {}", attr.dtype)))?),
+                    },
+                };
+
+                // NULL values of an attribute that isn't nullable, before appending anything
+                if let Fill::Input(pos) = fill {
+                    if input[pos].nullable && !attr.nullable {
+                        let col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                        for row in 0 .. src.rows() {
+                            if column_value(col, row)?.is_null() {
+                                return Err(DBError::make_column_not_nullable(attr.name.clone()))
+                            }
+                        }
+                    }
+                }
+
+                fills.push((fill, attr.nullable));
+            }
+
+            fills
+        };
+
+        for row in 0 .. src.rows() {
+            let out = self.add_row()?;
+
+            for (pos, &(ref fill, nullable)) in fills.iter().enumerate() {
+                let value = match *fill {
+                    Fill::Input(from) => {
+                        let col = src.column(from).ok_or(DBError::make_column_unknown_pos(from))?;
+                        self.set(pos, out, column_value(col, row)?)?;
+                        continue
+                    },
+                    Fill::Value(value) => value,
+                    Fill::Zero(ref value) => value,
+                    Fill::Null => &Value::NULL,
+                };
+
+                let col = self.column_mut(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                value.set_row(col, out)?;
+                if nullable && !value.is_null() {
+                    col.nulls_mut()?[out] = 0;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rows where the BOOLEAN `predicate` is true (and not NULL)
+    fn matching_rows<'e, E: Expr<'e>>(&self, predicate: &E) -> Result<Vec<bool>, DBError>
+        where 'alloc: 'e
+    {
+        let block = self.block.as_ref().unwrap();
+        let bound = predicate.bind(block.allocator(), block.schema())?;
+
+        if bound_attribute(&*bound)?.dtype != Type::BOOLEAN {
+            return Err(DBError::ExpressionInputType(
+                format!("table predicate {} is not BOOLEAN", bound.describe())))
+        }
+
+        let rows = block.rows();
+        let out = bound.evaluate(&**block, rows)?;
+        let data = column_row_data::<Boolean>(out.column(0).unwrap())?;
+        let nullable = out.schema()[0].nullable;
+
+        Ok((0 .. rows).map(|r| data.values[r] && !(nullable && data.nulls[r] != 0)).collect())
+    }
+
+    /// Delete the rows where the BOOLEAN `predicate` is true, returns the number of deleted rows.
+    ///
+    /// The remaining rows are copied into a new block, RLE encoded columns stay encoded.
+    pub fn delete_where<'e, E: Expr<'e>>(&mut self, predicate: E) -> Result<RowOffset, DBError>
+        where 'alloc: 'e
+    {
+        let matched = self.matching_rows(&predicate)?;
+        let deleted = matched.iter().filter(|m| **m).count();
+        if deleted == 0 {
+            return Ok(0)
+        }
+
+        let out = {
+            let block = self.block.as_ref().unwrap();
+            let alloc = block.allocator();
+            let keep: Vec<u8> = matched.iter().map(|m| !*m as u8).collect();
+
+            let mut columns = Vec::with_capacity(block.schema().count());
+            for pos in 0 .. block.schema().count() {
+                columns.push(filter_column(alloc, block.column(pos).unwrap(), &keep)?);
+            }
+
+            Block::from_columns(alloc, columns, block.rows() - deleted)?
+        };
+
+        self.replace_block(out);
+        Ok(deleted)
+    }
+
+    /// Set the named attributes to the value of their expressions in rows where the BOOLEAN
+    /// `predicate` is true, returns the number of updated rows.
+    ///
+    /// Expressions are evaluated over the rows before the update, and their result is converted
+    /// to the attribute type. The updated rows are copied into a new block.
+    pub fn update<'e, E: Expr<'e>>(&mut self, set: Vec<(&str, Box<Expr<'e> + 'e>)>, predicate: E)
+        -> Result<RowOffset, DBError>
+        where 'alloc: 'e
+    {
+        let matched = self.matching_rows(&predicate)?;
+        let updated = matched.iter().filter(|m| **m).count();
+        if updated == 0 {
+            return Ok(0)
+        }
+
+        let out = {
+            let block = self.block.as_ref().unwrap();
+            let alloc = block.allocator();
+            let rows = block.rows();
+
+            // Attribute position and its values for each row
+            let mut values: Vec<(usize, Block)> = Vec::with_capacity(set.len());
+            for (name, expr) in set {
+                let pos = block.schema().exists_ok(name)?;
+                let dtype = block.schema()[pos].dtype.clone();
+                let bound = coerce(alloc, expr.bind(alloc, block.schema())?, &dtype)?;
+                values.push((pos, bound.evaluate(&**block, rows)?));
+            }
+
+            let mut table = Table::new(alloc, block.schema(), None);
+            for row in 0 .. rows {
+                let out = table.add_row()?;
+
+                for pos in 0 .. block.schema().count() {
+                    let src = match values.iter().find(|v| v.0 == pos) {
+                        Some(v) if matched[row] => v.1.column(0).unwrap(),
+                        _ => block.column(pos).unwrap(),
+                    };
+
+                    table.set(pos, out, column_value(src, row)?)?;
+                }
+            }
+
+            table.take().unwrap()
+        };
+
+        self.replace_block(out);
+        Ok(updated)
     }
 }
 
@@ -196,6 +586,7 @@ mod tests {
     use allocator;
     use error::DBError;
     use schema::*;
+    use testing::{values_approx_eq, Tolerance};
     use types::*;
 
     // Append one row to table via TableAppender, verify that underlying block has one row.
@@ -250,7 +641,7 @@ mod tests {
     fn varlen_columns() {
         let bytes: [u8; 5] = [0, 1, 2, 3, 4];
 
-        let table = {
+        let mut table = {
             let attrs = vec![
                 Attribute{name: "one".to_string(), nullable: false, dtype: Type::BLOB},
                 Attribute{name: "two".to_string(), nullable: false, dtype: Type::TEXT},
@@ -279,15 +670,697 @@ mod tests {
         {
             let col0 = table.block_ref().column(0).unwrap();
             let rows = column_row_data::<Blob>(col0).unwrap();
-            assert_eq!(rows.values[0].as_ref(), bytes);
-            assert_eq!(rows.values[1].as_ref(), bytes);
+            assert_eq!(rows.bytes(0), bytes);
+            assert_eq!(rows.bytes(1), bytes);
         }
 
         {
             let col1 = table.block_ref().column(1).unwrap();
             let rows = column_row_data::<Text>(col1).unwrap();
-            assert_eq!(rows.values[0].as_ref() as &str, "one");
-            assert_eq!(rows.values[1].to_string(), String::from("two"));
+            assert_eq!(rows.try_as_str(0).unwrap(), "one");
+            assert_eq!(rows.try_as_str(1).unwrap(), "two");
+        }
+
+        // TEXT rows written as raw bytes are checked when read
+        let mut block = table.take().unwrap();
+        {
+            let col = block.column_mut(1).unwrap();
+            let handle = col.arena().unwrap().append(&[b'a', 0xc3]).unwrap();
+            col.rows_mut::<Text>().unwrap()[1] = handle;
+        }
+
+        let rows = block.rows();
+        let invalid = |e: &DBError| match *e {
+            DBError::InvalidUtf8 { row: 1, ref column } => column == "two",
+            _ => false,
+        };
+        assert!(column_value(block.column(1).unwrap(), 1).err().map_or(false, |e| invalid(&e)));
+        assert!(column_iter_opt::<Text>(block.column(1).unwrap(), rows).err()
+                .map_or(false, |e| invalid(&e)));
+        assert!(block.column_mut(1).unwrap().encode_dictionary(rows).err()
+                .map_or(false, |e| invalid(&e)));
+    }
+
+    // LIST rows with NULL elements, an empty list and a NULL list
+    #[test]
+    fn list_columns() {
+        let dtype = "LIST<INT32>".parse::<Type>().unwrap();
+        let schema = Schema::make_one_attr("list", true, dtype);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(vec![Value::INT32(1), Value::NULL, Value::INT32(3)])
+                .add_row().set(Vec::<i32>::new())
+                .add_row().set_null(true)
+                .add_row().set(vec![4 as i32])
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let column = table.block_ref().column(0).unwrap();
+        assert_eq!(column.attribute().dtype.to_string(), "LIST<INT32>");
+
+        let first = column_value(column, 0).unwrap();
+        let items = first.as_list().unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(match items[1] { Value::NULL => true, _ => false }, "Expected NULL element");
+        assert!(match items[2] { Value::INT32(3) => true, _ => false }, "Expected 3");
+
+        assert_eq!(column_value(column, 1).unwrap().as_list().unwrap().len(), 0);
+        assert!(column_value(column, 2).unwrap().as_list().is_none(), "Expected NULL list");
+
+        let last = column_value(column, 3).unwrap();
+        assert!(values_approx_eq(&last, &Value::LIST(vec![Value::INT32(4)]), Tolerance::Exact));
+    }
+
+    // Duplicates share a code, NULL rows keep their NULL. Values are decoded on read.
+    #[test]
+    fn dictionary_columns() {
+        let schema = Schema::make_one_attr("name", true, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("red")
+                .add_row().set("blue")
+                .add_row().set("red")
+                .add_row().set_null(true)
+                .add_row().set("blue")
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let mut block = table.take().unwrap();
+        let rows = block.rows();
+        block.column_mut(0).unwrap().encode_dictionary(rows).unwrap();
+
+        let column = block.column(0).unwrap();
+        assert_eq!(column.encoding(), Encoding::DICTIONARY);
+
+        let data = column_dictionary_data(column).unwrap();
+        assert_eq!(data.codes[..3], [0, 1, 0]);
+        assert_eq!(data.codes[4], 1);
+
+        let exact = Tolerance::Exact;
+        assert!(values_approx_eq(&column_value(column, 2).unwrap(), &Value::TEXT("red"), exact));
+        assert!(values_approx_eq(&column_value(column, 3).unwrap(), &Value::NULL, exact));
+
+        match column_row_data::<Text>(column) {
+            Err(DBError::ColumnEncoding(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // RLE encoded columns are readable by value and filter/take keep them RLE encoded
+    #[test]
+    fn rle_columns() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: true, dtype: Type::INT32},
+            Attribute{name: "name".to_string(), nullable: false, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1 as i32).set("a")
+                .add_row().set(1 as i32).set("a")
+                .add_row().set(1 as i32).set("b")
+                .add_row().set_null(true).set("a")
+                .add_row().set_null(true).set("b")
+                .add_row().set(7 as i32).set("a")
+                .add_row().set(7 as i32).set("b")
+                .add_row().set(7 as i32).set("a")
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let mut block = table.take().unwrap();
+        let rows = block.rows();
+
+        // Too few rows for RLE to pay off, unlike for the repeated TEXT values
+        assert_eq!(choose_encoding(block.column(0).unwrap(), rows).unwrap(), Encoding::PLAIN);
+        block.column_mut(0).unwrap().encode_rle(rows).unwrap();
+        block.encode_columns().unwrap();
+
+        let exact = Tolerance::Exact;
+        let column = block.column(0).unwrap();
+        assert_eq!(column.encoding(), Encoding::RLE);
+        assert_eq!(block.column(1).unwrap().encoding(), Encoding::DICTIONARY);
+
+        let runs: Vec<_> = column_rle_data(column).unwrap().runs().collect();
+        assert_eq!(runs, vec![(0, 0, 3), (1, 3, 2), (2, 5, 3)]);
+
+        assert!(values_approx_eq(&column_value(column, 2).unwrap(), &Value::INT32(1), exact));
+        assert!(values_approx_eq(&column_value(column, 4).unwrap(), &Value::NULL, exact));
+        assert!(values_approx_eq(&column_value(column, 7).unwrap(), &Value::INT32(7), exact));
+
+        let selection = [0, 1, 0, 1, 0, 0, 1, 1];
+        let filtered = filter_column(&allocator::GLOBAL, column, &selection).unwrap();
+        assert_eq!(filtered.encoding(), Encoding::RLE);
+        assert_eq!(column_rle_data(&filtered).unwrap().runs().count(), 3);
+
+        let taken = take_column(&allocator::GLOBAL, column, &[6, 5, 0, 3]).unwrap();
+        assert_eq!(taken.encoding(), Encoding::RLE);
+        assert_eq!(column_rle_data(&taken).unwrap().runs().count(), 3);
+
+        let expect = [Value::INT32(1), Value::NULL, Value::INT32(7), Value::INT32(7)];
+        for (row, value) in expect.iter().enumerate() {
+            assert!(values_approx_eq(&column_value(&filtered, row).unwrap(), value, exact));
+        }
+
+        let expect = [Value::INT32(7), Value::INT32(7), Value::INT32(1), Value::NULL];
+        for (row, value) in expect.iter().enumerate() {
+            assert!(values_approx_eq(&column_value(&taken, row).unwrap(), value, exact));
+        }
+
+        match column_row_data::<Int32>(column) {
+            Err(DBError::ColumnEncoding(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+
+    // Returned blocks come back empty but with their capacity, other schemas get new blocks
+    #[test]
+    fn block_pool_reuse() {
+        let schema = Schema::make_one_attr("name", true, Type::TEXT);
+        let pool = BlockPool::new(&allocator::GLOBAL, 1);
+
+        let mut table = Table::from_block(pool.get(&schema));
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("first")
+                .add_row().set_null(true)
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
         }
+
+        let block = table.take().unwrap();
+        let capacity = block.capacity();
+        pool.put(block);
+        assert_eq!(pool.len(), 1);
+
+        let other = Schema::make_one_attr("id", false, Type::INT32);
+        assert_eq!(pool.get(&other).capacity(), 0);
+
+        let mut table = Table::from_block(pool.get(&schema));
+        assert!(pool.is_empty());
+        assert_eq!(table.rows(), 0);
+        assert_eq!(table.block_ref().capacity(), capacity);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("second")
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let exact = Tolerance::Exact;
+        let block = table.take().unwrap();
+        let value = column_value(block.column(0).unwrap(), 0).unwrap();
+        assert!(values_approx_eq(&value, &Value::TEXT("second"), exact));
+    }
+
+    // Row values are checked against the schema, TEXT is copied and NULL flags set
+    #[test]
+    fn block_append_rows() {
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "id".to_string(), nullable: false, dtype: Type::INT64 },
+            Attribute { name: "name".to_string(), nullable: true, dtype: Type::TEXT },
+        ]).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+
+        {
+            let name = format!("row {}", 0);
+            let row = block.append_row(&[Value::INT64(0), Value::TEXT(&name)]).unwrap();
+            assert_eq!(row, 0);
+        }
+
+        let rows = vec![
+            vec![Value::INT64(1), Value::NULL],
+            vec![Value::INT64(2), Value::TEXT("two")],
+        ];
+        assert_eq!(block.append_rows(&rows).unwrap(), 1);
+
+        assert!(column_value(block.column(1).unwrap(), 0).unwrap() == Value::TEXT("row 0"));
+        assert!(column_value(block.column(1).unwrap(), 1).unwrap() == Value::NULL);
+        assert!(column_value(block.column(0).unwrap(), 2).unwrap() == Value::INT64(2));
+
+        match block.append_row(&[Value::INT32(3), Value::NULL]) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        match block.append_row(&[Value::NULL, Value::NULL]) {
+            Err(DBError::AttributeNullability(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        assert!(block.append_row(&[Value::INT64(3)]).is_err());
+
+        // A bad row leaves the block unchanged
+        let rows = vec![
+            vec![Value::INT64(3), Value::NULL],
+            vec![Value::INT64(4), Value::INT64(4)],
+        ];
+        assert!(block.append_rows(&rows).is_err());
+        assert_eq!(block.rows(), 3);
+    }
+
+    // Cells of any type, encoded or not, read as values
+    #[test]
+    fn view_values() {
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "id".to_string(), nullable: false, dtype: Type::UINT32 },
+            Attribute { name: "name".to_string(), nullable: true, dtype: Type::TEXT },
+        ]).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        for row in 0 .. 100 {
+            let name = if row % 10 == 0 { Value::NULL } else { Value::TEXT("same") };
+            block.append_row(&[Value::UINT32(row), name]).unwrap();
+        }
+
+        assert!(block.value(5, 0).unwrap() == Value::UINT32(5));
+        assert!(block.value(10, 1).unwrap() == Value::NULL);
+
+        block.encode_columns().unwrap();
+        assert_eq!(block.column(1).unwrap().encoding(), Encoding::DICTIONARY);
+        assert!(block.value(11, 1).unwrap() == Value::TEXT("same"));
+
+        match block.value(100, 0) {
+            Err(DBError::RowOutOfBounds) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        assert!(block.value(0, 2).is_err());
+    }
+
+    // Deleted rows are gone, updated rows take the expression value, other rows are unchanged
+    #[test]
+    fn delete_and_update() {
+        use expression::column::ColumnExpr;
+        use expression::comparison::EqaulsExpr;
+
+        let attrs = vec![
+            Attribute{name: "a".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "b".to_string(), nullable: true, dtype: Type::UINT32},
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for v in 0 .. 5 {
+                appender = appender.add_row().set(v as u32).set((v % 3) as u32);
+            }
+            appender = appender.add_row().set(5 as u32).set_null(true);
+
+            let status = appender.done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let eq = || EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("b"));
+        assert_eq!(table.delete_where(eq()).unwrap(), 3);
+        assert_eq!(table.rows(), 3);
+
+        let mut other = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut other)
+                .add_row().set(9 as u32).set(9 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        table.append_block(other.block_ref()).unwrap();
+        assert_eq!(table.rows(), 4);
+
+        // Every row with a non NULL b
+        let set: Vec<(&str, Box<Expr>)> = vec![("a", Box::new(ColumnExpr::named("b")))];
+        let non_null = EqaulsExpr::new(ColumnExpr::named("b"), ColumnExpr::named("b"));
+        assert_eq!(table.update(set, non_null).unwrap(), 3);
+
+        let exact = Tolerance::Exact;
+        let expect = [(0, Value::UINT32(0)), (1, Value::UINT32(1)), (5, Value::NULL),
+                      (9, Value::UINT32(9))];
+        for (row, &(a, ref b)) in expect.iter().enumerate() {
+            let block = table.block_ref();
+            let value = column_value(block.column(0).unwrap(), row).unwrap();
+            assert!(values_approx_eq(&value, &Value::UINT32(a), exact));
+            let value = column_value(block.column(1).unwrap(), row).unwrap();
+            assert!(values_approx_eq(&value, b, exact));
+        }
+
+        assert_eq!(table.delete_where(eq()).unwrap(), 3);
+        assert_eq!(table.rows(), 1);
+    }
+
+    // Snapshots keep seeing the rows as of when they were taken
+    #[test]
+    fn snapshot_isolation() {
+        use expression::column::ColumnExpr;
+        use expression::comparison::EqaulsExpr;
+
+        let schema = Schema::make_one_attr("a", false, Type::UINT32);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1 as u32)
+                .add_row().set(2 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let first = table.snapshot();
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(3 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+        table.set(0, 0, 7 as u32).unwrap();
+
+        let second = table.snapshot();
+        assert!(second.version() > first.version());
+
+        let a_eq_a = EqaulsExpr::new(ColumnExpr::named("a"), ColumnExpr::named("a"));
+        assert_eq!(table.delete_where(a_eq_a).unwrap(), 3);
+
+        let first_rows = column_row_data::<UInt32>(first.column(0).unwrap()).unwrap();
+        assert_eq!(&first_rows.values[.. first.rows()], &[1, 2]);
+
+        let second_rows = column_row_data::<UInt32>(second.column(0).unwrap()).unwrap();
+        assert_eq!(&second_rows.values[.. second.rows()], &[7, 2, 3]);
+
+        assert_eq!(table.rows(), 0);
+    }
+
+    // Snapshots (and their TEXT rows) are readable from other threads
+    #[test]
+    fn snapshot_threads() {
+        use std::thread;
+
+        let schema = Schema::make_one_attr("name", false, Type::TEXT);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set("one")
+                .add_row().set("two")
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let snapshot = table.snapshot();
+        let readers: Vec<_> = (0 .. 2)
+            .map(|row| {
+                let snapshot = snapshot.clone();
+                thread::spawn(move || {
+                    let rows = column_row_data::<Text>(snapshot.column(0).unwrap()).unwrap();
+                    rows.try_as_str(row).unwrap().to_string()
+                })
+            })
+            .collect();
+
+        table.set(0, 0, "changed").unwrap();
+
+        let names: Vec<String> = readers.into_iter().map(|r| r.join().unwrap()).collect();
+        assert_eq!(names, vec!["one", "two"]);
+    }
+
+    // Block clones share their column data until one of them is modified
+    #[test]
+    fn block_clones() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.append_rows(&[[Value::UINT32(1), Value::TEXT("one")],
+                            [Value::UINT32(2), Value::NULL]]).unwrap();
+
+        let mut copy = block.clone();
+        assert!(copy.column_mut(1).unwrap().is_shared());
+        assert!(copy.eq_data(&block));
+
+        copy.append_row(&[Value::UINT32(3), Value::TEXT("three")]).unwrap();
+        "uno".set_row(copy.column_mut(1).unwrap(), 0).unwrap();
+        assert!(!copy.column_mut(1).unwrap().is_shared());
+
+        assert_eq!(block.rows(), 2);
+        assert!(block.value(0, 1).unwrap() == Value::TEXT("one"));
+        assert!(copy.value(0, 1).unwrap() == Value::TEXT("uno"));
+        assert!(copy.value(2, 1).unwrap() == Value::TEXT("three"));
+
+        // Clearing a shared block leaves the other clones alone
+        let mut other = block.clone();
+        assert!(other.clear().is_none());
+        other.append_row(&[Value::UINT32(4), Value::NULL]).unwrap();
+        assert!(block.value(0, 0).unwrap() == Value::UINT32(1));
+        assert!(block.value(1, 1).unwrap() == Value::NULL);
+    }
+
+    // Slices and splits alias the block rows
+    #[test]
+    fn block_slices() {
+        let schema = Schema::make_one_attr("name", true, Type::TEXT);
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.append_rows(&[[Value::TEXT("a")], [Value::NULL], [Value::TEXT("c")],
+                            [Value::TEXT("d")]]).unwrap();
+
+        let slice = block.slice(1, 2).unwrap();
+        assert_eq!(slice.rows(), 2);
+        assert!(slice.value(0, 0).unwrap() == Value::NULL);
+        assert!(slice.value(1, 0).unwrap() == Value::TEXT("c"));
+        assert!(block.slice(3, 2).is_err());
+
+        let (head, tail) = block.split_at(3).unwrap();
+        assert_eq!((head.rows(), tail.rows()), (3, 1));
+        assert!(tail.value(0, 0).unwrap() == Value::TEXT("d"));
+
+        let (empty, all) = tail.split_at(0).unwrap();
+        assert_eq!((empty.rows(), all.rows()), (0, 1));
+        assert!(all.value(0, 0).unwrap() == Value::TEXT("d"));
+
+        match block.split_at(5) {
+            Err(DBError::RowOutOfBounds) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    // Rows are taken in index order, repeated rows are copied again
+    #[test]
+    fn take_rows() {
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "tags".to_string(), nullable: false,
+                      dtype: Type::LIST(Box::new(Type::INT32))},
+        ]).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.append_rows(&[
+            vec![Value::TEXT("a"), Value::LIST(vec![Value::INT32(1)])],
+            vec![Value::NULL, Value::LIST(vec![])],
+            vec![Value::TEXT("c"), Value::LIST(vec![Value::INT32(3), Value::INT32(4)])],
+        ]).unwrap();
+
+        let out = take(&allocator::GLOBAL, &block, &[2, 1, 2, 0]).unwrap();
+        assert_eq!(out.rows(), 4);
+        assert_eq!(out.schema().count(), 2);
+
+        let names = [Value::TEXT("c"), Value::NULL, Value::TEXT("c"), Value::TEXT("a")];
+        for (row, name) in names.iter().enumerate() {
+            assert!(out.value(row, 0).unwrap() == *name);
+        }
+        assert!(out.value(2, 1).unwrap() == Value::LIST(vec![Value::INT32(3), Value::INT32(4)]));
+        assert!(out.value(1, 1).unwrap() == Value::LIST(vec![]));
+
+        assert_eq!(take(&allocator::GLOBAL, &block, &[]).unwrap().rows(), 0);
+        match take(&allocator::GLOBAL, &block, &[0, 3]) {
+            Err(DBError::RowOutOfBounds) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+    }
+
+    // Concatenated blocks keep the row order, blocks of other types can't be concatenated
+    #[test]
+    fn block_concat() {
+        let schema = Schema::make_one_attr("name", true, Type::TEXT);
+        let mut first = Block::new(&allocator::GLOBAL, &schema);
+        first.append_rows(&[[Value::TEXT("a")], [Value::NULL]]).unwrap();
+        let mut second = Block::new(&allocator::GLOBAL, &schema);
+        second.append_row(&[Value::TEXT("c")]).unwrap();
+
+        let out = Block::concat(&[&first, &second, &first]).unwrap();
+        assert_eq!(out.rows(), 5);
+        assert!(out.value(1, 0).unwrap() == Value::NULL);
+        assert!(out.value(2, 0).unwrap() == Value::TEXT("c"));
+        assert!(out.value(3, 0).unwrap() == Value::TEXT("a"));
+        assert!(Block::concat(&[&first]).unwrap().eq_data(&first));
+
+        let blob = Schema::make_one_attr("name", true, Type::BLOB);
+        let other = Block::new(&allocator::GLOBAL, &blob);
+        match Block::concat(&[&first, &other]) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        };
+        assert!(Block::concat(&[]).is_err());
+    }
+
+    // Appending an input with a dropped and an added attribute
+    #[test]
+    fn append_reconciled() {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "note".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "score".to_string(), nullable: false, dtype: Type::INT64},
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        let attrs = vec![
+            Attribute{name: "extra".to_string(), nullable: false, dtype: Type::BOOLEAN},
+            Attribute{name: "id".to_string(), nullable: true, dtype: Type::UINT32},
+        ];
+        let mut input = Table::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap(), None);
+        {
+            let status = TableAppender::new(&mut input)
+                .add_row().set(true).set(1 as u32)
+                .add_row().set(false).set(2 as u32)
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        for reconcile in &[Reconcile::strict(), Reconcile { extra: ExtraColumns::Drop,
+                                                            .. Reconcile::strict() }] {
+            match table.append_reconciled(input.block_ref(), reconcile) {
+                Err(DBError::AttributeMissing(_)) => (), // nop
+                Err(e) => assert!(false, "Unexpected error {}", e),
+                Ok(_) => assert!(false, "Expected error"),
+            }
+        }
+
+        table.append_reconciled(input.block_ref(), &Reconcile::lenient()).unwrap();
+        let lenient = Reconcile::lenient().with_default("note", Value::TEXT("n/a"));
+        table.append_reconciled(input.block_ref(), &lenient).unwrap();
+        assert_eq!(table.rows(), 4);
+
+        let value = |col: usize, row: usize| column_value(table.column(col).unwrap(), row);
+        assert!(value(0, 1).unwrap() == Value::UINT32(2));
+        assert!(value(1, 0).unwrap() == Value::NULL);
+        assert!(value(1, 3).unwrap() == Value::TEXT("n/a"));
+        assert!(value(2, 2).unwrap() == Value::INT64(0));
+
+        // NULL id isn't appended into the not nullable attribute
+        input.set_null(1, 0, true).unwrap();
+        match table.append_reconciled(input.block_ref(), &Reconcile::lenient()) {
+            Err(DBError::AttributeNullability(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+        assert_eq!(table.rows(), 4);
+    }
+
+    // Conforming to a declared schema changes column nullability, NULLs of not nullable
+    // attributes are errors
+    #[test]
+    fn block_conform() {
+        let built = SchemaBuilder::new()
+            .add_nullable("a", Type::INT64)
+            .add("b", Type::TEXT)
+            .done()
+            .unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &built);
+        block.append_rows(&[[Value::INT64(1), Value::TEXT("x")],
+                            [Value::INT64(2), Value::TEXT("y")]]).unwrap();
+
+        let declared = SchemaBuilder::new()
+            .add("a", Type::INT64).meta("unit", "ms")
+            .add_nullable("b", Type::TEXT)
+            .done()
+            .unwrap();
+
+        let mut block = block.conform(&declared).unwrap();
+        assert!(!block.schema()[0].nullable);
+        assert!(block.schema()[1].nullable);
+        assert_eq!(block.schema().metadata(0).unwrap()["unit"], "ms");
+        assert!(block.value(1, 0).unwrap() == Value::INT64(2));
+        assert!(block.value(0, 1).unwrap() == Value::TEXT("x"));
+
+        block.append_row(&[Value::INT64(3), Value::NULL]).unwrap();
+        assert!(block.value(2, 1).unwrap() == Value::NULL);
+
+        let strict = SchemaBuilder::new()
+            .add("a", Type::INT64)
+            .add("b", Type::TEXT)
+            .done()
+            .unwrap();
+        match block.conform(&strict) {
+            Err(DBError::AttributeNullability(ref name)) => assert_eq!(name, "b"),
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let block = Block::new(&allocator::GLOBAL, &built);
+        let mistyped = SchemaBuilder::new()
+            .add("a", Type::INT32)
+            .add("b", Type::TEXT)
+            .done()
+            .unwrap();
+        assert!(block.conform(&mistyped).is_err());
+    }
+
+    // Options move values in and out, None for NULL
+    #[test]
+    fn option_values() {
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "id".to_string(), nullable: true, dtype: Type::UINT32 },
+            Attribute { name: "name".to_string(), nullable: true, dtype: Type::TEXT },
+        ]).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        let row = table.add_row().unwrap();
+        table.set(0, row, Some(7u32)).unwrap();
+        table.set(1, row, None::<&str>).unwrap();
+        let row = table.add_row().unwrap();
+        table.set(0, row, None::<u32>).unwrap();
+        table.set(1, row, Some("b")).unwrap();
+
+        let mut block = table.take().unwrap();
+        block.append_row(&[Some(9u32).into(), None::<&str>.into()]).unwrap();
+        let ids: Vec<Option<u32>> = block.column(0).map(|col| {
+            ::block::column_iter_opt::<UInt32>(col, block.rows()).unwrap().collect()
+        }).unwrap();
+        assert_eq!(ids, vec![Some(7), None, Some(9)]);
+
+        let names = block.column(1).unwrap();
+        let names: Vec<Option<&str>> =
+            ::block::column_iter_opt::<Text>(names, block.rows()).unwrap().collect();
+        assert_eq!(names, vec![None, Some("b"), None]);
+        assert!(Value::from(Some(1.5f64)) == Value::FLOAT64(1.5));
+        assert!(Value::from(None::<bool>) == Value::NULL);
+
+        match ::block::column_iter_opt::<Int64>(block.column(0).unwrap(), 2) {
+            Err(DBError::AttributeType(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        let rows = block.rows();
+        let col = block.column_mut(0).unwrap();
+        assert_eq!(col.iter_opt::<UInt32>(rows).unwrap().filter(|v| v.is_none()).count(), 1);
     }
 }
@@ -0,0 +1,420 @@
+// vim: set ts=4 sw=4 et :
+
+//! Comparison helpers for tests of operations and expressions.
+//!
+//! `assert_cursor_eq!(left, right[, tolerance])` compares the rows of two cursors in order and
+//! panics with the first difference:
+//!
+//! ```ignore
+//! let mut expected = ScanView::new(&block, None).bind(&allocator::GLOBAL)?;
+//! let mut actual = op.bind(&allocator::GLOBAL)?;
+//! assert_cursor_eq!(&mut *expected, &mut *actual, Tolerance::Ulps(4));
+//! ```
+
+use std::fmt;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::fmt::format_value;
+use ::operation::{Cursor, CursorChunk, DEFAULT_CURSOR_FETCH};
+use ::row::RowOffset;
+use ::table::Table;
+use ::types::Value;
+use ::util::hash;
+
+/// Panic with the first difference between the rows of two cursors (`&mut Cursor`), optionally
+/// comparing floats with a `Tolerance`. See `cursors_diff`.
+#[macro_export]
+macro_rules! assert_cursor_eq {
+    ($left:expr, $right:expr) => {
+        assert_cursor_eq!($left, $right, $crate::testing::Tolerance::Exact)
+    };
+    ($left:expr, $right:expr, $tolerance:expr) => {
+        match $crate::testing::cursors_diff($left, $right, $tolerance) {
+            Ok(None) => (),
+            Ok(Some(diff)) => panic!("Cursors differ: {}", diff),
+            Err(e) => panic!("Error reading cursors: {}", e),
+        }
+    };
+}
+
+/// How close two floating point values have to be to be considered equal.
+///
+/// For all tolerances two NaNs compare equal, and infinities are only equal to the same
+/// infinity.
+#[derive(Clone, Copy, Debug)]
+pub enum Tolerance {
+    /// `a == b`
+    Exact,
+    /// `|a - b| <= eps`
+    Absolute(f64),
+    /// `|a - b| <= eps * max(|a|, |b|)`
+    Relative(f64),
+    /// `a` and `b` are at most N representable values apart
+    Ulps(u32),
+}
+
+/// Map float bits onto integers that are ordered the same way as the floats
+fn ordered_bits_f64(v: f64) -> i64 {
+    let bits = v.to_bits() as i64;
+    if bits < 0 { i64::min_value() - bits } else { bits }
+}
+
+fn ordered_bits_f32(v: f32) -> i32 {
+    let bits = v.to_bits() as i32;
+    if bits < 0 { i32::min_value() - bits } else { bits }
+}
+
+fn special_eq(a: f64, b: f64) -> Option<bool> {
+    if a.is_nan() || b.is_nan() {
+        Some(a.is_nan() && b.is_nan())
+    } else if a.is_infinite() || b.is_infinite() {
+        Some(a == b)
+    } else {
+        None
+    }
+}
+
+pub fn approx_eq_f64(a: f64, b: f64, tolerance: Tolerance) -> bool {
+    if let Some(eq) = special_eq(a, b) {
+        return eq
+    }
+
+    match tolerance {
+        Tolerance::Exact        => a == b,
+        Tolerance::Absolute(e)  => (a - b).abs() <= e,
+        Tolerance::Relative(e)  => (a - b).abs() <= e * a.abs().max(b.abs()),
+        Tolerance::Ulps(ulps)   => {
+            let (x, y) = (ordered_bits_f64(a), ordered_bits_f64(b));
+            let dist = if x > y { x.wrapping_sub(y) } else { y.wrapping_sub(x) } as u64;
+            dist <= ulps as u64
+        },
+    }
+}
+
+/// ULP tolerance is measured in FLOAT32 representable values
+pub fn approx_eq_f32(a: f32, b: f32, tolerance: Tolerance) -> bool {
+    match tolerance {
+        Tolerance::Ulps(ulps) if special_eq(a as f64, b as f64).is_none() => {
+            let (x, y) = (ordered_bits_f32(a), ordered_bits_f32(b));
+            let dist = if x > y { x.wrapping_sub(y) } else { y.wrapping_sub(x) } as u32;
+            dist <= ulps
+        },
+        _ =>
+            approx_eq_f64(a as f64, b as f64, tolerance),
+    }
+}
+
+/// Compare two values, using `tolerance` for FLOAT32 / FLOAT64. Other types have to be exactly
+/// equal and of the same variant. NULL is equal to NULL. LISTs and STRUCTs are compared
+/// element-wise.
+pub fn values_approx_eq(a: &Value, b: &Value, tolerance: Tolerance) -> bool {
+    match (a, b) {
+        (&Value::NULL, &Value::NULL)                    => true,
+        (&Value::UINT32(x), &Value::UINT32(y))          => x == y,
+        (&Value::UINT64(x), &Value::UINT64(y))          => x == y,
+        (&Value::INT32(x), &Value::INT32(y))            => x == y,
+        (&Value::INT64(x), &Value::INT64(y))            => x == y,
+        (&Value::FLOAT32(x), &Value::FLOAT32(y))        => approx_eq_f32(x, y, tolerance),
+        (&Value::FLOAT64(x), &Value::FLOAT64(y))        => approx_eq_f64(x, y, tolerance),
+        (&Value::BOOLEAN(x), &Value::BOOLEAN(y))        => x == y,
+        (&Value::TIMESTAMP(x), &Value::TIMESTAMP(y))    => x == y,
+        (&Value::INTERVAL(x), &Value::INTERVAL(y))      => x == y,
+        (&Value::UUID(x), &Value::UUID(y))              => x == y,
+        (&Value::TEXT(x), &Value::TEXT(y))              => x == y,
+        (&Value::BLOB(x), &Value::BLOB(y))              => x == y,
+        (&Value::LIST(ref x), &Value::LIST(ref y))      =>
+            x.len() == y.len() && x.iter().zip(y).all(|(a, b)| values_approx_eq(a, b, tolerance)),
+        (&Value::STRUCT(ref x), &Value::STRUCT(ref y))  =>
+            x.len() == y.len() && x.iter().zip(y).all(|(a, b)| values_approx_eq(a, b, tolerance)),
+        _                                               => false,
+    }
+}
+
+/// First difference between the rows of two views, see `views_diff`
+#[derive(Clone, PartialEq, Debug)]
+pub enum Difference {
+    /// Different number of attributes, or types (or nullability) of an attribute
+    Schema(String),
+    /// Different values, formatted, of the row and attribute
+    Value { row: RowOffset, attribute: String, left: String, right: String },
+    /// Same values up to the shorter side, different number of rows (left, right)
+    Rows(RowOffset, RowOffset),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Difference::Schema(ref msg) => write!(f, "schema: {}", msg),
+            Difference::Value { row, ref attribute, ref left, ref right } =>
+                write!(f, "row {} attribute {}: {} != {}", row, attribute, left, right),
+            Difference::Rows(left, right) => write!(f, "{} rows != {} rows", left, right),
+        }
+    }
+}
+
+/// First difference between the rows of the views, in row order. Values are compared with
+/// `values_approx_eq` (NULL equals NULL), attributes by position: their names don't matter.
+pub fn views_diff<'v>(left: &'v View<'v>, right: &'v View<'v>, tolerance: Tolerance)
+    -> Result<Option<Difference>, DBError>
+{
+    let (lschema, rschema) = (left.schema(), right.schema());
+    if lschema.count() != rschema.count() {
+        let msg = format!("{} attributes != {} attributes", lschema.count(), rschema.count());
+        return Ok(Some(Difference::Schema(msg)))
+    }
+
+    for (l, r) in lschema.iter().zip(rschema.iter()) {
+        if l.dtype != r.dtype || l.nullable != r.nullable {
+            let msg = format!("attribute {} {}{} != {}{}", l.name, l.dtype,
+                              if l.nullable { " NULL" } else { "" }, r.dtype,
+                              if r.nullable { " NULL" } else { "" });
+            return Ok(Some(Difference::Schema(msg)))
+        }
+    }
+
+    for row in 0 .. left.rows().min(right.rows()) {
+        for (pos, attr) in lschema.iter().enumerate() {
+            let lcol = left.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            let rcol = right.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            let (l, r) = (column_value(lcol, row)?, column_value(rcol, row)?);
+
+            if !values_approx_eq(&l, &r, tolerance) {
+                return Ok(Some(Difference::Value {
+                    row: row,
+                    attribute: attr.name.clone(),
+                    left: format_value(&l, &attr.dtype, "NULL"),
+                    right: format_value(&r, &attr.dtype, "NULL"),
+                }))
+            }
+        }
+    }
+
+    if left.rows() != right.rows() {
+        return Ok(Some(Difference::Rows(left.rows(), right.rows())))
+    }
+
+    Ok(None)
+}
+
+/// All the rows of the cursor
+fn collect_rows<'a>(cursor: &mut Cursor<'a>, alloc: &'a Allocator) -> Result<Block<'a>, DBError> {
+    let mut out = Table::new(alloc, cursor.schema(), None);
+    loop {
+        match cursor.next(DEFAULT_CURSOR_FETCH)? {
+            CursorChunk::Next(view) => out.append_block(&view)?,
+            CursorChunk::End        => break,
+        }
+    }
+
+    Ok(out.take().unwrap())
+}
+
+/// First difference between all the rows of the cursors, in order (see `views_diff`). The rows
+/// can be split into chunks differently.
+pub fn cursors_diff<'a>(left: &mut Cursor<'a>, right: &mut Cursor<'a>, tolerance: Tolerance)
+    -> Result<Option<Difference>, DBError>
+{
+    let alloc = &::allocator::GLOBAL;
+    let (left, right) = (collect_rows(left, alloc)?, collect_rows(right, alloc)?);
+    views_diff(&left, &right, tolerance)
+}
+
+/// Strip trailing zeros from a decimal stored as an unscaled integer and scale (number of digits
+/// after the decimal point), eg. (1500, 3) is 1.500 and normalizes to (15, 1).
+pub fn normalize_decimal(unscaled: i64, scale: u32) -> (i64, u32) {
+    let (mut value, mut scale) = (unscaled, scale);
+
+    if value == 0 {
+        return (0, 0)
+    }
+
+    while scale > 0 && value % 10 == 0 {
+        value /= 10;
+        scale -= 1;
+    }
+
+    (value, scale)
+}
+
+/// Decimal equality regardless of scale, eg. 1.50 (150, 2) equals 1.5 (15, 1)
+pub fn decimal_eq(a: (i64, u32), b: (i64, u32)) -> bool {
+    normalize_decimal(a.0, a.1) == normalize_decimal(b.0, b.1)
+}
+
+/// Seed of the `util::hash` hashes of `result_fingerprint`
+const FINGERPRINT_SEED: u64 = 0x6669_6e67_6572_7072;
+
+/// Hash of all the rows returned by the cursor that does not depend on the order of the rows (or
+/// how they're split into chunks). Attribute types are part of the hash, attribute names are not.
+///
+/// Floats are hashed exactly (-0.0 equals 0.0 and all NaNs are the same), use `values_approx_eq`
+/// for results that can differ in rounding. Values are hashed with `util::hash`, so fingerprints
+/// are the same on every platform and Rust version and can be kept as expected results.
+pub fn result_fingerprint<'a>(cursor: &mut Cursor<'a>) -> Result<u64, DBError> {
+    let mut schema_hash = FINGERPRINT_SEED;
+    for attr in cursor.schema().iter() {
+        let dtype = attr.dtype.to_string();
+        schema_hash = hash::combine(schema_hash, hash::hash_value(&Value::TEXT(&dtype), 0));
+        schema_hash = hash::combine(schema_hash, attr.nullable as u64);
+    }
+
+    // Rows are combined with addition, so the same rows in any order (including duplicates)
+    // produce the same sum.
+    let mut rows_sum: u64 = 0;
+    let mut rows_count: u64 = 0;
+
+    loop {
+        let view = match cursor.next(DEFAULT_CURSOR_FETCH)? {
+            CursorChunk::Next(view) => view,
+            CursorChunk::End        => break,
+        };
+
+        let columns: Vec<usize> = (0 .. view.schema().count()).collect();
+        for row_hash in hash::hash_rows(&view, &columns, FINGERPRINT_SEED)? {
+            rows_sum = rows_sum.wrapping_add(row_hash);
+            rows_count += 1;
+        }
+    }
+
+    Ok(hash::combine(hash::combine(schema_hash, rows_count), rows_sum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{f32, f64};
+    use ::allocator;
+    use ::block::Block;
+    use ::operation::{Operation, ScanView};
+    use ::schema::{Attribute, Schema};
+    use ::table::Table;
+    use ::types::Type;
+
+    #[test]
+    fn float_tolerance() {
+        let sum = 0.1 + 0.2;
+        assert!(!approx_eq_f64(sum, 0.3, Tolerance::Exact));
+        assert!(approx_eq_f64(sum, 0.3, Tolerance::Ulps(1)));
+        assert!(approx_eq_f64(sum, 0.3, Tolerance::Absolute(1e-12)));
+        assert!(approx_eq_f64(1e20, 1e20 + 1e5, Tolerance::Relative(1e-12)));
+        assert!(!approx_eq_f64(1.0, 1.0001, Tolerance::Relative(1e-12)));
+
+        // Both sides of zero
+        assert!(approx_eq_f64(0.0, -0.0, Tolerance::Ulps(0)));
+        assert!(approx_eq_f32(f32::MIN_POSITIVE, -f32::MIN_POSITIVE, Tolerance::Ulps(2 << 23)));
+
+        assert!(approx_eq_f64(f64::NAN, f64::NAN, Tolerance::Exact));
+        assert!(!approx_eq_f64(f64::INFINITY, f64::MAX, Tolerance::Ulps(1)));
+    }
+
+    #[test]
+    fn decimal_scale() {
+        assert_eq!(normalize_decimal(1500, 3), (15, 1));
+        assert_eq!(normalize_decimal(0, 5), (0, 0));
+        assert!(decimal_eq((150, 2), (15, 1)));
+        assert!(!decimal_eq((150, 2), (15, 2)));
+    }
+
+    #[test]
+    fn value_compare() {
+        let tol = Tolerance::Absolute(1e-9);
+        assert!(values_approx_eq(&Value::FLOAT64(0.1 + 0.2), &Value::FLOAT64(0.3), tol));
+        assert!(values_approx_eq(&Value::TEXT("a"), &Value::TEXT("a"), tol));
+        assert!(!values_approx_eq(&Value::UINT32(1), &Value::UINT64(1), tol));
+        assert!(values_approx_eq(&Value::NULL, &Value::NULL, tol));
+    }
+
+    fn make_block(rows: &[(u32, Option<&str>)]) -> Block<'static> {
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        for &(id, name) in rows {
+            let row = table.add_row().unwrap();
+            table.set(0, row, id).unwrap();
+            match name {
+                Some(n) => table.set(1, row, n).unwrap(),
+                None    => table.set_null(1, row, true).unwrap(),
+            }
+        }
+
+        table.take().unwrap()
+    }
+
+    fn fingerprint(block: &Block) -> u64 {
+        let scan = ScanView::new(block, None);
+        let mut cursor = scan.bind(&allocator::GLOBAL).unwrap();
+        result_fingerprint(&mut *cursor).unwrap()
+    }
+
+    // Differences are reported at the first differing row and attribute
+    #[test]
+    fn view_differences() {
+        let a = make_block(&[(1, Some("one")), (2, None), (3, Some("three"))]);
+        let b = make_block(&[(1, Some("one")), (2, Some("two")), (3, Some("three"))]);
+        let c = make_block(&[(1, Some("one")), (2, None)]);
+
+        assert_eq!(views_diff(&a, &a, Tolerance::Exact).unwrap(), None);
+        let diff = views_diff(&a, &b, Tolerance::Exact).unwrap().unwrap();
+        assert_eq!(diff.to_string(), "row 1 attribute name: NULL != two");
+        assert_eq!(views_diff(&a, &c, Tolerance::Exact).unwrap(), Some(Difference::Rows(3, 2)));
+
+        let schema = Schema::make_one_attr("id", false, Type::UINT32);
+        let other = Block::new(&allocator::GLOBAL, &schema);
+        match views_diff(&a, &other, Tolerance::Exact).unwrap() {
+            Some(Difference::Schema(_)) => (), // nop
+            d => assert!(false, "Expected schema difference, not {:?}", d),
+        }
+
+        assert!(a.eq_data(&a.deep_copy().unwrap()));
+        assert!(!a.eq_data(&b));
+    }
+
+    // Cursors compare equal with the tolerance, and panic on the first difference
+    #[test]
+    fn cursor_assertions() {
+        let schema = Schema::make_one_attr("x", true, Type::FLOAT64);
+        let approx = |values: &[Option<f64>]| {
+            let mut block = Block::new(&allocator::GLOBAL, &schema);
+            for v in values {
+                block.append_row(&[v.map_or(Value::NULL, Value::FLOAT64)]).unwrap();
+            }
+            block
+        };
+
+        let a = approx(&[Some(0.1 + 0.2), None, Some(f64::NAN)]);
+        let b = approx(&[Some(0.3), None, Some(f64::NAN)]);
+        let bind = |block| ScanView::new(block, None).bind(&allocator::GLOBAL).unwrap();
+
+        assert_cursor_eq!(&mut *bind(&a), &mut *bind(&a));
+        assert_cursor_eq!(&mut *bind(&a), &mut *bind(&b), Tolerance::Ulps(1));
+
+        let diff = cursors_diff(&mut *bind(&a), &mut *bind(&b), Tolerance::Exact).unwrap();
+        match diff {
+            Some(Difference::Value { row: 0, .. }) => (), // nop
+            d => assert!(false, "Expected value difference, not {:?}", d),
+        }
+    }
+
+    // Fingerprint is the same regardless of row order, and different for different row multisets
+    #[test]
+    fn fingerprint_order_insensitive() {
+        let a = make_block(&[(1, Some("one")), (2, None), (3, Some("three"))]);
+        let b = make_block(&[(3, Some("three")), (1, Some("one")), (2, None)]);
+        let c = make_block(&[(1, Some("one")), (2, Some("two")), (3, Some("three"))]);
+        let d = make_block(&[(1, Some("one")), (1, Some("one")), (2, None), (3, Some("three"))]);
+
+        assert_eq!(fingerprint(&a), fingerprint(&b));
+        assert!(fingerprint(&a) != fingerprint(&c), "NULL not distinguished");
+        assert!(fingerprint(&a) != fingerprint(&d), "Duplicate row not counted");
+
+        // Fixed across platforms and toolchains
+        assert_eq!(fingerprint(&a), 0xc0d5_1157_af4f_ea7c);
+    }
+}
@@ -0,0 +1,451 @@
+// vim: set ts=4 sw=4 et :
+
+//! Random schemas and blocks, for property based tests of operations and serialization formats.
+//!
+//! A `Generator` is seeded, the same seed and `GenConfig` give the same schemas and blocks, so a
+//! failing case can be reproduced from the seed printed by the test:
+//!
+//! ```ignore
+//! let mut gen = Generator::new(seed);
+//! let schema = gen.schema();
+//! let block = gen.block(&allocator::GLOBAL, &schema)?;
+//! ```
+//!
+//! `round_trip_diff` runs a conversion (eg. writing and reading back a format) on random blocks
+//! and returns the first block it doesn't preserve. Rows are compared with
+//! `testing::views_diff`.
+
+use ::allocator::{self, Allocator};
+use ::block::Block;
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::testing::{Tolerance, views_diff};
+use ::types::{IntervalValue, Type, Value};
+use ::util::random::SplitMix64;
+use ::util::temporal::{MICROS_PER_DAY, days_from_civil};
+
+/// Distribution of the generated values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    /// Any value of the type: integers of the whole range, finite floats of any exponent,
+    /// TIMESTAMPs of years 1 to 9999, INTERVALs of up to 10000 years (and a year of micros) and
+    /// TEXT / BLOB values of up to `GenConfig::max_len` bytes
+    Uniform,
+    /// N distinct values (0 to N - 1, eg. "v7" for TEXT), as likely as each other
+    Bounded(u64),
+    /// N distinct values, skewed towards the smaller ones (log uniform)
+    Skewed(u64),
+    /// Edge values of the type: the limits, zero, NaN, infinities, empty TEXT and BLOB, ...
+    Edge,
+}
+
+/// What a `Generator` generates
+#[derive(Clone)]
+pub struct GenConfig {
+    /// Smallest and largest block rows
+    pub rows: (RowOffset, RowOffset),
+    /// Most attributes of a schema (at least 1)
+    pub max_attrs: usize,
+    /// Possible attribute types
+    pub types: Vec<Type>,
+    /// Probability of a value of a nullable attribute being NULL. Attributes are nullable, half
+    /// of the time, unless it's 0.
+    pub null_density: f64,
+    pub distribution: Distribution,
+    /// Longest TEXT (in bytes) or BLOB value, of the `Uniform` distribution
+    pub max_len: usize,
+    /// Most elements of a LIST value
+    pub max_list_len: usize,
+}
+
+impl Default for GenConfig {
+    fn default() -> GenConfig {
+        GenConfig {
+            rows: (0, 100),
+            max_attrs: 5,
+            types: vec![Type::UINT32, Type::UINT64, Type::INT32, Type::INT64, Type::FLOAT32,
+                        Type::FLOAT64, Type::BOOLEAN, Type::TIMESTAMP, Type::INTERVAL, Type::UUID,
+                        Type::TEXT, Type::BLOB],
+            null_density: 0.1,
+            distribution: Distribution::Uniform,
+            max_len: 32,
+            max_list_len: 4,
+        }
+    }
+}
+
+/// Generated value, owning its TEXT and BLOB data
+enum Datum {
+    Scalar(Value<'static>),
+    Text(String),
+    Blob(Vec<u8>),
+    List(Vec<Datum>),
+    Struct(Vec<Datum>),
+}
+
+impl Datum {
+    fn value(&self) -> Value {
+        match *self {
+            Datum::Scalar(ref v) => copy_scalar(v),
+            Datum::Text(ref v) => Value::TEXT(v),
+            Datum::Blob(ref v) => Value::BLOB(v),
+            Datum::List(ref items) => Value::LIST(items.iter().map(|d| d.value()).collect()),
+            Datum::Struct(ref fields) => Value::STRUCT(fields.iter().map(|d| d.value()).collect()),
+        }
+    }
+}
+
+/// Copy of a value without TEXT, BLOB, LIST or STRUCT data
+fn copy_scalar(v: &Value<'static>) -> Value<'static> {
+    match *v {
+        Value::UINT32(v) => Value::UINT32(v),
+        Value::UINT64(v) => Value::UINT64(v),
+        Value::INT32(v) => Value::INT32(v),
+        Value::INT64(v) => Value::INT64(v),
+        Value::FLOAT32(v) => Value::FLOAT32(v),
+        Value::FLOAT64(v) => Value::FLOAT64(v),
+        Value::BOOLEAN(v) => Value::BOOLEAN(v),
+        Value::TIMESTAMP(v) => Value::TIMESTAMP(v),
+        Value::INTERVAL(v) => Value::INTERVAL(v),
+        Value::UUID(v) => Value::UUID(v),
+        _ => Value::NULL,
+    }
+}
+
+/// Characters of the generated TEXT values, including multi-byte ones
+const TEXT_CHARS: &[char] = &['a', 'b', 'z', 'A', '0', '9', ' ', '_', ',', '"', '\n', '\0', 'é',
+                              'ß', '中', '🙂'];
+
+/// Seeded generator of random schemas and blocks
+pub struct Generator {
+    rng: SplitMix64,
+    pub config: GenConfig,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator::with_config(seed, GenConfig::default())
+    }
+
+    pub fn with_config(seed: u64, config: GenConfig) -> Generator {
+        Generator { rng: SplitMix64::new(seed), config: config }
+    }
+
+    /// Uniform in [lo, hi]
+    fn range(&mut self, lo: u64, hi: u64) -> u64 {
+        match (hi - lo).checked_add(1) {
+            Some(n) => lo + self.rng.below(n),
+            None => self.rng.next_u64(),
+        }
+    }
+
+    fn pick<'t, T>(&mut self, items: &'t [T]) -> &'t T {
+        &items[self.rng.below(items.len() as u64) as usize]
+    }
+
+    /// Schema of 1 to `max_attrs` attributes ("c0", "c1", ...) of the configured types
+    pub fn schema(&mut self) -> Schema {
+        let count = self.range(1, self.config.max_attrs.max(1) as u64) as usize;
+        let attrs = (0 .. count).map(|pos| {
+            let pick = self.rng.below(self.config.types.len() as u64) as usize;
+            let dtype = self.config.types[pick].clone();
+            let nullable = self.config.null_density > 0.0 && self.rng.below(2) == 0;
+            Attribute { name: format!("c{}", pos), nullable: nullable, dtype: dtype }
+        }).collect();
+
+        Schema::from_vec(attrs).unwrap()
+    }
+
+    /// Number of rows of a block, in the configured range
+    pub fn rows(&mut self) -> RowOffset {
+        let (lo, hi) = self.config.rows;
+        self.range(lo as u64, hi.max(lo) as u64) as RowOffset
+    }
+
+    /// Block of the schema with `rows()` random rows
+    pub fn block<'b>(&mut self, alloc: &'b Allocator, schema: &Schema)
+        -> Result<Block<'b>, DBError>
+    {
+        let rows = self.rows();
+        self.block_rows(alloc, schema, rows)
+    }
+
+    pub fn block_rows<'b>(&mut self, alloc: &'b Allocator, schema: &Schema, rows: RowOffset)
+        -> Result<Block<'b>, DBError>
+    {
+        let mut block = Block::new(alloc, schema);
+        let mut row = Vec::with_capacity(schema.count());
+
+        for _ in 0 .. rows {
+            row.clear();
+            for attr in schema.iter() {
+                row.push(self.datum(attr.nullable, &attr.dtype));
+            }
+
+            let values: Vec<Value> = row.iter().map(|d| d.value()).collect();
+            block.append_row(&values)?;
+        }
+
+        Ok(block)
+    }
+
+    /// Index of a value of the `Bounded` or `Skewed` distribution
+    fn index(&mut self) -> Option<u64> {
+        match self.config.distribution {
+            Distribution::Bounded(n) => Some(self.rng.below(n.max(1))),
+            Distribution::Skewed(n) => {
+                let n = n.max(1);
+                let k = ((n as f64 + 1.0).powf(self.rng.next_f64()) - 1.0) as u64;
+                Some(k.min(n - 1))
+            },
+            _ => None,
+        }
+    }
+
+    fn datum(&mut self, nullable: bool, dtype: &Type) -> Datum {
+        if nullable && self.rng.next_f64() < self.config.null_density {
+            return Datum::Scalar(Value::NULL)
+        }
+
+        match *dtype {
+            Type::LIST(ref elem) => {
+                let len = self.range(0, self.config.max_list_len as u64);
+                Datum::List((0 .. len).map(|_| self.datum(true, elem)).collect())
+            },
+            Type::STRUCT(ref fields) => Datum::Struct(
+                fields.iter().map(|f| self.datum(f.nullable, &f.dtype)).collect()),
+            _ => match self.index() {
+                Some(k) => self.indexed(dtype, k),
+                None if self.config.distribution == Distribution::Edge => self.edge(dtype),
+                None => self.uniform(dtype),
+            },
+        }
+    }
+
+    /// Value `k` of a `Bounded` / `Skewed` distribution
+    fn indexed(&mut self, dtype: &Type, k: u64) -> Datum {
+        let mut uuid = [0u8; 16];
+        uuid[8 ..].copy_from_slice(&k.to_be_bytes());
+
+        Datum::Scalar(match *dtype {
+            Type::UINT32 => Value::UINT32(k as u32),
+            Type::UINT64 => Value::UINT64(k),
+            Type::INT32 => Value::INT32(k as i32),
+            Type::INT64 => Value::INT64(k as i64),
+            Type::FLOAT32 => Value::FLOAT32(k as f32),
+            Type::FLOAT64 => Value::FLOAT64(k as f64),
+            Type::BOOLEAN => Value::BOOLEAN(k % 2 == 1),
+            Type::TIMESTAMP => Value::TIMESTAMP(k as i64),
+            Type::INTERVAL =>
+                Value::INTERVAL(IntervalValue { months: 0, days: k as i32, micros: 0 }),
+            Type::UUID => Value::UUID(uuid),
+            Type::TEXT => return Datum::Text(format!("v{}", k)),
+            _ => return Datum::Blob(k.to_be_bytes().to_vec()),
+        })
+    }
+
+    fn uniform(&mut self, dtype: &Type) -> Datum {
+        let year = |y: i64| days_from_civil(y, 1, 1) * MICROS_PER_DAY;
+
+        Datum::Scalar(match *dtype {
+            Type::UINT32 => Value::UINT32(self.rng.next_u64() as u32),
+            Type::UINT64 => Value::UINT64(self.rng.next_u64()),
+            Type::INT32 => Value::INT32(self.rng.next_u64() as i32),
+            Type::INT64 => Value::INT64(self.rng.next_u64() as i64),
+            Type::FLOAT32 => loop {
+                let v = f32::from_bits(self.rng.next_u64() as u32);
+                if v.is_finite() {
+                    break Value::FLOAT32(v)
+                }
+            },
+            Type::FLOAT64 => loop {
+                let v = f64::from_bits(self.rng.next_u64());
+                if v.is_finite() {
+                    break Value::FLOAT64(v)
+                }
+            },
+            Type::BOOLEAN => Value::BOOLEAN(self.rng.below(2) == 1),
+            Type::TIMESTAMP => {
+                let (lo, hi) = (year(1), year(10000) - 1);
+                Value::TIMESTAMP(lo + self.range(0, (hi - lo) as u64) as i64)
+            },
+            Type::INTERVAL => {
+                let months = self.range(0, 240_000) as i32 - 120_000;
+                let days = self.range(0, 7_300_000) as i32 - 3_650_000;
+                let micros = self.range(0, 2 * 365 * MICROS_PER_DAY as u64) as i64 -
+                    365 * MICROS_PER_DAY;
+                Value::INTERVAL(IntervalValue { months: months, days: days, micros: micros })
+            },
+            Type::UUID => {
+                let mut uuid = [0u8; 16];
+                uuid[.. 8].copy_from_slice(&self.rng.next_u64().to_le_bytes());
+                uuid[8 ..].copy_from_slice(&self.rng.next_u64().to_le_bytes());
+                Value::UUID(uuid)
+            },
+            Type::TEXT => {
+                let mut text = String::new();
+                let len = self.range(0, self.config.max_len as u64) as usize;
+                loop {
+                    let c = *self.pick(TEXT_CHARS);
+                    if text.len() + c.len_utf8() > len {
+                        break
+                    }
+                    text.push(c);
+                }
+                return Datum::Text(text)
+            },
+            _ => {
+                let len = self.range(0, self.config.max_len as u64);
+                return Datum::Blob((0 .. len).map(|_| self.rng.next_u64() as u8).collect())
+            },
+        })
+    }
+
+    fn edge(&mut self, dtype: &Type) -> Datum {
+        use std::{f32, f64, i32, i64, u32, u64};
+
+        Datum::Scalar(match *dtype {
+            Type::UINT32 => Value::UINT32(*self.pick(&[0, 1, u32::MAX])),
+            Type::UINT64 => Value::UINT64(*self.pick(&[0, 1, u64::MAX])),
+            Type::INT32 => Value::INT32(*self.pick(&[0, -1, i32::MIN, i32::MAX])),
+            Type::INT64 => Value::INT64(*self.pick(&[0, -1, i64::MIN, i64::MAX])),
+            Type::FLOAT32 => Value::FLOAT32(*self.pick(&[0.0, -0.0, f32::NAN, f32::INFINITY,
+                                                          f32::NEG_INFINITY, f32::MIN_POSITIVE,
+                                                          f32::MAX])),
+            Type::FLOAT64 => Value::FLOAT64(*self.pick(&[0.0, -0.0, f64::NAN, f64::INFINITY,
+                                                          f64::NEG_INFINITY, f64::MIN_POSITIVE,
+                                                          f64::MAX])),
+            Type::BOOLEAN => Value::BOOLEAN(self.rng.below(2) == 1),
+            Type::TIMESTAMP => Value::TIMESTAMP(*self.pick(&[0, -1, i64::MIN, i64::MAX])),
+            Type::INTERVAL => {
+                let (months, days, micros) =
+                    *self.pick(&[(0, 0, 0), (1, -30, 0), (0, 0, -1), (i32::MIN, i32::MAX, 0)]);
+                Value::INTERVAL(IntervalValue { months: months, days: days, micros: micros })
+            },
+            Type::UUID => Value::UUID([*self.pick(&[0u8, 0xff]); 16]),
+            Type::TEXT => {
+                let text = *self.pick(&["", " ", "\0", "é", "🙂", "\"quoted\", \n"]);
+                return Datum::Text(text.to_string())
+            },
+            _ => {
+                let blob: &[u8] = self.pick(&[&b""[..], b"\0", b"\xff\xfe", b"PGCOPY\n\xff"]);
+                return Datum::Blob(blob.to_vec())
+            },
+        })
+    }
+}
+
+/// First of `cases` random blocks (of random schemas) that `convert` doesn't preserve: a
+/// description of the case (its seed, to be reproduced with a `Generator` of the seed) and the
+/// difference, or the conversion error. Floats are compared exactly, NaNs being equal.
+pub fn round_trip_diff<F>(seed: u64, cases: usize, config: &GenConfig, mut convert: F)
+    -> Option<String>
+    where F: FnMut(&Block<'static>) -> Result<Block<'static>, DBError>
+{
+    for case in 0 .. cases as u64 {
+        let case_seed = seed.wrapping_add(case);
+        let mut gen = Generator::with_config(case_seed, config.clone());
+        let schema = gen.schema();
+
+        let block = match gen.block(&allocator::GLOBAL, &schema) {
+            Ok(block) => block,
+            Err(e) => return Some(format!("case seed {}: generating block: {}", case_seed, e)),
+        };
+
+        let diff = convert(&block).and_then(|out| {
+            views_diff(&block, &out, Tolerance::Exact).map(|d| d.map(|d| d.to_string()))
+        });
+        match diff {
+            Ok(None) => (),
+            Ok(Some(diff)) => return Some(format!("case seed {}: {}", case_seed, diff)),
+            Err(e) => return Some(format!("case seed {}: {}", case_seed, e)),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::block::View;
+    use ::decode::{PgCopyDecoder, PgCopyWriter};
+    use ::ipc::{IpcStreamReader, IpcStreamWriter};
+
+    // The same seed gives the same rows, NULLs about as dense as configured
+    #[test]
+    fn seeded_blocks() {
+        let config = GenConfig { rows: (200, 300), null_density: 0.5, ..GenConfig::default() };
+        let block = |seed| {
+            let mut gen = Generator::with_config(seed, config.clone());
+            let schema = gen.schema();
+            gen.block(&allocator::GLOBAL, &schema).unwrap()
+        };
+
+        let (a, b) = (block(7), block(7));
+        assert!(a.rows() >= 200 && a.rows() <= 300);
+        assert!(views_diff(&a, &b, Tolerance::Exact).unwrap().is_none());
+        assert!(views_diff(&a, &block(8), Tolerance::Exact).unwrap().is_some());
+
+        let schema = Schema::make_one_attr("v", true, Type::TEXT);
+        let mut gen = Generator::with_config(1, config.clone());
+        let block = gen.block_rows(&allocator::GLOBAL, &schema, 1000).unwrap();
+        let nulls = (0 .. 1000).filter(|r| block.value(*r, 0).unwrap() == Value::NULL).count();
+        assert!(nulls > 400 && nulls < 600, "{} NULLs", nulls);
+
+        let fields = vec![Attribute { name: "x".to_string(), nullable: true, dtype: Type::TEXT }];
+        let schema = Schema::from_vec(vec![
+            Attribute { name: "l".to_string(), nullable: false,
+                        dtype: Type::LIST(Box::new(Type::INT32)) },
+            Attribute { name: "s".to_string(), nullable: true, dtype: Type::STRUCT(fields) },
+        ]).unwrap();
+        assert_eq!(gen.block_rows(&allocator::GLOBAL, &schema, 10).unwrap().rows(), 10);
+
+        let config = GenConfig { distribution: Distribution::Bounded(3), ..config };
+        let schema = Schema::make_one_attr("v", false, Type::INT64);
+        let block = Generator::with_config(1, config).block(&allocator::GLOBAL, &schema).unwrap();
+        assert!((0 .. block.rows()).all(|r| match block.value(r, 0).unwrap() {
+            Value::INT64(v) => v >= 0 && v < 3,
+            _ => false,
+        }));
+    }
+
+    // Random blocks survive the Arrow IPC and PostgreSQL COPY round trips
+    #[test]
+    fn format_round_trips() {
+        for distribution in &[Distribution::Uniform, Distribution::Skewed(10)] {
+            let config = GenConfig { distribution: *distribution, ..GenConfig::default() };
+            let diff = round_trip_diff(1, 20, &config, |block| {
+                let mut writer = IpcStreamWriter::new(Vec::new(), block.schema())?;
+                writer.write_view(block)?;
+                let out = writer.finish()?;
+
+                let mut reader = IpcStreamReader::new(&out[..])?;
+                Ok(reader.next_block(&allocator::GLOBAL)?.unwrap())
+            });
+            assert_eq!(diff, None);
+
+            // PostgreSQL has no unsigned bigint
+            let mut config = config.clone();
+            config.types.retain(|t| *t != Type::UINT64);
+            let diff = round_trip_diff(1, 20, &config, |block| {
+                let mut writer = PgCopyWriter::new(Vec::new(), block.schema())?;
+                writer.write_view(block)?;
+                let out = writer.finish()?;
+                PgCopyDecoder::new().decode(&allocator::GLOBAL, block.schema(), &out)
+            });
+            assert_eq!(diff, None);
+        }
+
+        // Edge values find what the round trip doesn't preserve
+        let config = GenConfig { distribution: Distribution::Edge, types: vec![Type::TIMESTAMP],
+                                 ..GenConfig::default() };
+        let diff = round_trip_diff(1, 20, &config, |block| {
+            let mut writer = PgCopyWriter::new(Vec::new(), block.schema())?;
+            writer.write_view(block)?;
+            PgCopyDecoder::new().decode(&allocator::GLOBAL, block.schema(), &writer.finish()?)
+        });
+        assert!(diff.unwrap().contains("case seed"));
+    }
+}
@@ -1,21 +1,64 @@
 
-use std::convert::{AsRef, From};
+use std::convert::From;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::mem;
-use std::slice;
 use std::str;
 
 use super::error::DBError;
+use super::schema::Attribute;
+
+/// Assumed average bytes of a TEXT or BLOB value, for `Type::estimated_width`
+const VARLEN_WIDTH_ESTIMATE: usize = 24;
+/// Assumed average number of items of a LIST value, for `Type::estimated_width`
+const LIST_ITEMS_ESTIMATE: usize = 4;
+
+/// "Native" type storing `Column` data for VARLEN columns.
+///
+/// A handle of the value's bytes in the column `Heap`: the chunk, and offset and size in the
+/// chunk. Handles don't point into memory, so row data can be copied (or written out) as is and
+/// columns moved between threads, but they only mean something for the column heap they're in.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawData {
+    pub chunk: u32,
+    pub offset: u32,
+    pub size: u32,
+}
 
-/// "Native" type storing `Column` data for VARLEN columns
+/// Data referenced by the VARLEN rows of a column
 #[derive(Clone, Copy)]
-pub struct RawData {
-    // This cannot me a &[u8] slice because slices cannot be have a nullptr
-    pub data: *mut u8,
-    pub size: usize,
+pub enum Heap<'a> {
+    /// Column without VARLEN rows
+    Empty,
+    /// Chunks of the column arena
+    Chunks(&'a [&'a mut [u8]]),
+    /// A single chunk, eg. the heap of a memory mapped table file
+    Contiguous(&'a [u8]),
+}
+
+/// "Native" type storing `Column` data for INTERVAL columns.
+///
+/// Months and days are kept separate from the sub-day time since their length (in microseconds)
+/// depends on date the interval is applied to.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct IntervalValue {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+/// "Native" type storing `Column` data for LIST columns.
+///
+/// The elements of the list are stored in the child element column of the LIST column, the row
+/// stores the range of child column rows that belong to the list.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ListData {
+    pub offset: usize,
+    pub len: usize,
 }
 
 /// "Symbolic" Type of a `Column` `Attribute`
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum Type {
     UINT32,
     UINT64,
@@ -24,8 +67,18 @@ pub enum Type {
     FLOAT32,
     FLOAT64,
     BOOLEAN,
+    /// Microseconds since Unix epoch (UTC)
+    TIMESTAMP,
+    INTERVAL,
+    /// 16 byte UUID, ordered by its (big endian) byte representation
+    UUID,
     TEXT,
     BLOB,
+    /// List of values of the element type. Elements are always nullable.
+    LIST(Box<Type>),
+    /// Record of named fields. Each field is stored in a child column with the same rows as the
+    /// STRUCT column.
+    STRUCT(Vec<Attribute>),
 }
 
 /// Trait providing higher level metadata about types
@@ -56,6 +109,9 @@ pub struct Int64;
 pub struct Float32;
 pub struct Float64;
 pub struct Boolean;
+pub struct Timestamp;
+pub struct Interval;
+pub struct Uuid;
 pub struct Text;
 pub struct Blob;
 
@@ -94,6 +150,21 @@ impl ValueInfo for Boolean {
     const ENUM: Type = Type::BOOLEAN;
 }
 
+impl ValueInfo for Timestamp {
+    type Store = i64;
+    const ENUM: Type = Type::TIMESTAMP;
+}
+
+impl ValueInfo for Interval {
+    type Store = IntervalValue;
+    const ENUM: Type = Type::INTERVAL;
+}
+
+impl ValueInfo for Uuid {
+    type Store = [u8; 16];
+    const ENUM: Type = Type::UUID;
+}
+
 impl ValueInfo for Text {
     type Store = RawData;
     const ENUM: Type = Type::TEXT;
@@ -107,46 +178,160 @@ impl ValueInfo for Blob {
     const VARLEN: bool = true;
 }
 
-static UINT32: UInt32 = UInt32{};
-static UINT64: UInt64 = UInt64{};
-static INT32: Int32 = Int32{};
-static INT64: Int64 = Int64{};
-static FLOAT32: Float32 = Float32{};
-static FLOAT64: Float64 = Float64{};
-static BOOLEAN: Boolean = Boolean{};
-static TEXT: Text = Text{};
-static BLOB: Blob = Blob{};
+/// Evaluate code generic over the `ValueInfo` type of a `Type`, the one place listing which
+/// marker type belongs to which `Type`.
+///
+/// `dispatch_type!(dtype, class, T => expr, _ => other)` evaluates `expr` with `T` aliasing the
+/// marker type if `dtype` is in the type class, and `other` for the remaining types. Classes:
+///
+/// - `integer`: UINT32, UINT64, INT32, INT64
+/// - `numeric`: the integers, FLOAT32, FLOAT64
+/// - `fixed`: the numeric types, BOOLEAN, TIMESTAMP, INTERVAL, UUID (a `Copy` store)
+/// - `varlen`: TEXT, BLOB (a `RawData` store referencing the column heap)
+/// - `scalar`: the fixed and varlen types, everything but LIST and STRUCT
+macro_rules! dispatch_type {
+    (@arms $dtype:expr, $t:ident, $body:expr, $other:expr,
+     [$($variant:ident : $marker:ident),*]) => {
+        match $dtype {
+            $(::types::Type::$variant => {
+                #[allow(dead_code)]
+                type $t = ::types::$marker;
+                $body
+            },)*
+            _ => $other,
+        }
+    };
+    ($dtype:expr, integer, $t:ident => $body:expr, _ => $other:expr) => {
+        dispatch_type!(@arms $dtype, $t, $body, $other,
+                       [UINT32: UInt32, UINT64: UInt64, INT32: Int32, INT64: Int64])
+    };
+    ($dtype:expr, numeric, $t:ident => $body:expr, _ => $other:expr) => {
+        dispatch_type!(@arms $dtype, $t, $body, $other,
+                       [UINT32: UInt32, UINT64: UInt64, INT32: Int32, INT64: Int64,
+                        FLOAT32: Float32, FLOAT64: Float64])
+    };
+    ($dtype:expr, fixed, $t:ident => $body:expr, _ => $other:expr) => {
+        dispatch_type!(@arms $dtype, $t, $body, $other,
+                       [UINT32: UInt32, UINT64: UInt64, INT32: Int32, INT64: Int64,
+                        FLOAT32: Float32, FLOAT64: Float64, BOOLEAN: Boolean,
+                        TIMESTAMP: Timestamp, INTERVAL: Interval, UUID: Uuid])
+    };
+    ($dtype:expr, varlen, $t:ident => $body:expr, _ => $other:expr) => {
+        dispatch_type!(@arms $dtype, $t, $body, $other, [TEXT: Text, BLOB: Blob])
+    };
+    ($dtype:expr, scalar, $t:ident => $body:expr, _ => $other:expr) => {
+        dispatch_type!(@arms $dtype, $t, $body, $other,
+                       [UINT32: UInt32, UINT64: UInt64, INT32: Int32, INT64: Int64,
+                        FLOAT32: Float32, FLOAT64: Float64, BOOLEAN: Boolean,
+                        TIMESTAMP: Timestamp, INTERVAL: Interval, UUID: Uuid,
+                        TEXT: Text, BLOB: Blob])
+    };
+}
 
 impl Type {
-    pub fn name(self) -> &'static str {
-        match self {
-            Type::UINT32  => "UINT32",
-            Type::UINT64  => "UINT64",
-            Type::INT32   => "INT32",
-            Type::INT64   => "INT64",
-            Type::FLOAT32 => "FLOAT32",
-            Type::FLOAT64 => "FLOAT64",
-            Type::BOOLEAN => "BOOLEAN",
-            Type::TEXT    => "TEXT",
-            Type::BLOB    => "BLOB",
+    /// Name of the type without its type parameters (eg. element type of a LIST)
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Type::UINT32    => "UINT32",
+            Type::UINT64    => "UINT64",
+            Type::INT32     => "INT32",
+            Type::INT64     => "INT64",
+            Type::FLOAT32   => "FLOAT32",
+            Type::FLOAT64   => "FLOAT64",
+            Type::BOOLEAN   => "BOOLEAN",
+            Type::TIMESTAMP => "TIMESTAMP",
+            Type::INTERVAL  => "INTERVAL",
+            Type::UUID      => "UUID",
+            Type::TEXT      => "TEXT",
+            Type::BLOB      => "BLOB",
+            Type::LIST(_)   => "LIST",
+            Type::STRUCT(_) => "STRUCT",
         }
     }
 
-    // RUST is frustrating
-    // There's no implementation specialization,
-    // and can't use a associated trait type (defaulted or not) in an expression.
-    // So we have to keep repeating ourselves
-    pub fn size_of(self) -> usize {
-        match self {
-            Type::UINT32    => UINT32.size_of(),
-            Type::UINT64    => UINT64.size_of(),
-            Type::INT32     => INT32.size_of(),
-            Type::INT64     => INT64.size_of(),
-            Type::FLOAT32   => FLOAT32.size_of(),
-            Type::FLOAT64   => FLOAT64.size_of(),
-            Type::BOOLEAN   => BOOLEAN.size_of(),
-            Type::TEXT      => TEXT.size_of(),
-            Type::BLOB      => BLOB.size_of(),
+    pub fn size_of(&self) -> usize {
+        dispatch_type!(*self, scalar, T => mem::size_of::<<T as ValueInfo>::Store>(), _ => {
+            match *self {
+                Type::LIST(_)   => mem::size_of::<ListData>(),
+                // Field values are in the child columns. The STRUCT column rows are a placeholder
+                // byte so the column capacity can be derived from the row data like for any other
+                // type.
+                _               => mem::size_of::<u8>(),
+            }
+        })
+    }
+
+    /// Estimated bytes of a row of the type, including the data of VARLEN values and nested
+    /// columns. Used for sizing blocks, see `Schema::batch_rows()`.
+    pub fn estimated_width(&self) -> usize {
+        match *self {
+            Type::TEXT | Type::BLOB     => self.size_of() + VARLEN_WIDTH_ESTIMATE,
+            Type::LIST(ref item)        =>
+                self.size_of() + LIST_ITEMS_ESTIMATE * item.estimated_width(),
+            Type::STRUCT(ref fields)    => fields.iter()
+                .fold(self.size_of(), |w, f| w + f.dtype.estimated_width() + f.nullable as usize),
+            _                           => self.size_of(),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            Type::UINT32 | Type::UINT64 | Type::INT32 | Type::INT64 |
+            Type::FLOAT32 | Type::FLOAT64 => true,
+            _ => false,
+        }
+    }
+
+    /// Narrowest type both `a` and `b` can be implicitly (without loss of range) converted to.
+    ///
+    /// Mixed signed / unsigned integers promote to a signed type wide enough for both, integers
+    /// mixed with floats promote to FLOAT64. UINT64 has no common signed integer supertype. Non
+    /// numeric types are only compatible with themselves.
+    pub fn common_supertype(a: &Type, b: &Type) -> Option<Type> {
+        if a == b {
+            return Some(a.clone())
+        }
+
+        match (a, b) {
+            (&Type::UINT32, &Type::UINT64) | (&Type::UINT64, &Type::UINT32) =>
+                Some(Type::UINT64),
+            (&Type::INT32, &Type::INT64) | (&Type::INT64, &Type::INT32) |
+            (&Type::UINT32, &Type::INT32) | (&Type::INT32, &Type::UINT32) |
+            (&Type::UINT32, &Type::INT64) | (&Type::INT64, &Type::UINT32) =>
+                Some(Type::INT64),
+            (&Type::UINT64, &Type::INT32) | (&Type::INT32, &Type::UINT64) |
+            (&Type::UINT64, &Type::INT64) | (&Type::INT64, &Type::UINT64) =>
+                None,
+            (x, y) if x.is_numeric() && y.is_numeric() =>
+                Some(Type::FLOAT64),
+            _ =>
+                None,
+        }
+    }
+
+    /// True if values of type `from` can be explicitly cast (`CastExpr`) to the `to` type.
+    ///
+    /// Numeric types cast to each other (narrowing casts can overflow) and to and from BOOLEAN.
+    /// TIMESTAMP casts to and from INT64 microseconds. All the types except BLOB, LIST and STRUCT
+    /// format as TEXT, TEXT parses as the numeric types, BOOLEAN, INTERVAL and UUID.
+    pub fn can_cast(from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (x, y) if x == y =>
+                true,
+            (x, y) if x.is_numeric() && y.is_numeric() =>
+                true,
+            (&Type::BOOLEAN, x) | (x, &Type::BOOLEAN) if x.is_numeric() =>
+                true,
+            (&Type::TIMESTAMP, &Type::INT64) | (&Type::INT64, &Type::TIMESTAMP) =>
+                true,
+            (&Type::BLOB, _) | (&Type::LIST(_), _) | (&Type::STRUCT(_), _) =>
+                false,
+            (_, &Type::TEXT) =>
+                true,
+            (&Type::TEXT, x) =>
+                x.is_numeric() || *x == Type::BOOLEAN || *x == Type::INTERVAL || *x == Type::UUID,
+            _ =>
+                false,
         }
     }
 }
@@ -155,39 +340,153 @@ impl str::FromStr for Type {
     type Err = DBError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "UINT32"  => Ok(Type::UINT32),
-            "UINT64"  => Ok(Type::UINT64),
-            "INT32"   => Ok(Type::INT32),
-            "INT64"   => Ok(Type::INT64),
-            "FLOAT32" => Ok(Type::FLOAT32),
-            "FLOAT64" => Ok(Type::FLOAT64),
-            "BOOLEAN" => Ok(Type::BOOLEAN),
-            "TEXT"    => Ok(Type::TEXT),
-            "BLOB"    => Ok(Type::BLOB),
-            _         => Err(DBError::UnknownType(String::from(s)))
+            "UINT32"    => Ok(Type::UINT32),
+            "UINT64"    => Ok(Type::UINT64),
+            "INT32"     => Ok(Type::INT32),
+            "INT64"     => Ok(Type::INT64),
+            "FLOAT32"   => Ok(Type::FLOAT32),
+            "FLOAT64"   => Ok(Type::FLOAT64),
+            "BOOLEAN"   => Ok(Type::BOOLEAN),
+            "TIMESTAMP" => Ok(Type::TIMESTAMP),
+            "INTERVAL"  => Ok(Type::INTERVAL),
+            "UUID"      => Ok(Type::UUID),
+            "TEXT"      => Ok(Type::TEXT),
+            "BLOB"      => Ok(Type::BLOB),
+            _ if s.starts_with("LIST<") && s.ends_with(">") =>
+                s[5 .. s.len() - 1].parse::<Type>()
+                    .map(|item| Type::LIST(Box::new(item)))
+                    .map_err(|_| DBError::UnknownType(String::from(s))),
+            _ if s.starts_with("STRUCT<") && s.ends_with(">") =>
+                parse_struct_fields(&s[7 .. s.len() - 1])
+                    .map(Type::STRUCT)
+                    .map_err(|_| DBError::UnknownType(String::from(s))),
+            _           => Err(DBError::UnknownType(String::from(s)))
         }
     }
 }
 
-impl AsRef<[u8]> for RawData {
-    fn as_ref(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self.data, self.size) }
+/// Parse "name TYPE[ NULL], ..." STRUCT fields, splitting only on top level commas
+fn parse_struct_fields(s: &str) -> Result<Vec<Attribute>, DBError> {
+    let mut fields = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (pos, c) in s.char_indices().chain(Some((s.len(), ','))) {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                let field = s[start .. pos].trim();
+                let split = field.find(' ')
+                    .ok_or(DBError::UnknownType(String::from(field)))?;
+
+                let (name, dtype) = (&field[.. split], field[split ..].trim());
+                let (dtype, nullable) = if dtype.ends_with(" NULL") {
+                    (dtype[.. dtype.len() - 5].trim(), true)
+                } else {
+                    (dtype, false)
+                };
+
+                fields.push(Attribute {
+                    name: String::from(name),
+                    nullable: nullable,
+                    dtype: dtype.parse::<Type>()?,
+                });
+
+                start = pos + 1;
+            },
+            _ => (),
+        }
     }
+
+    Ok(fields)
 }
 
-impl AsRef<str> for RawData {
-    fn as_ref(&self) -> &str {
-        unsafe {
-            let slice = slice::from_raw_parts(self.data, self.size);
-            str::from_utf8_unchecked(slice)
+/// Full type name, including type parameters, eg. "LIST<INT32>" or "STRUCT<x INT32, y TEXT NULL>"
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::LIST(ref item) => write!(f, "LIST<{}>", item),
+            Type::STRUCT(ref fields) => {
+                f.write_str("STRUCT<")?;
+                for (pos, field) in fields.iter().enumerate() {
+                    let sep = if pos > 0 { ", " } else { "" };
+                    let null = if field.nullable { " NULL" } else { "" };
+                    write!(f, "{}{} {}{}", sep, field.name, field.dtype, null)?;
+                }
+                f.write_str(">")
+            },
+            _                    => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Types serialize as their full type name, which is stable across versions
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Type {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Type {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Type, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse::<Type>().map_err(::serde::de::Error::custom)
+    }
+}
+
+impl<'a> Heap<'a> {
+    /// Bytes of the handle. Panics if the handle is not of this heap.
+    pub fn bytes(&self, data: &RawData) -> &'a [u8] {
+        if data.size == 0 {
+            return &[]
+        }
+
+        let (start, end) = (data.offset as usize, data.offset as usize + data.size as usize);
+        match *self {
+            Heap::Empty             => panic!("VARLEN handle without a heap"),
+            Heap::Chunks(chunks)    => &chunks[data.chunk as usize][start .. end],
+            Heap::Contiguous(bytes) => &bytes[start .. end],
         }
     }
 }
 
-impl ToString for RawData {
-    fn to_string(&self) -> String {
-        let str: &str = self.as_ref();
-        String::from(str)
+/// Equality of stored values. VARLEN handles are equal if their referenced data is.
+pub trait StoreEq {
+    fn store_eq(&self, heap: &Heap, other: &Self, other_heap: &Heap) -> bool;
+}
+
+macro_rules! store_eq {
+    ($($native:ty),*) => {
+        $(impl StoreEq for $native {
+            fn store_eq(&self, _heap: &Heap, other: &$native, _other_heap: &Heap) -> bool {
+                self == other
+            }
+        })*
+    }
+}
+
+store_eq!(u32, u64, i32, i64, f32, f64, bool, IntervalValue, [u8; 16]);
+
+impl StoreEq for RawData {
+    fn store_eq(&self, heap: &Heap, other: &RawData, other_heap: &Heap) -> bool {
+        heap.bytes(self) == other_heap.bytes(other)
+    }
+}
+
+/// Parse humane interval strings such as "1 day", "-2 hours 30 minutes" or "1 year 02:00:00"
+impl str::FromStr for IntervalValue {
+    type Err = DBError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ::util::temporal::parse_interval(s)
+    }
+}
+
+impl fmt::Display for IntervalValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        ::util::temporal::format_interval(self, f)
     }
 }
 
@@ -196,6 +495,7 @@ pub struct NullType { }
 pub const NULL_VALUE: NullType = NullType {};
 
 /// Container storing any kind of value
+#[derive(PartialEq)]
 pub enum Value<'a> {
     NULL,
     UINT32(u32),
@@ -205,8 +505,83 @@ pub enum Value<'a> {
     FLOAT32(f32),
     FLOAT64(f64),
     BOOLEAN(bool),
+    TIMESTAMP(i64),
+    INTERVAL(IntervalValue),
+    UUID([u8; 16]),
     TEXT(&'a str),
     BLOB(&'a [u8]),
+    LIST(Vec<Value<'a>>),
+    /// Field values in STRUCT field order
+    STRUCT(Vec<Value<'a>>),
+}
+
+/// Floats are normalized, so -0.0 and 0.0 hash the same as do all NaNs
+impl<'a> Hash for Value<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        fn f64_bits(v: f64) -> u64 {
+            if v == 0.0 { 0 } else if v.is_nan() { ::std::f64::NAN.to_bits() } else { v.to_bits() }
+        }
+
+        match *self {
+            Value::NULL             => state.write_u8(0),
+            Value::UINT32(v)        => { state.write_u8(1); state.write_u32(v) },
+            Value::UINT64(v)        => { state.write_u8(2); state.write_u64(v) },
+            Value::INT32(v)         => { state.write_u8(3); state.write_i32(v) },
+            Value::INT64(v)         => { state.write_u8(4); state.write_i64(v) },
+            Value::FLOAT32(v)       => { state.write_u8(5); state.write_u64(f64_bits(v as f64)) },
+            Value::FLOAT64(v)       => { state.write_u8(6); state.write_u64(f64_bits(v)) },
+            Value::BOOLEAN(v)       => { state.write_u8(7); state.write_u8(v as u8) },
+            Value::TIMESTAMP(v)     => { state.write_u8(8); state.write_i64(v) },
+            Value::INTERVAL(v)      => {
+                state.write_u8(9);
+                state.write_i32(v.months);
+                state.write_i32(v.days);
+                state.write_i64(v.micros);
+            },
+            Value::UUID(ref v)      => { state.write_u8(10); state.write(v) },
+            Value::TEXT(v)          => {
+                state.write_u8(11);
+                state.write_usize(v.len());
+                state.write(v.as_bytes());
+            },
+            Value::BLOB(v)          => {
+                state.write_u8(12);
+                state.write_usize(v.len());
+                state.write(v);
+            },
+            Value::LIST(ref items)  => {
+                state.write_u8(13);
+                state.write_usize(items.len());
+                for item in items {
+                    item.hash(state);
+                }
+            },
+            Value::STRUCT(ref fields) => {
+                state.write_u8(14);
+                for field in fields {
+                    field.hash(state);
+                }
+            },
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Elements of a LIST value, `None` for other values (including NULL)
+    pub fn as_list(&self) -> Option<&[Value<'a>]> {
+        match *self {
+            Value::LIST(ref items) => Some(items.as_slice()),
+            _                      => None,
+        }
+    }
+
+    /// Field values of a STRUCT value, `None` for other values (including NULL)
+    pub fn as_struct(&self) -> Option<&[Value<'a>]> {
+        match *self {
+            Value::STRUCT(ref fields) => Some(fields.as_slice()),
+            _                         => None,
+        }
+    }
 }
 
 impl<'a> From<NullType> for Value<'a> {
@@ -251,6 +626,18 @@ impl<'a> From<f64> for Value<'a> {
     }
 }
 
+impl<'a> From<IntervalValue> for Value<'a> {
+    fn from(v: IntervalValue) -> Self {
+        Value::INTERVAL(v)
+    }
+}
+
+impl<'a> From<[u8; 16]> for Value<'a> {
+    fn from(v: [u8; 16]) -> Self {
+        Value::UUID(v)
+    }
+}
+
 impl<'a> From<&'a str> for Value<'a> {
     fn from(v: &'a str) -> Self {
         Value::TEXT(v)
@@ -262,3 +649,22 @@ impl<'a> From<&'a [u8]> for Value<'a> {
         Value::BLOB(v)
     }
 }
+
+impl<'a> From<bool> for Value<'a> {
+    fn from(v: bool) -> Self {
+        Value::BOOLEAN(v)
+    }
+}
+
+/// `None` is NULL
+impl<'a, T: Into<Value<'a>>> From<Option<T>> for Value<'a> {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::NULL, |v| v.into())
+    }
+}
+
+impl<'a> From<Vec<Value<'a>>> for Value<'a> {
+    fn from(v: Vec<Value<'a>>) -> Self {
+        Value::LIST(v)
+    }
+}
@@ -0,0 +1,332 @@
+// vim: set ts=4 sw=4 et :
+
+//! Delta plus bit-packing encoding of integer column values.
+//!
+//! The first value is stored as is, followed by the zigzag encoded differences between consecutive
+//! values. Differences are bit-packed in frames of `FRAME_SIZE` values, each frame using the bit
+//! width of its largest difference. Sorted columns (eg. IDs) end up using a few bits per value.
+//!
+//! Layout: `[count: u32][first: u64]` then for each frame `[width: u8][packed differences]`,
+//! all little endian.
+
+use ::block::{Column, RefColumn, column_row_data};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::types::ValueInfo;
+
+/// Number of differences sharing a bit width
+pub const FRAME_SIZE: usize = 128;
+
+/// Integer column value types that can be bit-packed
+pub trait PackedInt: Copy + Default {
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! packed_int {
+    ($t:ty) => {
+        impl PackedInt for $t {
+            fn to_bits(self) -> u64 { self as u64 }
+            fn from_bits(bits: u64) -> Self { bits as $t }
+        }
+    }
+}
+
+packed_int!(u32);
+packed_int!(u64);
+packed_int!(i32);
+packed_int!(i64);
+
+fn zigzag(v: u64) -> u64 {
+    let v = v as i64;
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> u64 {
+    (v >> 1) ^ (!(v & 1)).wrapping_add(1)
+}
+
+fn bit_width(v: u64) -> u32 {
+    64 - v.leading_zeros()
+}
+
+fn mask(width: u32) -> u64 {
+    if width >= 64 { !0 } else { (1 << width) - 1 }
+}
+
+fn truncated() -> DBError {
+    DBError::ValueParse(String::from("truncated bit-packed integers"))
+}
+
+/// Little endian word from (up to) the first 8 bytes, missing bytes are zero
+fn read_word(data: &[u8]) -> u64 {
+    data.iter().take(8).enumerate()
+        .fold(0, |word, (idx, byte)| word | (*byte as u64) << (idx * 8))
+}
+
+fn write_word(out: &mut Vec<u8>, word: u64, bytes: usize) {
+    for idx in 0 .. bytes {
+        out.push((word >> (idx * 8)) as u8);
+    }
+}
+
+/// Append the `width` wide values, packed least significant bit first
+fn pack_frame(values: &[u64], width: u32, out: &mut Vec<u8>) {
+    let mut acc = 0u64;
+    let mut bits = 0u32;
+
+    for v in values {
+        let space = 64 - bits;
+        acc |= v << bits;
+
+        if width < space {
+            bits += width;
+        } else {
+            write_word(out, acc, 8);
+            acc = if space == 64 { 0 } else { v >> space };
+            bits = width - space;
+        }
+    }
+
+    write_word(out, acc, ((bits + 7) / 8) as usize);
+}
+
+fn unpack_frame(data: &[u8], width: u32, out: &mut [u64]) {
+    let mask = mask(width);
+    let mut pos = 0;
+    let mut word = read_word(data);
+    let mut bits = 0u32;
+
+    for v in out.iter_mut() {
+        let space = 64 - bits;
+        let mut value = if space == 64 { word } else { word >> bits };
+
+        if width < space {
+            bits += width;
+        } else {
+            pos += 8;
+            word = read_word(&data[pos.min(data.len()) ..]);
+            bits = width - space;
+
+            if space < 64 && bits > 0 {
+                value |= word << space;
+            }
+        }
+
+        *v = value & mask;
+    }
+}
+
+/// Append the encoded `values` to `out`
+pub fn pack<T: PackedInt>(values: &[T], out: &mut Vec<u8>) {
+    write_word(out, values.len() as u64, 4);
+
+    let first = values.first().map_or(0, |v| v.to_bits());
+    write_word(out, first, 8);
+
+    let deltas: Vec<u64> = values.windows(2)
+        .map(|w| zigzag(w[1].to_bits().wrapping_sub(w[0].to_bits())))
+        .collect();
+
+    for frame in deltas.chunks(FRAME_SIZE) {
+        let width = frame.iter().map(|d| bit_width(*d)).max().unwrap_or(0);
+        out.push(width as u8);
+        pack_frame(frame, width, out);
+    }
+}
+
+/// Number of values encoded in `data`
+pub fn packed_len(data: &[u8]) -> Result<usize, DBError> {
+    if data.len() < 12 {
+        return Err(truncated())
+    }
+
+    Ok(read_word(&data[.. 4]) as usize)
+}
+
+/// Decode values from `data` into `out`, which has to fit them. Returns the number of bytes read.
+pub fn unpack<T: PackedInt>(data: &[u8], out: &mut [T]) -> Result<usize, DBError> {
+    let count = packed_len(data)?;
+    if count > out.len() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    if count == 0 {
+        return Ok(12)
+    }
+
+    let mut prev = read_word(&data[4 .. 12]);
+    out[0] = T::from_bits(prev);
+
+    let mut pos = 12;
+    let mut deltas = [0u64; FRAME_SIZE];
+
+    for frame in out[1 .. count].chunks_mut(FRAME_SIZE) {
+        let width = *data.get(pos).ok_or_else(truncated)? as u32;
+        let bytes = (frame.len() * width as usize + 7) / 8;
+
+        if width > 64 {
+            return Err(DBError::ValueParse(format!("bit-packed integer width {}", width)))
+        }
+
+        if data.len() < pos + 1 + bytes {
+            return Err(truncated())
+        }
+
+        unpack_frame(&data[pos + 1 .. pos + 1 + bytes], width, &mut deltas[.. frame.len()]);
+
+        for (v, delta) in frame.iter_mut().zip(deltas.iter()) {
+            prev = prev.wrapping_add(unzigzag(*delta));
+            *v = T::from_bits(prev);
+        }
+
+        pos += 1 + bytes;
+    }
+
+    Ok(pos)
+}
+
+fn pack_rows<T: ValueInfo>(col: &RefColumn, rows: RowOffset, out: &mut Vec<u8>)
+    -> Result<(), DBError>
+    where T::Store: PackedInt
+{
+    if rows > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let data = column_row_data::<T>(col)?;
+    if !col.attribute().nullable {
+        pack(&data.values[.. rows], out);
+        return Ok(())
+    }
+
+    // Values of NULL rows are not initialized, repeating the previous value packs them in 0 bits
+    let mut values = Vec::with_capacity(rows);
+    let mut prev = T::Store::default();
+    for row in 0 .. rows {
+        if data.nulls[row] == 0 {
+            prev = data.values[row];
+        }
+        values.push(prev);
+    }
+
+    pack(values.as_slice(), out);
+
+    let mut bitmap = vec![0u8; (rows + 7) / 8];
+    for row in (0 .. rows).filter(|r| data.nulls[*r] != 0) {
+        bitmap[row / 8] |= 1 << (row % 8);
+    }
+
+    out.extend(bitmap);
+    Ok(())
+}
+
+fn unpack_rows<T: ValueInfo>(data: &[u8], dst: &mut Column, rows: RowOffset)
+    -> Result<usize, DBError>
+    where T::Store: PackedInt
+{
+    if packed_len(data)? != rows || rows > dst.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let mut pos = unpack(data, &mut dst.rows_mut::<T>()?[.. rows])?;
+
+    if dst.attribute().nullable {
+        let bitmap = &data[pos ..];
+        if bitmap.len() < (rows + 7) / 8 {
+            return Err(truncated())
+        }
+
+        let nulls = dst.nulls_mut()?;
+        for row in 0 .. rows {
+            nulls[row] = (bitmap[row / 8] >> (row % 8)) & 1;
+        }
+
+        pos += (rows + 7) / 8;
+    }
+
+    Ok(pos)
+}
+
+/// Append the delta and bit-packed encoding of the first `rows` of an integer column (and its
+/// NULL vector) to `out`.
+pub fn pack_column(col: &RefColumn, rows: RowOffset, out: &mut Vec<u8>) -> Result<(), DBError> {
+    dispatch_type!(col.attribute().dtype, integer, T => pack_rows::<T>(col, rows, out),
+        _ => Err(DBError::AttributeType(col.attribute().name.clone())))
+}
+
+/// Decode `rows` rows packed by `pack_column` into `dst`, which needs the capacity for them.
+/// Returns the number of bytes read from `data`.
+pub fn unpack_column(data: &[u8], dst: &mut Column, rows: RowOffset) -> Result<usize, DBError> {
+    let dtype = dst.attribute().dtype.clone();
+    dispatch_type!(dtype, integer, T => unpack_rows::<T>(data, dst, rows),
+        _ => Err(DBError::AttributeType(dst.attribute().name.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::schema::Schema;
+    use ::table::{Table, TableAppender};
+    use ::types::{self, Type};
+
+    // Sorted IDs pack into a couple of bits per value
+    #[test]
+    fn sorted_round_trip() {
+        let ids: Vec<u64> = (0 .. 1000).map(|v| 1_000_000 + v * 3).collect();
+
+        let mut packed = Vec::new();
+        pack(ids.as_slice(), &mut packed);
+        assert!(packed.len() < ids.len() / 2, "Packed into {} bytes", packed.len());
+
+        let mut out = vec![0u64; ids.len()];
+        assert_eq!(unpack(packed.as_slice(), out.as_mut_slice()).unwrap(), packed.len());
+        assert_eq!(out, ids);
+
+        assert!(unpack(&packed[.. packed.len() - 1], out.as_mut_slice()).is_err());
+    }
+
+    #[test]
+    fn unsorted_round_trip() {
+        let values = [i64::max_value(), i64::min_value(), 0, -1, 5, 5, i64::max_value()];
+
+        let mut packed = Vec::new();
+        pack(&values, &mut packed);
+
+        let mut out = [0i64; 7];
+        unpack(packed.as_slice(), &mut out).unwrap();
+        assert_eq!(out, values);
+    }
+
+    #[test]
+    fn column_round_trip() {
+        let schema = Schema::make_one_attr("id", true, Type::INT32);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(10 as i32)
+                .add_row().set_null(true)
+                .add_row().set(12 as i32)
+                .done();
+
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let mut packed = Vec::new();
+        pack_column(table.column(0).unwrap(), 3, &mut packed).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(3).unwrap();
+
+        let read = unpack_column(packed.as_slice(), block.column_mut(0).unwrap(), 3).unwrap();
+        assert_eq!(read, packed.len());
+
+        let rows = column_row_data::<types::Int32>(block.column(0).unwrap()).unwrap();
+        assert_eq!(rows.nulls[.. 3], [0, 1, 0]);
+        assert_eq!(rows.values[0], 10);
+        assert_eq!(rows.values[2], 12);
+    }
+}
@@ -0,0 +1,108 @@
+// vim: set ts=4 sw=4 et :
+
+//! Bloom filter over column values.
+//!
+//! Used as a join side-channel: the filter is built from the join build side keys and pushed into
+//! the probe side scan (see `expression::bloom::BloomFilterExpr`), so probe rows that can't have
+//! a match are dropped before reaching the join.
+
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::LN_2;
+use std::hash::{Hash, Hasher};
+
+use ::block::{RefColumn, column_value};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::types::Value;
+
+/// Set membership test with false positives but no false negatives
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Filter sized for `items` values with a `fpp` (0 .. 1) false positive probability
+    pub fn new(items: usize, fpp: f64) -> BloomFilter {
+        let items = items.max(1) as f64;
+        let fpp = fpp.max(1e-9).min(0.5);
+
+        let bits = (-items * fpp.ln() / (LN_2 * LN_2)).ceil().max(64.0) as usize;
+        let hashes = ((bits as f64 / items) * LN_2).round().max(1.0) as u32;
+
+        BloomFilter { bits: vec![0; (bits + 63) / 64], hashes: hashes }
+    }
+
+    /// Filter of the non NULL values in the first `rows` of the column
+    pub fn from_column(col: &RefColumn, rows: RowOffset, fpp: f64) -> Result<BloomFilter, DBError> {
+        let mut filter = BloomFilter::new(rows, fpp);
+
+        for row in 0 .. rows {
+            let value = column_value(col, row)?;
+            if value != Value::NULL {
+                filter.insert(&value);
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// Number of bits
+    pub fn size(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    /// Bit positions for the value, using double hashing
+    fn positions(&self, value: &Value) -> (u64, u64) {
+        let mut state = DefaultHasher::new();
+        value.hash(&mut state);
+
+        let hash = state.finish();
+        // Odd step so it's never 0
+        (hash & 0xffff_ffff, (hash >> 32) | 1)
+    }
+
+    pub fn insert(&mut self, value: &Value) {
+        let (h1, h2) = self.positions(value);
+        let size = self.size() as u64;
+
+        for i in 0 .. self.hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % size;
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// False if the value was never inserted, true if it (probably) was
+    pub fn contains(&self, value: &Value) -> bool {
+        let (h1, h2) = self.positions(value);
+        let size = self.size() as u64;
+
+        (0 .. self.hashes as u64).all(|i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % size;
+            self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for v in 0 .. 1000 {
+            filter.insert(&Value::INT64(v * 7));
+        }
+
+        assert!((0 .. 1000).all(|v| filter.contains(&Value::INT64(v * 7))));
+
+        let false_positives = (0 .. 10000)
+            .filter(|v| filter.contains(&Value::INT64(v * 7 + 1)))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+
+        // Types are part of the hash
+        assert!(!filter.contains(&Value::TEXT("0")));
+    }
+}
@@ -1,12 +1,17 @@
-use ::block::Column;
+use ::block::{BoolBitmap, Column, Encoding, RefColumn, column_nulls, column_row_data, column_value};
 use ::error::DBError;
 use ::row::RowOffset;
-use ::types;
+use ::types::{self, IntervalValue, RawData, Type, Value, ValueInfo};
 
 /// Trait for setting column row values from rust native types.
 /// Deals correctly with types that need to store data in the column's arena.
 pub trait ValueSetter {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError>;
+
+    /// Setting this value marks the row as NULL
+    fn is_null(&self) -> bool {
+        false
+    }
 }
 
 impl ValueSetter for types::NullType {
@@ -15,6 +20,10 @@ impl ValueSetter for types::NullType {
         rows[row] = true as u8;
         Ok(())
     }
+
+    fn is_null(&self) -> bool {
+        true
+    }
 }
 
 impl ValueSetter for u32 {
@@ -49,6 +58,22 @@ impl ValueSetter for i64 {
     }
 }
 
+impl ValueSetter for f32 {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        let rows = col.rows_mut::<types::Float32>()?;
+        rows[row] = *self;
+        Ok(())
+    }
+}
+
+impl ValueSetter for f64 {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        let rows = col.rows_mut::<types::Float64>()?;
+        rows[row] = *self;
+        Ok(())
+    }
+}
+
 impl ValueSetter for bool {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
         let rows = col.rows_mut::<types::Boolean>()?;
@@ -57,46 +82,261 @@ impl ValueSetter for bool {
     }
 }
 
-impl<'b> ValueSetter for &'b str {
+impl ValueSetter for IntervalValue {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
-        let data = self.as_bytes();
-        let ptr = {
-            let arena = col.arena();
-            arena.append(data)?.1
-        };
+        let rows = col.rows_mut::<types::Interval>()?;
+        rows[row] = *self;
+        Ok(())
+    }
+}
 
-        let rows = col.rows_mut::<types::Text>()?;
-        rows[row] = types::RawData{data: ptr, size: data.len()};
+impl<'b> ValueSetter for &'b str {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        let handle = col.append_varlen(self.as_bytes())?;
+        col.rows_mut::<types::Text>()?[row] = handle;
         Ok(())
     }
 }
 
 impl ValueSetter for String {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
-        let data = self.as_bytes();
-        let ptr = {
-            let arena = col.arena();
-            arena.append(data)?.1
-        };
-
-        let rows = col.rows_mut::<types::Text>()?;
-        rows[row] = types::RawData{data: ptr, size: data.len()};
+        let handle = col.append_varlen(self.as_bytes())?;
+        col.rows_mut::<types::Text>()?[row] = handle;
         Ok(())
     }
 }
 
 impl<'b> ValueSetter for &'b[u8] {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
-        let ptr = {
-            let arena = col.arena();
-            arena.append(self)?.1
-        };
-
-        let rows = col.rows_mut::<types::Blob>()?;
-        rows[row] = types::RawData{data: ptr, size: self.len()};
+        let handle = col.arena()?.append(self)?;
+        col.rows_mut::<types::Blob>()?[row] = handle;
         Ok(())
     }
 }
 
+/// `None` sets the row NULL
+impl<T: ValueSetter> ValueSetter for Option<T> {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        match *self {
+            Some(ref v) => v.set_row(col, row),
+            None        => types::NULL_VALUE.set_row(col, row),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        self.as_ref().map_or(true, |v| v.is_null())
+    }
+}
+
+/// Sets a LIST column row. Elements are appended to the LIST element column.
+impl<T: ValueSetter> ValueSetter for Vec<T> {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        set_list_row(col, row, self.as_slice())
+    }
+}
+
+fn set_list_row<'a, T: ValueSetter>(col: &mut Column<'a>, row: RowOffset, items: &[T])
+    -> Result<(), DBError>
+{
+    let range = col.list_append(row, items.len())?;
+    let child = col.child_mut(0).unwrap();
+
+    for (pos, item) in items.iter().enumerate() {
+        let elem = range.offset + pos;
+        item.set_row(child, elem)?;
+
+        if !item.is_null() {
+            child.nulls_mut()?[elem] = 0;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets each of the STRUCT field columns. There has to be a value for every field.
+fn set_struct_row<'a, T: ValueSetter>(col: &mut Column<'a>, row: RowOffset, fields: &[T])
+    -> Result<(), DBError>
+{
+    let count = match col.attribute().dtype {
+        Type::STRUCT(ref f) => f.len(),
+        _ => return Err(DBError::AttributeType(col.attribute().name.clone())),
+    };
+
+    if count != fields.len() {
+        return Err(DBError::AttributeType(col.attribute().name.clone()))
+    }
+
+    for (pos, value) in fields.iter().enumerate() {
+        let child = col.child_mut(pos).unwrap();
+        value.set_row(child, row)?;
+
+        if child.attribute().nullable && !value.is_null() {
+            child.nulls_mut()?[row] = 0;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets the column row from a `Value`. The `Value` variant has to match the column type.
+impl<'b> ValueSetter for Value<'b> {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        match *self {
+            Value::NULL             => types::NULL_VALUE.set_row(col, row),
+            Value::UINT32(v)        => v.set_row(col, row),
+            Value::UINT64(v)        => v.set_row(col, row),
+            Value::INT32(v)         => v.set_row(col, row),
+            Value::INT64(v)         => v.set_row(col, row),
+            Value::FLOAT32(v)       => v.set_row(col, row),
+            Value::FLOAT64(v)       => v.set_row(col, row),
+            Value::BOOLEAN(v)       => v.set_row(col, row),
+            Value::TIMESTAMP(v)     => {
+                col.rows_mut::<types::Timestamp>()?[row] = v;
+                Ok(())
+            },
+            Value::INTERVAL(v)      => v.set_row(col, row),
+            Value::UUID(v)          => {
+                col.rows_mut::<types::Uuid>()?[row] = v;
+                Ok(())
+            },
+            Value::TEXT(v)          => v.set_row(col, row),
+            Value::BLOB(v)          => v.set_row(col, row),
+            Value::LIST(ref items)  => set_list_row(col, row, items),
+            Value::STRUCT(ref fields) => set_struct_row(col, row, fields),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        match *self {
+            Value::NULL => true,
+            _           => false,
+        }
+    }
+}
+
 // TODO: Make a value alias... we can set a value but without copying the data in the arena.
 // Clearly unsafe, but useful for things like join with Tiny... where it's always alive.
+
+/// Deep copy the first `rows` rows (values and null vector) of `src` into `dst`.
+///
+/// VARLEN values are copied into the arena of the `dst` column. Copying a nullable column into a
+/// not nullable column only succeeds if none of the copied rows are NULL. Encoded columns are
+/// decoded.
+pub fn copy_column(src: &RefColumn, dst: &mut Column, rows: RowOffset) -> Result<(), DBError> {
+    if src.attribute().dtype != dst.attribute().dtype {
+        return Err(DBError::AttributeType(dst.attribute().name.clone()))
+    }
+
+    if rows > src.capacity() || rows > dst.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    if src.encoding() != Encoding::PLAIN {
+        return copy_decoded_rows(src, dst, rows)
+    }
+
+    let dtype = &src.attribute().dtype;
+    dispatch_type!(*dtype, fixed, T => copy_rows::<T>(src, dst, rows),
+        _ => dispatch_type!(*dtype, varlen, T => copy_varlen_rows::<T>(src, dst, rows),
+            _ => match *dtype {
+                Type::LIST(_)   => copy_list_rows(src, dst, rows),
+                _               => copy_struct_rows(src, dst, rows),
+            }))
+}
+
+fn copy_rows<T: ValueInfo>(src: &RefColumn, dst: &mut Column, rows: RowOffset)
+    -> Result<(), DBError>
+    where T::Store: Copy
+{
+    let from = column_row_data::<T>(src)?;
+    dst.rows_mut::<T>()?[..rows].copy_from_slice(&from.values[..rows]);
+
+    let nulls = if src.attribute().nullable { Some(from.nulls) } else { None };
+    copy_nulls(nulls, dst, rows)
+}
+
+fn copy_varlen_rows<T>(src: &RefColumn, dst: &mut Column, rows: RowOffset)
+    -> Result<(), DBError>
+    where T: ValueInfo<Store=RawData>
+{
+    let from = column_row_data::<T>(src)?;
+    let nullable = src.attribute().nullable;
+
+    for row in 0 .. rows {
+        // Values of NULL rows are not initialized
+        let value = if nullable && from.nulls[row] != 0 {
+            RawData::default()
+        } else {
+            dst.append_varlen(from.bytes(row))?
+        };
+
+        dst.rows_mut::<T>()?[row] = value;
+    }
+
+    let nulls = if nullable { Some(from.nulls) } else { None };
+    copy_nulls(nulls, dst, rows)
+}
+
+/// LIST elements of the copied rows are appended to the element column of `dst`
+fn copy_list_rows(src: &RefColumn, dst: &mut Column, rows: RowOffset) -> Result<(), DBError> {
+    for row in 0 .. rows {
+        if let Value::LIST(items) = column_value(src, row)? {
+            set_list_row(dst, row, items.as_slice())?;
+        }
+    }
+
+    copy_nulls(column_nulls(src), dst, rows)
+}
+
+/// Fields of NULL STRUCT rows are not initialized, so those are skipped
+fn copy_struct_rows(src: &RefColumn, dst: &mut Column, rows: RowOffset) -> Result<(), DBError> {
+    match column_nulls(src) {
+        None => {
+            let mut pos = 0;
+            while let Some(field) = src.child(pos) {
+                let dst_field = dst.child_mut(pos)
+                    .ok_or(DBError::make_column_unknown_pos(pos))?;
+
+                copy_column(field, dst_field, rows)?;
+                pos += 1;
+            }
+        },
+        Some(_) => for row in 0 .. rows {
+            if let Value::STRUCT(fields) = column_value(src, row)? {
+                set_struct_row(dst, row, fields.as_slice())?;
+            }
+        },
+    }
+
+    copy_nulls(column_nulls(src), dst, rows)
+}
+
+fn copy_decoded_rows(src: &RefColumn, dst: &mut Column, rows: RowOffset) -> Result<(), DBError> {
+    for row in 0 .. rows {
+        let value = column_value(src, row)?;
+        if !value.is_null() {
+            value.set_row(dst, row)?;
+        }
+    }
+
+    copy_nulls(column_nulls(src), dst, rows)
+}
+
+fn copy_nulls(nulls: Option<BoolBitmap>, dst: &mut Column, rows: RowOffset) -> Result<(), DBError> {
+    if !dst.attribute().nullable {
+        return match nulls {
+            Some(n) if n[..rows].iter().any(|v| *v != 0) =>
+                Err(DBError::make_column_not_nullable(dst.attribute().name.clone())),
+            _ =>
+                Ok(())
+        }
+    }
+
+    let out = dst.nulls_mut()?;
+    match nulls {
+        Some(n) => out[..rows].copy_from_slice(&n[..rows]),
+        None    => for v in &mut out[..rows] { *v = 0 },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+// vim: set ts=4 sw=4 et :
+
+//! Stable hashes of row keys, for hash joins, grouping and partitioning.
+//!
+//! `hash_rows` hashes the key columns of all the rows of a view, a column at a time. The hashes
+//! only depend on the key values and the seed, not on the column encodings, the platform or the
+//! Rust version, so everything hashing the same keys with the same seed agrees on them: a hash
+//! index and its lookups (`hash_key`), the build and probe sides of a join, the partitions of an
+//! exchange. Different seeds give independent hashes (eg. for partitioning and then grouping the
+//! rows of a partition).
+//!
+//! With `fmix64` the 64 bit finalizer of MurmurHash3, the hash of a value is:
+//!
+//! - NULL: `fmix64(seed ^ NULL_HASH)`
+//! - UINT32, UINT64, INT32, INT64, TIMESTAMP (microseconds): `fmix64(seed ^ v)` of the value as a
+//!   64 bit integer (two's complement for the signed types)
+//! - FLOAT32, FLOAT64: `fmix64(seed ^ bits)` of the IEEE 754 bits of the value as a double, -0.0
+//!   hashed as 0.0 and every NaN as `0x7ff8000000000000`
+//! - BOOLEAN: `fmix64(seed ^ v)` of 0 or 1
+//! - INTERVAL: `combine(combine(seed, hm), hu)` of the hashes of `months << 32 | days` (as 32 bit
+//!   unsigned integers) and of the microseconds, as integers
+//! - UUID, TEXT (UTF-8), BLOB: `fmix64(fnv ^ len)`, `fnv` the FNV-1a hash of the bytes starting
+//!   from the offset basis `0xcbf29ce484222325 ^ seed`, `len` the number of bytes
+//! - LIST, STRUCT: `fmix64(h ^ len)`, `h` the items (or fields) hashes `combine`d from `seed`
+//!
+//! where `combine(h, v) = fmix64(h.rotate_left(5) ^ v)`. The hash of a row is the hashes of its
+//! key values `combine`d from `seed`, in key order. Integer values of different types (and floats
+//! of either width) hash the same, just like equal values of the same type always do.
+
+use ::block::{Encoding, RefColumn, View, column_row_data, column_value};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::types::{Blob, Boolean, Float32, Float64, Int32, Int64, RawData, Text, Timestamp, Type,
+              UInt32, UInt64, Value, ValueInfo};
+
+/// Constant of the NULL value hash
+pub const NULL_HASH: u64 = 0x6e75_6c6c_6e75_6c6c;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// MurmurHash3 64 bit finalizer
+pub fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^ k >> 33
+}
+
+/// Hash of the hash `h` followed by the hash `v`
+pub fn combine(h: u64, v: u64) -> u64 {
+    fmix64(h.rotate_left(5) ^ v)
+}
+
+fn float_bits(v: f64) -> u64 {
+    if v == 0.0 { 0 } else if v.is_nan() { 0x7ff8_0000_0000_0000 } else { v.to_bits() }
+}
+
+fn bytes_hash(bytes: &[u8], seed: u64) -> u64 {
+    let fnv = bytes.iter()
+        .fold(FNV_OFFSET_BASIS ^ seed, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME));
+    fmix64(fnv ^ bytes.len() as u64)
+}
+
+/// Hash of the value, see the module documentation
+pub fn hash_value(value: &Value, seed: u64) -> u64 {
+    let int = |v: u64| fmix64(seed ^ v);
+
+    match *value {
+        Value::NULL                 => int(NULL_HASH),
+        Value::UINT32(v)            => int(v as u64),
+        Value::UINT64(v)            => int(v),
+        Value::INT32(v)             => int(v as i64 as u64),
+        Value::INT64(v)             => int(v as u64),
+        Value::TIMESTAMP(v)         => int(v as u64),
+        Value::FLOAT32(v)           => int(float_bits(v as f64)),
+        Value::FLOAT64(v)           => int(float_bits(v)),
+        Value::BOOLEAN(v)           => int(v as u64),
+        Value::INTERVAL(v)          => {
+            let head = (v.months as u32 as u64) << 32 | v.days as u32 as u64;
+            combine(combine(seed, int(head)), int(v.micros as u64))
+        },
+        Value::UUID(ref v)          => bytes_hash(v, seed),
+        Value::TEXT(v)              => bytes_hash(v.as_bytes(), seed),
+        Value::BLOB(v)              => bytes_hash(v, seed),
+        Value::LIST(ref items)      => fmix64(hash_key(items, seed) ^ items.len() as u64),
+        Value::STRUCT(ref fields)   => fmix64(hash_key(fields, seed) ^ fields.len() as u64),
+    }
+}
+
+/// Hash of a row with the key `values`, in key order
+pub fn hash_key(values: &[Value], seed: u64) -> u64 {
+    values.iter().fold(seed, |h, v| combine(h, hash_value(v, seed)))
+}
+
+/// Combine the value hashes of the first `out.len()` rows of a PLAIN fixed width column into
+/// `out`, `bits` is the integer hashed for a value
+fn fixed_hashes<T: ValueInfo, F>(col: &RefColumn, seed: u64, bits: F, out: &mut [u64])
+    -> Result<(), DBError>
+    where F: Fn(T::Store) -> u64, T::Store: Copy
+{
+    let data = column_row_data::<T>(col)?;
+    let nullable = col.attribute().nullable;
+    let null = fmix64(seed ^ NULL_HASH);
+
+    for (row, h) in out.iter_mut().enumerate() {
+        let value = if nullable && data.nulls[row] != 0 {
+            null
+        } else {
+            fmix64(seed ^ bits(data.values[row]))
+        };
+        *h = combine(*h, value);
+    }
+
+    Ok(())
+}
+
+/// Combine the value hashes of the first `out.len()` rows of a PLAIN TEXT or BLOB column into
+/// `out`
+fn varlen_hashes<T: ValueInfo<Store=RawData>>(col: &RefColumn, seed: u64, out: &mut [u64])
+    -> Result<(), DBError>
+{
+    let data = column_row_data::<T>(col)?;
+    let nullable = col.attribute().nullable;
+    let null = fmix64(seed ^ NULL_HASH);
+
+    for (row, h) in out.iter_mut().enumerate() {
+        let value = if nullable && data.nulls[row] != 0 {
+            null
+        } else {
+            bytes_hash(data.bytes(row), seed)
+        };
+        *h = combine(*h, value);
+    }
+
+    Ok(())
+}
+
+/// Combine the value hashes of the first `out.len()` rows of the column into `out`
+fn column_hashes(col: &RefColumn, seed: u64, out: &mut [u64]) -> Result<(), DBError> {
+    if col.encoding() == Encoding::PLAIN {
+        let f32_bits = |v: f32| float_bits(v as f64);
+
+        match col.attribute().dtype {
+            Type::UINT32    => return fixed_hashes::<UInt32, _>(col, seed, |v| v as u64, out),
+            Type::UINT64    => return fixed_hashes::<UInt64, _>(col, seed, |v| v, out),
+            Type::INT32     => return fixed_hashes::<Int32, _>(col, seed, |v| v as u64, out),
+            Type::INT64     => return fixed_hashes::<Int64, _>(col, seed, |v| v as u64, out),
+            Type::TIMESTAMP => return fixed_hashes::<Timestamp, _>(col, seed, |v| v as u64, out),
+            Type::FLOAT32   => return fixed_hashes::<Float32, _>(col, seed, f32_bits, out),
+            Type::FLOAT64   => return fixed_hashes::<Float64, _>(col, seed, float_bits, out),
+            Type::BOOLEAN   => return fixed_hashes::<Boolean, _>(col, seed, |v| v as u64, out),
+            Type::TEXT      => return varlen_hashes::<Text>(col, seed, out),
+            Type::BLOB      => return varlen_hashes::<Blob>(col, seed, out),
+            _               => (),
+        }
+    }
+
+    // Encoded columns and the other types, by value
+    for (row, h) in out.iter_mut().enumerate() {
+        *h = combine(*h, hash_value(&column_value(col, row)?, seed));
+    }
+
+    Ok(())
+}
+
+/// Hash of the `key_columns` values of each row of the view, see the module documentation. The
+/// hash of a row equals the `hash_key` of its key values.
+pub fn hash_rows<'v>(view: &'v View<'v>, key_columns: &[usize], seed: u64)
+    -> Result<Vec<u64>, DBError>
+{
+    let mut out = vec![seed; view.rows()];
+
+    for pos in key_columns {
+        let col = view.column(*pos).ok_or(DBError::make_column_unknown_pos(*pos))?;
+        column_hashes(col, seed, &mut out)?;
+    }
+
+    Ok(out)
+}
+
+/// Hash of the key values of the row
+pub fn hash_row<'v>(view: &'v View<'v>, key_columns: &[usize], row: RowOffset, seed: u64)
+    -> Result<u64, DBError>
+{
+    let mut h = seed;
+    for pos in key_columns {
+        let col = view.column(*pos).ok_or(DBError::make_column_unknown_pos(*pos))?;
+        h = combine(h, hash_value(&column_value(col, row)?, seed));
+    }
+
+    Ok(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::schema::{Attribute, Schema};
+
+    fn make_block<'a>() -> Block<'a> {
+        let schema = Schema::from_vec(vec![
+            Attribute{name: "id".to_string(), nullable: true, dtype: Type::INT64},
+            Attribute{name: "name".to_string(), nullable: true, dtype: Type::TEXT},
+            Attribute{name: "score".to_string(), nullable: false, dtype: Type::FLOAT64},
+            Attribute{name: "flag".to_string(), nullable: false, dtype: Type::BOOLEAN},
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        let rows = [(Some(1), Some("a"), 0.0), (None, Some("b"), -0.0), (Some(1), Some("a"), 2.5),
+                    (Some(3), None, ::std::f64::NAN), (Some(3), None, -::std::f64::NAN)];
+
+        for (row, &(id, name, score)) in rows.iter().enumerate() {
+            block.append_row(&[id.map_or(Value::NULL, Value::INT64),
+                               name.map_or(Value::NULL, Value::TEXT),
+                               Value::FLOAT64(score), Value::BOOLEAN(row % 2 == 0)]).unwrap();
+        }
+        block
+    }
+
+    // Row hashes equal the hashes of their key values, whatever the column encoding
+    #[test]
+    fn row_hashes() {
+        let mut block = make_block();
+        let keys = [0, 1, 2, 3];
+
+        let hashes = hash_rows(&block, &keys, 7).unwrap();
+        for row in 0 .. block.rows() {
+            assert_eq!(hashes[row], hash_row(&block, &keys, row, 7).unwrap());
+        }
+
+        // -0.0 and 0.0, all NaNs hash the same
+        let scores = hash_rows(&block, &[2], 7).unwrap();
+        assert_eq!(scores[0], scores[1]);
+        assert_eq!(scores[3], scores[4]);
+        assert!(scores[0] != scores[2]);
+
+        let ids = hash_rows(&block, &[0, 1], 7).unwrap();
+        assert_eq!(ids[0], ids[2]);
+        assert!(ids[0] != ids[1]);
+        assert!(ids[0] != hash_rows(&block, &[0, 1], 8).unwrap()[0]);
+        assert_eq!(ids[0], hash_key(&[Value::INT64(1), Value::TEXT("a")], 7));
+        assert_eq!(ids[0], hash_key(&[Value::INT32(1), Value::TEXT("a")], 7));
+
+        let rows = block.rows();
+        block.column_mut(0).unwrap().encode_rle(rows).unwrap();
+        block.column_mut(1).unwrap().encode_dictionary(rows).unwrap();
+        assert_eq!(hash_rows(&block, &keys, 7).unwrap(), hashes);
+    }
+
+    // The documented hashes, so they can be computed the same way elsewhere
+    #[test]
+    fn documented_hashes() {
+        assert_eq!(fmix64(0), 0);
+        assert_eq!(fmix64(1), 0xb456_bcfc_34c2_cb2c);
+        assert_eq!(hash_value(&Value::INT64(1), 0), fmix64(1));
+        assert_eq!(hash_value(&Value::NULL, 3), fmix64(3 ^ NULL_HASH));
+        assert_eq!(hash_value(&Value::TEXT("a"), 0), fmix64(0xaf63_dc4c_8601_ec8c ^ 1));
+        assert_eq!(hash_key(&[Value::UINT32(1)], 0), combine(0, fmix64(1)));
+    }
+}
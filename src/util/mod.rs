@@ -1,5 +1,13 @@
+pub mod bitpack;
+pub mod bloom;
 pub mod copy_value;
+pub mod hash;
+pub mod hmac;
 pub mod math;
+pub mod random;
+pub mod sketch;
+pub mod temporal;
+pub mod uuid;
 
 pub use self::copy_value::ValueSetter;
 
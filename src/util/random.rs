@@ -0,0 +1,58 @@
+// vim: set ts=4 sw=4 et :
+
+//! Seedable pseudo random numbers, for sampling.
+//!
+//! Not for anything security sensitive. The sequence of a seed is the SplitMix64 one, so results
+//! (eg. of a sample) are reproducible across platforms and releases.
+
+/// SplitMix64 generator
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1), from the 53 high bits
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [0, n), n > 0
+    pub fn below(&mut self, n: u64) -> u64 {
+        // Multiply-shift, the bias is at most n / 2^64
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The reference SplitMix64 sequence, values stay in range
+    #[test]
+    fn splitmix_sequence() {
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+
+        let mut rng = SplitMix64::new(42);
+        for _ in 0 .. 1000 {
+            let f = rng.next_f64();
+            assert!(f >= 0.0 && f < 1.0);
+            assert!(rng.below(7) < 7);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+// vim: set ts=4 sw=4 et :
+
+//! Mergeable approximate summaries of column values.
+//!
+//! - `HyperLogLog` estimates the number of distinct values with a fixed number of registers,
+//!   instead of keeping a set of the values.
+//! - `QuantileSketch` (a DDSketch) estimates quantiles of numbers within a relative error, with
+//!   one counter for each logarithmically sized bucket.
+//!
+//! Sketches of parts of the input (eg. of each block) merge into the sketch of the whole input.
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use ::error::DBError;
+use ::types::Value;
+
+/// Smallest and largest `HyperLogLog` precision
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 18;
+
+/// Distinct count estimate, with a standard error of about 1.04 / sqrt(2 ^ precision)
+#[derive(Clone, PartialEq, Debug)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// Sketch with 2 ^ `precision` registers
+    pub fn new(precision: u8) -> Result<HyperLogLog, DBError> {
+        if precision < MIN_PRECISION || precision > MAX_PRECISION {
+            return Err(DBError::ValueOutOfRange(
+                format!("HyperLogLog precision {} not in {} .. {}", precision, MIN_PRECISION,
+                        MAX_PRECISION)))
+        }
+
+        Ok(HyperLogLog { precision: precision, registers: vec![0; 1 << precision] })
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn insert(&mut self, value: &Value) {
+        let mut state = DefaultHasher::new();
+        value.hash(&mut state);
+        let hash = state.finish();
+
+        let index = (hash >> (64 - self.precision)) as usize;
+        // Leading zeros of the remaining bits, the guard bit limits the count
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Add the values of the other sketch, it must have the same precision
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), DBError> {
+        if other.precision != self.precision {
+            return Err(DBError::ValueOutOfRange(
+                format!("HyperLogLog precision {} merged with {}", other.precision,
+                        self.precision)))
+        }
+
+        for (reg, &o) in self.registers.iter_mut().zip(&other.registers) {
+            *reg = (*reg).max(o);
+        }
+
+        Ok(())
+    }
+
+    /// Estimated number of distinct values inserted
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _  => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let sum: f64 = self.registers.iter().map(|&r| (-(r as f64)).exp2()).sum();
+        let estimate = alpha * m * m / sum;
+
+        // Linear counting is more accurate for small cardinalities
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// Quantile estimate, each quantile value is within `accuracy` relative error of the exact one
+#[derive(Clone, PartialEq, Debug)]
+pub struct QuantileSketch {
+    accuracy: f64,
+    gamma_ln: f64,
+    /// Counts of positive values by bucket index
+    positive: BTreeMap<i32, u64>,
+    /// Counts of the magnitude of negative values by bucket index
+    negative: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64,
+}
+
+/// Magnitude below which values count as 0
+const MIN_MAGNITUDE: f64 = 1e-9;
+
+impl QuantileSketch {
+    /// Sketch with a relative `accuracy` (0 .. 1), eg. 0.01 for quantiles within 1%
+    pub fn new(accuracy: f64) -> Result<QuantileSketch, DBError> {
+        if !(accuracy > 0.0 && accuracy < 1.0) {
+            return Err(DBError::ValueOutOfRange(
+                format!("Quantile sketch accuracy {} not in (0, 1)", accuracy)))
+        }
+
+        let gamma = (1.0 + accuracy) / (1.0 - accuracy);
+        Ok(QuantileSketch {
+            accuracy: accuracy,
+            gamma_ln: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zeros: 0,
+            count: 0,
+        })
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        self.accuracy
+    }
+
+    /// Number of values inserted
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    fn bucket(&self, magnitude: f64) -> i32 {
+        (magnitude.ln() / self.gamma_ln).ceil() as i32
+    }
+
+    /// Value representing the bucket, the lower and upper bucket bounds are within `accuracy`
+    fn bucket_value(&self, index: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (index as f64 * self.gamma_ln).exp() / (gamma + 1.0)
+    }
+
+    /// Add the number, NaNs are skipped
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return
+        }
+
+        if value.abs() < MIN_MAGNITUDE {
+            self.zeros += 1;
+        } else if value > 0.0 {
+            let index = self.bucket(value);
+            *self.positive.entry(index).or_insert(0) += 1;
+        } else {
+            let index = self.bucket(-value);
+            *self.negative.entry(index).or_insert(0) += 1;
+        }
+
+        self.count += 1;
+    }
+
+    /// Add the values of the other sketch, it must have the same accuracy
+    pub fn merge(&mut self, other: &QuantileSketch) -> Result<(), DBError> {
+        if other.accuracy != self.accuracy {
+            return Err(DBError::ValueOutOfRange(
+                format!("Quantile sketch accuracy {} merged with {}", other.accuracy,
+                        self.accuracy)))
+        }
+
+        for (&index, &n) in &other.positive {
+            *self.positive.entry(index).or_insert(0) += n;
+        }
+        for (&index, &n) in &other.negative {
+            *self.negative.entry(index).or_insert(0) += n;
+        }
+        self.zeros += other.zeros;
+        self.count += other.count;
+
+        Ok(())
+    }
+
+    /// Estimated value at the `quantile` (0 .. 1), None if the sketch is empty
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None
+        }
+
+        let quantile = quantile.max(0.0).min(1.0);
+        let rank = (quantile * (self.count - 1) as f64).round() as u64;
+
+        // Smallest values first: negative buckets by decreasing magnitude, zeros, positive buckets
+        let mut seen = 0;
+        for (&index, &n) in self.negative.iter().rev() {
+            seen += n;
+            if seen > rank {
+                return Some(-self.bucket_value(index))
+            }
+        }
+
+        seen += self.zeros;
+        if seen > rank {
+            return Some(0.0)
+        }
+
+        for (&index, &n) in &self.positive {
+            seen += n;
+            if seen > rank {
+                return Some(self.bucket_value(index))
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Estimates are within a few standard errors, merged sketches count each value once
+    #[test]
+    fn hyperloglog_estimate() {
+        let mut lhs = HyperLogLog::new(12).unwrap();
+        let mut rhs = HyperLogLog::new(12).unwrap();
+        for v in 0 .. 6000 {
+            lhs.insert(&Value::INT64(v));
+        }
+        for v in 4000 .. 10000 {
+            rhs.insert(&Value::INT64(v));
+        }
+
+        let error = |estimate: u64, exact: f64| (estimate as f64 - exact).abs() / exact;
+        assert!(error(lhs.estimate(), 6000.0) < 0.05, "estimate {}", lhs.estimate());
+
+        lhs.merge(&rhs).unwrap();
+        assert!(error(lhs.estimate(), 10000.0) < 0.05, "estimate {}", lhs.estimate());
+
+        let mut small = HyperLogLog::new(12).unwrap();
+        for v in 0 .. 100 {
+            small.insert(&Value::TEXT(if v % 2 == 0 { "even" } else { "odd" }));
+        }
+        assert_eq!(small.estimate(), 2);
+
+        assert!(HyperLogLog::new(3).is_err());
+        assert!(lhs.merge(&HyperLogLog::new(10).unwrap()).is_err());
+    }
+
+    // Quantiles within the relative accuracy, including negative values and zeros
+    #[test]
+    fn quantile_estimate() {
+        let mut lhs = QuantileSketch::new(0.01).unwrap();
+        let mut rhs = QuantileSketch::new(0.01).unwrap();
+        assert_eq!(lhs.quantile(0.5), None);
+
+        for v in 1 .. 501 {
+            lhs.insert(v as f64);
+        }
+        for v in 501 .. 1001 {
+            rhs.insert(v as f64);
+        }
+        lhs.merge(&rhs).unwrap();
+        assert_eq!(lhs.count(), 1000);
+
+        let close = |estimate: Option<f64>, exact: f64| {
+            (estimate.unwrap() - exact).abs() <= exact.abs() * 0.01
+        };
+        assert!(close(lhs.quantile(0.5), 501.0), "median {:?}", lhs.quantile(0.5));
+        assert!(close(lhs.quantile(0.99), 990.0), "p99 {:?}", lhs.quantile(0.99));
+        assert!(close(lhs.quantile(0.0), 1.0));
+        assert!(close(lhs.quantile(1.0), 1000.0));
+
+        let mut mixed = QuantileSketch::new(0.01).unwrap();
+        for &v in &[-100.0, -10.0, 0.0, 0.0, 10.0] {
+            mixed.insert(v);
+        }
+        assert!(close(mixed.quantile(0.0), -100.0));
+        assert!(close(mixed.quantile(0.25), -10.0));
+        assert_eq!(mixed.quantile(0.5), Some(0.0));
+
+        assert!(QuantileSketch::new(0.0).is_err());
+        assert!(lhs.merge(&QuantileSketch::new(0.05).unwrap()).is_err());
+    }
+}
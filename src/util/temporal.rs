@@ -0,0 +1,347 @@
+// vim: set ts=4 sw=4 et :
+
+//! Calendar arithmetic for TIMESTAMP and INTERVAL values.
+//!
+//! Timestamps are microseconds since the Unix epoch in UTC using the proleptic Gregorian
+//! calendar.
+
+use std::fmt;
+
+use ::error::DBError;
+use ::types::IntervalValue;
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_MINUTE: i64 = 60 * MICROS_PER_SECOND;
+pub const MICROS_PER_HOUR: i64 = 60 * MICROS_PER_MINUTE;
+pub const MICROS_PER_DAY: i64 = 24 * MICROS_PER_HOUR;
+
+/// Floor division, rounds towards negative infinity
+fn div_floor(n: i64, d: i64) -> i64 {
+    let q = n / d;
+    if (n % d != 0) && ((n < 0) != (d < 0)) { q - 1 } else { q }
+}
+
+/// Days since epoch for a (year, month [1-12], day [1-31]) civil date
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = div_floor(y, 400);
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Civil date (year, month [1-12], day [1-31]) from days since epoch
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = div_floor(z, 146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// ISO day of the week of timestamp `ts`, from 1 (Monday) to 7 (Sunday)
+pub fn day_of_week(ts: i64) -> u32 {
+    // 1970-01-01 was a Thursday
+    let days = div_floor(ts, MICROS_PER_DAY);
+    ((days + 3) - div_floor(days + 3, 7) * 7) as u32 + 1
+}
+
+fn out_of_range() -> DBError {
+    DBError::ValueOutOfRange(String::from("TIMESTAMP"))
+}
+
+/// Add `months` calendar months to timestamp `ts`.
+///
+/// The day of month is clamped to the length of the resulting month, eg. Jan 31st + 1 month is
+/// Feb 28th (or 29th).
+fn add_months(ts: i64, months: i32) -> Result<i64, DBError> {
+    if months == 0 {
+        return Ok(ts)
+    }
+
+    let days = div_floor(ts, MICROS_PER_DAY);
+    let time = ts - days * MICROS_PER_DAY;
+    let (year, month, day) = civil_from_days(days);
+
+    let total = year * 12 + (month as i64 - 1) + months as i64;
+    let new_year = div_floor(total, 12);
+    let new_month = (total - new_year * 12 + 1) as u32;
+    let new_day = ::std::cmp::min(day, days_in_month(new_year, new_month));
+
+    days_from_civil(new_year, new_month, new_day)
+        .checked_mul(MICROS_PER_DAY)
+        .and_then(|v| v.checked_add(time))
+        .ok_or_else(out_of_range)
+}
+
+/// `ts + interval`
+pub fn add_interval(ts: i64, interval: &IntervalValue) -> Result<i64, DBError> {
+    let ts = add_months(ts, interval.months)?;
+
+    (interval.days as i64).checked_mul(MICROS_PER_DAY)
+        .and_then(|days| ts.checked_add(days))
+        .and_then(|ts| ts.checked_add(interval.micros))
+        .ok_or_else(out_of_range)
+}
+
+/// `ts - interval`
+pub fn sub_interval(ts: i64, interval: &IntervalValue) -> Result<i64, DBError> {
+    let negated = IntervalValue {
+        months: interval.months.checked_neg().ok_or_else(out_of_range)?,
+        days: interval.days.checked_neg().ok_or_else(out_of_range)?,
+        micros: interval.micros.checked_neg().ok_or_else(out_of_range)?,
+    };
+
+    add_interval(ts, &negated)
+}
+
+/// `lhs - rhs` as an interval of whole days and the remaining time
+pub fn timestamp_diff(lhs: i64, rhs: i64) -> Result<IntervalValue, DBError> {
+    let diff = lhs.checked_sub(rhs)
+        .ok_or(DBError::ValueOutOfRange(String::from("INTERVAL")))?;
+
+    Ok(IntervalValue {
+        months: 0,
+        days: (diff / MICROS_PER_DAY) as i32,
+        micros: diff % MICROS_PER_DAY,
+    })
+}
+
+/// Format timestamp as "YYYY-MM-DD HH:MM:SS[.ffffff]"
+pub fn format_timestamp(ts: i64) -> String {
+    let days = div_floor(ts, MICROS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    let mut out = format!("{:04}-{:02}-{:02} ", year, month, day);
+    push_time(&mut out, ts - days * MICROS_PER_DAY);
+    out
+}
+
+/// Format timestamp as the local time `utc_offset` microseconds from UTC, followed by the offset:
+/// "YYYY-MM-DD HH:MM:SS[.ffffff]+HH:MM"
+pub fn format_local_timestamp(ts: i64, utc_offset: i64) -> String {
+    let mut out = format_timestamp(ts.saturating_add(utc_offset));
+    let minutes = utc_offset.abs() / MICROS_PER_MINUTE;
+    let sign = if utc_offset < 0 { '-' } else { '+' };
+    out.push_str(&format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60));
+    out
+}
+
+fn push_time(out: &mut String, micros: i64) {
+    let secs = micros / MICROS_PER_SECOND;
+    let frac = micros % MICROS_PER_SECOND;
+
+    out.push_str(&format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60));
+    if frac != 0 {
+        out.push_str(&format!(".{:06}", frac));
+    }
+}
+
+fn plural(n: i64, unit: &str) -> String {
+    format!("{} {}{}", n, unit, if n == 1 || n == -1 { "" } else { "s" })
+}
+
+/// Format an interval as "[N year[s]] [N month[s]] [N day[s]] [-]HH:MM:SS[.ffffff]"
+///
+/// The output can be parsed back by `parse_interval`.
+pub fn format_interval(v: &IntervalValue, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut parts = Vec::new();
+    let (years, months) = (v.months / 12, v.months % 12);
+
+    if years != 0 {
+        parts.push(plural(years as i64, "year"));
+    }
+
+    if months != 0 {
+        parts.push(plural(months as i64, "month"));
+    }
+
+    if v.days != 0 {
+        parts.push(plural(v.days as i64, "day"));
+    }
+
+    if v.micros != 0 || parts.is_empty() {
+        let mut time = String::new();
+        if v.micros < 0 {
+            time.push('-');
+        }
+        push_time(&mut time, v.micros.abs());
+        parts.push(time);
+    }
+
+    write!(f, "{}", parts.join(" "))
+}
+
+fn parse_error(s: &str) -> DBError {
+    DBError::ValueParse(format!("INTERVAL '{}'", s))
+}
+
+/// Parse "[-]HH:MM[:SS[.ffffff]]" into microseconds
+fn parse_time(s: &str) -> Option<i64> {
+    let (negative, s) = if s.starts_with('-') { (true, &s[1..]) } else { (false, s) };
+    let fields: Vec<&str> = s.split(':').collect();
+
+    if fields.len() < 2 || fields.len() > 3 {
+        return None
+    }
+
+    let hours: i64 = fields[0].parse().ok()?;
+    let minutes: i64 = fields[1].parse().ok()?;
+
+    let micros = if fields.len() == 3 {
+        let mut sec = fields[2].splitn(2, '.');
+        let whole: i64 = sec.next()?.parse().ok()?;
+        let frac = match sec.next() {
+            Some(digits) if digits.len() > 0 && digits.len() <= 6 &&
+                digits.chars().all(|c| c.is_digit(10)) =>
+                digits.parse::<i64>().ok()? * 10i64.pow(6 - digits.len() as u32),
+            Some(_) =>
+                return None,
+            None =>
+                0,
+        };
+        whole * MICROS_PER_SECOND + frac
+    } else {
+        0
+    };
+
+    let total = hours.checked_mul(MICROS_PER_HOUR)?
+        .checked_add(minutes.checked_mul(MICROS_PER_MINUTE)?)?
+        .checked_add(micros)?;
+
+    Some(if negative { -total } else { total })
+}
+
+/// Parse a humane interval string.
+///
+/// The string is a whitespace separated list of `<integer> <unit>` pairs and optionally a
+/// "HH:MM[:SS[.ffffff]]" time. Units are case insensitive and can be singular/plural or
+/// abbreviated: microsecond (us), millisecond (ms), second (s, sec), minute (m, min),
+/// hour (h, hr), day (d), week (w), month (mon), year (y, yr).
+pub fn parse_interval(s: &str) -> Result<IntervalValue, DBError> {
+    let mut out = IntervalValue::default();
+    let mut tokens = s.split_whitespace().peekable();
+
+    if tokens.peek().is_none() {
+        return Err(parse_error(s))
+    }
+
+    while let Some(token) = tokens.next() {
+        if token.contains(':') {
+            let time = parse_time(token).ok_or_else(|| parse_error(s))?;
+            out.micros = out.micros.checked_add(time).ok_or_else(|| parse_error(s))?;
+            continue
+        }
+
+        let amount: i64 = token.parse().map_err(|_| parse_error(s))?;
+        let unit = tokens.next().ok_or_else(|| parse_error(s))?.to_lowercase();
+        let unit = unit.trim_right_matches(',');
+
+        let (months, days, micros) = match unit {
+            "microsecond" | "microseconds" | "us" | "usec" | "usecs" => (0, 0, 1),
+            "millisecond" | "milliseconds" | "ms" | "msec" | "msecs" => (0, 0, 1000),
+            "second" | "seconds" | "s" | "sec" | "secs"              => (0, 0, MICROS_PER_SECOND),
+            "minute" | "minutes" | "m" | "min" | "mins"              => (0, 0, MICROS_PER_MINUTE),
+            "hour" | "hours" | "h" | "hr" | "hrs"                    => (0, 0, MICROS_PER_HOUR),
+            "day" | "days" | "d"                                     => (0, 1, 0),
+            "week" | "weeks" | "w"                                   => (0, 7, 0),
+            "month" | "months" | "mon" | "mons"                      => (1, 0, 0),
+            "year" | "years" | "y" | "yr" | "yrs"                    => (12, 0, 0),
+            _                                                        => return Err(parse_error(s)),
+        };
+
+        let add = |acc: i64, mult: i64| -> Option<i64> {
+            amount.checked_mul(mult).and_then(|v| v.checked_add(acc))
+        };
+
+        let new_months = add(out.months as i64, months).ok_or_else(|| parse_error(s))?;
+        let new_days = add(out.days as i64, days).ok_or_else(|| parse_error(s))?;
+
+        if new_months.abs() > i32::max_value() as i64 || new_days.abs() > i32::max_value() as i64 {
+            return Err(parse_error(s))
+        }
+
+        out.months = new_months as i32;
+        out.days = new_days as i32;
+        out.micros = add(out.micros, micros).ok_or_else(|| parse_error(s))?;
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(year: i64, month: u32, day: u32, hour: i64) -> i64 {
+        days_from_civil(year, month, day) * MICROS_PER_DAY + hour * MICROS_PER_HOUR
+    }
+
+    #[test]
+    fn parse_humane() {
+        let v: IntervalValue = "1 year 2 months 3 days 04:05:06.5".parse().unwrap();
+        assert_eq!(v, IntervalValue { months: 14, days: 3, micros: 4 * MICROS_PER_HOUR +
+            5 * MICROS_PER_MINUTE + 6 * MICROS_PER_SECOND + 500_000 });
+
+        let v: IntervalValue = "-2 Hours 30 min".parse().unwrap();
+        assert_eq!(v, IntervalValue { months: 0, days: 0, micros: -90 * MICROS_PER_MINUTE });
+
+        assert!("1 fortnight".parse::<IntervalValue>().is_err());
+        assert!("day".parse::<IntervalValue>().is_err());
+        assert!("".parse::<IntervalValue>().is_err());
+    }
+
+    // Display output parses back into the same interval
+    #[test]
+    fn format_round_trip() {
+        for s in &["1 day", "1 year 1 month 2 days -01:00:00", "00:00:00", "3 months 00:00:01.25"] {
+            let v: IntervalValue = s.parse().unwrap();
+            assert_eq!(v.to_string().parse::<IntervalValue>().unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn timestamp_arithmetic() {
+        let day: IntervalValue = "1 day".parse().unwrap();
+        assert_eq!(add_interval(ts(2017, 12, 31, 10), &day).unwrap(), ts(2018, 1, 1, 10));
+        assert_eq!(sub_interval(ts(2018, 1, 1, 10), &day).unwrap(), ts(2017, 12, 31, 10));
+
+        // Month end is clamped
+        let month: IntervalValue = "1 month".parse().unwrap();
+        assert_eq!(add_interval(ts(2016, 1, 31, 0), &month).unwrap(), ts(2016, 2, 29, 0));
+        assert_eq!(sub_interval(ts(1969, 3, 31, 0), &month).unwrap(), ts(1969, 2, 28, 0));
+
+        let diff = timestamp_diff(ts(2018, 1, 2, 12), ts(2018, 1, 1, 0)).unwrap();
+        assert_eq!(diff, IntervalValue { months: 0, days: 1, micros: 12 * MICROS_PER_HOUR });
+
+        assert_eq!(format_timestamp(ts(1969, 12, 31, 23) + 1), "1969-12-31 23:00:00.000001");
+        assert_eq!(format_local_timestamp(ts(2018, 1, 1, 2), -5 * MICROS_PER_HOUR),
+                   "2017-12-31 21:00:00-05:00");
+        assert_eq!(format_local_timestamp(ts(2018, 1, 1, 2), 330 * MICROS_PER_MINUTE),
+                   "2018-01-01 07:30:00+05:30");
+
+        // Thursday, Sunday and Monday
+        assert_eq!(day_of_week(ts(1970, 1, 1, 0)), 4);
+        assert_eq!(day_of_week(ts(1969, 12, 28, 23)), 7);
+        assert_eq!(day_of_week(ts(2018, 1, 1, 10)), 1);
+    }
+}
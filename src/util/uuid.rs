@@ -0,0 +1,76 @@
+// vim: set ts=4 sw=4 et :
+
+//! Conversion of UUID column values from / to their canonical string form
+//! ("xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx").
+
+use ::error::DBError;
+
+const HEX: &'static [u8; 16] = b"0123456789abcdef";
+
+/// Byte offsets of the hyphens in the canonical form
+const HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0' ..= b'9' => Some(c - b'0'),
+        b'a' ..= b'f' => Some(c - b'a' + 10),
+        b'A' ..= b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parse the canonical (hyphenated, case insensitive) UUID string form
+pub fn parse_uuid(s: &str) -> Result<[u8; 16], DBError> {
+    let err = || DBError::ValueParse(format!("UUID '{}'", s));
+    let bytes = s.as_bytes();
+
+    if bytes.len() != 36 || HYPHENS.iter().any(|pos| bytes[*pos] != b'-') {
+        return Err(err())
+    }
+
+    let mut digits = bytes.iter().enumerate()
+        .filter(|&(pos, _)| !HYPHENS.contains(&pos))
+        .map(|(_, c)| hex_value(*c));
+
+    let mut out = [0u8; 16];
+    for byte in out.iter_mut() {
+        let hi = digits.next().and_then(|d| d).ok_or_else(&err)?;
+        let lo = digits.next().and_then(|d| d).ok_or_else(&err)?;
+        *byte = hi << 4 | lo;
+    }
+
+    Ok(out)
+}
+
+/// Format as canonical lower case, hyphenated UUID string
+pub fn format_uuid(uuid: &[u8; 16]) -> String {
+    let mut out = String::with_capacity(36);
+
+    for (idx, byte) in uuid.iter().enumerate() {
+        if idx == 4 || idx == 6 || idx == 8 || idx == 10 {
+            out.push('-');
+        }
+
+        out.push(HEX[(byte >> 4) as usize] as char);
+        out.push(HEX[(byte & 0xf) as usize] as char);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let uuid = parse_uuid("123E4567-e89b-12d3-a456-426655440000").unwrap();
+        assert_eq!(uuid[0], 0x12);
+        assert_eq!(uuid[15], 0x00);
+        assert_eq!(format_uuid(&uuid), "123e4567-e89b-12d3-a456-426655440000");
+
+        assert!(parse_uuid("123e4567e89b12d3a456426655440000").is_err());
+        assert!(parse_uuid("123e4567-e89b-12d3-a456-42665544000g").is_err());
+        assert!(parse_uuid("123e4567-e89b-12d3-a456-4266554400001").is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Embedding vectors and similarity search.
+//!
+//! A vector column is a `LIST<FLOAT32>` column where every (non NULL) row has the same number of
+//! elements, the dimension of the vectors. Vectors are compared to a query vector using a
+//! `Metric`, `VectorTopK` finds the rows most similar to the query (brute force, every row is
+//! scored).
+
+use std::cmp::Ordering;
+
+use ::allocator::Allocator;
+use ::block::{Block, RefColumn, View, column_list_data, column_row_data, column_value};
+use ::error::DBError;
+use ::operation::{Operation, DEFAULT_CURSOR_FETCH, CursorChunk};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::table::Table;
+use ::types::{Float32, ListData, Type};
+
+/// Euclidean distance of two vectors of the same dimension
+pub fn l2_distance(lhs: &[f32], rhs: &[f32]) -> f32 {
+    lhs.iter().zip(rhs)
+        .map(|(l, r)| (l - r) * (l - r))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Cosine of the angle between two vectors of the same dimension, from -1 (opposite) to 1 (same
+/// direction). None if either is the zero vector.
+pub fn cosine_similarity(lhs: &[f32], rhs: &[f32]) -> Option<f32> {
+    let (mut dot, mut lhs_norm, mut rhs_norm) = (0f32, 0f32, 0f32);
+
+    for (l, r) in lhs.iter().zip(rhs) {
+        dot += l * r;
+        lhs_norm += l * l;
+        rhs_norm += r * r;
+    }
+
+    if lhs_norm == 0.0 || rhs_norm == 0.0 {
+        return None
+    }
+
+    Some(dot / (lhs_norm.sqrt() * rhs_norm.sqrt()))
+}
+
+/// Vector comparison function
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Metric {
+    /// `l2_distance`, lower is more similar
+    L2,
+    /// `cosine_similarity`, higher is more similar
+    Cosine,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Metric::L2      => "l2_distance",
+            Metric::Cosine  => "cosine_similarity",
+        }
+    }
+
+    /// Score of the vector against the query, None if undefined
+    pub fn score(&self, vector: &[f32], query: &[f32]) -> Option<f32> {
+        match *self {
+            Metric::L2      => Some(l2_distance(vector, query)),
+            Metric::Cosine  => cosine_similarity(vector, query),
+        }
+    }
+
+    /// Order of two scores, more similar first
+    pub fn rank(&self, lhs: f32, rhs: f32) -> Ordering {
+        let order = lhs.partial_cmp(&rhs).unwrap_or(Ordering::Equal);
+        match *self {
+            Metric::L2      => order,
+            Metric::Cosine  => order.reverse(),
+        }
+    }
+}
+
+/// Rows of a vector column
+pub struct VectorRows<'a> {
+    name: &'a str,
+    ranges: &'a [ListData],
+    nulls: Option<&'a [u8]>,
+    elements: &'a [f32],
+    element_nulls: &'a [u8],
+}
+
+/// Vectors of a `LIST<FLOAT32>` column
+pub fn column_vectors<'c>(col: &'c RefColumn) -> Result<VectorRows<'c>, DBError> {
+    let attr = col.attribute();
+    match attr.dtype {
+        Type::LIST(ref elem) if **elem == Type::FLOAT32 => (),
+        _ => return Err(DBError::AttributeType(attr.name.clone())),
+    }
+
+    let list = column_list_data(col)?;
+    let items = col.child(0).ok_or(DBError::make_column_unknown_pos(0))?;
+    let elements = column_row_data::<Float32>(items)?;
+
+    Ok(VectorRows {
+        name: &attr.name,
+        ranges: list.ranges,
+        nulls: if attr.nullable { Some(list.nulls) } else { None },
+        elements: elements.values,
+        element_nulls: elements.nulls,
+    })
+}
+
+impl<'a> VectorRows<'a> {
+    /// Vector of the row, None for NULL rows. The vector has to be of `dim` elements, none of
+    /// them NULL.
+    pub fn get(&self, row: RowOffset, dim: usize) -> Result<Option<&'a [f32]>, DBError> {
+        if self.nulls.map_or(false, |n| n[row] != 0) {
+            return Ok(None)
+        }
+
+        let range = self.ranges[row];
+        if range.len != dim {
+            return Err(DBError::ValueOutOfRange(format!(
+                "vector {} of {} elements, expected {}", self.name, range.len, dim)))
+        }
+
+        let end = range.offset + range.len;
+        if self.element_nulls[range.offset .. end].iter().any(|n| *n != 0) {
+            return Err(DBError::make_column_not_nullable(format!("{}.item", self.name)))
+        }
+
+        Ok(Some(&self.elements[range.offset .. end]))
+    }
+}
+
+/// Rows of the source with the `k` vectors most similar to a query vector, most similar first.
+///
+/// The output is the source attributes followed by a FLOAT32 `score` attribute. Rows with a NULL
+/// vector, or an undefined score, are skipped. Like `index::LookupJoin`, the selected rows come
+/// from all over the source so the result is materialized into a `Block`.
+pub struct VectorTopK<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    /// Vector attribute
+    pub column: String,
+    pub query: Vec<f32>,
+    pub metric: Metric,
+    pub k: usize,
+}
+
+/// Row of a top-k candidate
+enum Candidate {
+    /// Row of the current best rows
+    Best(RowOffset),
+    /// Row of the current source chunk
+    Chunk(RowOffset),
+}
+
+/// Copy the `src` attributes of a row and its score into a new row of `out`
+fn copy_row<'v>(out: &mut Table, src: &'v View<'v>, row: RowOffset, score: f32)
+    -> Result<(), DBError>
+{
+    let out_row = out.add_row()?;
+    let count = out.schema().count() - 1;
+
+    for pos in 0 .. count {
+        let col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        out.set(pos, out_row, column_value(col, row)?)?;
+    }
+
+    out.set(count, out_row, score)
+}
+
+impl<'a> VectorTopK<'a> {
+    pub fn new<T>(src: T, column: &str, query: Vec<f32>, metric: Metric, k: usize)
+        -> VectorTopK<'a>
+        where T: Operation<'a> + 'a
+    {
+        VectorTopK {
+            src: Box::new(src),
+            column: column.to_string(),
+            query: query,
+            metric: metric,
+            k: k,
+        }
+    }
+
+    pub fn execute<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+        let mut cursor = self.src.bind(alloc)?;
+
+        let (column, schema) = {
+            let src_schema = cursor.schema();
+            let column = src_schema.exists_ok(&self.column)?;
+
+            let mut attrs: Vec<Attribute> = src_schema.iter().cloned().collect();
+            attrs.push(Attribute { name: String::from("score"), nullable: false,
+                                   dtype: Type::FLOAT32 });
+
+            (column, Schema::from_vec(attrs)?)
+        };
+
+        let mut best = Table::new(alloc, &schema, None);
+        let mut best_scores: Vec<f32> = Vec::new();
+
+        loop {
+            let view = match cursor.next(DEFAULT_CURSOR_FETCH)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => break,
+            };
+
+            let mut candidates: Vec<(f32, Candidate)> = best_scores.iter().enumerate()
+                .map(|(row, score)| (*score, Candidate::Best(row)))
+                .collect();
+
+            {
+                let col = view.column(column).ok_or(DBError::make_column_unknown_pos(column))?;
+                let vectors = column_vectors(col)?;
+
+                for row in 0 .. view.rows() {
+                    let vector = match vectors.get(row, self.query.len())? {
+                        Some(vector) => vector,
+                        None => continue,
+                    };
+
+                    match self.metric.score(vector, &self.query) {
+                        Some(score) if !score.is_nan() =>
+                            candidates.push((score, Candidate::Chunk(row))),
+                        _ => (),
+                    }
+                }
+            }
+
+            // Stable, so ties keep the earlier rows
+            candidates.sort_by(|l, r| self.metric.rank(l.0, r.0));
+            candidates.truncate(self.k);
+
+            let mut next = Table::new(alloc, &schema, Some(candidates.len()));
+            for &(score, ref candidate) in &candidates {
+                match *candidate {
+                    Candidate::Best(row)    => copy_row(&mut next, &best, row, score)?,
+                    Candidate::Chunk(row)   => copy_row(&mut next, &view, row, score)?,
+                }
+            }
+
+            best = next;
+            best_scores = candidates.iter().map(|c| c.0).collect();
+        }
+
+        Ok(best.take().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::ScanView;
+    use ::table::TableAppender;
+    use ::types::{UInt32, Value};
+
+    fn vector<'a>(v: &[f32]) -> Value<'a> {
+        Value::LIST(v.iter().map(|e| Value::FLOAT32(*e)).collect())
+    }
+
+    // Kernels, and the top-k rows are ranked by the metric
+    #[test]
+    fn top_k_similar() {
+        assert_eq!(l2_distance(&[1.0, 2.0], &[4.0, 6.0]), 5.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), Some(1.0));
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]), Some(0.0));
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), None);
+
+        let attrs = vec![
+            Attribute{name: "id".to_string(), nullable: false, dtype: Type::UINT32},
+            Attribute{name: "embedding".to_string(), nullable: true,
+                      dtype: Type::LIST(Box::new(Type::FLOAT32))},
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        {
+            let status = TableAppender::new(&mut table)
+                .add_row().set(1 as u32).set(vector(&[1.0, 0.0]))
+                .add_row().set(2 as u32).set(vector(&[0.0, 4.0]))
+                .add_row().set(3 as u32).set_null(true)
+                .add_row().set(4 as u32).set(vector(&[3.0, 3.0]))
+                .add_row().set(5 as u32).set(vector(&[0.0, 0.0]))
+                .done();
+            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+        }
+
+        let block = table.take().unwrap();
+        let ids = |metric, k| {
+            let top = VectorTopK::new(ScanView::new(&block, None), "embedding", vec![0.0, 1.0],
+                                      metric, k);
+            let out = top.execute(&allocator::GLOBAL).unwrap();
+            let ids = column_row_data::<UInt32>(out.column(0).unwrap()).unwrap();
+            ids.values[.. out.rows()].to_vec()
+        };
+
+        assert_eq!(ids(Metric::L2, 2), vec![5, 1]);
+        assert_eq!(ids(Metric::Cosine, 3), vec![2, 4, 1]);
+        assert_eq!(ids(Metric::Cosine, 10).len(), 3);
+
+        let top = VectorTopK::new(ScanView::new(&block, None), "embedding", vec![0.0, 1.0, 2.0],
+                                  Metric::L2, 2);
+        match top.execute(&allocator::GLOBAL) {
+            Err(DBError::ValueOutOfRange(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+    }
+}